@@ -16,15 +16,10 @@ fn test_get(handler: &BinaryHandlerWithTimer, key: &Bytes) {
     let request = create_get_request_by_key(key);
     let result = handler.handle_request(request);
     match result {
-        Some(resp) => {
-            if let encoder::BinaryResponse::Get(_response) = resp {
-                assert_eq!(0, 0);
-            } else if let encoder::BinaryResponse::Error(_error) = resp {
-                assert_eq!(0, 0);
-            } else {
-                unreachable!();
-            }
-        }
+        Some(resp) => assert!(matches!(
+            resp,
+            encoder::BinaryResponse::Get(_) | encoder::BinaryResponse::Error(_)
+        )),
         None => unreachable!(),
     }
 }
@@ -33,15 +28,10 @@ fn test_set(handler: &BinaryHandlerWithTimer, key: Bytes, value: Bytes) {
     let request = create_set_request(key, value);
     let result = handler.handle_request(request);
     match result {
-        Some(resp) => {
-            if let encoder::BinaryResponse::Set(_response) = resp {
-                assert_eq!(0, 0);
-            } else if let encoder::BinaryResponse::Error(_error) = resp {
-                assert_eq!(0, 0);
-            } else {
-                unreachable!();
-            }
-        }
+        Some(resp) => assert!(matches!(
+            resp,
+            encoder::BinaryResponse::Set(_) | encoder::BinaryResponse::Error(_)
+        )),
         None => unreachable!(),
     }
 }
@@ -0,0 +1,144 @@
+use bytes::Bytes;
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use memcrs::cache::cache::{Cache, Record};
+use memcrs::cache::eviction_policy::EvictionPolicy;
+use memcrs::memcache::cli::parser::{MokaConfig, SieveConfig};
+use memcrs::memory_store::moka_store::MokaMemoryStore;
+use memcrs::memory_store::sieve_store::SieveMemoryStore;
+use memcrs::mock::mock_server::MockSystemTimer;
+use rand::rngs::StdRng;
+use rand::{RngExt, SeedableRng};
+use std::sync::Arc;
+
+// Trace can be replayed from a file with one key per line, e.g.
+// MEMCRS_TRACE=trace.txt cargo bench --bench hit_ratio
+const TRACE_ENV: &str = "MEMCRS_TRACE";
+const CACHE_CAPACITY: u64 = 10_000;
+const KEY_SPACE: usize = 100_000;
+const TRACE_LENGTH: usize = 200_000;
+const SCAN_EVERY: usize = 20_000;
+const SCAN_LENGTH: usize = 5_000;
+
+type Store = Arc<dyn Cache + Send + Sync>;
+
+/// Zipf distributed popular keys interleaved with one-off scans,
+/// which is where LRU suffers the most.
+fn generate_trace() -> Vec<Bytes> {
+    let mut rng = StdRng::seed_from_u64(0x5eed);
+    let mut cdf: Vec<f64> = Vec::with_capacity(KEY_SPACE);
+    let mut sum = 0.0;
+    for rank in 1..=KEY_SPACE {
+        sum += 1.0 / rank as f64;
+        cdf.push(sum);
+    }
+    let mut trace = Vec::with_capacity(TRACE_LENGTH + TRACE_LENGTH / SCAN_EVERY * SCAN_LENGTH);
+    let mut scan_key = 0;
+    for idx in 0..TRACE_LENGTH {
+        if idx % SCAN_EVERY == 0 {
+            for _ in 0..SCAN_LENGTH {
+                trace.push(Bytes::from(format!("scan:{}", scan_key)));
+                scan_key += 1;
+            }
+        }
+        let point = rng.random_range(0.0..sum);
+        let rank = cdf.partition_point(|value| *value < point);
+        trace.push(Bytes::from(format!("key:{}", rank)));
+    }
+    trace
+}
+
+fn load_trace() -> Vec<Bytes> {
+    match std::env::var(TRACE_ENV) {
+        Ok(path) => std::fs::read_to_string(&path)
+            .unwrap_or_else(|err| panic!("Cannot read trace {}: {}", path, err))
+            .lines()
+            .map(|line| Bytes::from(line.to_string()))
+            .collect(),
+        Err(_) => generate_trace(),
+    }
+}
+
+fn create_store(engine: &str) -> Store {
+    let timer = Arc::new(MockSystemTimer::new());
+    match engine {
+        "moka_lru" => Arc::new(
+            MokaMemoryStore::new(
+                timer,
+                MokaConfig {
+                    max_capacity: CACHE_CAPACITY,
+                    eviction_policy: EvictionPolicy::LeastRecentlyUsed,
+                },
+            )
+            .unwrap(),
+        ),
+        "moka_tiny_lfu" => Arc::new(
+            MokaMemoryStore::new(
+                timer,
+                MokaConfig {
+                    max_capacity: CACHE_CAPACITY,
+                    eviction_policy: EvictionPolicy::TinyLeastFrequentlyUsed,
+                },
+            )
+            .unwrap(),
+        ),
+        "sieve" => Arc::new(SieveMemoryStore::new(
+            timer,
+            SieveConfig {
+                max_capacity: CACHE_CAPACITY,
+                eviction_policy: EvictionPolicy::Sieve,
+            },
+        )),
+        _ => unreachable!(),
+    }
+}
+
+/// Replays the trace as a look-aside cache would, returns number of hits
+fn replay(store: &Store, trace: &[Bytes]) -> usize {
+    let value = Bytes::from_static(b"value");
+    let mut hits = 0;
+    for (idx, key) in trace.iter().enumerate() {
+        if store.get(key).is_ok() {
+            hits += 1;
+        } else {
+            let _ = store.set(key.clone(), Record::new(value.clone(), 0, 0, 0));
+        }
+        // moka applies evictions lazily
+        if idx % 1024 == 0 {
+            store.run_pending_tasks();
+        }
+    }
+    hits
+}
+
+fn criterion_trace_replay(c: &mut Criterion) {
+    let trace = load_trace();
+    let engines = ["moka_lru", "moka_tiny_lfu", "sieve"];
+
+    for engine in engines {
+        let store = create_store(engine);
+        let hits = replay(&store, &trace);
+        println!(
+            "{}: hit ratio {:.4} ({} requests)",
+            engine,
+            hits as f64 / trace.len() as f64,
+            trace.len()
+        );
+    }
+
+    let mut group = c.benchmark_group("criterion_trace_replay");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(trace.len() as u64));
+    for engine in engines {
+        group.bench_with_input(BenchmarkId::new(engine, trace.len()), &trace, |b, trace| {
+            b.iter_batched(
+                || create_store(engine),
+                |store| replay(&store, trace),
+                BatchSize::LargeInput,
+            );
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, criterion_trace_replay);
+criterion_main!(benches);
@@ -1,5 +1,7 @@
 use super::error::Result;
+use super::events::SharedListener;
 use bytes::Bytes;
+use futures::future::{self, BoxFuture};
 
 /// Cache key type
 pub type KeyType = Bytes;
@@ -88,11 +90,121 @@ pub struct SetStatus {
     pub cas: u64,
 }
 
+/// One page of a key scan
+#[derive(Debug)]
+pub struct ScanPage {
+    /// Live items, expiration of every record is a unix time or 0
+    pub items: Vec<(KeyType, Record)>,
+    /// Cursor of the next page, 0 once the scan is done
+    pub cursor: u64,
+}
+
+/// Compression counters, sizes are summed over values written since start
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CompressionStats {
+    pub algorithm: &'static str,
+    /// values large enough to be compressed
+    pub values: u64,
+    /// values stored compressed, the rest did not get smaller
+    pub compressed_values: u64,
+    pub bytes_in: u64,
+    pub bytes_out: u64,
+}
+
+impl CompressionStats {
+    pub fn ratio(&self) -> f64 {
+        match self.bytes_out {
+            0 => 1.0,
+            bytes_out => self.bytes_in as f64 / bytes_out as f64,
+        }
+    }
+}
+
+/// Items which left the store, summed over all engines of the store
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct EventStats {
+    pub evictions: u64,
+    pub expirations: u64,
+    pub replacements: u64,
+    pub deletions: u64,
+}
+
+/// Counters reported by the stats command
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CacheStats {
+    pub compression: Option<CompressionStats>,
+    pub events: Option<EventStats>,
+    /// Counters of every namespace by its name
+    pub namespaces: Vec<(String, CacheStats)>,
+}
+
+impl CacheStats {
+    /// Name and value of every stat
+    pub fn records(&self) -> Vec<(String, String)> {
+        let mut records = Vec::new();
+        if let Some(compression) = &self.compression {
+            records.push((
+                "compression_algorithm".to_string(),
+                compression.algorithm.to_string(),
+            ));
+            records.push((
+                "compression_values".to_string(),
+                compression.values.to_string(),
+            ));
+            records.push((
+                "compression_compressed_values".to_string(),
+                compression.compressed_values.to_string(),
+            ));
+            records.push((
+                "compression_bytes_in".to_string(),
+                compression.bytes_in.to_string(),
+            ));
+            records.push((
+                "compression_bytes_out".to_string(),
+                compression.bytes_out.to_string(),
+            ));
+            records.push((
+                "compression_ratio".to_string(),
+                format!("{:.2}", compression.ratio()),
+            ));
+        }
+        if let Some(events) = &self.events {
+            records.push(("evictions".to_string(), events.evictions.to_string()));
+            records.push(("expirations".to_string(), events.expirations.to_string()));
+            records.push(("replacements".to_string(), events.replacements.to_string()));
+            records.push(("deletions".to_string(), events.deletions.to_string()));
+        }
+        for (name, stats) in &self.namespaces {
+            records.extend(
+                stats
+                    .records()
+                    .into_iter()
+                    .map(|(stat, value)| (format!("namespace:{}:{}", name, stat), value)),
+            );
+        }
+        records
+    }
+}
+
 // An abstraction over a generic store key <=> value store
 pub trait Cache {
     /// Returns a value associated with a key
     fn get(&self, key: &KeyType) -> Result<Record>;
 
+    /// Like `get`, but a value kept outside of memory is read without
+    /// blocking the calling worker. Stores keeping values in memory
+    /// answer from `get`.
+    fn get_async<'a>(&'a self, key: &'a KeyType) -> BoxFuture<'a, Result<Record>> {
+        Box::pin(future::ready(self.get(key)))
+    }
+
+    /// True if `get` may wait for a read outside of memory, readers
+    /// should use `get_async` then. Stores wrapping other stores pass
+    /// the question down.
+    fn reads_outside_memory(&self) -> bool {
+        false
+    }
+
     /// Sets value that will be associated with a store.
     /// If value already exists in a store CAS field is compared
     /// and depending on CAS value comparison value is set or rejected.
@@ -147,6 +259,56 @@ pub trait Cache {
         delta: DeltaParam,
         increment: bool,
     ) -> Result<DeltaResult>;
+
+    /// Calls `f` for every item which is not expired. Every item is read
+    /// atomically, but items written concurrently may or may not be visited.
+    fn for_each_item(&self, f: &mut dyn FnMut(&KeyType, &Record));
+
+    /// Stores a record as is, keeping its CAS and expiration time,
+    /// used to reload items from a snapshot.
+    fn restore(&self, key: KeyType, record: Record) -> Result<()>;
+
+    /// Swaps value of the item for `record.value` if the item still has
+    /// CAS `record.header.cas`. CAS, flags and expiration are kept and no
+    /// event is reported, used to move values between memory and disk.
+    fn relocate(&self, key: KeyType, record: Record) -> Result<()>;
+
+    /// Adds counters of this store to `stats`, stores wrapping other
+    /// stores add their own counters and pass `stats` down.
+    fn add_stats(&self, _stats: &mut CacheStats) {}
+
+    /// Registers a listener for evictions, expirations, replaced and
+    /// deleted items and flushes of this store, see `CacheListener`.
+    /// Stores wrapping other stores pass the listener down.
+    fn subscribe(&self, listener: SharedListener);
+
+    /// Returns up to `count` items with keys starting with `prefix`, scan
+    /// starts with cursor 0 and continues with the cursor of the last page.
+    /// Items present during the whole scan are returned exactly once, items
+    /// added or removed in the meantime may be missed.
+    fn scan(&self, cursor: u64, count: usize, prefix: &[u8]) -> Result<ScanPage>;
+
+    /// Returns values associated with keys, results are in the order of `keys`.
+    /// Stores should override it to look up keys of the same shard together.
+    fn get_multi(&self, keys: &[KeyType]) -> Vec<Result<Record>> {
+        keys.iter().map(|key| self.get(key)).collect()
+    }
+
+    /// Sets every item like `set`, results are in the order of `items`
+    fn set_multi(&self, items: Vec<(KeyType, Record)>) -> Vec<Result<SetStatus>> {
+        items
+            .into_iter()
+            .map(|(key, record)| self.set(key, record))
+            .collect()
+    }
+
+    /// Deletes every key like `delete`, results are in the order of `items`
+    fn delete_multi(&self, items: Vec<(KeyType, CacheMetaData)>) -> Vec<Result<Record>> {
+        items
+            .into_iter()
+            .map(|(key, header)| self.delete(key, header))
+            .collect()
+    }
 }
 
 #[cfg(test)]
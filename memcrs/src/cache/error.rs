@@ -34,6 +34,26 @@ impl CacheError {
             CacheError::TemporaryFailure => "Temporary failure",
         }
     }
+
+    /// Error reported by a response status, None for success and
+    /// statuses memcrs does not know
+    pub fn from_status(status: u16) -> Option<CacheError> {
+        match status {
+            0x01 => Some(CacheError::NotFound),
+            0x02 => Some(CacheError::KeyExists),
+            0x03 => Some(CacheError::ValueTooLarge),
+            0x04 => Some(CacheError::InvalidArguments),
+            0x05 => Some(CacheError::ItemNotStored),
+            0x06 => Some(CacheError::ArithOnNonNumeric),
+            0x81 => Some(CacheError::UnkownCommand),
+            0x82 => Some(CacheError::OutOfMemory),
+            0x83 => Some(CacheError::NotSupported),
+            0x84 => Some(CacheError::InternalError),
+            0x85 => Some(CacheError::Busy),
+            0x86 => Some(CacheError::TemporaryFailure),
+            _ => None,
+        }
+    }
 }
 
 pub type Result<T> = std::result::Result<T, CacheError>;
@@ -78,4 +98,15 @@ mod tests {
             "Temporary failure"
         );
     }
+
+    #[test]
+    fn test_cache_error_from_status() {
+        assert_eq!(CacheError::from_status(0x00), None);
+        assert_eq!(CacheError::from_status(0x01), Some(CacheError::NotFound));
+        assert_eq!(
+            CacheError::from_status(CacheError::TemporaryFailure as u16),
+            Some(CacheError::TemporaryFailure)
+        );
+        assert_eq!(CacheError::from_status(0x20), None);
+    }
 }
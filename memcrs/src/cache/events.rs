@@ -0,0 +1,277 @@
+use crate::cache::cache::{CacheStats, EventStats, KeyType, Record};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::sync::{Arc, RwLock};
+
+/// Why an item left the store
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RemovalReason {
+    /// evicted to make room for other items
+    Size,
+    /// expiration time has passed, expired items are removed lazily
+    /// so the event fires once the store notices it
+    Expired,
+    /// value was overwritten by a write to the same key
+    Replaced,
+    /// deleted by a client
+    Explicit,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum CacheEvent {
+    Removed {
+        key: KeyType,
+        reason: RemovalReason,
+    },
+    /// Every item was flushed, items expire at `expiration` unix time,
+    /// 0 if they were removed at once
+    Flushed {
+        expiration: u32,
+    },
+}
+
+/// Receives events of a store.
+///
+/// Listeners are called synchronously by the thread changing the store,
+/// usually while it holds a shard lock, so they must not block and must
+/// not call back into the store.
+pub trait CacheListener {
+    fn on_event(&self, event: &CacheEvent);
+
+    /// Whether the listener wants records of dropped items, stores copy
+    /// them out only if a subscribed listener does
+    fn wants_records(&self) -> bool {
+        false
+    }
+
+    /// Called with the record of an item the store evicted or found
+    /// expired, before its `Removed` event
+    fn on_dropped(&self, _key: &KeyType, _record: &Record, _reason: RemovalReason) {}
+}
+
+pub type SharedListener = Arc<dyn CacheListener + Send + Sync>;
+
+/// Counts events of one store and passes them to subscribed listeners,
+/// events are only built once there is a listener.
+#[derive(Default)]
+pub struct StoreEvents {
+    evictions: AtomicU64,
+    expirations: AtomicU64,
+    replacements: AtomicU64,
+    deletions: AtomicU64,
+    subscribed: AtomicBool,
+    records: AtomicBool,
+    listeners: RwLock<Vec<SharedListener>>,
+}
+
+impl StoreEvents {
+    pub fn new() -> StoreEvents {
+        Default::default()
+    }
+
+    pub fn subscribe(&self, listener: SharedListener) {
+        if listener.wants_records() {
+            self.records.store(true, Ordering::Release);
+        }
+        self.listeners.write().unwrap().push(listener);
+        self.subscribed.store(true, Ordering::Release);
+    }
+
+    /// Whether records of dropped items should be passed to `dropped`
+    pub fn wants_records(&self) -> bool {
+        self.records.load(Ordering::Acquire)
+    }
+
+    pub fn removed(&self, key: &KeyType, reason: RemovalReason) {
+        let counter = match reason {
+            RemovalReason::Size => &self.evictions,
+            RemovalReason::Expired => &self.expirations,
+            RemovalReason::Replaced => &self.replacements,
+            RemovalReason::Explicit => &self.deletions,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        self.emit(|| CacheEvent::Removed {
+            key: key.clone(),
+            reason,
+        });
+    }
+
+    /// Same as `removed` for an item evicted or expired by the store,
+    /// listeners which want records get its record first
+    pub fn dropped(&self, key: &KeyType, record: &Record, reason: RemovalReason) {
+        if self.wants_records() {
+            for listener in self.listeners.read().unwrap().iter() {
+                if listener.wants_records() {
+                    listener.on_dropped(key, record, reason);
+                }
+            }
+        }
+        self.removed(key, reason);
+    }
+
+    pub fn flushed(&self, expiration: u32) {
+        self.emit(|| CacheEvent::Flushed { expiration });
+    }
+
+    fn emit(&self, event: impl FnOnce() -> CacheEvent) {
+        if !self.subscribed.load(Ordering::Acquire) {
+            return;
+        }
+        let event = event();
+        for listener in self.listeners.read().unwrap().iter() {
+            listener.on_event(&event);
+        }
+    }
+
+    pub fn add_stats(&self, stats: &mut CacheStats) {
+        let events = stats.events.get_or_insert_with(EventStats::default);
+        events.evictions += self.evictions.load(Ordering::Relaxed);
+        events.expirations += self.expirations.load(Ordering::Relaxed);
+        events.replacements += self.replacements.load(Ordering::Relaxed);
+        events.deletions += self.deletions.load(Ordering::Relaxed);
+    }
+}
+
+/// Forwards events to a bounded channel for consumers running on other
+/// threads. Events which do not fit into the channel are dropped and
+/// counted, a slow consumer never blocks the store.
+pub struct ChannelListener {
+    sender: SyncSender<CacheEvent>,
+    dropped: AtomicU64,
+}
+
+impl ChannelListener {
+    pub fn bounded(capacity: usize) -> (Arc<ChannelListener>, Receiver<CacheEvent>) {
+        let (sender, receiver) = mpsc::sync_channel(capacity);
+        let listener = ChannelListener {
+            sender,
+            dropped: AtomicU64::new(0),
+        };
+        (Arc::new(listener), receiver)
+    }
+
+    /// Number of events dropped because the channel was full or closed
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+impl CacheListener for ChannelListener {
+    fn on_event(&self, event: &CacheEvent) {
+        if self.sender.try_send(event.clone()).is_err() {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    use std::sync::Mutex;
+
+    fn removed(key: &str, reason: RemovalReason) -> CacheEvent {
+        CacheEvent::Removed {
+            key: Bytes::from(key.to_string()),
+            reason,
+        }
+    }
+
+    #[test]
+    fn test_events_are_counted_without_listeners() {
+        let events = StoreEvents::new();
+        events.removed(&Bytes::from("a"), RemovalReason::Size);
+        events.removed(&Bytes::from("b"), RemovalReason::Size);
+        events.removed(&Bytes::from("c"), RemovalReason::Expired);
+        events.flushed(0);
+        let mut stats = CacheStats::default();
+        events.add_stats(&mut stats);
+        assert_eq!(
+            stats.events.unwrap(),
+            EventStats {
+                evictions: 2,
+                expirations: 1,
+                replacements: 0,
+                deletions: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn test_every_listener_gets_events() {
+        let events = StoreEvents::new();
+        let (first, first_events) = ChannelListener::bounded(10);
+        let (second, second_events) = ChannelListener::bounded(10);
+        events.subscribe(first);
+        events.subscribe(second);
+        events.removed(&Bytes::from("a"), RemovalReason::Explicit);
+        events.flushed(100);
+        for receiver in [first_events, second_events] {
+            let received: Vec<CacheEvent> = receiver.try_iter().collect();
+            assert_eq!(
+                received,
+                vec![
+                    removed("a", RemovalReason::Explicit),
+                    CacheEvent::Flushed { expiration: 100 }
+                ]
+            );
+        }
+    }
+
+    struct RecordListener {
+        dropped: Mutex<Vec<(KeyType, Record)>>,
+    }
+
+    impl CacheListener for RecordListener {
+        fn on_event(&self, _event: &CacheEvent) {}
+
+        fn wants_records(&self) -> bool {
+            true
+        }
+
+        fn on_dropped(&self, key: &KeyType, record: &Record, _reason: RemovalReason) {
+            self.dropped
+                .lock()
+                .unwrap()
+                .push((key.clone(), record.clone()));
+        }
+    }
+
+    #[test]
+    fn test_dropped_records_reach_listeners_wanting_them() {
+        let events = StoreEvents::new();
+        let (channel, receiver) = ChannelListener::bounded(10);
+        events.subscribe(channel);
+        assert!(!events.wants_records());
+        let listener = Arc::new(RecordListener {
+            dropped: Mutex::new(Vec::new()),
+        });
+        events.subscribe(listener.clone());
+        assert!(events.wants_records());
+
+        let record = Record::new(Bytes::from("value"), 1, 0, 0);
+        events.dropped(&Bytes::from("a"), &record, RemovalReason::Size);
+        assert_eq!(
+            *listener.dropped.lock().unwrap(),
+            vec![(Bytes::from("a"), record)]
+        );
+        let received: Vec<CacheEvent> = receiver.try_iter().collect();
+        assert_eq!(received, vec![removed("a", RemovalReason::Size)]);
+    }
+
+    #[test]
+    fn test_full_channel_drops_events() {
+        let events = StoreEvents::new();
+        let (listener, receiver) = ChannelListener::bounded(2);
+        events.subscribe(listener.clone());
+        for key in ["a", "b", "c"] {
+            events.removed(&Bytes::from(key), RemovalReason::Replaced);
+        }
+        assert_eq!(listener.dropped(), 1);
+        assert_eq!(receiver.try_iter().count(), 2);
+
+        drop(receiver);
+        events.removed(&Bytes::from("d"), RemovalReason::Replaced);
+        assert_eq!(listener.dropped(), 2);
+    }
+}
@@ -5,6 +5,7 @@ pub enum EvictionPolicy {
     None,
     TinyLeastFrequentlyUsed,
     LeastRecentlyUsed,
+    Sieve,
 }
 
 impl EvictionPolicy {
@@ -13,6 +14,7 @@ impl EvictionPolicy {
             EvictionPolicy::None => "None",
             EvictionPolicy::TinyLeastFrequentlyUsed => "Tiny LFU",
             EvictionPolicy::LeastRecentlyUsed => "LFU",
+            EvictionPolicy::Sieve => "SIEVE",
         }
     }
 }
@@ -27,6 +29,7 @@ mod tests {
         assert_eq!(EvictionPolicy::None.as_str(), "None");
         assert_eq!(EvictionPolicy::TinyLeastFrequentlyUsed.as_str(), "Tiny LFU");
         assert_eq!(EvictionPolicy::LeastRecentlyUsed.as_str(), "LFU");
+        assert_eq!(EvictionPolicy::Sieve.as_str(), "SIEVE");
     }
 
     #[test]
@@ -34,9 +37,11 @@ mod tests {
         let none = EvictionPolicy::from_str("none", true).unwrap();
         let tiny_lfu = EvictionPolicy::from_str("tiny-least-frequently-used", true).unwrap();
         let lru = EvictionPolicy::from_str("least-recently-used", true).unwrap();
+        let sieve = EvictionPolicy::from_str("sieve", true).unwrap();
 
         assert_eq!(none, EvictionPolicy::None);
         assert_eq!(tiny_lfu, EvictionPolicy::TinyLeastFrequentlyUsed);
         assert_eq!(lru, EvictionPolicy::LeastRecentlyUsed);
+        assert_eq!(sieve, EvictionPolicy::Sieve);
     }
 }
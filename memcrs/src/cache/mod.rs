@@ -2,5 +2,6 @@
 pub mod cache;
 pub mod cache_capability;
 pub mod error;
+pub mod events;
 pub mod eviction_policy;
 pub mod pending_tasks_runner;
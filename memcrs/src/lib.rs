@@ -6,7 +6,10 @@ pub mod cache;
 pub mod memcache;
 pub mod memcache_server;
 pub mod memory_store;
+pub mod persistence;
 pub mod protocol;
+pub mod proxy;
+pub mod replication;
 pub mod server;
 pub mod version;
 
@@ -1,7 +1,14 @@
 use crate::cache::cache::Cache;
-use crate::memcache::cli::parser::{DashMapConfig, MokaConfig};
+use crate::memcache::cli::parser::{DashMapConfig, MokaConfig, SieveConfig, SlabConfig};
+use crate::memory_store::compressed_store::{CompressedMemoryStore, CompressionConfig};
 use crate::memory_store::dash_map_store::DashMapMemoryStore as DashMapStore;
+use crate::memory_store::ext_store::ExtStoreConfig;
 use crate::memory_store::moka_store::MokaMemoryStore as MokaStore;
+use crate::memory_store::namespaced_store::{NamespaceConfig, NamespacedMemoryStore};
+use crate::memory_store::partitioned_store::PartitionedMemoryStore;
+use crate::memory_store::sieve_store::SieveMemoryStore as SieveStore;
+use crate::memory_store::slab_store::SlabMemoryStore as SlabStore;
+use crate::memory_store::tiered_store::TieredMemoryStore;
 use crate::memory_store::StoreEngine;
 use crate::server::timer;
 use std::sync::Arc;
@@ -10,17 +17,99 @@ use std::sync::Arc;
 pub enum EngineStoreConfig {
     Moka(MokaConfig),
     DashMap(DashMapConfig),
+    Slab(SlabConfig),
+    Sieve(SieveConfig),
+}
+
+impl EngineStoreConfig {
+    /// Returns config for one of `partitions` equal stores, limits are
+    /// divided so that all partitions together keep the configured size.
+    pub fn partition(&self, partitions: usize) -> EngineStoreConfig {
+        let partitions = partitions.max(1) as u64;
+        match *self {
+            EngineStoreConfig::Moka(mut cfg) => {
+                cfg.max_capacity = (cfg.max_capacity / partitions).max(1);
+                EngineStoreConfig::Moka(cfg)
+            }
+            EngineStoreConfig::DashMap(mut cfg) => {
+                cfg.memory_limit /= partitions;
+                EngineStoreConfig::DashMap(cfg)
+            }
+            EngineStoreConfig::Slab(mut cfg) => {
+                cfg.memory_limit /= partitions;
+                EngineStoreConfig::Slab(cfg)
+            }
+            EngineStoreConfig::Sieve(mut cfg) => {
+                cfg.max_capacity = (cfg.max_capacity / partitions).max(1);
+                EngineStoreConfig::Sieve(cfg)
+            }
+        }
+    }
+
+    /// Returns config limited to `quota`, number of items for moka
+    /// and sieve, bytes for dash map and slab
+    pub fn with_quota(&self, quota: u64) -> EngineStoreConfig {
+        match *self {
+            EngineStoreConfig::Moka(mut cfg) => {
+                cfg.max_capacity = quota;
+                EngineStoreConfig::Moka(cfg)
+            }
+            EngineStoreConfig::DashMap(mut cfg) => {
+                cfg.memory_limit = quota;
+                EngineStoreConfig::DashMap(cfg)
+            }
+            EngineStoreConfig::Slab(mut cfg) => {
+                cfg.memory_limit = quota;
+                EngineStoreConfig::Slab(cfg)
+            }
+            EngineStoreConfig::Sieve(mut cfg) => {
+                cfg.max_capacity = quota;
+                EngineStoreConfig::Sieve(cfg)
+            }
+        }
+    }
 }
 
 #[allow(dead_code)]
 pub struct MemcacheStoreConfig {
     engine: StoreEngine,
     config: EngineStoreConfig,
+    ext_store: Option<ExtStoreConfig>,
+    compression: Option<CompressionConfig>,
+    namespaces: Vec<NamespaceConfig>,
 }
 
 impl MemcacheStoreConfig {
     pub fn new(engine: StoreEngine, config: EngineStoreConfig) -> MemcacheStoreConfig {
-        MemcacheStoreConfig { engine, config }
+        MemcacheStoreConfig {
+            engine,
+            config,
+            ext_store: None,
+            compression: None,
+            namespaces: Vec::new(),
+        }
+    }
+
+    /// Large values are moved from the engine to a page file
+    pub fn with_ext_store(mut self, ext_store: Option<ExtStoreConfig>) -> MemcacheStoreConfig {
+        self.ext_store = ext_store;
+        self
+    }
+
+    /// Large values are compressed before they reach the engine
+    pub fn with_compression(
+        mut self,
+        compression: Option<CompressionConfig>,
+    ) -> MemcacheStoreConfig {
+        self.compression = compression;
+        self
+    }
+
+    /// Keys of every namespace are kept in a store of their own,
+    /// page file is only used by the default store
+    pub fn with_namespaces(mut self, namespaces: Vec<NamespaceConfig>) -> MemcacheStoreConfig {
+        self.namespaces = namespaces;
+        self
     }
 
     pub fn engine(&self) -> StoreEngine {
@@ -43,21 +132,118 @@ impl MemcacheStoreBuilder {
     pub fn from_config(
         config: MemcacheStoreConfig,
         timer: Arc<dyn timer::Timer + Send + Sync>,
+    ) -> Arc<dyn Cache + Send + Sync> {
+        let store = MemcacheStoreBuilder::engine_store(config.engine, &config.config, &timer);
+        let store: Arc<dyn Cache + Send + Sync> = match &config.ext_store {
+            Some(ext_config) => match TieredMemoryStore::new(store, ext_config, timer.clone()) {
+                Ok(tiered) => Arc::new(tiered),
+                Err(err) => {
+                    error!(
+                        "Cannot create page file {}: {}",
+                        ext_config.path.display(),
+                        err
+                    );
+                    std::process::exit(1);
+                }
+            },
+            None => store,
+        };
+        let store: Arc<dyn Cache + Send + Sync> = match config.namespaces.is_empty() {
+            true => store,
+            false => {
+                let namespaces = config
+                    .namespaces
+                    .iter()
+                    .map(|namespace| {
+                        let engine_config = config.config.with_quota(namespace.quota);
+                        let namespace_store = MemcacheStoreBuilder::engine_store(
+                            config.engine,
+                            &engine_config,
+                            &timer,
+                        );
+                        (namespace.clone(), namespace_store)
+                    })
+                    .collect();
+                Arc::new(NamespacedMemoryStore::new(store, namespaces))
+            }
+        };
+        // values are compressed before they are moved to the page file
+        match config.compression {
+            Some(compression) => Arc::new(CompressedMemoryStore::new(store, compression)),
+            None => store,
+        }
+    }
+
+    fn engine_store(
+        engine: StoreEngine,
+        config: &EngineStoreConfig,
+        timer: &Arc<dyn timer::Timer + Send + Sync>,
     ) -> Arc<dyn Cache + Send + Sync> {
         let mut dashmap_config: Option<DashMapConfig> = None;
         let mut moka_config: Option<MokaConfig> = None;
-        match config.config {
+        let mut slab_config: Option<SlabConfig> = None;
+        let mut sieve_config: Option<SieveConfig> = None;
+        match *config {
             EngineStoreConfig::DashMap(cfg) => {
                 dashmap_config = Some(cfg);
             }
             EngineStoreConfig::Moka(cfg) => {
                 moka_config = Some(cfg);
             }
+            EngineStoreConfig::Slab(cfg) => {
+                slab_config = Some(cfg);
+            }
+            EngineStoreConfig::Sieve(cfg) => {
+                sieve_config = Some(cfg);
+            }
+        }
+        match engine {
+            StoreEngine::DashMap => {
+                Arc::new(DashMapStore::new(timer.clone(), dashmap_config.unwrap()))
+            }
+            StoreEngine::Moka => match MokaStore::new(timer.clone(), moka_config.unwrap()) {
+                Ok(store) => Arc::new(store),
+                Err(err) => {
+                    error!(
+                        "Cannot create moka store with {:?} eviction policy: {}",
+                        moka_config.unwrap().eviction_policy,
+                        err.to_static_string()
+                    );
+                    std::process::exit(1);
+                }
+            },
+            StoreEngine::Slab => Arc::new(SlabStore::new(timer.clone(), slab_config.unwrap())),
+            StoreEngine::Sieve => Arc::new(SieveStore::new(timer.clone(), sieve_config.unwrap())),
         }
-        let store: Arc<dyn Cache + Send + Sync> = match config.engine {
-            StoreEngine::DashMap => Arc::new(DashMapStore::new(timer, dashmap_config.unwrap())),
-            StoreEngine::Moka => Arc::new(MokaStore::new(timer, moka_config.unwrap())),
+    }
+
+    /// Splits a dash-map store between `partitions` worker threads, limits
+    /// are divided so that all partitions together keep the configured size.
+    /// Returns the store requests are served from and the partitioned store
+    /// under it, which workers attach their partitions to.
+    pub fn partitioned_from_config(
+        config: MemcacheStoreConfig,
+        timer: Arc<dyn timer::Timer + Send + Sync>,
+        partitions: usize,
+    ) -> (Arc<dyn Cache + Send + Sync>, Arc<PartitionedMemoryStore>) {
+        let partition_config = match config.config.partition(partitions) {
+            EngineStoreConfig::DashMap(cfg) => cfg,
+            _ => {
+                error!("Partitioned keyspace requires the dash-map engine");
+                std::process::exit(1);
+            }
+        };
+        let partitioned = Arc::new(PartitionedMemoryStore::new(
+            timer,
+            partition_config,
+            partitions.max(1),
+        ));
+        let store: Arc<dyn Cache + Send + Sync> = match config.compression {
+            Some(compression) => {
+                Arc::new(CompressedMemoryStore::new(partitioned.clone(), compression))
+            }
+            None => partitioned.clone(),
         };
-        store
+        (store, partitioned)
     }
 }
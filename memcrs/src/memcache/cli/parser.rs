@@ -1,10 +1,17 @@
 use crate::cache::eviction_policy::EvictionPolicy;
+use crate::memcache::store::ttl_rules::TtlRule;
+use crate::memory_store::compressed_store::{CompressionAlgorithm, MIN_COMPRESSION_SIZE};
+use crate::memory_store::namespaced_store::{NamespaceConfig, MAX_NAMESPACES};
+use crate::memory_store::tiered_store::MIN_EXT_ITEM_SIZE;
 use crate::memory_store::StoreEngine;
+use crate::persistence::aof::FsyncPolicy;
+use crate::server::timer::MAX_RELATIVE_EXPIRATION;
 use byte_unit::Byte;
-use clap::{Args, Parser, ValueEnum};
+use clap::parser::ValueSource;
+use clap::{Args, CommandFactory, FromArgMatches, Parser, ValueEnum};
 use core::result::Result;
 use git_version::git_version;
-use std::{fmt::Debug, net::IpAddr, ops::RangeInclusive};
+use std::{fmt::Debug, net::IpAddr, ops::RangeInclusive, path::PathBuf};
 
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug)]
 pub enum RuntimeType {
@@ -23,6 +30,14 @@ impl RuntimeType {
     }
 }
 
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum, Debug)]
+pub enum Mode {
+    /// serve requests from the local store
+    Server,
+    /// route requests to --backend servers by key
+    Proxy,
+}
+
 const GIT_VERSION: &str = git_version!();
 const DEFAULT_PORT: i32 = 11211;
 const DEFAULT_ADDRESS: &str = "127.0.0.1";
@@ -30,12 +45,25 @@ const CONNECTION_LIMIT: u32 = 1024;
 const LISTEN_BACKLOG: u32 = 1024;
 const MEMORY_LIMIT: &str = "64MiB";
 const MAX_ITEM_SIZE: &str = "1MiB";
+const SLAB_PAGE_SIZE: &str = "1MiB";
+const SLAB_GROWTH_FACTOR: f64 = 1.25;
+const AOF_REWRITE_MIN_SIZE: &str = "64MiB";
+const EXT_ITEM_SIZE: &str = "512B";
+const EXT_SIZE: &str = "1GiB";
+const EXT_PAGE_SIZE: &str = "64MiB";
+const COMPRESSION_MIN_SIZE: &str = "1KiB";
+const WRITE_BEHIND_QUEUE_SIZE: usize = 65536;
+const WRITE_BEHIND_BATCH_SIZE: usize = 256;
+const BACKEND_POOL_SIZE: usize = 4;
+const BACKEND_TIMEOUT_MS: u64 = 1000;
+const BACKEND_RETRY_DELAY_MS: u64 = 1000;
+const SHADOW_QUEUE_SIZE: usize = 65536;
 
 fn get_default_threads_number() -> usize {
     num_cpus::get_physical().to_string().parse().unwrap()
 }
 
-#[derive(Parser, Debug, Clone, Copy)]
+#[derive(Parser, Debug, Clone)]
 #[command(author, version, about, long_about = None, after_help = format!("Git version: {GIT_VERSION}"))]
 /// memcached compatible server implementation in Rust
 pub struct MemcrsdConfig {
@@ -75,12 +103,170 @@ pub struct MemcrsdConfig {
     /// do not pin worker threads to cores (only for current-thread runtime)
     pub cpu_no_pin: bool,
 
+    #[arg(long, value_name = "PARTITION-KEYSPACE", default_value_t = false)]
+    /// partition keyspace between worker threads, requests for keys owned
+    /// by other workers are forwarded to them (only for current-thread runtime
+    /// and dash-map engine)
+    pub partition_keyspace: bool,
+
+    #[arg(long, value_name = "SNAPSHOT-PATH")]
+    /// write snapshot of the cache to this file on shutdown,
+    /// every --snapshot-interval and on SIGUSR1
+    pub snapshot_path: Option<PathBuf>,
+
+    #[arg(long, value_name = "SECONDS", default_value_t = 0)]
+    /// interval between periodic snapshots in seconds, 0 disables them
+    pub snapshot_interval: u64,
+
+    #[arg(long, value_name = "RESTORE-FROM")]
+    /// load snapshot from this file on startup, expired items are skipped
+    pub restore_from: Option<PathBuf>,
+
+    #[arg(long, value_name = "AOF-PATH")]
+    /// log every mutation to this file, the log is replayed on startup
+    pub aof_path: Option<PathBuf>,
+
+    #[arg(long, value_name = "AOF-FSYNC", default_value_t = FsyncPolicy::EverySecond, value_enum)]
+    /// when mutation log is synced to disk: always, every-second or never
+    pub aof_fsync: FsyncPolicy,
+
+    #[arg(long, value_name = "AOF-REWRITE-MIN-SIZE", value_parser = parse_memory_mb, default_value = AOF_REWRITE_MIN_SIZE)]
+    /// mutation log is compacted once it is larger than this
+    /// and doubled in size since the last compaction
+    pub aof_rewrite_min_size: u64,
+
+    #[arg(long, value_name = "EXT-PATH")]
+    /// keep values larger than --ext-item-size in this page file,
+    /// only a small pointer stays in memory
+    pub ext_path: Option<PathBuf>,
+
+    #[arg(long, value_name = "EXT-ITEM-SIZE", value_parser = parse_ext_item_size, default_value = EXT_ITEM_SIZE)]
+    /// values larger than this are moved to the page file (at least 64B)
+    pub ext_item_size: u64,
+
+    #[arg(long, value_name = "EXT-SIZE", value_parser = parse_memory_mb, default_value = EXT_SIZE)]
+    /// maximum page file size, oldest page is reused once it is full
+    pub ext_size: u64,
+
+    #[arg(long, value_name = "EXT-PAGE-SIZE", value_parser = parse_ext_page_size, default_value = EXT_PAGE_SIZE)]
+    /// page file is written and compacted in pages of this size
+    pub ext_page_size: u64,
+
+    #[arg(long, value_name = "COMPRESSION", value_enum)]
+    /// compress values of at least --compression-min-size: zstd or lz4
+    pub compression: Option<CompressionAlgorithm>,
+
+    #[arg(long, value_name = "COMPRESSION-MIN-SIZE", value_parser = parse_compression_min_size, default_value = COMPRESSION_MIN_SIZE)]
+    /// smallest value which is compressed (at least 64B)
+    pub compression_min_size: u64,
+
+    #[arg(long, value_name = "CAS-NODE-ID", default_value_t = 0)]
+    /// prefix of CAS values, nodes with different ids never hand out
+    /// the same CAS
+    pub cas_node_id: u16,
+
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    /// track hot keys reported by `stats hotkeys` in one of N requests,
+    /// 0 disables tracking
+    pub hot_keys_sample_rate: u32,
+
+    #[arg(long = "ttl-rule", value_name = "PREFIX=DEFAULT-TTL:MAX-TTL[:MAX-SIZE]", value_parser = parse_ttl_rule)]
+    /// give items with keys starting with PREFIX a TTL when stored without
+    /// one, cap their TTL and reject values above MAX-SIZE, fields can be
+    /// left empty (can be repeated)
+    pub ttl_rules: Vec<TtlRule>,
+
+    #[arg(long, value_name = "WRITE-BEHIND-PATH")]
+    /// append sets and deletes done by clients to this file as JSON
+    /// lines, written in the background after clients got their response
+    pub write_behind_path: Option<PathBuf>,
+
+    #[arg(long, value_name = "N", default_value_t = WRITE_BEHIND_QUEUE_SIZE)]
+    /// mutations waiting to be written behind, mutations over it are dropped
+    pub write_behind_queue_size: usize,
+
+    #[arg(long, value_name = "N", default_value_t = WRITE_BEHIND_BATCH_SIZE)]
+    /// most mutations written behind at once
+    pub write_behind_batch_size: usize,
+
+    #[arg(long, value_name = "LOADER-DIR")]
+    /// load values of missing keys from files named after them
+    /// in this directory
+    pub loader_dir: Option<PathBuf>,
+
+    #[arg(long, value_name = "SECONDS", default_value_t = 0)]
+    /// TTL of loaded items, 0 means they never expire
+    pub loader_ttl: u32,
+
+    #[arg(long, value_name = "PORT")]
+    /// accept replicas on this port of the listen address, they get a
+    /// snapshot followed by every mutation done by clients
+    pub replication_port: Option<u16>,
+
+    #[arg(long, value_name = "HOST:PORT")]
+    /// replicate from the primary at HOST:PORT and reject client
+    /// writes until promoted with SIGUSR2, cannot be used with
+    /// --aof-path or --write-behind-path
+    pub replica_of: Option<String>,
+
+    #[arg(long, value_name = "HOST:PORT")]
+    /// copy items of the running server at HOST:PORT on startup, clients
+    /// are served while they arrive
+    pub warm_from: Option<String>,
+
+    #[arg(long, value_name = "MODE", default_value_t = Mode::Server, value_enum)]
+    /// server stores items, proxy routes every key to one of the
+    /// --backend servers with ketama consistent hashing
+    pub mode: Mode,
+
+    #[arg(long = "backend", value_name = "HOST:PORT")]
+    /// memcache server requests are routed to in proxy mode (can be repeated)
+    pub backends: Vec<String>,
+
+    #[arg(long, value_name = "N", default_value_t = BACKEND_POOL_SIZE)]
+    /// idle connections every worker keeps to each backend
+    pub backend_pool_size: usize,
+
+    #[arg(long, value_name = "MILLISECONDS", default_value_t = BACKEND_TIMEOUT_MS)]
+    /// longest wait for a backend to connect or respond before it is
+    /// marked down
+    pub backend_timeout_ms: u64,
+
+    #[arg(long, value_name = "MILLISECONDS", default_value_t = BACKEND_RETRY_DELAY_MS)]
+    /// how long keys of a backend which is down go to other backends
+    pub backend_retry_delay_ms: u64,
+
+    #[arg(long, value_name = "HOST:PORT")]
+    /// mirror client mutations to the memcache server at HOST:PORT in
+    /// the background, its responses are ignored
+    pub shadow_to: Option<String>,
+
+    #[arg(long, value_name = "N", default_value_t = 1)]
+    /// mirror one of N requests
+    pub shadow_sample_rate: u32,
+
+    #[arg(long)]
+    /// mirror gets too, not only mutations
+    pub shadow_reads: bool,
+
+    #[arg(long, value_name = "N", default_value_t = SHADOW_QUEUE_SIZE)]
+    /// requests waiting to be mirrored, requests over it are dropped
+    pub shadow_queue_size: usize,
+
+    #[arg(long = "namespace", value_name = "NAME:PREFIX:QUOTA", value_parser = parse_namespace)]
+    /// keep keys starting with PREFIX in a store of their own limited to
+    /// QUOTA: items for moka and sieve (a plain number), bytes for dash-map and
+    /// slab (can be repeated), namespaces are selected by key prefix only
+    pub namespaces: Vec<NamespaceConfig>,
+
     #[arg(short, long, value_name = "STORE-ENGINE",  verbatim_doc_comment, value_parser = parse_store_engine, default_value_t = StoreEngine::Moka, value_enum)]
     /// which underlying storage engine to use
     ///
     /// available options:
     /// dash-map – use the DashMap-based memory store
     /// moka     – use the Moka-based memory store (default)
+    /// slab     – use the memcached like slab allocator store
+    /// sieve    – use the lock sharded store with SIEVE eviction
     pub store_engine: StoreEngine,
 
     #[command(flatten)]
@@ -88,14 +274,26 @@ pub struct MemcrsdConfig {
 
     #[command(flatten)]
     pub dash_map: Option<DashMapConfig>,
+
+    #[command(flatten)]
+    pub slab: Option<SlabConfig>,
+
+    /// filled from moka options when sieve engine is selected
+    #[arg(skip)]
+    pub sieve: Option<SieveConfig>,
 }
 
 #[derive(Args, Debug, Clone, Copy)]
 #[group(multiple = true)]
 pub struct DashMapConfig {
     #[arg(long, value_name = "MEMORY-LIMIT", value_parser = parse_memory_mb, default_value = MEMORY_LIMIT)]
-    /// memory limit in megabytes
+    /// memory limit in megabytes (dash-map and slab engines),
+    /// with --eviction-policy 'none' dash-map rejects writes once it is reached
     pub memory_limit: u64,
+
+    #[arg(skip)]
+    /// set by --eviction-policy 'none', dash-map is unbounded otherwise
+    pub reject_when_full: bool,
 }
 
 impl DashMapConfig {
@@ -114,14 +312,54 @@ impl Default for DashMapConfig {
                 1024 * 1024 * 54
             }
         };
-        DashMapConfig { memory_limit }
+        DashMapConfig {
+            memory_limit,
+            reject_when_full: false,
+        }
+    }
+}
+
+#[derive(Args, Debug, Clone, Copy)]
+#[group(multiple = true)]
+pub struct SlabConfig {
+    /// preallocated memory, taken from --memory-limit
+    #[arg(skip = SlabConfig::get_default_memory_limit())]
+    pub memory_limit: u64,
+
+    #[arg(long, value_name = "GROWTH-FACTOR", value_parser = parse_growth_factor, default_value_t = SLAB_GROWTH_FACTOR)]
+    /// chunk size growth factor between slab classes
+    pub slab_growth_factor: f64,
+
+    #[arg(long, value_name = "PAGE-SIZE", value_parser = parse_slab_page_size, default_value = SLAB_PAGE_SIZE)]
+    /// slab page size, it is also the largest item which can be stored
+    pub slab_page_size: u64,
+
+    #[arg(long, value_name = "HUGE-PAGES", default_value_t = false)]
+    /// back slab memory with huge pages (falls back to regular pages)
+    pub slab_huge_pages: bool,
+}
+
+impl SlabConfig {
+    fn get_default_memory_limit() -> u64 {
+        DashMapConfig::default().memory_limit
+    }
+}
+
+impl Default for SlabConfig {
+    fn default() -> Self {
+        SlabConfig {
+            memory_limit: SlabConfig::get_default_memory_limit(),
+            slab_growth_factor: SLAB_GROWTH_FACTOR,
+            slab_page_size: parse_slab_page_size(SLAB_PAGE_SIZE).unwrap(),
+            slab_huge_pages: false,
+        }
     }
 }
 
 #[derive(Args, Debug, Clone, Copy)]
 pub struct MokaConfig {
     #[arg(long, value_name = "CAPACITY", default_value_t = MokaConfig::get_max_capacity_default())]
-    /// maximum Moka cache capacity (key->value pairs)
+    /// maximum Moka or SIEVE cache capacity (key->value pairs)
     pub max_capacity: u64,
 
     #[arg(long, value_name = "EVICTION-POLICY", verbatim_doc_comment, value_parser = parse_eviction_policy, default_value_t = MokaConfig::get_eviction_policy_default(), value_enum)]
@@ -129,7 +367,9 @@ pub struct MokaConfig {
     ///
     /// Possible values
     /// - tiny-lfu: tiny LFU,
-    /// - lru: least recently used (default).
+    /// - lru: least recently used (default),
+    /// - sieve: SIEVE, only with sieve store engine (default for it),
+    /// - none: never evict, writes are rejected once capacity is reached.
     pub eviction_policy: EvictionPolicy,
 }
 
@@ -152,7 +392,25 @@ impl Default for MokaConfig {
     }
 }
 
+/// SIEVE engine shares --max-capacity and --eviction-policy with moka
+#[derive(Debug, Clone, Copy)]
+pub struct SieveConfig {
+    pub max_capacity: u64,
+    pub eviction_policy: EvictionPolicy,
+}
+
+impl Default for SieveConfig {
+    fn default() -> Self {
+        SieveConfig {
+            max_capacity: MokaConfig::get_max_capacity_default(),
+            eviction_policy: EvictionPolicy::Sieve,
+        }
+    }
+}
+
 const PORT_RANGE: RangeInclusive<i32> = -1..=65535;
+const SLAB_PAGE_SIZE_RANGE: RangeInclusive<u64> = 1024..=1024 * 1024 * 1024;
+const EXT_PAGE_SIZE_RANGE: RangeInclusive<u64> = 1024..=1024 * 1024 * 1024;
 
 fn port_in_range(s: &str) -> Result<i32, String> {
     let port: i32 = s
@@ -176,12 +434,127 @@ fn parse_memory_mb(s: &str) -> Result<u64, String> {
     }
 }
 
+fn parse_growth_factor(s: &str) -> Result<f64, String> {
+    let factor: f64 = s
+        .parse()
+        .map_err(|_| format!("`{s}` isn't a growth factor"))?;
+    if factor > 1.0 && factor <= 2.0 {
+        Ok(factor)
+    } else {
+        Err(String::from("growth factor not in range (1.0, 2.0]"))
+    }
+}
+
+fn parse_slab_page_size(s: &str) -> Result<u64, String> {
+    let page_size = parse_memory_mb(s)?;
+    if SLAB_PAGE_SIZE_RANGE.contains(&page_size) {
+        Ok(page_size)
+    } else {
+        Err(String::from("slab page size not in range 1KiB-1GiB"))
+    }
+}
+
+fn parse_ext_item_size(s: &str) -> Result<u64, String> {
+    let item_size = parse_memory_mb(s)?;
+    if item_size >= MIN_EXT_ITEM_SIZE {
+        Ok(item_size)
+    } else {
+        Err(format!(
+            "ext item size has to be at least {}B",
+            MIN_EXT_ITEM_SIZE
+        ))
+    }
+}
+
+fn parse_compression_min_size(s: &str) -> Result<u64, String> {
+    let min_size = parse_memory_mb(s)?;
+    if min_size >= MIN_COMPRESSION_SIZE {
+        Ok(min_size)
+    } else {
+        Err(format!(
+            "compression min size has to be at least {}B",
+            MIN_COMPRESSION_SIZE
+        ))
+    }
+}
+
+fn parse_ext_page_size(s: &str) -> Result<u64, String> {
+    let page_size = parse_memory_mb(s)?;
+    if EXT_PAGE_SIZE_RANGE.contains(&page_size) {
+        Ok(page_size)
+    } else {
+        Err(String::from("ext page size not in range 1KiB-1GiB"))
+    }
+}
+
+fn parse_namespace(s: &str) -> Result<NamespaceConfig, String> {
+    // prefix may contain ':' itself
+    let (name, rest) = s
+        .split_once(':')
+        .ok_or_else(|| format!("`{s}` isn't NAME:PREFIX:QUOTA"))?;
+    let (prefix, quota) = rest
+        .rsplit_once(':')
+        .ok_or_else(|| format!("`{s}` isn't NAME:PREFIX:QUOTA"))?;
+    if name.is_empty() || prefix.is_empty() {
+        return Err(String::from("namespace name and prefix can't be empty"));
+    }
+    let quota_in_bytes = !quota.chars().all(|c| c.is_ascii_digit());
+    let quota = parse_memory_mb(quota)?;
+    if quota == 0 {
+        return Err(String::from("namespace quota has to be greater than 0"));
+    }
+    Ok(NamespaceConfig {
+        name: name.to_string(),
+        prefix: bytes::Bytes::from(prefix.to_string()),
+        quota,
+        quota_in_bytes,
+    })
+}
+
+fn parse_rule_ttl(s: &str) -> Result<Option<u32>, String> {
+    if s.is_empty() {
+        return Ok(None);
+    }
+    match s.parse::<u32>() {
+        Ok(ttl) if ttl > 0 && ttl <= MAX_RELATIVE_EXPIRATION => Ok(Some(ttl)),
+        _ => Err(format!(
+            "`{s}` isn't a TTL between 1 and {MAX_RELATIVE_EXPIRATION} seconds"
+        )),
+    }
+}
+
+fn parse_ttl_rule(s: &str) -> Result<TtlRule, String> {
+    let usage = || format!("`{s}` isn't PREFIX=DEFAULT-TTL:MAX-TTL[:MAX-SIZE]");
+    let (prefix, limits) = s.rsplit_once('=').ok_or_else(usage)?;
+    let limits: Vec<&str> = limits.split(':').collect();
+    if prefix.is_empty() || limits.len() < 2 || limits.len() > 3 {
+        return Err(usage());
+    }
+    let max_size = match limits.get(2) {
+        Some(size) if !size.is_empty() => Some(parse_memory_mb(size)?),
+        _ => None,
+    };
+    let rule = TtlRule {
+        prefix: bytes::Bytes::from(prefix.to_string()),
+        default_ttl: parse_rule_ttl(limits[0])?,
+        max_ttl: parse_rule_ttl(limits[1])?,
+        max_size,
+    };
+    if let (Some(default_ttl), Some(max_ttl)) = (rule.default_ttl, rule.max_ttl) {
+        if default_ttl > max_ttl {
+            return Err(format!("default TTL of `{prefix}` is above its max TTL"));
+        }
+    }
+    Ok(rule)
+}
+
 fn parse_eviction_policy(s: &str) -> Result<EvictionPolicy, String> {
     match s {
         "tiny-lfu" => Ok(EvictionPolicy::TinyLeastFrequentlyUsed),
         "tiny-least-frequently-used" => Ok(EvictionPolicy::TinyLeastFrequentlyUsed),
         "lru" => Ok(EvictionPolicy::LeastRecentlyUsed),
         "least-recently-used" => Ok(EvictionPolicy::LeastRecentlyUsed),
+        "sieve" => Ok(EvictionPolicy::Sieve),
         "none" => Ok(EvictionPolicy::None),
         _ => Err(format!("Invalid eviction policy: {}", s)),
     }
@@ -191,32 +564,288 @@ fn parse_store_engine(s: &str) -> Result<StoreEngine, String> {
     match s {
         "moka" => Ok(StoreEngine::Moka),
         "dash-map" => Ok(StoreEngine::DashMap),
+        "slab" => Ok(StoreEngine::Slab),
+        "sieve" => Ok(StoreEngine::Sieve),
         _ => Err(format!("Invalid store engine selected: {}", s)),
     }
 }
 
 impl MemcrsdConfig {
     fn from_args(args: Vec<String>) -> Result<MemcrsdConfig, String> {
-        let mut memcrs_args = MemcrsdConfig::parse_from(args.iter());
+        let matches = MemcrsdConfig::command().get_matches_from(args.iter());
+        let mut memcrs_args =
+            MemcrsdConfig::from_arg_matches(&matches).map_err(|err| err.to_string())?;
+        let eviction_policy_set =
+            matches.value_source("eviction_policy") == Some(ValueSource::CommandLine);
+        if memcrs_args.partition_keyspace && memcrs_args.runtime_type != RuntimeType::CurrentThread
+        {
+            return Result::Err(
+                "--partition-keyspace is only supported by --runtime-type 'current-thread'. See --help"
+                    .to_string(),
+            );
+        }
+        if memcrs_args.partition_keyspace {
+            if memcrs_args.store_engine != StoreEngine::DashMap {
+                return Result::Err(
+                    "--partition-keyspace requires --store-engine 'dash-map'. See --help"
+                        .to_string(),
+                );
+            }
+            if [
+                "snapshot_path",
+                "restore_from",
+                "aof_path",
+                "ext_path",
+                "replication_port",
+                "replica_of",
+                "warm_from",
+            ]
+            .iter()
+            .any(|id| matches.value_source(id) == Some(ValueSource::CommandLine))
+            {
+                return Result::Err(
+                    "--partition-keyspace keeps items in worker threads, snapshots, --aof-path, --ext-path, replication and --warm-from are not supported. See --help"
+                        .to_string(),
+                );
+            }
+        }
+        if memcrs_args.snapshot_interval > 0 && memcrs_args.snapshot_path.is_none() {
+            return Result::Err(
+                "--snapshot-interval requires --snapshot-path. See --help".to_string(),
+            );
+        }
+        if matches.value_source("aof_fsync") == Some(ValueSource::CommandLine)
+            && memcrs_args.aof_path.is_none()
+        {
+            return Result::Err("--aof-fsync requires --aof-path. See --help".to_string());
+        }
+        if memcrs_args.ext_path.is_none()
+            && ["ext_item_size", "ext_size", "ext_page_size"]
+                .iter()
+                .any(|id| matches.value_source(id) == Some(ValueSource::CommandLine))
+        {
+            return Result::Err("ext options require --ext-path. See --help".to_string());
+        }
+        if memcrs_args.write_behind_path.is_none()
+            && ["write_behind_queue_size", "write_behind_batch_size"]
+                .iter()
+                .any(|id| matches.value_source(id) == Some(ValueSource::CommandLine))
+        {
+            return Result::Err(
+                "write-behind options require --write-behind-path. See --help".to_string(),
+            );
+        }
+        if memcrs_args.write_behind_queue_size == 0 || memcrs_args.write_behind_batch_size == 0 {
+            return Result::Err(
+                "--write-behind-queue-size and --write-behind-batch-size have to be positive"
+                    .to_string(),
+            );
+        }
+        if memcrs_args.replication_port.is_some() && memcrs_args.replica_of.is_some() {
+            return Result::Err(
+                "--replication-port and --replica-of cannot be used together. See --help"
+                    .to_string(),
+            );
+        }
+        if memcrs_args.replica_of.is_some()
+            && (memcrs_args.aof_path.is_some() || memcrs_args.write_behind_path.is_some())
+        {
+            return Result::Err(
+                "--replica-of applies the primary's mutations to the store directly, --aof-path and --write-behind-path are not supported. See --help"
+                    .to_string(),
+            );
+        }
+        if memcrs_args.warm_from.is_some() && memcrs_args.replica_of.is_some() {
+            return Result::Err(
+                "--warm-from and --replica-of cannot be used together. See --help".to_string(),
+            );
+        }
+        if memcrs_args.mode == Mode::Proxy {
+            if memcrs_args.backends.is_empty() {
+                return Result::Err("--mode proxy requires --backend. See --help".to_string());
+            }
+            if [
+                "partition_keyspace",
+                "snapshot_path",
+                "restore_from",
+                "aof_path",
+                "write_behind_path",
+                "loader_dir",
+                "replication_port",
+                "replica_of",
+                "shadow_to",
+                "warm_from",
+            ]
+            .iter()
+            .any(|id| matches.value_source(id) == Some(ValueSource::CommandLine))
+            {
+                return Result::Err(
+                    "--mode proxy does not store items, persistence, replication, --shadow-to, --warm-from and --partition-keyspace are not supported. See --help"
+                        .to_string(),
+                );
+            }
+        } else if [
+            "backends",
+            "backend_pool_size",
+            "backend_timeout_ms",
+            "backend_retry_delay_ms",
+        ]
+        .iter()
+        .any(|id| matches.value_source(id) == Some(ValueSource::CommandLine))
+        {
+            return Result::Err("backend options require --mode proxy. See --help".to_string());
+        }
+        for (index, backend) in memcrs_args.backends.iter().enumerate() {
+            if memcrs_args.backends[..index].contains(backend) {
+                return Result::Err(format!("backend {} is given more than once", backend));
+            }
+        }
+        if memcrs_args.backend_timeout_ms == 0 {
+            return Result::Err("--backend-timeout-ms has to be positive".to_string());
+        }
+        if memcrs_args.shadow_to.is_none()
+            && ["shadow_sample_rate", "shadow_reads", "shadow_queue_size"]
+                .iter()
+                .any(|id| matches.value_source(id) == Some(ValueSource::CommandLine))
+        {
+            return Result::Err("shadow options require --shadow-to. See --help".to_string());
+        }
+        if memcrs_args.shadow_sample_rate == 0 || memcrs_args.shadow_queue_size == 0 {
+            return Result::Err(
+                "--shadow-sample-rate and --shadow-queue-size have to be positive".to_string(),
+            );
+        }
+        if memcrs_args.replication_port == Some(0) {
+            return Result::Err("--replication-port has to be positive".to_string());
+        }
+        if matches.value_source("compression_min_size") == Some(ValueSource::CommandLine)
+            && memcrs_args.compression.is_none()
+        {
+            return Result::Err(
+                "--compression-min-size requires --compression. See --help".to_string(),
+            );
+        }
+        if !memcrs_args.namespaces.is_empty() && memcrs_args.partition_keyspace {
+            return Result::Err(
+                "--namespace is not supported with --partition-keyspace. See --help".to_string(),
+            );
+        }
+        if memcrs_args.namespaces.len() > MAX_NAMESPACES {
+            return Result::Err(format!(
+                "at most {} namespaces are supported",
+                MAX_NAMESPACES
+            ));
+        }
+        for (index, rule) in memcrs_args.ttl_rules.iter().enumerate() {
+            if memcrs_args.ttl_rules[..index]
+                .iter()
+                .any(|other| other.prefix == rule.prefix)
+            {
+                return Result::Err("--ttl-rule prefixes have to be unique".to_string());
+            }
+        }
+        let counts_items = matches!(
+            memcrs_args.store_engine,
+            StoreEngine::Moka | StoreEngine::Sieve
+        );
+        for (index, namespace) in memcrs_args.namespaces.iter().enumerate() {
+            if counts_items && namespace.quota_in_bytes {
+                return Result::Err(format!(
+                    "namespace `{}` quota has to be a number of items for {}, not a size. See --help",
+                    namespace.name,
+                    memcrs_args.store_engine.as_str()
+                ));
+            }
+            if memcrs_args.namespaces[..index]
+                .iter()
+                .any(|other| other.name == namespace.name || other.prefix == namespace.prefix)
+            {
+                return Result::Err(format!(
+                    "namespace `{}` repeats name or prefix of another namespace",
+                    namespace.name
+                ));
+            }
+        }
+        if memcrs_args.ext_size < memcrs_args.ext_page_size * 2 {
+            return Result::Err(
+                "--ext-size has to hold at least two pages of --ext-page-size. See --help"
+                    .to_string(),
+            );
+        }
+        if memcrs_args.store_engine != StoreEngine::Slab && memcrs_args.slab.is_some() {
+            return Result::Err(
+                "slab options are only accepted by --store-engine 'slab'. See --help".to_string(),
+            );
+        }
         match memcrs_args.store_engine {
             StoreEngine::DashMap => {
-                let config = memcrs_args.dash_map.or(Some(DashMapConfig::default()));
-                memcrs_args.dash_map = config;
-                if memcrs_args.moka.is_some() {
-                    return Result::Err(
-                        "--store-engine 'dash-map' does not accept options from 'moka'; only dashmap-specific flags are allowed. See --help".to_string()
-                    );
+                let mut config = memcrs_args.dash_map.unwrap_or_default();
+                if let Some(moka) = memcrs_args.moka {
+                    // dash-map never evicts, it can only be asked to reject
+                    // writes once --memory-limit is reached
+                    if !eviction_policy_set
+                        || moka.eviction_policy != EvictionPolicy::None
+                        || matches.value_source("max_capacity") == Some(ValueSource::CommandLine)
+                    {
+                        return Result::Err(
+                            "--store-engine 'dash-map' does not accept options from 'moka' except --eviction-policy 'none'; only dashmap-specific flags are allowed. See --help".to_string()
+                        );
+                    }
+                    config.reject_when_full = true;
+                    memcrs_args.moka = None;
                 }
+                memcrs_args.dash_map = Some(config);
             }
             StoreEngine::Moka => {
                 let config = memcrs_args.moka.or(Some(MokaConfig::default()));
                 memcrs_args.moka = config;
+                if config.is_some_and(|cfg| cfg.eviction_policy == EvictionPolicy::Sieve) {
+                    return Result::Err(
+                        "--eviction-policy 'sieve' requires --store-engine 'sieve'. See: --help"
+                            .to_string(),
+                    );
+                }
                 if memcrs_args.dash_map.is_some() {
                     return Result::Err(
                         "--store-engine 'moka' does not accept options from 'dash-map'; only moka-specific flags are allowed. See: --help".to_string()
                     );
                 }
             }
+            StoreEngine::Slab => {
+                if memcrs_args.moka.is_some() {
+                    return Result::Err(
+                        "--store-engine 'slab' does not accept options from 'moka'; only slab-specific flags and --memory-limit are allowed. See: --help".to_string()
+                    );
+                }
+                let mut config = memcrs_args.slab.unwrap_or_default();
+                config.memory_limit = memcrs_args.dash_map.unwrap_or_default().memory_limit;
+                memcrs_args.slab = Some(config);
+                memcrs_args.dash_map = None;
+            }
+            StoreEngine::Sieve => {
+                if memcrs_args.dash_map.is_some() {
+                    return Result::Err(
+                        "--store-engine 'sieve' does not accept options from 'dash-map'; only --max-capacity and --eviction-policy are allowed. See: --help".to_string()
+                    );
+                }
+                let mut config = SieveConfig::default();
+                if let Some(moka) = memcrs_args.moka {
+                    config.max_capacity = moka.max_capacity;
+                    if eviction_policy_set {
+                        config.eviction_policy = moka.eviction_policy;
+                    }
+                }
+                if !matches!(
+                    config.eviction_policy,
+                    EvictionPolicy::Sieve | EvictionPolicy::None
+                ) {
+                    return Result::Err(
+                        "--store-engine 'sieve' supports only 'sieve' and 'none' eviction policies. See: --help".to_string()
+                    );
+                }
+                memcrs_args.sieve = Some(config);
+                memcrs_args.moka = None;
+            }
         }
         Ok(memcrs_args)
     }
@@ -278,7 +907,7 @@ mod tests {
             config.listen_address,
             DEFAULT_ADDRESS.parse::<IpAddr>().unwrap()
         );
-        assert_eq!(config.cpu_no_pin, false);
+        assert!(!config.cpu_no_pin);
         assert_eq!(config.runtime_type, RuntimeType::CurrentThread);
         assert_eq!(config.store_engine, StoreEngine::Moka);
         assert_eq!(
@@ -383,8 +1012,8 @@ mod tests {
 
     #[test]
     fn test_eviction_policy() {
-        let policy = vec!["tiny-lfu", "lru", "none"];
-        let policies = vec![
+        let policy = ["tiny-lfu", "lru", "none"];
+        let policies = [
             EvictionPolicy::TinyLeastFrequentlyUsed,
             EvictionPolicy::LeastRecentlyUsed,
             EvictionPolicy::None,
@@ -468,7 +1097,7 @@ mod tests {
             "lru".to_string(),
         ];
         let result = MemcrsdConfig::from_args(args);
-        assert_eq!(result.is_err(), false);
+        assert!(result.is_ok());
         let config = result.unwrap();
         assert_eq!(config.store_engine, StoreEngine::Moka);
         let moka_config = config.moka.unwrap();
@@ -491,11 +1120,23 @@ mod tests {
         ];
         let result = MemcrsdConfig::from_args(args);
 
-        assert_eq!(result.is_err(), false);
+        assert!(result.is_ok());
         let config = result.unwrap();
         assert_eq!(config.store_engine, StoreEngine::DashMap);
         let dashmap_config = config.dash_map.unwrap();
         assert_eq!(dashmap_config.memory_limit, 1024000000);
+        assert!(!dashmap_config.reject_when_full);
+
+        let args = vec![
+            "".to_string(),
+            "--store-engine".to_string(),
+            "dash-map".to_string(),
+            "--eviction-policy".to_string(),
+            "none".to_string(),
+        ];
+        let config = MemcrsdConfig::from_args(args).unwrap();
+        assert!(config.moka.is_none());
+        assert!(config.dash_map.unwrap().reject_when_full);
     }
 
     #[test]
@@ -513,6 +1154,17 @@ mod tests {
         let result = MemcrsdConfig::from_args(args);
 
         assert!(result.is_err());
+
+        let args = vec![
+            "".to_string(),
+            "--store-engine".to_string(),
+            "dash-map".to_string(),
+            "--max-capacity".to_string(),
+            "1024".to_string(),
+            "--eviction-policy".to_string(),
+            "none".to_string(),
+        ];
+        assert!(MemcrsdConfig::from_args(args).is_err());
     }
 
     #[test]
@@ -530,15 +1182,744 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_slab_flags() {
+        let args = vec![
+            "".to_string(),
+            "--store-engine".to_string(),
+            "slab".to_string(),
+            "--memory-limit".to_string(),
+            "128MiB".to_string(),
+            "--slab-growth-factor".to_string(),
+            "1.5".to_string(),
+            "--slab-page-size".to_string(),
+            "2MiB".to_string(),
+            "--slab-huge-pages".to_string(),
+        ];
+        let config = MemcrsdConfig::from_args(args).unwrap();
+        assert_eq!(config.store_engine, StoreEngine::Slab);
+        assert!(config.dash_map.is_none());
+        let slab_config = config.slab.unwrap();
+        assert_eq!(slab_config.memory_limit, parse_memory_mb("128MiB").unwrap());
+        assert_eq!(slab_config.slab_growth_factor, 1.5);
+        assert_eq!(slab_config.slab_page_size, 2 * 1024 * 1024);
+        assert!(slab_config.slab_huge_pages);
+    }
+
+    #[test]
+    fn test_slab_defaults() {
+        let args = vec![
+            "".to_string(),
+            "--store-engine".to_string(),
+            "slab".to_string(),
+        ];
+        let config = MemcrsdConfig::from_args(args).unwrap();
+        let slab_config = config.slab.unwrap();
+        assert_eq!(
+            slab_config.memory_limit,
+            parse_memory_mb(MEMORY_LIMIT).unwrap()
+        );
+        assert_eq!(slab_config.slab_growth_factor, SLAB_GROWTH_FACTOR);
+        assert_eq!(slab_config.slab_page_size, 1024 * 1024);
+        assert!(!slab_config.slab_huge_pages);
+    }
+
+    #[test]
+    fn test_invalid_slab_flags() {
+        let args = vec![
+            "".to_string(),
+            "--store-engine".to_string(),
+            "moka".to_string(),
+            "--slab-growth-factor".to_string(),
+            "1.5".to_string(),
+        ];
+        assert!(MemcrsdConfig::from_args(args).is_err());
+
+        let args = vec![
+            "".to_string(),
+            "--store-engine".to_string(),
+            "slab".to_string(),
+            "--max-capacity".to_string(),
+            "1024".to_string(),
+        ];
+        assert!(MemcrsdConfig::from_args(args).is_err());
+    }
+
+    #[test]
+    fn test_invalid_slab_values() {
+        for (flag, value) in [
+            ("--slab-growth-factor", "1.0"),
+            ("--slab-growth-factor", "abc"),
+            ("--slab-page-size", "512"),
+            ("--slab-page-size", "2GiB"),
+        ] {
+            let args = vec![
+                "".to_string(),
+                "--store-engine".to_string(),
+                "slab".to_string(),
+                flag.to_string(),
+                value.to_string(),
+            ];
+            assert!(MemcrsdConfig::try_parse_from(args).is_err());
+        }
+    }
+
+    #[test]
+    fn test_sieve_defaults() {
+        let args = vec![
+            "".to_string(),
+            "--store-engine".to_string(),
+            "sieve".to_string(),
+        ];
+        let config = MemcrsdConfig::from_args(args).unwrap();
+        assert_eq!(config.store_engine, StoreEngine::Sieve);
+        assert!(config.moka.is_none());
+        let sieve_config = config.sieve.unwrap();
+        assert_eq!(
+            sieve_config.max_capacity,
+            MokaConfig::get_max_capacity_default()
+        );
+        assert_eq!(sieve_config.eviction_policy, EvictionPolicy::Sieve);
+    }
+
+    #[test]
+    fn test_sieve_flags() {
+        let args = vec![
+            "".to_string(),
+            "--store-engine".to_string(),
+            "sieve".to_string(),
+            "--max-capacity".to_string(),
+            "1000".to_string(),
+        ];
+        let config = MemcrsdConfig::from_args(args).unwrap();
+        let sieve_config = config.sieve.unwrap();
+        assert_eq!(sieve_config.max_capacity, 1000);
+        assert_eq!(sieve_config.eviction_policy, EvictionPolicy::Sieve);
+
+        let args = vec![
+            "".to_string(),
+            "--store-engine".to_string(),
+            "sieve".to_string(),
+            "--eviction-policy".to_string(),
+            "none".to_string(),
+        ];
+        let config = MemcrsdConfig::from_args(args).unwrap();
+        assert_eq!(config.sieve.unwrap().eviction_policy, EvictionPolicy::None);
+    }
+
+    #[test]
+    fn test_invalid_sieve_flags() {
+        for args in [
+            vec!["--store-engine", "sieve", "--eviction-policy", "lru"],
+            vec!["--store-engine", "sieve", "--memory-limit", "128MiB"],
+            vec!["--store-engine", "moka", "--eviction-policy", "sieve"],
+            vec!["--eviction-policy", "sieve"],
+        ] {
+            let mut args: Vec<String> = args.into_iter().map(String::from).collect();
+            args.insert(0, "".to_string());
+            assert!(MemcrsdConfig::from_args(args).is_err());
+        }
+    }
+
+    #[test]
+    fn test_partition_keyspace_flag() {
+        let args = vec![
+            "".to_string(),
+            "--partition-keyspace".to_string(),
+            "--store-engine".to_string(),
+            "dash-map".to_string(),
+        ];
+        let config = MemcrsdConfig::from_args(args).unwrap();
+        assert!(config.partition_keyspace);
+
+        // default engine is moka
+        let args = vec!["".to_string(), "--partition-keyspace".to_string()];
+        assert!(MemcrsdConfig::from_args(args).is_err());
+
+        let args = vec!["".to_string()];
+        let config = MemcrsdConfig::from_args(args).unwrap();
+        assert!(!config.partition_keyspace);
+
+        let args = vec![
+            "".to_string(),
+            "--partition-keyspace".to_string(),
+            "--runtime-type".to_string(),
+            "multi-thread".to_string(),
+        ];
+        assert!(MemcrsdConfig::from_args(args).is_err());
+
+        // items of a partition can only be reached by its worker
+        for (option, value) in [
+            ("--snapshot-path", "memcrsd.snapshot"),
+            ("--restore-from", "memcrsd.snapshot"),
+            ("--aof-path", "memcrsd.aof"),
+            ("--ext-path", "memcrsd.ext"),
+            ("--replication-port", "11212"),
+            ("--replica-of", "127.0.0.1:11212"),
+            ("--warm-from", "127.0.0.1:11212"),
+        ] {
+            let args = vec![
+                "".to_string(),
+                "--partition-keyspace".to_string(),
+                "--store-engine".to_string(),
+                "dash-map".to_string(),
+                option.to_string(),
+                value.to_string(),
+            ];
+            assert!(MemcrsdConfig::from_args(args).is_err(), "{}", option);
+        }
+    }
+
+    #[test]
+    fn test_snapshot_flags() {
+        let args = vec![
+            "".to_string(),
+            "--snapshot-path".to_string(),
+            "/tmp/memcrs.snapshot".to_string(),
+            "--snapshot-interval".to_string(),
+            "60".to_string(),
+            "--restore-from".to_string(),
+            "/tmp/memcrs.snapshot".to_string(),
+        ];
+        let config = MemcrsdConfig::from_args(args).unwrap();
+        assert_eq!(
+            config.snapshot_path,
+            Some(PathBuf::from("/tmp/memcrs.snapshot"))
+        );
+        assert_eq!(config.snapshot_interval, 60);
+        assert_eq!(
+            config.restore_from,
+            Some(PathBuf::from("/tmp/memcrs.snapshot"))
+        );
+
+        let config = MemcrsdConfig::from_args(vec!["".to_string()]).unwrap();
+        assert!(config.snapshot_path.is_none());
+        assert_eq!(config.snapshot_interval, 0);
+        assert!(config.restore_from.is_none());
+
+        let args = vec![
+            "".to_string(),
+            "--snapshot-interval".to_string(),
+            "60".to_string(),
+        ];
+        assert!(MemcrsdConfig::from_args(args).is_err());
+    }
+
+    #[test]
+    fn test_aof_flags() {
+        let args = vec![
+            "".to_string(),
+            "--aof-path".to_string(),
+            "/tmp/memcrs.aof".to_string(),
+            "--aof-fsync".to_string(),
+            "always".to_string(),
+            "--aof-rewrite-min-size".to_string(),
+            "1MiB".to_string(),
+        ];
+        let config = MemcrsdConfig::from_args(args).unwrap();
+        assert_eq!(config.aof_path, Some(PathBuf::from("/tmp/memcrs.aof")));
+        assert_eq!(config.aof_fsync, FsyncPolicy::Always);
+        assert_eq!(config.aof_rewrite_min_size, 1024 * 1024);
+
+        let config = MemcrsdConfig::from_args(vec!["".to_string()]).unwrap();
+        assert!(config.aof_path.is_none());
+        assert_eq!(config.aof_fsync, FsyncPolicy::EverySecond);
+        assert_eq!(
+            config.aof_rewrite_min_size,
+            parse_memory_mb(AOF_REWRITE_MIN_SIZE).unwrap()
+        );
+
+        let args = vec![
+            "".to_string(),
+            "--aof-fsync".to_string(),
+            "never".to_string(),
+        ];
+        assert!(MemcrsdConfig::from_args(args).is_err());
+    }
+
+    #[test]
+    fn test_ext_flags() {
+        let args = vec![
+            "".to_string(),
+            "--ext-path".to_string(),
+            "/tmp/memcrs.ext".to_string(),
+            "--ext-item-size".to_string(),
+            "1KiB".to_string(),
+            "--ext-size".to_string(),
+            "16MiB".to_string(),
+            "--ext-page-size".to_string(),
+            "1MiB".to_string(),
+        ];
+        let config = MemcrsdConfig::from_args(args).unwrap();
+        assert_eq!(config.ext_path, Some(PathBuf::from("/tmp/memcrs.ext")));
+        assert_eq!(config.ext_item_size, 1024);
+        assert_eq!(config.ext_size, 16 * 1024 * 1024);
+        assert_eq!(config.ext_page_size, 1024 * 1024);
+
+        let config = MemcrsdConfig::from_args(vec!["".to_string()]).unwrap();
+        assert!(config.ext_path.is_none());
+        assert_eq!(
+            config.ext_item_size,
+            parse_memory_mb(EXT_ITEM_SIZE).unwrap()
+        );
+        assert_eq!(config.ext_size, parse_memory_mb(EXT_SIZE).unwrap());
+        assert_eq!(
+            config.ext_page_size,
+            parse_memory_mb(EXT_PAGE_SIZE).unwrap()
+        );
+
+        let args = vec![
+            "".to_string(),
+            "--ext-size".to_string(),
+            "16MiB".to_string(),
+        ];
+        assert!(MemcrsdConfig::from_args(args).is_err());
+
+        let args = vec![
+            "".to_string(),
+            "--ext-path".to_string(),
+            "/tmp/memcrs.ext".to_string(),
+            "--ext-size".to_string(),
+            "64MiB".to_string(),
+        ];
+        assert!(MemcrsdConfig::from_args(args).is_err());
+        assert!(parse_ext_item_size("32B").is_err());
+        assert!(parse_ext_page_size("2GiB").is_err());
+    }
+
+    #[test]
+    fn test_compression_flags() {
+        let args = vec![
+            "".to_string(),
+            "--compression".to_string(),
+            "lz4".to_string(),
+            "--compression-min-size".to_string(),
+            "4KiB".to_string(),
+        ];
+        let config = MemcrsdConfig::from_args(args).unwrap();
+        assert_eq!(config.compression, Some(CompressionAlgorithm::Lz4));
+        assert_eq!(config.compression_min_size, 4096);
+
+        let config = MemcrsdConfig::from_args(vec!["".to_string()]).unwrap();
+        assert!(config.compression.is_none());
+        assert_eq!(
+            config.compression_min_size,
+            parse_memory_mb(COMPRESSION_MIN_SIZE).unwrap()
+        );
+
+        let args = vec![
+            "".to_string(),
+            "--compression-min-size".to_string(),
+            "4KiB".to_string(),
+        ];
+        assert!(MemcrsdConfig::from_args(args).is_err());
+        assert!(parse_compression_min_size("16B").is_err());
+    }
+
+    #[test]
+    fn test_cas_node_id_flag() {
+        let config = MemcrsdConfig::from_args(vec!["".to_string()]).unwrap();
+        assert_eq!(config.cas_node_id, 0);
+
+        let args = vec!["".to_string(), "--cas-node-id".to_string(), "7".to_string()];
+        let config = MemcrsdConfig::from_args(args).unwrap();
+        assert_eq!(config.cas_node_id, 7);
+
+        let args = vec![
+            "".to_string(),
+            "--cas-node-id".to_string(),
+            "65536".to_string(),
+        ];
+        assert!(MemcrsdConfig::try_parse_from(args).is_err());
+    }
+
+    #[test]
+    fn test_hot_keys_sample_rate_flag() {
+        let config = MemcrsdConfig::from_args(vec!["".to_string()]).unwrap();
+        assert_eq!(config.hot_keys_sample_rate, 0);
+
+        let args = vec![
+            "".to_string(),
+            "--hot-keys-sample-rate".to_string(),
+            "100".to_string(),
+        ];
+        let config = MemcrsdConfig::from_args(args).unwrap();
+        assert_eq!(config.hot_keys_sample_rate, 100);
+    }
+
+    #[test]
+    fn test_write_behind_flags() {
+        let config = MemcrsdConfig::from_args(vec!["".to_string()]).unwrap();
+        assert_eq!(config.write_behind_path, None);
+        assert_eq!(config.write_behind_queue_size, WRITE_BEHIND_QUEUE_SIZE);
+        assert_eq!(config.write_behind_batch_size, WRITE_BEHIND_BATCH_SIZE);
+
+        let args = vec![
+            "".to_string(),
+            "--write-behind-path".to_string(),
+            "/var/lib/memcrs/mutations.jsonl".to_string(),
+            "--write-behind-queue-size".to_string(),
+            "1000".to_string(),
+            "--write-behind-batch-size".to_string(),
+            "10".to_string(),
+        ];
+        let config = MemcrsdConfig::from_args(args).unwrap();
+        assert_eq!(
+            config.write_behind_path,
+            Some(PathBuf::from("/var/lib/memcrs/mutations.jsonl"))
+        );
+        assert_eq!(config.write_behind_queue_size, 1000);
+        assert_eq!(config.write_behind_batch_size, 10);
+
+        let args = vec![
+            "".to_string(),
+            "--write-behind-path".to_string(),
+            "mutations.jsonl".to_string(),
+            "--write-behind-batch-size".to_string(),
+            "0".to_string(),
+        ];
+        assert!(MemcrsdConfig::from_args(args).is_err());
+        let args = vec![
+            "".to_string(),
+            "--write-behind-queue-size".to_string(),
+            "10".to_string(),
+        ];
+        assert!(MemcrsdConfig::from_args(args).is_err());
+    }
+
+    #[test]
+    fn test_loader_flags() {
+        let config = MemcrsdConfig::from_args(vec!["".to_string()]).unwrap();
+        assert_eq!(config.loader_dir, None);
+        assert_eq!(config.loader_ttl, 0);
+
+        let args = vec![
+            "".to_string(),
+            "--loader-dir".to_string(),
+            "/var/lib/values".to_string(),
+            "--loader-ttl".to_string(),
+            "60".to_string(),
+        ];
+        let config = MemcrsdConfig::from_args(args).unwrap();
+        assert_eq!(config.loader_dir, Some(PathBuf::from("/var/lib/values")));
+        assert_eq!(config.loader_ttl, 60);
+    }
+
+    #[test]
+    fn test_replication_flags() {
+        let config = MemcrsdConfig::from_args(vec!["".to_string()]).unwrap();
+        assert_eq!(config.replication_port, None);
+        assert_eq!(config.replica_of, None);
+
+        let args = vec![
+            "".to_string(),
+            "--replication-port".to_string(),
+            "11212".to_string(),
+        ];
+        let config = MemcrsdConfig::from_args(args).unwrap();
+        assert_eq!(config.replication_port, Some(11212));
+
+        let args = vec![
+            "".to_string(),
+            "--replica-of".to_string(),
+            "primary:11212".to_string(),
+        ];
+        let config = MemcrsdConfig::from_args(args).unwrap();
+        assert_eq!(config.replica_of, Some("primary:11212".to_string()));
+
+        let args = vec![
+            "".to_string(),
+            "--replication-port".to_string(),
+            "11212".to_string(),
+            "--replica-of".to_string(),
+            "primary:11212".to_string(),
+        ];
+        assert!(MemcrsdConfig::from_args(args).is_err());
+        let args = vec![
+            "".to_string(),
+            "--replication-port".to_string(),
+            "0".to_string(),
+        ];
+        assert!(MemcrsdConfig::from_args(args).is_err());
+        for flag in ["--aof-path", "--write-behind-path"] {
+            let args = vec![
+                "".to_string(),
+                "--replica-of".to_string(),
+                "primary:11212".to_string(),
+                flag.to_string(),
+                "/tmp/memcrs.log".to_string(),
+            ];
+            assert!(MemcrsdConfig::from_args(args).is_err());
+        }
+    }
+
+    #[test]
+    fn test_warm_from_flag() {
+        let config = MemcrsdConfig::from_args(vec!["".to_string()]).unwrap();
+        assert_eq!(config.warm_from, None);
+
+        let args = vec![
+            "".to_string(),
+            "--warm-from".to_string(),
+            "peer:11211".to_string(),
+        ];
+        let config = MemcrsdConfig::from_args(args).unwrap();
+        assert_eq!(config.warm_from, Some("peer:11211".to_string()));
+
+        let args = vec![
+            "".to_string(),
+            "--warm-from".to_string(),
+            "peer:11211".to_string(),
+            "--replica-of".to_string(),
+            "primary:11212".to_string(),
+        ];
+        assert!(MemcrsdConfig::from_args(args).is_err());
+    }
+
+    #[test]
+    fn test_proxy_flags() {
+        let config = MemcrsdConfig::from_args(vec!["".to_string()]).unwrap();
+        assert_eq!(config.mode, Mode::Server);
+        assert!(config.backends.is_empty());
+        assert_eq!(config.backend_pool_size, BACKEND_POOL_SIZE);
+        assert_eq!(config.backend_timeout_ms, BACKEND_TIMEOUT_MS);
+        assert_eq!(config.backend_retry_delay_ms, BACKEND_RETRY_DELAY_MS);
+
+        let args = vec![
+            "".to_string(),
+            "--mode".to_string(),
+            "proxy".to_string(),
+            "--backend".to_string(),
+            "10.0.0.1:11211".to_string(),
+            "--backend".to_string(),
+            "10.0.0.2:11211".to_string(),
+            "--backend-pool-size".to_string(),
+            "8".to_string(),
+            "--backend-timeout-ms".to_string(),
+            "250".to_string(),
+        ];
+        let config = MemcrsdConfig::from_args(args).unwrap();
+        assert_eq!(config.mode, Mode::Proxy);
+        assert_eq!(
+            config.backends,
+            vec!["10.0.0.1:11211".to_string(), "10.0.0.2:11211".to_string()]
+        );
+        assert_eq!(config.backend_pool_size, 8);
+        assert_eq!(config.backend_timeout_ms, 250);
+
+        let invalid = [
+            vec!["--mode", "proxy"],
+            vec!["--backend", "10.0.0.1:11211"],
+            vec![
+                "--mode",
+                "proxy",
+                "--backend",
+                "10.0.0.1:11211",
+                "--backend",
+                "10.0.0.1:11211",
+            ],
+            vec![
+                "--mode",
+                "proxy",
+                "--backend",
+                "10.0.0.1:11211",
+                "--snapshot-path",
+                "/tmp/snapshot",
+            ],
+        ];
+        for args in invalid {
+            let args = std::iter::once("")
+                .chain(args)
+                .map(String::from)
+                .collect::<Vec<_>>();
+            assert!(MemcrsdConfig::from_args(args).is_err());
+        }
+    }
+
+    #[test]
+    fn test_shadow_flags() {
+        let config = MemcrsdConfig::from_args(vec!["".to_string()]).unwrap();
+        assert_eq!(config.shadow_to, None);
+        assert_eq!(config.shadow_sample_rate, 1);
+        assert!(!config.shadow_reads);
+        assert_eq!(config.shadow_queue_size, SHADOW_QUEUE_SIZE);
+
+        let args = vec![
+            "".to_string(),
+            "--shadow-to".to_string(),
+            "10.0.0.1:11211".to_string(),
+            "--shadow-sample-rate".to_string(),
+            "10".to_string(),
+            "--shadow-reads".to_string(),
+            "--shadow-queue-size".to_string(),
+            "1024".to_string(),
+        ];
+        let config = MemcrsdConfig::from_args(args).unwrap();
+        assert_eq!(config.shadow_to, Some("10.0.0.1:11211".to_string()));
+        assert_eq!(config.shadow_sample_rate, 10);
+        assert!(config.shadow_reads);
+        assert_eq!(config.shadow_queue_size, 1024);
+
+        let invalid = [
+            vec!["--shadow-reads"],
+            vec!["--shadow-sample-rate", "2"],
+            vec!["--shadow-to", "10.0.0.1:11211", "--shadow-sample-rate", "0"],
+            vec![
+                "--mode",
+                "proxy",
+                "--backend",
+                "10.0.0.1:11211",
+                "--shadow-to",
+                "10.0.0.2:11211",
+            ],
+        ];
+        for args in invalid {
+            let args = std::iter::once("")
+                .chain(args)
+                .map(String::from)
+                .collect::<Vec<_>>();
+            assert!(MemcrsdConfig::from_args(args).is_err());
+        }
+    }
+
+    #[test]
+    fn test_ttl_rule_flags() {
+        let config = MemcrsdConfig::from_args(vec!["".to_string()]).unwrap();
+        assert!(config.ttl_rules.is_empty());
+
+        let args = vec![
+            "".to_string(),
+            "--ttl-rule".to_string(),
+            "session:=3600:86400".to_string(),
+            "--ttl-rule".to_string(),
+            "blob:=::1MiB".to_string(),
+        ];
+        let config = MemcrsdConfig::from_args(args).unwrap();
+        assert_eq!(
+            config.ttl_rules,
+            vec![
+                TtlRule {
+                    prefix: bytes::Bytes::from("session:"),
+                    default_ttl: Some(3600),
+                    max_ttl: Some(86400),
+                    max_size: None,
+                },
+                TtlRule {
+                    prefix: bytes::Bytes::from("blob:"),
+                    default_ttl: None,
+                    max_ttl: None,
+                    max_size: Some(1024 * 1024),
+                },
+            ]
+        );
+
+        let args = vec![
+            "".to_string(),
+            "--ttl-rule".to_string(),
+            "a=1:".to_string(),
+            "--ttl-rule".to_string(),
+            "a=:2".to_string(),
+        ];
+        assert!(MemcrsdConfig::from_args(args).is_err());
+
+        assert!(parse_ttl_rule("session:3600:86400").is_err());
+        assert!(parse_ttl_rule("=1:2").is_err());
+        assert!(parse_ttl_rule("a=1").is_err());
+        assert!(parse_ttl_rule("a=1:2:3:4").is_err());
+        assert!(parse_ttl_rule("a=0:").is_err());
+        assert!(parse_ttl_rule("a=:2592001").is_err());
+        assert!(parse_ttl_rule("a=20:10").is_err());
+        assert!(parse_ttl_rule("a=b=:10").is_ok());
+    }
+
+    #[test]
+    fn test_namespace_flags() {
+        let config = MemcrsdConfig::from_args(vec!["".to_string()]).unwrap();
+        assert!(config.namespaces.is_empty());
+
+        let args = vec![
+            "".to_string(),
+            "--store-engine".to_string(),
+            "dash-map".to_string(),
+            "--namespace".to_string(),
+            "team-a:a:b:1000".to_string(),
+            "--namespace".to_string(),
+            "team-b:b/:64MiB".to_string(),
+        ];
+        let config = MemcrsdConfig::from_args(args).unwrap();
+        assert_eq!(
+            config.namespaces,
+            vec![
+                NamespaceConfig {
+                    name: "team-a".to_string(),
+                    prefix: bytes::Bytes::from("a:b"),
+                    quota: 1000,
+                    quota_in_bytes: false,
+                },
+                NamespaceConfig {
+                    name: "team-b".to_string(),
+                    prefix: bytes::Bytes::from("b/"),
+                    quota: 64 * 1024 * 1024,
+                    quota_in_bytes: true,
+                },
+            ]
+        );
+
+        let args = vec![
+            "".to_string(),
+            "--namespace".to_string(),
+            "team-a:a:1000".to_string(),
+            "--namespace".to_string(),
+            "team-a:b:1000".to_string(),
+        ];
+        assert!(MemcrsdConfig::from_args(args).is_err());
+
+        let args = vec![
+            "".to_string(),
+            "--partition-keyspace".to_string(),
+            "--store-engine".to_string(),
+            "dash-map".to_string(),
+            "--namespace".to_string(),
+            "team-a:a:1000".to_string(),
+        ];
+        assert!(MemcrsdConfig::from_args(args).is_err());
+
+        // moka and sieve count items
+        for engine in ["moka", "sieve"] {
+            let args = vec![
+                "".to_string(),
+                "--store-engine".to_string(),
+                engine.to_string(),
+                "--namespace".to_string(),
+                "team-a:a:64MiB".to_string(),
+            ];
+            assert!(MemcrsdConfig::from_args(args).is_err());
+        }
+        let args = vec![
+            "".to_string(),
+            "--store-engine".to_string(),
+            "sieve".to_string(),
+            "--namespace".to_string(),
+            "team-a:a:1000".to_string(),
+        ];
+        let config = MemcrsdConfig::from_args(args).unwrap();
+        assert_eq!(config.namespaces[0].quota, 1000);
+
+        assert!(parse_namespace("team-a:1000").is_err());
+        assert!(parse_namespace("team-a::1000").is_err());
+        assert!(parse_namespace("team-a:a:0").is_err());
+        assert!(parse_namespace("team-a:a:lots").is_err());
+    }
+
     #[test]
     fn cpu_no_pin_flag_requested() {
         // Test if an invalid store engine results in an error
         let args = vec!["".to_string(), "--cpu-no-pin".to_string()];
         let result = MemcrsdConfig::try_parse_from(args);
 
-        assert_eq!(result.is_err(), false);
+        assert!(result.is_ok());
         let config = result.unwrap();
-        assert_eq!(config.cpu_no_pin, true);
+        assert!(config.cpu_no_pin);
     }
 
     #[test]
@@ -0,0 +1,259 @@
+use bytes::Bytes;
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::sync::Mutex;
+
+/// Keys reported for every metric
+pub const TOP_KEYS: usize = 10;
+const SKETCH_DEPTH: usize = 4;
+const SKETCH_WIDTH: usize = 2048;
+/// Counts are halved after this many samples, so keys which stopped
+/// being hot drop out of the top
+const DECAY_SAMPLES: u64 = 1 << 16;
+
+/// What keys are ranked by
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HotKeyMetric {
+    Reads,
+    Writes,
+    /// value bytes read and written
+    Bytes,
+}
+
+impl HotKeyMetric {
+    pub const ALL: [HotKeyMetric; 3] = [
+        HotKeyMetric::Reads,
+        HotKeyMetric::Writes,
+        HotKeyMetric::Bytes,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            HotKeyMetric::Reads => "reads",
+            HotKeyMetric::Writes => "writes",
+            HotKeyMetric::Bytes => "bytes",
+        }
+    }
+}
+
+/// Count-min sketch, estimates never undercount a key
+struct CountMinSketch {
+    hashers: [RandomState; SKETCH_DEPTH],
+    counters: Vec<u64>,
+}
+
+impl CountMinSketch {
+    fn new() -> CountMinSketch {
+        CountMinSketch {
+            hashers: std::array::from_fn(|_| RandomState::new()),
+            counters: vec![0; SKETCH_DEPTH * SKETCH_WIDTH],
+        }
+    }
+
+    /// Adds `count` to the key and returns its estimate
+    fn add(&mut self, key: &[u8], count: u64) -> u64 {
+        let mut estimate = u64::MAX;
+        for (row, hasher) in self.hashers.iter().enumerate() {
+            let column = hasher.hash_one(key) as usize % SKETCH_WIDTH;
+            let counter = &mut self.counters[row * SKETCH_WIDTH + column];
+            *counter = counter.saturating_add(count);
+            estimate = estimate.min(*counter);
+        }
+        estimate
+    }
+
+    fn halve(&mut self) {
+        self.counters.iter_mut().for_each(|counter| *counter /= 2);
+    }
+}
+
+/// Min-heap of the keys with the highest estimates
+struct TopK {
+    heap: Vec<(u64, Bytes)>,
+}
+
+impl TopK {
+    fn new() -> TopK {
+        TopK {
+            heap: Vec::with_capacity(TOP_KEYS),
+        }
+    }
+
+    fn offer(&mut self, key: &Bytes, estimate: u64) {
+        // estimates only grow, so a key already in the heap moves down
+        if let Some(idx) = self.heap.iter().position(|(_, top)| top == key) {
+            self.heap[idx].0 = estimate;
+            self.sift_down(idx);
+        } else if self.heap.len() < TOP_KEYS {
+            self.heap.push((estimate, key.clone()));
+            self.sift_up(self.heap.len() - 1);
+        } else if estimate > self.heap[0].0 {
+            self.heap[0] = (estimate, key.clone());
+            self.sift_down(0);
+        }
+    }
+
+    fn sift_up(&mut self, mut idx: usize) {
+        while idx > 0 {
+            let parent = (idx - 1) / 2;
+            if self.heap[parent].0 <= self.heap[idx].0 {
+                break;
+            }
+            self.heap.swap(parent, idx);
+            idx = parent;
+        }
+    }
+
+    fn sift_down(&mut self, mut idx: usize) {
+        loop {
+            let mut smallest = idx;
+            for child in [2 * idx + 1, 2 * idx + 2] {
+                if child < self.heap.len() && self.heap[child].0 < self.heap[smallest].0 {
+                    smallest = child;
+                }
+            }
+            if smallest == idx {
+                break;
+            }
+            self.heap.swap(smallest, idx);
+            idx = smallest;
+        }
+    }
+
+    /// Halving keeps the order, so the heap stays valid
+    fn halve(&mut self) {
+        self.heap.iter_mut().for_each(|(count, _)| *count /= 2);
+    }
+
+    fn sorted(&self) -> Vec<(Bytes, u64)> {
+        let mut top: Vec<(Bytes, u64)> = self
+            .heap
+            .iter()
+            .map(|(count, key)| (key.clone(), *count))
+            .collect();
+        top.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+        top
+    }
+}
+
+struct Tracker {
+    sketch: CountMinSketch,
+    top: TopK,
+    samples: u64,
+}
+
+impl Tracker {
+    fn new() -> Tracker {
+        Tracker {
+            sketch: CountMinSketch::new(),
+            top: TopK::new(),
+            samples: 0,
+        }
+    }
+
+    fn record(&mut self, key: &Bytes, count: u64) {
+        self.samples += 1;
+        if self.samples.is_multiple_of(DECAY_SAMPLES) {
+            self.sketch.halve();
+            self.top.halve();
+        }
+        let estimate = self.sketch.add(key, count);
+        self.top.offer(key, estimate);
+    }
+}
+
+/// Heavy hitter tracker, finds keys with the most reads, writes and
+/// bytes transferred from a sample of requests.
+pub struct HotKeys {
+    sample_rate: u32,
+    trackers: [Mutex<Tracker>; 3],
+}
+
+impl HotKeys {
+    /// Tracks one in `sample_rate` requests
+    pub fn new(sample_rate: u32) -> HotKeys {
+        HotKeys {
+            sample_rate: sample_rate.max(1),
+            trackers: std::array::from_fn(|_| Mutex::new(Tracker::new())),
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Decides if the current request is tracked
+    pub fn sampled(&self) -> bool {
+        self.sample_rate == 1 || rand::random::<u32>().is_multiple_of(self.sample_rate)
+    }
+
+    fn tracker(&self, metric: HotKeyMetric) -> &Mutex<Tracker> {
+        &self.trackers[metric as usize]
+    }
+
+    /// Records a sampled request
+    pub fn record(&self, metric: HotKeyMetric, key: &Bytes, count: u64) {
+        if count > 0 {
+            self.tracker(metric).lock().unwrap().record(key, count);
+        }
+    }
+
+    /// Top keys by estimated count, scaled by the sample rate to
+    /// approximate counts of all requests
+    pub fn top(&self, metric: HotKeyMetric) -> Vec<(Bytes, u64)> {
+        let mut top = self.tracker(metric).lock().unwrap().top.sorted();
+        for (_, count) in top.iter_mut() {
+            *count = count.saturating_mul(self.sample_rate as u64);
+        }
+        top
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_top_keys_are_found() {
+        let hot_keys = HotKeys::new(1);
+        for idx in 0..1000u64 {
+            let key = Bytes::from(format!("cold{}", idx));
+            hot_keys.record(HotKeyMetric::Reads, &key, 1);
+            if idx % 10 == 0 {
+                hot_keys.record(HotKeyMetric::Reads, &Bytes::from("hot"), 1);
+            }
+            if idx % 20 == 0 {
+                hot_keys.record(HotKeyMetric::Reads, &Bytes::from("warm"), 1);
+            }
+        }
+        let top = hot_keys.top(HotKeyMetric::Reads);
+        assert_eq!(top.len(), TOP_KEYS);
+        assert_eq!(top[0].0, Bytes::from("hot"));
+        assert!(top[0].1 >= 100);
+        assert_eq!(top[1].0, Bytes::from("warm"));
+        assert!(top[1].1 >= 50);
+        assert!(hot_keys.top(HotKeyMetric::Writes).is_empty());
+    }
+
+    #[test]
+    fn test_counts_are_scaled_by_sample_rate() {
+        let hot_keys = HotKeys::new(100);
+        hot_keys.record(HotKeyMetric::Bytes, &Bytes::from("key"), 10);
+        hot_keys.record(HotKeyMetric::Bytes, &Bytes::from("empty"), 0);
+        assert_eq!(
+            hot_keys.top(HotKeyMetric::Bytes),
+            vec![(Bytes::from("key"), 1000)]
+        );
+    }
+
+    #[test]
+    fn test_counts_decay() {
+        let mut tracker = Tracker::new();
+        let key = Bytes::from("key");
+        for _ in 0..DECAY_SAMPLES - 1 {
+            tracker.record(&key, 1);
+        }
+        tracker.record(&Bytes::from("other"), 1);
+        assert_eq!(tracker.top.sorted()[0], (key, (DECAY_SAMPLES - 1) / 2));
+    }
+}
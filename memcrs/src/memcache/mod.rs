@@ -1,3 +1,4 @@
 pub mod builder;
 pub mod cli;
+pub mod hot_keys;
 pub mod store;
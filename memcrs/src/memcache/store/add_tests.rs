@@ -3,6 +3,8 @@ use test_case::test_case;
 
 #[test_case(create_moka_server() ; "moka_backend")]
 #[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
 fn add_should_succeed_if_not_already_stored(server: MockServer) {
     let key = Bytes::from("key");
     let record = Record::new(from_string("test data"), 5, 0, 0);
@@ -12,6 +14,8 @@ fn add_should_succeed_if_not_already_stored(server: MockServer) {
 
 #[test_case(create_moka_server() ; "moka_backend")]
 #[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
 fn add_should_fail_if_already_stored(server: MockServer) {
     let key = Bytes::from("key");
     let record = Record::new(from_string("test data"), 5, 0, 0);
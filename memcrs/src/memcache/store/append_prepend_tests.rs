@@ -5,6 +5,8 @@ use test_case::test_case;
 
 #[test_case(create_moka_server() ; "moka_backend")]
 #[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
 fn append_should_fail_if_not_exist(server: MockServer) {
     let key = Bytes::from("key");
     let record = Record::new(from_string("test data"), 0, 0, 0);
@@ -18,6 +20,8 @@ fn append_should_fail_if_not_exist(server: MockServer) {
 
 #[test_case(create_moka_server() ; "moka_backend")]
 #[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
 fn append_should_add_at_the_end(server: MockServer) {
     let key = Bytes::from("key");
     let record = Record::new(from_string("Foo"), 0, 0, 0);
@@ -43,6 +47,8 @@ fn append_should_add_at_the_end(server: MockServer) {
 
 #[test_case(create_moka_server() ; "moka_backend")]
 #[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
 fn append_should_fail_on_cas_mismatch(server: MockServer) {
     let key = Bytes::from("key");
     let record = Record::new(from_string("Foo"), 0, 0, 0);
@@ -70,6 +76,8 @@ fn append_should_fail_on_cas_mismatch(server: MockServer) {
 
 #[test_case(create_moka_server() ; "moka_backend")]
 #[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
 fn prepend_should_fail_if_not_exist(server: MockServer) {
     let key = Bytes::from("key");
     let record = Record::new(from_string("test data"), 0, 0, 0);
@@ -83,6 +91,8 @@ fn prepend_should_fail_if_not_exist(server: MockServer) {
 
 #[test_case(create_moka_server() ; "moka_backend")]
 #[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
 fn prepend_should_add_at_the_begining(server: MockServer) {
     let key = Bytes::from("key");
     let record = Record::new(from_string("Foo"), 0, 0, 0);
@@ -108,6 +118,8 @@ fn prepend_should_add_at_the_begining(server: MockServer) {
 
 #[test_case(create_moka_server() ; "moka_backend")]
 #[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
 fn prepend_should_fail_on_cas_mismatch(server: MockServer) {
     let key = Bytes::from("key");
     let record = Record::new(from_string("Foo"), 0, 0, 0);
@@ -0,0 +1,130 @@
+use super::test_utils::*;
+use crate::cache::cache::DeltaParam;
+use std::collections::HashSet;
+use std::thread;
+use test_case::test_case;
+
+const THREADS: usize = 8;
+
+#[test_case(create_moka_server() ; "moka_backend")]
+#[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
+fn cas_update_should_get_new_cas(server: MockServer) {
+    let first = Bytes::from("first");
+    let second = Bytes::from("second");
+    let cas = server
+        .storage
+        .set(first.clone(), Record::new(from_string("a"), 0, 0, 0))
+        .unwrap()
+        .cas;
+    let other_cas = server
+        .storage
+        .set(second, Record::new(from_string("b"), 0, 0, 0))
+        .unwrap()
+        .cas;
+
+    let new_cas = server
+        .storage
+        .set(first.clone(), Record::new(from_string("c"), cas, 0, 0))
+        .unwrap()
+        .cas;
+    assert_ne!(new_cas, other_cas);
+    assert!(new_cas > other_cas);
+    assert_eq!(server.storage.get(&first).unwrap().header.cas, new_cas);
+
+    let appended_cas = server
+        .storage
+        .append(first.clone(), Record::new(from_string("d"), new_cas, 0, 0))
+        .unwrap()
+        .cas;
+    assert!(appended_cas > new_cas);
+}
+
+#[test_case(create_moka_server() ; "moka_backend")]
+#[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
+fn concurrent_mutations_should_get_unique_cas(server: MockServer) {
+    let store = &server.store;
+    let values: Vec<u64> = thread::scope(|scope| {
+        let handles: Vec<_> = (0..THREADS)
+            .map(|thread| {
+                scope.spawn(move || {
+                    let mut values = Vec::new();
+                    for idx in 0..500 {
+                        let key = Bytes::from(format!("key{}-{}", thread, idx % 50));
+                        let record = Record::new(from_string("value"), 0, 0, 0);
+                        values.push(store.set(key.clone(), record).unwrap().cas);
+                        let delta = DeltaParam { delta: 1, value: 0 };
+                        let counter = Bytes::from(format!("counter{}", thread));
+                        values.push(
+                            store
+                                .incr_decr(Meta::new(0, 0, 0), counter, delta, true)
+                                .unwrap()
+                                .cas,
+                        );
+                    }
+                    values
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect()
+    });
+
+    let unique: HashSet<u64> = values.iter().copied().collect();
+    assert_eq!(unique.len(), values.len());
+    assert!(!unique.contains(&0));
+}
+
+#[test_case(create_moka_server() ; "moka_backend")]
+#[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
+fn concurrent_cas_updates_should_not_be_lost(server: MockServer) {
+    const UPDATES: u64 = 200;
+    let key = Bytes::from("key");
+    let store = &server.store;
+    store
+        .set(key.clone(), Record::new(from_string("0"), 0, 0, 0))
+        .unwrap();
+
+    let values: Vec<u64> = thread::scope(|scope| {
+        let handles: Vec<_> = (0..THREADS)
+            .map(|_| {
+                let key = key.clone();
+                scope.spawn(move || {
+                    let mut values = Vec::new();
+                    while (values.len() as u64) < UPDATES {
+                        let record = store.get(&key).unwrap();
+                        let value: u64 =
+                            std::str::from_utf8(&record.value).unwrap().parse().unwrap();
+                        let new_record = Record::new(
+                            from_string(&(value + 1).to_string()),
+                            record.header.cas,
+                            0,
+                            0,
+                        );
+                        match store.set(key.clone(), new_record) {
+                            Ok(status) => values.push(status.cas),
+                            Err(err) => assert_eq!(err, CacheError::KeyExists),
+                        }
+                    }
+                    values
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect()
+    });
+
+    let unique: HashSet<u64> = values.iter().copied().collect();
+    assert_eq!(unique.len(), values.len());
+    let expected = (THREADS as u64 * UPDATES).to_string();
+    assert_eq!(store.get(&key).unwrap().value, from_string(&expected));
+}
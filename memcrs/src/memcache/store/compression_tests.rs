@@ -0,0 +1,165 @@
+use super::test_utils::*;
+use crate::cache::cache::Cache;
+use crate::memory_store::compressed_store::{
+    CompressedMemoryStore, CompressionAlgorithm, CompressionConfig,
+};
+use std::sync::Arc;
+use test_case::test_case;
+
+struct CompressedServer {
+    server: MockServer,
+    inner: Arc<dyn Cache + Send + Sync>,
+}
+
+fn create_compressed(server: MockServer, algorithm: CompressionAlgorithm) -> CompressedServer {
+    let inner = server.store.clone();
+    let config = CompressionConfig {
+        algorithm,
+        min_size: 1024,
+    };
+    let compressed = Arc::new(CompressedMemoryStore::new(inner.clone(), config));
+    CompressedServer {
+        server: MockServer::new(compressed, server.timer),
+        inner,
+    }
+}
+
+fn json_value() -> Bytes {
+    Bytes::from(r#"{"id":1234,"name":"memcrs","tags":["cache","rust"]}"#.repeat(40))
+}
+
+fn random_value(len: usize) -> Bytes {
+    Bytes::from((0..len).map(|_| rand::random::<u8>()).collect::<Vec<u8>>())
+}
+
+#[test_case(create_moka_server(), CompressionAlgorithm::Zstd ; "moka_backend_zstd")]
+#[test_case(create_dash_map_server(), CompressionAlgorithm::Zstd ; "dash_map_backend_zstd")]
+#[test_case(create_slab_server(), CompressionAlgorithm::Zstd ; "slab_backend_zstd")]
+#[test_case(create_sieve_server(), CompressionAlgorithm::Zstd ; "sieve_backend_zstd")]
+#[test_case(create_moka_server(), CompressionAlgorithm::Lz4 ; "moka_backend_lz4")]
+#[test_case(create_dash_map_server(), CompressionAlgorithm::Lz4 ; "dash_map_backend_lz4")]
+#[test_case(create_slab_server(), CompressionAlgorithm::Lz4 ; "slab_backend_lz4")]
+#[test_case(create_sieve_server(), CompressionAlgorithm::Lz4 ; "sieve_backend_lz4")]
+fn compressed_value_should_be_returned_unchanged(
+    server: MockServer,
+    algorithm: CompressionAlgorithm,
+) {
+    let compressed = create_compressed(server, algorithm);
+    let key = Bytes::from("key");
+    let value = json_value();
+    let status = compressed
+        .server
+        .storage
+        .set(key.clone(), Record::new(value.clone(), 0, 3, 0))
+        .unwrap();
+
+    assert!(compressed.inner.get(&key).unwrap().value.len() < value.len() / 2);
+    let record = compressed.server.storage.get(&key).unwrap();
+    assert_eq!(record.value, value);
+    assert_eq!(record.header.flags, 3);
+    assert_eq!(record.header.cas, status.cas);
+
+    let stats = compressed.server.storage.stats().compression.unwrap();
+    assert_eq!(stats.algorithm, algorithm.as_str());
+    assert_eq!(stats.values, 1);
+    assert_eq!(stats.compressed_values, 1);
+    assert_eq!(stats.bytes_in, value.len() as u64);
+    assert!(stats.ratio() > 2.0);
+}
+
+#[test_case(create_moka_server() ; "moka_backend")]
+#[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
+fn incompressible_value_should_be_stored_as_is(server: MockServer) {
+    let compressed = create_compressed(server, CompressionAlgorithm::Zstd);
+    let key = Bytes::from("key");
+    let value = random_value(2048);
+    compressed
+        .server
+        .storage
+        .set(key.clone(), Record::new(value.clone(), 0, 0, 0))
+        .unwrap();
+
+    assert_eq!(compressed.inner.get(&key).unwrap().value, value);
+    assert_eq!(compressed.server.storage.get(&key).unwrap().value, value);
+    let stats = compressed.server.storage.stats().compression.unwrap();
+    assert_eq!(stats.values, 1);
+    assert_eq!(stats.compressed_values, 0);
+    assert_eq!(stats.ratio(), 1.0);
+}
+
+#[test_case(create_moka_server() ; "moka_backend")]
+#[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
+fn small_value_should_not_be_compressed(server: MockServer) {
+    let compressed = create_compressed(server, CompressionAlgorithm::Zstd);
+    let storage = &compressed.server.storage;
+    let key = Bytes::from("counter");
+    storage
+        .set(key.clone(), Record::new(from_string("10"), 0, 0, 0))
+        .unwrap();
+    let result = storage
+        .increment(
+            Meta::new(0, 0, 0),
+            key.clone(),
+            crate::cache::cache::DeltaParam { delta: 5, value: 0 },
+        )
+        .unwrap();
+
+    assert_eq!(result.value, 15);
+    assert_eq!(compressed.inner.get(&key).unwrap().value, from_string("15"));
+    assert_eq!(storage.stats().compression.unwrap().values, 0);
+}
+
+#[test_case(create_moka_server() ; "moka_backend")]
+#[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
+fn append_and_prepend_should_use_original_value(server: MockServer) {
+    let compressed = create_compressed(server, CompressionAlgorithm::Lz4);
+    let storage = &compressed.server.storage;
+    let key = Bytes::from("key");
+    storage
+        .set(key.clone(), Record::new(json_value(), 0, 0, 0))
+        .unwrap();
+    storage
+        .append(key.clone(), Record::new(from_string("]"), 0, 0, 0))
+        .unwrap();
+    storage
+        .prepend(key.clone(), Record::new(from_string("["), 0, 0, 0))
+        .unwrap();
+
+    let mut expected = BytesMut::new();
+    expected.put_slice(b"[");
+    expected.put_slice(&json_value());
+    expected.put_slice(b"]");
+    assert_eq!(storage.get(&key).unwrap().value, expected.freeze());
+    assert!(compressed.inner.get(&key).unwrap().value.len() < json_value().len() / 2);
+}
+
+#[test_case(create_moka_server() ; "moka_backend")]
+#[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
+fn delete_scan_and_for_each_item_should_return_original_value(server: MockServer) {
+    let compressed = create_compressed(server, CompressionAlgorithm::Zstd);
+    let storage = &compressed.server.storage;
+    let key = Bytes::from("key");
+    storage
+        .set(key.clone(), Record::new(json_value(), 0, 0, 0))
+        .unwrap();
+
+    let mut values = Vec::new();
+    compressed
+        .server
+        .store
+        .for_each_item(&mut |_key, record| values.push(record.value.clone()));
+    assert_eq!(values, vec![json_value()]);
+    let page = compressed.server.storage.scan(0, 10, b"").unwrap();
+    assert_eq!(page.items[0].1.value, json_value());
+
+    let record = storage.delete(key, Meta::new(0, 0, 0)).unwrap();
+    assert_eq!(record.value, json_value());
+}
@@ -3,6 +3,8 @@ use test_case::test_case;
 
 #[test_case(create_moka_server() ; "moka_backend")]
 #[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
 fn delete_record(server: MockServer) {
     let key = Bytes::from("key");
     let record = Record::new(from_string("test data"), 0, 0, 0);
@@ -23,6 +25,8 @@ fn delete_record(server: MockServer) {
 
 #[test_case(create_moka_server() ; "moka_backend")]
 #[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
 fn delete_should_return_not_exists(server: MockServer) {
     let key = Bytes::from("key");
     let record = Record::new(from_string("test data"), 0, 0, 0);
@@ -40,6 +44,8 @@ fn delete_should_return_not_exists(server: MockServer) {
 
 #[test_case(create_moka_server() ; "moka_backend")]
 #[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
 fn delete_if_cas_doesnt_match_should_not_delete(server: MockServer) {
     let key = Bytes::from("key");
     let record = Record::new(from_string("test data"), 1, 0, 0);
@@ -57,6 +63,8 @@ fn delete_if_cas_doesnt_match_should_not_delete(server: MockServer) {
 
 #[test_case(create_moka_server() ; "moka_backend")]
 #[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
 fn delete_if_cas_match_should_succeed(server: MockServer) {
     let key = Bytes::from("key");
     let record = Record::new(from_string("test data"), 5, 0, 0);
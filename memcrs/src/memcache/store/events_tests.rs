@@ -0,0 +1,164 @@
+use super::test_utils::*;
+use crate::cache::events::{CacheEvent, ChannelListener, RemovalReason};
+use crate::cache::eviction_policy::EvictionPolicy;
+use crate::memcache::cli::parser::{MokaConfig, SieveConfig};
+use crate::mock::mock_server::MOCK_UNIX_START;
+use std::sync::mpsc::Receiver;
+use test_case::test_case;
+
+fn subscribe(server: &MockServer) -> Receiver<CacheEvent> {
+    let (listener, receiver) = ChannelListener::bounded(100);
+    server.store.subscribe(listener);
+    receiver
+}
+
+fn set(server: &MockServer, key: &str, expiration: u32) {
+    let record = Record::new(from_string("value"), 0, 0, expiration);
+    server
+        .storage
+        .set(Bytes::from(key.to_string()), record)
+        .unwrap();
+}
+
+fn removed(key: &str, reason: RemovalReason) -> CacheEvent {
+    CacheEvent::Removed {
+        key: Bytes::from(key.to_string()),
+        reason,
+    }
+}
+
+#[test_case(create_moka_server() ; "moka_backend")]
+#[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
+fn writes_should_report_replaced_and_deleted_items(server: MockServer) {
+    let events = subscribe(&server);
+    set(&server, "key", 0);
+    set(&server, "key", 0);
+    let record = Record::new(from_string("suffix"), 0, 0, 0);
+    server.storage.append(Bytes::from("key"), record).unwrap();
+    let delta = DeltaParam { delta: 1, value: 0 };
+    let _ = server
+        .storage
+        .increment(Meta::new(0, 0, 0), Bytes::from("key"), delta);
+    server
+        .storage
+        .delete(Bytes::from("key"), Meta::new(0, 0, 0))
+        .unwrap();
+    // nothing is removed by failed writes
+    let record = Record::new(from_string("value"), 0, 0, 0);
+    assert!(server.storage.replace(Bytes::from("key"), record).is_err());
+
+    let received: Vec<CacheEvent> = events.try_iter().collect();
+    assert_eq!(
+        received,
+        vec![
+            removed("key", RemovalReason::Replaced),
+            removed("key", RemovalReason::Replaced),
+            removed("key", RemovalReason::Explicit),
+        ]
+    );
+}
+
+#[test_case(create_moka_server() ; "moka_backend")]
+#[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
+fn expired_items_should_be_reported_once_noticed(server: MockServer) {
+    let events = subscribe(&server);
+    server.timer.set(100);
+    set(&server, "read", 5);
+    set(&server, "overwritten", 5);
+
+    server.timer.set(105);
+    assert_eq!(
+        server.storage.get(&Bytes::from("read")),
+        Err(CacheError::NotFound)
+    );
+    set(&server, "overwritten", 0);
+
+    let received: Vec<CacheEvent> = events.try_iter().collect();
+    assert_eq!(
+        received,
+        vec![
+            removed("read", RemovalReason::Expired),
+            removed("overwritten", RemovalReason::Expired),
+        ]
+    );
+}
+
+#[test_case(create_moka_server() ; "moka_backend")]
+#[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
+fn flush_should_be_reported(server: MockServer) {
+    let events = subscribe(&server);
+    set(&server, "key", 0);
+    server.storage.flush(Meta::new(0, 0, 0));
+    server.timer.set(100);
+    server.storage.flush(Meta::new(0, 0, 10));
+
+    let received: Vec<CacheEvent> = events.try_iter().collect();
+    assert_eq!(
+        received,
+        vec![
+            CacheEvent::Flushed { expiration: 0 },
+            CacheEvent::Flushed {
+                expiration: (MOCK_UNIX_START + 110) as u32
+            },
+        ]
+    );
+}
+
+#[test_case(create_moka_server_with_config(MokaConfig {
+    max_capacity: 2,
+    eviction_policy: EvictionPolicy::LeastRecentlyUsed,
+}) ; "moka_backend")]
+#[test_case(create_sieve_server_with_config(SieveConfig {
+    max_capacity: 2,
+    eviction_policy: EvictionPolicy::Sieve,
+}) ; "sieve_backend")]
+fn evictions_should_be_reported(server: MockServer) {
+    let events = subscribe(&server);
+    for key in ["key1", "key2", "key3", "key4"] {
+        set(&server, key, 0);
+        server.store.run_pending_tasks();
+    }
+
+    let evicted: Vec<CacheEvent> = events.try_iter().collect();
+    assert_eq!(evicted.len(), 2);
+    for event in evicted {
+        match event {
+            CacheEvent::Removed { key, reason } => {
+                assert_eq!(reason, RemovalReason::Size);
+                assert_eq!(server.storage.get(&key), Err(CacheError::NotFound));
+            }
+            event => unreachable!("{:?}", event),
+        }
+    }
+    assert_eq!(server.storage.stats().events.unwrap().evictions, 2);
+}
+
+#[test_case(create_moka_server() ; "moka_backend")]
+#[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
+fn events_should_be_counted_in_stats(server: MockServer) {
+    server.timer.set(100);
+    set(&server, "expiring", 5);
+    set(&server, "replaced", 0);
+    set(&server, "replaced", 0);
+    set(&server, "deleted", 0);
+    server
+        .storage
+        .delete(Bytes::from("deleted"), Meta::new(0, 0, 0))
+        .unwrap();
+    server.timer.set(105);
+    assert!(server.storage.get(&Bytes::from("expiring")).is_err());
+
+    let stats = server.storage.stats().events.unwrap();
+    assert_eq!(stats.evictions, 0);
+    assert_eq!(stats.expirations, 1);
+    assert_eq!(stats.replacements, 1);
+    assert_eq!(stats.deletions, 1);
+}
@@ -0,0 +1,222 @@
+use super::test_utils::*;
+use crate::cache::cache_capability::CacheCapability;
+use crate::cache::eviction_policy::EvictionPolicy;
+use crate::memcache::cli::parser::{DashMapConfig, MokaConfig, SieveConfig};
+use crate::memory_store::dash_map_store::DashMapMemoryStore;
+use crate::memory_store::moka_store::MokaMemoryStore;
+use crate::memory_store::sieve_store::SieveMemoryStore;
+use crate::mock::mock_server::MockSystemTimer;
+use std::sync::Arc;
+use test_case::test_case;
+
+const VALUE: &str = "test data";
+
+// key + metadata + value
+fn item_size(key: &str) -> u64 {
+    (key.len() + Meta::new(0, 0, 0).len() + VALUE.len()) as u64
+}
+
+// all servers are able to hold exactly two items
+fn create_no_evict_moka_server() -> MockServer {
+    create_moka_server_with_config(MokaConfig {
+        max_capacity: 2,
+        eviction_policy: EvictionPolicy::None,
+    })
+}
+
+fn create_no_evict_dash_map_server() -> MockServer {
+    create_dash_map_server_with_config(DashMapConfig {
+        memory_limit: 2 * item_size("key1"),
+        reject_when_full: true,
+    })
+}
+
+fn create_no_evict_sieve_server() -> MockServer {
+    create_sieve_server_with_config(SieveConfig {
+        max_capacity: 2,
+        eviction_policy: EvictionPolicy::None,
+    })
+}
+
+fn fill(server: &MockServer) {
+    for key in ["key1", "key2"] {
+        let record = Record::new(from_string(VALUE), 0, 0, 0);
+        let result = server.storage.set(Bytes::from(key), record);
+        assert!(result.is_ok());
+    }
+}
+
+#[test_case(create_no_evict_moka_server() ; "moka_backend")]
+#[test_case(create_no_evict_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_no_evict_sieve_server() ; "sieve_backend")]
+fn set_should_fail_when_cache_is_full(server: MockServer) {
+    fill(&server);
+    let record = Record::new(from_string(VALUE), 0, 0, 0);
+    let result = server.storage.set(Bytes::from("key3"), record);
+    match result {
+        Ok(_) => unreachable!(),
+        Err(err) => assert_eq!(err, CacheError::OutOfMemory),
+    }
+    assert!(server.storage.get(&Bytes::from("key1")).is_ok());
+    assert!(server.storage.get(&Bytes::from("key2")).is_ok());
+    assert!(server.storage.get(&Bytes::from("key3")).is_err());
+}
+
+#[test_case(create_no_evict_moka_server() ; "moka_backend")]
+#[test_case(create_no_evict_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_no_evict_sieve_server() ; "sieve_backend")]
+fn set_should_override_existing_key_when_cache_is_full(server: MockServer) {
+    fill(&server);
+    let record = Record::new(from_string("new data!"), 0, 0, 0);
+    let result = server.storage.set(Bytes::from("key1"), record.clone());
+    assert!(result.is_ok());
+    match server.storage.get(&Bytes::from("key1")) {
+        Ok(found) => assert_eq!(found, record),
+        Err(_err) => unreachable!(),
+    }
+}
+
+#[test_case(create_no_evict_moka_server() ; "moka_backend")]
+#[test_case(create_no_evict_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_no_evict_sieve_server() ; "sieve_backend")]
+fn add_should_fail_when_cache_is_full(server: MockServer) {
+    fill(&server);
+    let record = Record::new(from_string(VALUE), 0, 0, 0);
+    let result = server.storage.add(Bytes::from("key3"), record);
+    match result {
+        Ok(_) => unreachable!(),
+        Err(err) => assert_eq!(err, CacheError::OutOfMemory),
+    }
+}
+
+#[test_case(create_no_evict_moka_server() ; "moka_backend")]
+#[test_case(create_no_evict_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_no_evict_sieve_server() ; "sieve_backend")]
+fn increment_should_fail_to_create_value_when_cache_is_full(server: MockServer) {
+    fill(&server);
+    let delta = DeltaParam { delta: 1, value: 1 };
+    let result = server
+        .storage
+        .increment(Meta::new(0, 0, 0), Bytes::from("key3"), delta);
+    match result {
+        Ok(_) => unreachable!(),
+        Err(err) => assert_eq!(err, CacheError::OutOfMemory),
+    }
+}
+
+#[test]
+fn append_should_fail_when_memory_limit_is_reached() {
+    // moka limits number of entries, so only dash map
+    // can run out of memory when a value grows
+    let server = create_no_evict_dash_map_server();
+    fill(&server);
+    let record = Record::new(from_string("more"), 0, 0, 0);
+    let result = server.storage.append(Bytes::from("key1"), record);
+    match result {
+        Ok(_) => unreachable!(),
+        Err(err) => assert_eq!(err, CacheError::OutOfMemory),
+    }
+    match server.storage.get(&Bytes::from("key1")) {
+        Ok(found) => assert_eq!(found.value, from_string(VALUE)),
+        Err(_err) => unreachable!(),
+    }
+}
+
+#[test_case(create_no_evict_moka_server() ; "moka_backend")]
+#[test_case(create_no_evict_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_no_evict_sieve_server() ; "sieve_backend")]
+fn delete_should_release_space(server: MockServer) {
+    fill(&server);
+    let deleted = server
+        .storage
+        .delete(Bytes::from("key1"), Meta::new(0, 0, 0));
+    assert!(deleted.is_ok());
+    let record = Record::new(from_string(VALUE), 0, 0, 0);
+    let result = server.storage.set(Bytes::from("key3"), record);
+    assert!(result.is_ok());
+}
+
+#[test_case(create_no_evict_moka_server() ; "moka_backend")]
+#[test_case(create_no_evict_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_no_evict_sieve_server() ; "sieve_backend")]
+fn expired_item_should_release_space(server: MockServer) {
+    let record = Record::new(from_string(VALUE), 0, 0, 5);
+    let result = server.storage.set(Bytes::from("key1"), record);
+    assert!(result.is_ok());
+    let record = Record::new(from_string(VALUE), 0, 0, 0);
+    let result = server.storage.set(Bytes::from("key2"), record);
+    assert!(result.is_ok());
+
+    // key1 is never read, the write itself has to reclaim its space
+    server.timer.set(10);
+    let record = Record::new(from_string(VALUE), 0, 0, 0);
+    let result = server.storage.set(Bytes::from("key3"), record);
+    assert!(result.is_ok());
+    assert!(server.storage.get(&Bytes::from("key1")).is_err());
+    assert!(server.storage.get(&Bytes::from("key2")).is_ok());
+}
+
+#[test_case(create_no_evict_moka_server() ; "moka_backend")]
+#[test_case(create_no_evict_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_no_evict_sieve_server() ; "sieve_backend")]
+fn flush_should_release_space(server: MockServer) {
+    fill(&server);
+    server.storage.flush(Meta::new(0, 0, 0));
+    fill(&server);
+}
+
+#[test]
+fn moka_should_support_all_policies() {
+    let store =
+        MokaMemoryStore::new(Arc::new(MockSystemTimer::new()), MokaConfig::default()).unwrap();
+    assert!(store.is_policy_supported(EvictionPolicy::None));
+    assert!(store.is_policy_supported(EvictionPolicy::LeastRecentlyUsed));
+    assert!(store.is_policy_supported(EvictionPolicy::TinyLeastFrequentlyUsed));
+    assert!(!store.is_policy_supported(EvictionPolicy::Sieve));
+}
+
+#[test]
+fn moka_should_reject_sieve_policy() {
+    let store = MokaMemoryStore::new(
+        Arc::new(MockSystemTimer::new()),
+        MokaConfig {
+            max_capacity: 2,
+            eviction_policy: EvictionPolicy::Sieve,
+        },
+    );
+    assert!(matches!(store, Err(CacheError::NotSupported)));
+}
+
+#[test]
+fn dash_map_should_support_only_no_eviction() {
+    let store = DashMapMemoryStore::new(Arc::new(MockSystemTimer::new()), DashMapConfig::default());
+    assert!(store.is_policy_supported(EvictionPolicy::None));
+    assert!(!store.is_policy_supported(EvictionPolicy::LeastRecentlyUsed));
+    assert!(!store.is_policy_supported(EvictionPolicy::TinyLeastFrequentlyUsed));
+    assert!(!store.is_policy_supported(EvictionPolicy::Sieve));
+}
+
+#[test]
+fn sieve_should_support_sieve_and_no_eviction() {
+    let store = SieveMemoryStore::new(Arc::new(MockSystemTimer::new()), SieveConfig::default());
+    assert!(store.is_policy_supported(EvictionPolicy::Sieve));
+    assert!(store.is_policy_supported(EvictionPolicy::None));
+    assert!(!store.is_policy_supported(EvictionPolicy::LeastRecentlyUsed));
+    assert!(!store.is_policy_supported(EvictionPolicy::TinyLeastFrequentlyUsed));
+}
+
+#[test]
+fn sieve_should_evict_not_visited_item_when_full() {
+    let server = create_sieve_server_with_config(SieveConfig {
+        max_capacity: 2,
+        eviction_policy: EvictionPolicy::Sieve,
+    });
+    fill(&server);
+    assert!(server.storage.get(&Bytes::from("key1")).is_ok());
+    let record = Record::new(from_string(VALUE), 0, 0, 0);
+    let result = server.storage.set(Bytes::from("key3"), record);
+    assert!(result.is_ok());
+    assert!(server.storage.get(&Bytes::from("key1")).is_ok());
+    assert!(server.storage.get(&Bytes::from("key2")).is_err());
+    assert!(server.storage.get(&Bytes::from("key3")).is_ok());
+}
@@ -0,0 +1,208 @@
+use super::test_utils::*;
+use crate::cache::cache::DeltaParam;
+use crate::mock::mock_server::MOCK_UNIX_START;
+use crate::server::timer::MAX_RELATIVE_EXPIRATION;
+use test_case::test_case;
+
+const NOW: u32 = 100;
+
+fn unix_time(timestamp: u32) -> u32 {
+    (MOCK_UNIX_START + timestamp as u64) as u32
+}
+
+#[test_case(create_moka_server() ; "moka_backend")]
+#[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
+fn set_with_unix_time_should_expire_at_that_time(server: MockServer) {
+    server.timer.set(NOW);
+    let key = Bytes::from("key");
+    let record = Record::new(from_string("value"), 0, 0, unix_time(NOW + 10));
+    server.storage.set(key.clone(), record).unwrap();
+
+    server.timer.set(NOW + 9);
+    assert_eq!(
+        server.storage.get(&key).unwrap().header.time_to_live,
+        NOW + 10
+    );
+    server.timer.set(NOW + 10);
+    assert_eq!(server.storage.get(&key).unwrap_err(), CacheError::NotFound);
+}
+
+#[test_case(create_moka_server() ; "moka_backend")]
+#[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
+fn set_with_past_unix_time_should_be_expired_immediately(server: MockServer) {
+    server.timer.set(NOW);
+    let key = Bytes::from("key");
+    let record = Record::new(from_string("value"), 0, 0, unix_time(NOW - 1));
+    server.storage.set(key.clone(), record).unwrap();
+    assert_eq!(server.storage.get(&key).unwrap_err(), CacheError::NotFound);
+
+    // just above 30 days is a unix time long in the past
+    let record = Record::new(from_string("value"), 0, 0, MAX_RELATIVE_EXPIRATION + 1);
+    server.storage.set(key.clone(), record).unwrap();
+    assert_eq!(server.storage.get(&key).unwrap_err(), CacheError::NotFound);
+}
+
+#[test_case(create_moka_server() ; "moka_backend")]
+#[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
+fn thirty_days_should_still_be_relative(server: MockServer) {
+    server.timer.set(NOW);
+    let key = Bytes::from("key");
+    let record = Record::new(from_string("value"), 0, 0, MAX_RELATIVE_EXPIRATION);
+    server.storage.set(key.clone(), record).unwrap();
+
+    server.timer.set(NOW + MAX_RELATIVE_EXPIRATION - 1);
+    assert!(server.storage.get(&key).is_ok());
+    server.timer.set(NOW + MAX_RELATIVE_EXPIRATION);
+    assert_eq!(server.storage.get(&key).unwrap_err(), CacheError::NotFound);
+}
+
+#[test_case(create_moka_server() ; "moka_backend")]
+#[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
+fn add_and_replace_should_accept_unix_time(server: MockServer) {
+    server.timer.set(NOW);
+    let key = Bytes::from("key");
+    let record = Record::new(from_string("value"), 0, 0, unix_time(NOW + 60));
+    server.storage.add(key.clone(), record).unwrap();
+    assert_eq!(
+        server.storage.get(&key).unwrap().header.time_to_live,
+        NOW + 60
+    );
+
+    let record = Record::new(from_string("other"), 0, 0, unix_time(NOW + 20));
+    server.storage.replace(key.clone(), record).unwrap();
+    server.timer.set(NOW + 20);
+    assert_eq!(server.storage.get(&key).unwrap_err(), CacheError::NotFound);
+}
+
+#[test_case(create_moka_server() ; "moka_backend")]
+#[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
+fn increment_should_create_value_expiring_at_unix_time(server: MockServer) {
+    server.timer.set(NOW);
+    let key = Bytes::from("counter");
+    let delta = DeltaParam {
+        delta: 1,
+        value: 10,
+    };
+    let result = server
+        .storage
+        .increment(
+            Meta::new(0, 0, unix_time(NOW + 30)),
+            key.clone(),
+            delta.clone(),
+        )
+        .unwrap();
+    assert_eq!(result.value, 10);
+
+    // expiration of existing value is kept
+    server.timer.set(NOW + 10);
+    let result = server
+        .storage
+        .increment(Meta::new(0, 0, 0), key.clone(), delta)
+        .unwrap();
+    assert_eq!(result.value, 11);
+    assert_eq!(
+        server.storage.get(&key).unwrap().header.time_to_live,
+        NOW + 30
+    );
+
+    server.timer.set(NOW + 30);
+    assert_eq!(server.storage.get(&key).unwrap_err(), CacheError::NotFound);
+}
+
+#[test_case(create_moka_server() ; "moka_backend")]
+#[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
+fn increment_should_create_value_with_relative_expiration(server: MockServer) {
+    server.timer.set(NOW);
+    let key = Bytes::from("counter");
+    let delta = DeltaParam { delta: 1, value: 0 };
+    server
+        .storage
+        .increment(Meta::new(0, 0, 5), key.clone(), delta)
+        .unwrap();
+
+    server.timer.set(NOW + 4);
+    assert!(server.storage.get(&key).is_ok());
+    server.timer.set(NOW + 5);
+    assert_eq!(server.storage.get(&key).unwrap_err(), CacheError::NotFound);
+}
+
+#[test_case(create_moka_server() ; "moka_backend")]
+#[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
+fn flush_with_unix_time_should_expire_items_at_that_time(server: MockServer) {
+    server.timer.set(NOW);
+    let key = Bytes::from("key");
+    server
+        .storage
+        .set(key.clone(), Record::new(from_string("value"), 0, 0, 0))
+        .unwrap();
+
+    server.storage.flush(Meta::new(0, 0, unix_time(NOW + 5)));
+    server.timer.set(NOW + 4);
+    assert!(server.storage.get(&key).is_ok());
+    server.timer.set(NOW + 5);
+    assert_eq!(server.storage.get(&key).unwrap_err(), CacheError::NotFound);
+}
+
+#[test_case(create_moka_server() ; "moka_backend")]
+#[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
+fn flush_with_past_unix_time_should_expire_items_immediately(server: MockServer) {
+    server.timer.set(NOW);
+    let key = Bytes::from("key");
+    server
+        .storage
+        .set(key.clone(), Record::new(from_string("value"), 0, 0, 0))
+        .unwrap();
+
+    server.storage.flush(Meta::new(0, 0, unix_time(NOW - 1)));
+    assert_eq!(server.storage.get(&key).unwrap_err(), CacheError::NotFound);
+}
+
+#[test_case(create_moka_server() ; "moka_backend")]
+#[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
+fn clock_jump_should_only_affect_unix_times(server: MockServer) {
+    server.timer.set(NOW);
+    let relative = Bytes::from("relative");
+    server
+        .storage
+        .set(
+            relative.clone(),
+            Record::new(from_string("value"), 0, 0, 10),
+        )
+        .unwrap();
+
+    // system clock moved one hour forward
+    server.timer.set_process_started(MOCK_UNIX_START + 3600);
+    let absolute = Bytes::from("absolute");
+    let record = Record::new(from_string("value"), 0, 0, unix_time(NOW + 3605));
+    server.storage.set(absolute.clone(), record).unwrap();
+
+    server.timer.set(NOW + 5);
+    assert!(server.storage.get(&relative).is_ok());
+    assert_eq!(
+        server.storage.get(&absolute).unwrap_err(),
+        CacheError::NotFound
+    );
+    server.timer.set(NOW + 10);
+    assert_eq!(
+        server.storage.get(&relative).unwrap_err(),
+        CacheError::NotFound
+    );
+}
@@ -5,6 +5,8 @@ use test_case::test_case;
 
 #[test_case(create_moka_server() ; "moka_backend")]
 #[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
 fn increment_if_counter_doesnt_exists_it_should_created(server: MockServer) {
     const COUNTER_INITIAL_VALUE: u64 = 5;
     let key = Bytes::from("counter1");
@@ -26,6 +28,8 @@ fn increment_if_counter_doesnt_exists_it_should_created(server: MockServer) {
 
 #[test_case(create_moka_server() ; "moka_backend")]
 #[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
 fn increment_should_fail_on_cas_mismatch(server: MockServer) {
     const COUNTER_INITIAL_VALUE: u64 = 5;
     let key = Bytes::from("counter1");
@@ -52,6 +56,8 @@ fn increment_should_fail_on_cas_mismatch(server: MockServer) {
 
 #[test_case(create_moka_server() ; "moka_backend")]
 #[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
 fn increment_if_expire_equals_ffffffff_counter_should_not_be_created(server: MockServer) {
     let key = Bytes::from("counter1");
     let counter = IncrementParam { delta: 0, value: 0 };
@@ -69,6 +75,8 @@ fn increment_if_expire_equals_ffffffff_counter_should_not_be_created(server: Moc
 
 #[test_case(create_moka_server() ; "moka_backend")]
 #[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
 fn increment_value_should_be_incremented(server: MockServer) {
     const DELTA: u64 = 6;
     const EXPECTED_RESULT: u64 = 5 + DELTA;
@@ -97,6 +105,8 @@ fn increment_value_should_be_incremented(server: MockServer) {
 
 #[test_case(create_moka_server() ; "moka_backend")]
 #[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
 fn increment_if_value_is_not_number_it_should_be_error(server: MockServer) {
     const DELTA: u64 = 5;
     let key = Bytes::from("counter1");
@@ -122,6 +132,8 @@ fn increment_if_value_is_not_number_it_should_be_error(server: MockServer) {
 
 #[test_case(create_moka_server() ; "moka_backend")]
 #[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
 fn increment_if_value_cannot_be_parsed_it_should_be_error(server: MockServer) {
     const DELTA: u64 = 5;
     let key = Bytes::from("counter1");
@@ -149,6 +161,8 @@ fn increment_if_value_cannot_be_parsed_it_should_be_error(server: MockServer) {
 
 #[test_case(create_moka_server() ; "moka_backend")]
 #[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
 fn decrement_should_fail_on_cas_mismatch(server: MockServer) {
     const COUNTER_INITIAL_VALUE: u64 = 5;
     let key = Bytes::from("counter1");
@@ -175,6 +189,8 @@ fn decrement_should_fail_on_cas_mismatch(server: MockServer) {
 
 #[test_case(create_moka_server() ; "moka_backend")]
 #[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
 fn decrement_should_not_result_in_negative_value(server: MockServer) {
     const DELTA: u64 = 1;
     let key = Bytes::from("counter1");
@@ -202,6 +218,8 @@ fn decrement_should_not_result_in_negative_value(server: MockServer) {
 
 #[test_case(create_moka_server() ; "moka_backend")]
 #[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
 fn decrement_value_should_be_decremented(server: MockServer) {
     const DELTA: u64 = 1;
     const EXPECTED_RESULT: u64 = 4;
@@ -0,0 +1,236 @@
+use crate::cache::cache::{KeyType, Record};
+use crate::cache::error::{CacheError, Result};
+use bytes::Bytes;
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use tokio::sync::OnceCell;
+
+/// Source of values for keys missing in the cache, loads run on the
+/// blocking pool so a loader may do blocking I/O
+pub trait Loader: Send + Sync {
+    /// Value of `key`, `CacheError::NotFound` if the source has none
+    fn load(&self, key: &KeyType) -> Result<Bytes>;
+}
+
+/// Result of a load shared by every request which missed the key
+type Flight = Arc<OnceCell<Option<Record>>>;
+
+/// Populates the cache from a loader on misses.
+///
+/// Concurrent misses of the same key await a single load instead of
+/// all of them hitting the loader, workers keep serving other clients
+/// while they wait.
+pub struct ReadThrough {
+    loader: Arc<dyn Loader>,
+    time_to_live: u32,
+    in_flight: Mutex<HashMap<KeyType, Flight>>,
+}
+
+impl ReadThrough {
+    /// Loaded items are stored with `time_to_live`
+    pub fn new(loader: Arc<dyn Loader>, time_to_live: u32) -> ReadThrough {
+        ReadThrough {
+            loader,
+            time_to_live,
+            in_flight: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn time_to_live(&self) -> u32 {
+        self.time_to_live
+    }
+
+    /// Value from the loader, failed loads are treated as misses
+    pub async fn load(&self, key: &KeyType) -> Option<Bytes> {
+        let loader = self.loader.clone();
+        let load_key = key.clone();
+        let result = tokio::task::spawn_blocking(move || loader.load(&load_key))
+            .await
+            .unwrap_or(Err(CacheError::InternalError));
+        match result {
+            Ok(value) => Some(value),
+            Err(CacheError::NotFound) => None,
+            Err(err) => {
+                log::warn!("Loading {:?} failed: {}", key, err.to_static_string());
+                None
+            }
+        }
+    }
+
+    /// Runs `load` once for all callers missing `key` at the same time,
+    /// callers arriving while it runs await its result. If the caller
+    /// running it goes away, one of the waiting callers loads instead.
+    pub async fn single_flight<F>(&self, key: &KeyType, load: impl FnOnce() -> F) -> Option<Record>
+    where
+        F: Future<Output = Option<Record>>,
+    {
+        let flight = self
+            .in_flight
+            .lock()
+            .unwrap()
+            .entry(key.clone())
+            .or_default()
+            .clone();
+        let _landing = Landing {
+            read_through: self,
+            key,
+            flight: &flight,
+        };
+        flight.get_or_init(load).await.clone()
+    }
+
+    /// Number of loads in progress
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.lock().unwrap().len()
+    }
+}
+
+/// Forgets the flight once it has a result or no caller is left, also
+/// when the caller was dropped while waiting
+struct Landing<'a> {
+    read_through: &'a ReadThrough,
+    key: &'a KeyType,
+    flight: &'a Flight,
+}
+
+impl Drop for Landing<'_> {
+    fn drop(&mut self) {
+        let mut in_flight = self.read_through.in_flight.lock().unwrap();
+        // new callers join the flight under the same lock
+        let done = self.flight.initialized() || Arc::strong_count(self.flight) == 2;
+        if done
+            && in_flight
+                .get(self.key)
+                .is_some_and(|current| Arc::ptr_eq(current, self.flight))
+        {
+            in_flight.remove(self.key);
+        }
+    }
+}
+
+/// Loads the value of a key from the file named after it, keys which
+/// are not valid file names are never found
+pub struct DirectoryLoader {
+    dir: PathBuf,
+}
+
+impl DirectoryLoader {
+    pub fn new(dir: impl Into<PathBuf>) -> DirectoryLoader {
+        DirectoryLoader { dir: dir.into() }
+    }
+
+    fn path(&self, key: &KeyType) -> Option<PathBuf> {
+        let name = std::str::from_utf8(key).ok()?;
+        if name.is_empty() || name == "." || name == ".." || name.contains(['/', '\\', '\0']) {
+            return None;
+        }
+        Some(self.dir.join(name))
+    }
+}
+
+impl Loader for DirectoryLoader {
+    fn load(&self, key: &KeyType) -> Result<Bytes> {
+        let path = self.path(key).ok_or(CacheError::NotFound)?;
+        match std::fs::read(&path) {
+            Ok(value) => Ok(Bytes::from(value)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Err(CacheError::NotFound),
+            Err(err) => {
+                log::warn!("Cannot read {}: {}", path.display(), err);
+                Err(CacheError::InternalError)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    struct TempDir {
+        path: PathBuf,
+    }
+
+    impl TempDir {
+        fn new(name: &str) -> TempDir {
+            let path =
+                std::env::temp_dir().join(format!("memcrs-loader-{}-{}", name, std::process::id()));
+            let _ = fs::remove_dir_all(&path);
+            fs::create_dir_all(&path).unwrap();
+            TempDir { path }
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.path);
+        }
+    }
+
+    #[test]
+    fn test_directory_loader_reads_files_named_after_keys() {
+        let dir = TempDir::new("files");
+        fs::write(dir.path.join("key"), "value").unwrap();
+        fs::create_dir(dir.path.join("sub")).unwrap();
+        fs::write(dir.path.join("sub").join("key"), "value").unwrap();
+        let loader = DirectoryLoader::new(&dir.path);
+
+        assert_eq!(loader.load(&Bytes::from("key")), Ok(Bytes::from("value")));
+        assert_eq!(
+            loader.load(&Bytes::from("missing")),
+            Err(CacheError::NotFound)
+        );
+        for key in ["sub/key", "..", ".", ""] {
+            assert_eq!(loader.load(&Bytes::from(key)), Err(CacheError::NotFound));
+        }
+        assert_eq!(
+            loader.load(&Bytes::from("sub")),
+            Err(CacheError::InternalError)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_single_flight_shares_result_of_one_load() {
+        let read_through = ReadThrough::new(Arc::new(DirectoryLoader::new("")), 0);
+        let key = Bytes::from("key");
+        let loads = AtomicUsize::new(0);
+        let leader = read_through.single_flight(&key, || async {
+            loads.fetch_add(1, Ordering::SeqCst);
+            // keeps the flight open until the other caller joined it
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            Some(Record::new(Bytes::from("value"), 0, 0, 0))
+        });
+        let follower =
+            read_through.single_flight(&key, || async { panic!("second load of the same key") });
+        let (leader, follower) = tokio::join!(leader, follower);
+        assert_eq!(loads.into_inner(), 1);
+        for result in [leader, follower] {
+            assert_eq!(result.unwrap().value, Bytes::from("value"));
+        }
+        assert_eq!(read_through.in_flight(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_waiting_caller_loads_when_loading_caller_goes_away() {
+        let read_through = ReadThrough::new(Arc::new(DirectoryLoader::new("")), 0);
+        let key = Bytes::from("key");
+        let leader = read_through.single_flight(&key, || async {
+            tokio::time::sleep(Duration::from_secs(10)).await;
+            None
+        });
+        let follower = read_through.single_flight(&key, || async {
+            Some(Record::new(Bytes::from("value"), 0, 0, 0))
+        });
+        let (leader, follower) = tokio::join!(
+            tokio::time::timeout(Duration::from_millis(50), leader),
+            follower
+        );
+        assert!(leader.is_err());
+        assert_eq!(follower.unwrap().value, Bytes::from("value"));
+        assert_eq!(read_through.in_flight(), 0);
+    }
+}
@@ -0,0 +1,158 @@
+use super::loader::{Loader, ReadThrough};
+use super::test_utils::*;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use test_case::test_case;
+
+struct MapLoader {
+    values: HashMap<KeyType, Bytes>,
+    loads: AtomicUsize,
+}
+
+impl Loader for MapLoader {
+    fn load(&self, key: &KeyType) -> Result<Bytes> {
+        self.loads.fetch_add(1, Ordering::SeqCst);
+        self.values.get(key).cloned().ok_or(CacheError::NotFound)
+    }
+}
+
+fn block_on<F: std::future::Future>(future: F) -> F::Output {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_time()
+        .build()
+        .unwrap()
+        .block_on(future)
+}
+
+fn loading_store(server: &MockServer, time_to_live: u32) -> (MemcStore, Arc<MapLoader>) {
+    let loader = Arc::new(MapLoader {
+        values: HashMap::from([
+            (Bytes::from("a"), Bytes::from("loaded a")),
+            (Bytes::from("b"), Bytes::from("loaded b")),
+        ]),
+        loads: AtomicUsize::new(0),
+    });
+    let store = MemcStore::new(server.store.clone()).with_loader(Some(Arc::new(ReadThrough::new(
+        loader.clone(),
+        time_to_live,
+    ))));
+    (store, loader)
+}
+
+#[test_case(create_moka_server() ; "moka_backend")]
+#[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
+fn miss_should_load_and_store_item(server: MockServer) {
+    let (store, loader) = loading_store(&server, 10);
+    let key = Bytes::from("a");
+    let record = block_on(store.get_async(&key)).unwrap();
+    assert_eq!(record.value, Bytes::from("loaded a"));
+    assert_ne!(record.header.cas, 0);
+    assert_eq!(store.get(&key).unwrap().header.cas, record.header.cas);
+    assert_eq!(loader.loads.load(Ordering::SeqCst), 1);
+
+    // loaded item expires and is loaded again
+    server.timer.set(10);
+    assert_eq!(store.get(&key), Err(CacheError::NotFound));
+    assert!(block_on(store.get_async(&key)).is_ok());
+    assert_eq!(loader.loads.load(Ordering::SeqCst), 2);
+
+    assert_eq!(
+        block_on(store.get_async(&Bytes::from("c"))),
+        Err(CacheError::NotFound)
+    );
+    assert_eq!(
+        server.store.get(&Bytes::from("c")),
+        Err(CacheError::NotFound)
+    );
+}
+
+#[test_case(create_moka_server() ; "moka_backend")]
+#[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
+fn stored_items_should_not_be_loaded(server: MockServer) {
+    let (store, loader) = loading_store(&server, 0);
+    store
+        .set(Bytes::from("a"), Record::new(from_string("set a"), 0, 0, 0))
+        .unwrap();
+
+    let keys = [Bytes::from("a"), Bytes::from("b"), Bytes::from("c")];
+    let results = block_on(store.get_multi_async(&keys));
+    assert_eq!(results[0].as_ref().unwrap().value, from_string("set a"));
+    assert_eq!(results[1].as_ref().unwrap().value, Bytes::from("loaded b"));
+    assert_eq!(results[2], Err(CacheError::NotFound));
+    assert_eq!(loader.loads.load(Ordering::SeqCst), 2);
+}
+
+#[test_case(create_moka_server() ; "moka_backend")]
+#[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
+fn concurrent_misses_should_load_once(server: MockServer) {
+    let (store, loader) = loading_store(&server, 0);
+    let store = Arc::new(store);
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(4)
+        .build()
+        .unwrap();
+    runtime.block_on(async {
+        let misses: Vec<_> = (0..8)
+            .map(|_| {
+                let store = store.clone();
+                tokio::spawn(async move { store.get_async(&Bytes::from("a")).await })
+            })
+            .collect();
+        for miss in misses {
+            assert!(miss.await.unwrap().is_ok());
+        }
+    });
+    // a miss after the first load finished finds the stored item
+    assert_eq!(loader.loads.load(Ordering::SeqCst), 1);
+}
+
+struct SlowLoader {
+    loads: AtomicUsize,
+}
+
+impl Loader for SlowLoader {
+    fn load(&self, _key: &KeyType) -> Result<Bytes> {
+        self.loads.fetch_add(1, Ordering::SeqCst);
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        Ok(Bytes::from("loaded"))
+    }
+}
+
+#[test_case(create_moka_server() ; "moka_backend")]
+#[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
+fn waiting_misses_should_not_block_the_worker(server: MockServer) {
+    let loader = Arc::new(SlowLoader {
+        loads: AtomicUsize::new(0),
+    });
+    let store = MemcStore::new(server.store.clone())
+        .with_loader(Some(Arc::new(ReadThrough::new(loader.clone(), 0))));
+    store
+        .set(Bytes::from("b"), Record::new(from_string("b"), 0, 0, 0))
+        .unwrap();
+    // one thread serves both misses and a hit while the load runs
+    block_on(async {
+        let key = Bytes::from("a");
+        let hit = async {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            let hit = store.get_async(&Bytes::from("b")).await;
+            (hit, std::time::Instant::now())
+        };
+        let (first, second, (hit, hit_at)) =
+            tokio::join!(store.get_async(&key), store.get_async(&key), hit);
+        assert!(hit.is_ok());
+        // the hit was answered while the load was still running
+        assert!(hit_at.elapsed() >= std::time::Duration::from_millis(50));
+        assert_eq!(first.unwrap().value, Bytes::from("loaded"));
+        assert_eq!(second.unwrap().value, Bytes::from("loaded"));
+    });
+    assert_eq!(loader.loads.load(Ordering::SeqCst), 1);
+}
@@ -1,34 +1,383 @@
 use crate::cache::cache::{
-    Cache, CacheMetaData as CacheMeta, DecrementParam, DeltaParam, DeltaResult, IncrementParam,
-    KeyType as CacheKeyType, Record as CacheRecord, SetStatus as CacheSetStatus,
+    Cache, CacheMetaData as CacheMeta, CacheStats, DecrementParam, DeltaParam, DeltaResult,
+    IncrementParam, KeyType as CacheKeyType, Record as CacheRecord, ScanPage,
+    SetStatus as CacheSetStatus,
 };
-use crate::cache::error::Result;
+use crate::cache::error::{CacheError, Result};
+use crate::memcache::hot_keys::HotKeys;
+use crate::persistence::aof::{KeyState, MutationLog};
+use crate::persistence::write_behind::{WriteBehind, WriteBehindStats};
+use crate::proxy::shadow::{Shadow, ShadowStats};
+use crate::replication::{Replication, ReplicationStats};
+use futures::future::join_all;
+use loader::ReadThrough;
 use std::sync::Arc;
+use tags::{TagIndex, TagType};
+use ttl_rules::TtlRules;
+
+pub mod loader;
+pub mod tags;
+pub mod ttl_rules;
 
 pub type Record = CacheRecord;
 pub type Meta = CacheMeta;
 pub type SetStatus = CacheSetStatus;
 pub type KeyType = CacheKeyType;
 
+/// Counters of features the server runs next to the store, reported by
+/// the stats command after the counters of the store
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ServerStats {
+    pub write_behind: Option<WriteBehindStats>,
+    pub shadow: Option<ShadowStats>,
+    pub replication: Option<ReplicationStats>,
+}
+
+impl ServerStats {
+    /// Name and value of every stat
+    pub fn records(&self) -> Vec<(String, String)> {
+        let mut records = Vec::new();
+        if let Some(write_behind) = &self.write_behind {
+            records.push((
+                "write_behind_queued".to_string(),
+                write_behind.queued.to_string(),
+            ));
+            records.push((
+                "write_behind_written".to_string(),
+                write_behind.written.to_string(),
+            ));
+            records.push((
+                "write_behind_dropped".to_string(),
+                write_behind.dropped.to_string(),
+            ));
+            records.push((
+                "write_behind_failed".to_string(),
+                write_behind.failed.to_string(),
+            ));
+        }
+        if let Some(shadow) = &self.shadow {
+            records.push(("shadow_queued".to_string(), shadow.queued.to_string()));
+            records.push(("shadow_mirrored".to_string(), shadow.mirrored.to_string()));
+            records.push(("shadow_dropped".to_string(), shadow.dropped.to_string()));
+            records.push(("shadow_failed".to_string(), shadow.failed.to_string()));
+        }
+        if let Some(replication) = &self.replication {
+            records.push(("replication_role".to_string(), replication.role.to_string()));
+            records.push((
+                "replication_offset".to_string(),
+                replication.offset.to_string(),
+            ));
+            records.push((
+                "replication_replicas".to_string(),
+                replication.replicas.to_string(),
+            ));
+            if let Some(primary) = &replication.primary {
+                records.push(("replication_primary".to_string(), primary.clone()));
+                records.push((
+                    "replication_link".to_string(),
+                    replication.link.unwrap_or_default().to_string(),
+                ));
+                records.push((
+                    "replication_applied".to_string(),
+                    replication.applied.to_string(),
+                ));
+                records.push((
+                    "replication_full_syncs".to_string(),
+                    replication.full_syncs.to_string(),
+                ));
+                if let Some(lag_ms) = replication.lag_ms {
+                    records.push(("replication_lag_ms".to_string(), lag_ms.to_string()));
+                }
+            }
+        }
+        records
+    }
+}
+
 /**
  * Implements Memcache commands based
  * on Key Value Store
  */
 pub struct MemcStore {
     store: Arc<dyn Cache + Send + Sync>,
+    log: Option<Arc<MutationLog>>,
+    tags: Arc<TagIndex>,
+    hot_keys: Option<Arc<HotKeys>>,
+    ttl_rules: Option<Arc<TtlRules>>,
+    loader: Option<Arc<ReadThrough>>,
+    write_behind: Option<Arc<WriteBehind>>,
+    replication: Option<Arc<Replication>>,
+    shadow: Option<Arc<Shadow>>,
 }
 
 impl MemcStore {
     pub fn new(store: Arc<dyn Cache + Send + Sync>) -> MemcStore {
-        MemcStore { store }
+        Self::with_mutation_log(store, None)
+    }
+
+    /// Store recording every mutation in a mutation log
+    pub fn with_mutation_log(
+        store: Arc<dyn Cache + Send + Sync>,
+        log: Option<Arc<MutationLog>>,
+    ) -> MemcStore {
+        MemcStore {
+            store,
+            log,
+            tags: Arc::new(TagIndex::new()),
+            hot_keys: None,
+            ttl_rules: None,
+            loader: None,
+            write_behind: None,
+            replication: None,
+            shadow: None,
+        }
+    }
+
+    /// Shares item tags with other stores serving the same cache, the
+    /// store enabling the index subscribes it to the cache
+    pub fn with_tag_index(mut self, tags: Arc<TagIndex>) -> MemcStore {
+        self.tags = tags;
+        self
+    }
+
+    /// Hot key tracker of handlers serving this store
+    pub fn with_hot_keys(mut self, hot_keys: Option<Arc<HotKeys>>) -> MemcStore {
+        self.hot_keys = hot_keys;
+        self
+    }
+
+    pub fn hot_keys(&self) -> Option<Arc<HotKeys>> {
+        self.hot_keys.clone()
+    }
+
+    /// Rules limiting TTL and size of written items
+    pub fn with_ttl_rules(mut self, ttl_rules: Option<Arc<TtlRules>>) -> MemcStore {
+        self.ttl_rules = ttl_rules;
+        self
+    }
+
+    /// Loads missing items on reads, stores sharing the loader share
+    /// its in-flight loads
+    pub fn with_loader(mut self, loader: Option<Arc<ReadThrough>>) -> MemcStore {
+        self.loader = loader;
+        self
+    }
+
+    /// Hands sets and deletes done by clients to a write-behind sink
+    pub fn with_write_behind(mut self, write_behind: Option<Arc<WriteBehind>>) -> MemcStore {
+        self.write_behind = write_behind;
+        self
+    }
+
+    /// Publishes mutations to replicas, or marks the store of a replica
+    pub fn with_replication(mut self, replication: Option<Arc<Replication>>) -> MemcStore {
+        self.replication = replication;
+        self
+    }
+
+    /// Shadow server handlers serving this store mirror requests to
+    pub fn with_shadow(mut self, shadow: Option<Arc<Shadow>>) -> MemcStore {
+        self.shadow = shadow;
+        self
+    }
+
+    pub fn shadow(&self) -> Option<Arc<Shadow>> {
+        self.shadow.clone()
+    }
+
+    /// Clients cannot write to a replica until it is promoted
+    pub fn is_read_only(&self) -> bool {
+        self.replication
+            .as_ref()
+            .is_some_and(|replication| replication.is_read_only())
+    }
+
+    fn limit(&self, key: &KeyType, record: &mut Record) -> Result<()> {
+        match &self.ttl_rules {
+            Some(rules) => rules.apply(key, record),
+            None => Ok(()),
+        }
+    }
+
+    /// Applies a mutation, logging the state `state` says it left the
+    /// key in and publishing it to replicas
+    fn logged<T>(
+        &self,
+        key: KeyType,
+        mutation: impl FnOnce(KeyType) -> Result<T>,
+        state: impl Fn(&T) -> KeyState,
+    ) -> Result<T> {
+        let apply = |key: KeyType| match &self.log {
+            Some(log) => log.write(self.store.as_ref(), &key.clone(), || mutation(key), &state),
+            None => mutation(key),
+        };
+        match &self.replication {
+            Some(replication) => {
+                replication.write(self.store.as_ref(), &key.clone(), || apply(key), &state)
+            }
+            None => apply(key),
+        }
+    }
+
+    /// Applies a mutation done by a client like `logged`, and queues the
+    /// state it left the key in for the write-behind sink before another
+    /// mutation of the key is applied
+    fn written_behind<T>(
+        &self,
+        key: KeyType,
+        mutation: impl FnOnce(KeyType) -> Result<T>,
+        state: impl Fn(&T) -> KeyState,
+    ) -> Result<T> {
+        match &self.write_behind {
+            Some(write_behind) => write_behind.write(
+                self.store.as_ref(),
+                &key.clone(),
+                || self.logged(key, mutation, &state),
+                &state,
+            ),
+            None => self.logged(key, mutation, state),
+        }
+    }
+
+    /// Waits until mutations done so far are durable, see `MutationLog::durable`
+    pub async fn durable(&self) -> Result<()> {
+        match &self.log {
+            Some(log) => log.durable().await,
+            None => Ok(()),
+        }
+    }
+
+    /// Logged, replicated and written behind mutations are applied one by one
+    fn applied_one_by_one(&self) -> bool {
+        self.log.is_some() || self.replication.is_some() || self.write_behind.is_some()
+    }
+
+    /// Writes an item and remembers its tags, the item is invalid once
+    /// any of them is invalidated. Items written by clients are written
+    /// behind, loaded items came from there.
+    fn tagged(
+        &self,
+        key: KeyType,
+        mut record: Record,
+        tags: Vec<TagType>,
+        loaded: bool,
+        write: impl FnOnce(KeyType, Record) -> Result<SetStatus>,
+    ) -> Result<SetStatus> {
+        self.limit(&key, &mut record)?;
+        self.remove_invalidated(&key);
+        if !tags.is_empty() && self.tags.enable() {
+            self.store.subscribe(self.tags.clone());
+        }
+        let tags = self.tags.current(tags);
+        let written = record.clone();
+        let state = |status: &SetStatus| KeyState::written(written.clone(), status.cas);
+        let result = match loaded {
+            true => self.logged(key.clone(), |key| write(key, record), state),
+            false => self.written_behind(key.clone(), |key| write(key, record), state),
+        };
+        match result {
+            Ok(_) => self.tags.insert(key, tags),
+            Err(_) => self.tags.release(tags),
+        }
+        result
+    }
+
+    /// Deletes item whose tag was invalidated, so writes see it as missing
+    fn remove_invalidated(&self, key: &KeyType) {
+        if self.tags.is_valid(key) {
+            return;
+        }
+        match self.store.get(key) {
+            Ok(record) => self.expire(key, &record),
+            Err(_) => self.tags.remove(key),
+        }
+    }
+
+    /// Deletes invalidated item unless it was written in the meantime
+    fn expire(&self, key: &KeyType, record: &Record) {
+        let header = Meta::new(record.header.cas, 0, 0);
+        if self
+            .logged(
+                key.clone(),
+                |key| self.store.delete(key, header),
+                |_| KeyState::Deleted,
+            )
+            .is_ok()
+        {
+            self.tags.remove(key);
+        }
+    }
+
+    fn valid(&self, key: &KeyType, result: Result<Record>) -> Result<Record> {
+        let record = result?;
+        if self.tags.is_valid(key) {
+            return Ok(record);
+        }
+        self.expire(key, &record);
+        Err(CacheError::NotFound)
+    }
+
+    async fn read_through(&self, key: &KeyType, result: Result<Record>) -> Result<Record> {
+        match (&self.loader, result) {
+            (Some(loader), Err(CacheError::NotFound)) => loader
+                .single_flight(key, || self.load(loader, key))
+                .await
+                .ok_or(CacheError::NotFound),
+            (_, result) => result,
+        }
+    }
+
+    /// Stores value from the loader, a value written by a client while
+    /// it was loading wins
+    async fn load(&self, loader: &ReadThrough, key: &KeyType) -> Option<Record> {
+        if let Ok(record) = self.valid(key, self.stored(key).await) {
+            return Some(record);
+        }
+        let record = Record::new(loader.load(key).await?, 0, 0, loader.time_to_live());
+        let _ = self.tagged(
+            key.clone(),
+            record.clone(),
+            Vec::new(),
+            true,
+            |key, record| self.store.add(key, record),
+        );
+        // the stored record carries its CAS
+        self.stored(key).await.ok().or(Some(record))
+    }
+
+    /// Reads values kept outside of memory without blocking the worker
+    async fn stored(&self, key: &KeyType) -> Result<Record> {
+        match self.store.reads_outside_memory() {
+            true => self.store.get_async(key).await,
+            false => self.store.get(key),
+        }
     }
 
     pub fn set(&self, key: KeyType, record: Record) -> Result<SetStatus> {
-        self.store.set(key, record)
+        self.set_with_tags(key, record, Vec::new())
     }
 
+    pub fn set_with_tags(
+        &self,
+        key: KeyType,
+        record: Record,
+        tags: Vec<TagType>,
+    ) -> Result<SetStatus> {
+        self.tagged(key, record, tags, false, |key, record| {
+            self.store.set(key, record)
+        })
+    }
+
+    /// Misses are not loaded, requests of clients are served by `get_async`
     pub fn get(&self, key: &KeyType) -> Result<Record> {
-        self.store.get(key)
+        self.valid(key, self.store.get(key))
+    }
+
+    /// Like `get`, but a value kept outside of memory is read without
+    /// blocking the worker and misses are loaded by the loader
+    pub async fn get_async(&self, key: &KeyType) -> Result<Record> {
+        let result = self.valid(key, self.stored(key).await);
+        self.read_through(key, result).await
     }
 
     // fn touch_record(&self, _record: &mut Record) {
@@ -36,19 +385,84 @@ impl MemcStore {
     // }
 
     pub fn add(&self, key: KeyType, record: Record) -> Result<SetStatus> {
-        self.store.add(key, record)
+        self.add_with_tags(key, record, Vec::new())
+    }
+
+    pub fn add_with_tags(
+        &self,
+        key: KeyType,
+        record: Record,
+        tags: Vec<TagType>,
+    ) -> Result<SetStatus> {
+        self.tagged(key, record, tags, false, |key, record| {
+            self.store.add(key, record)
+        })
     }
 
     pub fn replace(&self, key: KeyType, record: Record) -> Result<SetStatus> {
-        self.store.replace(key, record)
+        self.replace_with_tags(key, record, Vec::new())
+    }
+
+    pub fn replace_with_tags(
+        &self,
+        key: KeyType,
+        record: Record,
+        tags: Vec<TagType>,
+    ) -> Result<SetStatus> {
+        self.tagged(key, record, tags, false, |key, record| {
+            self.store.replace(key, record)
+        })
     }
 
+    /// Appended item keeps its tags
     pub fn append(&self, key: KeyType, new_record: Record) -> Result<SetStatus> {
-        self.store.append(key, new_record)
+        self.remove_invalidated(&key);
+        self.written_behind(
+            key,
+            |key| self.concatenate(key, new_record, true),
+            |_| KeyState::Stored,
+        )
     }
 
     pub fn prepend(&self, key: KeyType, new_record: Record) -> Result<SetStatus> {
-        self.store.prepend(key, new_record)
+        self.remove_invalidated(&key);
+        self.written_behind(
+            key,
+            |key| self.concatenate(key, new_record, false),
+            |_| KeyState::Stored,
+        )
+    }
+
+    /// Appends or prepends unless the combined value would be larger than
+    /// max_size of the key's rule. The checked value is pinned by its CAS,
+    /// the check is repeated if it changed before the store was written.
+    fn concatenate(&self, key: KeyType, new_record: Record, append: bool) -> Result<SetStatus> {
+        let concatenate = |key, record| match append {
+            true => self.store.append(key, record),
+            false => self.store.prepend(key, record),
+        };
+        let max_size = match self
+            .ttl_rules
+            .as_ref()
+            .and_then(|rules| rules.max_size(&key))
+        {
+            Some(max_size) => max_size,
+            None => return concatenate(key, new_record),
+        };
+        loop {
+            let current = self.store.get(&key)?;
+            if (current.value.len() + new_record.value.len()) as u64 > max_size {
+                return Err(CacheError::ValueTooLarge);
+            }
+            let mut record = new_record.clone();
+            if record.header.cas == 0 {
+                record.header.cas = current.header.cas;
+            }
+            match concatenate(key.clone(), record) {
+                Err(CacheError::KeyExists) if new_record.header.cas == 0 => continue,
+                result => return result,
+            }
+        }
     }
 
     pub fn increment(
@@ -71,20 +485,176 @@ impl MemcStore {
 
     fn add_delta(
         &self,
-        header: Meta,
+        mut header: Meta,
         key: KeyType,
         delta: DeltaParam,
         increment: bool,
     ) -> Result<DeltaResult> {
-        self.store.incr_decr(header, key, delta, increment)
+        if let Some(rules) = &self.ttl_rules {
+            rules.apply_to_header(&key, &mut header);
+        }
+        self.remove_invalidated(&key);
+        self.written_behind(
+            key,
+            |key| self.store.incr_decr(header, key, delta, increment),
+            |_| KeyState::Stored,
+        )
     }
 
     pub fn delete(&self, key: KeyType, header: Meta) -> Result<Record> {
-        self.store.delete(key, header)
+        self.remove_invalidated(&key);
+        let record = self.written_behind(
+            key.clone(),
+            |key| self.store.delete(key, header),
+            |_| KeyState::Deleted,
+        )?;
+        self.tags.remove(&key);
+        Ok(record)
+    }
+
+    /// Results are in the order of `keys`, misses are not loaded
+    pub fn get_multi(&self, keys: &[KeyType]) -> Vec<Result<Record>> {
+        self.store
+            .get_multi(keys)
+            .into_iter()
+            .zip(keys)
+            .map(|(result, key)| self.valid(key, result))
+            .collect()
+    }
+
+    /// Like `get_multi`, keys are read one by one if values may be
+    /// outside of memory, missing keys are loaded concurrently
+    pub async fn get_multi_async(&self, keys: &[KeyType]) -> Vec<Result<Record>> {
+        let mut results = match self.store.reads_outside_memory() {
+            false => self.get_multi(keys),
+            true => {
+                let mut results = Vec::with_capacity(keys.len());
+                for key in keys {
+                    results.push(self.valid(key, self.stored(key).await));
+                }
+                results
+            }
+        };
+        if self.loader.is_none() {
+            return results;
+        }
+        let missing: Vec<usize> = (0..keys.len())
+            .filter(|&position| results[position] == Err(CacheError::NotFound))
+            .collect();
+        let loads = missing
+            .iter()
+            .map(|&position| self.read_through(&keys[position], Err(CacheError::NotFound)));
+        for (position, result) in missing.iter().zip(join_all(loads).await) {
+            results[*position] = result;
+        }
+        results
+    }
+
+    /// With a mutation log, replicas or a write-behind sink items are
+    /// applied one by one, so they see mutations in the order they were
+    /// applied in
+    pub fn set_multi(&self, items: Vec<(KeyType, Record)>) -> Vec<Result<SetStatus>> {
+        match self.applied_one_by_one() {
+            true => items
+                .into_iter()
+                .map(|(key, record)| self.set(key, record))
+                .collect(),
+            false => {
+                let mut accepted = Vec::with_capacity(items.len());
+                let mut rejected = Vec::new();
+                for (idx, (key, mut record)) in items.into_iter().enumerate() {
+                    match self.limit(&key, &mut record) {
+                        Ok(()) => accepted.push((key, record)),
+                        Err(err) => rejected.push((idx, err)),
+                    }
+                }
+                let keys: Vec<KeyType> = accepted.iter().map(|(key, _)| key.clone()).collect();
+                let mut results = self.store.set_multi(accepted);
+                for (key, result) in keys.into_iter().zip(&results) {
+                    if result.is_ok() {
+                        self.tags.insert(key, Vec::new());
+                    }
+                }
+                for (idx, err) in rejected {
+                    results.insert(idx, Err(err));
+                }
+                results
+            }
+        }
+    }
+
+    pub fn delete_multi(&self, items: Vec<(KeyType, Meta)>) -> Vec<Result<Record>> {
+        match self.applied_one_by_one() {
+            true => items
+                .into_iter()
+                .map(|(key, header)| self.delete(key, header))
+                .collect(),
+            false => {
+                for (key, _) in &items {
+                    self.remove_invalidated(key);
+                }
+                let keys: Vec<KeyType> = items.iter().map(|(key, _)| key.clone()).collect();
+                let results = self.store.delete_multi(items);
+                for (key, result) in keys.iter().zip(&results) {
+                    if result.is_ok() {
+                        self.tags.remove(key);
+                    }
+                }
+                results
+            }
+        }
+    }
+
+    /// Items of invalidated tags are left out, a page may get shorter
+    pub fn scan(&self, cursor: u64, count: usize, prefix: &[u8]) -> Result<ScanPage> {
+        let mut page = self.store.scan(cursor, count, prefix)?;
+        if !self.tags.is_empty() {
+            page.items.retain(|(key, _)| self.tags.is_valid(key));
+        }
+        Ok(page)
+    }
+
+    /// Logically expires every item stored with `tag`
+    pub fn invalidate_tag(&self, tag: TagType) {
+        self.tags.invalidate(tag)
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let mut stats = CacheStats::default();
+        self.store.add_stats(&mut stats);
+        stats
+    }
+
+    pub fn server_stats(&self) -> ServerStats {
+        ServerStats {
+            write_behind: self
+                .write_behind
+                .as_ref()
+                .map(|write_behind| write_behind.stats()),
+            shadow: self.shadow.as_ref().map(|shadow| shadow.stats()),
+            replication: self
+                .replication
+                .as_ref()
+                .map(|replication| replication.stats()),
+        }
     }
 
     pub fn flush(&self, header: Meta) {
-        self.store.flush(header)
+        let immediate = header.time_to_live == 0;
+        let flush = |header: Meta| match &self.log {
+            Some(log) => {
+                // flush is applied even if it could not be logged
+                let _ = log.flush(self.store.as_ref(), header);
+            }
+            None => self.store.flush(header),
+        };
+        match &self.replication {
+            Some(replication) => replication.flush(header, flush),
+            None => flush(header),
+        }
+        if immediate {
+            self.tags.clear();
+        }
     }
 }
 
@@ -93,22 +663,50 @@ mod add_tests;
 #[cfg(test)]
 mod append_prepend_tests;
 #[cfg(test)]
+mod cas_tests;
+#[cfg(test)]
+mod compression_tests;
+#[cfg(test)]
 mod delete_tests;
 #[cfg(test)]
+mod events_tests;
+#[cfg(test)]
+mod eviction_policy_tests;
+#[cfg(test)]
+mod expiration_tests;
+#[cfg(test)]
 mod flush_tests;
 #[cfg(test)]
 mod increment_decrement_tests;
 #[cfg(test)]
+mod loader_tests;
+#[cfg(test)]
+mod multi_tests;
+#[cfg(test)]
 mod replace_tests;
 #[cfg(test)]
+mod scan_tests;
+#[cfg(test)]
 mod set_tests;
+#[cfg(test)]
+mod snapshot_tests;
+#[cfg(test)]
+mod tags_tests;
+#[cfg(test)]
+mod tiered_tests;
+#[cfg(test)]
+mod ttl_rules_tests;
+#[cfg(test)]
+mod write_behind_tests;
 
 #[cfg(test)]
 mod test_utils {
     pub use super::*;
     pub use crate::cache::error::CacheError;
     pub use crate::mock::mock_server::{
-        create_dash_map_server, create_moka_server, MockServer, SetableTimer,
+        create_dash_map_server, create_dash_map_server_with_config, create_moka_server,
+        create_moka_server_with_config, create_sieve_server, create_sieve_server_with_config,
+        create_slab_server, MockServer, SetableTimer,
     };
     pub use crate::mock::value::{from_slice, from_string};
     pub use bytes::{BufMut, Bytes, BytesMut};
@@ -0,0 +1,225 @@
+use super::test_utils::*;
+use crate::cache::events::{CacheEvent, ChannelListener, RemovalReason};
+use crate::cache::eviction_policy::EvictionPolicy;
+use crate::memcache::cli::parser::{DashMapConfig, MokaConfig};
+use test_case::test_case;
+
+fn keys(count: usize) -> Vec<KeyType> {
+    (0..count)
+        .map(|idx| Bytes::from(format!("key{}", idx)))
+        .collect()
+}
+
+fn set_all(server: &MockServer, keys: &[KeyType]) -> Vec<Result<SetStatus>> {
+    let items = keys
+        .iter()
+        .map(|key| (key.clone(), Record::new(key.clone(), 0, 0, 0)))
+        .collect();
+    server.storage.set_multi(items)
+}
+
+#[test_case(create_moka_server() ; "moka_backend")]
+#[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
+fn get_multi_should_return_values_in_order_of_keys(server: MockServer) {
+    let keys = keys(100);
+    let statuses = set_all(&server, &keys);
+    assert!(statuses.iter().all(|status| status.is_ok()));
+
+    let mut lookup: Vec<KeyType> = keys.iter().rev().cloned().collect();
+    lookup.insert(50, Bytes::from("missing"));
+    let results = server.storage.get_multi(&lookup);
+    assert_eq!(results.len(), lookup.len());
+    for (key, result) in lookup.iter().zip(results) {
+        match server.storage.get(key) {
+            Ok(record) => {
+                let found = result.unwrap();
+                assert_eq!(&found.value, key);
+                assert_eq!(found.header.cas, record.header.cas);
+            }
+            Err(err) => assert_eq!(result.unwrap_err(), err),
+        }
+    }
+}
+
+#[test_case(create_moka_server() ; "moka_backend")]
+#[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
+fn get_multi_should_return_duplicated_keys(server: MockServer) {
+    let key = Bytes::from("key");
+    server
+        .storage
+        .set(key.clone(), Record::new(from_string("value"), 0, 0, 0))
+        .unwrap();
+    let results = server.storage.get_multi(&[key.clone(), key]);
+    assert_eq!(results.len(), 2);
+    assert!(results
+        .iter()
+        .all(|result| result.as_ref().unwrap().value == from_string("value")));
+}
+
+#[test_case(create_moka_server() ; "moka_backend")]
+#[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
+fn get_multi_should_not_return_expired_values(server: MockServer) {
+    server.timer.set(100);
+    let expiring = Bytes::from("expiring");
+    let kept = Bytes::from("kept");
+    server
+        .storage
+        .set(expiring.clone(), Record::new(from_string("a"), 0, 0, 5))
+        .unwrap();
+    server
+        .storage
+        .set(kept.clone(), Record::new(from_string("b"), 0, 0, 0))
+        .unwrap();
+
+    server.timer.set(105);
+    let results = server.storage.get_multi(&[expiring.clone(), kept]);
+    assert_eq!(results[0], Err(CacheError::NotFound));
+    assert_eq!(results[1].as_ref().unwrap().value, from_string("b"));
+    assert_eq!(server.storage.get(&expiring), Err(CacheError::NotFound));
+}
+
+#[test_case(create_moka_server() ; "moka_backend")]
+#[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
+fn set_multi_should_check_cas_of_every_item(server: MockServer) {
+    let keys = keys(3);
+    let statuses = set_all(&server, &keys);
+    let cas = statuses[1].as_ref().unwrap().cas;
+
+    let items = vec![
+        (keys[0].clone(), Record::new(from_string("new"), 0, 0, 0)),
+        (keys[1].clone(), Record::new(from_string("new"), cas, 0, 0)),
+        (keys[2].clone(), Record::new(from_string("new"), cas, 0, 0)),
+    ];
+    let results = server.storage.set_multi(items);
+    assert!(results[0].is_ok());
+    assert_ne!(results[1].as_ref().unwrap().cas, cas);
+    assert_eq!(results[2].as_ref().unwrap_err(), &CacheError::KeyExists);
+
+    assert_eq!(server.storage.get(&keys[0]).unwrap().value, "new");
+    assert_eq!(server.storage.get(&keys[1]).unwrap().value, "new");
+    assert_eq!(server.storage.get(&keys[2]).unwrap().value, keys[2]);
+}
+
+#[test_case(create_moka_server() ; "moka_backend")]
+#[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
+fn delete_multi_should_return_deleted_records(server: MockServer) {
+    let keys = keys(50);
+    let statuses = set_all(&server, &keys);
+
+    let mut items: Vec<(KeyType, Meta)> = keys
+        .iter()
+        .map(|key| (key.clone(), Meta::new(0, 0, 0)))
+        .collect();
+    // wrong cas
+    let wrong_cas = statuses[0].as_ref().unwrap().cas + 1;
+    items[0].1 = Meta::new(wrong_cas, 0, 0);
+    items.push((Bytes::from("missing"), Meta::new(0, 0, 0)));
+
+    let results = server.storage.delete_multi(items);
+    assert_eq!(results[0], Err(CacheError::KeyExists));
+    for (key, result) in keys.iter().zip(&results).skip(1) {
+        assert_eq!(&result.as_ref().unwrap().value, key);
+        assert_eq!(server.storage.get(key), Err(CacheError::NotFound));
+    }
+    assert_eq!(results[50], Err(CacheError::NotFound));
+    assert!(server.storage.get(&keys[0]).is_ok());
+}
+
+fn create_no_evict_moka_server() -> MockServer {
+    create_moka_server_with_config(MokaConfig {
+        max_capacity: 2,
+        eviction_policy: EvictionPolicy::None,
+    })
+}
+
+// holds exactly two items of `keys`
+fn create_no_evict_dash_map_server() -> MockServer {
+    let item_size = (b"key0".len() + Meta::new(0, 0, 0).len() + b"key0".len()) as u64;
+    create_dash_map_server_with_config(DashMapConfig {
+        memory_limit: 2 * item_size,
+        reject_when_full: true,
+    })
+}
+
+#[test_case(create_no_evict_moka_server() ; "moka_backend")]
+#[test_case(create_no_evict_dash_map_server() ; "dash_map_backend")]
+fn set_multi_should_account_memory_of_every_item(server: MockServer) {
+    let mut keys = keys(3);
+    let results = set_all(&server, &keys);
+    // items may be written shard by shard, any of them can be rejected
+    let rejected: Vec<usize> = (0..3).filter(|idx| results[*idx].is_err()).collect();
+    assert_eq!(rejected.len(), 1);
+    assert_eq!(
+        results[rejected[0]].as_ref().unwrap_err(),
+        &CacheError::OutOfMemory
+    );
+
+    // overwrites fit, deleted items make room for new ones
+    let rejected = keys.remove(rejected[0]);
+    let results = server
+        .storage
+        .delete_multi(vec![(keys[0].clone(), Meta::new(0, 0, 0))]);
+    assert!(results[0].is_ok());
+    let results = set_all(&server, &[keys[1].clone(), rejected.clone()]);
+    assert!(results.iter().all(|result| result.is_ok()));
+    assert_eq!(server.storage.get(&rejected).unwrap().value, rejected);
+}
+
+#[test_case(create_no_evict_moka_server() ; "moka_backend")]
+#[test_case(create_no_evict_dash_map_server() ; "dash_map_backend")]
+fn set_multi_should_reclaim_expired_items(server: MockServer) {
+    let keys = keys(3);
+    let items = keys[..2]
+        .iter()
+        .map(|key| (key.clone(), Record::new(key.clone(), 0, 0, 5)))
+        .collect();
+    assert!(server
+        .storage
+        .set_multi(items)
+        .iter()
+        .all(|result| result.is_ok()));
+
+    server.timer.set(5);
+    let results = set_all(&server, &keys[2..]);
+    assert!(results[0].is_ok());
+}
+
+#[test_case(create_moka_server() ; "moka_backend")]
+#[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
+fn multi_operations_should_report_removed_items(server: MockServer) {
+    let (listener, events) = ChannelListener::bounded(100);
+    server.store.subscribe(listener);
+    let keys = keys(2);
+    set_all(&server, &keys);
+    set_all(&server, &keys[..1]);
+    server
+        .storage
+        .delete_multi(vec![(keys[1].clone(), Meta::new(0, 0, 0))]);
+
+    let received: Vec<CacheEvent> = events.try_iter().collect();
+    assert_eq!(
+        received,
+        vec![
+            CacheEvent::Removed {
+                key: keys[0].clone(),
+                reason: RemovalReason::Replaced
+            },
+            CacheEvent::Removed {
+                key: keys[1].clone(),
+                reason: RemovalReason::Explicit
+            },
+        ]
+    );
+}
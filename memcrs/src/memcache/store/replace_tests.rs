@@ -3,6 +3,8 @@ use test_case::test_case;
 
 #[test_case(create_moka_server() ; "moka_backend")]
 #[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
 fn replace_should_fail_if_not_stored(server: MockServer) {
     let key = Bytes::from("key");
     let record = Record::new(from_string("test data"), 5, 0, 0);
@@ -15,6 +17,8 @@ fn replace_should_fail_if_not_stored(server: MockServer) {
 
 #[test_case(create_moka_server() ; "moka_backend")]
 #[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
 fn replace_should_succeed_if_stored(server: MockServer) {
     let key = Bytes::from("key");
     let record = Record::new(from_string("test data"), 0, 0, 0);
@@ -32,6 +36,8 @@ fn replace_should_succeed_if_stored(server: MockServer) {
 
 #[test_case(create_moka_server() ; "moka_backend")]
 #[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
 fn replace_should_fail_on_cas_mismatch(server: MockServer) {
     let key = Bytes::from("key");
     let record = Record::new(from_string("test data"), 0, 0, 0);
@@ -0,0 +1,140 @@
+use super::test_utils::*;
+use crate::mock::mock_server::MOCK_UNIX_START;
+use std::collections::HashSet;
+use test_case::test_case;
+
+fn insert_keys(server: &MockServer, prefix: &str, count: usize) {
+    for idx in 0..count {
+        let key = Bytes::from(format!("{}{}", prefix, idx));
+        server
+            .storage
+            .set(key, Record::new(from_string("value"), 0, 0, 0))
+            .unwrap();
+    }
+}
+
+fn scan_all(server: &MockServer, count: usize, prefix: &[u8]) -> Vec<(KeyType, Record)> {
+    let mut items = Vec::new();
+    let mut cursor = 0;
+    loop {
+        let page = server.storage.scan(cursor, count, prefix).unwrap();
+        assert!(page.items.len() <= count);
+        items.extend(page.items);
+        if page.cursor == 0 {
+            return items;
+        }
+        cursor = page.cursor;
+    }
+}
+
+#[test_case(create_moka_server() ; "moka_backend")]
+#[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
+fn scan_should_return_every_key_once(server: MockServer) {
+    insert_keys(&server, "key", 500);
+    server.store.run_pending_tasks();
+
+    let items = scan_all(&server, 37, b"");
+    let keys: HashSet<KeyType> = items.iter().map(|(key, _)| key.clone()).collect();
+    assert_eq!(items.len(), 500);
+    assert_eq!(keys.len(), 500);
+    for (key, record) in items {
+        assert_eq!(record.value, from_string("value"));
+        assert_eq!(
+            record.header.cas,
+            server.storage.get(&key).unwrap().header.cas
+        );
+    }
+}
+
+#[test_case(create_moka_server() ; "moka_backend")]
+#[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
+fn scan_should_filter_keys_by_prefix(server: MockServer) {
+    insert_keys(&server, "user:", 40);
+    insert_keys(&server, "session:", 60);
+    server.store.run_pending_tasks();
+
+    let items = scan_all(&server, 7, b"user:");
+    assert_eq!(items.len(), 40);
+    assert!(items.iter().all(|(key, _)| key.starts_with(b"user:")));
+}
+
+#[test_case(create_moka_server() ; "moka_backend")]
+#[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
+fn scan_should_skip_expired_items_and_return_unix_expiration(server: MockServer) {
+    server.timer.set(100);
+    server
+        .storage
+        .set(
+            Bytes::from("expiring"),
+            Record::new(from_string("a"), 0, 0, 5),
+        )
+        .unwrap();
+    server
+        .storage
+        .set(
+            Bytes::from("later"),
+            Record::new(from_string("b"), 0, 0, 60),
+        )
+        .unwrap();
+    server
+        .storage
+        .set(Bytes::from("never"), Record::new(from_string("c"), 0, 0, 0))
+        .unwrap();
+    server.store.run_pending_tasks();
+
+    server.timer.set(105);
+    let mut items = scan_all(&server, 10, b"");
+    items.sort_by(|(first, _), (second, _)| first.cmp(second));
+    assert_eq!(items.len(), 2);
+    assert_eq!(items[0].0, Bytes::from("later"));
+    assert_eq!(items[0].1.header.time_to_live as u64, MOCK_UNIX_START + 160);
+    assert_eq!(items[1].0, Bytes::from("never"));
+    assert_eq!(items[1].1.header.time_to_live, 0);
+}
+
+#[test_case(create_moka_server() ; "moka_backend")]
+#[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
+fn scan_of_empty_store_should_be_done_at_once(server: MockServer) {
+    let page = server.storage.scan(0, 10, b"").unwrap();
+    assert!(page.items.is_empty());
+    assert_eq!(page.cursor, 0);
+    assert_eq!(
+        server.storage.scan(u64::MAX, 10, b"").unwrap_err(),
+        CacheError::InvalidArguments
+    );
+}
+
+#[test_case(create_moka_server() ; "moka_backend")]
+#[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
+fn scan_should_continue_after_other_scans(server: MockServer) {
+    insert_keys(&server, "key", 200);
+    server.store.run_pending_tasks();
+
+    let mut keys = HashSet::new();
+    let mut cursor = 0;
+    loop {
+        let page = server.storage.scan(cursor, 10, b"").unwrap();
+        for (key, _) in page.items {
+            assert!(keys.insert(key));
+        }
+        // scans started in the meantime do not disturb this one
+        for _ in 0..20 {
+            assert!(!server.storage.scan(0, 1, b"").unwrap().items.is_empty());
+        }
+        if page.cursor == 0 {
+            break;
+        }
+        cursor = page.cursor;
+    }
+    assert_eq!(keys.len(), 200);
+}
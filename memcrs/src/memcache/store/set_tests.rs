@@ -3,7 +3,9 @@ use test_case::test_case;
 
 #[test_case(create_moka_server() ; "moka_backend")]
 #[test_case(create_dash_map_server() ; "dash_map_backend")]
-fn set_if_not_defined_cas_should_be_1(server: MockServer) {
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
+fn set_if_not_defined_cas_should_be_assigned(server: MockServer) {
     let key = Bytes::from("key");
     let record = Record::new(from_string("Test data"), 0, 0, 0);
     let result: std::result::Result<CacheSetStatus, CacheError> =
@@ -14,7 +16,8 @@ fn set_if_not_defined_cas_should_be_1(server: MockServer) {
     match found {
         Ok(r) => {
             assert_eq!(r, record);
-            assert_eq!(r.header.cas, 1)
+            assert_ne!(r.header.cas, 0);
+            assert_eq!(r.header.cas, result.unwrap().cas)
         }
         Err(_er) => unreachable!(),
     }
@@ -22,6 +25,8 @@ fn set_if_not_defined_cas_should_be_1(server: MockServer) {
 
 #[test_case(create_moka_server() ; "moka_backend")]
 #[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
 fn set_should_override_value_if_cas_is_0(server: MockServer) {
     let key = Bytes::from("key");
     let record = Record::new(from_string("Test data"), 0, 0, 0);
@@ -44,7 +49,9 @@ fn set_should_override_value_if_cas_is_0(server: MockServer) {
 
 #[test_case(create_moka_server() ; "moka_backend")]
 #[test_case(create_dash_map_server() ; "dash_map_backend")]
-fn set_if_cas_defined_it_should_be_returned(server: MockServer) {
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
+fn set_if_cas_defined_new_cas_should_be_returned(server: MockServer) {
     let storage = server.storage;
     let cas: u64 = 0xDEAD_BEEF;
     let key = Bytes::from("key");
@@ -57,7 +64,9 @@ fn set_if_cas_defined_it_should_be_returned(server: MockServer) {
     match found {
         Ok(r) => {
             assert_eq!(r, record);
-            assert_eq!(r.header.cas, cas + 1)
+            // new CAS comes from the global counter, not from the client
+            assert_ne!(r.header.cas, cas + 1);
+            assert_eq!(r.header.cas, result.unwrap().cas)
         }
         Err(_er) => unreachable!(),
     }
@@ -65,6 +74,8 @@ fn set_if_cas_defined_it_should_be_returned(server: MockServer) {
 
 #[test_case(create_moka_server() ; "moka_backend")]
 #[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
 fn set_insert_should_fail_on_cas_mismatch(server: MockServer) {
     let storage = server.storage;
     let cas: u64 = 0xDEAD_BEEF;
@@ -82,6 +93,8 @@ fn set_insert_should_fail_on_cas_mismatch(server: MockServer) {
 
 #[test_case(create_moka_server() ; "moka_backend")]
 #[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
 fn set_insert_should_not_fail_on_cas_max(server: MockServer) {
     let storage = server.storage;
     let cas: u64 = u64::MAX;
@@ -100,6 +113,8 @@ fn set_insert_should_not_fail_on_cas_max(server: MockServer) {
 
 #[test_case(create_moka_server() ; "moka_backend")]
 #[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
 fn set_record_should_expire_in_given_time(server: MockServer) {
     let cas: u64 = 0xDEAD_BEEF;
     let key = Bytes::from("key");
@@ -121,6 +136,8 @@ fn set_record_should_expire_in_given_time(server: MockServer) {
 
 #[test_case(create_moka_server() ; "moka_backend")]
 #[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
 fn set_should_not_fail_on_cas_mismatch(server: MockServer) {
     let cas: u64 = 0xDEAD_BEEF;
     let key = Bytes::from("key");
@@ -141,3 +158,47 @@ fn set_should_not_fail_on_cas_mismatch(server: MockServer) {
         Err(_err) => unreachable!(),
     }
 }
+
+#[test_case(create_moka_server() ; "moka_backend")]
+#[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
+fn get_should_remove_expired_record(server: MockServer) {
+    let key = Bytes::from("key");
+    let record = Record::new(from_string("test data"), 0, 0, 5);
+    assert!(server.storage.set(key.clone(), record).is_ok());
+
+    server.timer.set(10);
+    assert_eq!(server.storage.get(&key), Err(CacheError::NotFound));
+
+    // add fails for keys which are still stored
+    let record = Record::new(from_string("new data"), 0, 0, 0);
+    assert!(server.storage.add(key.clone(), record).is_ok());
+    match server.storage.get(&key) {
+        Ok(found) => assert_eq!(found.value, from_string("new data")),
+        Err(_err) => unreachable!(),
+    }
+}
+
+#[test_case(create_moka_server() ; "moka_backend")]
+#[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
+fn get_should_serve_concurrent_readers(server: MockServer) {
+    let key = Bytes::from("key");
+    let record = Record::new(from_string("test data"), 0, 0, 0);
+    assert!(server.storage.set(key.clone(), record.clone()).is_ok());
+
+    std::thread::scope(|scope| {
+        for _ in 0..4 {
+            scope.spawn(|| {
+                for _ in 0..1000 {
+                    match server.storage.get(&key) {
+                        Ok(found) => assert_eq!(found, record),
+                        Err(_err) => unreachable!(),
+                    }
+                }
+            });
+        }
+    });
+}
@@ -0,0 +1,94 @@
+use super::test_utils::*;
+use test_case::test_case;
+
+#[test_case(create_moka_server() ; "moka_backend")]
+#[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
+fn for_each_item_should_skip_expired_records(server: MockServer) {
+    for idx in 0..10 {
+        let ttl = if idx % 2 == 0 { 0 } else { 5 };
+        let record = Record::new(from_string("test data"), 0, idx, ttl);
+        let result = server
+            .storage
+            .set(Bytes::from(format!("key{}", idx)), record);
+        assert!(result.is_ok());
+    }
+    server.timer.set(10);
+
+    let mut keys = Vec::new();
+    server.store.for_each_item(&mut |key, record| {
+        assert_eq!(record.value, from_string("test data"));
+        keys.push(key.clone());
+    });
+    keys.sort();
+    let expected: Vec<Bytes> = (0..10)
+        .step_by(2)
+        .map(|idx| Bytes::from(format!("key{}", idx)))
+        .collect();
+    assert_eq!(keys, expected);
+}
+
+// shard locks are not held while the callback runs
+#[test_case(create_moka_server() ; "moka_backend")]
+#[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
+fn for_each_item_should_not_block_writers(server: MockServer) {
+    for idx in 0..10 {
+        let record = Record::new(from_string("test data"), 0, 0, 0);
+        let result = server
+            .storage
+            .set(Bytes::from(format!("key{}", idx)), record);
+        assert!(result.is_ok());
+    }
+    let mut visited = 0;
+    server.store.for_each_item(&mut |key, record| {
+        let result = server.storage.set(key.clone(), record.clone());
+        assert!(result.is_ok());
+        visited += 1;
+    });
+    assert_eq!(visited, 10);
+}
+
+#[test_case(create_moka_server() ; "moka_backend")]
+#[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
+fn restore_should_keep_cas_and_expiration(server: MockServer) {
+    let key = Bytes::from("key");
+    let record = Record::new(from_string("test data"), 1000, 7, 20);
+    assert!(server.store.restore(key.clone(), record).is_ok());
+
+    let record = server.storage.get(&key).unwrap();
+    assert_eq!(record.header.cas, 1000);
+    assert_eq!(record.header.flags, 7);
+    assert_eq!(record.header.time_to_live, 20);
+
+    let status = server
+        .storage
+        .set(Bytes::from("other"), Record::new(from_string("v"), 0, 0, 0))
+        .unwrap();
+    assert!(status.cas > 1000);
+
+    server.timer.set(20);
+    assert_eq!(server.storage.get(&key).unwrap_err(), CacheError::NotFound);
+}
+
+#[test_case(create_moka_server() ; "moka_backend")]
+#[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
+fn restore_should_not_overwrite_existing_key(server: MockServer) {
+    let key = Bytes::from("key");
+    let result = server
+        .storage
+        .set(key.clone(), Record::new(from_string("new"), 0, 0, 0));
+    assert!(result.is_ok());
+
+    let result = server
+        .store
+        .restore(key.clone(), Record::new(from_string("old"), 1, 0, 0));
+    assert_eq!(result.unwrap_err(), CacheError::KeyExists);
+    assert_eq!(server.storage.get(&key).unwrap().value, from_string("new"));
+}
@@ -0,0 +1,261 @@
+use crate::cache::cache::KeyType;
+use crate::cache::events::{CacheEvent, CacheListener, RemovalReason};
+use bytes::Bytes;
+use dashmap::DashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+pub type TagType = Bytes;
+
+/// Generation of every tag an item was stored with
+pub type ItemTags = Vec<(TagType, u64)>;
+
+/// Tags of stored items.
+///
+/// Every tag has a generation counter, an item remembers generations of
+/// its tags at the time it was stored and is valid as long as none of
+/// them changed. Invalidating a tag bumps its counter, so it is O(1) no
+/// matter how many items carry the tag, invalid items are removed once
+/// they are read.
+///
+/// The index is disabled until the first tagged write, so a cache which
+/// never uses tags does not pay for lookups in it. A tag is forgotten
+/// once no item, stored or being written, carries it.
+#[derive(Default)]
+pub struct TagIndex {
+    enabled: AtomicBool,
+    generations: DashMap<TagType, TagState>,
+    items: DashMap<KeyType, ItemTags>,
+}
+
+#[derive(Default)]
+struct TagState {
+    generation: u64,
+    /// items stored or being written with the tag
+    references: u64,
+}
+
+impl TagIndex {
+    pub fn new() -> TagIndex {
+        Default::default()
+    }
+
+    /// Enables the index, returns true for the caller which enabled it.
+    /// That caller has to subscribe the index to the store, so it forgets
+    /// items the store removes.
+    pub fn enable(&self) -> bool {
+        !self.enabled.load(Ordering::Acquire)
+            && self
+                .enabled
+                .compare_exchange(false, true, Ordering::AcqRel, Ordering::Acquire)
+                .is_ok()
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Acquire)
+    }
+
+    fn generation(&self, tag: &TagType) -> u64 {
+        self.generations
+            .get(tag)
+            .map_or(0, |state| state.generation)
+    }
+
+    /// Current generations of `tags`, taken before an item is written so
+    /// invalidations racing with the write are not lost. Tags are kept
+    /// until the item is removed, or `release` is called if the write fails.
+    pub fn current(&self, tags: Vec<TagType>) -> ItemTags {
+        tags.into_iter()
+            .map(|tag| {
+                let mut state = self.generations.entry(tag.clone()).or_default();
+                state.references += 1;
+                let generation = state.generation;
+                (tag, generation)
+            })
+            .collect()
+    }
+
+    /// Forgets tags no other item carries
+    pub fn release(&self, tags: ItemTags) {
+        for (tag, _generation) in tags {
+            if let Some(mut state) = self.generations.get_mut(&tag) {
+                state.references -= 1;
+            }
+            self.generations
+                .remove_if(&tag, |_tag, state| state.references == 0);
+        }
+    }
+
+    /// Remembers tags of an item, an item stored without tags forgets
+    /// tags of the value it replaced
+    pub fn insert(&self, key: KeyType, tags: ItemTags) {
+        if !tags.is_empty() {
+            if let Some(replaced) = self.items.insert(key, tags) {
+                self.release(replaced);
+            }
+        } else if self.is_enabled() {
+            self.remove(&key);
+        }
+    }
+
+    pub fn remove(&self, key: &KeyType) {
+        if !self.is_enabled() {
+            return;
+        }
+        if let Some((_key, tags)) = self.items.remove(key) {
+            self.release(tags);
+        }
+    }
+
+    /// False if any tag of the item was invalidated after it was stored
+    pub fn is_valid(&self, key: &KeyType) -> bool {
+        if !self.is_enabled() {
+            return true;
+        }
+        match self.items.get(key) {
+            Some(tags) => tags
+                .iter()
+                .all(|(tag, generation)| self.generation(tag) == *generation),
+            None => true,
+        }
+    }
+
+    /// Tags no item carries are not known, there is nothing to invalidate
+    pub fn invalidate(&self, tag: TagType) {
+        if let Some(mut state) = self.generations.get_mut(&tag) {
+            state.generation += 1;
+        }
+    }
+
+    pub fn clear(&self) {
+        let mut removed = Vec::new();
+        self.items.retain(|_key, tags| {
+            removed.push(std::mem::take(tags));
+            false
+        });
+        removed.into_iter().for_each(|tags| self.release(tags));
+    }
+
+    /// Number of items stored with tags
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+}
+
+/// Forgets tags of items removed by the store itself, replaced items keep
+/// their tags as the store writing them updates the index
+impl CacheListener for TagIndex {
+    fn on_event(&self, event: &CacheEvent) {
+        match event {
+            CacheEvent::Removed {
+                reason: RemovalReason::Replaced,
+                ..
+            } => {}
+            CacheEvent::Removed { key, .. } => self.remove(key),
+            CacheEvent::Flushed { expiration: 0 } => self.clear(),
+            CacheEvent::Flushed { .. } => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tags(tags: &[&'static str]) -> Vec<TagType> {
+        tags.iter().map(|tag| Bytes::from(*tag)).collect()
+    }
+
+    #[test]
+    fn test_invalidated_tag_invalidates_items() {
+        let index = TagIndex::new();
+        assert!(index.enable());
+        index.insert(Bytes::from("a"), index.current(tags(&["user:1", "page"])));
+        index.insert(Bytes::from("b"), index.current(tags(&["user:2"])));
+        index.insert(Bytes::from("c"), index.current(vec![]));
+        assert!(index.is_valid(&Bytes::from("a")));
+        assert_eq!(index.len(), 2);
+
+        index.invalidate(Bytes::from("page"));
+        assert!(!index.is_valid(&Bytes::from("a")));
+        assert!(index.is_valid(&Bytes::from("b")));
+        assert!(index.is_valid(&Bytes::from("c")));
+
+        // stored again after invalidation
+        index.insert(Bytes::from("a"), index.current(tags(&["page"])));
+        assert!(index.is_valid(&Bytes::from("a")));
+    }
+
+    #[test]
+    fn test_untagged_write_forgets_tags() {
+        let index = TagIndex::new();
+        assert!(index.enable());
+        index.insert(Bytes::from("a"), index.current(tags(&["page"])));
+        index.insert(Bytes::from("a"), index.current(vec![]));
+        index.invalidate(Bytes::from("page"));
+        assert!(index.is_valid(&Bytes::from("a")));
+        assert!(index.is_empty());
+    }
+
+    #[test]
+    fn test_removed_items_are_forgotten() {
+        let index = TagIndex::new();
+        assert!(index.enable());
+        for key in ["a", "b", "c"] {
+            index.insert(Bytes::from(key), index.current(tags(&["page"])));
+        }
+        let removed = |key, reason| CacheEvent::Removed {
+            key: Bytes::from(key),
+            reason,
+        };
+        index.on_event(&removed("a", RemovalReason::Replaced));
+        index.on_event(&removed("b", RemovalReason::Size));
+        index.on_event(&removed("c", RemovalReason::Expired));
+        assert_eq!(index.len(), 1);
+
+        index.on_event(&CacheEvent::Flushed { expiration: 100 });
+        assert_eq!(index.len(), 1);
+        index.on_event(&CacheEvent::Flushed { expiration: 0 });
+        assert!(index.is_empty());
+        assert!(index.generations.is_empty());
+    }
+
+    #[test]
+    fn test_disabled_index_keeps_nothing() {
+        let index = TagIndex::new();
+        index.insert(Bytes::from("a"), index.current(vec![]));
+        index.remove(&Bytes::from("a"));
+        index.invalidate(Bytes::from("page"));
+        assert!(index.is_valid(&Bytes::from("a")));
+        assert!(index.generations.is_empty());
+        assert!(index.enable());
+        assert!(!index.enable());
+    }
+
+    #[test]
+    fn test_tags_without_items_are_forgotten() {
+        let index = TagIndex::new();
+        assert!(index.enable());
+        index.insert(Bytes::from("a"), index.current(tags(&["page", "user:1"])));
+        index.insert(Bytes::from("b"), index.current(tags(&["page"])));
+        index.invalidate(Bytes::from("user:2"));
+        assert_eq!(index.generations.len(), 2);
+
+        // replaced value releases tags it no longer carries
+        index.insert(Bytes::from("a"), index.current(tags(&["page"])));
+        assert_eq!(index.generations.len(), 1);
+        index.remove(&Bytes::from("a"));
+        assert_eq!(index.generations.len(), 1);
+        index.remove(&Bytes::from("b"));
+        assert!(index.generations.is_empty());
+
+        // failed write releases tags it took
+        let tags = index.current(tags(&["page"]));
+        index.invalidate(Bytes::from("page"));
+        index.release(tags);
+        assert!(index.generations.is_empty());
+    }
+}
@@ -0,0 +1,110 @@
+use super::test_utils::*;
+use test_case::test_case;
+
+fn set_tagged(server: &MockServer, key: &str, tags: &[&'static str]) -> SetStatus {
+    let record = Record::new(from_string("value"), 0, 0, 0);
+    let tags = tags.iter().map(|tag| Bytes::from(*tag)).collect();
+    server
+        .storage
+        .set_with_tags(Bytes::from(key.to_string()), record, tags)
+        .unwrap()
+}
+
+fn get(server: &MockServer, key: &str) -> Result<Record> {
+    server.storage.get(&Bytes::from(key.to_string()))
+}
+
+#[test_case(create_moka_server() ; "moka_backend")]
+#[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
+fn invalidated_tag_should_expire_tagged_items(server: MockServer) {
+    set_tagged(&server, "user:1:profile", &["user:1"]);
+    set_tagged(&server, "user:1:friends", &["user:1", "friends"]);
+    set_tagged(&server, "user:2:profile", &["user:2"]);
+    set_tagged(&server, "untagged", &[]);
+
+    server.storage.invalidate_tag(Bytes::from("user:1"));
+    assert_eq!(get(&server, "user:1:profile"), Err(CacheError::NotFound));
+    assert_eq!(get(&server, "user:1:friends"), Err(CacheError::NotFound));
+    assert!(get(&server, "user:2:profile").is_ok());
+    assert!(get(&server, "untagged").is_ok());
+
+    // invalidated items are removed once read
+    let page = server.storage.scan(0, 10, b"").unwrap();
+    assert_eq!(page.items.len(), 2);
+}
+
+#[test_case(create_moka_server() ; "moka_backend")]
+#[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
+fn items_stored_after_invalidation_should_be_valid(server: MockServer) {
+    set_tagged(&server, "key", &["tag"]);
+    server.storage.invalidate_tag(Bytes::from("tag"));
+    set_tagged(&server, "key", &["tag"]);
+    assert!(get(&server, "key").is_ok());
+
+    // untagged write drops tags of the replaced value
+    set_tagged(&server, "other", &["tag"]);
+    set_tagged(&server, "other", &[]);
+    server.storage.invalidate_tag(Bytes::from("tag"));
+    assert_eq!(get(&server, "key"), Err(CacheError::NotFound));
+    assert!(get(&server, "other").is_ok());
+}
+
+#[test_case(create_moka_server() ; "moka_backend")]
+#[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
+fn writes_should_treat_invalidated_items_as_missing(server: MockServer) {
+    for key in ["add", "replace", "append", "delete"] {
+        set_tagged(&server, key, &["tag"]);
+    }
+    let key = Bytes::from("incr");
+    let record = Record::new(from_string("1"), 0, 0, 0);
+    server
+        .storage
+        .set_with_tags(key, record, vec![Bytes::from("tag")])
+        .unwrap();
+    server.storage.invalidate_tag(Bytes::from("tag"));
+
+    let record = Record::new(from_string("value"), 0, 0, 0);
+    assert!(server
+        .storage
+        .add(Bytes::from("add"), record.clone())
+        .is_ok());
+    let result = server
+        .storage
+        .replace(Bytes::from("replace"), record.clone());
+    assert_eq!(result.unwrap_err(), CacheError::NotFound);
+    let result = server.storage.append(Bytes::from("append"), record);
+    assert_eq!(result.unwrap_err(), CacheError::ItemNotStored);
+    let delta = DeltaParam { delta: 1, value: 0 };
+    let result = server
+        .storage
+        .increment(Meta::new(0, 0, 0), Bytes::from("incr"), delta);
+    assert_eq!(result.unwrap().value, 0);
+    assert_eq!(
+        server
+            .storage
+            .delete(Bytes::from("delete"), Meta::new(0, 0, 0)),
+        Err(CacheError::NotFound)
+    );
+}
+
+#[test_case(create_moka_server() ; "moka_backend")]
+#[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
+fn get_multi_should_skip_invalidated_items(server: MockServer) {
+    set_tagged(&server, "a", &["tag"]);
+    set_tagged(&server, "b", &[]);
+    server.storage.invalidate_tag(Bytes::from("tag"));
+
+    let results = server
+        .storage
+        .get_multi(&[Bytes::from("a"), Bytes::from("b")]);
+    assert_eq!(results[0], Err(CacheError::NotFound));
+    assert!(results[1].is_ok());
+}
@@ -0,0 +1,480 @@
+use super::test_utils::*;
+use crate::cache::cache::Cache;
+use crate::cache::events::ChannelListener;
+use crate::cache::eviction_policy::EvictionPolicy;
+use crate::memcache::cli::parser::{MokaConfig, SieveConfig};
+use crate::memory_store::ext_store::ExtStoreConfig;
+use crate::memory_store::tiered_store::TieredMemoryStore;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use test_case::test_case;
+
+struct TieredServer {
+    server: MockServer,
+    inner: Arc<dyn Cache + Send + Sync>,
+    tiered: Arc<TieredMemoryStore>,
+}
+
+static FILE_ID: AtomicUsize = AtomicUsize::new(0);
+
+fn create_tiered(server: MockServer, name: &str, size: u64, page_size: u64) -> TieredServer {
+    let path = std::env::temp_dir().join(format!(
+        "memcrs-tiered-{}-{}-{}",
+        name,
+        std::process::id(),
+        FILE_ID.fetch_add(1, Ordering::Relaxed)
+    ));
+    let config = ExtStoreConfig {
+        path: path.clone(),
+        item_size: 64,
+        size,
+        page_size,
+    };
+    let inner = server.store.clone();
+    let tiered =
+        Arc::new(TieredMemoryStore::new(inner.clone(), &config, server.timer.clone()).unwrap());
+    // file stays readable through the open handle
+    let _ = std::fs::remove_file(&path);
+    TieredServer {
+        server: MockServer::new(tiered.clone(), server.timer),
+        inner,
+        tiered,
+    }
+}
+
+fn large_value(byte: u8, len: usize) -> Bytes {
+    Bytes::from(vec![byte; len])
+}
+
+#[test_case(create_moka_server() ; "moka_backend")]
+#[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
+fn large_value_should_be_moved_to_page_file(server: MockServer) {
+    let tiered = create_tiered(server, "moved", 4096, 1024);
+    let key = Bytes::from("key");
+    let value = large_value(b'a', 300);
+    let status = tiered
+        .server
+        .storage
+        .set(key.clone(), Record::new(value.clone(), 0, 5, 0))
+        .unwrap();
+
+    let in_memory = tiered.inner.get(&key).unwrap();
+    assert!(in_memory.value.len() < 64);
+    let record = tiered.server.storage.get(&key).unwrap();
+    assert_eq!(record.value, value);
+    assert_eq!(record.header.flags, 5);
+    assert_eq!(record.header.cas, status.cas);
+    assert_eq!(tiered.tiered.ext_store().live_bytes(), 309);
+}
+
+#[test_case(create_moka_server() ; "moka_backend")]
+#[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
+fn small_value_should_stay_in_memory(server: MockServer) {
+    let tiered = create_tiered(server, "small", 4096, 1024);
+    let key = Bytes::from("key");
+    let value = large_value(b'a', 64);
+    tiered
+        .server
+        .storage
+        .set(key.clone(), Record::new(value.clone(), 0, 0, 0))
+        .unwrap();
+
+    assert_eq!(tiered.inner.get(&key).unwrap().value, value);
+    assert_eq!(tiered.tiered.ext_store().used_bytes(), 0);
+}
+
+#[test_case(create_moka_server() ; "moka_backend")]
+#[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
+fn append_and_prepend_should_use_value_from_page_file(server: MockServer) {
+    let tiered = create_tiered(server, "append", 4096, 1024);
+    let storage = &tiered.server.storage;
+    let key = Bytes::from("key");
+    storage
+        .set(key.clone(), Record::new(large_value(b'b', 100), 0, 0, 0))
+        .unwrap();
+    storage
+        .append(key.clone(), Record::new(from_string("c"), 0, 0, 0))
+        .unwrap();
+    storage
+        .prepend(key.clone(), Record::new(from_string("a"), 0, 0, 0))
+        .unwrap();
+
+    let mut expected = BytesMut::new();
+    expected.put_slice(b"a");
+    expected.put_slice(&large_value(b'b', 100));
+    expected.put_slice(b"c");
+    assert_eq!(storage.get(&key).unwrap().value, expected.freeze());
+    // only the latest value is referenced
+    assert_eq!(tiered.tiered.ext_store().live_bytes(), 6 + 3 + 102);
+
+    let result = storage.append(
+        Bytes::from("missing"),
+        Record::new(from_string("c"), 0, 0, 0),
+    );
+    assert_eq!(result.unwrap_err(), CacheError::ItemNotStored);
+}
+
+#[test_case(create_moka_server() ; "moka_backend")]
+#[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
+fn overwritten_values_should_be_reclaimed_by_compaction(server: MockServer) {
+    // three items fit in a page
+    let tiered = create_tiered(server, "compaction", 4096, 1024);
+    let storage = &tiered.server.storage;
+    let other = Bytes::from("oth");
+    storage
+        .set(other.clone(), Record::new(large_value(b'o', 300), 0, 0, 0))
+        .unwrap();
+    let key = Bytes::from("key");
+    for idx in 0..5 {
+        storage
+            .set(
+                key.clone(),
+                Record::new(large_value(b'0' + idx, 300), 0, 0, 0),
+            )
+            .unwrap();
+    }
+    let ext = tiered.tiered.ext_store();
+    assert_eq!(ext.used_bytes(), 6 * 309);
+    assert_eq!(ext.live_bytes(), 2 * 309);
+
+    assert!(tiered.tiered.compact());
+    assert!(tiered.tiered.compact());
+    assert!(!tiered.tiered.compact());
+    assert_eq!(ext.used_bytes(), 2 * 309);
+    assert_eq!(ext.live_bytes(), 2 * 309);
+    assert_eq!(storage.get(&other).unwrap().value, large_value(b'o', 300));
+    assert_eq!(storage.get(&key).unwrap().value, large_value(b'4', 300));
+}
+
+#[test_case(create_moka_server() ; "moka_backend")]
+#[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
+fn compaction_should_keep_expiration(server: MockServer) {
+    let tiered = create_tiered(server, "expiration", 4096, 1024);
+    let storage = &tiered.server.storage;
+    let key = Bytes::from("key");
+    storage
+        .set(key.clone(), Record::new(large_value(b'a', 300), 0, 0, 20))
+        .unwrap();
+    // seal the page and leave the first item as the only live one
+    for _ in 0..3 {
+        storage
+            .set(
+                Bytes::from("oth"),
+                Record::new(large_value(b'b', 300), 0, 0, 0),
+            )
+            .unwrap();
+    }
+    tiered.server.timer.set(5);
+    assert!(tiered.tiered.compact());
+
+    let record = storage.get(&key).unwrap();
+    assert_eq!(record.value, large_value(b'a', 300));
+    assert_eq!(record.header.time_to_live, 20);
+    tiered.server.timer.set(20);
+    assert_eq!(storage.get(&key).unwrap_err(), CacheError::NotFound);
+}
+
+#[test_case(create_moka_server() ; "moka_backend")]
+#[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
+fn delete_should_release_page_file_space(server: MockServer) {
+    let tiered = create_tiered(server, "delete", 4096, 1024);
+    let storage = &tiered.server.storage;
+    let key = Bytes::from("key");
+    let value = large_value(b'a', 300);
+    storage
+        .set(key.clone(), Record::new(value.clone(), 0, 0, 0))
+        .unwrap();
+
+    let record = storage.delete(key.clone(), Meta::new(0, 0, 0)).unwrap();
+    assert_eq!(record.value, value);
+    assert_eq!(tiered.tiered.ext_store().live_bytes(), 0);
+    assert_eq!(storage.get(&key).unwrap_err(), CacheError::NotFound);
+}
+
+#[test_case(create_moka_server() ; "moka_backend")]
+#[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
+fn flush_should_drop_page_file_items(server: MockServer) {
+    let tiered = create_tiered(server, "flush", 4096, 1024);
+    let storage = &tiered.server.storage;
+    let key = Bytes::from("key");
+    storage
+        .set(key.clone(), Record::new(large_value(b'a', 300), 0, 0, 0))
+        .unwrap();
+
+    storage.flush(Meta::new(0, 0, 0));
+    assert_eq!(tiered.tiered.ext_store().used_bytes(), 0);
+    assert_eq!(storage.get(&key).unwrap_err(), CacheError::NotFound);
+}
+
+#[test_case(create_moka_server() ; "moka_backend")]
+#[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
+fn reused_page_should_turn_into_miss(server: MockServer) {
+    // one item per page, third write reuses the oldest page
+    let tiered = create_tiered(server, "reused", 2048, 1024);
+    let storage = &tiered.server.storage;
+    for idx in 0..3 {
+        storage
+            .set(
+                Bytes::from(format!("key{}", idx)),
+                Record::new(large_value(b'a', 600), 0, 0, 0),
+            )
+            .unwrap();
+    }
+
+    let key = Bytes::from("key0");
+    assert_eq!(storage.get(&key).unwrap_err(), CacheError::NotFound);
+    assert_eq!(tiered.inner.get(&key).unwrap_err(), CacheError::NotFound);
+    assert!(storage.get(&Bytes::from("key2")).is_ok());
+}
+
+#[test_case(create_moka_server() ; "moka_backend")]
+#[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
+fn for_each_item_should_read_values_from_page_file(server: MockServer) {
+    let tiered = create_tiered(server, "for_each", 4096, 1024);
+    let storage = &tiered.server.storage;
+    let key = Bytes::from("key");
+    let value = large_value(b'a', 300);
+    storage
+        .set(key.clone(), Record::new(value.clone(), 0, 0, 0))
+        .unwrap();
+
+    let mut values = Vec::new();
+    tiered
+        .server
+        .store
+        .for_each_item(&mut |_key, record| values.push(record.value.clone()));
+    assert_eq!(values, vec![value]);
+
+    let result = storage.increment(
+        Meta::new(0, 0, 0),
+        key,
+        crate::cache::cache::DeltaParam { delta: 1, value: 0 },
+    );
+    assert_eq!(result.unwrap_err(), CacheError::ArithOnNonNumeric);
+}
+
+#[test_case(create_moka_server() ; "moka_backend")]
+#[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
+fn get_async_should_read_value_from_page_file(server: MockServer) {
+    let tiered = create_tiered(server, "get_async", 4096, 1024);
+    let storage = &tiered.server.storage;
+    let key = Bytes::from("key");
+    let value = large_value(b'a', 300);
+    storage
+        .set(key.clone(), Record::new(value.clone(), 0, 0, 0))
+        .unwrap();
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    let record = runtime.block_on(storage.get_async(&key)).unwrap();
+    assert_eq!(record.value, value);
+    let results = runtime.block_on(storage.get_multi_async(&[key, Bytes::from("missing")]));
+    assert_eq!(results[0].as_ref().unwrap().value, value);
+    assert_eq!(results[1].as_ref().unwrap_err(), &CacheError::NotFound);
+}
+
+#[test_case(create_moka_server() ; "moka_backend")]
+#[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
+fn concurrent_overwrites_should_release_every_value_once(server: MockServer) {
+    let tiered = create_tiered(server, "overwrites", 1024 * 1024, 64 * 1024);
+    let key = Bytes::from("key");
+    std::thread::scope(|scope| {
+        for thread in 0..4u8 {
+            let storage = &tiered.server.storage;
+            let key = key.clone();
+            scope.spawn(move || {
+                for _ in 0..50 {
+                    let value = large_value(b'a' + thread, 300);
+                    storage
+                        .set(key.clone(), Record::new(value, 0, 0, 0))
+                        .unwrap();
+                }
+            });
+        }
+    });
+
+    // only the value which won is referenced
+    assert_eq!(tiered.tiered.ext_store().live_bytes(), 309);
+}
+
+#[test_case(create_moka_server_with_config(MokaConfig {
+    max_capacity: 2,
+    eviction_policy: EvictionPolicy::LeastRecentlyUsed,
+}) ; "moka_backend")]
+#[test_case(create_sieve_server_with_config(SieveConfig {
+    max_capacity: 2,
+    eviction_policy: EvictionPolicy::Sieve,
+}) ; "sieve_backend")]
+fn evicted_items_should_be_read_from_page_file(server: MockServer) {
+    let tiered = create_tiered(server, "evicted", 8192, 1024);
+    let storage = &tiered.server.storage;
+    let mut statuses = Vec::new();
+    for idx in 0..4 {
+        let record = Record::new(large_value(b'0' + idx, 300), 0, idx as u32, 0);
+        let key = Bytes::from(format!("key{}", idx));
+        statuses.push(storage.set(key, record).unwrap());
+        tiered.server.store.run_pending_tasks();
+    }
+    let in_memory = (0..4)
+        .filter(|idx| {
+            tiered
+                .inner
+                .get(&Bytes::from(format!("key{}", idx)))
+                .is_ok()
+        })
+        .count();
+    assert_eq!(in_memory, 2);
+
+    for idx in 0..4 {
+        let record = storage.get(&Bytes::from(format!("key{}", idx))).unwrap();
+        assert_eq!(record.value, large_value(b'0' + idx, 300));
+        assert_eq!(record.header.flags, idx as u32);
+        assert_eq!(record.header.cas, statuses[idx as usize].cas);
+        tiered.server.store.run_pending_tasks();
+    }
+    // values were never written again
+    assert_eq!(tiered.tiered.ext_store().used_bytes(), 4 * 310);
+    assert_eq!(tiered.tiered.ext_store().live_bytes(), 4 * 310);
+}
+
+#[test_case(create_moka_server_with_config(MokaConfig {
+    max_capacity: 2,
+    eviction_policy: EvictionPolicy::LeastRecentlyUsed,
+}) ; "moka_backend")]
+#[test_case(create_sieve_server_with_config(SieveConfig {
+    max_capacity: 2,
+    eviction_policy: EvictionPolicy::Sieve,
+}) ; "sieve_backend")]
+fn evicted_items_should_expire(server: MockServer) {
+    let tiered = create_tiered(server, "evicted_expired", 8192, 1024);
+    let storage = &tiered.server.storage;
+    for idx in 0..4 {
+        let record = Record::new(large_value(b'a', 300), 0, 0, 10);
+        storage
+            .set(Bytes::from(format!("key{}", idx)), record)
+            .unwrap();
+        tiered.server.store.run_pending_tasks();
+    }
+
+    tiered.server.timer.set(10);
+    for idx in 0..4 {
+        let key = Bytes::from(format!("key{}", idx));
+        assert_eq!(storage.get(&key).unwrap_err(), CacheError::NotFound);
+    }
+    assert_eq!(tiered.tiered.ext_store().live_bytes(), 0);
+}
+
+#[test_case(create_moka_server() ; "moka_backend")]
+#[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
+fn expired_items_should_release_page_file_space(server: MockServer) {
+    let tiered = create_tiered(server, "expired", 4096, 1024);
+    let storage = &tiered.server.storage;
+    for key in ["read", "overwritten"] {
+        storage
+            .set(
+                Bytes::from(key),
+                Record::new(large_value(b'a', 300), 0, 0, 10),
+            )
+            .unwrap();
+    }
+
+    tiered.server.timer.set(10);
+    assert_eq!(
+        storage.get(&Bytes::from("read")).unwrap_err(),
+        CacheError::NotFound
+    );
+    storage
+        .set(
+            Bytes::from("overwritten"),
+            Record::new(large_value(b'b', 300), 0, 0, 0),
+        )
+        .unwrap();
+    assert_eq!(tiered.tiered.ext_store().live_bytes(), 317);
+}
+
+#[test_case(create_moka_server() ; "moka_backend")]
+#[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
+fn compaction_should_keep_cas_and_report_nothing(server: MockServer) {
+    let tiered = create_tiered(server, "compaction_cas", 4096, 1024);
+    let storage = &tiered.server.storage;
+    let key = Bytes::from("key");
+    let status = storage
+        .set(key.clone(), Record::new(large_value(b'a', 300), 0, 0, 0))
+        .unwrap();
+    for _ in 0..3 {
+        storage
+            .set(
+                Bytes::from("oth"),
+                Record::new(large_value(b'b', 300), 0, 0, 0),
+            )
+            .unwrap();
+    }
+    let (listener, events) = ChannelListener::bounded(10);
+    tiered.server.store.subscribe(listener);
+    assert!(tiered.tiered.compact());
+
+    let record = storage.get(&key).unwrap();
+    assert_eq!(record.value, large_value(b'a', 300));
+    assert_eq!(record.header.cas, status.cas);
+    assert_eq!(events.try_iter().count(), 0);
+}
+
+#[test_case(create_moka_server_with_config(MokaConfig {
+    max_capacity: 2,
+    eviction_policy: EvictionPolicy::LeastRecentlyUsed,
+}) ; "moka_backend")]
+#[test_case(create_sieve_server_with_config(SieveConfig {
+    max_capacity: 2,
+    eviction_policy: EvictionPolicy::Sieve,
+}) ; "sieve_backend")]
+fn compaction_should_rescue_evicted_items(server: MockServer) {
+    // three items fit in a page
+    let tiered = create_tiered(server, "compaction_evicted", 8192, 1024);
+    let storage = &tiered.server.storage;
+    for idx in 0..4 {
+        let record = Record::new(large_value(b'0' + idx, 300), 0, 0, 0);
+        storage
+            .set(Bytes::from(format!("key{}", idx)), record)
+            .unwrap();
+        tiered.server.store.run_pending_tasks();
+    }
+    for key in ["key1", "key2"] {
+        storage
+            .delete(Bytes::from(key), Meta::new(0, 0, 0))
+            .unwrap();
+    }
+
+    assert!(tiered.tiered.compact());
+    assert_eq!(tiered.tiered.ext_store().used_bytes(), 2 * 310);
+    let record = storage.get(&Bytes::from("key0")).unwrap();
+    assert_eq!(record.value, large_value(b'0', 300));
+    let record = storage.get(&Bytes::from("key3")).unwrap();
+    assert_eq!(record.value, large_value(b'3', 300));
+}
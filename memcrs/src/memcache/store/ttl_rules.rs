@@ -0,0 +1,164 @@
+use crate::cache::cache::{CacheMetaData, KeyType, Record};
+use crate::cache::error::{CacheError, Result};
+use crate::protocol::binary::network::DELTA_NO_INITIAL_VALUE;
+use crate::server::timer::{Timer, MAX_RELATIVE_EXPIRATION};
+use bytes::Bytes;
+use std::sync::Arc;
+
+/// Limits applied to items with keys starting with `prefix`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TtlRule {
+    pub prefix: Bytes,
+    /// TTL of items stored without one
+    pub default_ttl: Option<u32>,
+    /// Longest TTL an item can get, items which should never expire
+    /// expire after it too
+    pub max_ttl: Option<u32>,
+    /// Largest value which can be stored
+    pub max_size: Option<u64>,
+}
+
+/// Rules enforced on writes before records reach the store, the rule
+/// with the longest matching prefix applies
+pub struct TtlRules {
+    rules: Vec<TtlRule>,
+    timer: Arc<dyn Timer + Send + Sync>,
+}
+
+impl TtlRules {
+    pub fn new(mut rules: Vec<TtlRule>, timer: Arc<dyn Timer + Send + Sync>) -> TtlRules {
+        rules.sort_by_key(|rule| std::cmp::Reverse(rule.prefix.len()));
+        TtlRules { rules, timer }
+    }
+
+    fn rule_for(&self, key: &KeyType) -> Option<&TtlRule> {
+        self.rules.iter().find(|rule| key.starts_with(&rule.prefix))
+    }
+
+    /// Applies default and maximum TTL and rejects values which are
+    /// too large
+    pub fn apply(&self, key: &KeyType, record: &mut Record) -> Result<()> {
+        let rule = match self.rule_for(key) {
+            Some(rule) => rule,
+            None => return Ok(()),
+        };
+        if let Some(max_size) = rule.max_size {
+            if record.value.len() as u64 > max_size {
+                return Err(CacheError::ValueTooLarge);
+            }
+        }
+        record.header.time_to_live = self.time_to_live(rule, record.header.time_to_live);
+        Ok(())
+    }
+
+    /// Largest value which can be stored under `key`
+    pub fn max_size(&self, key: &KeyType) -> Option<u64> {
+        self.rule_for(key).and_then(|rule| rule.max_size)
+    }
+
+    /// Applies TTL limits to items created by increment and decrement,
+    /// requests which must not create an item are left alone
+    pub fn apply_to_header(&self, key: &KeyType, header: &mut CacheMetaData) {
+        if header.time_to_live == DELTA_NO_INITIAL_VALUE {
+            return;
+        }
+        if let Some(rule) = self.rule_for(key) {
+            header.time_to_live = self.time_to_live(rule, header.time_to_live);
+        }
+    }
+
+    fn time_to_live(&self, rule: &TtlRule, time_to_live: u32) -> u32 {
+        let time_to_live = match time_to_live {
+            0 => rule.default_ttl.unwrap_or(0),
+            ttl => ttl,
+        };
+        let max_ttl = match rule.max_ttl {
+            Some(max_ttl) => max_ttl,
+            None => return time_to_live,
+        };
+        let remaining = match time_to_live {
+            0 => u64::MAX,
+            ttl if ttl <= MAX_RELATIVE_EXPIRATION => ttl as u64,
+            // absolute unix time, times in the past are left to expire
+            ttl => match (ttl as u64).checked_sub(self.timer.unix_timestamp()) {
+                Some(remaining) => remaining,
+                None => return time_to_live,
+            },
+        };
+        if remaining > max_ttl as u64 {
+            max_ttl
+        } else {
+            time_to_live
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::mock_server::{MockSystemTimer, MOCK_UNIX_START};
+
+    fn rule(prefix: &'static str, default_ttl: Option<u32>, max_ttl: Option<u32>) -> TtlRule {
+        TtlRule {
+            prefix: Bytes::from(prefix),
+            default_ttl,
+            max_ttl,
+            max_size: None,
+        }
+    }
+
+    fn ttl_of(rules: &TtlRules, key: &'static str, time_to_live: u32) -> u32 {
+        let mut record = Record::new(Bytes::from("value"), 0, 0, time_to_live);
+        rules.apply(&Bytes::from(key), &mut record).unwrap();
+        record.header.time_to_live
+    }
+
+    #[test]
+    fn test_longest_prefix_rule_applies() {
+        let rules = TtlRules::new(
+            vec![
+                rule("session", Some(60), None),
+                rule("session:admin", Some(10), None),
+            ],
+            Arc::new(MockSystemTimer::new()),
+        );
+        assert_eq!(ttl_of(&rules, "session:1", 0), 60);
+        assert_eq!(ttl_of(&rules, "session:admin:1", 0), 10);
+        assert_eq!(ttl_of(&rules, "session:1", 5), 5);
+        assert_eq!(ttl_of(&rules, "other", 0), 0);
+    }
+
+    #[test]
+    fn test_max_ttl_clamps_relative_absolute_and_infinite_ttl() {
+        let rules = TtlRules::new(
+            vec![rule("key", None, Some(100))],
+            Arc::new(MockSystemTimer::new()),
+        );
+        let now = MOCK_UNIX_START as u32;
+        assert_eq!(ttl_of(&rules, "key", 0), 100);
+        assert_eq!(ttl_of(&rules, "key", 50), 50);
+        assert_eq!(ttl_of(&rules, "key", 500), 100);
+        assert_eq!(ttl_of(&rules, "key", now + 50), now + 50);
+        assert_eq!(ttl_of(&rules, "key", now + 500), 100);
+        assert_eq!(ttl_of(&rules, "key", now - 500), now - 500);
+    }
+
+    #[test]
+    fn test_large_values_and_delta_without_initial_value() {
+        let mut limited = rule("key", Some(10), None);
+        limited.max_size = Some(4);
+        let rules = TtlRules::new(vec![limited], Arc::new(MockSystemTimer::new()));
+        let mut record = Record::new(Bytes::from("value"), 0, 0, 0);
+        assert_eq!(
+            rules.apply(&Bytes::from("key"), &mut record),
+            Err(CacheError::ValueTooLarge)
+        );
+
+        let mut header = CacheMetaData::new(0, 0, DELTA_NO_INITIAL_VALUE);
+        rules.apply_to_header(&Bytes::from("key"), &mut header);
+        assert_eq!(header.time_to_live, DELTA_NO_INITIAL_VALUE);
+        let mut header = CacheMetaData::new(0, 0, 0);
+        rules.apply_to_header(&Bytes::from("key"), &mut header);
+        assert_eq!(header.time_to_live, 10);
+    }
+}
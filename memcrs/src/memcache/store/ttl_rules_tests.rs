@@ -0,0 +1,141 @@
+use super::test_utils::*;
+use super::ttl_rules::{TtlRule, TtlRules};
+use std::sync::Arc;
+use test_case::test_case;
+
+fn limited_store(server: &MockServer) -> MemcStore {
+    let rules = vec![
+        TtlRule {
+            prefix: Bytes::from("session:"),
+            default_ttl: Some(10),
+            max_ttl: Some(100),
+            max_size: None,
+        },
+        TtlRule {
+            prefix: Bytes::from("blob:"),
+            default_ttl: None,
+            max_ttl: None,
+            max_size: Some(4),
+        },
+    ];
+    MemcStore::new(server.store.clone())
+        .with_ttl_rules(Some(Arc::new(TtlRules::new(rules, server.timer.clone()))))
+}
+
+fn record(expiration: u32) -> Record {
+    Record::new(from_string("test"), 0, 0, expiration)
+}
+
+#[test_case(create_moka_server() ; "moka_backend")]
+#[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
+fn writes_should_get_default_and_max_ttl(server: MockServer) {
+    let store = limited_store(&server);
+    store
+        .set(Bytes::from("session:default"), record(0))
+        .unwrap();
+    store
+        .add(Bytes::from("session:clamped"), record(1000))
+        .unwrap();
+    store.set(Bytes::from("session:kept"), record(50)).unwrap();
+    store.set(Bytes::from("other"), record(0)).unwrap();
+    let delta = DeltaParam { delta: 1, value: 0 };
+    store
+        .increment(Meta::new(0, 0, 0), Bytes::from("session:counter"), delta)
+        .unwrap();
+
+    server.timer.set(10);
+    for key in ["session:default", "session:counter"] {
+        assert_eq!(store.get(&Bytes::from(key)), Err(CacheError::NotFound));
+    }
+    server.timer.set(50);
+    assert_eq!(
+        store.get(&Bytes::from("session:kept")),
+        Err(CacheError::NotFound)
+    );
+    server.timer.set(100);
+    assert_eq!(
+        store.get(&Bytes::from("session:clamped")),
+        Err(CacheError::NotFound)
+    );
+    assert!(store.get(&Bytes::from("other")).is_ok());
+}
+
+#[test_case(create_moka_server() ; "moka_backend")]
+#[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
+fn replace_should_clamp_ttl(server: MockServer) {
+    let store = limited_store(&server);
+    store.set(Bytes::from("session:key"), record(5)).unwrap();
+    store
+        .replace(Bytes::from("session:key"), record(0))
+        .unwrap();
+    server.timer.set(10);
+    assert_eq!(
+        store.get(&Bytes::from("session:key")),
+        Err(CacheError::NotFound)
+    );
+}
+
+#[test_case(create_moka_server() ; "moka_backend")]
+#[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
+fn values_over_max_size_should_be_rejected(server: MockServer) {
+    let store = limited_store(&server);
+    let large = Record::new(from_string("large"), 0, 0, 0);
+    assert_eq!(
+        store.set(Bytes::from("blob:a"), large.clone()).unwrap_err(),
+        CacheError::ValueTooLarge
+    );
+    assert!(store.set(Bytes::from("blob:b"), record(0)).is_ok());
+
+    let results = store.set_multi(vec![
+        (Bytes::from("blob:c"), record(0)),
+        (Bytes::from("blob:d"), large.clone()),
+        (Bytes::from("other"), large),
+    ]);
+    assert!(results[0].is_ok());
+    assert_eq!(results[1].as_ref().unwrap_err(), &CacheError::ValueTooLarge);
+    assert!(results[2].is_ok());
+    assert_eq!(store.get(&Bytes::from("blob:d")), Err(CacheError::NotFound));
+}
+
+#[test_case(create_moka_server() ; "moka_backend")]
+#[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
+fn concatenated_values_over_max_size_should_be_rejected(server: MockServer) {
+    let store = limited_store(&server);
+    let key = Bytes::from("blob:a");
+    store
+        .set(key.clone(), Record::new(from_string("ab"), 0, 0, 0))
+        .unwrap();
+    let part = Record::new(from_string("cd"), 0, 0, 0);
+    assert!(store.append(key.clone(), part.clone()).is_ok());
+    assert_eq!(
+        store.append(key.clone(), part.clone()).unwrap_err(),
+        CacheError::ValueTooLarge
+    );
+    assert_eq!(
+        store.prepend(key.clone(), part.clone()).unwrap_err(),
+        CacheError::ValueTooLarge
+    );
+    assert_eq!(store.get(&key).unwrap().value, from_string("abcd"));
+
+    // CAS of the client is still checked
+    let key = Bytes::from("blob:b");
+    let status = store
+        .set(key.clone(), Record::new(from_string("a"), 0, 0, 0))
+        .unwrap();
+    let stale = Record::new(from_string("b"), status.cas + 1, 0, 0);
+    assert_eq!(
+        store.prepend(key.clone(), stale).unwrap_err(),
+        CacheError::KeyExists
+    );
+    let current = Record::new(from_string("b"), status.cas, 0, 0);
+    assert!(store.prepend(key.clone(), current).is_ok());
+    assert_eq!(store.get(&key).unwrap().value, from_string("ba"));
+}
@@ -0,0 +1,165 @@
+use super::test_utils::*;
+use crate::persistence::write_behind::{write_behind, Mutation, Sink, WriteBehindConfig};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use test_case::test_case;
+use tokio_util::sync::CancellationToken;
+
+#[derive(Default)]
+struct RecordingSink {
+    mutations: Mutex<Vec<Mutation>>,
+}
+
+impl Sink for RecordingSink {
+    fn write(&self, batch: &[Mutation]) -> std::io::Result<()> {
+        self.mutations.lock().unwrap().extend_from_slice(batch);
+        Ok(())
+    }
+}
+
+/// Runs `mutate` on a store writing behind and returns mutations the
+/// sink received
+fn written_behind(server: &MockServer, mutate: impl FnOnce(&MemcStore)) -> Vec<Mutation> {
+    let sink = Arc::new(RecordingSink::default());
+    let token = CancellationToken::new();
+    let (queue, service) = write_behind(
+        sink.clone(),
+        WriteBehindConfig::default(),
+        server.timer.clone(),
+        token.clone(),
+    );
+    let store = MemcStore::new(server.store.clone()).with_write_behind(Some(queue));
+    mutate(&store);
+    token.cancel();
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap()
+        .block_on(service.run());
+    let mutations = sink.mutations.lock().unwrap().clone();
+    mutations
+}
+
+fn set(key: &'static str, value: &'static str) -> Mutation {
+    Mutation::Set {
+        key: Bytes::from(key),
+        value: Bytes::from(value),
+        flags: 0,
+        expiration: 0,
+    }
+}
+
+fn delete(key: &'static str) -> Mutation {
+    Mutation::Delete {
+        key: Bytes::from(key),
+    }
+}
+
+#[test_case(create_moka_server() ; "moka_backend")]
+#[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
+fn successful_mutations_should_be_written_behind(server: MockServer) {
+    let mutations = written_behind(&server, |store| {
+        let record = |value| Record::new(from_string(value), 0, 0, 0);
+        store.set(Bytes::from("a"), record("1")).unwrap();
+        store.add(Bytes::from("a"), record("2")).unwrap_err();
+        store.append(Bytes::from("a"), record("0")).unwrap();
+        let delta = DeltaParam { delta: 5, value: 0 };
+        store
+            .increment(Meta::new(0, 0, 0), Bytes::from("a"), delta)
+            .unwrap();
+        store.delete(Bytes::from("a"), Meta::new(0, 0, 0)).unwrap();
+        store
+            .delete(Bytes::from("a"), Meta::new(0, 0, 0))
+            .unwrap_err();
+        store.set_multi(vec![(Bytes::from("b"), record("3"))]);
+        store.delete_multi(vec![(Bytes::from("b"), Meta::new(0, 0, 0))]);
+        // flush only empties the cache
+        store.flush(Meta::new(0, 0, 0));
+    });
+    assert_eq!(
+        mutations,
+        vec![
+            set("a", "1"),
+            set("a", "10"),
+            set("a", "15"),
+            delete("a"),
+            set("b", "3"),
+            delete("b"),
+        ]
+    );
+}
+
+#[test_case(create_moka_server() ; "moka_backend")]
+#[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
+fn written_items_should_carry_flags_and_expiration(server: MockServer) {
+    let mutations = written_behind(&server, |store| {
+        let record = Record::new(from_string("value"), 0, 7, 100);
+        store.set(Bytes::from("key"), record).unwrap();
+    });
+    assert_eq!(
+        mutations,
+        vec![Mutation::Set {
+            key: Bytes::from("key"),
+            value: from_string("value"),
+            flags: 7,
+            expiration: crate::mock::mock_server::MOCK_UNIX_START + 100,
+        }]
+    );
+}
+
+#[test_case(create_moka_server() ; "moka_backend")]
+#[test_case(create_dash_map_server() ; "dash_map_backend")]
+#[test_case(create_slab_server() ; "slab_backend")]
+#[test_case(create_sieve_server() ; "sieve_backend")]
+fn concurrent_mutations_should_leave_sink_like_cache(server: MockServer) {
+    let keys: Vec<Bytes> = (0..4)
+        .map(|key| Bytes::from(format!("key{}", key)))
+        .collect();
+    let mut cached = HashMap::new();
+    let mutations = written_behind(&server, |store| {
+        std::thread::scope(|scope| {
+            for thread in 0..8u64 {
+                let keys = &keys;
+                scope.spawn(move || {
+                    for round in 0..200u64 {
+                        let key = keys[((thread + round) % 4) as usize].clone();
+                        let value = Bytes::from((thread * 1000 + round).to_string());
+                        let header = Meta::new(0, 0, 0);
+                        match (thread + round) % 5 {
+                            0 | 1 => {
+                                let _ = store.set(key, Record::new(value, 0, 0, 0));
+                            }
+                            2 => {
+                                let _ = store.append(key, Record::new(value, 0, 0, 0));
+                            }
+                            3 => {
+                                let delta = DeltaParam { delta: 1, value: 0 };
+                                let _ = store.increment(header, key, delta);
+                            }
+                            _ => {
+                                let _ = store.delete(key, header);
+                            }
+                        }
+                    }
+                });
+            }
+        });
+        for key in &keys {
+            if let Ok(record) = store.get(key) {
+                cached.insert(key.clone(), record.value);
+            }
+        }
+    });
+    let mut sink = HashMap::new();
+    for mutation in mutations {
+        match mutation {
+            Mutation::Set { key, value, .. } => sink.insert(key, value),
+            Mutation::Delete { key } => sink.remove(&key),
+        };
+    }
+    assert_eq!(sink, cached);
+}
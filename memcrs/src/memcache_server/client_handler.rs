@@ -11,10 +11,15 @@ use tracing::{debug, error};
 //use tracing_attributes::instrument;
 
 use super::handler;
+use super::partition_router::PartitionRouter;
 use crate::memcache::store as storage;
 use crate::protocol::binary::connection::MemcacheBinaryConnection;
 use crate::protocol::binary::decoder::BinaryRequest;
 use crate::protocol::binary::encoder::BinaryResponse;
+use crate::protocol::binary::network::GetRequest;
+
+/// Longest run of quiet gets resolved with one store lookup
+const MAX_QUIET_GET_BATCH: usize = 1024;
 
 pub struct ClientConfig {
     pub(crate) item_memory_limit: u32,
@@ -33,6 +38,12 @@ pub struct Client {
     /// the newly available permit and resume accepting connections.
     limit_connections: Arc<Semaphore>,
     cancellation_token: CancellationToken,
+    router: Option<PartitionRouter>,
+    /// Quiet gets waiting to be resolved together
+    quiet_gets: Vec<GetRequest>,
+    /// Mutations were handled since the last response, it is not sent
+    /// before they are durable
+    mutated: bool,
 }
 
 impl Client {
@@ -51,9 +62,17 @@ impl Client {
             handler: handler::BinaryHandler::new(store),
             limit_connections,
             cancellation_token,
+            router: None,
+            quiet_gets: Vec::new(),
+            mutated: false,
         }
     }
 
+    pub fn with_router(mut self, router: Option<PartitionRouter>) -> Self {
+        self.router = router;
+        self
+    }
+
     pub async fn handle(&mut self) {
         debug!("New client connected: {}", self.addr);
 
@@ -61,6 +80,10 @@ impl Client {
         // we parse the request, and if it's valid we generate a response
         // based on the values in the storage.
         loop {
+            // nothing more to read without waiting, respond to the run so far
+            if !self.stream.has_buffered_data() && self.flush_quiet_gets().await {
+                return;
+            }
             tokio::select! {
                     _ = self.cancellation_token.cancelled() => {
                         info!("Cancelling client loop for {}", self.addr);
@@ -119,13 +142,33 @@ impl Client {
             request.get_key()
         );
 
+        // quiet gets are collected until a request which is not a quiet get,
+        // usually the terminating noop, arrives
+        let request = match request {
+            BinaryRequest::GetQuietly(get_request) | BinaryRequest::GetKeyQuietly(get_request) => {
+                self.quiet_gets.push(get_request);
+                if self.quiet_gets.len() >= MAX_QUIET_GET_BATCH {
+                    return self.flush_quiet_gets().await;
+                }
+                return false;
+            }
+            request => request,
+        };
+        if self.flush_quiet_gets().await {
+            return true;
+        }
+
         if let BinaryRequest::QuitQuietly(_req) = request {
             debug!("Closing client socket quit quietly");
             if let Err(_e) = self.stream.shutdown().await.map_err(log_error) {}
             return true;
         }
 
-        let resp = self.handler.handle_request(request);
+        self.mutated |= request.is_mutation();
+        let resp = match &self.router {
+            Some(router) => router.handle_request(&self.handler, request).await,
+            None => self.handler.handle_request(request).await,
+        };
         match resp {
             Some(response) => {
                 let mut socket_close = false;
@@ -133,6 +176,12 @@ impl Client {
                     socket_close = true;
                 }
 
+                // a mutation which cannot be made durable is not acknowledged
+                if std::mem::take(&mut self.mutated) && self.handler.durable().await.is_err() {
+                    error!("Mutation log is not durable, closing client socket");
+                    if let Err(_e) = self.stream.shutdown().await.map_err(log_error) {}
+                    return true;
+                }
                 debug!("Sending response {:?}", response);
                 if let Err(e) = self.stream.write(&response).await {
                     error!("error on sending response; error = {:?}", e);
@@ -149,6 +198,28 @@ impl Client {
             None => false,
         }
     }
+
+    /// Resolves collected quiet gets and sends found values
+    /// Returns true if we should leave client receive loop
+    async fn flush_quiet_gets(&mut self) -> bool {
+        if self.quiet_gets.is_empty() {
+            return false;
+        }
+        let requests = std::mem::take(&mut self.quiet_gets);
+        let responses = match &self.router {
+            Some(router) => router.get_quietly_multi(&self.handler, requests).await,
+            None => self.handler.get_quietly_multi(requests).await,
+        };
+        if responses.is_empty() {
+            return false;
+        }
+        debug!("Sending {} quiet get responses", responses.len());
+        if let Err(e) = self.stream.write_batch(&responses).await {
+            error!("error on sending response; error = {:?}", e);
+            return true;
+        }
+        false
+    }
 }
 
 impl Drop for Client {
@@ -1,9 +1,10 @@
+use crate::memcache_server::handler::BinaryHandler;
 use crate::memcache_server::listener_factory::ListenerFactory;
+use crate::memcache_server::partition_router::{self, PartitionReceiver, PartitionRouter};
 use crate::{memcache::cli::parser::MemcrsdConfig, memcache_server::server_context::ServerContext};
 extern crate core_affinity;
 use crate::memcache_server::{self, register_cancellation, server_thread};
 use core_affinity::CoreId;
-use std::sync::Arc;
 use tokio::runtime::Builder;
 
 pub struct CurrentThreadRuntimeBuilder {
@@ -21,10 +22,20 @@ impl CurrentThreadRuntimeBuilder {
         let task_runner = self.ctxt.pending_tasks_runner();
         let core_ids = core_affinity::get_core_ids().unwrap();
         let listener_factory =
-            memcache_server::listener_factory::create_listener_from_config(self.config);
+            memcache_server::listener_factory::create_listener_from_config(&self.config);
 
-        for i in 0..self.config.threads {
-            self.spawn_worker_runtime(listener_factory.clone(), core_ids.clone(), i);
+        // in partitioned keyspace mode every worker owns one partition of the store
+        let mut routers = match self.ctxt.partitioned_store() {
+            Some(store) => PartitionRouter::create(store)
+                .into_iter()
+                .map(Some)
+                .collect(),
+            None => Vec::new(),
+        };
+        routers.resize_with(self.config.threads, || None);
+
+        for (i, router) in routers.into_iter().enumerate() {
+            self.spawn_worker_runtime(listener_factory.clone(), core_ids.clone(), i, router);
         }
 
         let mut control_runtime = create_current_thread_runtime();
@@ -38,9 +49,11 @@ impl CurrentThreadRuntimeBuilder {
         listener_factory: ListenerFactory,
         core_ids_clone: Vec<CoreId>,
         i: usize,
+        router: Option<(PartitionRouter, PartitionReceiver)>,
     ) {
         let cancellation_token = self.ctxt.cancellation_token().clone();
-        let store_rc = Arc::clone(&self.ctxt.store());
+        let memc_store = self.ctxt.memc_store();
+        let proxy = self.ctxt.proxy();
         let memc_config = memcache_server::memc_tcp::MemcacheServerConfig::new(
             60,
             self.config.connection_limit,
@@ -57,9 +70,20 @@ impl CurrentThreadRuntimeBuilder {
             let worker_runtime = create_current_thread_runtime();
             let mut tcp_server = memcache_server::memc_tcp::MemcacheTcpServer::new(
                 memc_config,
-                store_rc,
+                memc_store.clone(),
                 cancellation_token.clone(),
-            );
+            )
+            .with_proxy(proxy);
+            if let Some((router, receiver)) = router {
+                router.attach();
+                let handler = BinaryHandler::new(memc_store);
+                worker_runtime.spawn(partition_router::serve_partition(
+                    receiver,
+                    handler,
+                    cancellation_token.clone(),
+                ));
+                tcp_server = tcp_server.with_router(router);
+            }
             let listener = listener_factory.get_tcp_listener().unwrap_or_else(|e| {
                 log::error!("Failed to create TCP listener: {}", e);
                 std::process::exit(1);
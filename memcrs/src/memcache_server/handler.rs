@@ -1,13 +1,19 @@
 use crate::cache::cache;
 use crate::cache::error::CacheError;
+use crate::memcache::hot_keys::{HotKeyMetric, HotKeys};
 use crate::memcache::store;
 use crate::protocol::binary::encoder::storage_error_to_response;
 use crate::protocol::binary::{decoder, encoder, network};
+use crate::proxy::shadow::Shadow;
 use crate::version::MEMCRS_VERSION;
 use bytes::Bytes;
 use std::sync::Arc;
 
 const EXTRAS_LENGTH: u8 = 4;
+const SCAN_EXTRAS_LENGTH: u8 = 8;
+/// items returned when scan request does not set count
+const DEFAULT_SCAN_COUNT: usize = 1000;
+const MAX_SCAN_COUNT: usize = 10_000;
 
 fn into_record_meta(request_header: &network::RequestHeader, expiration: u32) -> store::Meta {
     store::Meta::new(request_header.cas, request_header.opaque, expiration)
@@ -22,6 +28,38 @@ fn into_quiet_get(response: encoder::BinaryResponse) -> Option<encoder::BinaryRe
     Some(response)
 }
 
+/// Percent-encodes everything but unreserved characters, like memcached
+fn uri_encode(key: &[u8], dst: &mut String) {
+    for &byte in key {
+        if byte.is_ascii_alphanumeric() || b"-._~".contains(&byte) {
+            dst.push(byte as char);
+        } else {
+            dst.push_str(&format!("%{:02X}", byte));
+        }
+    }
+}
+
+/// Formats items like lru_crawler metadump, not every engine tracks last
+/// access, fetched flag and slab class, they are always `la=0 fetch=no cls=0`
+fn metadump(items: &[(store::KeyType, store::Record)]) -> Bytes {
+    let mut dump = String::new();
+    for (key, record) in items {
+        dump.push_str("key=");
+        uri_encode(key, &mut dump);
+        let exp = match record.header.time_to_live {
+            0 => -1,
+            exp => exp as i64,
+        };
+        dump.push_str(&format!(
+            " exp={} la=0 cas={} fetch=no cls=0 size={}\n",
+            exp,
+            record.header.cas,
+            key.len() + record.len()
+        ));
+    }
+    Bytes::from(dump)
+}
+
 fn into_quiet_mutation(response: encoder::BinaryResponse) -> Option<encoder::BinaryResponse> {
     if let encoder::BinaryResponse::Error(_resp) = &response {
         return Some(response);
@@ -29,19 +67,102 @@ fn into_quiet_mutation(response: encoder::BinaryResponse) -> Option<encoder::Bin
     None
 }
 
+/// Key and metric of a request picked for hot key tracking and the size
+/// of the value it writes
+type HotKeySample = (HotKeyMetric, Bytes, u64);
+
 pub struct BinaryHandler {
     storage: Arc<store::MemcStore>,
+    hot_keys: Option<Arc<HotKeys>>,
+    shadow: Option<Arc<Shadow>>,
 }
 
 impl BinaryHandler {
     pub fn new(store: Arc<store::MemcStore>) -> BinaryHandler {
-        BinaryHandler { storage: store }
+        BinaryHandler {
+            hot_keys: store.hot_keys(),
+            shadow: store.shadow(),
+            storage: store,
+        }
+    }
+
+    /// Waits until mutations handled so far are durable
+    pub async fn durable(&self) -> Result<(), CacheError> {
+        self.storage.durable().await
+    }
+
+    pub async fn handle_request(
+        &self,
+        req: decoder::BinaryRequest,
+    ) -> Option<encoder::BinaryResponse> {
+        let sample = self.sample(&req);
+        if let Some(shadow) = &self.shadow {
+            shadow.mirror(&req);
+        }
+        let response = self.dispatch(req).await;
+        if let Some((metric, key, bytes)) = sample {
+            let bytes = match &response {
+                Some(encoder::BinaryResponse::Get(response)) => response.value.len() as u64,
+                _ => bytes,
+            };
+            self.track(metric, &key, bytes);
+        }
+        response
+    }
+
+    fn sample(&self, req: &decoder::BinaryRequest) -> Option<HotKeySample> {
+        let hot_keys = self.hot_keys.as_ref()?;
+        let (metric, bytes) = match req {
+            decoder::BinaryRequest::Get(_)
+            | decoder::BinaryRequest::GetKey(_)
+            | decoder::BinaryRequest::GetQuietly(_)
+            | decoder::BinaryRequest::GetKeyQuietly(_) => (HotKeyMetric::Reads, 0),
+            decoder::BinaryRequest::Set(request)
+            | decoder::BinaryRequest::SetQuietly(request)
+            | decoder::BinaryRequest::Add(request)
+            | decoder::BinaryRequest::AddQuietly(request)
+            | decoder::BinaryRequest::Replace(request)
+            | decoder::BinaryRequest::ReplaceQuietly(request) => {
+                (HotKeyMetric::Writes, request.value.len())
+            }
+            decoder::BinaryRequest::Append(request)
+            | decoder::BinaryRequest::AppendQuietly(request)
+            | decoder::BinaryRequest::Prepend(request)
+            | decoder::BinaryRequest::PrependQuietly(request) => {
+                (HotKeyMetric::Writes, request.value.len())
+            }
+            decoder::BinaryRequest::Increment(_)
+            | decoder::BinaryRequest::IncrementQuiet(_)
+            | decoder::BinaryRequest::Decrement(_)
+            | decoder::BinaryRequest::DecrementQuiet(_)
+            | decoder::BinaryRequest::Delete(_)
+            | decoder::BinaryRequest::DeleteQuiet(_) => (HotKeyMetric::Writes, 0),
+            _ => return None,
+        };
+        if !hot_keys.sampled() {
+            return None;
+        }
+        Some((metric, req.get_key(), bytes as u64))
     }
 
-    pub fn handle_request(&self, req: decoder::BinaryRequest) -> Option<encoder::BinaryResponse> {
+    fn track(&self, metric: HotKeyMetric, key: &Bytes, bytes: u64) {
+        if let Some(hot_keys) = &self.hot_keys {
+            hot_keys.record(metric, key, 1);
+            hot_keys.record(HotKeyMetric::Bytes, key, bytes);
+        }
+    }
+
+    async fn dispatch(&self, req: decoder::BinaryRequest) -> Option<encoder::BinaryResponse> {
         let request_header = req.get_header();
         let mut response_header =
             network::ResponseHeader::new(request_header.opcode, request_header.opaque);
+        if req.is_mutation() && self.storage.is_read_only() {
+            // quiet mutations report errors too
+            return Some(storage_error_to_response(
+                CacheError::NotSupported,
+                &mut response_header,
+            ));
+        }
 
         match req {
             decoder::BinaryRequest::Delete(delete_request) => {
@@ -58,11 +179,11 @@ impl BinaryHandler {
             }
             decoder::BinaryRequest::Get(get_request)
             | decoder::BinaryRequest::GetKey(get_request) => {
-                Some(self.get(get_request, &mut response_header))
+                Some(self.get(get_request, &mut response_header).await)
             }
             decoder::BinaryRequest::GetQuietly(get_quiet_req)
             | decoder::BinaryRequest::GetKeyQuietly(get_quiet_req) => {
-                into_quiet_get(self.get(get_quiet_req, &mut response_header))
+                into_quiet_get(self.get(get_quiet_req, &mut response_header).await)
             }
             decoder::BinaryRequest::Increment(inc_request) => {
                 Some(self.increment(inc_request, &mut response_header))
@@ -81,10 +202,8 @@ impl BinaryHandler {
                     header: response_header,
                 }))
             }
-            decoder::BinaryRequest::Stats(_stat_request) => {
-                Some(encoder::BinaryResponse::Stats(network::StatsResponse {
-                    header: response_header,
-                }))
+            decoder::BinaryRequest::Stats(stats_request) => {
+                Some(self.stats(stats_request, &mut response_header))
             }
             decoder::BinaryRequest::Quit(_quit_req) => {
                 Some(encoder::BinaryResponse::Quit(network::QuitResponse {
@@ -128,6 +247,17 @@ impl BinaryHandler {
                     version: String::from(MEMCRS_VERSION),
                 }))
             }
+            decoder::BinaryRequest::Scan(scan_request) => {
+                Some(self.scan(scan_request, &mut response_header))
+            }
+            decoder::BinaryRequest::InvalidateTag(request) => {
+                self.storage.invalidate_tag(request.key);
+                Some(encoder::BinaryResponse::InvalidateTag(
+                    network::InvalidateTagResponse {
+                        header: response_header,
+                    },
+                ))
+            }
             decoder::BinaryRequest::ItemTooLarge(_set_request) => Some(storage_error_to_response(
                 CacheError::ValueTooLarge,
                 &mut response_header,
@@ -150,9 +280,11 @@ impl BinaryHandler {
             request.expiration,
         );
         let result = if self.is_add_command(request.header.opcode) {
-            self.storage.add(request.key, record)
+            self.storage
+                .add_with_tags(request.key, record, request.tags)
         } else {
-            self.storage.replace(request.key, record)
+            self.storage
+                .replace_with_tags(request.key, record, request.tags)
         };
 
         match result {
@@ -220,7 +352,10 @@ impl BinaryHandler {
             set_req.expiration,
         );
 
-        match self.storage.set(set_req.key, record) {
+        match self
+            .storage
+            .set_with_tags(set_req.key, record, set_req.tags)
+        {
             Ok(status) => {
                 response_header.cas = status.cas;
                 encoder::BinaryResponse::Set(network::SetResponse {
@@ -248,13 +383,71 @@ impl BinaryHandler {
         }
     }
 
-    fn get(
+    /// Resolves a run of quiet gets with one store lookup, responses of
+    /// keys which were not found are left out
+    pub async fn get_quietly_multi(
+        &self,
+        requests: Vec<network::GetRequest>,
+    ) -> Vec<encoder::BinaryResponse> {
+        self.get_quietly_each(requests)
+            .await
+            .into_iter()
+            .flatten()
+            .collect()
+    }
+
+    /// Like get_quietly_multi, the response of every request is at its
+    /// position, None if the key was not found
+    pub async fn get_quietly_each(
+        &self,
+        requests: Vec<network::GetRequest>,
+    ) -> Vec<Option<encoder::BinaryResponse>> {
+        let keys: Vec<store::KeyType> =
+            requests.iter().map(|request| request.key.clone()).collect();
+        let results = self.storage.get_multi_async(&keys).await;
+        if let Some(shadow) = &self.shadow {
+            shadow.mirror_reads(&requests);
+        }
+        if let Some(hot_keys) = &self.hot_keys {
+            for (key, result) in keys.iter().zip(&results) {
+                if hot_keys.sampled() {
+                    let bytes = result.as_ref().map_or(0, |record| record.value.len());
+                    self.track(HotKeyMetric::Reads, key, bytes as u64);
+                }
+            }
+        }
+        requests
+            .into_iter()
+            .zip(results)
+            .map(|(request, result)| {
+                let mut response_header =
+                    network::ResponseHeader::new(request.header.opcode, request.header.opaque);
+                into_quiet_get(self.get_response(request, result, &mut response_header))
+            })
+            .collect()
+    }
+
+    /// Flushes the store without responding, for flushes which other
+    /// workers respond to
+    pub fn flush_store(&self, expiration: u32) {
+        self.storage.flush(store::Meta::new(0, 0, expiration));
+    }
+
+    async fn get(
         &self,
         get_request: network::GetRequest,
         response_header: &mut network::ResponseHeader,
     ) -> encoder::BinaryResponse {
-        let result = self.storage.get(&get_request.key);
+        let result = self.storage.get_async(&get_request.key).await;
+        self.get_response(get_request, result, response_header)
+    }
 
+    fn get_response(
+        &self,
+        get_request: network::GetRequest,
+        result: Result<store::Record, CacheError>,
+        response_header: &mut network::ResponseHeader,
+    ) -> encoder::BinaryResponse {
         match result {
             Ok(record) => {
                 let include_key = self.is_get_key_command(get_request.header.opcode);
@@ -282,6 +475,78 @@ impl BinaryHandler {
         opcode == network::Command::GetKey as u8 || opcode == network::Command::GetKeyQuiet as u8
     }
 
+    fn scan(
+        &self,
+        scan_request: network::ScanRequest,
+        response_header: &mut network::ResponseHeader,
+    ) -> encoder::BinaryResponse {
+        let count = match scan_request.count as usize {
+            0 => DEFAULT_SCAN_COUNT,
+            count => count.min(MAX_SCAN_COUNT),
+        };
+        match self
+            .storage
+            .scan(scan_request.cursor, count, &scan_request.prefix)
+        {
+            Ok(page) => {
+                let dump = metadump(&page.items);
+                response_header.extras_length = SCAN_EXTRAS_LENGTH;
+                response_header.body_length = SCAN_EXTRAS_LENGTH as u32 + dump.len() as u32;
+                encoder::BinaryResponse::Scan(network::ScanResponse {
+                    header: *response_header,
+                    cursor: page.cursor,
+                    dump,
+                })
+            }
+            Err(err) => storage_error_to_response(err, response_header),
+        }
+    }
+
+    fn stats(
+        &self,
+        stats_request: network::StatsRequest,
+        response_header: &mut network::ResponseHeader,
+    ) -> encoder::BinaryResponse {
+        let records = match &stats_request.key[..] {
+            b"" => {
+                let mut records = self.storage.stats().records();
+                records.extend(self.storage.server_stats().records());
+                records
+            }
+            b"hotkeys" => self.hot_key_records(),
+            _ => return storage_error_to_response(CacheError::NotFound, response_header),
+        };
+        encoder::BinaryResponse::Stats(network::StatsResponse {
+            header: *response_header,
+            records,
+        })
+    }
+
+    /// Top keys of every metric as `<metric>:<rank>:key` and
+    /// `<metric>:<rank>:count`, empty when tracking is disabled
+    fn hot_key_records(&self) -> Vec<(String, String)> {
+        let hot_keys = match &self.hot_keys {
+            Some(hot_keys) => hot_keys,
+            None => return Vec::new(),
+        };
+        let mut records = vec![(
+            "sample_rate".to_string(),
+            hot_keys.sample_rate().to_string(),
+        )];
+        for metric in HotKeyMetric::ALL {
+            for (rank, (key, count)) in hot_keys.top(metric).into_iter().enumerate() {
+                let mut encoded = String::new();
+                uri_encode(&key, &mut encoded);
+                records.push((format!("{}:{}:key", metric.name(), rank + 1), encoded));
+                records.push((
+                    format!("{}:{}:count", metric.name(), rank + 1),
+                    count.to_string(),
+                ));
+            }
+        }
+        records
+    }
+
     fn flush(
         &self,
         flush_request: network::FlushRequest,
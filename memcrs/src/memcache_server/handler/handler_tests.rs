@@ -6,19 +6,25 @@ mod tests {
     use super::network;
     use crate::cache::cache;
     use crate::cache::error;
+    use crate::memcache::hot_keys::HotKeys;
     use crate::memcache::store;
+    use crate::memcache_server::handler::BinaryHandler;
     use crate::memcache_server::handler::EXTRAS_LENGTH;
     use crate::mock::handler::*;
-    use crate::mock::mock_server::SetableTimer;
+    use crate::mock::mock_server::{create_dash_map_server, SetableTimer};
     use crate::mock::value::from_string;
     use crate::protocol::binary::encoder;
+    use crate::replication::Replication;
     use crate::version::MEMCRS_VERSION;
     use test_case::test_case;
 
     use bytes::Bytes;
+    use std::sync::Arc;
 
     #[test_case(create_moka_handler() ; "moka_backend")]
     #[test_case(create_dash_map_handler() ; "dash_map_backend")]
+    #[test_case(create_slab_handler() ; "slab_backend")]
+    #[test_case(create_sieve_handler() ; "sieve_backend")]
     fn get_request_should_return_not_found_when_not_exists(handler: BinaryHandlerWithTimer) {
         let key = Bytes::from("key");
         let header = create_header(network::Command::Get, &key);
@@ -42,6 +48,8 @@ mod tests {
 
     #[test_case(create_moka_handler() ; "moka_backend")]
     #[test_case(create_dash_map_handler() ; "dash_map_backend")]
+    #[test_case(create_slab_handler() ; "slab_backend")]
+    #[test_case(create_sieve_handler() ; "sieve_backend")]
     fn get_quiet_request_should_return_none_when_not_exists(handler: BinaryHandlerWithTimer) {
         let key = Bytes::from("key");
         let header = create_header(network::Command::GetQuiet, &key);
@@ -54,6 +62,8 @@ mod tests {
 
     #[test_case(create_moka_handler() ; "moka_backend")]
     #[test_case(create_dash_map_handler() ; "dash_map_backend")]
+    #[test_case(create_slab_handler() ; "slab_backend")]
+    #[test_case(create_sieve_handler() ; "sieve_backend")]
     fn get_quiet_key_request_should_return_none_when_not_exists(handler: BinaryHandlerWithTimer) {
         let key = Bytes::from("key");
         let header = create_header(network::Command::GetQuiet, &key);
@@ -67,6 +77,8 @@ mod tests {
 
     #[test_case(create_moka_handler() ; "moka_backend")]
     #[test_case(create_dash_map_handler() ; "dash_map_backend")]
+    #[test_case(create_slab_handler() ; "slab_backend")]
+    #[test_case(create_sieve_handler() ; "sieve_backend")]
     fn get_key_request_should_return_key_and_record(handler: BinaryHandlerWithTimer) {
         let key = Bytes::from("test_key");
         let value = from_string("test value");
@@ -105,6 +117,8 @@ mod tests {
 
     #[test_case(create_moka_handler() ; "moka_backend")]
     #[test_case(create_dash_map_handler() ; "dash_map_backend")]
+    #[test_case(create_slab_handler() ; "slab_backend")]
+    #[test_case(create_sieve_handler() ; "sieve_backend")]
     fn get_quiet_key_request_should_return_key_and_record(handler: BinaryHandlerWithTimer) {
         let key = Bytes::from("test_key");
         let value = from_string("test value");
@@ -143,6 +157,47 @@ mod tests {
 
     #[test_case(create_moka_handler() ; "moka_backend")]
     #[test_case(create_dash_map_handler() ; "dash_map_backend")]
+    #[test_case(create_slab_handler() ; "slab_backend")]
+    #[test_case(create_sieve_handler() ; "sieve_backend")]
+    fn get_quietly_multi_should_skip_missing_keys(handler: BinaryHandlerWithTimer) {
+        let first = Bytes::from("first");
+        let second = Bytes::from("second");
+        insert_value(&handler, first.clone(), from_string("first value"));
+        insert_value(&handler, second.clone(), from_string("second value"));
+
+        let requests = vec![
+            network::GetRequest {
+                header: create_header(network::Command::GetQuiet, &first),
+                key: first.clone(),
+            },
+            network::GetRequest {
+                header: create_header(network::Command::GetQuiet, b"missing"),
+                key: Bytes::from("missing"),
+            },
+            network::GetRequest {
+                header: create_header(network::Command::GetKeyQuiet, &second),
+                key: second.clone(),
+            },
+        ];
+        let responses = futures::executor::block_on(handler.handler.get_quietly_multi(requests));
+        assert_eq!(responses.len(), 2);
+        match (&responses[0], &responses[1]) {
+            (encoder::BinaryResponse::Get(first), encoder::BinaryResponse::Get(second)) => {
+                assert_eq!(first.header.opcode, network::Command::GetQuiet as u8);
+                assert!(first.key.is_empty());
+                assert_eq!(first.value, from_string("first value"));
+                assert_eq!(second.header.opcode, network::Command::GetKeyQuiet as u8);
+                assert_eq!(second.key, Bytes::from("second"));
+                assert_eq!(second.value, from_string("second value"));
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test_case(create_moka_handler() ; "moka_backend")]
+    #[test_case(create_dash_map_handler() ; "dash_map_backend")]
+    #[test_case(create_slab_handler() ; "slab_backend")]
+    #[test_case(create_sieve_handler() ; "sieve_backend")]
     fn get_request_should_return_record(handler: BinaryHandlerWithTimer) {
         let key = Bytes::from("key");
         let header = create_header(network::Command::Get, &key);
@@ -180,6 +235,8 @@ mod tests {
 
     #[test_case(create_moka_handler() ; "moka_backend")]
     #[test_case(create_dash_map_handler() ; "dash_map_backend")]
+    #[test_case(create_slab_handler() ; "slab_backend")]
+    #[test_case(create_sieve_handler() ; "sieve_backend")]
     fn get_request_should_not_return_expired_record(handler: BinaryHandlerWithTimer) {
         let key = Bytes::from("key");
         let header = create_header(network::Command::Get, &key);
@@ -207,6 +264,8 @@ mod tests {
 
     #[test_case(create_moka_handler() ; "moka_backend")]
     #[test_case(create_dash_map_handler() ; "dash_map_backend")]
+    #[test_case(create_slab_handler() ; "slab_backend")]
+    #[test_case(create_sieve_handler() ; "sieve_backend")]
 
     fn get_request_should_return_not_expired_record(handler: BinaryHandlerWithTimer) {
         let key = Bytes::from("key");
@@ -244,6 +303,8 @@ mod tests {
 
     #[test_case(create_moka_handler() ; "moka_backend")]
     #[test_case(create_dash_map_handler() ; "dash_map_backend")]
+    #[test_case(create_slab_handler() ; "slab_backend")]
+    #[test_case(create_sieve_handler() ; "sieve_backend")]
     fn set_request_should_succeed(handler: BinaryHandlerWithTimer) {
         let key = Bytes::from("key");
         let header = create_header(network::Command::Set, &key);
@@ -255,6 +316,7 @@ mod tests {
             expiration: 0,
             key,
             value,
+            tags: Vec::new(),
         });
         let result = handler.handle_request(request);
         match result {
@@ -272,6 +334,8 @@ mod tests {
 
     #[test_case(create_moka_handler() ; "moka_backend")]
     #[test_case(create_dash_map_handler() ; "dash_map_backend")]
+    #[test_case(create_slab_handler() ; "slab_backend")]
+    #[test_case(create_sieve_handler() ; "sieve_backend")]
     fn set_request_should_return_item_too_large_(handler: BinaryHandlerWithTimer) {
         let key = Bytes::from("key");
         let header = create_header(network::Command::Set, &key);
@@ -283,6 +347,7 @@ mod tests {
             expiration: 0,
             key,
             value,
+            tags: Vec::new(),
         });
         let result = handler.handle_request(request);
         match result {
@@ -308,6 +373,8 @@ mod tests {
 
     #[test_case(create_moka_handler() ; "moka_backend")]
     #[test_case(create_dash_map_handler() ; "dash_map_backend")]
+    #[test_case(create_slab_handler() ; "slab_backend")]
+    #[test_case(create_sieve_handler() ; "sieve_backend")]
     fn set_request_on_cas_mismatch_should_return_key_exists(handler: BinaryHandlerWithTimer) {
         let key = Bytes::from("key");
         let mut header = create_header(network::Command::Set, &key);
@@ -320,6 +387,7 @@ mod tests {
             expiration: 0,
             key: key.clone(),
             value: value.clone(),
+            tags: Vec::new(),
         });
 
         let result = handler.handle_request(request);
@@ -342,6 +410,7 @@ mod tests {
             expiration: 0,
             key,
             value,
+            tags: Vec::new(),
         });
 
         let result = handler.handle_request(request);
@@ -368,6 +437,8 @@ mod tests {
 
     #[test_case(create_moka_handler() ; "moka_backend")]
     #[test_case(create_dash_map_handler() ; "dash_map_backend")]
+    #[test_case(create_slab_handler() ; "slab_backend")]
+    #[test_case(create_sieve_handler() ; "sieve_backend")]
     fn version_request_should_return_version(handler: BinaryHandlerWithTimer) {
         let key = String::from("").into_bytes();
         let header = create_header(network::Command::Version, &key);
@@ -396,6 +467,8 @@ mod tests {
 
     #[test_case(create_moka_handler() ; "moka_backend")]
     #[test_case(create_dash_map_handler() ; "dash_map_backend")]
+    #[test_case(create_slab_handler() ; "slab_backend")]
+    #[test_case(create_sieve_handler() ; "sieve_backend")]
     fn increment_request_should_return_cas(handler: BinaryHandlerWithTimer) {
         const EXPECTED_VALUE: u64 = 1;
         let key = Bytes::from("counter");
@@ -433,6 +506,8 @@ mod tests {
 
     #[test_case(create_moka_handler() ; "moka_backend")]
     #[test_case(create_dash_map_handler() ; "dash_map_backend")]
+    #[test_case(create_slab_handler() ; "slab_backend")]
+    #[test_case(create_sieve_handler() ; "sieve_backend")]
     fn increment_request_should_increment_value(handler: BinaryHandlerWithTimer) {
         const EXPECTED_VALUE: u64 = 101;
         let key = Bytes::from("counter");
@@ -473,6 +548,8 @@ mod tests {
 
     #[test_case(create_moka_handler() ; "moka_backend")]
     #[test_case(create_dash_map_handler() ; "dash_map_backend")]
+    #[test_case(create_slab_handler() ; "slab_backend")]
+    #[test_case(create_sieve_handler() ; "sieve_backend")]
     fn increment_quiet_should_increment_value(handler: BinaryHandlerWithTimer) {
         let key = Bytes::from("counter");
         let value = from_string("100");
@@ -488,9 +565,8 @@ mod tests {
         });
 
         let result = handler.handle_request(request);
-        match result {
-            Some(_resp) => unreachable!(),
-            None => {}
+        if let Some(_resp) = result {
+            unreachable!()
         }
         let incremented_value = get_value(&handler, key.clone()).unwrap();
         let expected_value = from_string("101");
@@ -499,6 +575,8 @@ mod tests {
 
     #[test_case(create_moka_handler() ; "moka_backend")]
     #[test_case(create_dash_map_handler() ; "dash_map_backend")]
+    #[test_case(create_slab_handler() ; "slab_backend")]
+    #[test_case(create_sieve_handler() ; "sieve_backend")]
     fn decrement_request_should_return_cas(handler: BinaryHandlerWithTimer) {
         const EXPECTED_VALUE: u64 = 1;
         let key = Bytes::from("counter");
@@ -536,6 +614,8 @@ mod tests {
 
     #[test_case(create_moka_handler() ; "moka_backend")]
     #[test_case(create_dash_map_handler() ; "dash_map_backend")]
+    #[test_case(create_slab_handler() ; "slab_backend")]
+    #[test_case(create_sieve_handler() ; "sieve_backend")]
     fn decrement_request_should_decrement_value(handler: BinaryHandlerWithTimer) {
         const EXPECTED_VALUE: u64 = 99;
         let key = Bytes::from("counter");
@@ -576,6 +656,8 @@ mod tests {
 
     #[test_case(create_moka_handler() ; "moka_backend")]
     #[test_case(create_dash_map_handler() ; "dash_map_backend")]
+    #[test_case(create_slab_handler() ; "slab_backend")]
+    #[test_case(create_sieve_handler() ; "sieve_backend")]
     fn decrement_quiet_should_increment_value(handler: BinaryHandlerWithTimer) {
         let key = Bytes::from("counter");
         let value = from_string("100");
@@ -591,9 +673,8 @@ mod tests {
         });
 
         let result = handler.handle_request(request);
-        match result {
-            Some(_resp) => unreachable!(),
-            None => {}
+        if let Some(_resp) = result {
+            unreachable!()
         }
         let dec_value = get_value(&handler, key.clone()).unwrap();
         let expected_value = from_string("99");
@@ -602,6 +683,8 @@ mod tests {
 
     #[test_case(create_moka_handler() ; "moka_backend")]
     #[test_case(create_dash_map_handler() ; "dash_map_backend")]
+    #[test_case(create_slab_handler() ; "slab_backend")]
+    #[test_case(create_sieve_handler() ; "sieve_backend")]
     fn increment_request_should_error_when_expiration_is_ffffffff(handler: BinaryHandlerWithTimer) {
         let key = Bytes::from("counter");
         let header = create_header(network::Command::Increment, &key);
@@ -637,6 +720,8 @@ mod tests {
 
     #[test_case(create_moka_handler() ; "moka_backend")]
     #[test_case(create_dash_map_handler() ; "dash_map_backend")]
+    #[test_case(create_slab_handler() ; "slab_backend")]
+    #[test_case(create_sieve_handler() ; "sieve_backend")]
     fn decrement_request_should_error_when_expiration_is_ffffffff(handler: BinaryHandlerWithTimer) {
         let key = Bytes::from("counter");
         let header = create_header(network::Command::Decrement, &key);
@@ -672,6 +757,8 @@ mod tests {
 
     #[test_case(create_moka_handler() ; "moka_backend")]
     #[test_case(create_dash_map_handler() ; "dash_map_backend")]
+    #[test_case(create_slab_handler() ; "slab_backend")]
+    #[test_case(create_sieve_handler() ; "sieve_backend")]
     fn flush_should_remove_all(handler: BinaryHandlerWithTimer) {
         let value = from_string("test value");
         for key_suffix in 0..100 {
@@ -712,6 +799,8 @@ mod tests {
 
     #[test_case(create_moka_handler() ; "moka_backend")]
     #[test_case(create_dash_map_handler() ; "dash_map_backend")]
+    #[test_case(create_slab_handler() ; "slab_backend")]
+    #[test_case(create_sieve_handler() ; "sieve_backend")]
     fn flush_quiet_should_remove_all(handler: BinaryHandlerWithTimer) {
         let value = from_string("test value");
         for key_suffix in 0..100 {
@@ -733,11 +822,8 @@ mod tests {
         });
 
         let result = handler.handle_request(request);
-        match result {
-            Some(_resp) => {
-                unreachable!();
-            }
-            None => {}
+        if let Some(_resp) = result {
+            unreachable!();
         }
 
         for key_suffix in 0..100 {
@@ -749,6 +835,8 @@ mod tests {
 
     #[test_case(create_moka_handler() ; "moka_backend")]
     #[test_case(create_dash_map_handler() ; "dash_map_backend")]
+    #[test_case(create_slab_handler() ; "slab_backend")]
+    #[test_case(create_sieve_handler() ; "sieve_backend")]
     fn delete_should_remove_from_store(handler: BinaryHandlerWithTimer) {
         let value = from_string("test value");
         let key = Bytes::from("test_key");
@@ -774,6 +862,182 @@ mod tests {
 
     #[test_case(create_moka_handler() ; "moka_backend")]
     #[test_case(create_dash_map_handler() ; "dash_map_backend")]
+    #[test_case(create_slab_handler() ; "slab_backend")]
+    #[test_case(create_sieve_handler() ; "sieve_backend")]
+    fn scan_request_should_return_metadump(handler: BinaryHandlerWithTimer) {
+        let key = Bytes::from("user:a b");
+        let value = from_string("test value");
+        insert_value(&handler, key.clone(), value.clone());
+        insert_value(&handler, Bytes::from("other"), value.clone());
+        let record = handler.handler.storage.get(&key).unwrap();
+
+        let prefix = Bytes::from("user:");
+        let request = decoder::BinaryRequest::Scan(network::ScanRequest {
+            header: create_header(network::Command::Scan, &prefix),
+            cursor: 0,
+            count: 0,
+            prefix,
+        });
+        match handler.handle_request(request) {
+            Some(encoder::BinaryResponse::Scan(response)) => {
+                let expected = format!(
+                    "key=user%3Aa%20b exp=-1 la=0 cas={} fetch=no cls=0 size={}\n",
+                    record.header.cas,
+                    key.len() + record.len()
+                );
+                assert_eq!(response.cursor, 0);
+                assert_eq!(response.dump, Bytes::from(expected));
+                check_header(
+                    &response.header,
+                    network::Command::Scan,
+                    0,
+                    8,
+                    0,
+                    0,
+                    8 + response.dump.len() as u32,
+                );
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test_case(create_moka_handler() ; "moka_backend")]
+    #[test_case(create_dash_map_handler() ; "dash_map_backend")]
+    #[test_case(create_slab_handler() ; "slab_backend")]
+    #[test_case(create_sieve_handler() ; "sieve_backend")]
+    fn scan_request_with_invalid_cursor_should_fail(handler: BinaryHandlerWithTimer) {
+        let request = decoder::BinaryRequest::Scan(network::ScanRequest {
+            header: create_header(network::Command::Scan, b""),
+            cursor: u64::MAX,
+            count: 10,
+            prefix: Bytes::new(),
+        });
+        match handler.handle_request(request) {
+            Some(encoder::BinaryResponse::Error(response)) => {
+                assert_eq!(
+                    response.header.status,
+                    network::ResponseStatus::InvalidArguments as u16
+                );
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn stats_request(
+        handler: &BinaryHandlerWithTimer,
+        key: &'static str,
+    ) -> encoder::BinaryResponse {
+        let key = Bytes::from(key);
+        let request = decoder::BinaryRequest::Stats(network::StatsRequest {
+            header: create_header(network::Command::Stat, &key),
+            key,
+        });
+        handler.handle_request(request).unwrap()
+    }
+
+    #[test]
+    fn stats_hotkeys_request_should_return_top_keys() {
+        let server = create_dash_map_server();
+        let storage = store::MemcStore::new(server.store.clone())
+            .with_hot_keys(Some(Arc::new(HotKeys::new(1))));
+        let handler =
+            BinaryHandlerWithTimer::new(BinaryHandler::new(Arc::new(storage)), server.timer);
+        for _ in 0..3 {
+            insert_value(&handler, Bytes::from("hot key"), from_string("value"));
+        }
+        insert_value(&handler, Bytes::from("cold"), from_string("value"));
+        for _ in 0..2 {
+            assert!(get_value(&handler, Bytes::from("hot key")).is_some());
+        }
+
+        match stats_request(&handler, "hotkeys") {
+            encoder::BinaryResponse::Stats(response) => {
+                let record = |name: &str| {
+                    response
+                        .records
+                        .iter()
+                        .find(|(stat, _)| stat == name)
+                        .map(|(_, value)| value.as_str())
+                };
+                assert_eq!(record("sample_rate"), Some("1"));
+                assert_eq!(record("reads:1:key"), Some("hot%20key"));
+                assert_eq!(record("reads:1:count"), Some("2"));
+                assert_eq!(record("reads:2:key"), None);
+                assert_eq!(record("writes:1:key"), Some("hot%20key"));
+                assert_eq!(record("writes:1:count"), Some("3"));
+                assert_eq!(record("writes:2:key"), Some("cold"));
+                assert_eq!(record("bytes:1:count"), Some("25"));
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test_case(create_moka_handler() ; "moka_backend")]
+    #[test_case(create_dash_map_handler() ; "dash_map_backend")]
+    #[test_case(create_slab_handler() ; "slab_backend")]
+    #[test_case(create_sieve_handler() ; "sieve_backend")]
+    fn stats_request_should_reject_unknown_group(handler: BinaryHandlerWithTimer) {
+        match stats_request(&handler, "hotkeys") {
+            encoder::BinaryResponse::Stats(response) => assert!(response.records.is_empty()),
+            _ => unreachable!(),
+        }
+        match stats_request(&handler, "unknown") {
+            encoder::BinaryResponse::Error(response) => {
+                assert_eq!(
+                    response.header.status,
+                    network::ResponseStatus::KeyNotExists as u16
+                );
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test_case(create_moka_handler() ; "moka_backend")]
+    #[test_case(create_dash_map_handler() ; "dash_map_backend")]
+    #[test_case(create_slab_handler() ; "slab_backend")]
+    #[test_case(create_sieve_handler() ; "sieve_backend")]
+    fn invalidate_tag_request_should_expire_tagged_items(handler: BinaryHandlerWithTimer) {
+        let key = Bytes::from("user:1:profile");
+        let request = decoder::BinaryRequest::Set(network::SetRequest {
+            header: create_header(network::Command::Set, &key),
+            flags: 0,
+            expiration: 0,
+            key: key.clone(),
+            value: from_string("profile"),
+            tags: vec![Bytes::from("user:1")],
+        });
+        assert!(matches!(
+            handler.handle_request(request),
+            Some(encoder::BinaryResponse::Set(_))
+        ));
+        assert!(get_value(&handler, key.clone()).is_some());
+
+        let tag = Bytes::from("user:1");
+        let request = decoder::BinaryRequest::InvalidateTag(network::InvalidateTagRequest {
+            header: create_header(network::Command::InvalidateTag, &tag),
+            key: tag,
+        });
+        match handler.handle_request(request) {
+            Some(encoder::BinaryResponse::InvalidateTag(response)) => {
+                check_header(
+                    &response.header,
+                    network::Command::InvalidateTag,
+                    0,
+                    0,
+                    0,
+                    0,
+                    0,
+                );
+            }
+            _ => unreachable!(),
+        }
+        assert!(get_value(&handler, key).is_none());
+    }
+
+    #[test_case(create_moka_handler() ; "moka_backend")]
+    #[test_case(create_dash_map_handler() ; "dash_map_backend")]
+    #[test_case(create_slab_handler() ; "slab_backend")]
+    #[test_case(create_sieve_handler() ; "sieve_backend")]
     fn delete_should_return_error_if_not_exists(handler: BinaryHandlerWithTimer) {
         let key = Bytes::from("test_key");
 
@@ -805,6 +1069,8 @@ mod tests {
 
     #[test_case(create_moka_handler() ; "moka_backend")]
     #[test_case(create_dash_map_handler() ; "dash_map_backend")]
+    #[test_case(create_slab_handler() ; "slab_backend")]
+    #[test_case(create_sieve_handler() ; "sieve_backend")]
     fn noop_request(handler: BinaryHandlerWithTimer) {
         let key = String::from("").into_bytes();
 
@@ -825,6 +1091,8 @@ mod tests {
 
     #[test_case(create_moka_handler() ; "moka_backend")]
     #[test_case(create_dash_map_handler() ; "dash_map_backend")]
+    #[test_case(create_slab_handler() ; "slab_backend")]
+    #[test_case(create_sieve_handler() ; "sieve_backend")]
     fn quit_request(handler: BinaryHandlerWithTimer) {
         let key = String::from("").into_bytes();
 
@@ -845,20 +1113,23 @@ mod tests {
 
     #[test_case(create_moka_handler() ; "moka_backend")]
     #[test_case(create_dash_map_handler() ; "dash_map_backend")]
+    #[test_case(create_slab_handler() ; "slab_backend")]
+    #[test_case(create_sieve_handler() ; "sieve_backend")]
     fn quit_quiet_request(handler: BinaryHandlerWithTimer) {
         let key = String::from("").into_bytes();
 
         let header = create_header(network::Command::QuitQuiet, &key);
         let request = decoder::BinaryRequest::QuitQuietly(network::QuitRequest { header });
         let result = handler.handle_request(request);
-        match result {
-            Some(_resp) => unreachable!(),
-            None => {}
+        if let Some(_resp) = result {
+            unreachable!()
         }
     }
 
     #[test_case(create_moka_handler() ; "moka_backend")]
     #[test_case(create_dash_map_handler() ; "dash_map_backend")]
+    #[test_case(create_slab_handler() ; "slab_backend")]
+    #[test_case(create_sieve_handler() ; "sieve_backend")]
     fn add_request_should_succeed_if_item_not_exists_and_fail_if_exists(
         handler: BinaryHandlerWithTimer,
     ) {
@@ -873,6 +1144,7 @@ mod tests {
             expiration: 0,
             key: key.clone(),
             value: value.clone(),
+            tags: Vec::new(),
         });
 
         let result = handler.handle_request(request);
@@ -895,6 +1167,7 @@ mod tests {
             expiration: 0,
             key,
             value,
+            tags: Vec::new(),
         });
 
         let result = handler.handle_request(request);
@@ -921,6 +1194,8 @@ mod tests {
 
     #[test_case(create_moka_handler() ; "moka_backend")]
     #[test_case(create_dash_map_handler() ; "dash_map_backend")]
+    #[test_case(create_slab_handler() ; "slab_backend")]
+    #[test_case(create_sieve_handler() ; "sieve_backend")]
     fn add_quiet_request_should_succeed_if_item_does_not_exists(handler: BinaryHandlerWithTimer) {
         let key = Bytes::from("key");
         let mut header = create_header(network::Command::Add, &key);
@@ -933,15 +1208,13 @@ mod tests {
             expiration: 0,
             key: key.clone(),
             value: value.clone(),
+            tags: Vec::new(),
         });
 
         let result = handler.handle_request(request);
 
-        match result {
-            Some(_resp) => {
-                unreachable!();
-            }
-            None => {}
+        if let Some(_resp) = result {
+            unreachable!();
         }
         header.cas = 100;
         let request = decoder::BinaryRequest::AddQuietly(network::AddRequest {
@@ -950,6 +1223,7 @@ mod tests {
             expiration: 0,
             key,
             value,
+            tags: Vec::new(),
         });
 
         let result = handler.handle_request(request);
@@ -976,6 +1250,8 @@ mod tests {
 
     #[test_case(create_moka_handler() ; "moka_backend")]
     #[test_case(create_dash_map_handler() ; "dash_map_backend")]
+    #[test_case(create_slab_handler() ; "slab_backend")]
+    #[test_case(create_sieve_handler() ; "sieve_backend")]
     fn replace_request_should_fail_if_not_exists_and_succeed_if_exists(
         handler: BinaryHandlerWithTimer,
     ) {
@@ -990,6 +1266,7 @@ mod tests {
             expiration: 0,
             key: key.clone(),
             value: value.clone(),
+            tags: Vec::new(),
         });
 
         let result = handler.handle_request(request);
@@ -1026,6 +1303,7 @@ mod tests {
             expiration: 0,
             key: key.clone(),
             value: value.clone(),
+            tags: Vec::new(),
         });
 
         let result = handler.handle_request(request);
@@ -1046,6 +1324,8 @@ mod tests {
 
     #[test_case(create_moka_handler() ; "moka_backend")]
     #[test_case(create_dash_map_handler() ; "dash_map_backend")]
+    #[test_case(create_slab_handler() ; "slab_backend")]
+    #[test_case(create_sieve_handler() ; "sieve_backend")]
     fn replace_quiet_request_should_fail_if_not_exists_and_succeed_if_exists(
         handler: BinaryHandlerWithTimer,
     ) {
@@ -1060,6 +1340,7 @@ mod tests {
             expiration: 0,
             key: key.clone(),
             value: value.clone(),
+            tags: Vec::new(),
         });
 
         let result = handler.handle_request(request);
@@ -1096,14 +1377,12 @@ mod tests {
             expiration: 0,
             key: key.clone(),
             value: value.clone(),
+            tags: Vec::new(),
         });
 
         let result = handler.handle_request(request);
-        match result {
-            Some(_resp) => {
-                unreachable!();
-            }
-            None => {}
+        if let Some(_resp) = result {
+            unreachable!();
         }
         let replaced_value = get_value(&handler, key.clone()).unwrap();
         assert_eq!(replaced_value, value);
@@ -1111,6 +1390,8 @@ mod tests {
 
     #[test_case(create_moka_handler() ; "moka_backend")]
     #[test_case(create_dash_map_handler() ; "dash_map_backend")]
+    #[test_case(create_slab_handler() ; "slab_backend")]
+    #[test_case(create_sieve_handler() ; "sieve_backend")]
     fn append_request_should_succeed_when_value_exists(handler: BinaryHandlerWithTimer) {
         let key = Bytes::from("hello");
         let value = from_string("hello ");
@@ -1140,6 +1421,8 @@ mod tests {
 
     #[test_case(create_moka_handler() ; "moka_backend")]
     #[test_case(create_dash_map_handler() ; "dash_map_backend")]
+    #[test_case(create_slab_handler() ; "slab_backend")]
+    #[test_case(create_sieve_handler() ; "sieve_backend")]
     fn append_quiet_request_should_succeed_when_value_exists(handler: BinaryHandlerWithTimer) {
         let key = Bytes::from("hello");
         let value = from_string("hello ");
@@ -1152,9 +1435,8 @@ mod tests {
             value: from_string("world!"),
         });
         let result = handler.handle_request(request);
-        match result {
-            Some(_resp) => unreachable!(),
-            None => {}
+        if let Some(_resp) = result {
+            unreachable!()
         }
         let server_value = get_value(&handler, key).unwrap();
         assert_eq!(server_value, from_string("hello world!"));
@@ -1162,6 +1444,8 @@ mod tests {
 
     #[test_case(create_moka_handler() ; "moka_backend")]
     #[test_case(create_dash_map_handler() ; "dash_map_backend")]
+    #[test_case(create_slab_handler() ; "slab_backend")]
+    #[test_case(create_sieve_handler() ; "sieve_backend")]
     fn prepend_request_should_succeed_when_value_exists(handler: BinaryHandlerWithTimer) {
         let key = Bytes::from("hello");
         let value = from_string(" hello");
@@ -1191,6 +1475,8 @@ mod tests {
 
     #[test_case(create_moka_handler() ; "moka_backend")]
     #[test_case(create_dash_map_handler() ; "dash_map_backend")]
+    #[test_case(create_slab_handler() ; "slab_backend")]
+    #[test_case(create_sieve_handler() ; "sieve_backend")]
     fn prepend_quiet_request_should_succeed_when_value_exists(handler: BinaryHandlerWithTimer) {
         let key = Bytes::from("hello");
         let value = from_string("hello");
@@ -1203,11 +1489,51 @@ mod tests {
             value: from_string("world! "),
         });
         let result = handler.handle_request(request);
-        match result {
-            Some(_resp) => unreachable!(),
-            None => {}
+        if let Some(_resp) = result {
+            unreachable!()
         }
         let server_value = get_value(&handler, key).unwrap();
         assert_eq!(server_value, from_string("world! hello"));
     }
+
+    #[test]
+    fn replica_should_reject_mutations_until_promoted() {
+        let server = create_dash_map_server();
+        server
+            .store
+            .set(
+                Bytes::from("key"),
+                cache::Record::new(from_string("value"), 0, 0, 0),
+            )
+            .unwrap();
+        let replication = Arc::new(Replication::replica(
+            server.timer.clone(),
+            "127.0.0.1:11212".to_string(),
+        ));
+        let storage =
+            store::MemcStore::new(server.store.clone()).with_replication(Some(replication.clone()));
+        let handler =
+            BinaryHandlerWithTimer::new(BinaryHandler::new(Arc::new(storage)), server.timer);
+
+        let key = Bytes::from("key");
+        let header = create_header(network::Command::DeleteQuiet, &key);
+        let request = decoder::BinaryRequest::DeleteQuiet(network::DeleteRequest {
+            header,
+            key: key.clone(),
+        });
+        match handler.handle_request(request) {
+            Some(encoder::BinaryResponse::Error(response)) => {
+                assert_eq!(
+                    response.header.status,
+                    error::CacheError::NotSupported as u16
+                );
+            }
+            _ => unreachable!(),
+        }
+        assert_eq!(get_value(&handler, key.clone()), Some(from_string("value")));
+
+        assert!(replication.promote());
+        insert_value(&handler, key.clone(), from_string("promoted"));
+        assert_eq!(get_value(&handler, key), Some(from_string("promoted")));
+    }
 }
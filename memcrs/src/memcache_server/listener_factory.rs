@@ -12,7 +12,7 @@ pub struct ListenerFactory {
     factory: ListenerSocketFactory,
 }
 
-pub fn create_listener_from_config(memc_config: MemcrsdConfig) -> ListenerFactory {
+pub fn create_listener_from_config(memc_config: &MemcrsdConfig) -> ListenerFactory {
     let config = ListenSocketConfig {
         port: memc_config.port,
         listen_address: memc_config.listen_address,
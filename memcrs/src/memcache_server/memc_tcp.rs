@@ -1,7 +1,9 @@
+use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::Duration;
 
 use tokio::io;
-use tokio::net::TcpListener;
+use tokio::net::{TcpListener, TcpStream};
 use tokio::sync::Semaphore;
 use tokio_util::sync::CancellationToken;
 use tracing::error;
@@ -9,8 +11,12 @@ use tracing::error;
 //use tracing_attributes::instrument;
 
 use super::client_handler;
-use crate::cache::cache::Cache;
+use super::partition_router::PartitionRouter;
+use super::text_client::TextClient;
 use crate::memcache::store as storage;
+use crate::proxy::client::{detect_protocol, Protocol, ProxyClient};
+use crate::proxy::text::TextProxyClient;
+use crate::proxy::{BackendPool, Proxy};
 
 #[derive(Clone, Copy)]
 pub struct MemcacheServerConfig {
@@ -34,22 +40,39 @@ pub struct MemcacheTcpServer {
     limit_connections: Arc<Semaphore>,
     config: MemcacheServerConfig,
     cancellation_token: CancellationToken,
+    router: Option<PartitionRouter>,
+    proxy: Option<Arc<BackendPool>>,
 }
 
 impl MemcacheTcpServer {
     pub fn new(
         config: MemcacheServerConfig,
-        store: Arc<dyn Cache + Send + Sync>,
+        storage: Arc<storage::MemcStore>,
         cancellation_token: CancellationToken,
     ) -> MemcacheTcpServer {
         MemcacheTcpServer {
-            storage: Arc::new(storage::MemcStore::new(store)),
+            storage,
             limit_connections: Arc::new(Semaphore::new(config.connection_limit as usize)),
             config,
             cancellation_token,
+            router: None,
+            proxy: None,
         }
     }
 
+    /// Forwards requests for keys owned by other workers (partitioned keyspace mode)
+    pub fn with_router(mut self, router: PartitionRouter) -> MemcacheTcpServer {
+        self.router = Some(router);
+        self
+    }
+
+    /// Forwards requests to backends instead of serving them (proxy mode),
+    /// backend connections are shared by clients of this server
+    pub fn with_proxy(mut self, proxy: Option<Arc<Proxy>>) -> MemcacheTcpServer {
+        self.proxy = proxy.map(|proxy| Arc::new(BackendPool::new(proxy)));
+        self
+    }
+
     pub async fn run(&mut self, std_listener: std::net::TcpListener) -> io::Result<()> {
         let listener = TcpListener::from_std(std_listener).unwrap_or_else(|e| {
             log::error!("Failed to create Tokio TCP listener: {}", e);
@@ -68,20 +91,11 @@ impl MemcacheTcpServer {
                             socket.set_zero_linger().unwrap_or_else(|err| {
                                 log::error!("System call set_zero_linger failure: {}", err);
                             });
-                            let mut client = client_handler::Client::new(
-                                Arc::clone(&self.storage),
-                                socket,
-                                peer_addr,
-                                self.get_client_config(),
-                                Arc::clone(&self.limit_connections),
-                                self.cancellation_token.clone()
-                            );
-
-                            self.limit_connections.acquire().await.unwrap().forget();
-                            // Like with other small servers, we'll `spawn` this client to ensure it
-                            // runs concurrently with all other clients. The `move` keyword is used
-                            // here to move ownership of our store handle into the async closure.
-                            tokio::spawn(async move { client.handle().await });
+                            if let Some(pool) = self.proxy.clone() {
+                                self.spawn_proxy_client(pool, socket, peer_addr).await;
+                                continue;
+                            }
+                            self.spawn_client(socket, peer_addr).await;
                         },
                         Err(err) => {
                             error!("Accept error: {}", err);
@@ -96,6 +110,94 @@ impl MemcacheTcpServer {
         }
     }
 
+    /// Serves a binary or a text protocol client, told by the first
+    /// byte it sends
+    async fn spawn_client(&self, socket: TcpStream, addr: SocketAddr) {
+        let storage = Arc::clone(&self.storage);
+        let config = self.get_client_config();
+        let limit_connections = Arc::clone(&self.limit_connections);
+        let cancellation_token = self.cancellation_token.clone();
+        let router = self.router.clone();
+        self.limit_connections.acquire().await.unwrap().forget();
+        // Like with other small servers, we'll `spawn` this client to ensure it
+        // runs concurrently with all other clients. The `move` keyword is used
+        // here to move ownership of our store handle into the async closure.
+        tokio::spawn(async move {
+            let wait = Duration::from_secs(config.rx_timeout_secs as u64);
+            match detect_protocol(&socket, wait).await {
+                Some(Protocol::Binary) => {
+                    client_handler::Client::new(
+                        storage,
+                        socket,
+                        addr,
+                        config,
+                        limit_connections,
+                        cancellation_token,
+                    )
+                    .with_router(router)
+                    .handle()
+                    .await
+                }
+                Some(Protocol::Text) => {
+                    TextClient::new(
+                        storage,
+                        socket,
+                        addr,
+                        config,
+                        limit_connections,
+                        cancellation_token,
+                    )
+                    .with_router(router)
+                    .handle()
+                    .await
+                }
+                None => limit_connections.add_permits(1),
+            }
+        });
+    }
+
+    async fn spawn_proxy_client(
+        &self,
+        pool: Arc<BackendPool>,
+        socket: TcpStream,
+        addr: SocketAddr,
+    ) {
+        let config = self.get_client_config();
+        let limit_connections = Arc::clone(&self.limit_connections);
+        let cancellation_token = self.cancellation_token.clone();
+        self.limit_connections.acquire().await.unwrap().forget();
+        tokio::spawn(async move {
+            let wait = Duration::from_secs(config.rx_timeout_secs as u64);
+            match detect_protocol(&socket, wait).await {
+                Some(Protocol::Binary) => {
+                    ProxyClient::new(
+                        pool,
+                        socket,
+                        addr,
+                        config,
+                        limit_connections,
+                        cancellation_token,
+                    )
+                    .handle()
+                    .await
+                }
+                Some(Protocol::Text) => {
+                    TextProxyClient::new(
+                        pool,
+                        socket,
+                        addr,
+                        config,
+                        limit_connections,
+                        cancellation_token,
+                    )
+                    .handle()
+                    .await
+                }
+                None => limit_connections.add_permits(1),
+            }
+        });
+    }
+
     fn get_client_config(&self) -> client_handler::ClientConfig {
         client_handler::ClientConfig {
             item_memory_limit: self.config.item_memory_limit,
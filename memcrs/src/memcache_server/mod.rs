@@ -4,9 +4,11 @@ pub mod handler;
 mod listen_socket_config;
 pub mod listener_factory;
 pub mod memc_tcp;
+pub mod partition_router;
 mod port_file_writer;
 pub mod register_cancellation;
 pub mod runtime_builder;
 pub mod server_context;
 mod server_thread;
+pub mod text_client;
 mod threadpool_runtime_builder;
@@ -0,0 +1,479 @@
+use std::sync::Arc;
+
+use futures::future::join_all;
+use futures::stream::{FuturesUnordered, StreamExt};
+use tokio::sync::{mpsc, oneshot};
+use tokio_util::sync::CancellationToken;
+
+use super::handler;
+use crate::memory_store::partitioned_store::PartitionedMemoryStore;
+use crate::memory_store::shared_store_state::SharedStoreState;
+use crate::protocol::binary::decoder::BinaryRequest;
+use crate::protocol::binary::encoder::BinaryResponse;
+use crate::protocol::binary::network::GetRequest;
+
+/// Messages queued for a partition owner, senders wait once it is full
+const PARTITION_QUEUE_SIZE: usize = 1024;
+/// Forwarded messages a partition owner serves at the same time
+const MAX_SERVED: usize = 256;
+
+/// Work sent to the owner of a partition
+pub enum Forwarded {
+    Request {
+        request: BinaryRequest,
+        response: oneshot::Sender<Option<BinaryResponse>>,
+    },
+    /// Quiet gets of one client run for keys the partition owns
+    QuietGets {
+        requests: Vec<GetRequest>,
+        responses: oneshot::Sender<Vec<Option<BinaryResponse>>>,
+    },
+    /// Flush of the partition, the worker which got the flush responds
+    Flush {
+        expiration: u32,
+        done: oneshot::Sender<()>,
+    },
+}
+
+pub type PartitionReceiver = mpsc::Receiver<Forwarded>;
+
+/// Routes requests in partitioned keyspace mode.
+///
+/// Every worker owns one partition of the store, requests for keys
+/// owned by other workers are sent to them over a channel and the
+/// response is sent back, so a partition is only ever touched by its
+/// owner. Runs of quiet gets are sent as one message per partition,
+/// flushes go to every partition and scans to the partition the
+/// cursor is in.
+#[derive(Clone)]
+pub struct PartitionRouter {
+    local: usize,
+    store: Arc<PartitionedMemoryStore>,
+    partitions: Arc<Vec<mpsc::Sender<Forwarded>>>,
+}
+
+impl PartitionRouter {
+    /// Creates a router for every partition of a store and receivers on
+    /// which partition owners should serve forwarded requests.
+    pub fn create(store: Arc<PartitionedMemoryStore>) -> Vec<(PartitionRouter, PartitionReceiver)> {
+        let (senders, receivers): (Vec<_>, Vec<_>) = (0..store.partitions())
+            .map(|_| mpsc::channel(PARTITION_QUEUE_SIZE))
+            .unzip();
+        let senders = Arc::new(senders);
+        receivers
+            .into_iter()
+            .enumerate()
+            .map(|(local, receiver)| {
+                let router = PartitionRouter {
+                    local,
+                    store: store.clone(),
+                    partitions: senders.clone(),
+                };
+                (router, receiver)
+            })
+            .collect()
+    }
+
+    /// Makes the calling thread the owner of the local partition, it has
+    /// to be called by the worker thread before it serves requests
+    pub fn attach(&self) {
+        self.store.attach(self.local)
+    }
+
+    /// Returns partition owning the request key, None if request
+    /// can be handled by any worker
+    fn owner(&self, request: &BinaryRequest) -> Option<usize> {
+        let owner = match request {
+            BinaryRequest::Noop(_)
+            | BinaryRequest::Version(_)
+            | BinaryRequest::Stats(_)
+            | BinaryRequest::InvalidateTag(_)
+            | BinaryRequest::UnkownCommand(_)
+            | BinaryRequest::ItemTooLarge(_)
+            | BinaryRequest::Flush(_)
+            | BinaryRequest::FlushQuietly(_)
+            | BinaryRequest::Quit(_)
+            | BinaryRequest::QuitQuietly(_) => return None,
+            BinaryRequest::Scan(request) => self.store.scan_partition(request.cursor),
+            _ => self.store.partition_for(&request.get_key()),
+        };
+        // cursors out of range are rejected by the local store
+        (owner != self.local && owner < self.store.partitions()).then_some(owner)
+    }
+
+    /// Handles request locally or forwards it to the key owner
+    pub async fn handle_request(
+        &self,
+        handler: &handler::BinaryHandler,
+        request: BinaryRequest,
+    ) -> Option<BinaryResponse> {
+        if let BinaryRequest::Flush(flush) | BinaryRequest::FlushQuietly(flush) = &request {
+            self.flush_remote(flush.expiration).await;
+            return handler.handle_request(request).await;
+        }
+        let owner = match self.owner(&request) {
+            Some(owner) => owner,
+            None => return handler.handle_request(request).await,
+        };
+        let (response, receiver) = oneshot::channel();
+        let forwarded = Forwarded::Request { request, response };
+        if let Err(mpsc::error::SendError(Forwarded::Request { request, .. })) =
+            self.partitions[owner].send(forwarded).await
+        {
+            // owner is gone, server is shutting down
+            debug!("Partition {} is not available", owner);
+            return handler.handle_request(request).await;
+        }
+        match receiver.await {
+            Ok(response) => response,
+            Err(_) => {
+                error!("Partition {} dropped forwarded request", owner);
+                None
+            }
+        }
+    }
+
+    /// Resolves a run of quiet gets like BinaryHandler::get_quietly_multi,
+    /// every owner gets its keys in one message and owners are asked
+    /// at the same time
+    pub async fn get_quietly_multi(
+        &self,
+        handler: &handler::BinaryHandler,
+        requests: Vec<GetRequest>,
+    ) -> Vec<BinaryResponse> {
+        let groups = SharedStoreState::group_by_shard(requests.iter().map(|r| &r.key), |key| {
+            self.store.partition_for(key)
+        });
+        let mut requests: Vec<Option<GetRequest>> = requests.into_iter().map(Some).collect();
+        let mut responses: Vec<Option<BinaryResponse>> = requests.iter().map(|_| None).collect();
+        let lookups = groups.into_iter().map(|(partition, positions)| {
+            let batch = positions
+                .iter()
+                .filter_map(|&position| requests[position].take())
+                .collect();
+            async move {
+                let results = self.get_quietly_each(handler, partition, batch).await;
+                (positions, results)
+            }
+        });
+        for (positions, results) in join_all(lookups).await {
+            for (position, response) in positions.into_iter().zip(results) {
+                responses[position] = response;
+            }
+        }
+        responses.into_iter().flatten().collect()
+    }
+
+    async fn get_quietly_each(
+        &self,
+        handler: &handler::BinaryHandler,
+        partition: usize,
+        requests: Vec<GetRequest>,
+    ) -> Vec<Option<BinaryResponse>> {
+        if partition == self.local {
+            return handler.get_quietly_each(requests).await;
+        }
+        let (responses, receiver) = oneshot::channel();
+        let forwarded = Forwarded::QuietGets {
+            requests,
+            responses,
+        };
+        if let Err(mpsc::error::SendError(Forwarded::QuietGets { requests, .. })) =
+            self.partitions[partition].send(forwarded).await
+        {
+            debug!("Partition {} is not available", partition);
+            return handler.get_quietly_each(requests).await;
+        }
+        receiver.await.unwrap_or_else(|_| {
+            error!("Partition {} dropped forwarded quiet gets", partition);
+            Vec::new()
+        })
+    }
+
+    /// Flushes partitions of other workers, the local one is flushed
+    /// by the request itself
+    async fn flush_remote(&self, expiration: u32) {
+        let flushes = (0..self.partitions.len())
+            .filter(|partition| *partition != self.local)
+            .map(|partition| async move {
+                let (done, receiver) = oneshot::channel();
+                let forwarded = Forwarded::Flush { expiration, done };
+                if self.partitions[partition].send(forwarded).await.is_err()
+                    || receiver.await.is_err()
+                {
+                    debug!("Partition {} was not flushed", partition);
+                }
+            });
+        join_all(flushes).await;
+    }
+}
+
+/// Serves requests forwarded by other workers to the partition owner,
+/// up to MAX_SERVED of them at the same time
+pub async fn serve_partition(
+    mut receiver: PartitionReceiver,
+    handler: handler::BinaryHandler,
+    cancellation_token: CancellationToken,
+) {
+    let mut served = FuturesUnordered::new();
+    loop {
+        tokio::select! {
+            forwarded = receiver.recv(), if served.len() < MAX_SERVED => {
+                match forwarded {
+                    Some(forwarded) => served.push(serve(&handler, forwarded)),
+                    None => break,
+                }
+            }
+            Some(()) = served.next(), if !served.is_empty() => {}
+            _ = cancellation_token.cancelled() => {
+                debug!("Cancelling partition server loop...");
+                break;
+            }
+        }
+    }
+}
+
+async fn serve(handler: &handler::BinaryHandler, forwarded: Forwarded) {
+    // client could have disconnected in the meantime
+    match forwarded {
+        Forwarded::Request { request, response } => {
+            let _ = response.send(handler.handle_request(request).await);
+        }
+        Forwarded::QuietGets {
+            requests,
+            responses,
+        } => {
+            let _ = responses.send(handler.get_quietly_each(requests).await);
+        }
+        Forwarded::Flush { expiration, done } => {
+            handler.flush_store(expiration);
+            let _ = done.send(());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memcache::cli::parser::DashMapConfig;
+    use crate::memcache::store::MemcStore;
+    use crate::mock::handler::{create_get_request_by_key, create_header, create_set_request};
+    use crate::mock::mock_server::MockSystemTimer;
+    use crate::protocol::binary::network;
+    use bytes::Bytes;
+    use std::collections::HashSet;
+    use std::thread::JoinHandle;
+
+    /// Router of partition 0 attached to the test thread and the owner of
+    /// partition 1 serving on a thread of its own, like a worker
+    fn create_routers(cancellation_token: &CancellationToken) -> (PartitionRouter, JoinHandle<()>) {
+        let store = Arc::new(PartitionedMemoryStore::new(
+            Arc::new(MockSystemTimer::new()),
+            DashMapConfig::default(),
+            2,
+        ));
+        let mut routers = PartitionRouter::create(store);
+        let (owner_router, owner_receiver) = routers.pop().unwrap();
+        let (router, _receiver) = routers.pop().unwrap();
+        router.attach();
+        let cancellation_token = cancellation_token.clone();
+        let owner = std::thread::spawn(move || {
+            owner_router.attach();
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .unwrap();
+            let handler = create_handler(&owner_router);
+            runtime.block_on(serve_partition(owner_receiver, handler, cancellation_token));
+        });
+        (router, owner)
+    }
+
+    fn create_handler(router: &PartitionRouter) -> handler::BinaryHandler {
+        handler::BinaryHandler::new(Arc::new(MemcStore::new(router.store.clone())))
+    }
+
+    /// Keys of both partitions
+    fn keys(router: &PartitionRouter, count: usize) -> Vec<Bytes> {
+        let keys: Vec<Bytes> = (0..count)
+            .map(|idx| Bytes::from(format!("key{}", idx)))
+            .collect();
+        let partitions: HashSet<usize> = keys
+            .iter()
+            .map(|key| router.store.partition_for(key))
+            .collect();
+        assert_eq!(partitions.len(), 2);
+        keys
+    }
+
+    fn remote_key(router: &PartitionRouter) -> Bytes {
+        (0..)
+            .map(|idx| Bytes::from(format!("key{}", idx)))
+            .find(|key| router.store.partition_for(key) != router.local)
+            .unwrap()
+    }
+
+    async fn set_all(router: &PartitionRouter, handler: &handler::BinaryHandler, keys: &[Bytes]) {
+        for key in keys {
+            let response = router
+                .handle_request(handler, create_set_request(key.clone(), key.clone()))
+                .await;
+            assert!(matches!(response, Some(BinaryResponse::Set(_))));
+        }
+    }
+
+    async fn get_value(
+        router: &PartitionRouter,
+        handler: &handler::BinaryHandler,
+        key: &Bytes,
+    ) -> Option<Bytes> {
+        match router
+            .handle_request(handler, create_get_request_by_key(key))
+            .await
+        {
+            Some(BinaryResponse::Get(response)) => Some(response.value),
+            _ => None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_remote_key_is_forwarded_to_owner() {
+        let cancellation_token = CancellationToken::new();
+        let (router, owner) = create_routers(&cancellation_token);
+        let handler = create_handler(&router);
+        let key = remote_key(&router);
+        let response = router
+            .handle_request(
+                &handler,
+                create_set_request(key.clone(), Bytes::from("value")),
+            )
+            .await;
+        assert!(matches!(response, Some(BinaryResponse::Set(_))));
+        assert_eq!(
+            get_value(&router, &handler, &key).await,
+            Some(Bytes::from("value"))
+        );
+        // item is kept by the owner only
+        assert!(!matches!(
+            handler
+                .handle_request(create_get_request_by_key(&key))
+                .await,
+            Some(BinaryResponse::Get(_))
+        ));
+
+        cancellation_token.cancel();
+        owner.join().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_quiet_gets_keep_order_across_partitions() {
+        let cancellation_token = CancellationToken::new();
+        let (router, owner) = create_routers(&cancellation_token);
+        let handler = create_handler(&router);
+        let keys = keys(&router, 32);
+        set_all(&router, &handler, &keys).await;
+
+        let mut lookup = keys.clone();
+        lookup.insert(5, Bytes::from("missing"));
+        let requests = lookup
+            .into_iter()
+            .map(|key| network::GetRequest {
+                header: create_header(network::Command::GetKeyQuiet, &key),
+                key,
+            })
+            .collect();
+        let responses = router.get_quietly_multi(&handler, requests).await;
+        let values: Vec<Bytes> = responses
+            .into_iter()
+            .map(|response| match response {
+                BinaryResponse::Get(response) => response.value,
+                _ => unreachable!(),
+            })
+            .collect();
+        assert_eq!(values, keys);
+
+        cancellation_token.cancel();
+        owner.join().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_flush_reaches_all_partitions() {
+        let cancellation_token = CancellationToken::new();
+        let (router, owner) = create_routers(&cancellation_token);
+        let handler = create_handler(&router);
+        let keys = keys(&router, 32);
+        set_all(&router, &handler, &keys).await;
+
+        let flush = BinaryRequest::Flush(network::FlushRequest {
+            header: create_header(network::Command::Flush, &[]),
+            expiration: 0,
+        });
+        assert!(matches!(
+            router.handle_request(&handler, flush).await,
+            Some(BinaryResponse::Flush(_))
+        ));
+        for key in &keys {
+            assert_eq!(get_value(&router, &handler, key).await, None);
+        }
+
+        cancellation_token.cancel();
+        owner.join().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_scan_follows_cursor_to_owner() {
+        let cancellation_token = CancellationToken::new();
+        let (router, owner) = create_routers(&cancellation_token);
+        let handler = create_handler(&router);
+        let keys = keys(&router, 32);
+        set_all(&router, &handler, &keys).await;
+
+        let mut scanned = HashSet::new();
+        let mut cursor = 0;
+        loop {
+            let scan = BinaryRequest::Scan(network::ScanRequest {
+                header: create_header(network::Command::Scan, &[]),
+                cursor,
+                count: 5,
+                prefix: Bytes::new(),
+            });
+            let page = match router.handle_request(&handler, scan).await {
+                Some(BinaryResponse::Scan(page)) => page,
+                _ => unreachable!(),
+            };
+            for line in std::str::from_utf8(&page.dump).unwrap().lines() {
+                let key = line.split_whitespace().next().unwrap();
+                let key = key.strip_prefix("key=").unwrap();
+                assert!(scanned.insert(Bytes::from(key.to_string())));
+            }
+            if page.cursor == 0 {
+                break;
+            }
+            cursor = page.cursor;
+        }
+        assert_eq!(scanned, keys.into_iter().collect());
+
+        cancellation_token.cancel();
+        owner.join().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_keyless_request_is_handled_locally() {
+        let cancellation_token = CancellationToken::new();
+        let (router, owner) = create_routers(&cancellation_token);
+        let handler = create_handler(&router);
+        let request = create_get_request_by_key(&remote_key(&router));
+        assert!(router.owner(&request).is_some());
+        let noop = BinaryRequest::Noop(network::NoopRequest {
+            header: Default::default(),
+        });
+        assert!(router.owner(&noop).is_none());
+        assert!(matches!(
+            router.handle_request(&handler, noop).await,
+            Some(BinaryResponse::Noop(_))
+        ));
+
+        cancellation_token.cancel();
+        owner.join().unwrap();
+    }
+}
@@ -1,44 +1,358 @@
 extern crate core_affinity;
 use crate::memcache;
 use crate::memcache::builder::EngineStoreConfig;
-use crate::memcache::cli::parser::RuntimeType;
+use crate::memcache::cli::parser::{Mode, RuntimeType};
+use crate::memcache::store::loader::{DirectoryLoader, Loader};
 use crate::memcache_server::current_thread_runtime_builder::CurrentThreadRuntimeBuilder;
 use crate::memcache_server::server_context::ServerContext;
 use crate::memcache_server::threadpool_runtime_builder::ThreadpoolRuntimeBuilder;
+use crate::memory_store::cas::CasIds;
+use crate::memory_store::compressed_store::CompressionConfig;
+use crate::memory_store::ext_store::ExtStoreConfig;
+use crate::persistence::aof::{self, MutationLog, MutationLogService};
+use crate::persistence::snapshot::{self, SnapshotService};
+use crate::persistence::write_behind::{
+    self, JsonLinesSink, WriteBehindConfig, WriteBehindService,
+};
+use crate::proxy::shadow::{self, ShadowConfig, ShadowService};
+use crate::proxy::warm::WarmService;
+use crate::proxy::{Proxy, ProxyConfig};
+use crate::replication::primary::ReplicationServer;
+use crate::replication::replica::ReplicaService;
+use crate::replication::Replication;
+use crate::server::timer;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
 
 use crate::memcache::cli::parser::MemcrsdConfig;
 
-fn create_current_thread_server(config: MemcrsdConfig, ctxt: ServerContext) {
+/// Background tasks running next to the server: persistence,
+/// replication, shadowing and warm-up
+#[derive(Default)]
+struct BackgroundServices {
+    snapshots: Option<Arc<SnapshotService>>,
+    mutation_log: Option<Arc<MutationLogService>>,
+    write_behind: Option<Arc<WriteBehindService>>,
+    write_behind_task: Option<tokio::task::JoinHandle<()>>,
+    replication_server: Option<Arc<ReplicationServer>>,
+    replica: Option<Arc<ReplicaService>>,
+    shadow: Option<Arc<ShadowService>>,
+    warm: Option<Arc<WarmService>>,
+}
+
+impl BackgroundServices {
+    fn spawn(&mut self, runtime: &tokio::runtime::Runtime) {
+        if let Some(snapshots) = self.snapshots.clone() {
+            runtime.spawn(snapshots.run());
+        }
+        if let Some(mutation_log) = self.mutation_log.clone() {
+            runtime.spawn(mutation_log.run());
+        }
+        if let Some(write_behind) = self.write_behind.clone() {
+            self.write_behind_task = Some(runtime.spawn(write_behind.run()));
+        }
+        if let Some(replication_server) = self.replication_server.clone() {
+            runtime.spawn(replication_server.run());
+        }
+        if let Some(replica) = self.replica.clone() {
+            runtime.spawn(replica.run());
+        }
+        if let Some(shadow) = self.shadow.clone() {
+            runtime.spawn(shadow.run());
+        }
+        if let Some(warm) = self.warm.clone() {
+            runtime.spawn(warm.run());
+        }
+    }
+
+    /// Called on shutdown, connections are closed by now
+    fn shutdown(&mut self, runtime: &tokio::runtime::Runtime) {
+        // the service writes mutations still queued once it is cancelled
+        if let Some(task) = self.write_behind_task.take() {
+            if let Err(err) = runtime.block_on(task) {
+                error!("Write-behind service failed: {}", err);
+            }
+        }
+        if let Some(mutation_log) = &self.mutation_log {
+            mutation_log.sync();
+        }
+        if let Some(snapshots) = &self.snapshots {
+            snapshots.snapshot();
+        }
+    }
+}
+
+fn create_current_thread_server(
+    config: MemcrsdConfig,
+    ctxt: ServerContext,
+    services: BackgroundServices,
+) {
     let system_timer = ctxt.system_timer();
     let runtime_builder = CurrentThreadRuntimeBuilder::new(config, ctxt.clone());
     let runtime = runtime_builder.build();
-    runtime.block_on(system_timer.run())
+    run_until_cancelled(runtime, system_timer, services)
 }
 
-fn create_threadpool_server(config: MemcrsdConfig, ctxt: ServerContext) {
+fn create_threadpool_server(
+    config: MemcrsdConfig,
+    ctxt: ServerContext,
+    services: BackgroundServices,
+) {
     let system_timer = ctxt.system_timer();
     let runtime_builder = ThreadpoolRuntimeBuilder::new(config, ctxt.clone());
     let runtime = runtime_builder.build();
-    runtime.block_on(system_timer.run())
+    run_until_cancelled(runtime, system_timer, services)
 }
 
-pub fn start_memcrs_server(config: MemcrsdConfig) {
+fn run_until_cancelled(
+    runtime: tokio::runtime::Runtime,
+    system_timer: Arc<timer::SystemTimer>,
+    mut services: BackgroundServices,
+) {
+    services.spawn(&runtime);
+    runtime.block_on(system_timer.run());
+    services.shutdown(&runtime);
+}
+
+fn create_snapshot_service(
+    config: &MemcrsdConfig,
+    ctxt: &ServerContext,
+) -> Option<Arc<SnapshotService>> {
+    let path = config.snapshot_path.clone()?;
+    let interval =
+        (config.snapshot_interval > 0).then(|| Duration::from_secs(config.snapshot_interval));
+    Some(Arc::new(SnapshotService::new(
+        ctxt.store(),
+        ctxt.system_timer(),
+        path,
+        interval,
+        ctxt.cancellation_token(),
+    )))
+}
+
+fn restore(config: &MemcrsdConfig, ctxt: &ServerContext) {
+    let path = match &config.restore_from {
+        Some(path) => path,
+        None => return,
+    };
+    let store = ctxt.store();
+    let system_timer = ctxt.system_timer();
+    match snapshot::restore_snapshot(store.as_ref(), system_timer.as_ref(), path) {
+        Ok(stats) => info!(
+            "Restored {} items from {}, {} expired, {} skipped",
+            stats.restored,
+            path.display(),
+            stats.expired,
+            stats.skipped
+        ),
+        Err(err) => error!("Cannot restore snapshot from {}: {}", path.display(), err),
+    }
+}
+
+/// Replays mutation log and opens it for appending
+fn open_mutation_log(
+    config: &MemcrsdConfig,
+    ctxt: ServerContext,
+) -> (ServerContext, Option<Arc<MutationLogService>>) {
+    let path = match &config.aof_path {
+        Some(path) => path,
+        None => return (ctxt, None),
+    };
+    let store = ctxt.store();
+    let system_timer = ctxt.system_timer();
+    match aof::replay(path, store.as_ref(), system_timer.as_ref()) {
+        Ok(stats) => info!(
+            "Replayed {} mutations from {}",
+            stats.entries,
+            path.display()
+        ),
+        Err(err) => {
+            error!("Cannot replay mutation log {}: {}", path.display(), err);
+            std::process::exit(1);
+        }
+    }
+    let log = match MutationLog::open(
+        path,
+        config.aof_fsync,
+        config.aof_rewrite_min_size,
+        system_timer,
+    ) {
+        Ok(log) => Arc::new(log),
+        Err(err) => {
+            error!("Cannot open mutation log {}: {}", path.display(), err);
+            std::process::exit(1);
+        }
+    };
+    let service = Arc::new(MutationLogService::new(
+        log.clone(),
+        store,
+        ctxt.cancellation_token(),
+    ));
+    (ctxt.with_mutation_log(log), Some(service))
+}
+
+/// Opens the sink mutations are written behind to
+fn open_write_behind(
+    config: &MemcrsdConfig,
+    ctxt: ServerContext,
+) -> (ServerContext, Option<Arc<WriteBehindService>>) {
+    let path = match &config.write_behind_path {
+        Some(path) => path,
+        None => return (ctxt, None),
+    };
+    let sink = match JsonLinesSink::open(path) {
+        Ok(sink) => Arc::new(sink),
+        Err(err) => {
+            error!("Cannot open write-behind file {}: {}", path.display(), err);
+            std::process::exit(1);
+        }
+    };
+    let write_behind_config = WriteBehindConfig {
+        queue_size: config.write_behind_queue_size,
+        batch_size: config.write_behind_batch_size,
+        ..Default::default()
+    };
+    let (queue, service) = write_behind::write_behind(
+        sink,
+        write_behind_config,
+        ctxt.system_timer(),
+        ctxt.cancellation_token(),
+    );
+    (ctxt.with_write_behind(queue), Some(service))
+}
+
+/// Mirrors client requests to the shadow server
+fn open_shadow(
+    config: &MemcrsdConfig,
+    ctxt: ServerContext,
+) -> (ServerContext, Option<Arc<ShadowService>>) {
+    let address = match &config.shadow_to {
+        Some(address) => address.clone(),
+        None => return (ctxt, None),
+    };
+    let (queue, service) = shadow::shadow(
+        ShadowConfig {
+            address,
+            sample_rate: config.shadow_sample_rate,
+            reads: config.shadow_reads,
+            queue_size: config.shadow_queue_size,
+        },
+        ctxt.cancellation_token(),
+    );
+    (ctxt.with_shadow(queue), Some(service))
+}
+
+/// Makes the server a primary accepting replicas or a replica of one
+fn open_replication(
+    config: &MemcrsdConfig,
+    ctxt: ServerContext,
+) -> (ServerContext, BackgroundServices) {
+    let mut services = BackgroundServices::default();
+    if let Some(port) = config.replication_port {
+        let replication = Arc::new(Replication::primary(ctxt.system_timer()));
+        services.replication_server = Some(Arc::new(ReplicationServer::new(
+            replication.clone(),
+            ctxt.store(),
+            ctxt.system_timer(),
+            SocketAddr::new(config.listen_address, port),
+            ctxt.cancellation_token(),
+        )));
+        return (ctxt.with_replication(replication), services);
+    }
+    if let Some(primary) = &config.replica_of {
+        let replication = Arc::new(Replication::replica(ctxt.system_timer(), primary.clone()));
+        services.replica = Some(Arc::new(ReplicaService::new(
+            replication.clone(),
+            ctxt.store(),
+            ctxt.system_timer(),
+            primary.clone(),
+            ctxt.cancellation_token(),
+        )));
+        return (ctxt.with_replication(replication), services);
+    }
+    (ctxt, services)
+}
+
+/// Creates server context for engine selected in config
+pub fn create_server_context(config: &MemcrsdConfig) -> ServerContext {
+    CasIds::global().set_node_id(config.cas_node_id);
     let engine_store_config = match config.store_engine {
         crate::memory_store::StoreEngine::DashMap => {
             EngineStoreConfig::DashMap(config.dash_map.unwrap())
         }
         crate::memory_store::StoreEngine::Moka => EngineStoreConfig::Moka(config.moka.unwrap()),
+        crate::memory_store::StoreEngine::Slab => EngineStoreConfig::Slab(config.slab.unwrap()),
+        crate::memory_store::StoreEngine::Sieve => EngineStoreConfig::Sieve(config.sieve.unwrap()),
     };
 
+    let ext_store_config = config.ext_path.clone().map(|path| ExtStoreConfig {
+        path,
+        item_size: config.ext_item_size,
+        size: config.ext_size,
+        page_size: config.ext_page_size,
+    });
     let store_config =
-        memcache::builder::MemcacheStoreConfig::new(config.store_engine, engine_store_config);
-    let ctxt = ServerContext::get_default_server_context(store_config);
+        memcache::builder::MemcacheStoreConfig::new(config.store_engine, engine_store_config)
+            .with_ext_store(ext_store_config)
+            .with_compression(config.compression.map(|algorithm| CompressionConfig {
+                algorithm,
+                min_size: config.compression_min_size,
+            }))
+            .with_namespaces(config.namespaces.clone());
+    let ctxt = if config.partition_keyspace {
+        ServerContext::get_partitioned_server_context(store_config, config.threads)
+    } else {
+        ServerContext::get_default_server_context(store_config)
+    };
+    ctxt.with_hot_keys(config.hot_keys_sample_rate)
+        .with_ttl_rules(config.ttl_rules.clone())
+        .with_loader(
+            config
+                .loader_dir
+                .as_ref()
+                .map(|dir| Arc::new(DirectoryLoader::new(dir)) as Arc<dyn Loader>),
+            config.loader_ttl,
+        )
+        .with_proxy((config.mode == Mode::Proxy).then(|| {
+            Arc::new(Proxy::new(ProxyConfig {
+                backends: config.backends.clone(),
+                pool_size: config.backend_pool_size,
+                timeout: Duration::from_millis(config.backend_timeout_ms),
+                retry_delay: Duration::from_millis(config.backend_retry_delay_ms),
+            }))
+        }))
+}
+
+pub fn start_memcrs_server(config: MemcrsdConfig) {
+    let ctxt = create_server_context(&config);
     start_memcrs_server_with_ctxt(config, ctxt)
 }
 
 pub fn start_memcrs_server_with_ctxt(config: MemcrsdConfig, ctxt: ServerContext) {
+    // restored before listeners are bound, clients never see a partial cache,
+    // mutation log is newer than a snapshot so it is replayed on top of it
+    restore(&config, &ctxt);
+    let (ctxt, mutation_log) = open_mutation_log(&config, ctxt);
+    let (ctxt, write_behind) = open_write_behind(&config, ctxt);
+    let (ctxt, shadow) = open_shadow(&config, ctxt);
+    let (ctxt, replication) = open_replication(&config, ctxt);
+    let services = BackgroundServices {
+        snapshots: create_snapshot_service(&config, &ctxt),
+        mutation_log,
+        write_behind,
+        shadow,
+        warm: config.warm_from.clone().map(|peer| {
+            Arc::new(WarmService::new(
+                peer,
+                ctxt.store(),
+                ctxt.cancellation_token(),
+            ))
+        }),
+        ..replication
+    };
     match config.runtime_type {
-        RuntimeType::CurrentThread => create_current_thread_server(config, ctxt),
-        RuntimeType::MultiThread => create_threadpool_server(config, ctxt),
+        RuntimeType::CurrentThread => create_current_thread_server(config, ctxt, services),
+        RuntimeType::MultiThread => create_threadpool_server(config, ctxt, services),
     }
 }
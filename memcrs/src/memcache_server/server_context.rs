@@ -3,7 +3,20 @@ use tokio_util::sync::CancellationToken;
 
 use crate::{
     cache::{cache::Cache, pending_tasks_runner},
-    memcache,
+    memcache::{
+        self,
+        hot_keys::HotKeys,
+        store::{
+            loader::{Loader, ReadThrough},
+            tags::TagIndex,
+            ttl_rules::{TtlRule, TtlRules},
+            MemcStore,
+        },
+    },
+    memory_store::partitioned_store::PartitionedMemoryStore,
+    persistence::{aof::MutationLog, write_behind::WriteBehind},
+    proxy::{shadow::Shadow, Proxy},
+    replication::Replication,
     server::timer,
 };
 
@@ -13,6 +26,16 @@ pub struct ServerContext {
     system_timer: Arc<timer::SystemTimer>,
     store: Arc<dyn Cache + Send + Sync>,
     pending_tasks_runner: Arc<pending_tasks_runner::PendingTasksRunner>,
+    partitioned_store: Option<Arc<PartitionedMemoryStore>>,
+    mutation_log: Option<Arc<MutationLog>>,
+    tags: Arc<TagIndex>,
+    hot_keys: Option<Arc<HotKeys>>,
+    ttl_rules: Option<Arc<TtlRules>>,
+    loader: Option<Arc<ReadThrough>>,
+    write_behind: Option<Arc<WriteBehind>>,
+    replication: Option<Arc<Replication>>,
+    proxy: Option<Arc<Proxy>>,
+    shadow: Option<Arc<Shadow>>,
 }
 
 impl ServerContext {
@@ -25,18 +48,112 @@ impl ServerContext {
             store_config,
             system_timer.clone(),
         );
+        Self::new(cancellation_token, system_timer, store, None)
+    }
+
+    /// Context for partitioned keyspace mode, store is split into `partitions`
+    /// partitions, each owned by one worker thread.
+    pub fn get_partitioned_server_context(
+        store_config: memcache::builder::MemcacheStoreConfig,
+        partitions: usize,
+    ) -> Self {
+        let cancellation_token = CancellationToken::new();
+        let system_timer = Arc::new(timer::SystemTimer::new(cancellation_token.clone()));
+        let (store, partitioned_store) =
+            memcache::builder::MemcacheStoreBuilder::partitioned_from_config(
+                store_config,
+                system_timer.clone(),
+                partitions,
+            );
+        Self::new(
+            cancellation_token,
+            system_timer,
+            store,
+            Some(partitioned_store),
+        )
+    }
+
+    fn new(
+        cancellation_token: CancellationToken,
+        system_timer: Arc<timer::SystemTimer>,
+        store: Arc<dyn Cache + Send + Sync>,
+        partitioned_store: Option<Arc<PartitionedMemoryStore>>,
+    ) -> Self {
         let pending_tasks_runner = Arc::new(pending_tasks_runner::PendingTasksRunner::new(
             store.clone(),
             cancellation_token.clone(),
         ));
+        let tags = Arc::new(TagIndex::new());
         Self {
             cancellation_token,
             system_timer,
             store,
             pending_tasks_runner,
+            partitioned_store,
+            mutation_log: None,
+            tags,
+            hot_keys: None,
+            ttl_rules: None,
+            loader: None,
+            write_behind: None,
+            replication: None,
+            proxy: None,
+            shadow: None,
         }
     }
 
+    /// Records mutations done through stores created with `memc_store`
+    pub fn with_mutation_log(mut self, mutation_log: Arc<MutationLog>) -> Self {
+        self.mutation_log = Some(mutation_log);
+        self
+    }
+
+    /// Tracks hot keys in one of `sample_rate` requests, 0 disables it
+    pub fn with_hot_keys(mut self, sample_rate: u32) -> Self {
+        self.hot_keys = (sample_rate > 0).then(|| Arc::new(HotKeys::new(sample_rate)));
+        self
+    }
+
+    /// Limits TTL and size of items written by clients
+    pub fn with_ttl_rules(mut self, rules: Vec<TtlRule>) -> Self {
+        self.ttl_rules =
+            (!rules.is_empty()).then(|| Arc::new(TtlRules::new(rules, self.system_timer.clone())));
+        self
+    }
+
+    /// Loads items missing in the cache on reads
+    pub fn with_loader(mut self, loader: Option<Arc<dyn Loader>>, time_to_live: u32) -> Self {
+        self.loader = loader.map(|loader| Arc::new(ReadThrough::new(loader, time_to_live)));
+        self
+    }
+
+    /// Queues mutations done through stores created with `memc_store`
+    /// for a write-behind sink
+    pub fn with_write_behind(mut self, write_behind: Arc<WriteBehind>) -> Self {
+        self.write_behind = Some(write_behind);
+        self
+    }
+
+    /// Publishes mutations to replicas or makes stores read only on a
+    /// replica
+    pub fn with_replication(mut self, replication: Arc<Replication>) -> Self {
+        self.replication = Some(replication);
+        self
+    }
+
+    /// Forwards client requests to backends instead of serving them
+    pub fn with_proxy(mut self, proxy: Option<Arc<Proxy>>) -> Self {
+        self.proxy = proxy;
+        self
+    }
+
+    /// Mirrors requests handled by stores created with `memc_store`
+    /// to a shadow server
+    pub fn with_shadow(mut self, shadow: Arc<Shadow>) -> Self {
+        self.shadow = Some(shadow);
+        self
+    }
+
     pub fn cancellation_token(&self) -> CancellationToken {
         self.cancellation_token.clone()
     }
@@ -52,4 +169,36 @@ impl ServerContext {
     pub fn pending_tasks_runner(&self) -> Arc<pending_tasks_runner::PendingTasksRunner> {
         self.pending_tasks_runner.clone()
     }
+
+    /// Partitioned store, only set in partitioned keyspace mode
+    pub fn partitioned_store(&self) -> Option<Arc<PartitionedMemoryStore>> {
+        self.partitioned_store.clone()
+    }
+
+    pub fn mutation_log(&self) -> Option<Arc<MutationLog>> {
+        self.mutation_log.clone()
+    }
+
+    pub fn replication(&self) -> Option<Arc<Replication>> {
+        self.replication.clone()
+    }
+
+    /// Proxy, only set in proxy mode
+    pub fn proxy(&self) -> Option<Arc<Proxy>> {
+        self.proxy.clone()
+    }
+
+    /// Store serving client requests, item tags are shared by all of them
+    pub fn memc_store(&self) -> Arc<MemcStore> {
+        Arc::new(
+            MemcStore::with_mutation_log(self.store.clone(), self.mutation_log.clone())
+                .with_tag_index(self.tags.clone())
+                .with_hot_keys(self.hot_keys.clone())
+                .with_ttl_rules(self.ttl_rules.clone())
+                .with_loader(self.loader.clone())
+                .with_write_behind(self.write_behind.clone())
+                .with_replication(self.replication.clone())
+                .with_shadow(self.shadow.clone()),
+        )
+    }
 }
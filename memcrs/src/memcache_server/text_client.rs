@@ -0,0 +1,228 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::sync::Semaphore;
+use tokio::time::timeout;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error};
+
+use super::client_handler::ClientConfig;
+use super::handler;
+use super::partition_router::PartitionRouter;
+use crate::memcache::store as storage;
+use crate::protocol::binary::decoder::BinaryRequest;
+use crate::protocol::binary::encoder::BinaryResponse;
+use crate::protocol::binary::network;
+use crate::protocol::text::connection::MemcacheTextConnection;
+use crate::protocol::text::decoder::{header, TextCommand};
+use crate::protocol::text::encoder::{put_line, put_reply, put_values, ReplyKind};
+use crate::version::MEMCRS_VERSION;
+use bytes::{Bytes, BytesMut};
+
+/// Items read for one page of a metadump, every page is sent once read
+const METADUMP_PAGE_COUNT: u32 = 1000;
+
+/// Text protocol client, its commands are handled as the binary requests
+/// they translate to
+pub struct TextClient {
+    stream: MemcacheTextConnection,
+    addr: SocketAddr,
+    config: ClientConfig,
+    handler: handler::BinaryHandler,
+    /// See client_handler::Client
+    limit_connections: Arc<Semaphore>,
+    cancellation_token: CancellationToken,
+    router: Option<PartitionRouter>,
+}
+
+impl TextClient {
+    pub fn new(
+        store: Arc<storage::MemcStore>,
+        socket: TcpStream,
+        addr: SocketAddr,
+        config: ClientConfig,
+        limit_connections: Arc<Semaphore>,
+        cancellation_token: CancellationToken,
+    ) -> Self {
+        TextClient {
+            stream: MemcacheTextConnection::new(socket, config.item_memory_limit),
+            addr,
+            config,
+            handler: handler::BinaryHandler::new(store),
+            limit_connections,
+            cancellation_token,
+            router: None,
+        }
+    }
+
+    pub fn with_router(mut self, router: Option<PartitionRouter>) -> Self {
+        self.router = router;
+        self
+    }
+
+    pub async fn handle(&mut self) {
+        debug!("New text client connected: {}", self.addr);
+        loop {
+            tokio::select! {
+                _ = self.cancellation_token.cancelled() => {
+                    info!("Cancelling text client loop for {}", self.addr);
+                    let _ = self.stream.shutdown().await;
+                    return;
+                }
+                command_or_none = timeout(
+                    Duration::from_secs(self.config.rx_timeout_secs as u64),
+                    self.stream.read_frame(),
+                ) => {
+                    let client_close = match command_or_none {
+                        Ok(Ok(Some(command))) => self.handle_command(command).await,
+                        Ok(Ok(None)) => {
+                            debug!("Connection closed: {}", self.addr);
+                            true
+                        }
+                        Ok(Err(err)) => {
+                            error!("Error when reading command; error = {:?}", err);
+                            true
+                        }
+                        Err(err) => {
+                            debug!(
+                                "Timeout {}s elapsed, disconecting client: {}, error: {}",
+                                self.config.rx_timeout_secs, self.addr, err
+                            );
+                            true
+                        }
+                    };
+                    if client_close {
+                        return;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns true if we should leave client receive loop
+    async fn handle_command(&mut self, command: TextCommand) -> bool {
+        let mut reply = BytesMut::new();
+        match command {
+            TextCommand::Get { keys, cas } => self.get(keys, cas, &mut reply).await,
+            TextCommand::Request { request, noreply } => {
+                let kind = ReplyKind::of(&request);
+                let mutation = request.is_mutation();
+                let response = self.request(request).await;
+                // a mutation which cannot be made durable is not acknowledged
+                if mutation && self.handler.durable().await.is_err() {
+                    error!("Mutation log is not durable, closing client socket");
+                    let _ = self.stream.shutdown().await;
+                    return true;
+                }
+                if let (Some(response), false) = (response, noreply) {
+                    put_reply(&mut reply, kind, &response);
+                }
+            }
+            TextCommand::Version => put_line(&mut reply, &format!("VERSION {}", MEMCRS_VERSION)),
+            TextCommand::Stats => {
+                let request = BinaryRequest::Stats(network::StatsRequest {
+                    header: header(network::Command::Stat, 0),
+                    key: Bytes::new(),
+                });
+                if let Some(BinaryResponse::Stats(response)) = self.request(request).await {
+                    for (name, value) in response.records {
+                        put_line(&mut reply, &format!("STAT {} {}", name, value));
+                    }
+                }
+                put_line(&mut reply, "END");
+            }
+            TextCommand::Metadump { prefix } => return self.metadump(prefix).await,
+            TextCommand::Quit => {
+                let _ = self.stream.shutdown().await;
+                return true;
+            }
+            TextCommand::TooLarge { noreply } => {
+                if !noreply {
+                    put_line(&mut reply, "SERVER_ERROR object too large for cache");
+                }
+            }
+            TextCommand::ClientError(message) => put_line(&mut reply, message),
+            TextCommand::Unknown => put_line(&mut reply, "ERROR"),
+        }
+        self.send(&reply).await
+    }
+
+    async fn request(&self, request: BinaryRequest) -> Option<BinaryResponse> {
+        match &self.router {
+            Some(router) => router.handle_request(&self.handler, request).await,
+            None => self.handler.handle_request(request).await,
+        }
+    }
+
+    /// Keys are looked up together like a quiet get run
+    async fn get(&self, keys: Vec<Bytes>, cas: bool, reply: &mut BytesMut) {
+        let requests = keys
+            .into_iter()
+            .map(|key| network::GetKeyQuietRequest {
+                header: header(network::Command::GetKeyQuiet, 0),
+                key,
+            })
+            .collect();
+        let responses = match &self.router {
+            Some(router) => router.get_quietly_multi(&self.handler, requests).await,
+            None => self.handler.get_quietly_multi(requests).await,
+        };
+        put_values(reply, responses, cas);
+    }
+
+    /// Sends one lru_crawler metadump line for every item, page by page
+    /// as the store is scanned
+    /// Returns true if we should leave client receive loop
+    async fn metadump(&mut self, prefix: Bytes) -> bool {
+        let mut cursor = 0;
+        loop {
+            let request = BinaryRequest::Scan(network::ScanRequest {
+                header: header(network::Command::Scan, 0),
+                cursor,
+                count: METADUMP_PAGE_COUNT,
+                prefix: prefix.clone(),
+            });
+            let page = match self.request(request).await {
+                Some(BinaryResponse::Scan(page)) => page,
+                response => {
+                    let message = match response {
+                        Some(BinaryResponse::Error(response)) => response.error,
+                        _ => "metadump failed",
+                    };
+                    let mut reply = BytesMut::new();
+                    put_line(&mut reply, &format!("SERVER_ERROR {}", message));
+                    return self.send(&reply).await;
+                }
+            };
+            if self.send(&page.dump).await {
+                return true;
+            }
+            if page.cursor == 0 {
+                break;
+            }
+            cursor = page.cursor;
+        }
+        let mut reply = BytesMut::new();
+        put_line(&mut reply, "END");
+        self.send(&reply).await
+    }
+
+    /// Returns true if we should leave client receive loop
+    async fn send(&mut self, data: &[u8]) -> bool {
+        if data.is_empty() {
+            return false;
+        }
+        if let Err(e) = self.stream.write(data).await {
+            error!("error on sending response; error = {:?}", e);
+            return true;
+        }
+        false
+    }
+}
+
+impl Drop for TextClient {
+    fn drop(&mut self) {
+        self.limit_connections.add_permits(1);
+    }
+}
@@ -1,7 +1,6 @@
 use crate::{memcache::cli::parser::MemcrsdConfig, memcache_server::server_context::ServerContext};
 extern crate core_affinity;
 use crate::memcache_server::{self, register_cancellation, server_thread};
-use std::sync::Arc;
 use tokio::runtime::Builder;
 
 pub struct ThreadpoolRuntimeBuilder {
@@ -16,7 +15,6 @@ impl ThreadpoolRuntimeBuilder {
 
     pub fn build(&self) -> tokio::runtime::Runtime {
         let cancellation_token = self.ctxt.cancellation_token();
-        let store = self.ctxt.store();
         let task_runner = self.ctxt.pending_tasks_runner();
 
         let memc_config = memcache_server::memc_tcp::MemcacheServerConfig::new(
@@ -26,7 +24,7 @@ impl ThreadpoolRuntimeBuilder {
         );
 
         let listener_factory =
-            memcache_server::listener_factory::create_listener_from_config(self.config);
+            memcache_server::listener_factory::create_listener_from_config(&self.config);
         let listener = listener_factory.get_tcp_listener().unwrap_or_else(|e| {
             log::error!("Failed to create TCP listener: {}", e);
             std::process::exit(1);
@@ -35,9 +33,10 @@ impl ThreadpoolRuntimeBuilder {
         let mut runtime = create_multi_thread_runtime(self.config.threads);
         let mut tcp_server = memcache_server::memc_tcp::MemcacheTcpServer::new(
             memc_config,
-            Arc::clone(&store),
+            self.ctxt.memc_store(),
             cancellation_token.clone(),
-        );
+        )
+        .with_proxy(self.ctxt.proxy());
 
         runtime.spawn(async move { task_runner.run().await });
         runtime.spawn(async move { tcp_server.run(listener).await });
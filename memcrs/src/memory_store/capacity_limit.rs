@@ -0,0 +1,152 @@
+use crate::cache::error::{CacheError, Result};
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+
+/// Tracks usage of a bounded resource (bytes or entries, depending on the
+/// engine) for stores that reject writes instead of evicting when full.
+///
+/// An unbounded limit does no accounting at all, so engines which rely on
+/// their own eviction pay nothing for it.
+pub struct CapacityLimit {
+    limit: Option<u64>,
+    used: AtomicU64,
+    // server time of the last sweep for expired items
+    last_reclaim: AtomicU32,
+}
+
+impl CapacityLimit {
+    pub fn bounded(limit: u64) -> CapacityLimit {
+        CapacityLimit {
+            limit: Some(limit),
+            used: AtomicU64::new(0),
+            last_reclaim: AtomicU32::new(u32::MAX),
+        }
+    }
+
+    pub fn unbounded() -> CapacityLimit {
+        CapacityLimit {
+            limit: None,
+            used: AtomicU64::new(0),
+            last_reclaim: AtomicU32::new(u32::MAX),
+        }
+    }
+
+    pub fn is_bounded(&self) -> bool {
+        self.limit.is_some()
+    }
+
+    /// Reserves `amount` units, fails with OutOfMemory if the limit
+    /// would be exceeded.
+    pub fn reserve(&self, amount: u64) -> Result<()> {
+        let limit = match self.limit {
+            Some(limit) => limit,
+            None => return Ok(()),
+        };
+        self.used
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |used| {
+                used.checked_add(amount).filter(|total| *total <= limit)
+            })
+            .map(|_| ())
+            .map_err(|_| CacheError::OutOfMemory)
+    }
+
+    /// Whether expired items should be dropped before reserving `amount`
+    /// units: the limit would be exceeded and nobody swept the store at
+    /// server time `now` yet. A store full of live items is swept at most
+    /// once a second, not on every rejected write.
+    pub fn should_reclaim(&self, amount: u64, now: u32) -> bool {
+        let limit = match self.limit {
+            Some(limit) => limit,
+            None => return false,
+        };
+        if self.used().saturating_add(amount) <= limit {
+            return false;
+        }
+        self.last_reclaim.swap(now, Ordering::AcqRel) != now
+    }
+
+    /// Returns `amount` units back to the pool.
+    pub fn release(&self, amount: u64) {
+        if self.limit.is_none() {
+            return;
+        }
+        let _ = self
+            .used
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |used| {
+                Some(used.saturating_sub(amount))
+            });
+    }
+
+    /// Accounts for a stored item changing its size from `old` to `new`.
+    pub fn resize(&self, old: u64, new: u64) -> Result<()> {
+        if new > old {
+            self.reserve(new - old)
+        } else {
+            self.release(old - new);
+            Ok(())
+        }
+    }
+
+    pub fn reset(&self) {
+        self.used.store(0, Ordering::Release);
+    }
+
+    pub fn used(&self) -> u64 {
+        self.used.load(Ordering::Acquire)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unbounded_never_fails() {
+        let limit = CapacityLimit::unbounded();
+        assert!(!limit.is_bounded());
+        assert!(limit.reserve(u64::MAX).is_ok());
+        assert!(limit.reserve(u64::MAX).is_ok());
+        assert_eq!(limit.used(), 0);
+    }
+
+    #[test]
+    fn test_bounded_rejects_over_limit() {
+        let limit = CapacityLimit::bounded(10);
+        assert!(limit.reserve(6).is_ok());
+        assert_eq!(limit.reserve(5), Err(CacheError::OutOfMemory));
+        assert!(limit.reserve(4).is_ok());
+        assert_eq!(limit.used(), 10);
+        limit.release(3);
+        assert_eq!(limit.used(), 7);
+    }
+
+    #[test]
+    fn test_resize() {
+        let limit = CapacityLimit::bounded(10);
+        assert!(limit.reserve(5).is_ok());
+        assert!(limit.resize(5, 10).is_ok());
+        assert_eq!(limit.resize(10, 11), Err(CacheError::OutOfMemory));
+        assert!(limit.resize(10, 2).is_ok());
+        assert_eq!(limit.used(), 2);
+    }
+
+    #[test]
+    fn test_should_reclaim_once_per_second() {
+        let limit = CapacityLimit::bounded(10);
+        assert!(limit.reserve(8).is_ok());
+        assert!(!limit.should_reclaim(2, 1));
+        assert!(limit.should_reclaim(3, 1));
+        assert!(!limit.should_reclaim(3, 1));
+        assert!(limit.should_reclaim(3, 2));
+        assert!(!CapacityLimit::unbounded().should_reclaim(u64::MAX, 1));
+    }
+
+    #[test]
+    fn test_release_does_not_underflow() {
+        let limit = CapacityLimit::bounded(10);
+        assert!(limit.reserve(1).is_ok());
+        limit.release(5);
+        assert_eq!(limit.used(), 0);
+        limit.reset();
+        assert_eq!(limit.used(), 0);
+    }
+}
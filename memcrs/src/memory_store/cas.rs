@@ -0,0 +1,129 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Low bits of CAS are a counter, high bits are the node id
+const COUNTER_BITS: u32 = 48;
+const COUNTER_MASK: u64 = (1 << COUNTER_BITS) - 1;
+
+/// Source of CAS values.
+///
+/// Every mutation takes a new value from the counter, so two items never
+/// share a CAS, no matter which store or partition they live in. Nodes with
+/// different ids never hand out the same CAS, which keeps CAS unique across
+/// replicas.
+pub struct CasIds {
+    node: AtomicU64,
+    counter: AtomicU64,
+}
+
+/// CAS values of all stores in the process, like memcached's global counter
+static CAS_IDS: CasIds = CasIds::new();
+
+impl Default for CasIds {
+    fn default() -> Self {
+        CasIds::new()
+    }
+}
+
+impl CasIds {
+    pub const fn new() -> CasIds {
+        CasIds {
+            node: AtomicU64::new(0),
+            counter: AtomicU64::new(1),
+        }
+    }
+
+    pub fn global() -> &'static CasIds {
+        &CAS_IDS
+    }
+
+    /// Sets prefix of CAS values handed out from now on
+    pub fn set_node_id(&self, node_id: u16) {
+        self.node
+            .store((node_id as u64) << COUNTER_BITS, Ordering::Release);
+    }
+
+    pub fn node_id(&self) -> u16 {
+        (self.node.load(Ordering::Acquire) >> COUNTER_BITS) as u16
+    }
+
+    pub fn next(&self) -> u64 {
+        let counter = loop {
+            let counter = self.counter.fetch_add(1, Ordering::AcqRel) & COUNTER_MASK;
+            // 0 is not a valid CAS, skipped when counter wraps around
+            if counter != 0 {
+                break counter;
+            }
+        };
+        self.node.load(Ordering::Acquire) | counter
+    }
+
+    /// Makes sure CAS values handed out later are greater than `cas`,
+    /// used when records with existing CAS are restored.
+    pub fn advance(&self, cas: u64) {
+        self.counter
+            .fetch_max((cas & COUNTER_MASK) + 1, Ordering::AcqRel);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn test_next_is_unique_across_threads() {
+        let ids = Arc::new(CasIds::new());
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let ids = ids.clone();
+                thread::spawn(move || (0..10_000).map(|_| ids.next()).collect::<Vec<u64>>())
+            })
+            .collect();
+        let mut seen = HashSet::new();
+        for handle in handles {
+            let values = handle.join().unwrap();
+            // every thread sees increasing values
+            assert!(values.windows(2).all(|pair| pair[0] < pair[1]));
+            for value in values {
+                assert!(seen.insert(value), "duplicate CAS {}", value);
+            }
+        }
+        assert_eq!(seen.len(), 80_000);
+        assert!(!seen.contains(&0));
+    }
+
+    #[test]
+    fn test_node_id_is_prefix() {
+        let first = CasIds::new();
+        first.set_node_id(1);
+        let second = CasIds::new();
+        second.set_node_id(2);
+        assert_eq!(first.node_id(), 1);
+
+        let cas = first.next();
+        assert_eq!(cas >> COUNTER_BITS, 1);
+        assert_eq!(cas & COUNTER_MASK, 1);
+        assert_ne!(cas, second.next());
+    }
+
+    #[test]
+    fn test_advance() {
+        let ids = CasIds::new();
+        ids.set_node_id(3);
+        ids.advance((7 << COUNTER_BITS) | 41);
+        assert_eq!(ids.next(), (3 << COUNTER_BITS) | 42);
+        // never goes back
+        ids.advance(5);
+        assert_eq!(ids.next(), (3 << COUNTER_BITS) | 43);
+    }
+
+    #[test]
+    fn test_wrap_around_skips_zero() {
+        let ids = CasIds::new();
+        ids.advance(COUNTER_MASK - 1);
+        assert_eq!(ids.next(), COUNTER_MASK);
+        assert_eq!(ids.next(), 1);
+    }
+}
@@ -0,0 +1,334 @@
+use crate::cache::cache::{
+    Cache, CacheMetaData, CacheStats, CompressionStats, DeltaParam, DeltaResult, KeyType, Record,
+    ScanPage, SetStatus,
+};
+use crate::cache::error::{CacheError, Result};
+use crate::cache::events::SharedListener;
+
+use bytes::{Bytes, BytesMut};
+use clap::ValueEnum;
+use futures::future::BoxFuture;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+const MARKER_LEN: usize = 16;
+/// marker | algorithm u8 | original len u32 | compressed value
+const HEADER_LEN: usize = MARKER_LEN + 5;
+const ZSTD_LEVEL: i32 = 3;
+/// smallest value worth compressing
+pub const MIN_COMPRESSION_SIZE: u64 = 64;
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug)]
+pub enum CompressionAlgorithm {
+    /// better ratio, slower
+    Zstd,
+    /// faster, lower ratio
+    Lz4,
+}
+
+impl CompressionAlgorithm {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CompressionAlgorithm::Zstd => "zstd",
+            CompressionAlgorithm::Lz4 => "lz4",
+        }
+    }
+
+    fn id(&self) -> u8 {
+        match self {
+            CompressionAlgorithm::Zstd => 1,
+            CompressionAlgorithm::Lz4 => 2,
+        }
+    }
+
+    fn compress(&self, value: &[u8]) -> Option<Vec<u8>> {
+        match self {
+            CompressionAlgorithm::Zstd => zstd::bulk::compress(value, ZSTD_LEVEL).ok(),
+            CompressionAlgorithm::Lz4 => Some(lz4_flex::block::compress(value)),
+        }
+    }
+}
+
+fn decompress(id: u8, value: &[u8], len: usize) -> Option<Vec<u8>> {
+    match id {
+        1 => zstd::bulk::decompress(value, len).ok(),
+        2 => lz4_flex::block::decompress(value, len).ok(),
+        _ => None,
+    }
+    .filter(|value| value.len() == len)
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct CompressionConfig {
+    pub algorithm: CompressionAlgorithm,
+    /// values of at least this size are compressed
+    pub min_size: u64,
+}
+
+#[derive(Default)]
+struct CompressionCounters {
+    values: AtomicU64,
+    compressed_values: AtomicU64,
+    bytes_in: AtomicU64,
+    bytes_out: AtomicU64,
+}
+
+impl CompressionCounters {
+    fn record(&self, bytes_in: usize, bytes_out: usize) {
+        self.values.fetch_add(1, Ordering::Relaxed);
+        if bytes_out < bytes_in {
+            self.compressed_values.fetch_add(1, Ordering::Relaxed);
+        }
+        self.bytes_in.fetch_add(bytes_in as u64, Ordering::Relaxed);
+        self.bytes_out
+            .fetch_add(bytes_out as u64, Ordering::Relaxed);
+    }
+}
+
+/// Wrapper compressing large values before they reach the wrapped store.
+///
+/// The wrapped store only sees compressed values, so its memory limits
+/// count compressed sizes. Values which do not get smaller are stored as
+/// they are. Compressed values start with a marker chosen at random on
+/// startup, so no client value is mistaken for a compressed one.
+pub struct CompressedMemoryStore {
+    store: Arc<dyn Cache + Send + Sync>,
+    algorithm: CompressionAlgorithm,
+    min_size: usize,
+    marker: [u8; MARKER_LEN],
+    counters: CompressionCounters,
+}
+
+impl CompressedMemoryStore {
+    pub fn new(
+        store: Arc<dyn Cache + Send + Sync>,
+        config: CompressionConfig,
+    ) -> CompressedMemoryStore {
+        let mut marker: [u8; MARKER_LEN] = rand::random();
+        // never a digit, incr and decr fail on compressed values
+        marker[0] = 0xFF;
+        CompressedMemoryStore {
+            store,
+            algorithm: config.algorithm,
+            min_size: config.min_size.max(MIN_COMPRESSION_SIZE) as usize,
+            marker,
+            counters: CompressionCounters::default(),
+        }
+    }
+
+    fn compress(&self, record: Record) -> Record {
+        if record.value.len() < self.min_size {
+            return record;
+        }
+        let len = record.value.len();
+        let compressed = self
+            .algorithm
+            .compress(&record.value)
+            .filter(|compressed| HEADER_LEN + compressed.len() < len);
+        let value = match compressed {
+            Some(compressed) => {
+                let mut value = BytesMut::with_capacity(HEADER_LEN + compressed.len());
+                value.extend_from_slice(&self.marker);
+                value.extend_from_slice(&[self.algorithm.id()]);
+                value.extend_from_slice(&(len as u32).to_be_bytes());
+                value.extend_from_slice(&compressed);
+                value.freeze()
+            }
+            None => record.value,
+        };
+        self.counters.record(len, value.len());
+        Record {
+            header: record.header,
+            value,
+        }
+    }
+
+    fn decompress(&self, record: Record) -> Result<Record> {
+        let value = &record.value;
+        if value.len() < HEADER_LEN || value[..MARKER_LEN] != self.marker {
+            return Ok(record);
+        }
+        let len = u32::from_be_bytes(value[MARKER_LEN + 1..HEADER_LEN].try_into().unwrap());
+        match decompress(value[MARKER_LEN], &value[HEADER_LEN..], len as usize) {
+            Some(value) => Ok(Record {
+                header: record.header,
+                value: Bytes::from(value),
+            }),
+            None => {
+                error!("Cannot decompress stored value");
+                Err(CacheError::InternalError)
+            }
+        }
+    }
+
+    fn append_prepend_common(
+        &self,
+        key: KeyType,
+        new_record: Record,
+        is_append: bool,
+    ) -> Result<SetStatus> {
+        loop {
+            let prev_record = match self.store.get(&key) {
+                Ok(record) => record,
+                Err(CacheError::NotFound) => return Err(CacheError::ItemNotStored),
+                Err(err) => return Err(err),
+            };
+            let cas = new_record.header.cas;
+            if cas != 0 && prev_record.header.cas != cas {
+                return Err(CacheError::KeyExists);
+            }
+            let prev_cas = prev_record.header.cas;
+            let prev_value = self.decompress(prev_record)?.value;
+            let mut value = BytesMut::with_capacity(prev_value.len() + new_record.value.len());
+            if is_append {
+                value.extend_from_slice(&prev_value);
+                value.extend_from_slice(&new_record.value);
+            } else {
+                value.extend_from_slice(&new_record.value);
+                value.extend_from_slice(&prev_value);
+            }
+            // replace with CAS of the read record, fails if it changed since
+            let header = CacheMetaData::new(
+                prev_cas,
+                new_record.header.flags,
+                new_record.header.time_to_live,
+            );
+            let record = self.compress(Record {
+                header,
+                value: value.freeze(),
+            });
+            match self.store.replace(key.clone(), record) {
+                Ok(status) => return Ok(status),
+                Err(CacheError::KeyExists) => continue,
+                Err(CacheError::NotFound) => return Err(CacheError::ItemNotStored),
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+impl Cache for CompressedMemoryStore {
+    fn get(&self, key: &KeyType) -> Result<Record> {
+        self.decompress(self.store.get(key)?)
+    }
+
+    fn get_async<'a>(&'a self, key: &'a KeyType) -> BoxFuture<'a, Result<Record>> {
+        Box::pin(async move { self.decompress(self.store.get_async(key).await?) })
+    }
+
+    fn reads_outside_memory(&self) -> bool {
+        self.store.reads_outside_memory()
+    }
+
+    fn set(&self, key: KeyType, record: Record) -> Result<SetStatus> {
+        self.store.set(key, self.compress(record))
+    }
+
+    fn delete(&self, key: KeyType, header: CacheMetaData) -> Result<Record> {
+        self.decompress(self.store.delete(key, header)?)
+    }
+
+    fn flush(&self, header: CacheMetaData) {
+        self.store.flush(header)
+    }
+
+    fn run_pending_tasks(&self) {
+        self.store.run_pending_tasks()
+    }
+
+    fn add(&self, key: KeyType, record: Record) -> Result<SetStatus> {
+        self.store.add(key, self.compress(record))
+    }
+
+    fn replace(&self, key: KeyType, record: Record) -> Result<SetStatus> {
+        self.store.replace(key, self.compress(record))
+    }
+
+    fn append(&self, key: KeyType, new_record: Record) -> Result<SetStatus> {
+        self.append_prepend_common(key, new_record, true)
+    }
+
+    fn prepend(&self, key: KeyType, new_record: Record) -> Result<SetStatus> {
+        self.append_prepend_common(key, new_record, false)
+    }
+
+    /// Numbers are never compressed, compressed values are not numeric
+    fn incr_decr(
+        &self,
+        header: CacheMetaData,
+        key: KeyType,
+        delta: DeltaParam,
+        increment: bool,
+    ) -> Result<DeltaResult> {
+        self.store.incr_decr(header, key, delta, increment)
+    }
+
+    fn for_each_item(&self, f: &mut dyn FnMut(&KeyType, &Record)) {
+        self.store.for_each_item(&mut |key, record| {
+            if let Ok(record) = self.decompress(record.clone()) {
+                f(key, &record)
+            }
+        });
+    }
+
+    fn restore(&self, key: KeyType, record: Record) -> Result<()> {
+        self.store.restore(key, self.compress(record))
+    }
+
+    fn relocate(&self, key: KeyType, record: Record) -> Result<()> {
+        self.store.relocate(key, self.compress(record))
+    }
+
+    fn scan(&self, cursor: u64, count: usize, prefix: &[u8]) -> Result<ScanPage> {
+        let page = self.store.scan(cursor, count, prefix)?;
+        let items = page
+            .items
+            .into_iter()
+            .filter_map(|(key, record)| self.decompress(record).ok().map(|record| (key, record)))
+            .collect();
+        Ok(ScanPage {
+            items,
+            cursor: page.cursor,
+        })
+    }
+
+    fn get_multi(&self, keys: &[KeyType]) -> Vec<Result<Record>> {
+        self.store
+            .get_multi(keys)
+            .into_iter()
+            .map(|result| result.and_then(|record| self.decompress(record)))
+            .collect()
+    }
+
+    fn set_multi(&self, items: Vec<(KeyType, Record)>) -> Vec<Result<SetStatus>> {
+        let items = items
+            .into_iter()
+            .map(|(key, record)| (key, self.compress(record)))
+            .collect();
+        self.store.set_multi(items)
+    }
+
+    fn delete_multi(&self, items: Vec<(KeyType, CacheMetaData)>) -> Vec<Result<Record>> {
+        self.store
+            .delete_multi(items)
+            .into_iter()
+            .map(|result| result.and_then(|record| self.decompress(record)))
+            .collect()
+    }
+
+    fn add_stats(&self, stats: &mut CacheStats) {
+        let compression = stats.compression.get_or_insert(CompressionStats {
+            algorithm: self.algorithm.as_str(),
+            ..Default::default()
+        });
+        compression.values += self.counters.values.load(Ordering::Relaxed);
+        compression.compressed_values += self.counters.compressed_values.load(Ordering::Relaxed);
+        compression.bytes_in += self.counters.bytes_in.load(Ordering::Relaxed);
+        compression.bytes_out += self.counters.bytes_out.load(Ordering::Relaxed);
+        self.store.add_stats(stats)
+    }
+
+    fn subscribe(&self, listener: SharedListener) {
+        self.store.subscribe(listener)
+    }
+}
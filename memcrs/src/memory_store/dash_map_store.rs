@@ -1,27 +1,45 @@
 use crate::cache::cache::{
-    Cache, CacheMetaData, DeltaParam, DeltaResult, KeyType, Record, SetStatus,
+    Cache, CacheMetaData, CacheStats, DeltaParam, DeltaResult, KeyType, Record, ScanPage, SetStatus,
 };
+use crate::cache::cache_capability::CacheCapability;
 use crate::cache::error::{CacheError, Result};
+use crate::cache::events::{RemovalReason, SharedListener};
+use crate::cache::eviction_policy::EvictionPolicy;
 use crate::memcache::cli::parser::DashMapConfig;
+use crate::memory_store::capacity_limit::CapacityLimit;
 use crate::memory_store::parallelism::get_number_of_shards;
 use crate::memory_store::shared_store_state::SharedStoreState;
 use crate::protocol::binary::network::DELTA_NO_INITIAL_VALUE;
 use crate::server::timer;
 
 use bytes::{Bytes, BytesMut};
-use dashmap::DashMap;
+use dashmap::{DashMap, SharedValue};
+use std::hash::BuildHasher;
 use std::sync::Arc;
 
 type Storage = DashMap<KeyType, Record>;
+
+/// digits of u64::MAX, the longest value incr/decr can store
+const DELTA_VALUE_MAX_LEN: u64 = 20;
+
+/// DashMap store never evicts. With `reject_when_full` writes which need
+/// more memory than the memory limit allows are rejected with
+/// OutOfMemory, otherwise the store is unbounded.
 pub struct DashMapMemoryStore {
     memory: Storage,
     store_state: SharedStoreState,
+    capacity: CapacityLimit,
+}
+
+#[inline]
+fn item_size(key: &KeyType, record: &Record) -> u64 {
+    (key.len() + record.len()) as u64
 }
 
 impl DashMapMemoryStore {
     pub fn new(
         timer: Arc<dyn timer::Timer + Send + Sync>,
-        _cfg: DashMapConfig,
+        cfg: DashMapConfig,
     ) -> DashMapMemoryStore {
         let parallelism = std::thread::available_parallelism().map_or(1, usize::from);
         let shards = get_number_of_shards(parallelism);
@@ -30,6 +48,73 @@ impl DashMapMemoryStore {
         DashMapMemoryStore {
             memory: DashMap::with_shard_amount(shards),
             store_state,
+            capacity: match cfg.reject_when_full {
+                true => CapacityLimit::bounded(cfg.memory_limit),
+                false => CapacityLimit::unbounded(),
+            },
+        }
+    }
+
+    /// Drops expired items before a write of `amount` bytes is rejected,
+    /// items nobody reads again would hold the memory forever otherwise
+    fn reclaim_expired(&self, amount: u64) {
+        if !self
+            .capacity
+            .should_reclaim(amount, self.store_state.timestamp())
+        {
+            return;
+        }
+        self.memory.retain(|key, record| {
+            if !self.store_state.check_if_expired(key, record) {
+                return true;
+            }
+            self.capacity.release(item_size(key, record));
+            self.store_state
+                .dropped(key, record, RemovalReason::Expired);
+            false
+        });
+    }
+
+    /// Hash the map stores `key` with
+    fn hash(&self, key: &KeyType) -> u64 {
+        self.memory.hasher().hash_one(key)
+    }
+
+    /// Overwrites `stored` with `record` unless the CAS sent with it
+    /// does not match, the shard of the key must be write locked
+    fn overwrite(
+        &self,
+        key: &KeyType,
+        stored: &mut Record,
+        mut record: Record,
+    ) -> Result<SetStatus> {
+        if SharedStoreState::cas_mismatch(&record, stored.header.cas) {
+            return Err(CacheError::KeyExists);
+        }
+        self.capacity
+            .resize(item_size(key, stored), item_size(key, &record))?;
+        self.store_state.overwritten(key, stored);
+        let cas = self.store_state.set_cas_ttl(&mut record);
+        *stored = record;
+        Ok(SetStatus { cas })
+    }
+
+    /// Calls `f` for every item of a shard which is not expired, the
+    /// shard is read locked meanwhile
+    fn for_each_in_shard(&self, shard: usize, mut f: impl FnMut(&KeyType, &Record)) {
+        let shard = self.memory.shards()[shard].read();
+        // SAFETY: the table is not resized or written to while the shard
+        // read guard is held, the guard lives until the end of the function,
+        // so the iterator never outlives it
+        for bucket in unsafe { shard.iter() } {
+            // SAFETY: the bucket was yielded by the iterator above and the
+            // shard read guard is still held, so it points to a live entry
+            // which nobody writes to
+            let (key, record) = unsafe { bucket.as_ref() };
+            let record = record.get();
+            if !self.store_state.check_if_expired(key, record) {
+                f(key, record);
+            }
         }
     }
 
@@ -39,6 +124,7 @@ impl DashMapMemoryStore {
         mut new_record: Record,
         is_append: bool,
     ) -> Result<SetStatus> {
+        self.reclaim_expired(item_size(&key, &new_record));
         let cas = new_record.header.cas;
         let new_cas = self.store_state.set_cas_ttl(&mut new_record);
         match self.memory.entry(key) {
@@ -57,6 +143,11 @@ impl DashMapMemoryStore {
                     new_value.extend_from_slice(&prev_record.value);
                 }
                 new_record.value = new_value.freeze();
+                self.capacity.resize(
+                    item_size(entry.key(), prev_record),
+                    item_size(entry.key(), &new_record),
+                )?;
+                self.store_state.overwritten(entry.key(), prev_record);
                 entry.insert(new_record);
                 Ok(SetStatus { cas: new_cas })
             }
@@ -68,31 +159,34 @@ impl DashMapMemoryStore {
 impl Cache for DashMapMemoryStore {
     /// Returns a value associated with a key
     fn get(&self, key: &KeyType) -> Result<Record> {
-        match self.memory.entry(key.clone()) {
-            dashmap::mapref::entry::Entry::Occupied(entry) => {
-                let value = entry.get();
-                if self.store_state.check_if_expired(key, value) {
-                    entry.remove();
-                    return Err(CacheError::NotFound);
-                }
-                Ok(value.clone())
+        // shard read lock only, write lock is taken just to drop expired record
+        {
+            let record = self.memory.get(key).ok_or(CacheError::NotFound)?;
+            if !self.store_state.check_if_expired(key, &record) {
+                return Ok(record.clone());
             }
-            dashmap::mapref::entry::Entry::Vacant(_) => Err(CacheError::NotFound),
         }
+        // record could have been replaced before write lock was taken
+        if let Some((key, record)) = self.memory.remove_if(key, |key, record| {
+            self.store_state.check_if_expired(key, record)
+        }) {
+            self.capacity.release(item_size(&key, &record));
+            self.store_state
+                .dropped(&key, &record, RemovalReason::Expired);
+        }
+        Err(CacheError::NotFound)
     }
 
     fn set(&self, key: KeyType, mut record: Record) -> Result<SetStatus> {
+        self.reclaim_expired(item_size(&key, &record));
         match self.memory.entry(key) {
-            dashmap::mapref::entry::Entry::Occupied(mut entry) => {
-                let cas = entry.get().header.cas;
-                if SharedStoreState::cas_mismatch(&record, cas) {
-                    return Err(CacheError::KeyExists);
-                }
-                let cas = self.store_state.set_cas_ttl(&mut record);
-                entry.insert(record);
-                Ok(SetStatus { cas })
+            dashmap::mapref::entry::Entry::Occupied(entry) => {
+                let mut entry = entry.into_ref();
+                let (key, stored) = entry.pair_mut();
+                self.overwrite(key, stored, record)
             }
             dashmap::mapref::entry::Entry::Vacant(entry) => {
+                self.capacity.reserve(item_size(entry.key(), &record))?;
                 let cas = self.store_state.set_cas_ttl(&mut record);
                 entry.insert(record);
                 Ok(SetStatus { cas })
@@ -107,7 +201,11 @@ impl Cache for DashMapMemoryStore {
             cas_match = Some(result);
             result
         }) {
-            Some(key_value) => Ok(key_value.1),
+            Some((key, record)) => {
+                self.capacity.release(item_size(&key, &record));
+                self.store_state.removed(&key, RemovalReason::Explicit);
+                Ok(record)
+            }
             None => match cas_match {
                 Some(_value) => Err(CacheError::KeyExists),
                 None => Err(CacheError::NotFound),
@@ -122,8 +220,12 @@ impl Cache for DashMapMemoryStore {
                 value
             });
         } else {
-            self.memory.clear();
+            self.memory.retain(|key, record| {
+                self.capacity.release(item_size(key, record));
+                false
+            });
         }
+        self.store_state.flushed(header.time_to_live);
     }
 
     fn run_pending_tasks(&self) {}
@@ -131,10 +233,12 @@ impl Cache for DashMapMemoryStore {
     /// Adds a new key-value pair to the cache, but only if the key does not already exist.
     /// If the key exists, the operation fails with KeyExists error.
     fn add(&self, key: KeyType, mut record: Record) -> Result<SetStatus> {
+        self.reclaim_expired(item_size(&key, &record));
         let cas = self.store_state.set_cas_ttl(&mut record);
         match self.memory.entry(key) {
             dashmap::mapref::entry::Entry::Occupied(_) => Err(CacheError::KeyExists),
             dashmap::mapref::entry::Entry::Vacant(entry) => {
+                self.capacity.reserve(item_size(entry.key(), &record))?;
                 entry.insert(record);
                 Ok(SetStatus { cas })
             }
@@ -143,16 +247,13 @@ impl Cache for DashMapMemoryStore {
 
     /// Replaces the value of an existing key in the cache, but only if the key already exists.
     /// If the key does not exist, the operation fails with NotFound error.
-    fn replace(&self, key: KeyType, mut record: Record) -> Result<SetStatus> {
+    fn replace(&self, key: KeyType, record: Record) -> Result<SetStatus> {
+        self.reclaim_expired(item_size(&key, &record));
         match self.memory.entry(key) {
-            dashmap::mapref::entry::Entry::Occupied(mut entry) => {
-                let cas = entry.get().header.cas;
-                if SharedStoreState::cas_mismatch(&record, cas) {
-                    return Err(CacheError::KeyExists);
-                }
-                let new_cas = self.store_state.set_cas_ttl(&mut record);
-                entry.insert(record);
-                Ok(SetStatus { cas: new_cas })
+            dashmap::mapref::entry::Entry::Occupied(entry) => {
+                let mut entry = entry.into_ref();
+                let (key, stored) = entry.pair_mut();
+                self.overwrite(key, stored, record)
             }
             dashmap::mapref::entry::Entry::Vacant(_) => Err(CacheError::NotFound),
         }
@@ -182,21 +283,30 @@ impl Cache for DashMapMemoryStore {
         increment: bool,
     ) -> Result<DeltaResult> {
         let cas = header.cas;
+        self.reclaim_expired(key.len() as u64 + DELTA_VALUE_MAX_LEN);
 
         match self.memory.entry(key) {
             dashmap::mapref::entry::Entry::Occupied(mut entry) => {
+                let key = entry.key().clone();
+                let key_len = key.len() as u64;
                 let record = entry.get_mut();
                 match self.store_state.incr_decr_common(record, delta, increment) {
-                    Ok(new_value) => {
+                    Ok(delta_value) => {
                         let tmp_record = Record::new(Bytes::new(), cas, 0, 0);
                         if SharedStoreState::cas_mismatch(&tmp_record, record.header.cas) {
                             return Err(CacheError::KeyExists);
                         }
-                        let new_cas = self.store_state.set_cas_ttl(record);
-                        record.value = Bytes::from(new_value.to_string());
+                        let new_value = Bytes::from(delta_value.to_string());
+                        self.capacity.resize(
+                            key_len + record.len() as u64,
+                            key_len + (record.header.len() + new_value.len()) as u64,
+                        )?;
+                        self.store_state.overwritten(&key, record);
+                        let new_cas = self.store_state.get_cas_id();
+                        record.value = new_value;
                         record.header.cas = new_cas;
                         Ok(DeltaResult {
-                            value: new_value,
+                            value: delta_value,
                             cas: new_cas,
                         })
                     }
@@ -210,8 +320,9 @@ impl Cache for DashMapMemoryStore {
                         Bytes::from(delta.value.to_string()),
                         cas,
                         0,
-                        header.get_expiration(),
+                        self.store_state.expiration(header.get_expiration()),
                     );
+                    self.capacity.reserve(item_size(entry.key(), &record))?;
                     entry.insert(record);
                     return Ok(DeltaResult {
                         cas,
@@ -222,4 +333,179 @@ impl Cache for DashMapMemoryStore {
             }
         }
     }
+
+    fn for_each_item(&self, f: &mut dyn FnMut(&KeyType, &Record)) {
+        // items are copied out under the lock, `f` may do I/O and must
+        // not block writers of the shard
+        let mut items = Vec::new();
+        for shard in 0..self.memory.shards().len() {
+            self.for_each_in_shard(shard, |key, record| {
+                items.push((key.clone(), record.clone()));
+            });
+            items.drain(..).for_each(|(key, record)| f(&key, &record));
+        }
+    }
+
+    fn restore(&self, key: KeyType, record: Record) -> Result<()> {
+        self.reclaim_expired(item_size(&key, &record));
+        match self.memory.entry(key) {
+            // key was written after the snapshot was taken, it is newer
+            dashmap::mapref::entry::Entry::Occupied(_) => Err(CacheError::KeyExists),
+            dashmap::mapref::entry::Entry::Vacant(entry) => {
+                self.capacity.reserve(item_size(entry.key(), &record))?;
+                self.store_state.advance_cas_id(record.header.cas);
+                entry.insert(record);
+                Ok(())
+            }
+        }
+    }
+
+    fn relocate(&self, key: KeyType, record: Record) -> Result<()> {
+        let mut stored = self.memory.get_mut(&key).ok_or(CacheError::NotFound)?;
+        if stored.header.cas != record.header.cas {
+            return Err(CacheError::KeyExists);
+        }
+        let moved = Record {
+            header: stored.header.clone(),
+            value: record.value,
+        };
+        self.capacity
+            .resize(item_size(&key, &stored), item_size(&key, &moved))?;
+        *stored = moved;
+        Ok(())
+    }
+
+    fn add_stats(&self, stats: &mut CacheStats) {
+        self.store_state.add_stats(stats)
+    }
+
+    fn subscribe(&self, listener: SharedListener) {
+        self.store_state.subscribe(listener)
+    }
+
+    /// Keys of the same shard are read under one shard read lock
+    fn get_multi(&self, keys: &[KeyType]) -> Vec<Result<Record>> {
+        let shards = self.memory.shards();
+        let mut results: Vec<Result<Record>> =
+            keys.iter().map(|_| Err(CacheError::NotFound)).collect();
+        let mut expired = Vec::new();
+        for (shard, positions) in
+            SharedStoreState::group_by_shard(keys.iter(), |key| self.memory.determine_map(key))
+        {
+            let shard = shards[shard].read();
+            for position in positions {
+                let key = &keys[position];
+                if let Some((_, record)) = shard.get(self.hash(key), |(stored, _)| stored == key) {
+                    let record = record.get();
+                    if self.store_state.check_if_expired(key, record) {
+                        expired.push(position);
+                    } else {
+                        results[position] = Ok(record.clone());
+                    }
+                }
+            }
+        }
+        // removed under write lock, like in get
+        for position in expired {
+            results[position] = self.get(&keys[position]);
+        }
+        results
+    }
+
+    /// Items of the same shard are written under one shard write lock
+    fn set_multi(&self, items: Vec<(KeyType, Record)>) -> Vec<Result<SetStatus>> {
+        // shard locks are not held yet, expired items can be dropped
+        self.reclaim_expired(
+            items
+                .iter()
+                .map(|(key, record)| item_size(key, record))
+                .sum(),
+        );
+        let groups = SharedStoreState::group_by_shard(items.iter().map(|(key, _)| key), |key| {
+            self.memory.determine_map(key)
+        });
+        let shards = self.memory.shards();
+        let mut items: Vec<Option<(KeyType, Record)>> = items.into_iter().map(Some).collect();
+        let mut results: Vec<Result<SetStatus>> =
+            items.iter().map(|_| Err(CacheError::NotFound)).collect();
+        for (shard, positions) in groups {
+            let mut shard = shards[shard].write();
+            for position in positions {
+                let Some((key, mut record)) = items[position].take() else {
+                    continue;
+                };
+                let hash = self.hash(&key);
+                results[position] = match shard.get_mut(hash, |(stored, _)| *stored == key) {
+                    Some((key, stored)) => self.overwrite(key, stored.get_mut(), record),
+                    None => self.capacity.reserve(item_size(&key, &record)).map(|()| {
+                        let cas = self.store_state.set_cas_ttl(&mut record);
+                        shard.insert(hash, (key, SharedValue::new(record)), |(key, _)| {
+                            self.hash(key)
+                        });
+                        SetStatus { cas }
+                    }),
+                };
+            }
+        }
+        results
+    }
+
+    /// Keys of the same shard are removed under one shard write lock
+    fn delete_multi(&self, items: Vec<(KeyType, CacheMetaData)>) -> Vec<Result<Record>> {
+        let groups = SharedStoreState::group_by_shard(items.iter().map(|(key, _)| key), |key| {
+            self.memory.determine_map(key)
+        });
+        let shards = self.memory.shards();
+        let mut items: Vec<Option<(KeyType, CacheMetaData)>> =
+            items.into_iter().map(Some).collect();
+        let mut results: Vec<Result<Record>> =
+            items.iter().map(|_| Err(CacheError::NotFound)).collect();
+        for (shard, positions) in groups {
+            let mut shard = shards[shard].write();
+            for position in positions {
+                let Some((key, header)) = items[position].take() else {
+                    continue;
+                };
+                let hash = self.hash(&key);
+                let cas_match = shard
+                    .get(hash, |(stored, _)| *stored == key)
+                    .map(|(_, record)| header.cas == 0 || record.get().header.cas == header.cas);
+                results[position] = match cas_match {
+                    None => Err(CacheError::NotFound),
+                    Some(false) => Err(CacheError::KeyExists),
+                    Some(true) => match shard.remove_entry(hash, |(stored, _)| *stored == key) {
+                        Some((key, record)) => {
+                            let record = record.into_inner();
+                            self.capacity.release(item_size(&key, &record));
+                            self.store_state.removed(&key, RemovalReason::Explicit);
+                            Ok(record)
+                        }
+                        None => Err(CacheError::NotFound),
+                    },
+                };
+            }
+        }
+        results
+    }
+
+    /// Every shard is a scan segment, read locked while it is scanned
+    fn scan(&self, cursor: u64, count: usize, prefix: &[u8]) -> Result<ScanPage> {
+        self.store_state.scan_segments(
+            cursor,
+            count,
+            prefix,
+            self.memory.shards().len(),
+            |shard, collector| {
+                self.for_each_in_shard(shard, |key, record| {
+                    collector.offer(self.hash(key), key, record);
+                })
+            },
+        )
+    }
+}
+
+impl CacheCapability for DashMapMemoryStore {
+    fn is_policy_supported(&self, policy: EvictionPolicy) -> bool {
+        policy == EvictionPolicy::None
+    }
 }
@@ -0,0 +1,480 @@
+use crate::cache::cache::KeyType;
+
+use bytes::Bytes;
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::Mutex;
+
+/// key len u16 | value len u32 | key | value
+const ITEM_HEADER_LEN: usize = 6;
+
+#[derive(Clone, Debug)]
+pub struct ExtStoreConfig {
+    pub path: PathBuf,
+    /// values larger than this are written to the page file
+    pub item_size: u64,
+    pub size: u64,
+    pub page_size: u64,
+}
+
+/// Position of a value in the page file, `generation` is bumped every
+/// time a page is reused so stale locations are detected on read.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ExtLocation {
+    pub page: u32,
+    pub generation: u32,
+    pub offset: u32,
+    pub len: u32,
+}
+
+/// Item read back from a page during compaction
+pub struct ExtItem {
+    pub location: ExtLocation,
+    pub key: KeyType,
+    pub value: Bytes,
+}
+
+#[derive(Default)]
+struct Page {
+    generation: AtomicU32,
+    used: AtomicU64,
+    /// bytes of items written minus items known to be overwritten or deleted
+    live: AtomicU64,
+}
+
+#[derive(Default)]
+struct Pages {
+    active: Option<u32>,
+    offset: u64,
+    free: VecDeque<u32>,
+    /// full pages, oldest first
+    sealed: VecDeque<u32>,
+}
+
+/// File split into equal pages, items are appended to the active page.
+///
+/// Once all pages are used the oldest one is reused and items stored in it
+/// are lost, same as items evicted from memory. Space of overwritten items
+/// is reclaimed by compacting pages, see `compaction_candidate`.
+pub struct ExtStore {
+    file: File,
+    page_size: u64,
+    pages: Vec<Page>,
+    state: Mutex<Pages>,
+    /// sealed pages reused before they were compacted
+    reused: AtomicU64,
+    /// seek and read/write pairs on the shared file cursor
+    #[cfg(not(unix))]
+    cursor: Mutex<()>,
+}
+
+impl ExtStore {
+    /// Creates the page file, previous content is discarded as locations
+    /// of items are kept in memory only.
+    pub fn open(config: &ExtStoreConfig) -> io::Result<ExtStore> {
+        let page_count = config.size / config.page_size.max(1);
+        if page_count < 2 || config.page_size > u32::MAX as u64 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "page file has to hold at least two pages of at most 4GiB",
+            ));
+        }
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&config.path)?;
+        let pages: Vec<Page> = (0..page_count).map(|_| Page::default()).collect();
+        let state = Pages {
+            free: (0..page_count as u32).collect(),
+            ..Default::default()
+        };
+        Ok(ExtStore {
+            file,
+            page_size: config.page_size,
+            pages,
+            state: Mutex::new(state),
+            reused: AtomicU64::new(0),
+            #[cfg(not(unix))]
+            cursor: Mutex::new(()),
+        })
+    }
+
+    pub fn fits(&self, key: &KeyType, value_len: usize) -> bool {
+        item_len(key.len(), value_len) <= self.page_size
+    }
+
+    pub fn write(&self, key: &KeyType, value: &Bytes) -> io::Result<ExtLocation> {
+        let len = item_len(key.len(), value.len());
+        if len > self.page_size {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "item is larger than a page",
+            ));
+        }
+        let mut buf = Vec::with_capacity(len as usize);
+        buf.extend_from_slice(&(key.len() as u16).to_be_bytes());
+        buf.extend_from_slice(&(value.len() as u32).to_be_bytes());
+        buf.extend_from_slice(key);
+        buf.extend_from_slice(value);
+
+        let mut state = self.state.lock().unwrap();
+        if state.active.is_none() || state.offset + len > self.page_size {
+            self.open_page(&mut state)?;
+        }
+        let page = state.active.expect("page was opened");
+        let offset = state.offset;
+        self.write_at(&buf, self.position(page, offset))?;
+        state.offset += len;
+        let info = &self.pages[page as usize];
+        info.used.store(state.offset, Ordering::Release);
+        info.live.fetch_add(len, Ordering::Relaxed);
+        Ok(ExtLocation {
+            page,
+            generation: info.generation.load(Ordering::Acquire),
+            offset: offset as u32,
+            len: value.len() as u32,
+        })
+    }
+
+    /// Whether the page of `location` was not reused since it was written
+    pub fn contains(&self, location: ExtLocation) -> bool {
+        self.pages
+            .get(location.page as usize)
+            .is_some_and(|page| page.generation.load(Ordering::Acquire) == location.generation)
+    }
+
+    /// Returns None if the page was reused since the value was written
+    pub fn read(&self, key: &KeyType, location: ExtLocation) -> io::Result<Option<Bytes>> {
+        let page = match self.pages.get(location.page as usize) {
+            Some(page) => page,
+            None => return Ok(None),
+        };
+        if page.generation.load(Ordering::Acquire) != location.generation {
+            return Ok(None);
+        }
+        let len = item_len(key.len(), location.len as usize) as usize;
+        let mut buf = vec![0u8; len];
+        self.read_at(
+            &mut buf,
+            self.position(location.page, location.offset as u64),
+        )?;
+        // page could have been reused while it was read
+        if page.generation.load(Ordering::Acquire) != location.generation {
+            return Ok(None);
+        }
+        let key_len = u16::from_be_bytes([buf[0], buf[1]]) as usize;
+        let value_len = u32::from_be_bytes([buf[2], buf[3], buf[4], buf[5]]);
+        let value_start = ITEM_HEADER_LEN + key.len();
+        if key_len != key.len()
+            || value_len != location.len
+            || buf[ITEM_HEADER_LEN..value_start] != key[..]
+        {
+            return Ok(None);
+        }
+        Ok(Some(Bytes::from(buf).slice(value_start..)))
+    }
+
+    /// Marks item as no longer referenced, it is dropped on compaction
+    pub fn release(&self, key: &KeyType, location: ExtLocation) {
+        let page = match self.pages.get(location.page as usize) {
+            Some(page) => page,
+            None => return,
+        };
+        if page.generation.load(Ordering::Acquire) != location.generation {
+            return;
+        }
+        let len = item_len(key.len(), location.len as usize);
+        let _ = page
+            .live
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |live| {
+                Some(live.saturating_sub(len))
+            });
+    }
+
+    /// Drops all items
+    pub fn reset(&self) {
+        let mut state = self.state.lock().unwrap();
+        (0..self.pages.len() as u32).for_each(|page| self.reset_page(page));
+        *state = Pages {
+            free: (0..self.pages.len() as u32).collect(),
+            ..Default::default()
+        };
+    }
+
+    /// Picks the full page with the smallest share of live bytes, if it
+    /// is below `max_live_ratio`. The page is not reused until it is passed
+    /// to `free_page`, live items should be written again before that.
+    pub fn compaction_candidate(&self, max_live_ratio: f64) -> Option<(u32, u32)> {
+        let mut state = self.state.lock().unwrap();
+        let (index, page) = state
+            .sealed
+            .iter()
+            .enumerate()
+            .map(|(index, page)| (index, *page, self.live_ratio(*page)))
+            .filter(|(_, _, ratio)| *ratio < max_live_ratio)
+            .min_by(|left, right| left.2.total_cmp(&right.2))
+            .map(|(index, page, _)| (index, page))?;
+        state.sealed.remove(index);
+        let generation = self.pages[page as usize].generation.load(Ordering::Acquire);
+        Some((page, generation))
+    }
+
+    pub fn read_page(&self, page: u32) -> io::Result<Vec<ExtItem>> {
+        let info = &self.pages[page as usize];
+        let generation = info.generation.load(Ordering::Acquire);
+        let mut buf = vec![0u8; info.used.load(Ordering::Acquire) as usize];
+        self.read_at(&mut buf, self.position(page, 0))?;
+        let buf = Bytes::from(buf);
+        let mut items = Vec::new();
+        let mut offset = 0;
+        while offset + ITEM_HEADER_LEN <= buf.len() {
+            let key_len = u16::from_be_bytes([buf[offset], buf[offset + 1]]) as usize;
+            let value_len = u32::from_be_bytes([
+                buf[offset + 2],
+                buf[offset + 3],
+                buf[offset + 4],
+                buf[offset + 5],
+            ]);
+            let key_start = offset + ITEM_HEADER_LEN;
+            let value_start = key_start + key_len;
+            let end = value_start + value_len as usize;
+            if end > buf.len() {
+                break;
+            }
+            items.push(ExtItem {
+                location: ExtLocation {
+                    page,
+                    generation,
+                    offset: offset as u32,
+                    len: value_len,
+                },
+                key: buf.slice(key_start..value_start),
+                value: buf.slice(value_start..end),
+            });
+            offset = end;
+        }
+        Ok(items)
+    }
+
+    /// Returns page taken by `compaction_candidate` to the free list
+    pub fn free_page(&self, page: u32, generation: u32) {
+        let mut state = self.state.lock().unwrap();
+        // page was already freed by a reset
+        if self.pages[page as usize].generation.load(Ordering::Acquire) != generation {
+            return;
+        }
+        self.reset_page(page);
+        state.free.push_back(page);
+    }
+
+    /// Number of times a page was reused with its items still in it,
+    /// locations kept elsewhere may have turned stale once it changes
+    pub fn reused_pages(&self) -> u64 {
+        self.reused.load(Ordering::Relaxed)
+    }
+
+    pub fn used_bytes(&self) -> u64 {
+        self.pages
+            .iter()
+            .map(|page| page.used.load(Ordering::Relaxed))
+            .sum()
+    }
+
+    pub fn live_bytes(&self) -> u64 {
+        self.pages
+            .iter()
+            .map(|page| page.live.load(Ordering::Relaxed))
+            .sum()
+    }
+
+    fn open_page(&self, state: &mut Pages) -> io::Result<()> {
+        if let Some(active) = state.active.take() {
+            state.sealed.push_back(active);
+        }
+        let page = match state.free.pop_front() {
+            Some(page) => page,
+            None => {
+                let oldest = state.sealed.pop_front().ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::StorageFull, "no page can be reused")
+                })?;
+                self.reset_page(oldest);
+                self.reused.fetch_add(1, Ordering::Relaxed);
+                oldest
+            }
+        };
+        state.active = Some(page);
+        state.offset = 0;
+        Ok(())
+    }
+
+    fn reset_page(&self, page: u32) {
+        let info = &self.pages[page as usize];
+        info.generation.fetch_add(1, Ordering::AcqRel);
+        info.used.store(0, Ordering::Release);
+        info.live.store(0, Ordering::Relaxed);
+    }
+
+    fn live_ratio(&self, page: u32) -> f64 {
+        let info = &self.pages[page as usize];
+        let used = info.used.load(Ordering::Relaxed).max(1);
+        info.live.load(Ordering::Relaxed) as f64 / used as f64
+    }
+
+    fn position(&self, page: u32, offset: u64) -> u64 {
+        page as u64 * self.page_size + offset
+    }
+
+    #[cfg(unix)]
+    fn read_at(&self, buf: &mut [u8], position: u64) -> io::Result<()> {
+        use std::os::unix::fs::FileExt;
+        self.file.read_exact_at(buf, position)
+    }
+
+    #[cfg(not(unix))]
+    fn read_at(&self, buf: &mut [u8], position: u64) -> io::Result<()> {
+        use std::io::{Read, Seek, SeekFrom};
+        let _cursor = self.cursor.lock().unwrap();
+        let mut file = &self.file;
+        file.seek(SeekFrom::Start(position))?;
+        file.read_exact(buf)
+    }
+
+    #[cfg(unix)]
+    fn write_at(&self, buf: &[u8], position: u64) -> io::Result<()> {
+        use std::os::unix::fs::FileExt;
+        self.file.write_all_at(buf, position)
+    }
+
+    #[cfg(not(unix))]
+    fn write_at(&self, buf: &[u8], position: u64) -> io::Result<()> {
+        use std::io::{Seek, SeekFrom, Write};
+        let _cursor = self.cursor.lock().unwrap();
+        let mut file = &self.file;
+        file.seek(SeekFrom::Start(position))?;
+        file.write_all(buf)
+    }
+}
+
+fn item_len(key_len: usize, value_len: usize) -> u64 {
+    (ITEM_HEADER_LEN + key_len + value_len) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    struct TempExtStore {
+        store: ExtStore,
+    }
+
+    impl TempExtStore {
+        fn new(name: &str, size: u64, page_size: u64) -> TempExtStore {
+            let path =
+                std::env::temp_dir().join(format!("memcrs-ext-{}-{}", name, std::process::id()));
+            let config = ExtStoreConfig {
+                path: path.clone(),
+                item_size: 64,
+                size,
+                page_size,
+            };
+            let store = ExtStore::open(&config).unwrap();
+            // file stays readable through the open handle
+            let _ = fs::remove_file(&path);
+            TempExtStore { store }
+        }
+    }
+
+    fn bytes(value: &str) -> Bytes {
+        Bytes::from(value.to_string())
+    }
+
+    #[test]
+    fn open_should_require_two_pages() {
+        let config = ExtStoreConfig {
+            path: std::env::temp_dir().join("memcrs-ext-one-page"),
+            item_size: 64,
+            size: 1024,
+            page_size: 1024,
+        };
+        assert!(ExtStore::open(&config).is_err());
+    }
+
+    #[test]
+    fn written_value_should_be_read_back() {
+        let ext = TempExtStore::new("read", 4096, 1024);
+        let location = ext.store.write(&bytes("key"), &bytes("value")).unwrap();
+        let value = ext.store.read(&bytes("key"), location).unwrap();
+        assert_eq!(value, Some(bytes("value")));
+        assert_eq!(ext.store.live_bytes(), 14);
+    }
+
+    #[test]
+    fn read_should_verify_key() {
+        let ext = TempExtStore::new("key", 4096, 1024);
+        let location = ext.store.write(&bytes("key"), &bytes("value")).unwrap();
+        assert_eq!(ext.store.read(&bytes("yek"), location).unwrap(), None);
+    }
+
+    #[test]
+    fn reused_page_should_not_be_read() {
+        let ext = TempExtStore::new("reuse", 2048, 1024);
+        let value = Bytes::from(vec![b'a'; 600]);
+        let first = ext.store.write(&bytes("first"), &value).unwrap();
+        ext.store.write(&bytes("second"), &value).unwrap();
+        // both pages are used, third write reuses the page of the first one
+        let third = ext.store.write(&bytes("third"), &value).unwrap();
+        assert_eq!(third.page, first.page);
+        assert_eq!(ext.store.read(&bytes("first"), first).unwrap(), None);
+        assert_eq!(ext.store.read(&bytes("third"), third).unwrap(), Some(value));
+        assert!(!ext.store.contains(first));
+        assert!(ext.store.contains(third));
+        assert_eq!(ext.store.reused_pages(), 1);
+    }
+
+    #[test]
+    fn item_larger_than_page_should_be_rejected() {
+        let ext = TempExtStore::new("large", 2048, 1024);
+        let value = Bytes::from(vec![b'a'; 1024]);
+        assert!(!ext.store.fits(&bytes("key"), value.len()));
+        assert!(ext.store.write(&bytes("key"), &value).is_err());
+    }
+
+    #[test]
+    fn released_page_should_be_compaction_candidate() {
+        let ext = TempExtStore::new("compact", 4096, 1024);
+        let value = Bytes::from(vec![b'a'; 400]);
+        let first = ext.store.write(&bytes("first"), &value).unwrap();
+        let second = ext.store.write(&bytes("second"), &value).unwrap();
+        // seals the page of first two items
+        ext.store.write(&bytes("third"), &value).unwrap();
+        assert_eq!(ext.store.compaction_candidate(0.6), None);
+
+        ext.store.release(&bytes("first"), first);
+        let (page, generation) = ext.store.compaction_candidate(0.6).unwrap();
+        assert_eq!(page, first.page);
+        let items = ext.store.read_page(page).unwrap();
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[1].key, bytes("second"));
+        assert_eq!(items[1].location, second);
+        assert_eq!(items[1].value, value);
+
+        ext.store.free_page(page, generation);
+        assert_eq!(ext.store.read(&bytes("second"), second).unwrap(), None);
+    }
+
+    #[test]
+    fn reset_should_drop_all_items() {
+        let ext = TempExtStore::new("reset", 4096, 1024);
+        let location = ext.store.write(&bytes("key"), &bytes("value")).unwrap();
+        ext.store.reset();
+        assert_eq!(ext.store.read(&bytes("key"), location).unwrap(), None);
+        assert_eq!(ext.store.used_bytes(), 0);
+        assert_eq!(ext.store.live_bytes(), 0);
+    }
+}
@@ -1,9 +1,21 @@
 use clap::ValueEnum;
 
+pub mod capacity_limit;
+pub mod cas;
+pub mod compressed_store;
 pub mod dash_map_store;
+pub mod ext_store;
 pub mod moka_store;
+pub mod namespaced_store;
 mod parallelism;
+pub mod partitioned_store;
+pub mod scan;
 pub mod shared_store_state;
+pub mod sieve;
+pub mod sieve_store;
+pub mod slab_allocator;
+pub mod slab_store;
+pub mod tiered_store;
 
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug)]
 pub enum StoreEngine {
@@ -11,6 +23,10 @@ pub enum StoreEngine {
     DashMap,
     /// store based on moka library
     Moka,
+    /// store based on preallocated slab classes
+    Slab,
+    /// lock sharded store evicting with SIEVE
+    Sieve,
 }
 
 impl StoreEngine {
@@ -18,6 +34,8 @@ impl StoreEngine {
         match self {
             StoreEngine::DashMap => "DashMap backend",
             StoreEngine::Moka => "Moka backend",
+            StoreEngine::Slab => "Slab backend",
+            StoreEngine::Sieve => "SIEVE backend",
         }
     }
 }
@@ -30,17 +48,25 @@ mod tests {
     fn test_as_str() {
         assert_eq!(StoreEngine::DashMap.as_str(), "DashMap backend");
         assert_eq!(StoreEngine::Moka.as_str(), "Moka backend");
+        assert_eq!(StoreEngine::Slab.as_str(), "Slab backend");
+        assert_eq!(StoreEngine::Sieve.as_str(), "SIEVE backend");
     }
 
     #[test]
     fn test_enum_ordering() {
         assert!(StoreEngine::DashMap < StoreEngine::Moka);
+        assert!(StoreEngine::Moka < StoreEngine::Slab);
+        assert!(StoreEngine::Slab < StoreEngine::Sieve);
     }
 
     #[test]
     fn test_enum_equality() {
         assert_eq!(StoreEngine::DashMap, StoreEngine::DashMap);
         assert_eq!(StoreEngine::Moka, StoreEngine::Moka);
+        assert_eq!(StoreEngine::Slab, StoreEngine::Slab);
+        assert_eq!(StoreEngine::Sieve, StoreEngine::Sieve);
         assert_ne!(StoreEngine::DashMap, StoreEngine::Moka);
+        assert_ne!(StoreEngine::Moka, StoreEngine::Slab);
+        assert_ne!(StoreEngine::Slab, StoreEngine::Sieve);
     }
 }
@@ -1,17 +1,23 @@
 use crate::cache::cache::{
-    Cache, CacheMetaData, DeltaParam, DeltaResult, KeyType, Record, SetStatus,
+    Cache, CacheMetaData, CacheStats, DeltaParam, DeltaResult, KeyType, Record, ScanPage, SetStatus,
 };
+use crate::cache::cache_capability::CacheCapability;
 use crate::cache::error::{CacheError, Result};
+use crate::cache::events::{RemovalReason, SharedListener, StoreEvents};
 use crate::cache::eviction_policy;
 use crate::memcache::cli::parser::MokaConfig;
+use crate::memory_store::capacity_limit::CapacityLimit;
 use crate::memory_store::shared_store_state::SharedStoreState;
 use crate::protocol::binary::network::DELTA_NO_INITIAL_VALUE;
 use crate::server::timer;
 use bytes::{Bytes, BytesMut};
+use moka::notification::RemovalCause;
 use moka::ops::compute::Op;
 use moka::policy::EvictionPolicy as EvictionPolicyType;
 // use moka::sync::SegmentedCache;
 use moka::sync::Cache as MokaCache;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, RandomState};
 use std::sync::Arc;
 
 //type MokaStorage = SegmentedCache<KeyType, Record, hash_map::RandomState>;
@@ -20,33 +26,131 @@ type MokaStorage = MokaCache<KeyType, Record>;
 pub struct MokaMemoryStore {
     memory: MokaStorage,
     store_state: SharedStoreState,
+    // only bounded for EvictionPolicy::None, otherwise moka evicts on its own
+    capacity: CapacityLimit,
+    // orders keys for scans
+    hasher: RandomState,
 }
 
 impl MokaMemoryStore {
+    /// Fails with NotSupported for SIEVE, it is only implemented
+    /// by the sieve engine
     pub fn new(
         timer: Arc<dyn timer::Timer + Send + Sync>,
         moka_config: MokaConfig,
-    ) -> MokaMemoryStore {
+    ) -> Result<MokaMemoryStore> {
         let store_state = SharedStoreState::new(timer.clone());
-        let eviction_policy = match moka_config.eviction_policy {
-            eviction_policy::EvictionPolicy::None => EvictionPolicyType::lru(),
-            eviction_policy::EvictionPolicy::TinyLeastFrequentlyUsed => {
-                EvictionPolicyType::tiny_lfu()
-            }
-            eviction_policy::EvictionPolicy::LeastRecentlyUsed => EvictionPolicyType::lru(),
+        let (cache, capacity): (MokaStorage, CapacityLimit) = match moka_config.eviction_policy {
+            eviction_policy::EvictionPolicy::None => (
+                // moka is left unbounded so it never evicts,
+                // max capacity is enforced by the store itself
+                MokaCache::builder().build(),
+                CapacityLimit::bounded(moka_config.max_capacity),
+            ),
+            eviction_policy::EvictionPolicy::TinyLeastFrequentlyUsed => (
+                MokaMemoryStore::build_evicting_cache(
+                    moka_config.max_capacity,
+                    EvictionPolicyType::tiny_lfu(),
+                    store_state.events(),
+                ),
+                CapacityLimit::unbounded(),
+            ),
+            eviction_policy::EvictionPolicy::LeastRecentlyUsed => (
+                MokaMemoryStore::build_evicting_cache(
+                    moka_config.max_capacity,
+                    EvictionPolicyType::lru(),
+                    store_state.events(),
+                ),
+                CapacityLimit::unbounded(),
+            ),
+            eviction_policy::EvictionPolicy::Sieve => return Err(CacheError::NotSupported),
         };
-        let cache: MokaStorage = MokaCache::builder()
+        Ok(MokaMemoryStore {
+            memory: cache,
+            store_state,
+            capacity,
+            hasher: RandomState::new(),
+        })
+    }
+
+    fn build_evicting_cache(
+        max_capacity: u64,
+        eviction_policy: EvictionPolicyType,
+        events: Arc<StoreEvents>,
+    ) -> MokaStorage {
+        MokaCache::builder()
             // Max entries
-            .max_capacity(moka_config.max_capacity)
+            .max_capacity(max_capacity)
             // Create the cache.
             .eviction_policy(eviction_policy)
-            .build();
-        MokaMemoryStore {
-            memory: cache,
-            store_state,
+            // expirations, removals and replacements are reported by the
+            // store itself, moka only knows about evictions
+            .eviction_listener(move |key: Arc<KeyType>, record, cause| {
+                if cause == RemovalCause::Size {
+                    events.dropped(&key, &record, RemovalReason::Size);
+                }
+            })
+            .build()
+    }
+
+    /// Removes record if it is expired, it could have been replaced since
+    /// it was read
+    fn remove_expired(&self, key: &KeyType) {
+        let _entry =
+            self.memory
+                .entry(key.clone())
+                .and_compute_with(|maybe_entry| match maybe_entry {
+                    Some(entry) if self.store_state.check_if_expired(key, entry.value()) => {
+                        self.capacity.release(1);
+                        self.store_state
+                            .dropped(key, entry.value(), RemovalReason::Expired);
+                        Op::Remove
+                    }
+                    _ => Op::Nop,
+                });
+    }
+
+    /// Drops expired items before a new item is rejected, items nobody
+    /// reads again would hold the capacity forever otherwise
+    fn reclaim_expired(&self) {
+        if !self
+            .capacity
+            .should_reclaim(1, self.store_state.timestamp())
+        {
+            return;
+        }
+        for (key, record) in self.memory.iter() {
+            if self.store_state.check_if_expired(&key, &record) {
+                self.remove_expired(&key);
+            }
         }
     }
 
+    /// Sets an item like `set`, expired items are reclaimed by the caller
+    fn set_reclaimed(&self, key: KeyType, mut record: Record) -> Result<SetStatus> {
+        let mut result: Result<SetStatus> = Err(CacheError::KeyExists);
+        let _entry = self.memory.entry(key).and_compute_with(|maybe_entry| {
+            match maybe_entry {
+                Some(entry) => {
+                    if SharedStoreState::cas_mismatch(&record, entry.value().header.cas) {
+                        return Op::Nop;
+                    }
+                    self.store_state.overwritten(entry.key(), entry.value());
+                }
+                None => {
+                    if let Err(err) = self.capacity.reserve(1) {
+                        result = Err(err);
+                        return Op::Nop;
+                    }
+                }
+            }
+            let cas = self.store_state.set_cas_ttl(&mut record);
+            result = Ok(SetStatus { cas });
+            Op::Put(record)
+        });
+        result
+    }
+
     fn append_prepend_common(
         &self,
         key: KeyType,
@@ -59,11 +163,12 @@ impl MokaMemoryStore {
             .entry(key)
             .and_compute_with(|maybe_entry| match maybe_entry {
                 Some(entry) => {
-                    let prev_record = entry.into_value();
-                    if SharedStoreState::cas_mismatch(&new_record, prev_record.header.cas) {
+                    if SharedStoreState::cas_mismatch(&new_record, entry.value().header.cas) {
                         result = Err(CacheError::KeyExists);
                         Op::Nop
                     } else {
+                        self.store_state.overwritten(entry.key(), entry.value());
+                        let prev_record = entry.into_value();
                         self.store_state.set_cas_ttl(&mut new_record);
                         let mut new_value = BytesMut::with_capacity(
                             prev_record.value.len() + new_record.value.len(),
@@ -91,53 +196,31 @@ impl MokaMemoryStore {
 impl Cache for MokaMemoryStore {
     /// Returns a value associated with a key
     fn get(&self, key: &KeyType) -> Result<Record> {
-        let mut result = Err(CacheError::NotFound);
-
-        let _entry =
-            self.memory
-                .entry(key.clone())
-                .and_compute_with(|maybe_entry| match maybe_entry {
-                    Some(record) => {
-                        if self.store_state.check_if_expired(key, record.value()) {
-                            result = Err(CacheError::NotFound);
-                            return Op::Remove;
-                        }
-                        result = Ok(record.value().clone());
-                        Op::Nop
-                    }
-                    None => {
-                        result = Err(CacheError::NotFound);
-                        Op::Nop
-                    }
-                });
-        result
+        // moka reads are lock free, entry is locked only to drop expired record
+        let record = self.memory.get(key).ok_or(CacheError::NotFound)?;
+        if !self.store_state.check_if_expired(key, &record) {
+            return Ok(record);
+        }
+        self.remove_expired(key);
+        Err(CacheError::NotFound)
     }
 
-    fn set(&self, key: KeyType, mut record: Record) -> Result<SetStatus> {
+    fn set(&self, key: KeyType, record: Record) -> Result<SetStatus> {
         //trace!("Set: {:?}", &record.header);
-        let mut result: Result<SetStatus> = Err(CacheError::KeyExists);
-        let _entry = self.memory.entry(key).and_compute_with(|maybe_entry| {
-            if let Some(entry) = maybe_entry {
-                let key_value = entry.into_value();
-                if SharedStoreState::cas_mismatch(&record, key_value.header.cas) {
-                    return Op::Nop;
-                }
-            }
-            let cas = self.store_state.set_cas_ttl(&mut record);
-            result = Ok(SetStatus { cas });
-            Op::Put(record)
-        });
-        result
+        self.reclaim_expired();
+        self.set_reclaimed(key, record)
     }
 
     fn delete(&self, key: KeyType, header: CacheMetaData) -> Result<Record> {
         let mut result: Result<Record> = Err(CacheError::NotFound);
         let _entry = self.memory.entry(key).and_compute_with(|maybe_entry| {
             if let Some(entry) = maybe_entry {
-                let record = entry.into_value();
-                let should_remove = header.cas == 0 || record.header.cas == header.cas;
+                let should_remove = header.cas == 0 || entry.value().header.cas == header.cas;
                 if should_remove {
-                    result = Ok(record);
+                    self.store_state
+                        .removed(entry.key(), RemovalReason::Explicit);
+                    result = Ok(entry.into_value());
+                    self.capacity.release(1);
                     return Op::Remove;
                 }
                 result = Err(CacheError::KeyExists);
@@ -150,17 +233,24 @@ impl Cache for MokaMemoryStore {
 
     fn flush(&self, header: CacheMetaData) {
         if header.time_to_live > 0 {
-            // FIXME!!!
-            // self.memory.iter().for_each(|re| {
-
-            // });
-            // self.memory.alter_all(|_key, mut value| {
-            //     value.header.time_to_live = header.time_to_live;
-            //     value
-            // });
+            let expiration = self.store_state.expiration(header.time_to_live);
+            self.memory.iter().for_each(|(key, _record)| {
+                self.memory
+                    .entry(key.as_ref().clone())
+                    .and_compute_with(|maybe_entry| match maybe_entry {
+                        Some(entry) => {
+                            let mut record = entry.into_value();
+                            record.header.time_to_live = expiration;
+                            Op::Put(record)
+                        }
+                        None => Op::Nop,
+                    });
+            });
         } else {
             self.memory.invalidate_all();
+            self.capacity.reset();
         }
+        self.store_state.flushed(header.time_to_live);
     }
 
     fn run_pending_tasks(&self) {
@@ -170,12 +260,26 @@ impl Cache for MokaMemoryStore {
     /// Adds a new key-value pair to the cache, but only if the key does not already exist.
     /// If the key exists, the operation fails with KeyExists error.
     fn add(&self, key: KeyType, mut record: Record) -> Result<SetStatus> {
+        self.reclaim_expired();
         let cas = self.store_state.set_cas_ttl(&mut record);
-        let entry = self.memory.entry(key).or_insert(record);
-        match entry.is_fresh() {
-            true => Ok(SetStatus { cas }),
-            false => Err(CacheError::KeyExists),
-        }
+        let mut result: Result<SetStatus> = Err(CacheError::KeyExists);
+        let _entry = self
+            .memory
+            .entry(key)
+            .and_compute_with(|maybe_entry| match maybe_entry {
+                Some(_entry) => Op::Nop,
+                None => match self.capacity.reserve(1) {
+                    Ok(()) => {
+                        result = Ok(SetStatus { cas });
+                        Op::Put(record)
+                    }
+                    Err(err) => {
+                        result = Err(err);
+                        Op::Nop
+                    }
+                },
+            });
+        result
     }
 
     /// Replaces the value of an existing key in the cache, but only if the key already exists.
@@ -187,11 +291,12 @@ impl Cache for MokaMemoryStore {
             .entry(key)
             .and_compute_with(|maybe_entry| match maybe_entry {
                 Some(entry) => {
-                    let cas = entry.into_value().header.cas;
+                    let cas = entry.value().header.cas;
                     if SharedStoreState::cas_mismatch(&record, cas) {
                         result = Err(CacheError::KeyExists);
                         Op::Nop
                     } else {
+                        self.store_state.overwritten(entry.key(), entry.value());
                         self.store_state.set_cas_ttl(&mut record);
                         result = Ok(SetStatus {
                             cas: record.header.cas,
@@ -228,13 +333,14 @@ impl Cache for MokaMemoryStore {
         increment: bool,
     ) -> Result<DeltaResult> {
         let cas = header.cas;
+        self.reclaim_expired();
         let mut result: Result<DeltaResult> = Err(CacheError::NotFound);
         let _entry = self
             .memory
             .entry(key)
             .and_compute_with(|maybe_entry| match maybe_entry {
                 Some(entry) => {
-                    let mut record = entry.into_value();
+                    let mut record = entry.value().clone();
                     let entry_cas = record.header.cas;
                     let tmp_record = Record::new(Bytes::new(), cas, 0, 0);
                     if SharedStoreState::cas_mismatch(&tmp_record, entry_cas) {
@@ -243,6 +349,7 @@ impl Cache for MokaMemoryStore {
                     } else {
                         match self.store_state.incr_decr_common(&record, delta, increment) {
                             Ok(new_value) => {
+                                self.store_state.overwritten(entry.key(), entry.value());
                                 let new_cas = self.store_state.get_cas_id();
                                 record.value = Bytes::from(new_value.to_string());
                                 record.header.cas = new_cas;
@@ -261,12 +368,16 @@ impl Cache for MokaMemoryStore {
                 }
                 None => {
                     if header.get_expiration() != DELTA_NO_INITIAL_VALUE {
+                        if let Err(err) = self.capacity.reserve(1) {
+                            result = Err(err);
+                            return Op::Nop;
+                        }
                         let cas = self.store_state.get_cas_id();
                         let record = Record::new(
                             Bytes::from(delta.value.to_string()),
                             cas,
                             0,
-                            header.get_expiration(),
+                            self.store_state.expiration(header.get_expiration()),
                         );
 
                         result = Ok(DeltaResult {
@@ -280,4 +391,126 @@ impl Cache for MokaMemoryStore {
             });
         result
     }
+
+    fn for_each_item(&self, f: &mut dyn FnMut(&KeyType, &Record)) {
+        self.memory.iter().for_each(|(key, record)| {
+            if !self.store_state.check_if_expired(&key, &record) {
+                f(&key, &record)
+            }
+        });
+    }
+
+    fn restore(&self, key: KeyType, record: Record) -> Result<()> {
+        self.reclaim_expired();
+        let mut result: Result<()> = Err(CacheError::KeyExists);
+        let _entry = self
+            .memory
+            .entry(key)
+            .and_compute_with(|maybe_entry| match maybe_entry {
+                // key was written after the snapshot was taken, it is newer
+                Some(_entry) => Op::Nop,
+                None => match self.capacity.reserve(1) {
+                    Ok(()) => {
+                        self.store_state.advance_cas_id(record.header.cas);
+                        result = Ok(());
+                        Op::Put(record)
+                    }
+                    Err(err) => {
+                        result = Err(err);
+                        Op::Nop
+                    }
+                },
+            });
+        result
+    }
+
+    fn relocate(&self, key: KeyType, record: Record) -> Result<()> {
+        let mut result: Result<()> = Err(CacheError::NotFound);
+        let _entry = self
+            .memory
+            .entry(key)
+            .and_compute_with(|maybe_entry| match maybe_entry {
+                Some(entry) if entry.value().header.cas == record.header.cas => {
+                    result = Ok(());
+                    Op::Put(Record {
+                        header: entry.into_value().header,
+                        value: record.value,
+                    })
+                }
+                Some(_entry) => {
+                    result = Err(CacheError::KeyExists);
+                    Op::Nop
+                }
+                None => Op::Nop,
+            });
+        result
+    }
+
+    fn add_stats(&self, stats: &mut CacheStats) {
+        self.store_state.add_stats(stats)
+    }
+
+    fn subscribe(&self, listener: SharedListener) {
+        self.store_state.subscribe(listener)
+    }
+
+    /// Moka has no batched reads, but a key repeated in the batch is
+    /// looked up once and expired items are removed after all lookups
+    fn get_multi(&self, keys: &[KeyType]) -> Vec<Result<Record>> {
+        let mut looked_up: HashMap<&KeyType, usize> = HashMap::with_capacity(keys.len());
+        let mut results: Vec<Result<Record>> = Vec::with_capacity(keys.len());
+        let mut expired = Vec::new();
+        for key in keys {
+            if let Some(&position) = looked_up.get(key) {
+                let result = match &results[position] {
+                    Ok(record) => Ok(record.clone()),
+                    Err(_) => Err(CacheError::NotFound),
+                };
+                results.push(result);
+                continue;
+            }
+            looked_up.insert(key, results.len());
+            results.push(match self.memory.get(key) {
+                Some(record) if self.store_state.check_if_expired(key, &record) => {
+                    expired.push(key);
+                    Err(CacheError::NotFound)
+                }
+                Some(record) => Ok(record),
+                None => Err(CacheError::NotFound),
+            });
+        }
+        for key in expired {
+            self.remove_expired(key);
+        }
+        results
+    }
+
+    /// Expired items are reclaimed once for the whole batch
+    fn set_multi(&self, items: Vec<(KeyType, Record)>) -> Vec<Result<SetStatus>> {
+        self.reclaim_expired();
+        items
+            .into_iter()
+            .map(|(key, record)| self.set_reclaimed(key, record))
+            .collect()
+    }
+
+    /// Moka does not expose its segments and cannot resume a walk, so
+    /// every page walks the cache and keeps only the items which come
+    /// next in the scan order, no state is kept between pages.
+    fn scan(&self, cursor: u64, count: usize, prefix: &[u8]) -> Result<ScanPage> {
+        self.store_state
+            .scan_segments(cursor, count, prefix, 1, |_segment, collector| {
+                for (key, record) in self.memory.iter() {
+                    if !self.store_state.check_if_expired(&key, &record) {
+                        collector.offer(self.hasher.hash_one(&*key), &key, &record);
+                    }
+                }
+            })
+    }
+}
+
+impl CacheCapability for MokaMemoryStore {
+    fn is_policy_supported(&self, policy: eviction_policy::EvictionPolicy) -> bool {
+        policy != eviction_policy::EvictionPolicy::Sieve
+    }
 }
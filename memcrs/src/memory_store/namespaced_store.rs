@@ -0,0 +1,434 @@
+use crate::cache::cache::{
+    Cache, CacheMetaData, CacheStats, DeltaParam, DeltaResult, KeyType, Record, ScanPage, SetStatus,
+};
+use crate::cache::error::{CacheError, Result};
+use crate::cache::events::SharedListener;
+use crate::memory_store::scan::STORE_CURSOR_BITS;
+use crate::memory_store::shared_store_state::SharedStoreState;
+
+use bytes::Bytes;
+use futures::future::BoxFuture;
+use std::sync::Arc;
+
+const STORE_CURSOR_MASK: u64 = (1 << STORE_CURSOR_BITS) - 1;
+/// store index has to fit in scan cursor bits above cursor of the store,
+/// index 0 is the default store
+pub const MAX_NAMESPACES: usize = (1 << (u64::BITS - STORE_CURSOR_BITS)) - 1;
+
+/// Keys starting with `prefix` are kept in a store of their own, limited
+/// by `quota`: number of items for moka and sieve, bytes for dash map and slab
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NamespaceConfig {
+    pub name: String,
+    pub prefix: Bytes,
+    pub quota: u64,
+    /// quota was given with a unit like `MiB`, so it is a size in bytes
+    pub quota_in_bytes: bool,
+}
+
+struct Namespace {
+    name: String,
+    prefix: Bytes,
+    store: Arc<dyn Cache + Send + Sync>,
+}
+
+/// Keyspace split by key prefix between independent stores.
+///
+/// Every namespace has its own quota and evicts on its own, so a namespace
+/// which is written heavily never evicts items of other namespaces. Keys
+/// without a namespace prefix go to the default store, a key belongs to
+/// the namespace with the longest matching prefix.
+///
+/// The key is the only thing a request carries to select a namespace by:
+/// there is no SASL user and all clients share one listener.
+pub struct NamespacedMemoryStore {
+    // default store first, namespaces by descending prefix length
+    stores: Vec<Arc<dyn Cache + Send + Sync>>,
+    namespaces: Vec<Namespace>,
+}
+
+impl NamespacedMemoryStore {
+    pub fn new(
+        default: Arc<dyn Cache + Send + Sync>,
+        namespaces: Vec<(NamespaceConfig, Arc<dyn Cache + Send + Sync>)>,
+    ) -> NamespacedMemoryStore {
+        assert!(
+            namespaces.len() <= MAX_NAMESPACES,
+            "At most {} namespaces are supported",
+            MAX_NAMESPACES
+        );
+        let mut namespaces: Vec<Namespace> = namespaces
+            .into_iter()
+            .map(|(config, store)| Namespace {
+                name: config.name,
+                prefix: config.prefix,
+                store,
+            })
+            .collect();
+        namespaces.sort_by_key(|namespace| std::cmp::Reverse(namespace.prefix.len()));
+        let stores = std::iter::once(default)
+            .chain(namespaces.iter().map(|namespace| namespace.store.clone()))
+            .collect();
+        NamespacedMemoryStore { stores, namespaces }
+    }
+
+    /// Returns index of the store owning the key, 0 is the default store
+    fn store_for(&self, key: &KeyType) -> usize {
+        self.namespaces
+            .iter()
+            .position(|namespace| key.starts_with(&namespace.prefix))
+            .map_or(0, |index| index + 1)
+    }
+
+    fn store(&self, key: &KeyType) -> &(dyn Cache + Send + Sync) {
+        self.stores[self.store_for(key)].as_ref()
+    }
+}
+
+impl Cache for NamespacedMemoryStore {
+    fn get(&self, key: &KeyType) -> Result<Record> {
+        self.store(key).get(key)
+    }
+
+    fn get_async<'a>(&'a self, key: &'a KeyType) -> BoxFuture<'a, Result<Record>> {
+        self.store(key).get_async(key)
+    }
+
+    fn reads_outside_memory(&self) -> bool {
+        self.stores.iter().any(|store| store.reads_outside_memory())
+    }
+
+    fn set(&self, key: KeyType, record: Record) -> Result<SetStatus> {
+        self.store(&key).set(key, record)
+    }
+
+    fn delete(&self, key: KeyType, header: CacheMetaData) -> Result<Record> {
+        self.store(&key).delete(key, header)
+    }
+
+    fn flush(&self, header: CacheMetaData) {
+        self.stores
+            .iter()
+            .for_each(|store| store.flush(header.clone()));
+    }
+
+    fn run_pending_tasks(&self) {
+        self.stores
+            .iter()
+            .for_each(|store| store.run_pending_tasks());
+    }
+
+    fn add(&self, key: KeyType, record: Record) -> Result<SetStatus> {
+        self.store(&key).add(key, record)
+    }
+
+    fn replace(&self, key: KeyType, record: Record) -> Result<SetStatus> {
+        self.store(&key).replace(key, record)
+    }
+
+    fn append(&self, key: KeyType, new_record: Record) -> Result<SetStatus> {
+        self.store(&key).append(key, new_record)
+    }
+
+    fn prepend(&self, key: KeyType, new_record: Record) -> Result<SetStatus> {
+        self.store(&key).prepend(key, new_record)
+    }
+
+    fn incr_decr(
+        &self,
+        header: CacheMetaData,
+        key: KeyType,
+        delta: DeltaParam,
+        increment: bool,
+    ) -> Result<DeltaResult> {
+        self.store(&key).incr_decr(header, key, delta, increment)
+    }
+
+    fn for_each_item(&self, f: &mut dyn FnMut(&KeyType, &Record)) {
+        self.stores.iter().for_each(|store| store.for_each_item(f));
+    }
+
+    fn restore(&self, key: KeyType, record: Record) -> Result<()> {
+        self.store(&key).restore(key, record)
+    }
+
+    fn relocate(&self, key: KeyType, record: Record) -> Result<()> {
+        self.store(&key).relocate(key, record)
+    }
+
+    /// Counters of all stores are summed up, every namespace is
+    /// also reported on its own
+    fn add_stats(&self, stats: &mut CacheStats) {
+        self.stores.iter().for_each(|store| store.add_stats(stats));
+        for namespace in &self.namespaces {
+            let mut namespace_stats = CacheStats::default();
+            namespace.store.add_stats(&mut namespace_stats);
+            stats
+                .namespaces
+                .push((namespace.name.clone(), namespace_stats));
+        }
+    }
+
+    /// Flush reaches every store, so listeners get a flush event
+    /// from each of them
+    fn subscribe(&self, listener: SharedListener) {
+        self.stores
+            .iter()
+            .for_each(|store| store.subscribe(listener.clone()));
+    }
+
+    /// Stores are scanned one after another, the store is kept in
+    /// bits of the cursor above the cursor of the store
+    fn scan(&self, cursor: u64, count: usize, prefix: &[u8]) -> Result<ScanPage> {
+        let count = count.max(1);
+        let mut store = (cursor >> STORE_CURSOR_BITS) as usize;
+        let mut store_cursor = cursor & STORE_CURSOR_MASK;
+        if store >= self.stores.len() {
+            return Err(CacheError::InvalidArguments);
+        }
+        let mut items = Vec::new();
+        while store < self.stores.len() && items.len() < count {
+            let page = self.stores[store].scan(store_cursor, count - items.len(), prefix)?;
+            items.extend(page.items);
+            store_cursor = page.cursor;
+            if store_cursor == 0 {
+                store += 1;
+            }
+        }
+        let cursor = if store < self.stores.len() {
+            ((store as u64) << STORE_CURSOR_BITS) | store_cursor
+        } else {
+            0
+        };
+        Ok(ScanPage { items, cursor })
+    }
+
+    /// Every store gets one call with its keys
+    fn get_multi(&self, keys: &[KeyType]) -> Vec<Result<Record>> {
+        let mut results: Vec<Result<Record>> =
+            keys.iter().map(|_| Err(CacheError::NotFound)).collect();
+        for (store, positions) in
+            SharedStoreState::group_by_shard(keys.iter(), |key| self.store_for(key))
+        {
+            let store_keys: Vec<KeyType> = positions
+                .iter()
+                .map(|&position| keys[position].clone())
+                .collect();
+            let store_results = self.stores[store].get_multi(&store_keys);
+            for (position, result) in positions.into_iter().zip(store_results) {
+                results[position] = result;
+            }
+        }
+        results
+    }
+
+    fn set_multi(&self, items: Vec<(KeyType, Record)>) -> Vec<Result<SetStatus>> {
+        let groups = SharedStoreState::group_by_shard(items.iter().map(|(key, _)| key), |key| {
+            self.store_for(key)
+        });
+        let mut items: Vec<Option<(KeyType, Record)>> = items.into_iter().map(Some).collect();
+        let mut results: Vec<Result<SetStatus>> =
+            items.iter().map(|_| Err(CacheError::NotFound)).collect();
+        for (store, positions) in groups {
+            let store_items = positions
+                .iter()
+                .filter_map(|&position| items[position].take())
+                .collect();
+            let store_results = self.stores[store].set_multi(store_items);
+            for (position, result) in positions.into_iter().zip(store_results) {
+                results[position] = result;
+            }
+        }
+        results
+    }
+
+    fn delete_multi(&self, items: Vec<(KeyType, CacheMetaData)>) -> Vec<Result<Record>> {
+        let groups = SharedStoreState::group_by_shard(items.iter().map(|(key, _)| key), |key| {
+            self.store_for(key)
+        });
+        let mut items: Vec<Option<(KeyType, CacheMetaData)>> =
+            items.into_iter().map(Some).collect();
+        let mut results: Vec<Result<Record>> =
+            items.iter().map(|_| Err(CacheError::NotFound)).collect();
+        for (store, positions) in groups {
+            let store_items = positions
+                .iter()
+                .filter_map(|&position| items[position].take())
+                .collect();
+            let store_results = self.stores[store].delete_multi(store_items);
+            for (position, result) in positions.into_iter().zip(store_results) {
+                results[position] = result;
+            }
+        }
+        results
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memcache::cli::parser::{DashMapConfig, SieveConfig};
+    use crate::memory_store::dash_map_store::DashMapMemoryStore;
+    use crate::memory_store::sieve_store::SieveMemoryStore;
+    use crate::mock::mock_server::MockSystemTimer;
+
+    fn namespace(name: &str, prefix: &str) -> NamespaceConfig {
+        NamespaceConfig {
+            name: name.to_string(),
+            prefix: Bytes::from(prefix.to_string()),
+            quota: 0,
+            quota_in_bytes: false,
+        }
+    }
+
+    fn create_store(
+        prefixes: &[&str],
+    ) -> (NamespacedMemoryStore, Vec<Arc<dyn Cache + Send + Sync>>) {
+        let timer = Arc::new(MockSystemTimer::new());
+        let stores: Vec<Arc<dyn Cache + Send + Sync>> = (0..=prefixes.len())
+            .map(|_| {
+                Arc::new(DashMapMemoryStore::new(
+                    timer.clone(),
+                    DashMapConfig::default(),
+                )) as Arc<dyn Cache + Send + Sync>
+            })
+            .collect();
+        let namespaces = prefixes
+            .iter()
+            .zip(&stores[1..])
+            .map(|(prefix, store)| (namespace(prefix, prefix), store.clone()))
+            .collect();
+        (
+            NamespacedMemoryStore::new(stores[0].clone(), namespaces),
+            stores,
+        )
+    }
+
+    fn set(store: &NamespacedMemoryStore, key: &str) {
+        let record = Record::new(Bytes::from("value"), 0, 0, 0);
+        assert!(store.set(Bytes::from(key.to_string()), record).is_ok());
+    }
+
+    #[test]
+    fn test_key_is_stored_in_namespace_with_longest_prefix() {
+        let (store, stores) = create_store(&["a:", "a:b:"]);
+        set(&store, "a:key");
+        set(&store, "a:b:key");
+        set(&store, "key");
+        for (key, owner) in [("key", 0), ("a:key", 1), ("a:b:key", 2)] {
+            let key = Bytes::from(key);
+            for (index, namespace_store) in stores.iter().enumerate() {
+                assert_eq!(namespace_store.get(&key).is_ok(), index == owner);
+            }
+            assert!(store.get(&key).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_multi_ops_keep_order_across_namespaces() {
+        let (store, stores) = create_store(&["a:", "b:"]);
+        let keys: Vec<KeyType> = ["a:1", "x", "b:1", "a:2", "y", "b:2"]
+            .iter()
+            .map(|key| Bytes::from(key.to_string()))
+            .collect();
+        let items = keys
+            .iter()
+            .map(|key| (key.clone(), Record::new(key.clone(), 0, 0, 0)))
+            .collect();
+        assert!(store.set_multi(items).iter().all(|result| result.is_ok()));
+        assert!(stores[1].get(&Bytes::from("a:2")).is_ok());
+
+        let results = store.get_multi(&keys);
+        for (key, result) in keys.iter().zip(&results) {
+            assert_eq!(&result.as_ref().unwrap().value, key);
+        }
+        let deletes = keys
+            .iter()
+            .map(|key| (key.clone(), CacheMetaData::new(0, 0, 0)))
+            .collect();
+        for (key, result) in keys.iter().zip(store.delete_multi(deletes)) {
+            assert_eq!(&result.unwrap().value, key);
+        }
+    }
+
+    #[test]
+    fn test_scan_covers_all_namespaces() {
+        let (store, _stores) = create_store(&["a:", "b:"]);
+        for idx in 0..30 {
+            set(&store, &format!("a:{}", idx));
+            set(&store, &format!("b:{}", idx));
+            set(&store, &format!("{}", idx));
+        }
+        let mut keys = std::collections::HashSet::new();
+        let mut cursor = 0;
+        loop {
+            let page = store.scan(cursor, 7, b"").unwrap();
+            for (key, _record) in page.items {
+                assert!(keys.insert(key));
+            }
+            if page.cursor == 0 {
+                break;
+            }
+            cursor = page.cursor;
+        }
+        assert_eq!(keys.len(), 90);
+        assert_eq!(
+            store.scan(3 << STORE_CURSOR_BITS, 7, b"").unwrap_err(),
+            CacheError::InvalidArguments
+        );
+    }
+
+    #[test]
+    fn test_full_namespace_evicts_only_its_own_items() {
+        let timer = Arc::new(MockSystemTimer::new());
+        let sieve = |max_capacity| {
+            Arc::new(SieveMemoryStore::new(
+                timer.clone(),
+                SieveConfig {
+                    max_capacity,
+                    ..Default::default()
+                },
+            )) as Arc<dyn Cache + Send + Sync>
+        };
+        let (default_store, namespace_store) = (sieve(1000), sieve(16));
+        let store = NamespacedMemoryStore::new(
+            default_store.clone(),
+            vec![(namespace("a:", "a:"), namespace_store.clone())],
+        );
+        for idx in 0..20 {
+            set(&store, &format!("{}", idx));
+        }
+        for idx in 0..200 {
+            set(&store, &format!("a:{}", idx));
+        }
+
+        for idx in 0..20 {
+            assert!(store.get(&Bytes::from(format!("{}", idx))).is_ok());
+        }
+        let mut items = 0;
+        namespace_store.for_each_item(&mut |_key, _record| items += 1);
+        assert!(items <= 16);
+        let mut stats = CacheStats::default();
+        store.add_stats(&mut stats);
+        assert_eq!(stats.events.unwrap().evictions, 200 - items);
+        let (_name, namespace_stats) = &stats.namespaces[0];
+        assert_eq!(namespace_stats.events.unwrap().evictions, 200 - items);
+    }
+
+    #[test]
+    fn test_stats_are_reported_per_namespace() {
+        let (store, _stores) = create_store(&["a:"]);
+        set(&store, "a:key");
+        set(&store, "a:key");
+        set(&store, "key");
+        set(&store, "key");
+        set(&store, "key");
+        let mut stats = CacheStats::default();
+        store.add_stats(&mut stats);
+        assert_eq!(stats.events.unwrap().replacements, 3);
+        assert_eq!(stats.namespaces.len(), 1);
+        let (name, namespace_stats) = &stats.namespaces[0];
+        assert_eq!(name, "a:");
+        assert_eq!(namespace_stats.events.unwrap().replacements, 1);
+    }
+}
@@ -0,0 +1,636 @@
+use crate::cache::cache::{
+    Cache, CacheMetaData, CacheStats, DeltaParam, DeltaResult, KeyType, Record, ScanPage, SetStatus,
+};
+use crate::cache::error::{CacheError, Result};
+use crate::cache::events::{RemovalReason, SharedListener};
+use crate::memcache::cli::parser::DashMapConfig;
+use crate::memory_store::capacity_limit::CapacityLimit;
+use crate::memory_store::scan::STORE_CURSOR_BITS;
+use crate::memory_store::shared_store_state::SharedStoreState;
+use crate::protocol::binary::network::DELTA_NO_INITIAL_VALUE;
+use crate::server::timer;
+
+use bytes::{Bytes, BytesMut};
+use std::cell::RefCell;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, RandomState};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+const PARTITION_CURSOR_MASK: u64 = (1 << STORE_CURSOR_BITS) - 1;
+/// partition index has to fit in scan cursor bits above partition cursor
+const MAX_PARTITIONS: usize = 1 << (u64::BITS - STORE_CURSOR_BITS);
+
+/// digits of u64::MAX, the longest value incr/decr can store
+const DELTA_VALUE_MAX_LEN: u64 = 20;
+
+/// Partitioned stores are told apart by id in partitions a thread owns
+static NEXT_STORE_ID: AtomicU64 = AtomicU64::new(0);
+
+thread_local! {
+    /// Partitions owned by the current thread by store id
+    static OWNED: RefCell<HashMap<u64, Partition>> = RefCell::new(HashMap::new());
+}
+
+#[inline]
+fn item_size(key: &KeyType, record: &Record) -> u64 {
+    (key.len() + record.len()) as u64
+}
+
+/// Keyspace hash-partitioned between worker threads, shared-nothing.
+///
+/// Used by partitioned keyspace mode where every worker thread owns one
+/// partition. Items of a partition are kept in a plain hash map of the
+/// thread owning it, there are no locks on the request path. Requests
+/// for keys owned by other workers are forwarded to them before they
+/// reach the store, a store call for a key the calling thread does not
+/// own fails with InternalError. Flush, iteration and scan only see the
+/// partition of the calling thread, the router sends them to the owners.
+/// Like the dash-map store partitions never evict, with
+/// `reject_when_full` writes over the memory limit of the partition
+/// are rejected with OutOfMemory.
+pub struct PartitionedMemoryStore {
+    id: u64,
+    hasher: RandomState,
+    /// config of a single partition, limits are already divided
+    config: DashMapConfig,
+    /// state of every partition, written by its owner only
+    states: Vec<Arc<SharedStoreState>>,
+}
+
+impl PartitionedMemoryStore {
+    /// `config` is the config of a single partition
+    pub fn new(
+        timer: Arc<dyn timer::Timer + Send + Sync>,
+        config: DashMapConfig,
+        partitions: usize,
+    ) -> PartitionedMemoryStore {
+        assert!(partitions > 0, "At least one partition is required");
+        assert!(
+            partitions <= MAX_PARTITIONS,
+            "At most {} partitions are supported",
+            MAX_PARTITIONS
+        );
+        PartitionedMemoryStore {
+            id: NEXT_STORE_ID.fetch_add(1, Ordering::Relaxed),
+            hasher: RandomState::new(),
+            config,
+            states: (0..partitions)
+                .map(|_| Arc::new(SharedStoreState::new(timer.clone())))
+                .collect(),
+        }
+    }
+
+    /// Makes the calling thread the owner of partition `index`, only
+    /// the owner reads and writes items of the partition
+    pub fn attach(&self, index: usize) {
+        let partition = Partition {
+            index,
+            items: HashMap::new(),
+            state: self.states[index].clone(),
+            capacity: match self.config.reject_when_full {
+                true => CapacityLimit::bounded(self.config.memory_limit),
+                false => CapacityLimit::unbounded(),
+            },
+        };
+        OWNED.with(|owned| owned.borrow_mut().insert(self.id, partition));
+    }
+
+    /// Returns index of a partition which owns the key
+    pub fn partition_for(&self, key: &KeyType) -> usize {
+        (self.hasher.hash_one(key) as usize) % self.states.len()
+    }
+
+    /// Returns index of a partition a scan continues in
+    pub fn scan_partition(&self, cursor: u64) -> usize {
+        (cursor >> STORE_CURSOR_BITS) as usize
+    }
+
+    pub fn partitions(&self) -> usize {
+        self.states.len()
+    }
+
+    /// Runs `f` on partition `index` if it is owned by the calling thread
+    fn with_partition<R>(
+        &self,
+        index: usize,
+        f: impl FnOnce(&mut Partition) -> Result<R>,
+    ) -> Result<R> {
+        OWNED.with(|owned| match owned.borrow_mut().get_mut(&self.id) {
+            Some(partition) if partition.index == index => f(partition),
+            _ => {
+                debug!("Partition {} is not owned by the calling thread", index);
+                Err(CacheError::InternalError)
+            }
+        })
+    }
+
+    /// Runs `f` on the partition of the calling thread, if it owns one
+    fn with_local(&self, f: impl FnOnce(&mut Partition)) {
+        OWNED.with(|owned| {
+            if let Some(partition) = owned.borrow_mut().get_mut(&self.id) {
+                f(partition)
+            }
+        })
+    }
+}
+
+/// Items of one partition, only ever touched by the thread owning it
+struct Partition {
+    index: usize,
+    items: HashMap<KeyType, Record>,
+    state: Arc<SharedStoreState>,
+    capacity: CapacityLimit,
+}
+
+impl Partition {
+    /// Drops expired items before a write of `amount` bytes is rejected,
+    /// items nobody reads again would hold the memory forever otherwise
+    fn reclaim_expired(&mut self, amount: u64) {
+        if !self.capacity.should_reclaim(amount, self.state.timestamp()) {
+            return;
+        }
+        let (state, capacity) = (&self.state, &self.capacity);
+        self.items.retain(|key, record| {
+            if !state.check_if_expired(key, record) {
+                return true;
+            }
+            capacity.release(item_size(key, record));
+            state.dropped(key, record, RemovalReason::Expired);
+            false
+        });
+    }
+
+    fn get(&mut self, key: &KeyType) -> Result<Record> {
+        let record = self.items.get(key).ok_or(CacheError::NotFound)?;
+        if !self.state.check_if_expired(key, record) {
+            return Ok(record.clone());
+        }
+        if let Some((key, record)) = self.items.remove_entry(key) {
+            self.capacity.release(item_size(&key, &record));
+            self.state.dropped(&key, &record, RemovalReason::Expired);
+        }
+        Err(CacheError::NotFound)
+    }
+
+    /// Overwrites `stored` with `record` unless the CAS sent with it
+    /// does not match
+    fn overwrite(
+        state: &SharedStoreState,
+        capacity: &CapacityLimit,
+        key: &KeyType,
+        stored: &mut Record,
+        mut record: Record,
+    ) -> Result<SetStatus> {
+        if SharedStoreState::cas_mismatch(&record, stored.header.cas) {
+            return Err(CacheError::KeyExists);
+        }
+        capacity.resize(item_size(key, stored), item_size(key, &record))?;
+        state.overwritten(key, stored);
+        let cas = state.set_cas_ttl(&mut record);
+        *stored = record;
+        Ok(SetStatus { cas })
+    }
+
+    fn set(&mut self, key: KeyType, mut record: Record) -> Result<SetStatus> {
+        self.reclaim_expired(item_size(&key, &record));
+        match self.items.get_mut(&key) {
+            Some(stored) => Self::overwrite(&self.state, &self.capacity, &key, stored, record),
+            None => {
+                self.capacity.reserve(item_size(&key, &record))?;
+                let cas = self.state.set_cas_ttl(&mut record);
+                self.items.insert(key, record);
+                Ok(SetStatus { cas })
+            }
+        }
+    }
+
+    fn delete(&mut self, key: &KeyType, header: CacheMetaData) -> Result<Record> {
+        let stored = self.items.get(key).ok_or(CacheError::NotFound)?;
+        if header.cas != 0 && stored.header.cas != header.cas {
+            return Err(CacheError::KeyExists);
+        }
+        let (key, record) = self.items.remove_entry(key).ok_or(CacheError::NotFound)?;
+        self.capacity.release(item_size(&key, &record));
+        self.state.removed(&key, RemovalReason::Explicit);
+        Ok(record)
+    }
+
+    fn flush(&mut self, header: CacheMetaData) {
+        if header.time_to_live > 0 {
+            for record in self.items.values_mut() {
+                self.state.update_ttl(record, header.time_to_live);
+            }
+        } else {
+            self.items.clear();
+            self.capacity.reset();
+        }
+        self.state.flushed(header.time_to_live);
+    }
+
+    fn add(&mut self, key: KeyType, mut record: Record) -> Result<SetStatus> {
+        self.reclaim_expired(item_size(&key, &record));
+        match self.items.entry(key) {
+            Entry::Occupied(_) => Err(CacheError::KeyExists),
+            Entry::Vacant(entry) => {
+                self.capacity.reserve(item_size(entry.key(), &record))?;
+                let cas = self.state.set_cas_ttl(&mut record);
+                entry.insert(record);
+                Ok(SetStatus { cas })
+            }
+        }
+    }
+
+    fn replace(&mut self, key: KeyType, record: Record) -> Result<SetStatus> {
+        self.reclaim_expired(item_size(&key, &record));
+        match self.items.get_mut(&key) {
+            Some(stored) => Self::overwrite(&self.state, &self.capacity, &key, stored, record),
+            None => Err(CacheError::NotFound),
+        }
+    }
+
+    fn append_prepend_common(
+        &mut self,
+        key: KeyType,
+        mut new_record: Record,
+        is_append: bool,
+    ) -> Result<SetStatus> {
+        self.reclaim_expired(item_size(&key, &new_record));
+        let cas = new_record.header.cas;
+        let prev_record = self.items.get_mut(&key).ok_or(CacheError::ItemNotStored)?;
+        if cas != 0 && prev_record.header.cas != cas {
+            return Err(CacheError::KeyExists);
+        }
+        let new_cas = self.state.set_cas_ttl(&mut new_record);
+        let mut new_value =
+            BytesMut::with_capacity(prev_record.value.len() + new_record.value.len());
+        if is_append {
+            new_value.extend_from_slice(&prev_record.value);
+            new_value.extend_from_slice(&new_record.value);
+        } else {
+            new_value.extend_from_slice(&new_record.value);
+            new_value.extend_from_slice(&prev_record.value);
+        }
+        new_record.value = new_value.freeze();
+        self.capacity
+            .resize(item_size(&key, prev_record), item_size(&key, &new_record))?;
+        self.state.overwritten(&key, prev_record);
+        *prev_record = new_record;
+        Ok(SetStatus { cas: new_cas })
+    }
+
+    fn incr_decr(
+        &mut self,
+        header: CacheMetaData,
+        key: KeyType,
+        delta: DeltaParam,
+        increment: bool,
+    ) -> Result<DeltaResult> {
+        self.reclaim_expired(key.len() as u64 + DELTA_VALUE_MAX_LEN);
+        match self.items.get_mut(&key) {
+            Some(record) => {
+                let delta_value = self.state.incr_decr_common(record, delta, increment)?;
+                let tmp_record = Record::new(Bytes::new(), header.cas, 0, 0);
+                if SharedStoreState::cas_mismatch(&tmp_record, record.header.cas) {
+                    return Err(CacheError::KeyExists);
+                }
+                let new_value = Bytes::from(delta_value.to_string());
+                let key_len = key.len() as u64;
+                self.capacity.resize(
+                    key_len + record.len() as u64,
+                    key_len + (record.header.len() + new_value.len()) as u64,
+                )?;
+                self.state.overwritten(&key, record);
+                let new_cas = self.state.get_cas_id();
+                record.value = new_value;
+                record.header.cas = new_cas;
+                Ok(DeltaResult {
+                    value: delta_value,
+                    cas: new_cas,
+                })
+            }
+            None if header.get_expiration() != DELTA_NO_INITIAL_VALUE => {
+                let cas = self.state.get_cas_id();
+                let record = Record::new(
+                    Bytes::from(delta.value.to_string()),
+                    cas,
+                    0,
+                    self.state.expiration(header.get_expiration()),
+                );
+                self.capacity.reserve(item_size(&key, &record))?;
+                self.items.insert(key, record);
+                Ok(DeltaResult {
+                    cas,
+                    value: delta.value,
+                })
+            }
+            None => Err(CacheError::NotFound),
+        }
+    }
+
+    fn restore(&mut self, key: KeyType, record: Record) -> Result<()> {
+        self.reclaim_expired(item_size(&key, &record));
+        match self.items.entry(key) {
+            // key was written after the snapshot was taken, it is newer
+            Entry::Occupied(_) => Err(CacheError::KeyExists),
+            Entry::Vacant(entry) => {
+                self.capacity.reserve(item_size(entry.key(), &record))?;
+                self.state.advance_cas_id(record.header.cas);
+                entry.insert(record);
+                Ok(())
+            }
+        }
+    }
+
+    fn relocate(&mut self, key: KeyType, record: Record) -> Result<()> {
+        let stored = self.items.get_mut(&key).ok_or(CacheError::NotFound)?;
+        if stored.header.cas != record.header.cas {
+            return Err(CacheError::KeyExists);
+        }
+        let moved = Record {
+            header: stored.header.clone(),
+            value: record.value,
+        };
+        self.capacity
+            .resize(item_size(&key, stored), item_size(&key, &moved))?;
+        *stored = moved;
+        Ok(())
+    }
+}
+
+impl Cache for PartitionedMemoryStore {
+    fn get(&self, key: &KeyType) -> Result<Record> {
+        self.with_partition(self.partition_for(key), |partition| partition.get(key))
+    }
+
+    fn set(&self, key: KeyType, record: Record) -> Result<SetStatus> {
+        self.with_partition(self.partition_for(&key), |partition| {
+            partition.set(key, record)
+        })
+    }
+
+    fn delete(&self, key: KeyType, header: CacheMetaData) -> Result<Record> {
+        self.with_partition(self.partition_for(&key), |partition| {
+            partition.delete(&key, header)
+        })
+    }
+
+    /// Flushes the partition of the calling thread only
+    fn flush(&self, header: CacheMetaData) {
+        self.with_local(|partition| partition.flush(header))
+    }
+
+    fn run_pending_tasks(&self) {}
+
+    fn add(&self, key: KeyType, record: Record) -> Result<SetStatus> {
+        self.with_partition(self.partition_for(&key), |partition| {
+            partition.add(key, record)
+        })
+    }
+
+    fn replace(&self, key: KeyType, record: Record) -> Result<SetStatus> {
+        self.with_partition(self.partition_for(&key), |partition| {
+            partition.replace(key, record)
+        })
+    }
+
+    fn append(&self, key: KeyType, new_record: Record) -> Result<SetStatus> {
+        self.with_partition(self.partition_for(&key), |partition| {
+            partition.append_prepend_common(key, new_record, true)
+        })
+    }
+
+    fn prepend(&self, key: KeyType, new_record: Record) -> Result<SetStatus> {
+        self.with_partition(self.partition_for(&key), |partition| {
+            partition.append_prepend_common(key, new_record, false)
+        })
+    }
+
+    fn incr_decr(
+        &self,
+        header: CacheMetaData,
+        key: KeyType,
+        delta: DeltaParam,
+        increment: bool,
+    ) -> Result<DeltaResult> {
+        self.with_partition(self.partition_for(&key), |partition| {
+            partition.incr_decr(header, key, delta, increment)
+        })
+    }
+
+    /// Iterates the partition of the calling thread only
+    fn for_each_item(&self, f: &mut dyn FnMut(&KeyType, &Record)) {
+        // items are copied out, `f` may call back into the store
+        let mut items = Vec::new();
+        self.with_local(|partition| {
+            items.extend(
+                partition
+                    .items
+                    .iter()
+                    .filter(|(key, record)| !partition.state.check_if_expired(key, record))
+                    .map(|(key, record)| (key.clone(), record.clone())),
+            )
+        });
+        items.iter().for_each(|(key, record)| f(key, record));
+    }
+
+    fn restore(&self, key: KeyType, record: Record) -> Result<()> {
+        self.with_partition(self.partition_for(&key), |partition| {
+            partition.restore(key, record)
+        })
+    }
+
+    fn relocate(&self, key: KeyType, record: Record) -> Result<()> {
+        self.with_partition(self.partition_for(&key), |partition| {
+            partition.relocate(key, record)
+        })
+    }
+
+    fn add_stats(&self, stats: &mut CacheStats) {
+        self.states.iter().for_each(|state| state.add_stats(stats));
+    }
+
+    /// Every partition is flushed by its owner, so listeners get a flush
+    /// event from each of them
+    fn subscribe(&self, listener: SharedListener) {
+        self.states
+            .iter()
+            .for_each(|state| state.subscribe(listener.clone()));
+    }
+
+    /// Scans the partition the cursor is in, which has to be the partition
+    /// of the calling thread. The partition is kept in bits of the cursor
+    /// above the cursor of the partition, once it is done the cursor
+    /// points to the start of the next partition.
+    fn scan(&self, cursor: u64, count: usize, prefix: &[u8]) -> Result<ScanPage> {
+        let index = self.scan_partition(cursor);
+        if index >= self.partitions() {
+            return Err(CacheError::InvalidArguments);
+        }
+        let mut page = self.with_partition(index, |partition| {
+            partition.state.scan_segments(
+                cursor & PARTITION_CURSOR_MASK,
+                count,
+                prefix,
+                1,
+                |_segment, collector| {
+                    for (key, record) in &partition.items {
+                        if !partition.state.check_if_expired(key, record) {
+                            collector.offer(self.hasher.hash_one(key), key, record);
+                        }
+                    }
+                },
+            )
+        })?;
+        page.cursor = match page.cursor {
+            0 if index + 1 < self.partitions() => ((index + 1) as u64) << STORE_CURSOR_BITS,
+            0 => 0,
+            partition_cursor => ((index as u64) << STORE_CURSOR_BITS) | partition_cursor,
+        };
+        Ok(page)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::mock_server::MockSystemTimer;
+
+    fn create_store(partitions: usize, local: usize) -> PartitionedMemoryStore {
+        let store = PartitionedMemoryStore::new(
+            Arc::new(MockSystemTimer::new()),
+            DashMapConfig::default(),
+            partitions,
+        );
+        store.attach(local);
+        store
+    }
+
+    fn keys_of(store: &PartitionedMemoryStore, partition: usize, count: usize) -> Vec<KeyType> {
+        (0..)
+            .map(|idx| Bytes::from(format!("key{}", idx)))
+            .filter(|key| store.partition_for(key) == partition)
+            .take(count)
+            .collect()
+    }
+
+    #[test]
+    fn test_only_owner_reads_and_writes_partition() {
+        let store = create_store(4, 1);
+        for key in keys_of(&store, 1, 16) {
+            let record = Record::new(Bytes::from("value"), 0, 0, 0);
+            assert!(store.set(key.clone(), record).is_ok());
+            assert!(store.get(&key).is_ok());
+        }
+        for partition in [0, 2, 3] {
+            let key = keys_of(&store, partition, 1).remove(0);
+            let record = Record::new(Bytes::from("value"), 0, 0, 0);
+            assert_eq!(
+                store.set(key.clone(), record).err(),
+                Some(CacheError::InternalError)
+            );
+            assert_eq!(store.get(&key), Err(CacheError::InternalError));
+        }
+    }
+
+    #[test]
+    fn test_partitions_are_owned_per_thread() {
+        let store = Arc::new(create_store(2, 0));
+        let key = keys_of(&store, 0, 1).remove(0);
+        let record = Record::new(Bytes::from("value"), 0, 0, 0);
+        assert!(store.set(key.clone(), record).is_ok());
+
+        let other = store.clone();
+        let other_key = keys_of(&store, 1, 1).remove(0);
+        std::thread::spawn(move || {
+            // owner of partition 0 is another thread
+            assert_eq!(other.get(&key), Err(CacheError::InternalError));
+            other.attach(1);
+            let record = Record::new(Bytes::from("value"), 0, 0, 0);
+            assert!(other.set(other_key.clone(), record).is_ok());
+            assert!(other.get(&other_key).is_ok());
+        })
+        .join()
+        .unwrap();
+    }
+
+    #[test]
+    fn test_scan_moves_to_next_partition() {
+        let store = create_store(4, 2);
+        let keys = keys_of(&store, 2, 20);
+        for key in &keys {
+            let record = Record::new(Bytes::from("value"), 0, 0, 0);
+            assert!(store.set(key.clone(), record).is_ok());
+        }
+        let mut scanned = std::collections::HashSet::new();
+        let mut cursor = 2 << STORE_CURSOR_BITS;
+        while store.scan_partition(cursor) == 2 {
+            let page = store.scan(cursor, 7, b"").unwrap();
+            assert!(page.items.len() <= 7);
+            for (key, _record) in page.items {
+                assert!(scanned.insert(key));
+            }
+            cursor = page.cursor;
+        }
+        assert_eq!(scanned.len(), keys.len());
+        assert_eq!(cursor, 3 << STORE_CURSOR_BITS);
+        // partition 3 is owned by another thread
+        assert_eq!(
+            store.scan(cursor, 7, b"").err(),
+            Some(CacheError::InternalError)
+        );
+        assert_eq!(
+            store.scan(4 << STORE_CURSOR_BITS, 7, b"").err(),
+            Some(CacheError::InvalidArguments)
+        );
+    }
+
+    #[test]
+    fn test_scan_of_last_partition_ends_scan() {
+        let store = create_store(2, 1);
+        for key in keys_of(&store, 1, 5) {
+            let record = Record::new(Bytes::from("value"), 0, 0, 0);
+            assert!(store.set(key, record).is_ok());
+        }
+        let page = store.scan(1 << STORE_CURSOR_BITS, 10, b"").unwrap();
+        assert_eq!(page.items.len(), 5);
+        assert_eq!(page.cursor, 0);
+    }
+
+    #[test]
+    fn test_flush_clears_local_partition() {
+        let store = create_store(4, 0);
+        let keys = keys_of(&store, 0, 16);
+        for key in &keys {
+            let record = Record::new(Bytes::from("value"), 0, 0, 0);
+            assert!(store.set(key.clone(), record).is_ok());
+        }
+        store.flush(CacheMetaData::new(0, 0, 0));
+        for key in &keys {
+            assert_eq!(store.get(key), Err(CacheError::NotFound));
+        }
+    }
+
+    #[test]
+    fn test_memory_limit_is_kept_per_partition() {
+        let store = PartitionedMemoryStore::new(
+            Arc::new(MockSystemTimer::new()),
+            DashMapConfig {
+                memory_limit: 64,
+                reject_when_full: true,
+            },
+            2,
+        );
+        store.attach(0);
+        let keys = keys_of(&store, 0, 2);
+        let value = Bytes::from(vec![b'a'; 40]);
+        let record = Record::new(value.clone(), 0, 0, 0);
+        assert!(store.set(keys[0].clone(), record).is_ok());
+        let record = Record::new(value, 0, 0, 0);
+        assert_eq!(
+            store.set(keys[1].clone(), record).err(),
+            Some(CacheError::OutOfMemory)
+        );
+    }
+}
@@ -0,0 +1,212 @@
+use crate::cache::cache::{KeyType, Record, ScanPage};
+use crate::cache::error::{CacheError, Result};
+use std::collections::BTreeMap;
+
+/// Cursors of a single store use the low bits only, stores wrapping
+/// other stores keep their own position above them
+pub const STORE_CURSOR_BITS: u32 = 56;
+/// Low bits of a store cursor are a position in the key hash space,
+/// high bits are the segment (shard) of the store
+const POSITION_BITS: u32 = 40;
+const POSITION_MASK: u64 = (1 << POSITION_BITS) - 1;
+const MAX_SEGMENTS: usize = 1 << (STORE_CURSOR_BITS - POSITION_BITS);
+
+/// Position of a key in the scan order of its segment
+pub fn position(hash: u64) -> u64 {
+    hash >> (u64::BITS - POSITION_BITS)
+}
+
+/// Collects items of one segment which come next in the scan order.
+///
+/// Keeps the items with the lowest positions not below the cursor, all
+/// items sharing a position are kept together, so a page never ends in
+/// the middle of a position.
+pub struct ScanCollector<'a> {
+    from: u64,
+    limit: usize,
+    prefix: &'a [u8],
+    items: BTreeMap<u64, Vec<(KeyType, Record)>>,
+    len: usize,
+}
+
+impl<'a> ScanCollector<'a> {
+    fn new(from: u64, limit: usize, prefix: &'a [u8]) -> ScanCollector<'a> {
+        ScanCollector {
+            from,
+            limit,
+            prefix,
+            items: BTreeMap::new(),
+            len: 0,
+        }
+    }
+
+    /// `hash` is the hash the store computes for the key, it has to be
+    /// the same for the whole scan
+    pub fn offer(&mut self, hash: u64, key: &KeyType, record: &Record) {
+        let position = position(hash);
+        if position < self.from || !key.starts_with(self.prefix) {
+            return;
+        }
+        if self.len >= self.limit {
+            match self.items.last_key_value() {
+                Some((last, _)) if position > *last => return,
+                _ => {}
+            }
+        }
+        self.items
+            .entry(position)
+            .or_default()
+            .push((key.clone(), record.clone()));
+        self.len += 1;
+        // drop highest positions as long as there are enough items without them
+        while let Some((_, last)) = self.items.last_key_value() {
+            if self.len - last.len() < self.limit {
+                break;
+            }
+            self.len -= last.len();
+            self.items.pop_last();
+        }
+    }
+
+    /// Moves collected items to `items`, returns position the next page of
+    /// the segment starts at or None if the segment is done
+    fn finish(self, items: &mut Vec<(KeyType, Record)>) -> Option<u64> {
+        let filled = self.len >= self.limit;
+        let last = self.items.last_key_value().map(|(position, _)| *position);
+        items.extend(self.items.into_values().flatten());
+        match last {
+            Some(last) if filled && last < POSITION_MASK => Some(last + 1),
+            _ => None,
+        }
+    }
+}
+
+/// Scans a store split into `segments`, `visit` offers every live item
+/// of a segment to the collector
+pub fn scan_segments(
+    cursor: u64,
+    count: usize,
+    prefix: &[u8],
+    segments: usize,
+    mut visit: impl FnMut(usize, &mut ScanCollector),
+) -> Result<ScanPage> {
+    if cursor >> STORE_CURSOR_BITS != 0 {
+        return Err(CacheError::InvalidArguments);
+    }
+    debug_assert!(segments <= MAX_SEGMENTS);
+    let count = count.max(1);
+    let mut segment = (cursor >> POSITION_BITS) as usize;
+    let mut from = cursor & POSITION_MASK;
+    let mut items = Vec::new();
+    while segment < segments && items.len() < count {
+        let mut collector = ScanCollector::new(from, count - items.len(), prefix);
+        visit(segment, &mut collector);
+        match collector.finish(&mut items) {
+            Some(next) => from = next,
+            None => {
+                segment += 1;
+                from = 0;
+            }
+        }
+    }
+    let cursor = if segment < segments {
+        ((segment as u64) << POSITION_BITS) | from
+    } else {
+        0
+    };
+    Ok(ScanPage { items, cursor })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    use std::collections::HashSet;
+
+    fn segments_of(keys: usize, segments: usize) -> Vec<Vec<(u64, KeyType)>> {
+        let mut result = vec![Vec::new(); segments];
+        for idx in 0..keys {
+            let hash = (idx as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+            result[idx % segments].push((hash, Bytes::from(format!("key{}", idx))));
+        }
+        result
+    }
+
+    fn scan_all(
+        segments: &[Vec<(u64, KeyType)>],
+        count: usize,
+        prefix: &[u8],
+    ) -> (Vec<KeyType>, usize) {
+        let record = Record::new(Bytes::from("value"), 1, 0, 0);
+        let mut keys = Vec::new();
+        let mut pages = 0;
+        let mut cursor = 0;
+        loop {
+            let page = scan_segments(
+                cursor,
+                count,
+                prefix,
+                segments.len(),
+                |segment, collector| {
+                    for (hash, key) in &segments[segment] {
+                        collector.offer(*hash, key, &record);
+                    }
+                },
+            )
+            .unwrap();
+            assert!(page.items.len() <= count);
+            keys.extend(page.items.into_iter().map(|(key, _)| key));
+            pages += 1;
+            if page.cursor == 0 {
+                return (keys, pages);
+            }
+            cursor = page.cursor;
+        }
+    }
+
+    #[test]
+    fn test_scan_returns_every_key_once() {
+        let segments = segments_of(1000, 8);
+        let (keys, pages) = scan_all(&segments, 64, b"");
+        let unique: HashSet<KeyType> = keys.iter().cloned().collect();
+        assert_eq!(keys.len(), 1000);
+        assert_eq!(unique.len(), 1000);
+        assert_eq!(pages, 1000 / 64 + 1);
+    }
+
+    #[test]
+    fn test_scan_filters_by_prefix() {
+        let segments = segments_of(1000, 4);
+        let (keys, _) = scan_all(&segments, 10, b"key1");
+        // key1, key10-key19, key100-key199
+        assert_eq!(keys.len(), 111);
+        assert!(keys.iter().all(|key| key.starts_with(b"key1")));
+    }
+
+    #[test]
+    fn test_equal_positions_stay_on_one_page() {
+        let record = Record::new(Bytes::from("value"), 1, 0, 0);
+        let keys: Vec<KeyType> = (0..5).map(|idx| Bytes::from(format!("k{}", idx))).collect();
+        let page = scan_segments(0, 2, b"", 1, |_, collector| {
+            for key in &keys {
+                collector.offer(7 << 24, key, &record);
+            }
+        })
+        .unwrap();
+        assert_eq!(page.items.len(), 5);
+        let next = scan_segments(page.cursor, 2, b"", 1, |_, collector| {
+            for key in &keys {
+                collector.offer(7 << 24, key, &record);
+            }
+        })
+        .unwrap();
+        assert!(next.items.is_empty());
+        assert_eq!(next.cursor, 0);
+    }
+
+    #[test]
+    fn test_invalid_cursor() {
+        let result = scan_segments(1 << STORE_CURSOR_BITS, 10, b"", 1, |_, _| {});
+        assert_eq!(result.unwrap_err(), CacheError::InvalidArguments);
+    }
+}
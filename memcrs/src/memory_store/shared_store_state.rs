@@ -1,20 +1,87 @@
-use crate::cache::cache::{DeltaParam, KeyType, Record};
+use crate::cache::cache::{CacheStats, DeltaParam, KeyType, Record, ScanPage};
 use crate::cache::error::{CacheError, Result};
-use crate::server::timer::Timer;
-use std::sync::atomic::Ordering;
-use std::sync::{atomic::AtomicU64, Arc};
+use crate::cache::events::{RemovalReason, SharedListener, StoreEvents};
+use crate::memory_store::cas::CasIds;
+use crate::memory_store::scan::{self, ScanCollector};
+use crate::server::timer::{Timer, MAX_RELATIVE_EXPIRATION};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+/// Server timestamp of items which are already expired
+const EXPIRED: u32 = 1;
 
 pub struct SharedStoreState {
     timer: Arc<dyn Timer + Send + Sync>,
-    cas_id: AtomicU64,
+    cas_ids: &'static CasIds,
+    events: Arc<StoreEvents>,
 }
 
 impl SharedStoreState {
     pub fn new(timer: Arc<dyn Timer + Send + Sync>) -> SharedStoreState {
         SharedStoreState {
             timer,
-            cas_id: AtomicU64::new(1),
+            cas_ids: CasIds::global(),
+            events: Arc::new(StoreEvents::new()),
+        }
+    }
+
+    /// Events of the store, for engines reporting them from their own callbacks
+    pub fn events(&self) -> Arc<StoreEvents> {
+        self.events.clone()
+    }
+
+    pub fn subscribe(&self, listener: SharedListener) {
+        self.events.subscribe(listener)
+    }
+
+    pub fn removed(&self, key: &KeyType, reason: RemovalReason) {
+        self.events.removed(key, reason)
+    }
+
+    /// Reports item evicted or found expired, see `StoreEvents::dropped`
+    pub fn dropped(&self, key: &KeyType, record: &Record, reason: RemovalReason) {
+        self.events.dropped(key, record, reason)
+    }
+
+    /// Whether records of dropped items are wanted by a listener
+    pub fn wants_records(&self) -> bool {
+        self.events.wants_records()
+    }
+
+    /// Reports record about to be overwritten, an expired record
+    /// was never visible to the write so it counts as expired
+    pub fn overwritten(&self, key: &KeyType, prev_record: &Record) {
+        match self.check_if_expired(key, prev_record) {
+            true => self
+                .events
+                .dropped(key, prev_record, RemovalReason::Expired),
+            false => self.events.removed(key, RemovalReason::Replaced),
+        }
+    }
+
+    /// `time_to_live` is the delay sent with the flush command
+    pub fn flushed(&self, time_to_live: u32) {
+        let expiration = match time_to_live {
+            0 => 0,
+            ttl => self.unix_expiration(self.expiration(ttl)),
+        };
+        self.events.flushed(expiration)
+    }
+
+    pub fn add_stats(&self, stats: &mut CacheStats) {
+        self.events.add_stats(stats)
+    }
+
+    /// Groups positions of keys by shard, shards are in ascending order
+    pub fn group_by_shard<'a>(
+        keys: impl Iterator<Item = &'a KeyType>,
+        shard_index: impl Fn(&KeyType) -> usize,
+    ) -> Vec<(usize, Vec<usize>)> {
+        let mut shards: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+        for (position, key) in keys.enumerate() {
+            shards.entry(shard_index(key)).or_default().push(position);
         }
+        shards.into_iter().collect()
     }
 
     #[inline]
@@ -23,19 +90,55 @@ impl SharedStoreState {
     }
 
     pub fn update_ttl(&self, record: &mut Record, ttl: u32) {
-        let timestamp = self.timestamp();
-        record.header.time_to_live = timestamp + ttl;
+        record.header.time_to_live = self.expiration(ttl);
     }
 
-    pub fn set_cas_ttl(&self, record: &mut Record) -> u64 {
-        record.header.cas = match record.header.cas {
-            0 => self.get_cas_id(),
-            _ => record.header.cas.wrapping_add(1),
-        };
+    /// Converts expiration sent by a client to server timestamp, 0 never expires.
+    /// Up to 30 days it is relative to now, above that it is an absolute unix
+    /// time and times in the past mean the item is already expired.
+    pub fn expiration(&self, time_to_live: u32) -> u32 {
         let timestamp = self.timestamp();
-        if record.header.time_to_live > 0 {
-            record.header.time_to_live += timestamp;
+        match time_to_live {
+            0 => 0,
+            ttl if ttl <= MAX_RELATIVE_EXPIRATION => timestamp.saturating_add(ttl),
+            ttl => match (ttl as u64).checked_sub(self.timer.unix_timestamp()) {
+                Some(remaining) if remaining > 0 => {
+                    (timestamp as u64 + remaining).min(u32::MAX as u64) as u32
+                }
+                _ => EXPIRED,
+            },
+        }
+    }
+
+    /// Converts server timestamp of an item to unix time, 0 never expires
+    pub fn unix_expiration(&self, time_to_live: u32) -> u32 {
+        match time_to_live {
+            0 => 0,
+            ttl => (self.timer.process_started() + ttl as u64).min(u32::MAX as u64) as u32,
         }
+    }
+
+    /// Scans a store split into `segments`, see `scan::scan_segments`.
+    /// Expirations of returned records are converted to unix time.
+    pub fn scan_segments(
+        &self,
+        cursor: u64,
+        count: usize,
+        prefix: &[u8],
+        segments: usize,
+        visit: impl FnMut(usize, &mut ScanCollector),
+    ) -> Result<ScanPage> {
+        let mut page = scan::scan_segments(cursor, count, prefix, segments, visit)?;
+        for (_key, record) in page.items.iter_mut() {
+            record.header.time_to_live = self.unix_expiration(record.header.time_to_live);
+        }
+        Ok(page)
+    }
+
+    /// Gives record a new CAS, CAS sent by the client must be checked before
+    pub fn set_cas_ttl(&self, record: &mut Record) -> u64 {
+        record.header.cas = self.get_cas_id();
+        record.header.time_to_live = self.expiration(record.header.time_to_live);
         record.header.cas
     }
 
@@ -44,7 +147,13 @@ impl SharedStoreState {
     }
 
     pub fn get_cas_id(&self) -> u64 {
-        self.cas_id.fetch_add(1, Ordering::Release)
+        self.cas_ids.next()
+    }
+
+    /// Makes sure CAS ids handed out later are greater than `cas`,
+    /// used when records with existing CAS are restored.
+    pub fn advance_cas_id(&self, cas: u64) {
+        self.cas_ids.advance(cas)
     }
 
     /// Default implementation for performing arithmetic operations on a numeric value.
@@ -0,0 +1,397 @@
+use crate::cache::cache::{KeyType, Record};
+use crate::cache::error::{CacheError, Result};
+use bytes::Bytes;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+const NIL: u32 = u32::MAX;
+
+struct Node {
+    key: KeyType,
+    record: Record,
+    // set on hit, cleared by the hand, never needs exclusive access
+    visited: AtomicBool,
+    // towards newer items
+    prev: u32,
+    // towards older items
+    next: u32,
+}
+
+/// Single shard of the SIEVE cache.
+///
+/// Items are kept in insertion order, new items go to the head. Hits only
+/// mark an item as visited, so lookups can run under a shared lock. On
+/// eviction the hand walks from the tail towards the head, clearing the
+/// visited bit, and removes the first item which was not visited since the
+/// hand last passed it.
+pub struct SieveShard {
+    index: HashMap<KeyType, u32>,
+    nodes: Vec<Node>,
+    free: Vec<u32>,
+    head: u32,
+    tail: u32,
+    hand: u32,
+    capacity: usize,
+    // server time of the last sweep for expired items
+    last_sweep: u32,
+}
+
+impl SieveShard {
+    pub fn new(capacity: usize) -> SieveShard {
+        SieveShard {
+            index: HashMap::new(),
+            nodes: Vec::new(),
+            free: Vec::new(),
+            head: NIL,
+            tail: NIL,
+            hand: NIL,
+            capacity: capacity.max(1),
+            last_sweep: u32::MAX,
+        }
+    }
+
+    /// Returns a record and marks it as visited
+    pub fn get(&self, key: &KeyType) -> Option<&Record> {
+        let node = &self.nodes[*self.index.get(key)? as usize];
+        if !node.visited.load(Ordering::Relaxed) {
+            node.visited.store(true, Ordering::Relaxed);
+        }
+        Some(&node.record)
+    }
+
+    /// Returns a record without affecting eviction order
+    pub fn peek(&self, key: &KeyType) -> Option<&Record> {
+        self.index
+            .get(key)
+            .map(|idx| &self.nodes[*idx as usize].record)
+    }
+
+    /// Returns a mutable record without affecting eviction order
+    pub fn peek_mut(&mut self, key: &KeyType) -> Option<&mut Record> {
+        self.index
+            .get(key)
+            .map(|idx| &mut self.nodes[*idx as usize].record)
+    }
+
+    /// Stores a record, updating an existing item counts as a hit. When
+    /// the shard is full an item is evicted, unless `evict` is false in
+    /// which case OutOfMemory is returned. Returns the evicted item.
+    pub fn insert(
+        &mut self,
+        key: KeyType,
+        record: Record,
+        evict: bool,
+    ) -> Result<Option<(KeyType, Record)>> {
+        if let Some(idx) = self.index.get(&key) {
+            let node = &mut self.nodes[*idx as usize];
+            node.record = record;
+            node.visited.store(true, Ordering::Relaxed);
+            return Ok(None);
+        }
+        let mut evicted = None;
+        if self.index.len() >= self.capacity {
+            if !evict {
+                return Err(CacheError::OutOfMemory);
+            }
+            evicted = self.evict();
+        }
+        let node = Node {
+            key: key.clone(),
+            record,
+            visited: AtomicBool::new(false),
+            prev: NIL,
+            next: NIL,
+        };
+        let idx = match self.free.pop() {
+            Some(idx) => {
+                self.nodes[idx as usize] = node;
+                idx
+            }
+            None => {
+                self.nodes.push(node);
+                (self.nodes.len() - 1) as u32
+            }
+        };
+        self.link_head(idx);
+        self.index.insert(key, idx);
+        Ok(evicted)
+    }
+
+    pub fn remove(&mut self, key: &KeyType) -> Option<Record> {
+        let idx = self.index.remove(key)?;
+        self.unlink(idx);
+        self.free.push(idx);
+        let node = &mut self.nodes[idx as usize];
+        // drop references to key and value buffers
+        node.key = KeyType::new();
+        let record = node.record.clone();
+        node.record.value = Bytes::new();
+        Some(record)
+    }
+
+    pub fn clear(&mut self) {
+        self.index.clear();
+        self.nodes.clear();
+        self.free.clear();
+        self.head = NIL;
+        self.tail = NIL;
+        self.hand = NIL;
+    }
+
+    pub fn for_each_record_mut(&mut self, mut f: impl FnMut(&mut Record)) {
+        for idx in self.index.values() {
+            f(&mut self.nodes[*idx as usize].record);
+        }
+    }
+
+    /// Visits every item, visited bits are left intact
+    pub fn for_each(&self, mut f: impl FnMut(&KeyType, &Record)) {
+        for (key, idx) in &self.index {
+            f(key, &self.nodes[*idx as usize].record);
+        }
+    }
+
+    /// Whether inserting `key` needs a free slot the shard does not have
+    pub fn is_full_for(&self, key: &KeyType) -> bool {
+        self.index.len() >= self.capacity && !self.index.contains_key(key)
+    }
+
+    /// Removes items for which `expired` returns true and returns them.
+    /// Sweeps at most once per server time `now`, so a shard full of
+    /// live items is not walked on every rejected write.
+    pub fn remove_expired(
+        &mut self,
+        now: u32,
+        expired: impl Fn(&KeyType, &Record) -> bool,
+    ) -> Vec<(KeyType, Record)> {
+        if self.last_sweep == now {
+            return Vec::new();
+        }
+        self.last_sweep = now;
+        let keys: Vec<KeyType> = self
+            .index
+            .iter()
+            .filter(|(key, idx)| expired(key, &self.nodes[**idx as usize].record))
+            .map(|(key, _)| key.clone())
+            .collect();
+        keys.into_iter()
+            .filter_map(|key| self.remove(&key).map(|record| (key, record)))
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    fn evict(&mut self) -> Option<(KeyType, Record)> {
+        let mut idx = if self.hand == NIL {
+            self.tail
+        } else {
+            self.hand
+        };
+        while idx != NIL
+            && self.nodes[idx as usize]
+                .visited
+                .swap(false, Ordering::Relaxed)
+        {
+            idx = match self.nodes[idx as usize].prev {
+                NIL => self.tail,
+                prev => prev,
+            };
+        }
+        if idx == NIL {
+            return None;
+        }
+        // unlink moves the hand to the next newer item
+        self.hand = idx;
+        let key = self.nodes[idx as usize].key.clone();
+        self.remove(&key).map(|record| (key, record))
+    }
+
+    fn link_head(&mut self, idx: u32) {
+        let old_head = self.head;
+        {
+            let node = &mut self.nodes[idx as usize];
+            node.prev = NIL;
+            node.next = old_head;
+        }
+        if old_head != NIL {
+            self.nodes[old_head as usize].prev = idx;
+        } else {
+            self.tail = idx;
+        }
+        self.head = idx;
+    }
+
+    fn unlink(&mut self, idx: u32) {
+        let (prev, next) = {
+            let node = &self.nodes[idx as usize];
+            (node.prev, node.next)
+        };
+        if self.hand == idx {
+            self.hand = prev;
+        }
+        if prev != NIL {
+            self.nodes[prev as usize].next = next;
+        } else {
+            self.head = next;
+        }
+        if next != NIL {
+            self.nodes[next as usize].prev = prev;
+        } else {
+            self.tail = prev;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(value: &'static str) -> Record {
+        Record::new(Bytes::from(value), 0, 0, 0)
+    }
+
+    fn key(key: &'static str) -> KeyType {
+        Bytes::from(key)
+    }
+
+    #[test]
+    fn test_insert_get_remove() {
+        let mut shard = SieveShard::new(4);
+        assert!(shard.is_empty());
+        shard.insert(key("a"), record("1"), true).unwrap();
+        shard.insert(key("b"), record("2"), true).unwrap();
+        assert_eq!(shard.len(), 2);
+        assert_eq!(shard.get(&key("a")).unwrap().value, Bytes::from("1"));
+        assert_eq!(shard.remove(&key("a")).unwrap().value, Bytes::from("1"));
+        assert!(shard.get(&key("a")).is_none());
+        assert_eq!(shard.len(), 1);
+    }
+
+    #[test]
+    fn test_evicts_oldest_not_visited() {
+        let mut shard = SieveShard::new(3);
+        for k in ["a", "b", "c"] {
+            shard.insert(key(k), record("v"), true).unwrap();
+        }
+        shard.get(&key("a"));
+        assert_eq!(
+            shard.insert(key("d"), record("v"), true).unwrap(),
+            Some((key("b"), record("v")))
+        );
+        assert!(shard.peek(&key("a")).is_some());
+        assert!(shard.peek(&key("b")).is_none());
+        assert!(shard.peek(&key("c")).is_some());
+        assert!(shard.peek(&key("d")).is_some());
+    }
+
+    #[test]
+    fn test_hand_clears_visited_items() {
+        let mut shard = SieveShard::new(3);
+        for k in ["a", "b", "c"] {
+            shard.insert(key(k), record("v"), true).unwrap();
+        }
+        shard.get(&key("a"));
+        shard.get(&key("b"));
+        // hand clears a and b, then evicts c
+        shard.insert(key("d"), record("v"), true).unwrap();
+        assert!(shard.peek(&key("c")).is_none());
+        // a lost its second chance when the hand passed it
+        shard.insert(key("e"), record("v"), true).unwrap();
+        assert!(shard.peek(&key("a")).is_none());
+        assert!(shard.peek(&key("b")).is_some());
+        assert!(shard.peek(&key("d")).is_some());
+    }
+
+    #[test]
+    fn test_hand_resumes_after_last_victim() {
+        let mut shard = SieveShard::new(3);
+        for k in ["a", "b", "c"] {
+            shard.insert(key(k), record("v"), true).unwrap();
+        }
+        shard.get(&key("a"));
+        // hand clears a, evicts b and stops at c
+        shard.insert(key("d"), record("v"), true).unwrap();
+        assert!(shard.peek(&key("b")).is_none());
+        shard.get(&key("c"));
+        // a is not visited, but the hand is already past it
+        shard.insert(key("e"), record("v"), true).unwrap();
+        assert!(shard.peek(&key("a")).is_some());
+        assert!(shard.peek(&key("c")).is_some());
+        assert!(shard.peek(&key("d")).is_none());
+    }
+
+    #[test]
+    fn test_all_visited_evicts_tail() {
+        let mut shard = SieveShard::new(2);
+        shard.insert(key("a"), record("v"), true).unwrap();
+        shard.insert(key("b"), record("v"), true).unwrap();
+        shard.get(&key("a"));
+        shard.get(&key("b"));
+        shard.insert(key("c"), record("v"), true).unwrap();
+        assert_eq!(shard.len(), 2);
+        assert!(shard.peek(&key("a")).is_none());
+    }
+
+    #[test]
+    fn test_no_eviction_rejects_new_items() {
+        let mut shard = SieveShard::new(1);
+        shard.insert(key("a"), record("1"), false).unwrap();
+        assert_eq!(
+            shard.insert(key("b"), record("2"), false),
+            Err(CacheError::OutOfMemory)
+        );
+        assert!(shard.insert(key("a"), record("3"), false).is_ok());
+        assert_eq!(shard.peek(&key("a")).unwrap().value, Bytes::from("3"));
+    }
+
+    #[test]
+    fn test_remove_expired_once_per_second() {
+        let mut shard = SieveShard::new(2);
+        shard.insert(key("a"), record("old"), false).unwrap();
+        shard.insert(key("b"), record("new"), false).unwrap();
+        assert!(shard.is_full_for(&key("c")));
+        assert!(!shard.is_full_for(&key("a")));
+        let expired = |_: &KeyType, record: &Record| record.value == "old";
+        assert_eq!(
+            shard.remove_expired(1, expired),
+            vec![(key("a"), record("old"))]
+        );
+        assert!(!shard.is_full_for(&key("c")));
+        shard.insert(key("c"), record("old"), false).unwrap();
+        assert!(shard.remove_expired(1, expired).is_empty());
+        assert_eq!(
+            shard.remove_expired(2, expired),
+            vec![(key("c"), record("old"))]
+        );
+    }
+
+    #[test]
+    fn test_removed_slots_are_reused() {
+        let mut shard = SieveShard::new(2);
+        for _ in 0..10 {
+            shard.insert(key("a"), record("v"), true).unwrap();
+            shard.insert(key("b"), record("v"), true).unwrap();
+            shard.remove(&key("a"));
+            shard.remove(&key("b"));
+        }
+        assert!(shard.is_empty());
+        assert_eq!(shard.nodes.len(), 2);
+    }
+
+    #[test]
+    fn test_clear() {
+        let mut shard = SieveShard::new(2);
+        shard.insert(key("a"), record("v"), true).unwrap();
+        shard.clear();
+        assert!(shard.is_empty());
+        shard.insert(key("b"), record("v"), true).unwrap();
+        shard.insert(key("c"), record("v"), true).unwrap();
+        assert_eq!(shard.len(), 2);
+    }
+}
@@ -0,0 +1,417 @@
+use crate::cache::cache::{
+    Cache, CacheMetaData, CacheStats, DeltaParam, DeltaResult, KeyType, Record, ScanPage, SetStatus,
+};
+use crate::cache::cache_capability::CacheCapability;
+use crate::cache::error::{CacheError, Result};
+use crate::cache::events::{RemovalReason, SharedListener};
+use crate::cache::eviction_policy::EvictionPolicy;
+use crate::memcache::cli::parser::SieveConfig;
+use crate::memory_store::parallelism::get_number_of_shards;
+use crate::memory_store::shared_store_state::SharedStoreState;
+use crate::memory_store::sieve::SieveShard;
+use crate::protocol::binary::network::DELTA_NO_INITIAL_VALUE;
+use crate::server::timer;
+
+use bytes::{Bytes, BytesMut};
+use std::hash::{BuildHasher, RandomState};
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+/// Lock sharded store evicting with SIEVE algorithm.
+///
+/// Capacity is a number of items, split evenly between shards. Reads
+/// only take a shared lock, SIEVE does not reorder items on hit.
+pub struct SieveMemoryStore {
+    shards: Vec<RwLock<SieveShard>>,
+    hasher: RandomState,
+    store_state: SharedStoreState,
+    evict: bool,
+}
+
+impl SieveMemoryStore {
+    // small shards make eviction decisions on too little history
+    const MIN_ITEMS_PER_SHARD: u64 = 1024;
+
+    pub fn new(timer: Arc<dyn timer::Timer + Send + Sync>, cfg: SieveConfig) -> SieveMemoryStore {
+        let parallelism = std::thread::available_parallelism().map_or(1, usize::from);
+        let max_shards = (cfg.max_capacity / SieveMemoryStore::MIN_ITEMS_PER_SHARD).max(1);
+        let shards = (get_number_of_shards(parallelism) as u64).min(max_shards);
+        let shard_capacity = cfg.max_capacity.div_ceil(shards) as usize;
+        info!(
+            "Number of shards: {}, items per shard: {}",
+            shards, shard_capacity
+        );
+        SieveMemoryStore {
+            shards: (0..shards)
+                .map(|_| RwLock::new(SieveShard::new(shard_capacity)))
+                .collect(),
+            hasher: RandomState::new(),
+            store_state: SharedStoreState::new(timer),
+            evict: cfg.eviction_policy != EvictionPolicy::None,
+        }
+    }
+
+    fn shard_index(&self, key: &KeyType) -> usize {
+        (self.hasher.hash_one(key) as usize) % self.shards.len()
+    }
+
+    fn read_shard(&self, key: &KeyType) -> RwLockReadGuard<'_, SieveShard> {
+        self.shards[self.shard_index(key)].read().unwrap()
+    }
+
+    fn write_shard(&self, key: &KeyType) -> RwLockWriteGuard<'_, SieveShard> {
+        self.shards[self.shard_index(key)].write().unwrap()
+    }
+
+    /// Returns record stored under key, expired records are removed
+    fn get_live(&self, shard: &mut SieveShard, key: &KeyType) -> Option<Record> {
+        let record = shard.get(key)?;
+        if self.store_state.check_if_expired(key, record) {
+            if let Some(record) = shard.remove(key) {
+                self.store_state
+                    .dropped(key, &record, RemovalReason::Expired);
+            }
+            return None;
+        }
+        Some(record.clone())
+    }
+
+    /// Inserts record, reports the item it overwrites or evicts
+    fn insert_locked(&self, shard: &mut SieveShard, key: KeyType, record: Record) -> Result<()> {
+        if let Some(prev_record) = shard.peek(&key) {
+            self.store_state.overwritten(&key, prev_record);
+        }
+        if !self.evict && shard.is_full_for(&key) {
+            // expired items nobody reads again would hold the slots forever
+            let expired = shard.remove_expired(self.store_state.timestamp(), |key, record| {
+                self.store_state.check_if_expired(key, record)
+            });
+            for (key, record) in expired {
+                self.store_state
+                    .dropped(&key, &record, RemovalReason::Expired);
+            }
+        }
+        if let Some((evicted, record)) = shard.insert(key, record, self.evict)? {
+            self.store_state
+                .dropped(&evicted, &record, RemovalReason::Size);
+        }
+        Ok(())
+    }
+
+    fn set_locked(
+        &self,
+        shard: &mut SieveShard,
+        key: KeyType,
+        mut record: Record,
+    ) -> Result<SetStatus> {
+        if let Some(prev_record) = shard.peek(&key) {
+            if SharedStoreState::cas_mismatch(&record, prev_record.header.cas) {
+                return Err(CacheError::KeyExists);
+            }
+        }
+        let cas = self.store_state.set_cas_ttl(&mut record);
+        self.insert_locked(shard, key, record)?;
+        Ok(SetStatus { cas })
+    }
+
+    fn delete_locked(
+        &self,
+        shard: &mut SieveShard,
+        key: KeyType,
+        header: CacheMetaData,
+    ) -> Result<Record> {
+        let record = shard.peek(&key).ok_or(CacheError::NotFound)?;
+        if header.cas != 0 && record.header.cas != header.cas {
+            return Err(CacheError::KeyExists);
+        }
+        let record = shard.remove(&key).ok_or(CacheError::NotFound)?;
+        self.store_state.removed(&key, RemovalReason::Explicit);
+        Ok(record)
+    }
+
+    fn append_prepend_common(
+        &self,
+        key: KeyType,
+        mut new_record: Record,
+        is_append: bool,
+    ) -> Result<SetStatus> {
+        let mut shard = self.write_shard(&key);
+        let prev_record = match self.get_live(&mut shard, &key) {
+            Some(record) => record,
+            None => return Err(CacheError::ItemNotStored),
+        };
+        if SharedStoreState::cas_mismatch(&new_record, prev_record.header.cas) {
+            return Err(CacheError::KeyExists);
+        }
+        let cas = self.store_state.set_cas_ttl(&mut new_record);
+        let mut new_value =
+            BytesMut::with_capacity(prev_record.value.len() + new_record.value.len());
+        if is_append {
+            new_value.extend_from_slice(&prev_record.value);
+            new_value.extend_from_slice(&new_record.value);
+        } else {
+            new_value.extend_from_slice(&new_record.value);
+            new_value.extend_from_slice(&prev_record.value);
+        }
+        new_record.value = new_value.freeze();
+        self.insert_locked(&mut shard, key, new_record)?;
+        Ok(SetStatus { cas })
+    }
+}
+
+impl Cache for SieveMemoryStore {
+    /// Returns a value associated with a key
+    fn get(&self, key: &KeyType) -> Result<Record> {
+        {
+            let shard = self.read_shard(key);
+            let record = shard.get(key).ok_or(CacheError::NotFound)?;
+            if !self.store_state.check_if_expired(key, record) {
+                return Ok(record.clone());
+            }
+        }
+        // expired, record could have been replaced before write lock was taken
+        let mut shard = self.write_shard(key);
+        self.get_live(&mut shard, key).ok_or(CacheError::NotFound)
+    }
+
+    fn set(&self, key: KeyType, record: Record) -> Result<SetStatus> {
+        let mut shard = self.write_shard(&key);
+        self.set_locked(&mut shard, key, record)
+    }
+
+    fn delete(&self, key: KeyType, header: CacheMetaData) -> Result<Record> {
+        let mut shard = self.write_shard(&key);
+        self.delete_locked(&mut shard, key, header)
+    }
+
+    fn flush(&self, header: CacheMetaData) {
+        for shard in &self.shards {
+            let mut shard = shard.write().unwrap();
+            if header.time_to_live > 0 {
+                let ttl = self.store_state.expiration(header.time_to_live);
+                shard.for_each_record_mut(|record| record.header.time_to_live = ttl);
+            } else {
+                shard.clear();
+            }
+        }
+        self.store_state.flushed(header.time_to_live);
+    }
+
+    fn run_pending_tasks(&self) {}
+
+    /// Adds a new key-value pair to the cache, but only if the key does not already exist.
+    /// If the key exists, the operation fails with KeyExists error.
+    fn add(&self, key: KeyType, mut record: Record) -> Result<SetStatus> {
+        let cas = self.store_state.set_cas_ttl(&mut record);
+        let mut shard = self.write_shard(&key);
+        if shard.peek(&key).is_some() {
+            return Err(CacheError::KeyExists);
+        }
+        self.insert_locked(&mut shard, key, record)?;
+        Ok(SetStatus { cas })
+    }
+
+    /// Replaces the value of an existing key in the cache, but only if the key already exists.
+    /// If the key does not exist, the operation fails with NotFound error.
+    fn replace(&self, key: KeyType, mut record: Record) -> Result<SetStatus> {
+        let mut shard = self.write_shard(&key);
+        let prev_record = shard.peek(&key).ok_or(CacheError::NotFound)?;
+        if SharedStoreState::cas_mismatch(&record, prev_record.header.cas) {
+            return Err(CacheError::KeyExists);
+        }
+        let cas = self.store_state.set_cas_ttl(&mut record);
+        self.insert_locked(&mut shard, key, record)?;
+        Ok(SetStatus { cas })
+    }
+
+    /// Appends the new value to the existing value for the given key.
+    /// The key must already exist in the cache, otherwise the operation fails with NotFound error.
+    fn append(&self, key: KeyType, new_record: Record) -> Result<SetStatus> {
+        self.append_prepend_common(key, new_record, true)
+    }
+
+    /// Prepends the new value to the existing value for the given key.
+    /// The key must already exist in the cache, otherwise the operation fails with NotFound error.
+    fn prepend(&self, key: KeyType, new_record: Record) -> Result<SetStatus> {
+        self.append_prepend_common(key, new_record, false)
+    }
+
+    /// Performs an arithmetic operation (increment or decrement) on a numeric value stored in the cache.
+    /// If `increment` is true, adds `delta` to the value; otherwise, subtracts `delta`.
+    /// The value must be a valid unsigned 64-bit integer.
+    /// Returns the new value after the operation.
+    fn incr_decr(
+        &self,
+        header: CacheMetaData,
+        key: KeyType,
+        delta: DeltaParam,
+        increment: bool,
+    ) -> Result<DeltaResult> {
+        let mut shard = self.write_shard(&key);
+        match shard.peek(&key) {
+            Some(record) => {
+                let mut record = record.clone();
+                let tmp_record = Record::new(Bytes::new(), header.cas, 0, 0);
+                if SharedStoreState::cas_mismatch(&tmp_record, record.header.cas) {
+                    return Err(CacheError::KeyExists);
+                }
+                let value = self
+                    .store_state
+                    .incr_decr_common(&record, delta, increment)?;
+                let cas = self.store_state.get_cas_id();
+                record.value = Bytes::from(value.to_string());
+                record.header.cas = cas;
+                self.insert_locked(&mut shard, key, record)?;
+                Ok(DeltaResult { cas, value })
+            }
+            None => {
+                if header.get_expiration() == DELTA_NO_INITIAL_VALUE {
+                    return Err(CacheError::NotFound);
+                }
+                let cas = self.store_state.get_cas_id();
+                let record = Record::new(
+                    Bytes::from(delta.value.to_string()),
+                    cas,
+                    0,
+                    self.store_state.expiration(header.get_expiration()),
+                );
+                self.insert_locked(&mut shard, key, record)?;
+                Ok(DeltaResult {
+                    cas,
+                    value: delta.value,
+                })
+            }
+        }
+    }
+
+    fn for_each_item(&self, f: &mut dyn FnMut(&KeyType, &Record)) {
+        // items are copied out under the lock, `f` may do I/O and must
+        // not block writers of the shard
+        let mut items = Vec::new();
+        for shard in &self.shards {
+            shard.read().unwrap().for_each(|key, record| {
+                if !self.store_state.check_if_expired(key, record) {
+                    items.push((key.clone(), record.clone()));
+                }
+            });
+            items.drain(..).for_each(|(key, record)| f(&key, &record));
+        }
+    }
+
+    fn restore(&self, key: KeyType, record: Record) -> Result<()> {
+        let mut shard = self.write_shard(&key);
+        // key was written after the snapshot was taken, it is newer
+        if shard.peek(&key).is_some() {
+            return Err(CacheError::KeyExists);
+        }
+        self.store_state.advance_cas_id(record.header.cas);
+        self.insert_locked(&mut shard, key, record)
+    }
+
+    fn relocate(&self, key: KeyType, record: Record) -> Result<()> {
+        let mut shard = self.write_shard(&key);
+        let stored = shard.peek_mut(&key).ok_or(CacheError::NotFound)?;
+        if stored.header.cas != record.header.cas {
+            return Err(CacheError::KeyExists);
+        }
+        stored.value = record.value;
+        Ok(())
+    }
+
+    fn add_stats(&self, stats: &mut CacheStats) {
+        self.store_state.add_stats(stats)
+    }
+
+    fn subscribe(&self, listener: SharedListener) {
+        self.store_state.subscribe(listener)
+    }
+
+    /// Keys of the same shard are read under one read lock
+    fn get_multi(&self, keys: &[KeyType]) -> Vec<Result<Record>> {
+        let mut results: Vec<Result<Record>> =
+            keys.iter().map(|_| Err(CacheError::NotFound)).collect();
+        let mut expired = Vec::new();
+        for (shard, positions) in
+            SharedStoreState::group_by_shard(keys.iter(), |key| self.shard_index(key))
+        {
+            let shard = self.shards[shard].read().unwrap();
+            for position in positions {
+                let key = &keys[position];
+                if let Some(record) = shard.get(key) {
+                    if self.store_state.check_if_expired(key, record) {
+                        expired.push(position);
+                    } else {
+                        results[position] = Ok(record.clone());
+                    }
+                }
+            }
+        }
+        // removed under write lock, like in get
+        for position in expired {
+            results[position] = self.get(&keys[position]);
+        }
+        results
+    }
+
+    /// Items of the same shard are written under one write lock
+    fn set_multi(&self, items: Vec<(KeyType, Record)>) -> Vec<Result<SetStatus>> {
+        let groups = SharedStoreState::group_by_shard(items.iter().map(|(key, _)| key), |key| {
+            self.shard_index(key)
+        });
+        let mut items: Vec<Option<(KeyType, Record)>> = items.into_iter().map(Some).collect();
+        let mut results: Vec<Result<SetStatus>> =
+            items.iter().map(|_| Err(CacheError::NotFound)).collect();
+        for (shard, positions) in groups {
+            let mut shard = self.shards[shard].write().unwrap();
+            for position in positions {
+                if let Some((key, record)) = items[position].take() {
+                    results[position] = self.set_locked(&mut shard, key, record);
+                }
+            }
+        }
+        results
+    }
+
+    /// Keys of the same shard are removed under one write lock
+    fn delete_multi(&self, items: Vec<(KeyType, CacheMetaData)>) -> Vec<Result<Record>> {
+        let groups = SharedStoreState::group_by_shard(items.iter().map(|(key, _)| key), |key| {
+            self.shard_index(key)
+        });
+        let mut items: Vec<Option<(KeyType, CacheMetaData)>> =
+            items.into_iter().map(Some).collect();
+        let mut results: Vec<Result<Record>> =
+            items.iter().map(|_| Err(CacheError::NotFound)).collect();
+        for (shard, positions) in groups {
+            let mut shard = self.shards[shard].write().unwrap();
+            for position in positions {
+                if let Some((key, header)) = items[position].take() {
+                    results[position] = self.delete_locked(&mut shard, key, header);
+                }
+            }
+        }
+        results
+    }
+
+    /// Every shard is a scan segment, read locked while it is scanned
+    fn scan(&self, cursor: u64, count: usize, prefix: &[u8]) -> Result<ScanPage> {
+        self.store_state.scan_segments(
+            cursor,
+            count,
+            prefix,
+            self.shards.len(),
+            |shard, collector| {
+                let shard = self.shards[shard].read().unwrap();
+                shard.for_each(|key, record| {
+                    if !self.store_state.check_if_expired(key, record) {
+                        collector.offer(self.hasher.hash_one(key), key, record);
+                    }
+                });
+            },
+        )
+    }
+}
+
+impl CacheCapability for SieveMemoryStore {
+    fn is_policy_supported(&self, policy: EvictionPolicy) -> bool {
+        matches!(policy, EvictionPolicy::Sieve | EvictionPolicy::None)
+    }
+}
@@ -0,0 +1,877 @@
+use crate::cache::cache::{CacheMetaData, KeyType, Record};
+use crate::cache::error::{CacheError, Result};
+use bytes::Bytes;
+use std::alloc::{self, Layout};
+use std::collections::HashMap;
+use std::hash::{BuildHasher, RandomState};
+use std::ptr::NonNull;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// Smallest chunk handed out by the allocator
+pub const MIN_CHUNK_SIZE: usize = 64;
+/// Same limit as in memcached
+pub const MAX_SLAB_CLASSES: usize = 64;
+/// Item header and the longest key the protocol accepts, pages are
+/// this much larger than the largest value
+pub const MAX_ITEM_OVERHEAD: usize = ITEM_HEADER_LEN + 256;
+const CHUNK_ALIGN: usize = 8;
+const ARENA_ALIGN: usize = 4096;
+const NIL: u32 = u32::MAX;
+
+/// Computes chunk sizes of all slab classes, every class is `growth_factor`
+/// times larger than the previous one and the last class always spans
+/// a whole page.
+pub fn slab_class_sizes(page_size: usize, growth_factor: f64) -> Vec<usize> {
+    let mut sizes = Vec::new();
+    let mut size = MIN_CHUNK_SIZE;
+    while sizes.len() < MAX_SLAB_CLASSES - 1 && (size as f64) <= (page_size as f64) / growth_factor
+    {
+        sizes.push(size);
+        let next = ((size as f64) * growth_factor) as usize;
+        // always grow by at least alignment, otherwise small factors
+        // would produce duplicated classes
+        size = (next.max(size + CHUNK_ALIGN) + CHUNK_ALIGN - 1) & !(CHUNK_ALIGN - 1);
+    }
+    sizes.push(page_size);
+    sizes
+}
+
+/// Memory preallocated at startup, divided into pages of equal size.
+/// Pages are handed out to slab classes on demand and never returned.
+pub struct Arena {
+    ptr: NonNull<u8>,
+    len: usize,
+    page_size: usize,
+    next_page: AtomicUsize,
+    #[cfg(target_os = "linux")]
+    mmaped: bool,
+}
+
+// Arena memory is only accessed through chunks, every chunk
+// is owned by exactly one shard which serializes access to it.
+unsafe impl Send for Arena {}
+unsafe impl Sync for Arena {}
+
+impl Arena {
+    pub fn new(memory_limit: u64, page_size: usize, huge_pages: bool) -> Arena {
+        let pages = ((memory_limit as usize) / page_size).max(1);
+        let len = pages * page_size;
+        #[cfg(target_os = "linux")]
+        if huge_pages {
+            if let Some(ptr) = Arena::mmap_huge_pages(len) {
+                return Arena {
+                    ptr,
+                    len,
+                    page_size,
+                    next_page: AtomicUsize::new(0),
+                    mmaped: true,
+                };
+            }
+        }
+        #[cfg(not(target_os = "linux"))]
+        if huge_pages {
+            warn!("Huge pages are not supported on this platform");
+        }
+        let layout = Layout::from_size_align(len, ARENA_ALIGN).expect("Invalid arena size");
+        // zeroed allocation is backed by lazily mapped pages
+        let ptr = unsafe { alloc::alloc_zeroed(layout) };
+        let ptr = NonNull::new(ptr).unwrap_or_else(|| alloc::handle_alloc_error(layout));
+        Arena {
+            ptr,
+            len,
+            page_size,
+            next_page: AtomicUsize::new(0),
+            #[cfg(target_os = "linux")]
+            mmaped: false,
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    fn mmap_huge_pages(len: usize) -> Option<NonNull<u8>> {
+        let ptr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | libc::MAP_HUGETLB,
+                -1,
+                0,
+            )
+        };
+        if ptr != libc::MAP_FAILED {
+            info!("Slab arena backed by huge pages");
+            return NonNull::new(ptr as *mut u8);
+        }
+        // no preallocated huge pages, fall back to transparent huge pages
+        let ptr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            warn!("Cannot map slab arena, huge pages will not be used");
+            return None;
+        }
+        if unsafe { libc::madvise(ptr, len, libc::MADV_HUGEPAGE) } != 0 {
+            warn!("Transparent huge pages are not available for slab arena");
+        } else {
+            info!("Slab arena backed by transparent huge pages");
+        }
+        NonNull::new(ptr as *mut u8)
+    }
+
+    pub fn page_size(&self) -> usize {
+        self.page_size
+    }
+
+    pub fn total_pages(&self) -> usize {
+        self.len / self.page_size
+    }
+
+    pub fn used_pages(&self) -> usize {
+        self.next_page
+            .load(Ordering::Acquire)
+            .min(self.total_pages())
+    }
+
+    /// Takes a page from the pool of unassigned pages
+    pub fn take_page(&self) -> Option<usize> {
+        let total = self.total_pages();
+        self.next_page
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |page| {
+                (page < total).then_some(page + 1)
+            })
+            .ok()
+    }
+
+    /// # Safety
+    /// Caller has to have exclusive access to the memory range.
+    unsafe fn write(&self, offset: usize, data: &[u8]) {
+        debug_assert!(offset + data.len() <= self.len);
+        std::ptr::copy_nonoverlapping(data.as_ptr(), self.ptr.as_ptr().add(offset), data.len());
+    }
+
+    /// # Safety
+    /// Memory range must not be written concurrently.
+    unsafe fn read(&self, offset: usize, len: usize) -> &[u8] {
+        debug_assert!(offset + len <= self.len);
+        std::slice::from_raw_parts(self.ptr.as_ptr().add(offset), len)
+    }
+}
+
+impl Drop for Arena {
+    fn drop(&mut self) {
+        #[cfg(target_os = "linux")]
+        if self.mmaped {
+            unsafe { libc::munmap(self.ptr.as_ptr() as *mut libc::c_void, self.len) };
+            return;
+        }
+        let layout = Layout::from_size_align(self.len, ARENA_ALIGN).expect("Invalid arena size");
+        unsafe { alloc::dealloc(self.ptr.as_ptr(), layout) };
+    }
+}
+
+/// Item header in front of key and value of every chunk. Besides the
+/// item meta data it holds links of the LRU list (the free list for free
+/// chunks) and of the hash chain, so the whole item is counted against
+/// the arena size.
+pub const ITEM_HEADER_LEN: usize = 40;
+const CAS: usize = 0;
+const FLAGS: usize = 8;
+const TIME_TO_LIVE: usize = 12;
+const VALUE_LEN: usize = 16;
+const KEY_LEN: usize = 20;
+const PREV: usize = 24;
+const NEXT: usize = 28;
+const HASH_NEXT: usize = 32;
+const HASH_NEXT_CLASS: usize = 36;
+
+/// Location of an item in a shard
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ChunkRef {
+    class: u8,
+    chunk: u32,
+}
+
+struct SlabClass {
+    chunk_size: usize,
+    chunks_per_page: usize,
+    pages: Vec<usize>,
+    /// free chunks are linked through their `NEXT` field
+    free_head: u32,
+    lru_head: u32,
+    lru_tail: u32,
+}
+
+impl SlabClass {
+    fn new(chunk_size: usize, page_size: usize) -> SlabClass {
+        SlabClass {
+            chunk_size,
+            chunks_per_page: page_size / chunk_size,
+            pages: Vec::new(),
+            free_head: NIL,
+            lru_head: NIL,
+            lru_tail: NIL,
+        }
+    }
+
+    fn offset(&self, chunk: u32, page_size: usize) -> usize {
+        let chunk = chunk as usize;
+        let page = self.pages[chunk / self.chunks_per_page];
+        page * page_size + (chunk % self.chunks_per_page) * self.chunk_size
+    }
+}
+
+/// Part of the keyspace with its own index, slab classes and LRU lists.
+/// Pages are taken from the shared arena, once assigned they belong
+/// to the shard so all reads and writes of its chunks are serialized
+/// by the shard lock. Items with the same key hash are chained through
+/// their headers, only the chain heads are kept outside of the arena.
+pub struct SlabShard {
+    arena: Arc<Arena>,
+    index: HashMap<u64, ChunkRef>,
+    hasher: RandomState,
+    classes: Vec<SlabClass>,
+    items: usize,
+    read_evicted: bool,
+}
+
+/// Key of an evicted item, its record is only copied out of the arena
+/// if the shard was asked to, see `SlabShard::read_evicted`
+pub type Evicted = (KeyType, Option<Record>);
+
+impl SlabShard {
+    pub fn new(arena: Arc<Arena>, class_sizes: &[usize]) -> SlabShard {
+        let page_size = arena.page_size();
+        SlabShard {
+            arena,
+            index: HashMap::new(),
+            hasher: RandomState::new(),
+            classes: class_sizes
+                .iter()
+                .map(|size| SlabClass::new(*size, page_size))
+                .collect(),
+            items: 0,
+            read_evicted: false,
+        }
+    }
+
+    /// Whether records of evicted items are copied out before their
+    /// chunks are reused
+    pub fn read_evicted(&mut self, read: bool) {
+        self.read_evicted = read;
+    }
+
+    fn evicted(&self, key: KeyType, chunk_ref: ChunkRef) -> Evicted {
+        let record = self.read_evicted.then(|| self.read(chunk_ref));
+        (key, record)
+    }
+
+    fn offset(&self, chunk_ref: ChunkRef) -> usize {
+        self.classes[chunk_ref.class as usize].offset(chunk_ref.chunk, self.arena.page_size())
+    }
+
+    fn bytes(&self, chunk_ref: ChunkRef, field: usize, len: usize) -> &[u8] {
+        unsafe { self.arena.read(self.offset(chunk_ref) + field, len) }
+    }
+
+    fn set_bytes(&mut self, chunk_ref: ChunkRef, field: usize, data: &[u8]) {
+        unsafe { self.arena.write(self.offset(chunk_ref) + field, data) }
+    }
+
+    fn u32_field(&self, chunk_ref: ChunkRef, field: usize) -> u32 {
+        u32::from_le_bytes(self.bytes(chunk_ref, field, 4).try_into().unwrap())
+    }
+
+    fn set_u32_field(&mut self, chunk_ref: ChunkRef, field: usize, value: u32) {
+        self.set_bytes(chunk_ref, field, &value.to_le_bytes())
+    }
+
+    fn key_len(&self, chunk_ref: ChunkRef) -> usize {
+        u16::from_le_bytes(self.bytes(chunk_ref, KEY_LEN, 2).try_into().unwrap()) as usize
+    }
+
+    fn key(&self, chunk_ref: ChunkRef) -> &[u8] {
+        self.bytes(chunk_ref, ITEM_HEADER_LEN, self.key_len(chunk_ref))
+    }
+
+    fn hash(&self, key: &[u8]) -> u64 {
+        self.hasher.hash_one(key)
+    }
+
+    fn class_for(&self, len: usize) -> Result<u8> {
+        self.classes
+            .iter()
+            .position(|class| class.chunk_size >= len)
+            .map(|class| class as u8)
+            .ok_or(CacheError::ValueTooLarge)
+    }
+
+    fn unlink(&mut self, chunk_ref: ChunkRef) {
+        let prev = self.u32_field(chunk_ref, PREV);
+        let next = self.u32_field(chunk_ref, NEXT);
+        let class = chunk_ref.class;
+        match prev {
+            NIL => self.classes[class as usize].lru_head = next,
+            prev => self.set_u32_field(ChunkRef { class, chunk: prev }, NEXT, next),
+        }
+        match next {
+            NIL => self.classes[class as usize].lru_tail = prev,
+            next => self.set_u32_field(ChunkRef { class, chunk: next }, PREV, prev),
+        }
+        self.set_u32_field(chunk_ref, PREV, NIL);
+        self.set_u32_field(chunk_ref, NEXT, NIL);
+    }
+
+    fn link_head(&mut self, chunk_ref: ChunkRef) {
+        let class = chunk_ref.class;
+        let head = self.classes[class as usize].lru_head;
+        self.set_u32_field(chunk_ref, PREV, NIL);
+        self.set_u32_field(chunk_ref, NEXT, head);
+        match head {
+            NIL => self.classes[class as usize].lru_tail = chunk_ref.chunk,
+            head => self.set_u32_field(ChunkRef { class, chunk: head }, PREV, chunk_ref.chunk),
+        }
+        self.classes[class as usize].lru_head = chunk_ref.chunk;
+    }
+
+    fn hash_next(&self, chunk_ref: ChunkRef) -> Option<ChunkRef> {
+        match self.u32_field(chunk_ref, HASH_NEXT) {
+            NIL => None,
+            chunk => Some(ChunkRef {
+                class: self.bytes(chunk_ref, HASH_NEXT_CLASS, 1)[0],
+                chunk,
+            }),
+        }
+    }
+
+    fn set_hash_next(&mut self, chunk_ref: ChunkRef, next: Option<ChunkRef>) {
+        let (class, chunk) = next.map_or((0, NIL), |next| (next.class, next.chunk));
+        self.set_u32_field(chunk_ref, HASH_NEXT, chunk);
+        self.set_bytes(chunk_ref, HASH_NEXT_CLASS, &[class]);
+    }
+
+    fn link_hash(&mut self, hash: u64, chunk_ref: ChunkRef) {
+        let head = self.index.insert(hash, chunk_ref);
+        self.set_hash_next(chunk_ref, head);
+        self.items += 1;
+    }
+
+    fn unlink_hash(&mut self, hash: u64, chunk_ref: ChunkRef) {
+        let next = self.hash_next(chunk_ref);
+        let mut current = self.index[&hash];
+        if current == chunk_ref {
+            match next {
+                Some(next) => self.index.insert(hash, next),
+                None => self.index.remove(&hash),
+            };
+        } else {
+            while let Some(following) = self.hash_next(current) {
+                if following == chunk_ref {
+                    self.set_hash_next(current, next);
+                    break;
+                }
+                current = following;
+            }
+        }
+        self.items -= 1;
+    }
+
+    fn push_free(&mut self, chunk_ref: ChunkRef) {
+        let class = &self.classes[chunk_ref.class as usize];
+        let head = class.free_head;
+        self.set_u32_field(chunk_ref, NEXT, head);
+        self.classes[chunk_ref.class as usize].free_head = chunk_ref.chunk;
+    }
+
+    fn pop_free(&mut self, class: u8) -> Option<u32> {
+        let chunk = match self.classes[class as usize].free_head {
+            NIL => return None,
+            chunk => chunk,
+        };
+        let next = self.u32_field(ChunkRef { class, chunk }, NEXT);
+        self.classes[class as usize].free_head = next;
+        Some(chunk)
+    }
+
+    fn add_page(&mut self, class: u8, page: usize) {
+        let slab_class = &mut self.classes[class as usize];
+        let first = (slab_class.pages.len() * slab_class.chunks_per_page) as u32;
+        let chunks = slab_class.chunks_per_page as u32;
+        slab_class.pages.push(page);
+        for chunk in (first..first + chunks).rev() {
+            self.push_free(ChunkRef { class, chunk });
+        }
+    }
+
+    /// Returns a free chunk and the items evicted to free it
+    fn allocate(&mut self, class: u8) -> Result<(u32, Vec<Evicted>)> {
+        if let Some(chunk) = self.pop_free(class) {
+            return Ok((chunk, Vec::new()));
+        }
+        if let Some(page) = self.arena.take_page() {
+            self.add_page(class, page);
+            return Ok((
+                self.pop_free(class).expect("New page has no chunks"),
+                Vec::new(),
+            ));
+        }
+        // evict least recently used item of this class
+        let victim = match self.classes[class as usize].lru_tail {
+            NIL => {
+                let evicted = self.reassign_page(class)?;
+                return Ok((
+                    self.pop_free(class).expect("New page has no chunks"),
+                    evicted,
+                ));
+            }
+            chunk => ChunkRef { class, chunk },
+        };
+        let key = Bytes::copy_from_slice(self.key(victim));
+        debug!("Evicting item from slab class {}", class);
+        let evicted = self.evicted(key, victim);
+        self.unlink_hash(self.hash(&evicted.0), victim);
+        self.release(victim);
+        let chunk = self.pop_free(class).expect("Evicted chunk not released");
+        Ok((chunk, vec![evicted]))
+    }
+
+    /// A class without pages cannot evict anything once the arena is
+    /// exhausted, so it takes the last page of the class holding the most
+    /// pages, like memcached's slab rebalancing. Items stored on that
+    /// page are evicted and returned.
+    fn reassign_page(&mut self, class: u8) -> Result<Vec<Evicted>> {
+        let donor = (0..self.classes.len() as u8)
+            .filter(|donor| *donor != class)
+            .max_by_key(|donor| self.classes[*donor as usize].pages.len())
+            .filter(|donor| !self.classes[*donor as usize].pages.is_empty())
+            .ok_or(CacheError::OutOfMemory)?;
+        let chunks_per_page = self.classes[donor as usize].chunks_per_page;
+        let first = ((self.classes[donor as usize].pages.len() - 1) * chunks_per_page) as u32;
+        let on_page = first..first + chunks_per_page as u32;
+        // keep free chunks of the remaining pages, in their order
+        let mut free_on_page = vec![false; chunks_per_page];
+        let mut kept = Vec::new();
+        while let Some(chunk) = self.pop_free(donor) {
+            match on_page.contains(&chunk) {
+                true => free_on_page[(chunk - first) as usize] = true,
+                false => kept.push(chunk),
+            }
+        }
+        for chunk in kept.into_iter().rev() {
+            self.push_free(ChunkRef {
+                class: donor,
+                chunk,
+            });
+        }
+        let mut evicted = Vec::new();
+        for chunk in on_page.filter(|chunk| !free_on_page[(chunk - first) as usize]) {
+            let victim = ChunkRef {
+                class: donor,
+                chunk,
+            };
+            let key = Bytes::copy_from_slice(self.key(victim));
+            self.unlink_hash(self.hash(&key), victim);
+            self.unlink(victim);
+            evicted.push(self.evicted(key, victim));
+        }
+        debug!(
+            "Moving page from slab class {} to {}, evicted {} items",
+            donor,
+            class,
+            evicted.len()
+        );
+        let page = self.classes[donor as usize].pages.pop().unwrap();
+        self.add_page(class, page);
+        Ok(evicted)
+    }
+
+    fn release(&mut self, chunk_ref: ChunkRef) {
+        self.unlink(chunk_ref);
+        self.push_free(chunk_ref);
+    }
+
+    pub fn get(&self, key: &KeyType) -> Option<ChunkRef> {
+        let mut current = self.index.get(&self.hash(key)).copied();
+        while let Some(chunk_ref) = current {
+            if self.key(chunk_ref) == key.as_ref() {
+                return Some(chunk_ref);
+            }
+            current = self.hash_next(chunk_ref);
+        }
+        None
+    }
+
+    pub fn header(&self, chunk_ref: ChunkRef) -> CacheMetaData {
+        let header = self.bytes(chunk_ref, CAS, VALUE_LEN);
+        CacheMetaData::new(
+            u64::from_le_bytes(header[CAS..FLAGS].try_into().unwrap()),
+            u32::from_le_bytes(header[FLAGS..TIME_TO_LIVE].try_into().unwrap()),
+            u32::from_le_bytes(header[TIME_TO_LIVE..VALUE_LEN].try_into().unwrap()),
+        )
+    }
+
+    fn set_header(&mut self, chunk_ref: ChunkRef, header: &CacheMetaData) {
+        let mut data = [0u8; VALUE_LEN];
+        data[CAS..FLAGS].copy_from_slice(&header.cas.to_le_bytes());
+        data[FLAGS..TIME_TO_LIVE].copy_from_slice(&header.flags.to_le_bytes());
+        data[TIME_TO_LIVE..VALUE_LEN].copy_from_slice(&header.time_to_live.to_le_bytes());
+        self.set_bytes(chunk_ref, CAS, &data);
+    }
+
+    /// Marks item as most recently used
+    pub fn touch(&mut self, chunk_ref: ChunkRef) {
+        self.unlink(chunk_ref);
+        self.link_head(chunk_ref);
+    }
+
+    /// Copies item out of the arena
+    pub fn read(&self, chunk_ref: ChunkRef) -> Record {
+        let value_len = self.u32_field(chunk_ref, VALUE_LEN) as usize;
+        let value = self.bytes(
+            chunk_ref,
+            ITEM_HEADER_LEN + self.key_len(chunk_ref),
+            value_len,
+        );
+        Record {
+            header: self.header(chunk_ref),
+            value: Bytes::copy_from_slice(value),
+        }
+    }
+
+    /// Stores item in the arena. Previous item stored under the same key
+    /// reuses its chunk if it is of the same class, otherwise it is
+    /// released only once the new chunk is allocated, so a failed write
+    /// keeps it. Returns the evicted items.
+    pub fn insert(&mut self, key: KeyType, record: Record) -> Result<Vec<Evicted>> {
+        if key.len() > u16::MAX as usize {
+            return Err(CacheError::InvalidArguments);
+        }
+        let class = self.class_for(ITEM_HEADER_LEN + key.len() + record.value.len())?;
+        let hash = self.hash(&key);
+        let (chunk, evicted) = match self.get(&key) {
+            Some(previous) if previous.class == class => {
+                self.unlink_hash(hash, previous);
+                self.unlink(previous);
+                (previous.chunk, Vec::new())
+            }
+            previous => {
+                let (chunk, mut evicted) = self.allocate(class)?;
+                if previous.is_some() {
+                    // a reassigned page may have held the previous item,
+                    // it is overwritten rather than evicted
+                    evicted.retain(|(evicted, _)| *evicted != key);
+                    self.remove(&key);
+                }
+                (chunk, evicted)
+            }
+        };
+        let chunk_ref = ChunkRef { class, chunk };
+        self.set_header(chunk_ref, &record.header);
+        self.set_u32_field(chunk_ref, VALUE_LEN, record.value.len() as u32);
+        self.set_bytes(chunk_ref, KEY_LEN, &(key.len() as u16).to_le_bytes());
+        self.set_bytes(chunk_ref, ITEM_HEADER_LEN, &key);
+        self.set_bytes(chunk_ref, ITEM_HEADER_LEN + key.len(), &record.value);
+        self.link_head(chunk_ref);
+        self.link_hash(hash, chunk_ref);
+        Ok(evicted)
+    }
+
+    /// Overwrites value of a stored item in place, LRU order is left
+    /// intact. Returns false if the new value has a different length.
+    pub fn write_value(&mut self, chunk_ref: ChunkRef, value: &[u8]) -> bool {
+        if self.u32_field(chunk_ref, VALUE_LEN) as usize != value.len() {
+            return false;
+        }
+        self.set_bytes(chunk_ref, ITEM_HEADER_LEN + self.key_len(chunk_ref), value);
+        true
+    }
+
+    pub fn remove(&mut self, key: &KeyType) -> Option<ChunkRef> {
+        let chunk_ref = self.get(key)?;
+        self.unlink_hash(self.hash(key), chunk_ref);
+        self.release(chunk_ref);
+        Some(chunk_ref)
+    }
+
+    pub fn clear(&mut self) {
+        for class in 0..self.classes.len() as u8 {
+            while let Some(chunk_ref) = self.lru_head(class) {
+                self.release(chunk_ref);
+            }
+        }
+        self.index.clear();
+        self.items = 0;
+    }
+
+    fn lru_head(&self, class: u8) -> Option<ChunkRef> {
+        match self.classes[class as usize].lru_head {
+            NIL => None,
+            chunk => Some(ChunkRef { class, chunk }),
+        }
+    }
+
+    /// Every stored item is on the LRU list of its class
+    fn stored_chunks(&self) -> Vec<ChunkRef> {
+        let mut chunks = Vec::with_capacity(self.items);
+        for class in 0..self.classes.len() as u8 {
+            let mut current = self.lru_head(class);
+            while let Some(chunk_ref) = current {
+                chunks.push(chunk_ref);
+                current = match self.u32_field(chunk_ref, NEXT) {
+                    NIL => None,
+                    chunk => Some(ChunkRef { class, chunk }),
+                };
+            }
+        }
+        chunks
+    }
+
+    pub fn for_each_header_mut(&mut self, mut f: impl FnMut(&mut CacheMetaData)) {
+        for chunk_ref in self.stored_chunks() {
+            let mut header = self.header(chunk_ref);
+            f(&mut header);
+            self.set_header(chunk_ref, &header);
+        }
+    }
+
+    /// Copies every item out of the arena, LRU order is left intact
+    pub fn for_each(&self, mut f: impl FnMut(&KeyType, Record)) {
+        for chunk_ref in self.stored_chunks() {
+            let key = Bytes::copy_from_slice(self.key(chunk_ref));
+            f(&key, self.read(chunk_ref));
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.items
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAGE_SIZE: usize = 1024;
+
+    fn record(len: usize) -> Record {
+        Record::new(Bytes::from(vec![b'a'; len]), 1, 0, 0)
+    }
+
+    #[test]
+    fn test_slab_class_sizes() {
+        let sizes = slab_class_sizes(1024 * 1024, 1.25);
+        assert_eq!(sizes[0], MIN_CHUNK_SIZE);
+        assert_eq!(*sizes.last().unwrap(), 1024 * 1024);
+        assert!(sizes.len() <= MAX_SLAB_CLASSES);
+        for pair in sizes.windows(2) {
+            assert!(pair[0] < pair[1]);
+            assert_eq!(pair[0] % CHUNK_ALIGN, 0);
+        }
+    }
+
+    #[test]
+    fn test_slab_class_sizes_small_factor() {
+        let sizes = slab_class_sizes(PAGE_SIZE, 1.01);
+        for pair in sizes.windows(2) {
+            assert!(pair[0] < pair[1]);
+        }
+    }
+
+    #[test]
+    fn test_arena_pages() {
+        let arena = Arena::new(4 * PAGE_SIZE as u64 + 10, PAGE_SIZE, false);
+        assert_eq!(arena.total_pages(), 4);
+        for page in 0..4 {
+            assert_eq!(arena.take_page(), Some(page));
+        }
+        assert_eq!(arena.take_page(), None);
+        assert_eq!(arena.used_pages(), 4);
+    }
+
+    #[test]
+    fn test_arena_huge_pages_fallback() {
+        let arena = Arena::new(2 * PAGE_SIZE as u64, PAGE_SIZE, true);
+        assert_eq!(arena.total_pages(), 2);
+    }
+
+    #[test]
+    fn test_insert_read_remove() {
+        let arena = Arena::new(PAGE_SIZE as u64, PAGE_SIZE, false);
+        let mut shard = SlabShard::new(Arc::new(arena), &slab_class_sizes(PAGE_SIZE, 2.0));
+        let key = Bytes::from("key");
+        let value = Record::new(Bytes::from("value"), 5, 7, 0);
+        assert!(shard.insert(key.clone(), value.clone()).is_ok());
+        let chunk = shard.get(&key).unwrap();
+        let found = shard.read(chunk);
+        assert_eq!(found, value);
+        assert_eq!(found.header.cas, 5);
+        assert_eq!(found.header.flags, 7);
+        assert!(shard.remove(&key).is_some());
+        assert!(shard.get(&key).is_none());
+        assert!(shard.is_empty());
+    }
+
+    #[test]
+    fn test_key_and_header_count_against_chunk() {
+        let arena = Arena::new(2 * PAGE_SIZE as u64, PAGE_SIZE, false);
+        let mut shard = SlabShard::new(Arc::new(arena), &slab_class_sizes(PAGE_SIZE, 2.0));
+        // 10 byte value fits a 64 byte chunk only with a short key
+        let short = Bytes::from("k");
+        let long = Bytes::from(vec![b'k'; 30]);
+        assert!(shard.insert(short.clone(), record(10)).is_ok());
+        assert!(shard.insert(long.clone(), record(10)).is_ok());
+        assert_eq!(shard.get(&short).unwrap().class, 0);
+        assert_eq!(shard.get(&long).unwrap().class, 1);
+        assert_eq!(shard.read(shard.get(&long).unwrap()), record(10));
+    }
+
+    #[test]
+    fn test_too_large_value() {
+        let arena = Arena::new(PAGE_SIZE as u64, PAGE_SIZE, false);
+        let mut shard = SlabShard::new(Arc::new(arena), &slab_class_sizes(PAGE_SIZE, 2.0));
+        let result = shard.insert(Bytes::from("key"), record(PAGE_SIZE + 1));
+        assert_eq!(result, Err(CacheError::ValueTooLarge));
+    }
+
+    #[test]
+    fn test_lru_eviction_within_class() {
+        // one page of 64 byte chunks holds 16 items
+        let arena = Arena::new(PAGE_SIZE as u64, PAGE_SIZE, false);
+        let mut shard = SlabShard::new(Arc::new(arena), &slab_class_sizes(PAGE_SIZE, 2.0));
+        for idx in 0..16 {
+            let key = Bytes::from(format!("key{}", idx));
+            assert!(shard.insert(key, record(10)).is_ok());
+        }
+        // key0 becomes most recently used, key1 is evicted
+        let key0 = Bytes::from("key0");
+        shard.touch(shard.get(&key0).unwrap());
+        assert_eq!(
+            shard.insert(Bytes::from("new"), record(10)),
+            Ok(vec![(Bytes::from("key1"), None)])
+        );
+        assert_eq!(shard.len(), 16);
+        assert!(shard.get(&key0).is_some());
+        assert!(shard.get(&Bytes::from("key1")).is_none());
+        assert!(shard.get(&Bytes::from("new")).is_some());
+    }
+
+    #[test]
+    fn test_evicted_records_are_read_if_asked() {
+        let arena = Arena::new(2 * PAGE_SIZE as u64, PAGE_SIZE, false);
+        let mut shard = SlabShard::new(Arc::new(arena), &slab_class_sizes(PAGE_SIZE, 2.0));
+        shard.read_evicted(true);
+        for idx in 0..32 {
+            let key = Bytes::from(format!("key{}", idx));
+            assert!(shard.insert(key, record(10)).is_ok());
+        }
+        assert_eq!(
+            shard.insert(Bytes::from("new"), record(10)),
+            Ok(vec![(Bytes::from("key0"), Some(record(10)))])
+        );
+        let evicted = shard.insert(Bytes::from("large"), record(500)).unwrap();
+        assert_eq!(evicted.len(), 16);
+        assert!(evicted
+            .iter()
+            .all(|(_, evicted)| *evicted == Some(record(10))));
+    }
+
+    #[test]
+    fn test_value_is_written_in_place() {
+        let arena = Arena::new(PAGE_SIZE as u64, PAGE_SIZE, false);
+        let mut shard = SlabShard::new(Arc::new(arena), &slab_class_sizes(PAGE_SIZE, 2.0));
+        let key = Bytes::from("key");
+        assert!(shard.insert(key.clone(), record(10)).is_ok());
+        let chunk = shard.get(&key).unwrap();
+        assert!(!shard.write_value(chunk, b"short"));
+        assert!(shard.write_value(chunk, b"bbbbbbbbbb"));
+        let expected = Record::new(Bytes::from("bbbbbbbbbb"), 1, 0, 0);
+        assert_eq!(shard.read(chunk), expected);
+    }
+
+    #[test]
+    fn test_page_moves_to_class_without_pages() {
+        let arena = Arena::new(2 * PAGE_SIZE as u64, PAGE_SIZE, false);
+        let mut shard = SlabShard::new(Arc::new(arena), &slab_class_sizes(PAGE_SIZE, 2.0));
+        // class 0 takes both pages, 16 items each
+        for idx in 0..32 {
+            let key = Bytes::from(format!("key{}", idx));
+            assert!(shard.insert(key, record(10)).is_ok());
+        }
+        assert!(shard.remove(&Bytes::from("key20")).is_some());
+        // the last page of class 0 is moved, its items are evicted
+        let evicted = shard.insert(Bytes::from("large"), record(500)).unwrap();
+        let expected: Vec<Evicted> = (16..32)
+            .filter(|idx| *idx != 20)
+            .map(|idx| (Bytes::from(format!("key{}", idx)), None))
+            .collect();
+        assert_eq!(evicted, expected);
+        assert_eq!(shard.len(), 17);
+        assert_eq!(shard.classes[0].pages.len(), 1);
+        assert!(shard.get(&Bytes::from("key0")).is_some());
+        assert_eq!(
+            shard.read(shard.get(&Bytes::from("large")).unwrap()),
+            record(500)
+        );
+        // free chunks of the moved page are gone from class 0
+        assert!(shard.pop_free(0).is_none());
+    }
+
+    #[test]
+    fn test_overwrite_evicted_by_page_move() {
+        let arena = Arena::new(PAGE_SIZE as u64, PAGE_SIZE, false);
+        let mut shard = SlabShard::new(Arc::new(arena), &slab_class_sizes(PAGE_SIZE, 2.0));
+        let key = Bytes::from("key");
+        assert!(shard.insert(key.clone(), record(10)).is_ok());
+        assert!(shard.insert(Bytes::from("other"), record(10)).is_ok());
+        // the previous item is overwritten, not reported as evicted
+        let evicted = shard.insert(key.clone(), record(500)).unwrap();
+        assert_eq!(evicted, vec![(Bytes::from("other"), None)]);
+        assert_eq!(shard.len(), 1);
+        let chunk = shard.get(&key).unwrap();
+        assert_eq!(shard.read(chunk), record(500));
+    }
+
+    #[test]
+    fn test_overwrite_reuses_chunk_of_same_class() {
+        // one page of 64 byte chunks holds 16 items
+        let arena = Arena::new(PAGE_SIZE as u64, PAGE_SIZE, false);
+        let mut shard = SlabShard::new(Arc::new(arena), &slab_class_sizes(PAGE_SIZE, 2.0));
+        for idx in 0..16 {
+            let key = Bytes::from(format!("key{}", idx));
+            assert!(shard.insert(key, record(10)).is_ok());
+        }
+        let key = Bytes::from("key0");
+        assert_eq!(shard.insert(key.clone(), record(20)), Ok(Vec::new()));
+        assert_eq!(shard.len(), 16);
+        let chunk = shard.get(&key).unwrap();
+        assert_eq!(shard.read(chunk), record(20));
+    }
+
+    #[test]
+    fn test_clear_releases_chunks() {
+        let arena = Arena::new(PAGE_SIZE as u64, PAGE_SIZE, false);
+        let mut shard = SlabShard::new(Arc::new(arena), &slab_class_sizes(PAGE_SIZE, 2.0));
+        for idx in 0..16 {
+            let key = Bytes::from(format!("key{}", idx));
+            assert!(shard.insert(key, record(10)).is_ok());
+        }
+        shard.clear();
+        assert!(shard.is_empty());
+        let free = std::iter::from_fn(|| shard.pop_free(0)).count();
+        assert_eq!(free, 16);
+        assert_eq!(shard.classes[0].lru_head, NIL);
+        assert_eq!(shard.classes[0].lru_tail, NIL);
+        assert!(shard.index.is_empty());
+    }
+}
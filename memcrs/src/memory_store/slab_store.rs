@@ -0,0 +1,436 @@
+use crate::cache::cache::{
+    Cache, CacheMetaData, CacheStats, DeltaParam, DeltaResult, KeyType, Record, ScanPage, SetStatus,
+};
+use crate::cache::cache_capability::CacheCapability;
+use crate::cache::error::{CacheError, Result};
+use crate::cache::events::{RemovalReason, SharedListener};
+use crate::cache::eviction_policy::EvictionPolicy;
+use crate::memcache::cli::parser::SlabConfig;
+use crate::memory_store::parallelism::get_number_of_shards;
+use crate::memory_store::shared_store_state::SharedStoreState;
+use crate::memory_store::slab_allocator::{
+    slab_class_sizes, Arena, ChunkRef, Evicted, SlabShard, MAX_ITEM_OVERHEAD,
+};
+use crate::protocol::binary::network::DELTA_NO_INITIAL_VALUE;
+use crate::server::timer;
+
+use bytes::{Bytes, BytesMut};
+use std::hash::{BuildHasher, RandomState};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// Store keeping values in preallocated slab classes, like memcached.
+///
+/// Memory usage never exceeds configured memory limit, once there are no
+/// free pages left least recently used item of the same slab class is
+/// evicted.
+pub struct SlabMemoryStore {
+    shards: Vec<Mutex<SlabShard>>,
+    hasher: RandomState,
+    store_state: SharedStoreState,
+}
+
+impl SlabMemoryStore {
+    // every shard should be able to get a few pages for its slab classes
+    const MIN_PAGES_PER_SHARD: usize = 16;
+
+    pub fn new(timer: Arc<dyn timer::Timer + Send + Sync>, cfg: SlabConfig) -> SlabMemoryStore {
+        // a value of the page size still fits a chunk with its key
+        let page_size = cfg.slab_page_size as usize + MAX_ITEM_OVERHEAD;
+        let arena = Arc::new(Arena::new(cfg.memory_limit, page_size, cfg.slab_huge_pages));
+        let class_sizes = slab_class_sizes(page_size, cfg.slab_growth_factor);
+        let parallelism = std::thread::available_parallelism().map_or(1, usize::from);
+        let max_shards = (arena.total_pages() / SlabMemoryStore::MIN_PAGES_PER_SHARD).max(1);
+        let shards = get_number_of_shards(parallelism).min(max_shards);
+        info!(
+            "Slab pages: {}, slab classes: {}, number of shards: {}",
+            arena.total_pages(),
+            class_sizes.len(),
+            shards
+        );
+        SlabMemoryStore {
+            shards: (0..shards)
+                .map(|_| Mutex::new(SlabShard::new(arena.clone(), &class_sizes)))
+                .collect(),
+            hasher: RandomState::new(),
+            store_state: SharedStoreState::new(timer),
+        }
+    }
+
+    fn shard_index(&self, key: &KeyType) -> usize {
+        (self.hasher.hash_one(key) as usize) % self.shards.len()
+    }
+
+    fn shard(&self, key: &KeyType) -> MutexGuard<'_, SlabShard> {
+        self.shards[self.shard_index(key)].lock().unwrap()
+    }
+
+    /// Returns record stored under key, expired records are removed
+    fn get_live(&self, shard: &mut SlabShard, key: &KeyType) -> Option<Record> {
+        let chunk = shard.get(key)?;
+        let record = shard.read(chunk);
+        if self.store_state.check_if_expired(key, &record) {
+            shard.remove(key);
+            self.store_state
+                .dropped(key, &record, RemovalReason::Expired);
+            return None;
+        }
+        shard.touch(chunk);
+        Some(record)
+    }
+
+    /// Inserts record, reports the item it overwrites or evicts. The
+    /// previous item can be released even if the new one does not fit.
+    fn insert_locked(&self, shard: &mut SlabShard, key: KeyType, record: Record) -> Result<()> {
+        let prev_record = shard
+            .get(&key)
+            .map(|chunk| self.overwritten_record(shard, &key, chunk));
+        let inserted = shard.insert(key.clone(), record);
+        if let Some(prev_record) = prev_record {
+            if inserted.is_ok() || shard.get(&key).is_none() {
+                self.store_state.overwritten(&key, &prev_record);
+            }
+        }
+        self.evicted(inserted?);
+        Ok(())
+    }
+
+    /// Record of an item about to be overwritten, its value is only
+    /// copied out if it expired and a listener wants it
+    fn overwritten_record(&self, shard: &SlabShard, key: &KeyType, chunk: ChunkRef) -> Record {
+        let record = Record {
+            header: shard.header(chunk),
+            value: Bytes::new(),
+        };
+        if self.store_state.wants_records() && self.store_state.check_if_expired(key, &record) {
+            return shard.read(chunk);
+        }
+        record
+    }
+
+    fn evicted(&self, evicted: Vec<Evicted>) {
+        for (key, record) in evicted {
+            match record {
+                Some(record) => self.store_state.dropped(&key, &record, RemovalReason::Size),
+                None => self.store_state.removed(&key, RemovalReason::Size),
+            }
+        }
+    }
+
+    fn set_locked(
+        &self,
+        shard: &mut SlabShard,
+        key: KeyType,
+        mut record: Record,
+    ) -> Result<SetStatus> {
+        if let Some(chunk) = shard.get(&key) {
+            if SharedStoreState::cas_mismatch(&record, shard.header(chunk).cas) {
+                return Err(CacheError::KeyExists);
+            }
+        }
+        let cas = self.store_state.set_cas_ttl(&mut record);
+        self.insert_locked(shard, key, record)?;
+        Ok(SetStatus { cas })
+    }
+
+    fn delete_locked(
+        &self,
+        shard: &mut SlabShard,
+        key: KeyType,
+        header: CacheMetaData,
+    ) -> Result<Record> {
+        let chunk = shard.get(&key).ok_or(CacheError::NotFound)?;
+        let record = shard.read(chunk);
+        if header.cas != 0 && record.header.cas != header.cas {
+            return Err(CacheError::KeyExists);
+        }
+        shard.remove(&key);
+        self.store_state.removed(&key, RemovalReason::Explicit);
+        Ok(record)
+    }
+
+    fn append_prepend_common(
+        &self,
+        key: KeyType,
+        mut new_record: Record,
+        is_append: bool,
+    ) -> Result<SetStatus> {
+        let mut shard = self.shard(&key);
+        let prev_record = match self.get_live(&mut shard, &key) {
+            Some(record) => record,
+            None => return Err(CacheError::ItemNotStored),
+        };
+        if SharedStoreState::cas_mismatch(&new_record, prev_record.header.cas) {
+            return Err(CacheError::KeyExists);
+        }
+        let cas = self.store_state.set_cas_ttl(&mut new_record);
+        let mut new_value =
+            BytesMut::with_capacity(prev_record.value.len() + new_record.value.len());
+        if is_append {
+            new_value.extend_from_slice(&prev_record.value);
+            new_value.extend_from_slice(&new_record.value);
+        } else {
+            new_value.extend_from_slice(&new_record.value);
+            new_value.extend_from_slice(&prev_record.value);
+        }
+        new_record.value = new_value.freeze();
+        self.insert_locked(&mut shard, key, new_record)?;
+        Ok(SetStatus { cas })
+    }
+}
+
+impl Cache for SlabMemoryStore {
+    /// Returns a value associated with a key
+    fn get(&self, key: &KeyType) -> Result<Record> {
+        let mut shard = self.shard(key);
+        self.get_live(&mut shard, key).ok_or(CacheError::NotFound)
+    }
+
+    fn set(&self, key: KeyType, record: Record) -> Result<SetStatus> {
+        let mut shard = self.shard(&key);
+        self.set_locked(&mut shard, key, record)
+    }
+
+    fn delete(&self, key: KeyType, header: CacheMetaData) -> Result<Record> {
+        let mut shard = self.shard(&key);
+        self.delete_locked(&mut shard, key, header)
+    }
+
+    fn flush(&self, header: CacheMetaData) {
+        for shard in &self.shards {
+            let mut shard = shard.lock().unwrap();
+            if header.time_to_live > 0 {
+                let ttl = self.store_state.expiration(header.time_to_live);
+                shard.for_each_header_mut(|item_header| item_header.time_to_live = ttl);
+            } else {
+                shard.clear();
+            }
+        }
+        self.store_state.flushed(header.time_to_live);
+    }
+
+    fn run_pending_tasks(&self) {}
+
+    /// Adds a new key-value pair to the cache, but only if the key does not already exist.
+    /// If the key exists, the operation fails with KeyExists error.
+    fn add(&self, key: KeyType, mut record: Record) -> Result<SetStatus> {
+        let cas = self.store_state.set_cas_ttl(&mut record);
+        let mut shard = self.shard(&key);
+        if shard.get(&key).is_some() {
+            return Err(CacheError::KeyExists);
+        }
+        self.insert_locked(&mut shard, key, record)?;
+        Ok(SetStatus { cas })
+    }
+
+    /// Replaces the value of an existing key in the cache, but only if the key already exists.
+    /// If the key does not exist, the operation fails with NotFound error.
+    fn replace(&self, key: KeyType, mut record: Record) -> Result<SetStatus> {
+        let mut shard = self.shard(&key);
+        let chunk = shard.get(&key).ok_or(CacheError::NotFound)?;
+        if SharedStoreState::cas_mismatch(&record, shard.header(chunk).cas) {
+            return Err(CacheError::KeyExists);
+        }
+        let cas = self.store_state.set_cas_ttl(&mut record);
+        self.insert_locked(&mut shard, key, record)?;
+        Ok(SetStatus { cas })
+    }
+
+    /// Appends the new value to the existing value for the given key.
+    /// The key must already exist in the cache, otherwise the operation fails with NotFound error.
+    fn append(&self, key: KeyType, new_record: Record) -> Result<SetStatus> {
+        self.append_prepend_common(key, new_record, true)
+    }
+
+    /// Prepends the new value to the existing value for the given key.
+    /// The key must already exist in the cache, otherwise the operation fails with NotFound error.
+    fn prepend(&self, key: KeyType, new_record: Record) -> Result<SetStatus> {
+        self.append_prepend_common(key, new_record, false)
+    }
+
+    /// Performs an arithmetic operation (increment or decrement) on a numeric value stored in the cache.
+    /// If `increment` is true, adds `delta` to the value; otherwise, subtracts `delta`.
+    /// The value must be a valid unsigned 64-bit integer.
+    /// Returns the new value after the operation.
+    fn incr_decr(
+        &self,
+        header: CacheMetaData,
+        key: KeyType,
+        delta: DeltaParam,
+        increment: bool,
+    ) -> Result<DeltaResult> {
+        let mut shard = self.shard(&key);
+        match shard.get(&key) {
+            Some(chunk) => {
+                let mut record = shard.read(chunk);
+                let tmp_record = Record::new(Bytes::new(), header.cas, 0, 0);
+                if SharedStoreState::cas_mismatch(&tmp_record, record.header.cas) {
+                    return Err(CacheError::KeyExists);
+                }
+                let value = self
+                    .store_state
+                    .incr_decr_common(&record, delta, increment)?;
+                let cas = self.store_state.get_cas_id();
+                record.value = Bytes::from(value.to_string());
+                record.header.cas = cas;
+                self.insert_locked(&mut shard, key, record)?;
+                Ok(DeltaResult { cas, value })
+            }
+            None => {
+                if header.get_expiration() == DELTA_NO_INITIAL_VALUE {
+                    return Err(CacheError::NotFound);
+                }
+                let cas = self.store_state.get_cas_id();
+                let record = Record::new(
+                    Bytes::from(delta.value.to_string()),
+                    cas,
+                    0,
+                    self.store_state.expiration(header.get_expiration()),
+                );
+                self.insert_locked(&mut shard, key, record)?;
+                Ok(DeltaResult {
+                    cas,
+                    value: delta.value,
+                })
+            }
+        }
+    }
+
+    fn for_each_item(&self, f: &mut dyn FnMut(&KeyType, &Record)) {
+        // items of a shard are copied out under its lock, `f` may do I/O
+        // and must not block writers of the shard
+        let mut items = Vec::new();
+        for shard in &self.shards {
+            shard.lock().unwrap().for_each(|key, record| {
+                if !self.store_state.check_if_expired(key, &record) {
+                    items.push((key.clone(), record));
+                }
+            });
+            items.drain(..).for_each(|(key, record)| f(&key, &record));
+        }
+    }
+
+    fn restore(&self, key: KeyType, record: Record) -> Result<()> {
+        let mut shard = self.shard(&key);
+        // key was written after the snapshot was taken, it is newer
+        if shard.get(&key).is_some() {
+            return Err(CacheError::KeyExists);
+        }
+        self.store_state.advance_cas_id(record.header.cas);
+        self.insert_locked(&mut shard, key, record)
+    }
+
+    /// Value of the same length is written in place, otherwise the item
+    /// is stored again and becomes most recently used
+    fn relocate(&self, key: KeyType, record: Record) -> Result<()> {
+        let mut shard = self.shard(&key);
+        let chunk = shard.get(&key).ok_or(CacheError::NotFound)?;
+        let header = shard.header(chunk);
+        if header.cas != record.header.cas {
+            return Err(CacheError::KeyExists);
+        }
+        if !shard.write_value(chunk, &record.value) {
+            let evicted = shard.insert(
+                key,
+                Record {
+                    header,
+                    value: record.value,
+                },
+            )?;
+            self.evicted(evicted);
+        }
+        Ok(())
+    }
+
+    fn add_stats(&self, stats: &mut CacheStats) {
+        self.store_state.add_stats(stats)
+    }
+
+    fn subscribe(&self, listener: SharedListener) {
+        if listener.wants_records() {
+            for shard in &self.shards {
+                shard.lock().unwrap().read_evicted(true);
+            }
+        }
+        self.store_state.subscribe(listener)
+    }
+
+    /// Keys of the same shard are read under one lock
+    fn get_multi(&self, keys: &[KeyType]) -> Vec<Result<Record>> {
+        let mut results: Vec<Result<Record>> =
+            keys.iter().map(|_| Err(CacheError::NotFound)).collect();
+        for (shard, positions) in
+            SharedStoreState::group_by_shard(keys.iter(), |key| self.shard_index(key))
+        {
+            let mut shard = self.shards[shard].lock().unwrap();
+            for position in positions {
+                results[position] = self
+                    .get_live(&mut shard, &keys[position])
+                    .ok_or(CacheError::NotFound);
+            }
+        }
+        results
+    }
+
+    /// Items of the same shard are written under one lock
+    fn set_multi(&self, items: Vec<(KeyType, Record)>) -> Vec<Result<SetStatus>> {
+        let groups = SharedStoreState::group_by_shard(items.iter().map(|(key, _)| key), |key| {
+            self.shard_index(key)
+        });
+        let mut items: Vec<Option<(KeyType, Record)>> = items.into_iter().map(Some).collect();
+        let mut results: Vec<Result<SetStatus>> =
+            items.iter().map(|_| Err(CacheError::NotFound)).collect();
+        for (shard, positions) in groups {
+            let mut shard = self.shards[shard].lock().unwrap();
+            for position in positions {
+                if let Some((key, record)) = items[position].take() {
+                    results[position] = self.set_locked(&mut shard, key, record);
+                }
+            }
+        }
+        results
+    }
+
+    /// Keys of the same shard are removed under one lock
+    fn delete_multi(&self, items: Vec<(KeyType, CacheMetaData)>) -> Vec<Result<Record>> {
+        let groups = SharedStoreState::group_by_shard(items.iter().map(|(key, _)| key), |key| {
+            self.shard_index(key)
+        });
+        let mut items: Vec<Option<(KeyType, CacheMetaData)>> =
+            items.into_iter().map(Some).collect();
+        let mut results: Vec<Result<Record>> =
+            items.iter().map(|_| Err(CacheError::NotFound)).collect();
+        for (shard, positions) in groups {
+            let mut shard = self.shards[shard].lock().unwrap();
+            for position in positions {
+                if let Some((key, header)) = items[position].take() {
+                    results[position] = self.delete_locked(&mut shard, key, header);
+                }
+            }
+        }
+        results
+    }
+
+    /// Every shard is a scan segment, locked while it is scanned
+    fn scan(&self, cursor: u64, count: usize, prefix: &[u8]) -> Result<ScanPage> {
+        self.store_state.scan_segments(
+            cursor,
+            count,
+            prefix,
+            self.shards.len(),
+            |shard, collector| {
+                let shard = self.shards[shard].lock().unwrap();
+                shard.for_each(|key, record| {
+                    if !self.store_state.check_if_expired(key, &record) {
+                        collector.offer(self.hasher.hash_one(key), key, &record);
+                    }
+                });
+            },
+        )
+    }
+}
+
+impl CacheCapability for SlabMemoryStore {
+    fn is_policy_supported(&self, policy: EvictionPolicy) -> bool {
+        policy == EvictionPolicy::LeastRecentlyUsed
+    }
+}
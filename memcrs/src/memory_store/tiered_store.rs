@@ -0,0 +1,622 @@
+use crate::cache::cache::{
+    Cache, CacheMetaData, CacheStats, DeltaParam, DeltaResult, KeyType, Record, ScanPage, SetStatus,
+};
+use crate::cache::error::{CacheError, Result};
+use crate::cache::events::{CacheEvent, CacheListener, RemovalReason, SharedListener};
+use crate::memory_store::ext_store::{ExtItem, ExtLocation, ExtStore, ExtStoreConfig};
+use crate::memory_store::shared_store_state::SharedStoreState;
+use crate::server::timer;
+
+use bytes::{Bytes, BytesMut};
+use dashmap::DashMap;
+use futures::future::BoxFuture;
+use std::io;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::Arc;
+
+const MARKER_LEN: usize = 16;
+/// marker | page u32 | generation u32 | offset u32 | len u32
+const POINTER_LEN: usize = MARKER_LEN + 16;
+/// pages with less live data are compacted
+const COMPACTION_RATIO: f64 = 0.5;
+/// smallest value worth moving out of memory
+pub const MIN_EXT_ITEM_SIZE: u64 = 64;
+
+/// Wrapper keeping large values in a page file, extstore-style.
+///
+/// Values larger than the configured item size are appended to the page
+/// file and the wrapped store keeps a record with all the metadata and a
+/// small pointer instead of the value. Pointers start with a marker chosen
+/// at random on startup, so no client value is mistaken for a pointer.
+///
+/// Values are read back with positioned reads and no lock is held,
+/// `get_async` reads on the blocking pool so workers keep serving other
+/// clients. Space of overwritten and deleted values is reclaimed by
+/// compacting pages from `run_pending_tasks`, rescued values keep their CAS.
+///
+/// Records with pointers evicted by the wrapped store stay in an index of
+/// the tier and are moved back to the store once their key is used again,
+/// values kept in memory are dropped on eviction. Scans leave evicted
+/// items out.
+pub struct TieredMemoryStore {
+    store: Arc<dyn Cache + Send + Sync>,
+    tier: Arc<Tier>,
+    store_state: SharedStoreState,
+    last_compaction: AtomicU32,
+    /// `ExtStore::reused_pages` when stale evicted records were dropped
+    purged: AtomicU64,
+}
+
+/// Page file and records evicted from the wrapped store, subscribed to
+/// the store to learn about evicted and expired items
+struct Tier {
+    ext: Arc<ExtStore>,
+    item_size: usize,
+    marker: [u8; MARKER_LEN],
+    /// evicted records, their values are pointers
+    evicted: DashMap<KeyType, Record>,
+}
+
+impl Tier {
+    fn location(&self, value: &Bytes) -> Option<ExtLocation> {
+        if value.len() != POINTER_LEN || value[..MARKER_LEN] != self.marker {
+            return None;
+        }
+        let field = |index: usize| {
+            let start = MARKER_LEN + index * 4;
+            u32::from_be_bytes(value[start..start + 4].try_into().unwrap())
+        };
+        Some(ExtLocation {
+            page: field(0),
+            generation: field(1),
+            offset: field(2),
+            len: field(3),
+        })
+    }
+
+    fn pointer(&self, location: ExtLocation) -> Bytes {
+        let mut pointer = BytesMut::with_capacity(POINTER_LEN);
+        pointer.extend_from_slice(&self.marker);
+        pointer.extend_from_slice(&location.page.to_be_bytes());
+        pointer.extend_from_slice(&location.generation.to_be_bytes());
+        pointer.extend_from_slice(&location.offset.to_be_bytes());
+        pointer.extend_from_slice(&location.len.to_be_bytes());
+        pointer.freeze()
+    }
+
+    /// Moves large value to the page file, value stays in memory
+    /// if it cannot be written
+    fn spill(&self, key: &KeyType, record: Record) -> (Record, Option<ExtLocation>) {
+        if record.value.len() <= self.item_size || !self.ext.fits(key, record.value.len()) {
+            return (record, None);
+        }
+        match self.ext.write(key, &record.value) {
+            Ok(location) => (
+                Record {
+                    header: record.header,
+                    value: self.pointer(location),
+                },
+                Some(location),
+            ),
+            Err(err) => {
+                warn!("Cannot write value to page file: {}", err);
+                (record, None)
+            }
+        }
+    }
+
+    fn release(&self, key: &KeyType, location: Option<ExtLocation>) {
+        if let Some(location) = location {
+            self.ext.release(key, location);
+        }
+    }
+}
+
+impl CacheListener for Tier {
+    fn on_event(&self, _event: &CacheEvent) {}
+
+    fn wants_records(&self) -> bool {
+        true
+    }
+
+    /// Evicted records keep their values in the page file, expired
+    /// ones release them
+    fn on_dropped(&self, key: &KeyType, record: &Record, reason: RemovalReason) {
+        let location = match self.location(&record.value) {
+            Some(location) => location,
+            None => return,
+        };
+        match reason {
+            RemovalReason::Size if self.ext.contains(location) => {
+                if let Some(previous) = self.evicted.insert(key.clone(), record.clone()) {
+                    self.release(key, self.location(&previous.value));
+                }
+            }
+            _ => self.ext.release(key, location),
+        }
+    }
+}
+
+impl TieredMemoryStore {
+    pub fn new(
+        store: Arc<dyn Cache + Send + Sync>,
+        config: &ExtStoreConfig,
+        timer: Arc<dyn timer::Timer + Send + Sync>,
+    ) -> io::Result<TieredMemoryStore> {
+        let mut marker: [u8; MARKER_LEN] = rand::random();
+        // never a digit, incr and decr fail on pointers
+        marker[0] = 0xFF;
+        let tier = Arc::new(Tier {
+            ext: Arc::new(ExtStore::open(config)?),
+            item_size: config.item_size.max(MIN_EXT_ITEM_SIZE) as usize,
+            marker,
+            evicted: DashMap::new(),
+        });
+        store.subscribe(tier.clone());
+        Ok(TieredMemoryStore {
+            store,
+            tier,
+            store_state: SharedStoreState::new(timer),
+            last_compaction: AtomicU32::new(0),
+            purged: AtomicU64::new(0),
+        })
+    }
+
+    pub fn ext_store(&self) -> &ExtStore {
+        &self.tier.ext
+    }
+
+    /// Compacts at most one page, returns true if a page was reclaimed
+    pub fn compact(&self) -> bool {
+        let (page, generation) = match self.tier.ext.compaction_candidate(COMPACTION_RATIO) {
+            Some(candidate) => candidate,
+            None => return false,
+        };
+        match self.tier.ext.read_page(page) {
+            Ok(items) => items.into_iter().for_each(|item| self.rescue(item)),
+            Err(err) => error!("Cannot read page {} for compaction: {}", page, err),
+        }
+        self.tier.ext.free_page(page, generation);
+        true
+    }
+
+    /// Writes value which is still referenced to the active page, the
+    /// pointer is swapped in place so the item keeps its CAS
+    fn rescue(&self, item: ExtItem) {
+        if self.rescue_evicted(&item) {
+            return;
+        }
+        let record = match self.store.get(&item.key) {
+            Ok(record) => record,
+            Err(_) => return,
+        };
+        if self.tier.location(&record.value) != Some(item.location) {
+            return;
+        }
+        let location = match self.tier.ext.write(&item.key, &item.value) {
+            Ok(location) => location,
+            Err(err) => {
+                warn!(
+                    "Cannot rescue value from page {}: {}",
+                    item.location.page, err
+                );
+                return;
+            }
+        };
+        let moved = Record {
+            header: record.header,
+            value: self.tier.pointer(location),
+        };
+        // fails if the item was changed after it was read
+        if self.store.relocate(item.key.clone(), moved).is_err() {
+            self.tier.ext.release(&item.key, location);
+        }
+    }
+
+    /// Rescues value of an evicted record, returns false if the key
+    /// was not evicted
+    fn rescue_evicted(&self, item: &ExtItem) -> bool {
+        let mut record = match self.tier.evicted.get_mut(&item.key) {
+            Some(record) => record,
+            None => return false,
+        };
+        if self.tier.location(&record.value) == Some(item.location) {
+            match self.tier.ext.write(&item.key, &item.value) {
+                Ok(location) => record.value = self.tier.pointer(location),
+                Err(err) => warn!(
+                    "Cannot rescue value from page {}: {}",
+                    item.location.page, err
+                ),
+            }
+        }
+        true
+    }
+
+    /// Moves evicted record back to the wrapped store, so every
+    /// operation finds the item there
+    fn thaw(&self, key: &KeyType) {
+        if !self.tier.evicted.contains_key(key) {
+            return;
+        }
+        let record = match self.tier.evicted.remove(key) {
+            Some((_key, record)) => record,
+            None => return,
+        };
+        let location = self.tier.location(&record.value);
+        if self.store_state.check_if_expired(key, &record) {
+            self.tier.release(key, location);
+            return;
+        }
+        // key written after the eviction holds a newer item
+        if let Err(err) = self.store.restore(key.clone(), record) {
+            debug!("Evicted item not restored: {:?}", err);
+            self.tier.release(key, location);
+        }
+    }
+
+    /// Drops evicted records whose page was reused or which expired, the
+    /// index is only walked after a page was reused
+    fn purge_evicted(&self) {
+        let reused = self.tier.ext.reused_pages();
+        if self.purged.swap(reused, Ordering::Relaxed) == reused {
+            return;
+        }
+        self.tier
+            .evicted
+            .retain(|key, record| match self.tier.location(&record.value) {
+                Some(location) if !self.tier.ext.contains(location) => false,
+                location if self.store_state.check_if_expired(key, record) => {
+                    self.tier.release(key, location);
+                    false
+                }
+                _ => true,
+            });
+    }
+
+    /// Returns None if the value is no longer in the page file
+    fn load(&self, key: &KeyType, record: &Record) -> Option<Record> {
+        match self.tier.location(&record.value) {
+            Some(location) => self.loaded(record, self.tier.ext.read(key, location)),
+            None => Some(record.clone()),
+        }
+    }
+
+    /// Like load, but the page file is read on the blocking pool
+    async fn load_async(&self, key: &KeyType, record: &Record) -> Option<Record> {
+        let location = match self.tier.location(&record.value) {
+            Some(location) => location,
+            None => return Some(record.clone()),
+        };
+        let ext = self.tier.ext.clone();
+        let read_key = key.clone();
+        let value = tokio::task::spawn_blocking(move || ext.read(&read_key, location))
+            .await
+            .unwrap_or_else(|err| Err(io::Error::other(err)));
+        self.loaded(record, value)
+    }
+
+    fn loaded(&self, record: &Record, value: io::Result<Option<Bytes>>) -> Option<Record> {
+        match value {
+            Ok(value) => value.map(|value| Record {
+                header: record.header.clone(),
+                value,
+            }),
+            Err(err) => {
+                error!("Cannot read value from page file: {}", err);
+                None
+            }
+        }
+    }
+
+    /// Like load, but drops the item which is no longer in the page file
+    fn load_or_drop(&self, key: &KeyType, record: Record) -> Result<Record> {
+        let loaded = self.load(key, &record);
+        self.found_or_drop(key, record, loaded)
+    }
+
+    fn found_or_drop(
+        &self,
+        key: &KeyType,
+        record: Record,
+        loaded: Option<Record>,
+    ) -> Result<Record> {
+        match loaded {
+            Some(record) => Ok(record),
+            None => {
+                // page was reused, item is gone
+                let header = CacheMetaData::new(record.header.cas, 0, 0);
+                let _ = self.store.delete(key.clone(), header);
+                Err(CacheError::NotFound)
+            }
+        }
+    }
+
+    /// Releases page file space of the new value if it was not stored
+    fn complete<T>(
+        &self,
+        key: &KeyType,
+        location: Option<ExtLocation>,
+        result: Result<T>,
+    ) -> Result<T> {
+        if result.is_err() {
+            self.tier.release(key, location);
+        }
+        result
+    }
+
+    /// Writes `record` over the item which was read just before, so the
+    /// page file space of exactly the overwritten value is released. A
+    /// missing item is added unless `must_exist`, CAS of the client is
+    /// checked against the read item.
+    fn overwrite(&self, key: &KeyType, record: Record, must_exist: bool) -> Result<SetStatus> {
+        let cas = record.header.cas;
+        loop {
+            let current = match self.store.get(key) {
+                Ok(current) => current,
+                Err(CacheError::NotFound) if must_exist => return Err(CacheError::NotFound),
+                // the store decides what CAS of a missing item means
+                Err(CacheError::NotFound) if cas != 0 => {
+                    return self.store.set(key.clone(), record)
+                }
+                Err(CacheError::NotFound) => match self.store.add(key.clone(), record.clone()) {
+                    Err(CacheError::KeyExists) => continue,
+                    result => return result,
+                },
+                Err(err) => return Err(err),
+            };
+            if cas != 0 && current.header.cas != cas {
+                return Err(CacheError::KeyExists);
+            }
+            // replace with CAS of the read item, fails if it changed since
+            let header = CacheMetaData::new(
+                current.header.cas,
+                record.header.flags,
+                record.header.time_to_live,
+            );
+            let write = Record {
+                header,
+                value: record.value.clone(),
+            };
+            match self.store.replace(key.clone(), write) {
+                Ok(status) => {
+                    self.tier.release(key, self.tier.location(&current.value));
+                    return Ok(status);
+                }
+                Err(CacheError::KeyExists | CacheError::NotFound) if cas == 0 => continue,
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    fn append_prepend_common(
+        &self,
+        key: KeyType,
+        new_record: Record,
+        is_append: bool,
+    ) -> Result<SetStatus> {
+        loop {
+            let prev_record = match self.store.get(&key) {
+                Ok(record) => record,
+                Err(CacheError::NotFound) => return Err(CacheError::ItemNotStored),
+                Err(err) => return Err(err),
+            };
+            let cas = new_record.header.cas;
+            if cas != 0 && prev_record.header.cas != cas {
+                return Err(CacheError::KeyExists);
+            }
+            let previous = self.tier.location(&prev_record.value);
+            let prev_value = match self.load(&key, &prev_record) {
+                Some(record) => record.value,
+                None => return Err(CacheError::ItemNotStored),
+            };
+            let mut value = BytesMut::with_capacity(prev_value.len() + new_record.value.len());
+            if is_append {
+                value.extend_from_slice(&prev_value);
+                value.extend_from_slice(&new_record.value);
+            } else {
+                value.extend_from_slice(&new_record.value);
+                value.extend_from_slice(&prev_value);
+            }
+            // replace with CAS of the read record, fails if it changed since
+            let header = CacheMetaData::new(
+                prev_record.header.cas,
+                new_record.header.flags,
+                new_record.header.time_to_live,
+            );
+            let (record, location) = self.tier.spill(
+                &key,
+                Record {
+                    header,
+                    value: value.freeze(),
+                },
+            );
+            match self.store.replace(key.clone(), record) {
+                Ok(status) => {
+                    self.tier.release(&key, previous);
+                    return Ok(status);
+                }
+                Err(CacheError::KeyExists) => self.tier.release(&key, location),
+                Err(CacheError::NotFound) => {
+                    self.tier.release(&key, location);
+                    return Err(CacheError::ItemNotStored);
+                }
+                Err(err) => {
+                    self.tier.release(&key, location);
+                    return Err(err);
+                }
+            }
+        }
+    }
+}
+
+impl Cache for TieredMemoryStore {
+    fn get(&self, key: &KeyType) -> Result<Record> {
+        self.thaw(key);
+        let record = self.store.get(key)?;
+        self.load_or_drop(key, record)
+    }
+
+    fn get_async<'a>(&'a self, key: &'a KeyType) -> BoxFuture<'a, Result<Record>> {
+        Box::pin(async move {
+            self.thaw(key);
+            let record = self.store.get(key)?;
+            let loaded = self.load_async(key, &record).await;
+            self.found_or_drop(key, record, loaded)
+        })
+    }
+
+    fn reads_outside_memory(&self) -> bool {
+        true
+    }
+
+    fn set(&self, key: KeyType, record: Record) -> Result<SetStatus> {
+        self.thaw(&key);
+        let (record, location) = self.tier.spill(&key, record);
+        let result = self.overwrite(&key, record, false);
+        self.complete(&key, location, result)
+    }
+
+    fn delete(&self, key: KeyType, header: CacheMetaData) -> Result<Record> {
+        self.thaw(&key);
+        let record = self.store.delete(key.clone(), header)?;
+        let location = self.tier.location(&record.value);
+        let value = self
+            .load(&key, &record)
+            .map(|record| record.value)
+            .unwrap_or_default();
+        self.tier.release(&key, location);
+        Ok(Record {
+            header: record.header,
+            value,
+        })
+    }
+
+    /// Evicted records expire with the flush, like the items in memory
+    fn flush(&self, header: CacheMetaData) {
+        let time_to_live = header.time_to_live;
+        self.store.flush(header);
+        if time_to_live == 0 {
+            self.tier.evicted.clear();
+            self.tier.ext.reset();
+            return;
+        }
+        self.tier.evicted.alter_all(|_key, mut record| {
+            self.store_state.update_ttl(&mut record, time_to_live);
+            record
+        });
+    }
+
+    fn run_pending_tasks(&self) {
+        self.store.run_pending_tasks();
+        let now = self.store_state.timestamp();
+        if self.last_compaction.swap(now, Ordering::Relaxed) != now {
+            self.compact();
+            self.purge_evicted();
+        }
+    }
+
+    fn add(&self, key: KeyType, record: Record) -> Result<SetStatus> {
+        self.thaw(&key);
+        let (record, location) = self.tier.spill(&key, record);
+        let result = self.store.add(key.clone(), record);
+        self.complete(&key, location, result)
+    }
+
+    fn replace(&self, key: KeyType, record: Record) -> Result<SetStatus> {
+        self.thaw(&key);
+        let (record, location) = self.tier.spill(&key, record);
+        let result = self.overwrite(&key, record, true);
+        self.complete(&key, location, result)
+    }
+
+    fn append(&self, key: KeyType, new_record: Record) -> Result<SetStatus> {
+        self.thaw(&key);
+        self.append_prepend_common(key, new_record, true)
+    }
+
+    fn prepend(&self, key: KeyType, new_record: Record) -> Result<SetStatus> {
+        self.thaw(&key);
+        self.append_prepend_common(key, new_record, false)
+    }
+
+    /// Numbers are never spilled, pointers are not numeric
+    fn incr_decr(
+        &self,
+        header: CacheMetaData,
+        key: KeyType,
+        delta: DeltaParam,
+        increment: bool,
+    ) -> Result<DeltaResult> {
+        self.thaw(&key);
+        self.store.incr_decr(header, key, delta, increment)
+    }
+
+    fn for_each_item(&self, f: &mut dyn FnMut(&KeyType, &Record)) {
+        self.store.for_each_item(&mut |key, record| {
+            if let Some(record) = self.load(key, record) {
+                f(key, &record)
+            }
+        });
+        // copied out, the index is written by the wrapped store under its locks
+        let evicted: Vec<(KeyType, Record)> = self
+            .tier
+            .evicted
+            .iter()
+            .map(|entry| (entry.key().clone(), entry.value().clone()))
+            .collect();
+        for (key, record) in evicted {
+            if self.store_state.check_if_expired(&key, &record) {
+                continue;
+            }
+            if let Some(record) = self.load(&key, &record) {
+                f(&key, &record)
+            }
+        }
+    }
+
+    fn restore(&self, key: KeyType, record: Record) -> Result<()> {
+        self.thaw(&key);
+        let (record, location) = self.tier.spill(&key, record);
+        let result = self.store.restore(key.clone(), record);
+        self.complete(&key, location, result)
+    }
+
+    fn relocate(&self, key: KeyType, record: Record) -> Result<()> {
+        let (record, location) = self.tier.spill(&key, record);
+        let result = self.store.relocate(key.clone(), record);
+        self.complete(&key, location, result)
+    }
+
+    /// Items which are no longer in the page file or were evicted
+    /// to it are left out
+    fn scan(&self, cursor: u64, count: usize, prefix: &[u8]) -> Result<ScanPage> {
+        let page = self.store.scan(cursor, count, prefix)?;
+        let items = page
+            .items
+            .into_iter()
+            .filter_map(|(key, record)| self.load(&key, &record).map(|record| (key, record)))
+            .collect();
+        Ok(ScanPage {
+            items,
+            cursor: page.cursor,
+        })
+    }
+
+    fn get_multi(&self, keys: &[KeyType]) -> Vec<Result<Record>> {
+        keys.iter().for_each(|key| self.thaw(key));
+        self.store
+            .get_multi(keys)
+            .into_iter()
+            .zip(keys)
+            .map(|(result, key)| result.and_then(|record| self.load_or_drop(key, record)))
+            .collect()
+    }
+
+    fn add_stats(&self, stats: &mut CacheStats) {
+        self.store.add_stats(stats)
+    }
+
+    fn subscribe(&self, listener: SharedListener) {
+        self.store.subscribe(listener)
+    }
+}
@@ -1,6 +1,8 @@
 use crate::memcache_server::handler::BinaryHandler;
 use crate::mock::mock_server::create_dash_map_storage;
 use crate::mock::mock_server::create_moka_storage;
+use crate::mock::mock_server::create_sieve_storage;
+use crate::mock::mock_server::create_slab_storage;
 use crate::protocol::binary::decoder;
 use crate::protocol::binary::decoder::BinaryRequest;
 use crate::protocol::binary::encoder;
@@ -23,7 +25,7 @@ impl BinaryHandlerWithTimer {
     }
 
     pub fn handle_request(&self, req: decoder::BinaryRequest) -> Option<encoder::BinaryResponse> {
-        self.handler.handle_request(req)
+        futures::executor::block_on(self.handler.handle_request(req))
     }
 }
 
@@ -43,6 +45,22 @@ pub fn create_moka_handler() -> BinaryHandlerWithTimer {
     )
 }
 
+pub fn create_slab_handler() -> BinaryHandlerWithTimer {
+    let store_with_timer = create_slab_storage();
+    BinaryHandlerWithTimer::new(
+        BinaryHandler::new(store_with_timer.memc_store),
+        store_with_timer.timer,
+    )
+}
+
+pub fn create_sieve_handler() -> BinaryHandlerWithTimer {
+    let store_with_timer = create_sieve_storage();
+    BinaryHandlerWithTimer::new(
+        BinaryHandler::new(store_with_timer.memc_store),
+        store_with_timer.timer,
+    )
+}
+
 pub fn create_get_request(header: network::RequestHeader, key: Bytes) -> BinaryRequest {
     decoder::BinaryRequest::Get(network::GetRequest {
         header,
@@ -90,6 +108,7 @@ pub fn create_set_request(key: Bytes, value: Bytes) -> decoder::BinaryRequest {
         flags: FLAGS,
         expiration: 0,
         value: value.clone(),
+        tags: Vec::new(),
     })
 }
 
@@ -119,6 +138,7 @@ pub fn insert_value_with_expire(
         flags: FLAGS,
         expiration,
         value: value.clone(),
+        tags: Vec::new(),
     });
 
     let result = handler.handle_request(request);
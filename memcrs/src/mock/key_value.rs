@@ -57,7 +57,7 @@ mod tests {
         let value = create_random_value(size);
         assert_eq!(value.len(), size);
         for &byte in value.iter() {
-            assert!(byte >= b'a' && byte <= b'z', "Byte out of range: {}", byte);
+            assert!(byte.is_ascii_lowercase(), "Byte out of range: {}", byte);
         }
     }
 
@@ -1,25 +1,34 @@
 use crate::cache::cache::Cache;
-use crate::memcache::cli::parser::{DashMapConfig, MokaConfig};
+use crate::memcache::cli::parser::{DashMapConfig, MokaConfig, SieveConfig, SlabConfig};
 use crate::memcache::store::MemcStore;
 use crate::memory_store::dash_map_store::DashMapMemoryStore;
 use crate::memory_store::moka_store::MokaMemoryStore as MokaStore;
+use crate::memory_store::sieve_store::SieveMemoryStore;
+use crate::memory_store::slab_store::SlabMemoryStore;
 use crate::server::timer;
-use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
 use std::sync::Arc;
 
+/// Unix time of mock timestamp 0, unless changed with `set_process_started`
+pub const MOCK_UNIX_START: u64 = 1_700_000_000;
+
 pub struct MockSystemTimer {
     pub current_time: AtomicU32,
+    pub process_started: AtomicU64,
 }
 
 pub trait SetableTimer: timer::Timer {
     fn set(&self, time: u32);
     fn add_seconds(&self, seconds: u32);
+    /// Simulates a system clock jump, timestamp is not changed
+    fn set_process_started(&self, unix_time: u64);
 }
 
 impl MockSystemTimer {
     pub fn new() -> Self {
         MockSystemTimer {
             current_time: AtomicU32::new(0),
+            process_started: AtomicU64::new(MOCK_UNIX_START),
         }
     }
 }
@@ -34,6 +43,10 @@ impl timer::Timer for MockSystemTimer {
     fn timestamp(&self) -> u32 {
         self.current_time.load(Ordering::Relaxed)
     }
+
+    fn process_started(&self) -> u64 {
+        self.process_started.load(Ordering::Relaxed)
+    }
 }
 
 impl SetableTimer for MockSystemTimer {
@@ -44,37 +57,73 @@ impl SetableTimer for MockSystemTimer {
     fn add_seconds(&self, seconds: u32) {
         self.current_time.fetch_add(seconds, Ordering::Release);
     }
+
+    fn set_process_started(&self, unix_time: u64) {
+        self.process_started.store(unix_time, Ordering::Relaxed)
+    }
 }
 
 pub struct MockServer {
     pub timer: Arc<MockSystemTimer>,
     pub storage: MemcStore,
+    pub store: Arc<dyn Cache + Send + Sync>,
 }
 
 impl MockServer {
     pub fn new(store: Arc<dyn Cache + Send + Sync>, timer: Arc<MockSystemTimer>) -> Self {
         MockServer {
             timer,
-            storage: MemcStore::new(store),
+            storage: MemcStore::new(store.clone()),
+            store,
         }
     }
 }
 
 pub fn create_moka_server() -> MockServer {
-    let config = MokaConfig::default();
+    create_moka_server_with_config(MokaConfig::default())
+}
+
+pub fn create_moka_server_with_config(config: MokaConfig) -> MockServer {
     let timer = Arc::new(MockSystemTimer::new());
-    MockServer::new(Arc::new(MokaStore::new(timer.clone(), config)), timer)
+    MockServer::new(
+        Arc::new(MokaStore::new(timer.clone(), config).unwrap()),
+        timer,
+    )
 }
 
 pub fn create_dash_map_server() -> MockServer {
+    create_dash_map_server_with_config(DashMapConfig::default())
+}
+
+pub fn create_dash_map_server_with_config(config: DashMapConfig) -> MockServer {
     let timer = Arc::new(MockSystemTimer::new());
-    let config = DashMapConfig::default();
     MockServer::new(
         Arc::new(DashMapMemoryStore::new(timer.clone(), config)),
         timer,
     )
 }
 
+pub fn create_slab_server() -> MockServer {
+    create_slab_server_with_config(SlabConfig::default())
+}
+
+pub fn create_slab_server_with_config(config: SlabConfig) -> MockServer {
+    let timer = Arc::new(MockSystemTimer::new());
+    MockServer::new(Arc::new(SlabMemoryStore::new(timer.clone(), config)), timer)
+}
+
+pub fn create_sieve_server() -> MockServer {
+    create_sieve_server_with_config(SieveConfig::default())
+}
+
+pub fn create_sieve_server_with_config(config: SieveConfig) -> MockServer {
+    let timer = Arc::new(MockSystemTimer::new());
+    MockServer::new(
+        Arc::new(SieveMemoryStore::new(timer.clone(), config)),
+        timer,
+    )
+}
+
 pub struct StoreWithMockTimer {
     pub timer: Arc<MockSystemTimer>,
     pub memc_store: Arc<MemcStore>,
@@ -93,7 +142,26 @@ pub fn create_dash_map_storage() -> StoreWithMockTimer {
 pub fn create_moka_storage() -> StoreWithMockTimer {
     let config = MokaConfig::default();
     let timer = Arc::new(MockSystemTimer::new());
-    let memc_store = Arc::new(MemcStore::new(Arc::new(MokaStore::new(
+    let memc_store = Arc::new(MemcStore::new(Arc::new(
+        MokaStore::new(timer.clone(), config).unwrap(),
+    )));
+    StoreWithMockTimer { timer, memc_store }
+}
+
+pub fn create_slab_storage() -> StoreWithMockTimer {
+    let config = SlabConfig::default();
+    let timer = Arc::new(MockSystemTimer::new());
+    let memc_store = Arc::new(MemcStore::new(Arc::new(SlabMemoryStore::new(
+        timer.clone(),
+        config,
+    ))));
+    StoreWithMockTimer { timer, memc_store }
+}
+
+pub fn create_sieve_storage() -> StoreWithMockTimer {
+    let config = SieveConfig::default();
+    let timer = Arc::new(MockSystemTimer::new());
+    let memc_store = Arc::new(MemcStore::new(Arc::new(SieveMemoryStore::new(
         timer.clone(),
         config,
     ))));
@@ -0,0 +1,894 @@
+use super::format::{
+    invalid_data, read_key, read_record, read_u16, read_u64, read_u8, write_key, write_record,
+    write_record_expiring, Clock,
+};
+use super::stripes::KeyStripes;
+use crate::cache::cache::{Cache, CacheMetaData, KeyType, Record};
+use crate::cache::error::{CacheError, Result};
+use crate::server::timer::{Timer, MAX_RELATIVE_EXPIRATION};
+
+use clap::ValueEnum;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+use tokio::sync::{watch, Notify};
+use tokio_util::sync::CancellationToken;
+
+/// Mutation log layout, all integers are big endian:
+///
+/// ```text
+/// header: magic "MCRSAOF1" | version u16
+/// set:    tag 1 | key len u16 | key | flags u32 | cas u64
+///         | expiration u64 (unix seconds, 0 never expires)
+///         | value len u32 | value
+/// delete: tag 2 | key len u16 | key
+/// flush:  tag 3 | expiration u64 (unix seconds, 0 immediately)
+/// ```
+///
+/// Every mutation is logged as the state it left the key in, so
+/// entries can be replayed in order regardless of CAS values.
+const MAGIC: &[u8; 8] = b"MCRSAOF1";
+const VERSION: u16 = 1;
+const HEADER_LEN: u64 = (MAGIC.len() + 2) as u64;
+const TAG_SET: u8 = 1;
+const TAG_DELETE: u8 = 2;
+const TAG_FLUSH: u8 = 3;
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug)]
+pub enum FsyncPolicy {
+    /// fsync after every mutation, responses wait until it is durable
+    Always,
+    /// fsync once a second, up to a second of mutations can be lost
+    EverySecond,
+    /// leave flushing to the operating system
+    Never,
+}
+
+impl FsyncPolicy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FsyncPolicy::Always => "always",
+            FsyncPolicy::EverySecond => "every second",
+            FsyncPolicy::Never => "never",
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ReplayStats {
+    /// entries applied to the store
+    pub entries: u64,
+    /// true if the last entry was incomplete and was cut off
+    pub truncated: bool,
+}
+
+/// State a mutation left its key in
+#[derive(Clone)]
+pub enum KeyState {
+    /// item as written by the client with the CAS the store gave it,
+    /// its expiration is the TTL sent by the client
+    Written(Record),
+    Deleted,
+    /// only the store knows the result, like of an append, it is read back
+    Stored,
+}
+
+impl KeyState {
+    pub fn written(mut record: Record, cas: u64) -> KeyState {
+        record.header.cas = cas;
+        KeyState::Written(record)
+    }
+}
+
+struct LogFile {
+    writer: BufWriter<File>,
+    size: u64,
+    /// entries appended since the log was opened
+    entries: u64,
+    /// entries written while the log is rewritten, they are
+    /// appended to the rewritten log once it is complete
+    rewrite_buffer: Option<Vec<u8>>,
+}
+
+impl LogFile {
+    fn append(&mut self, entry: &[u8]) -> io::Result<()> {
+        self.writer.write_all(entry)?;
+        self.size += entry.len() as u64;
+        self.entries += 1;
+        if let Some(buffer) = self.rewrite_buffer.as_mut() {
+            buffer.extend_from_slice(entry);
+        }
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct SyncState {
+    /// entries known to be durable
+    synced: u64,
+    /// an fsync failed, no entry synced after it is durable
+    failed: bool,
+}
+
+/// Append-only log of store mutations.
+///
+/// A mutation and its log entry are done under the lock of the key's
+/// stripe, so entries of a key are in the order its mutations were
+/// applied in while unrelated keys are not serialized. The file itself
+/// is only locked to copy an entry into its buffer. Reads are not
+/// affected.
+///
+/// Nothing is fsynced on the threads doing mutations. With fsync policy
+/// always the log service fsyncs appended entries in batches and
+/// responses wait for it with `durable`.
+///
+/// A failed fsync may have dropped the dirty pages it was writing, so
+/// a later successful one proves nothing about them. The first failure
+/// is final: entries not synced before it never become durable and
+/// `durable` fails for them and for every entry appended later.
+pub struct MutationLog {
+    file: Mutex<LogFile>,
+    stripes: KeyStripes,
+    /// entries appended, copy of the count kept with the file
+    appended: AtomicU64,
+    synced: watch::Sender<SyncState>,
+    /// wakes the service once there are entries to fsync
+    pending: Notify,
+    path: PathBuf,
+    fsync: FsyncPolicy,
+    rewrite_min_size: u64,
+    /// log size right after the last rewrite
+    rewrite_base_size: AtomicU64,
+    timer: Arc<dyn Timer + Send + Sync>,
+}
+
+impl MutationLog {
+    /// Opens log for appending, creates it if it does not exist
+    pub fn open(
+        path: &Path,
+        fsync: FsyncPolicy,
+        rewrite_min_size: u64,
+        timer: Arc<dyn Timer + Send + Sync>,
+    ) -> io::Result<MutationLog> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        let mut size = file.metadata()?.len();
+        if size == 0 {
+            write_header(&mut file)?;
+            file.sync_all()?;
+            size = HEADER_LEN;
+        } else {
+            read_header(&mut file)?;
+        }
+        file.seek(SeekFrom::End(0))?;
+        Ok(MutationLog {
+            file: Mutex::new(LogFile {
+                writer: BufWriter::new(file),
+                size,
+                entries: 0,
+                rewrite_buffer: None,
+            }),
+            stripes: KeyStripes::new(),
+            appended: AtomicU64::new(0),
+            synced: watch::Sender::new(SyncState::default()),
+            pending: Notify::new(),
+            path: path.to_path_buf(),
+            fsync,
+            rewrite_min_size,
+            rewrite_base_size: AtomicU64::new(size),
+            timer,
+        })
+    }
+
+    fn lock(&self) -> MutexGuard<'_, LogFile> {
+        self.file.lock().unwrap()
+    }
+
+    /// Applies a mutation of a key and logs the state `state` says it
+    /// left the key in
+    pub fn write<T>(
+        &self,
+        store: &dyn Cache,
+        key: &KeyType,
+        mutation: impl FnOnce() -> Result<T>,
+        state: impl FnOnce(&T) -> KeyState,
+    ) -> Result<T> {
+        let _stripe = self.stripes.lock(key);
+        let result = mutation()?;
+        let entry = state_entry(store, self.timer.as_ref(), key, state(&result));
+        self.append(&entry)?;
+        Ok(result)
+    }
+
+    /// Flushes the store and logs it
+    pub fn flush(&self, store: &dyn Cache, header: CacheMetaData) -> Result<()> {
+        let _stripes = self.stripes.lock_all();
+        let entry = flush_entry(self.timer.as_ref(), &header);
+        store.flush(header);
+        self.append(&entry)
+    }
+
+    fn append(&self, entry: &[u8]) -> Result<()> {
+        let mut file = self.lock();
+        file.append(entry).map_err(|err| {
+            error!("Cannot write to {}: {}", self.path.display(), err);
+            // mutation is already applied, but it is not durable
+            CacheError::InternalError
+        })?;
+        self.appended.store(file.entries, Ordering::Release);
+        if self.fsync == FsyncPolicy::Always {
+            self.pending.notify_one();
+        }
+        Ok(())
+    }
+
+    /// Waits until entries appended so far are fsynced, returns at once
+    /// unless fsync policy is always. Fails once an fsync failed before
+    /// they were synced.
+    pub async fn durable(&self) -> Result<()> {
+        if self.fsync != FsyncPolicy::Always {
+            return Ok(());
+        }
+        let appended = self.appended.load(Ordering::Acquire);
+        let mut synced = self.synced.subscribe();
+        let durable = match synced
+            .wait_for(|state| state.synced >= appended || state.failed)
+            .await
+        {
+            Ok(state) => state.synced >= appended,
+            // the log is gone, nothing waits for entries anymore
+            Err(_) => true,
+        };
+        if durable {
+            Ok(())
+        } else {
+            Err(CacheError::InternalError)
+        }
+    }
+
+    /// Writes buffered entries to the log, fsyncs it unless policy is never
+    pub fn sync(&self) -> io::Result<()> {
+        if self.synced.borrow().failed {
+            return Err(io::Error::other("previous fsync failed"));
+        }
+        let (file, entries) = {
+            let mut file = self.lock();
+            let entries = file.entries;
+            let file = file.writer.flush().and_then(|_| match self.fsync {
+                FsyncPolicy::Never => Ok(None),
+                _ => file.writer.get_ref().try_clone().map(Some),
+            });
+            match file {
+                Ok(Some(file)) => (file, entries),
+                Ok(None) => return Ok(()),
+                Err(err) => return Err(self.sync_failed(err)),
+            }
+        };
+        // writers are not blocked by fsync
+        file.sync_data().map_err(|err| self.sync_failed(err))?;
+        self.synced.send_if_modified(|state| {
+            let advanced = entries > state.synced;
+            state.synced = state.synced.max(entries);
+            advanced
+        });
+        Ok(())
+    }
+
+    fn sync_failed(&self, err: io::Error) -> io::Error {
+        if self.fsync == FsyncPolicy::Always {
+            self.synced.send_modify(|state| state.failed = true);
+        }
+        err
+    }
+
+    pub fn size(&self) -> u64 {
+        self.lock().size
+    }
+
+    /// Log is rewritten once it doubled since the last rewrite
+    pub fn needs_rewrite(&self) -> bool {
+        let size = self.size();
+        size > self.rewrite_min_size && size > 2 * self.rewrite_base_size.load(Ordering::Acquire)
+    }
+
+    /// Replaces the log with one set entry for every live item.
+    ///
+    /// Store is walked without blocking writers, mutations done in the
+    /// meantime are appended to the new log after the walk, replaying
+    /// them on top of the walked state gives the current state.
+    pub fn rewrite(&self, store: &dyn Cache) -> io::Result<()> {
+        {
+            let mut file = self.lock();
+            if file.rewrite_buffer.is_some() {
+                return Ok(());
+            }
+            file.rewrite_buffer = Some(Vec::new());
+        }
+        let mut tmp_path = self.path.as_os_str().to_owned();
+        tmp_path.push(".rewrite");
+        let tmp_path = PathBuf::from(tmp_path);
+        let result = self.rewrite_to(store, &tmp_path);
+        if result.is_err() {
+            self.lock().rewrite_buffer = None;
+            let _ = fs::remove_file(&tmp_path);
+        }
+        result
+    }
+
+    fn rewrite_to(&self, store: &dyn Cache, tmp_path: &Path) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(tmp_path)?);
+        write_header(&mut writer)?;
+        let clock = Clock::new(self.timer.as_ref());
+        let mut result = Ok(());
+        store.for_each_item(&mut |key, record| {
+            if result.is_ok() {
+                result = writer
+                    .write_all(&[TAG_SET])
+                    .and_then(|_| write_record(&mut writer, &clock, key, record));
+            }
+        });
+        result?;
+        // walked part is synced before writers are blocked
+        writer.flush()?;
+        writer.get_ref().sync_all()?;
+
+        let mut file = self.lock();
+        let buffer = file.rewrite_buffer.take().unwrap_or_default();
+        writer.write_all(&buffer)?;
+        writer.flush()?;
+        writer.get_ref().sync_data()?;
+        fs::rename(tmp_path, &self.path)?;
+        // entries synced to the new log are lost if the rename is not
+        sync_parent_dir(&self.path)?;
+        let size = writer.get_ref().metadata()?.len();
+        file.writer = writer;
+        file.size = size;
+        self.rewrite_base_size.store(size, Ordering::Release);
+        Ok(())
+    }
+}
+
+/// Makes a rename of `path` durable
+#[cfg(unix)]
+fn sync_parent_dir(path: &Path) -> io::Result<()> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    File::open(dir)?.sync_all()
+}
+
+/// Directories cannot be opened for syncing, renames are left to the
+/// file system
+#[cfg(not(unix))]
+fn sync_parent_dir(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+/// Unix time a TTL sent by a client expires at, 0 never expires
+pub(crate) fn unix_expiration(timer: &dyn Timer, time_to_live: u32) -> u64 {
+    match time_to_live {
+        0 => 0,
+        ttl if ttl > MAX_RELATIVE_EXPIRATION => ttl as u64,
+        ttl => timer.unix_timestamp() + ttl as u64,
+    }
+}
+
+/// Entry setting `key` to `state`, or deleting it
+pub(crate) fn state_entry(
+    store: &dyn Cache,
+    timer: &dyn Timer,
+    key: &KeyType,
+    state: KeyState,
+) -> Vec<u8> {
+    let mut entry = Vec::new();
+    let stored = match state {
+        KeyState::Written(record) => {
+            let expiration = unix_expiration(timer, record.header.time_to_live);
+            entry.push(TAG_SET);
+            let _ = write_record_expiring(&mut entry, key, &record, expiration);
+            return entry;
+        }
+        KeyState::Deleted => None,
+        KeyState::Stored => store.get(key).ok(),
+    };
+    match stored {
+        Some(record) => {
+            entry.push(TAG_SET);
+            let _ = write_record(&mut entry, &Clock::new(timer), key, &record);
+        }
+        None => {
+            entry.push(TAG_DELETE);
+            let _ = write_key(&mut entry, key);
+        }
+    }
+    entry
+}
+
+/// Entry of a flush with `header` passed to the store
+pub(crate) fn flush_entry(timer: &dyn Timer, header: &CacheMetaData) -> Vec<u8> {
+    let expiration = unix_expiration(timer, header.time_to_live);
+    let mut entry = vec![TAG_FLUSH];
+    entry.extend_from_slice(&expiration.to_be_bytes());
+    entry
+}
+
+fn write_header(writer: &mut impl Write) -> io::Result<()> {
+    writer.write_all(MAGIC)?;
+    writer.write_all(&VERSION.to_be_bytes())
+}
+
+fn read_header(reader: &mut impl Read) -> io::Result<()> {
+    let mut magic = [0u8; 8];
+    reader.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(invalid_data("Not a memcrs mutation log"));
+    }
+    let version = read_u16(reader)?;
+    if version != VERSION {
+        return Err(invalid_data(&format!(
+            "Unsupported mutation log version: {}",
+            version
+        )));
+    }
+    Ok(())
+}
+
+/// Counts bytes read, so a torn entry can be cut off
+struct CountingReader<R> {
+    inner: R,
+    count: u64,
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.count += read as u64;
+        Ok(read)
+    }
+}
+
+/// Applies entry which starts with `tag` to a store
+pub(crate) fn apply_entry(
+    reader: &mut impl Read,
+    tag: u8,
+    store: &dyn Cache,
+    clock: &Clock,
+) -> io::Result<()> {
+    let any_cas = || CacheMetaData::new(0, 0, 0);
+    match tag {
+        TAG_SET => {
+            let stored = read_record(reader)?;
+            let key = stored.key.clone();
+            let _ = store.delete(key, any_cas());
+            if let Some((key, record)) = stored.into_record(clock) {
+                if let Err(err) = store.restore(key, record) {
+                    debug!("Logged item not restored: {:?}", err);
+                }
+            }
+        }
+        TAG_DELETE => {
+            let key = read_key(reader)?;
+            let _ = store.delete(key, any_cas());
+        }
+        TAG_FLUSH => {
+            // logged as unix time, store takes it as absolute expiration
+            let expiration = read_u64(reader)?;
+            store.flush(CacheMetaData::new(
+                0,
+                0,
+                expiration.min(u32::MAX as u64) as u32,
+            ));
+        }
+        _ => return Err(invalid_data("Corrupted mutation log entry")),
+    }
+    Ok(())
+}
+
+/// Replays log into a store. An incomplete last entry, left by a crash
+/// in the middle of a write, is cut off.
+pub fn replay(path: &Path, store: &dyn Cache, timer: &dyn Timer) -> io::Result<ReplayStats> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(ReplayStats::default()),
+        Err(err) => return Err(err),
+    };
+    let mut reader = CountingReader {
+        inner: BufReader::new(file),
+        count: 0,
+    };
+    read_header(&mut reader)?;
+    let clock = Clock::new(timer);
+    let mut stats = ReplayStats::default();
+    let mut valid_len = reader.count;
+    loop {
+        let tag = match read_u8(&mut reader) {
+            Ok(tag) => tag,
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => break,
+            Err(err) => return Err(err),
+        };
+        match apply_entry(&mut reader, tag, store, &clock) {
+            Ok(()) => {
+                stats.entries += 1;
+                valid_len = reader.count;
+            }
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => {
+                warn!(
+                    "Mutation log {} ends with incomplete entry, truncating it",
+                    path.display()
+                );
+                OpenOptions::new()
+                    .write(true)
+                    .open(path)?
+                    .set_len(valid_len)?;
+                stats.truncated = true;
+                break;
+            }
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(stats)
+}
+
+/// Syncs the log every second and rewrites it once it grows too large
+pub struct MutationLogService {
+    log: Arc<MutationLog>,
+    store: Arc<dyn Cache + Send + Sync>,
+    cancellation_token: CancellationToken,
+}
+
+impl MutationLogService {
+    const SYNC_INTERVAL: Duration = Duration::from_secs(1);
+
+    pub fn new(
+        log: Arc<MutationLog>,
+        store: Arc<dyn Cache + Send + Sync>,
+        cancellation_token: CancellationToken,
+    ) -> Self {
+        MutationLogService {
+            log,
+            store,
+            cancellation_token,
+        }
+    }
+
+    pub fn sync(&self) {
+        if let Err(err) = self.log.sync() {
+            error!("Cannot sync {}: {}", self.log.path.display(), err);
+        }
+    }
+
+    pub async fn run(self: Arc<Self>) {
+        // fsyncs waited for by responses are not held up by rewrites
+        let sync_pending = (self.log.fsync == FsyncPolicy::Always)
+            .then(|| tokio::spawn(self.clone().sync_pending()));
+        let mut interval = tokio::time::interval(MutationLogService::SYNC_INTERVAL);
+        loop {
+            tokio::select! {
+                _ = self.cancellation_token.cancelled() => {
+                    info!("Mutation log service received cancellation signal, stopping...");
+                    break;
+                },
+                _ = interval.tick() => {
+                    let service = self.clone();
+                    let result = tokio::task::spawn_blocking(move || {
+                        service.sync();
+                        if service.log.needs_rewrite() {
+                            info!("Rewriting mutation log, size: {}", service.log.size());
+                            if let Err(err) = service.log.rewrite(service.store.as_ref()) {
+                                error!("Cannot rewrite {}: {}", service.log.path.display(), err);
+                            }
+                        }
+                    })
+                    .await;
+                    if let Err(err) = result {
+                        error!("Mutation log task failed: {}", err);
+                    }
+                },
+            }
+        }
+        if let Some(task) = sync_pending {
+            let _ = task.await;
+        }
+    }
+
+    /// Fsyncs entries as they are appended, entries appended during an
+    /// fsync are made durable together by the next one
+    async fn sync_pending(self: Arc<Self>) {
+        loop {
+            tokio::select! {
+                _ = self.cancellation_token.cancelled() => break,
+                _ = self.log.pending.notified() => {
+                    let service = self.clone();
+                    if let Err(err) = tokio::task::spawn_blocking(move || service.sync()).await {
+                        error!("Mutation log task failed: {}", err);
+                    }
+                },
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::cache::SetStatus;
+    use crate::memcache::cli::parser::DashMapConfig;
+    use crate::memory_store::dash_map_store::DashMapMemoryStore;
+    use crate::mock::mock_server::{MockSystemTimer, SetableTimer};
+    use bytes::Bytes;
+
+    struct TempLog {
+        path: PathBuf,
+    }
+
+    impl TempLog {
+        fn new(name: &str) -> TempLog {
+            let path =
+                std::env::temp_dir().join(format!("memcrs-aof-{}-{}", name, std::process::id()));
+            let _ = fs::remove_file(&path);
+            TempLog { path }
+        }
+    }
+
+    impl Drop for TempLog {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+
+    fn create_store(timer: Arc<MockSystemTimer>) -> DashMapMemoryStore {
+        DashMapMemoryStore::new(timer, DashMapConfig::default())
+    }
+
+    fn open(log: &TempLog, timer: Arc<MockSystemTimer>) -> MutationLog {
+        MutationLog::open(&log.path, FsyncPolicy::Always, 0, timer).unwrap()
+    }
+
+    fn set(log: &MutationLog, store: &dyn Cache, key: &str, value: &str) {
+        let key = Bytes::from(key.to_string());
+        let record = Record::new(Bytes::from(value.to_string()), 0, 0, 0);
+        log.write(
+            store,
+            &key,
+            || store.set(key.clone(), record.clone()),
+            |status| KeyState::written(record.clone(), status.cas),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_replay_restores_mutations() {
+        let temp = TempLog::new("replay");
+        let timer = Arc::new(MockSystemTimer::new());
+        let store = create_store(timer.clone());
+        let log = open(&temp, timer.clone());
+        set(&log, &store, "a", "1");
+        set(&log, &store, "b", "2");
+        set(&log, &store, "a", "3");
+        let key = Bytes::from("b");
+        log.write(
+            &store,
+            &key,
+            || store.delete(key.clone(), CacheMetaData::new(0, 0, 0)),
+            |_| KeyState::Deleted,
+        )
+        .unwrap();
+        let cas = store.get(&Bytes::from("a")).unwrap().header.cas;
+        drop(log);
+
+        let restored = create_store(timer.clone());
+        let stats = replay(&temp.path, &restored, timer.as_ref()).unwrap();
+        assert_eq!(stats.entries, 4);
+        assert!(!stats.truncated);
+        let record = restored.get(&Bytes::from("a")).unwrap();
+        assert_eq!(record.value, Bytes::from("3"));
+        assert_eq!(record.header.cas, cas);
+        assert!(restored.get(&Bytes::from("b")).is_err());
+    }
+
+    #[test]
+    fn test_failed_mutation_is_not_logged() {
+        let temp = TempLog::new("failed");
+        let timer = Arc::new(MockSystemTimer::new());
+        let store = create_store(timer.clone());
+        let log = open(&temp, timer.clone());
+        let size = log.size();
+        let key = Bytes::from("missing");
+        let result = log.write(
+            &store,
+            &key,
+            || store.replace(key.clone(), Record::new(Bytes::from("v"), 0, 0, 0)),
+            |_| KeyState::Stored,
+        );
+        assert_eq!(result.unwrap_err(), CacheError::NotFound);
+        assert_eq!(log.size(), size);
+    }
+
+    #[test]
+    fn test_written_record_is_logged_without_reading_store() {
+        let temp = TempLog::new("written");
+        let timer = Arc::new(MockSystemTimer::new());
+        let store = create_store(timer.clone());
+        let log = open(&temp, timer.clone());
+        let key = Bytes::from("a");
+        let record = Record::new(Bytes::from("1"), 0, 5, 100);
+        log.write(
+            &store,
+            &key,
+            || Ok(SetStatus { cas: 7 }),
+            |status| KeyState::written(record.clone(), status.cas),
+        )
+        .unwrap();
+        assert!(store.get(&key).is_err());
+        drop(log);
+
+        let restored = create_store(timer.clone());
+        replay(&temp.path, &restored, timer.as_ref()).unwrap();
+        let found = restored.get(&key).unwrap();
+        assert_eq!(found.value, Bytes::from("1"));
+        assert_eq!(found.header.cas, 7);
+        assert_eq!(found.header.flags, 5);
+        timer.set(100);
+        assert!(restored.get(&key).is_err());
+    }
+
+    #[test]
+    fn test_concurrent_writes_replay_to_store_state() {
+        let temp = TempLog::new("concurrent");
+        let timer = Arc::new(MockSystemTimer::new());
+        let store = create_store(timer.clone());
+        let log = open(&temp, timer.clone());
+        std::thread::scope(|scope| {
+            for thread in 0..4 {
+                let (log, store) = (&log, &store);
+                scope.spawn(move || {
+                    for idx in 0..200 {
+                        let key = format!("key{}", idx % 10);
+                        set(log, store, &key, &format!("{}-{}", thread, idx));
+                    }
+                });
+            }
+        });
+        drop(log);
+
+        let restored = create_store(timer.clone());
+        replay(&temp.path, &restored, timer.as_ref()).unwrap();
+        for idx in 0..10 {
+            let key = Bytes::from(format!("key{}", idx));
+            assert_eq!(restored.get(&key).unwrap(), store.get(&key).unwrap());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_durable_waits_for_sync() {
+        let temp = TempLog::new("durable");
+        let timer = Arc::new(MockSystemTimer::new());
+        let store = create_store(timer.clone());
+        let log = open(&temp, timer.clone());
+        log.durable().await.unwrap();
+        set(&log, &store, "a", "1");
+        let wait = tokio::time::timeout(Duration::from_millis(50), log.durable());
+        assert!(wait.await.is_err());
+        log.sync().unwrap();
+        log.durable().await.unwrap();
+
+        let log = MutationLog::open(&temp.path, FsyncPolicy::EverySecond, 0, timer).unwrap();
+        set(&log, &store, "b", "2");
+        log.durable().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_failed_sync_is_final() {
+        let temp = TempLog::new("failed_sync");
+        let timer = Arc::new(MockSystemTimer::new());
+        let store = create_store(timer.clone());
+        let log = Arc::new(open(&temp, timer));
+        set(&log, &store, "a", "1");
+        log.sync().unwrap();
+        set(&log, &store, "b", "2");
+        let waiting = tokio::spawn({
+            let log = log.clone();
+            async move { log.durable().await }
+        });
+        tokio::task::yield_now().await;
+        log.sync_failed(io::Error::other("fsync failed"));
+        assert!(waiting.await.unwrap().is_err());
+        assert!(log.sync().is_err());
+        set(&log, &store, "c", "3");
+        assert!(log.durable().await.is_err());
+    }
+
+    #[test]
+    fn test_replay_flush() {
+        let temp = TempLog::new("flush");
+        let timer = Arc::new(MockSystemTimer::new());
+        let store = create_store(timer.clone());
+        let log = open(&temp, timer.clone());
+        set(&log, &store, "a", "1");
+        log.flush(&store, CacheMetaData::new(0, 0, 0)).unwrap();
+        set(&log, &store, "b", "2");
+        drop(log);
+
+        let restored = create_store(timer.clone());
+        replay(&temp.path, &restored, timer.as_ref()).unwrap();
+        assert!(restored.get(&Bytes::from("a")).is_err());
+        assert!(restored.get(&Bytes::from("b")).is_ok());
+    }
+
+    #[test]
+    fn test_torn_entry_is_truncated() {
+        let temp = TempLog::new("torn");
+        let timer = Arc::new(MockSystemTimer::new());
+        let store = create_store(timer.clone());
+        let log = open(&temp, timer.clone());
+        set(&log, &store, "a", "1");
+        set(&log, &store, "b", "2");
+        let size = log.size();
+        drop(log);
+        let file = OpenOptions::new().write(true).open(&temp.path).unwrap();
+        file.set_len(size - 3).unwrap();
+
+        let restored = create_store(timer.clone());
+        let stats = replay(&temp.path, &restored, timer.as_ref()).unwrap();
+        assert_eq!(stats.entries, 1);
+        assert!(stats.truncated);
+        assert!(restored.get(&Bytes::from("a")).is_ok());
+        assert!(restored.get(&Bytes::from("b")).is_err());
+
+        // log can be appended to after truncation
+        let log = open(&temp, timer.clone());
+        set(&log, &restored, "c", "3");
+        drop(log);
+        let restored = create_store(timer.clone());
+        let stats = replay(&temp.path, &restored, timer.as_ref()).unwrap();
+        assert_eq!(stats.entries, 2);
+        assert!(!stats.truncated);
+    }
+
+    #[test]
+    fn test_rewrite_compacts_log() {
+        let temp = TempLog::new("rewrite");
+        let timer = Arc::new(MockSystemTimer::new());
+        let store = create_store(timer.clone());
+        let log = open(&temp, timer.clone());
+        for idx in 0..100 {
+            set(&log, &store, "key", &idx.to_string());
+        }
+        set(&log, &store, "other", "value");
+        let size = log.size();
+        assert!(log.needs_rewrite());
+        log.rewrite(&store).unwrap();
+        assert!(log.size() < size);
+        assert!(!log.needs_rewrite());
+        set(&log, &store, "after", "rewrite");
+        drop(log);
+
+        let restored = create_store(timer.clone());
+        let stats = replay(&temp.path, &restored, timer.as_ref()).unwrap();
+        assert_eq!(stats.entries, 3);
+        assert_eq!(
+            restored.get(&Bytes::from("key")).unwrap().value,
+            Bytes::from("99")
+        );
+        assert!(restored.get(&Bytes::from("other")).is_ok());
+        assert!(restored.get(&Bytes::from("after")).is_ok());
+    }
+
+    #[test]
+    fn test_invalid_log_is_rejected() {
+        let temp = TempLog::new("invalid");
+        fs::write(&temp.path, b"not a log").unwrap();
+        let timer = Arc::new(MockSystemTimer::new());
+        let store = create_store(timer.clone());
+        assert!(replay(&temp.path, &store, timer.as_ref()).is_err());
+        assert!(MutationLog::open(&temp.path, FsyncPolicy::Never, 0, timer).is_err());
+    }
+}
@@ -0,0 +1,148 @@
+use crate::cache::cache::{KeyType, Record};
+use crate::server::timer::Timer;
+
+use bytes::Bytes;
+use std::io::{self, Read, Write};
+
+/// Converts expiration between server timestamps, which start when
+/// the server starts, and unix time which survives restarts.
+pub(crate) struct Clock {
+    pub(crate) unix_now: u64,
+    server_now: u32,
+}
+
+impl Clock {
+    pub(crate) fn new(timer: &dyn Timer) -> Clock {
+        Clock {
+            unix_now: timer.unix_timestamp(),
+            server_now: timer.timestamp(),
+        }
+    }
+
+    pub(crate) fn to_unix(&self, time_to_live: u32) -> u64 {
+        match time_to_live {
+            0 => 0,
+            ttl => (self.unix_now + ttl as u64).saturating_sub(self.server_now as u64),
+        }
+    }
+
+    /// Returns None if expiration is in the past
+    pub(crate) fn to_server(&self, expiration: u64) -> Option<u32> {
+        match expiration {
+            0 => Some(0),
+            expiration if expiration <= self.unix_now => None,
+            expiration => {
+                let remaining = expiration - self.unix_now;
+                Some((self.server_now as u64 + remaining).min(u32::MAX as u64) as u32)
+            }
+        }
+    }
+}
+
+/// Record as stored on disk, expiration is in unix seconds
+pub(crate) struct StoredRecord {
+    pub(crate) key: KeyType,
+    pub(crate) flags: u32,
+    pub(crate) cas: u64,
+    pub(crate) expiration: u64,
+    pub(crate) value: Bytes,
+}
+
+impl StoredRecord {
+    /// Returns None if record expired
+    pub(crate) fn into_record(self, clock: &Clock) -> Option<(KeyType, Record)> {
+        let time_to_live = clock.to_server(self.expiration)?;
+        Some((
+            self.key,
+            Record::new(self.value, self.cas, self.flags, time_to_live),
+        ))
+    }
+}
+
+/// key len u16 | key | flags u32 | cas u64 | expiration u64 | value len u32 | value
+pub(crate) fn write_record(
+    writer: &mut impl Write,
+    clock: &Clock,
+    key: &KeyType,
+    record: &Record,
+) -> io::Result<()> {
+    let expiration = clock.to_unix(record.header.time_to_live);
+    write_record_expiring(writer, key, record, expiration)
+}
+
+/// Same as `write_record` for a record which expires at unix time
+/// `expiration` instead of its server timestamp
+pub(crate) fn write_record_expiring(
+    writer: &mut impl Write,
+    key: &KeyType,
+    record: &Record,
+    expiration: u64,
+) -> io::Result<()> {
+    writer.write_all(&(key.len() as u16).to_be_bytes())?;
+    writer.write_all(key)?;
+    writer.write_all(&record.header.flags.to_be_bytes())?;
+    writer.write_all(&record.header.cas.to_be_bytes())?;
+    writer.write_all(&expiration.to_be_bytes())?;
+    writer.write_all(&(record.value.len() as u32).to_be_bytes())?;
+    writer.write_all(&record.value)
+}
+
+pub(crate) fn read_record(reader: &mut impl Read) -> io::Result<StoredRecord> {
+    let key = read_key(reader)?;
+    let flags = read_u32(reader)?;
+    let cas = read_u64(reader)?;
+    let expiration = read_u64(reader)?;
+    let value_len = read_u32(reader)? as usize;
+    let value = read_bytes(reader, value_len)?;
+    Ok(StoredRecord {
+        key,
+        flags,
+        cas,
+        expiration,
+        value,
+    })
+}
+
+pub(crate) fn write_key(writer: &mut impl Write, key: &KeyType) -> io::Result<()> {
+    writer.write_all(&(key.len() as u16).to_be_bytes())?;
+    writer.write_all(key)
+}
+
+pub(crate) fn read_key(reader: &mut impl Read) -> io::Result<KeyType> {
+    let key_len = read_u16(reader)? as usize;
+    read_bytes(reader, key_len)
+}
+
+pub(crate) fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+pub(crate) fn read_u8(reader: &mut impl Read) -> io::Result<u8> {
+    let mut buf = [0u8; 1];
+    reader.read_exact(&mut buf)?;
+    Ok(buf[0])
+}
+
+pub(crate) fn read_u16(reader: &mut impl Read) -> io::Result<u16> {
+    let mut buf = [0u8; 2];
+    reader.read_exact(&mut buf)?;
+    Ok(u16::from_be_bytes(buf))
+}
+
+pub(crate) fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut buf = [0u8; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_be_bytes(buf))
+}
+
+pub(crate) fn read_u64(reader: &mut impl Read) -> io::Result<u64> {
+    let mut buf = [0u8; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(u64::from_be_bytes(buf))
+}
+
+pub(crate) fn read_bytes(reader: &mut impl Read, len: usize) -> io::Result<Bytes> {
+    let mut buf = vec![0u8; len];
+    reader.read_exact(&mut buf)?;
+    Ok(Bytes::from(buf))
+}
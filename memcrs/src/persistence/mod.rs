@@ -0,0 +1,5 @@
+pub mod aof;
+pub(crate) mod format;
+pub mod snapshot;
+pub(crate) mod stripes;
+pub mod write_behind;
@@ -0,0 +1,389 @@
+use super::format::{invalid_data, read_record, read_u16, read_u64, read_u8, write_record, Clock};
+use crate::cache::cache::{Cache, KeyType, Record};
+use crate::server::admin_signal::{AdminSignal, AdminSignalKind};
+use crate::server::timer::Timer;
+
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio_util::sync::CancellationToken;
+
+/// Snapshot file layout, all integers are big endian:
+///
+/// ```text
+/// header: magic "MCRSSNAP" | version u16 | created at u64 (unix seconds)
+/// item:   tag 1 | key len u16 | key | flags u32 | cas u64
+///         | expiration u64 (unix seconds, 0 never expires)
+///         | value len u32 | value
+/// footer: tag 0 | number of items u64
+/// ```
+const MAGIC: &[u8; 8] = b"MCRSSNAP";
+const VERSION: u16 = 1;
+const TAG_ITEM: u8 = 1;
+const TAG_END: u8 = 0;
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct RestoreStats {
+    /// items loaded into the store
+    pub restored: u64,
+    /// items which expired since snapshot was taken
+    pub expired: u64,
+    /// items rejected by the store, e.g. already present or no room left
+    pub skipped: u64,
+}
+
+fn write_item(
+    writer: &mut impl Write,
+    clock: &Clock,
+    key: &KeyType,
+    record: &Record,
+) -> io::Result<()> {
+    writer.write_all(&[TAG_ITEM])?;
+    write_record(writer, clock, key, record)
+}
+
+/// Writes every live item of a store, returns number of items written.
+///
+/// Store is not frozen while it is walked, every item is written as it
+/// was at some point during the snapshot, items modified concurrently
+/// may be written in their old or new version.
+pub fn write_to(writer: &mut impl Write, store: &dyn Cache, timer: &dyn Timer) -> io::Result<u64> {
+    let clock = Clock::new(timer);
+    writer.write_all(MAGIC)?;
+    writer.write_all(&VERSION.to_be_bytes())?;
+    writer.write_all(&clock.unix_now.to_be_bytes())?;
+    let mut items: u64 = 0;
+    let mut result = Ok(());
+    store.for_each_item(&mut |key, record| {
+        if result.is_ok() {
+            result = write_item(writer, &clock, key, record);
+            items += 1;
+        }
+    });
+    result?;
+    writer.write_all(&[TAG_END])?;
+    writer.write_all(&items.to_be_bytes())?;
+    Ok(items)
+}
+
+/// Loads items into a store, expired items are dropped.
+pub fn restore_from(
+    reader: &mut impl Read,
+    store: &dyn Cache,
+    timer: &dyn Timer,
+) -> io::Result<RestoreStats> {
+    let mut magic = [0u8; 8];
+    reader.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(invalid_data("Not a memcrs snapshot"));
+    }
+    let version = read_u16(reader)?;
+    if version != VERSION {
+        return Err(invalid_data(&format!(
+            "Unsupported snapshot version: {}",
+            version
+        )));
+    }
+    let _created = read_u64(reader)?;
+    let clock = Clock::new(timer);
+    let mut stats = RestoreStats::default();
+    loop {
+        match read_u8(reader)? {
+            TAG_ITEM => {}
+            TAG_END => break,
+            _ => return Err(invalid_data("Corrupted snapshot item")),
+        }
+        let (key, record) = match read_record(reader)?.into_record(&clock) {
+            Some(item) => item,
+            None => {
+                stats.expired += 1;
+                continue;
+            }
+        };
+        match store.restore(key, record) {
+            Ok(()) => stats.restored += 1,
+            Err(err) => {
+                debug!("Snapshot item not restored: {:?}", err);
+                stats.skipped += 1;
+            }
+        }
+    }
+    let items = read_u64(reader)?;
+    if items != stats.restored + stats.expired + stats.skipped {
+        return Err(invalid_data("Snapshot item count mismatch"));
+    }
+    Ok(stats)
+}
+
+/// Writes snapshot to a temporary file which replaces `path` once it is
+/// synced, so a crash never leaves a partially written snapshot behind.
+pub fn write_snapshot(store: &dyn Cache, timer: &dyn Timer, path: &Path) -> io::Result<u64> {
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    let tmp_path = PathBuf::from(tmp_path);
+    let file = File::create(&tmp_path)?;
+    let mut writer = BufWriter::new(file);
+    let items = write_to(&mut writer, store, timer)?;
+    let file = writer.into_inner().map_err(|err| err.into_error())?;
+    file.sync_all()?;
+    fs::rename(&tmp_path, path)?;
+    Ok(items)
+}
+
+pub fn restore_snapshot(
+    store: &dyn Cache,
+    timer: &dyn Timer,
+    path: &Path,
+) -> io::Result<RestoreStats> {
+    let mut reader = BufReader::new(File::open(path)?);
+    restore_from(&mut reader, store, timer)
+}
+
+/// Takes snapshots periodically and on SIGUSR1.
+pub struct SnapshotService {
+    store: Arc<dyn Cache + Send + Sync>,
+    timer: Arc<dyn Timer + Send + Sync>,
+    path: PathBuf,
+    interval: Option<Duration>,
+    cancellation_token: CancellationToken,
+}
+
+impl SnapshotService {
+    pub fn new(
+        store: Arc<dyn Cache + Send + Sync>,
+        timer: Arc<dyn Timer + Send + Sync>,
+        path: PathBuf,
+        interval: Option<Duration>,
+        cancellation_token: CancellationToken,
+    ) -> Self {
+        SnapshotService {
+            store,
+            timer,
+            path,
+            interval,
+            cancellation_token,
+        }
+    }
+
+    /// Writes snapshot, errors are logged
+    pub fn snapshot(&self) {
+        let start = Instant::now();
+        match write_snapshot(self.store.as_ref(), self.timer.as_ref(), &self.path) {
+            Ok(items) => info!(
+                "Snapshot of {} items written to {} in {:?}",
+                items,
+                self.path.display(),
+                start.elapsed()
+            ),
+            Err(err) => error!("Cannot write snapshot to {}: {}", self.path.display(), err),
+        }
+    }
+
+    pub async fn run(self: Arc<Self>) {
+        let mut interval = self.interval.map(|period| {
+            let mut interval =
+                tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            interval
+        });
+        let mut admin_signal = AdminSignal::new(AdminSignalKind::Snapshot);
+        loop {
+            tokio::select! {
+                _ = self.cancellation_token.cancelled() => {
+                    info!("Snapshot service received cancellation signal, stopping...");
+                    break;
+                },
+                _ = async { interval.as_mut().unwrap().tick().await }, if interval.is_some() => {
+                    self.snapshot_in_background().await;
+                },
+                _ = admin_signal.recv() => {
+                    info!("Snapshot requested");
+                    self.snapshot_in_background().await;
+                },
+            }
+        }
+    }
+
+    async fn snapshot_in_background(self: &Arc<Self>) {
+        let service = self.clone();
+        if let Err(err) = tokio::task::spawn_blocking(move || service.snapshot()).await {
+            error!("Snapshot task failed: {}", err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memcache::cli::parser::DashMapConfig;
+    use crate::memory_store::dash_map_store::DashMapMemoryStore;
+    use crate::mock::mock_server::{MockSystemTimer, SetableTimer};
+    use bytes::Bytes;
+
+    fn create_store(timer: Arc<MockSystemTimer>) -> DashMapMemoryStore {
+        DashMapMemoryStore::new(timer, DashMapConfig::default())
+    }
+
+    fn snapshot(store: &dyn Cache, timer: &dyn Timer) -> Vec<u8> {
+        let mut buf = Vec::new();
+        write_to(&mut buf, store, timer).unwrap();
+        buf
+    }
+
+    #[test]
+    fn test_round_trip_keeps_metadata() {
+        let timer = Arc::new(MockSystemTimer::new());
+        let store = create_store(timer.clone());
+        store
+            .set(
+                Bytes::from("key"),
+                Record::new(Bytes::from("value"), 0, 42, 0),
+            )
+            .unwrap();
+        let cas = store.get(&Bytes::from("key")).unwrap().header.cas;
+        let buf = snapshot(&store, timer.as_ref());
+
+        let restored = create_store(timer.clone());
+        let stats = restore_from(&mut buf.as_slice(), &restored, timer.as_ref()).unwrap();
+        assert_eq!(
+            stats,
+            RestoreStats {
+                restored: 1,
+                ..Default::default()
+            }
+        );
+        let record = restored.get(&Bytes::from("key")).unwrap();
+        assert_eq!(record.value, Bytes::from("value"));
+        assert_eq!(record.header.flags, 42);
+        assert_eq!(record.header.cas, cas);
+        assert_eq!(record.header.time_to_live, 0);
+        // CAS ids are not reused after restore
+        let status = restored
+            .set(Bytes::from("other"), Record::new(Bytes::from("v"), 0, 0, 0))
+            .unwrap();
+        assert!(status.cas > cas);
+    }
+
+    #[test]
+    fn test_remaining_ttl_is_kept_across_restart() {
+        let timer = Arc::new(MockSystemTimer::new());
+        timer.set(100);
+        let store = create_store(timer.clone());
+        store
+            .set(
+                Bytes::from("key"),
+                Record::new(Bytes::from("value"), 0, 0, 50),
+            )
+            .unwrap();
+        timer.set(110);
+        let buf = snapshot(&store, timer.as_ref());
+
+        // restarted server starts counting from 0
+        let restarted_timer = Arc::new(MockSystemTimer::new());
+        restarted_timer.set_process_started(timer.unix_timestamp());
+        let restored = create_store(restarted_timer.clone());
+        restore_from(&mut buf.as_slice(), &restored, restarted_timer.as_ref()).unwrap();
+        let ttl = restored
+            .get(&Bytes::from("key"))
+            .unwrap()
+            .header
+            .time_to_live;
+        assert_eq!(ttl, 40);
+    }
+
+    #[test]
+    fn test_expired_items_are_skipped() {
+        let timer = Arc::new(MockSystemTimer::new());
+        let store = create_store(timer.clone());
+        let mut buf = Vec::new();
+        let clock = Clock::new(timer.as_ref());
+        buf.extend_from_slice(MAGIC);
+        buf.extend_from_slice(&VERSION.to_be_bytes());
+        buf.extend_from_slice(&clock.unix_now.to_be_bytes());
+        let record = Record::new(Bytes::from("value"), 1, 0, 0);
+        write_item(&mut buf, &clock, &Bytes::from("live"), &record).unwrap();
+        // expired an hour ago
+        buf.push(TAG_ITEM);
+        buf.extend_from_slice(&4u16.to_be_bytes());
+        buf.extend_from_slice(b"dead");
+        buf.extend_from_slice(&0u32.to_be_bytes());
+        buf.extend_from_slice(&2u64.to_be_bytes());
+        buf.extend_from_slice(&(clock.unix_now - 3600).to_be_bytes());
+        buf.extend_from_slice(&1u32.to_be_bytes());
+        buf.push(b'x');
+        buf.push(TAG_END);
+        buf.extend_from_slice(&2u64.to_be_bytes());
+
+        let stats = restore_from(&mut buf.as_slice(), &store, timer.as_ref()).unwrap();
+        assert_eq!(stats.restored, 1);
+        assert_eq!(stats.expired, 1);
+        assert!(store.get(&Bytes::from("live")).is_ok());
+        assert!(store.get(&Bytes::from("dead")).is_err());
+    }
+
+    #[test]
+    fn test_existing_keys_are_not_overwritten() {
+        let timer = Arc::new(MockSystemTimer::new());
+        let store = create_store(timer.clone());
+        store
+            .set(Bytes::from("key"), Record::new(Bytes::from("old"), 0, 0, 0))
+            .unwrap();
+        let buf = snapshot(&store, timer.as_ref());
+        store
+            .set(Bytes::from("key"), Record::new(Bytes::from("new"), 0, 0, 0))
+            .unwrap();
+        let stats = restore_from(&mut buf.as_slice(), &store, timer.as_ref()).unwrap();
+        assert_eq!(stats.skipped, 1);
+        assert_eq!(
+            store.get(&Bytes::from("key")).unwrap().value,
+            Bytes::from("new")
+        );
+    }
+
+    #[test]
+    fn test_invalid_snapshots_are_rejected() {
+        let timer = Arc::new(MockSystemTimer::new());
+        let store = create_store(timer.clone());
+        store
+            .set(
+                Bytes::from("key"),
+                Record::new(Bytes::from("value"), 0, 0, 0),
+            )
+            .unwrap();
+        let buf = snapshot(&store, timer.as_ref());
+
+        let mut bad_magic = buf.clone();
+        bad_magic[0] = b'X';
+        let mut bad_version = buf.clone();
+        bad_version[MAGIC.len() + 1] = 9;
+        let truncated = &buf[..buf.len() - 4];
+        for data in [bad_magic.as_slice(), bad_version.as_slice(), truncated] {
+            let restored = create_store(timer.clone());
+            let mut data = data;
+            assert!(restore_from(&mut data, &restored, timer.as_ref()).is_err());
+        }
+    }
+
+    #[test]
+    fn test_write_snapshot_to_file() {
+        let timer = Arc::new(MockSystemTimer::new());
+        let store = create_store(timer.clone());
+        for idx in 0..100 {
+            store
+                .set(
+                    Bytes::from(format!("key{}", idx)),
+                    Record::new(Bytes::from("value"), 0, 0, 0),
+                )
+                .unwrap();
+        }
+        let path = std::env::temp_dir().join(format!("memcrs-snapshot-{}", std::process::id()));
+        assert_eq!(write_snapshot(&store, timer.as_ref(), &path).unwrap(), 100);
+        let restored = create_store(timer.clone());
+        let stats = restore_snapshot(&restored, timer.as_ref(), &path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(stats.restored, 100);
+        assert!(restored.get(&Bytes::from("key99")).is_ok());
+    }
+}
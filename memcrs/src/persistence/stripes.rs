@@ -0,0 +1,36 @@
+use crate::cache::cache::KeyType;
+use std::hash::{BuildHasher, RandomState};
+use std::sync::{Mutex, MutexGuard};
+
+/// Mutations of different keys only wait for each other if the keys
+/// share a stripe
+const STRIPES: usize = 64;
+
+/// Locks ordering mutations of a key with the entries they produce,
+/// without serializing mutations of unrelated keys
+pub(crate) struct KeyStripes {
+    hasher: RandomState,
+    stripes: Vec<Mutex<()>>,
+}
+
+impl KeyStripes {
+    pub(crate) fn new() -> KeyStripes {
+        KeyStripes {
+            hasher: RandomState::new(),
+            stripes: (0..STRIPES).map(|_| Mutex::new(())).collect(),
+        }
+    }
+
+    pub(crate) fn lock(&self, key: &KeyType) -> MutexGuard<'_, ()> {
+        let stripe = self.hasher.hash_one(key) as usize % STRIPES;
+        self.stripes[stripe].lock().unwrap()
+    }
+
+    /// Waits for mutations of all keys, used by flushes
+    pub(crate) fn lock_all(&self) -> Vec<MutexGuard<'_, ()>> {
+        self.stripes
+            .iter()
+            .map(|stripe| stripe.lock().unwrap())
+            .collect()
+    }
+}
@@ -0,0 +1,511 @@
+use super::aof::{unix_expiration, KeyState};
+use super::format::Clock;
+use super::stripes::KeyStripes;
+use crate::cache::cache::{Cache, KeyType, Record};
+use crate::cache::error::Result;
+use crate::server::timer::Timer;
+use bytes::Bytes;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+
+/// Mutation done by a client, handed to a sink after it was applied
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Mutation {
+    Set {
+        key: KeyType,
+        value: Bytes,
+        flags: u32,
+        /// unix time, 0 if the item never expires
+        expiration: u64,
+    },
+    Delete {
+        key: KeyType,
+    },
+}
+
+/// Destination of mutations written behind the cache
+pub trait Sink: Send + Sync {
+    /// Writes mutations in order, a failed batch is retried as a whole
+    fn write(&self, batch: &[Mutation]) -> io::Result<()>;
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct WriteBehindConfig {
+    /// mutations waiting for the sink, mutations over it are dropped
+    pub queue_size: usize,
+    /// most mutations handed to the sink at once
+    pub batch_size: usize,
+    /// attempts after the first one before a batch is dropped
+    pub retries: u32,
+    /// delay before the first retry, doubled for every next one
+    pub retry_delay: Duration,
+}
+
+impl Default for WriteBehindConfig {
+    fn default() -> Self {
+        WriteBehindConfig {
+            queue_size: 65536,
+            batch_size: 256,
+            retries: 3,
+            retry_delay: Duration::from_millis(100),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Counters {
+    queued: AtomicU64,
+    written: AtomicU64,
+    dropped: AtomicU64,
+    failed: AtomicU64,
+}
+
+/// Mutations handed to the write-behind sink, counted in mutations
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct WriteBehindStats {
+    pub queued: u64,
+    pub written: u64,
+    /// mutations which did not fit in the queue
+    pub dropped: u64,
+    /// mutations of batches the sink failed to write after all retries
+    pub failed: u64,
+}
+
+/// Queues mutations for the sink without waiting for it
+pub struct WriteBehind {
+    queue: mpsc::Sender<Mutation>,
+    timer: Arc<dyn Timer + Send + Sync>,
+    counters: Arc<Counters>,
+    stripes: KeyStripes,
+}
+
+impl WriteBehind {
+    /// Applies a mutation of a key and queues the state `state` says it
+    /// left the key in, mutations of a key are queued in the order they
+    /// were applied
+    pub fn write<T>(
+        &self,
+        store: &dyn Cache,
+        key: &KeyType,
+        mutation: impl FnOnce() -> Result<T>,
+        state: impl FnOnce(&T) -> KeyState,
+    ) -> Result<T> {
+        let _stripe = self.stripes.lock(key);
+        let result = mutation()?;
+        match state(&result) {
+            KeyState::Written(record) => self.send(Mutation::Set {
+                key: key.clone(),
+                value: record.value,
+                flags: record.header.flags,
+                expiration: unix_expiration(self.timer.as_ref(), record.header.time_to_live),
+            }),
+            KeyState::Deleted => self.delete(key.clone()),
+            KeyState::Stored => match store.get(key) {
+                Ok(record) => self.set(key.clone(), &record),
+                Err(_) => self.delete(key.clone()),
+            },
+        }
+        Ok(result)
+    }
+
+    /// Queues item as it is stored now
+    fn set(&self, key: KeyType, record: &Record) {
+        let clock = Clock::new(self.timer.as_ref());
+        self.send(Mutation::Set {
+            key,
+            value: record.value.clone(),
+            flags: record.header.flags,
+            expiration: clock.to_unix(record.header.time_to_live),
+        })
+    }
+
+    fn delete(&self, key: KeyType) {
+        self.send(Mutation::Delete { key })
+    }
+
+    fn send(&self, mutation: Mutation) {
+        match self.queue.try_send(mutation) {
+            Ok(()) => self.counters.queued.fetch_add(1, Ordering::Relaxed),
+            Err(_) => self.counters.dropped.fetch_add(1, Ordering::Relaxed),
+        };
+    }
+
+    pub fn stats(&self) -> WriteBehindStats {
+        WriteBehindStats {
+            queued: self.counters.queued.load(Ordering::Relaxed),
+            written: self.counters.written.load(Ordering::Relaxed),
+            dropped: self.counters.dropped.load(Ordering::Relaxed),
+            failed: self.counters.failed.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Hands queued mutations to the sink in batches, retrying failed ones
+pub struct WriteBehindService {
+    receiver: Mutex<Option<mpsc::Receiver<Mutation>>>,
+    sink: Arc<dyn Sink>,
+    config: WriteBehindConfig,
+    counters: Arc<Counters>,
+    cancellation_token: CancellationToken,
+}
+
+/// Queue of mutations and the service draining it into `sink`
+pub fn write_behind(
+    sink: Arc<dyn Sink>,
+    config: WriteBehindConfig,
+    timer: Arc<dyn Timer + Send + Sync>,
+    cancellation_token: CancellationToken,
+) -> (Arc<WriteBehind>, Arc<WriteBehindService>) {
+    let (sender, receiver) = mpsc::channel(config.queue_size.max(1));
+    let counters = Arc::new(Counters::default());
+    let queue = Arc::new(WriteBehind {
+        queue: sender,
+        timer,
+        counters: counters.clone(),
+        stripes: KeyStripes::new(),
+    });
+    let service = Arc::new(WriteBehindService {
+        receiver: Mutex::new(Some(receiver)),
+        sink,
+        config,
+        counters,
+        cancellation_token,
+    });
+    (queue, service)
+}
+
+impl WriteBehindService {
+    /// Runs until cancelled, mutations queued by then are written
+    /// before it returns
+    pub async fn run(self: Arc<Self>) {
+        let mut receiver = match self.receiver.lock().unwrap().take() {
+            Some(receiver) => receiver,
+            None => return,
+        };
+        let batch_size = self.config.batch_size.max(1);
+        loop {
+            let mutation = tokio::select! {
+                _ = self.cancellation_token.cancelled() => {
+                    info!("Write-behind service received cancellation signal, stopping...");
+                    break;
+                },
+                mutation = receiver.recv() => match mutation {
+                    Some(mutation) => mutation,
+                    None => break,
+                },
+            };
+            let mut batch = vec![mutation];
+            while batch.len() < batch_size {
+                match receiver.try_recv() {
+                    Ok(mutation) => batch.push(mutation),
+                    Err(_) => break,
+                }
+            }
+            self.write(batch).await;
+        }
+        receiver.close();
+        let mut batch = Vec::new();
+        while let Ok(mutation) = receiver.try_recv() {
+            batch.push(mutation);
+            if batch.len() == batch_size {
+                self.write(std::mem::take(&mut batch)).await;
+            }
+        }
+        if !batch.is_empty() {
+            self.write(batch).await;
+        }
+    }
+
+    async fn write(&self, batch: Vec<Mutation>) {
+        let batch = Arc::new(batch);
+        let mut delay = self.config.retry_delay;
+        for attempt in 0..=self.config.retries {
+            if attempt > 0 {
+                tokio::time::sleep(delay).await;
+                delay *= 2;
+            }
+            let sink = self.sink.clone();
+            let mutations = batch.clone();
+            match tokio::task::spawn_blocking(move || sink.write(&mutations)).await {
+                Ok(Ok(())) => {
+                    self.counters
+                        .written
+                        .fetch_add(batch.len() as u64, Ordering::Relaxed);
+                    return;
+                }
+                Ok(Err(err)) => warn!(
+                    "Write-behind batch of {} mutations failed, attempt {}: {}",
+                    batch.len(),
+                    attempt + 1,
+                    err
+                ),
+                Err(err) => error!("Write-behind task failed: {}", err),
+            }
+        }
+        error!(
+            "Dropping write-behind batch of {} mutations after {} retries",
+            batch.len(),
+            self.config.retries
+        );
+        self.counters
+            .failed
+            .fetch_add(batch.len() as u64, Ordering::Relaxed);
+    }
+}
+
+/// Appends every mutation as a JSON object on its own line, keys and
+/// values which are not UTF-8 are written hex encoded to `key_hex` and
+/// `value_hex`
+pub struct JsonLinesSink {
+    path: PathBuf,
+    writer: Mutex<BufWriter<File>>,
+}
+
+impl JsonLinesSink {
+    pub fn open(path: &Path) -> io::Result<JsonLinesSink> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(JsonLinesSink {
+            path: path.to_path_buf(),
+            writer: Mutex::new(BufWriter::new(file)),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+fn push_json_string(line: &mut String, value: &str) {
+    line.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => line.push_str("\\\""),
+            '\\' => line.push_str("\\\\"),
+            '\n' => line.push_str("\\n"),
+            '\r' => line.push_str("\\r"),
+            '\t' => line.push_str("\\t"),
+            c if (c as u32) < 0x20 => line.push_str(&format!("\\u{:04x}", c as u32)),
+            c => line.push(c),
+        }
+    }
+    line.push('"');
+}
+
+fn push_bytes_field(line: &mut String, name: &str, value: &[u8]) {
+    match std::str::from_utf8(value) {
+        Ok(value) => {
+            line.push_str(&format!(",\"{}\":", name));
+            push_json_string(line, value);
+        }
+        Err(_) => {
+            line.push_str(&format!(",\"{}_hex\":\"", name));
+            for byte in value {
+                line.push_str(&format!("{:02x}", byte));
+            }
+            line.push('"');
+        }
+    }
+}
+
+fn json_line(mutation: &Mutation) -> String {
+    let mut line = String::new();
+    match mutation {
+        Mutation::Set {
+            key,
+            value,
+            flags,
+            expiration,
+        } => {
+            line.push_str("{\"op\":\"set\"");
+            push_bytes_field(&mut line, "key", key);
+            push_bytes_field(&mut line, "value", value);
+            line.push_str(&format!(
+                ",\"flags\":{},\"expiration\":{}}}",
+                flags, expiration
+            ));
+        }
+        Mutation::Delete { key } => {
+            line.push_str("{\"op\":\"delete\"");
+            push_bytes_field(&mut line, "key", key);
+            line.push('}');
+        }
+    }
+    line.push('\n');
+    line
+}
+
+impl Sink for JsonLinesSink {
+    fn write(&self, batch: &[Mutation]) -> io::Result<()> {
+        let mut writer = self.writer.lock().unwrap();
+        for mutation in batch {
+            writer.write_all(json_line(mutation).as_bytes())?;
+        }
+        writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::mock_server::{MockSystemTimer, MOCK_UNIX_START};
+    use std::fs;
+    use std::sync::atomic::AtomicU32;
+
+    struct TempFile {
+        path: PathBuf,
+    }
+
+    impl TempFile {
+        fn new(name: &str) -> TempFile {
+            let path = std::env::temp_dir().join(format!(
+                "memcrs-write-behind-{}-{}",
+                name,
+                std::process::id()
+            ));
+            let _ = fs::remove_file(&path);
+            TempFile { path }
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+
+    /// Fails the first `failures` batches
+    struct FlakySink {
+        failures: AtomicU32,
+        batches: Mutex<Vec<Vec<Mutation>>>,
+    }
+
+    impl Sink for FlakySink {
+        fn write(&self, batch: &[Mutation]) -> io::Result<()> {
+            if self
+                .failures
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |failures| {
+                    failures.checked_sub(1)
+                })
+                .is_ok()
+            {
+                return Err(io::Error::other("sink is down"));
+            }
+            self.batches.lock().unwrap().push(batch.to_vec());
+            Ok(())
+        }
+    }
+
+    fn delete(key: &'static str) -> Mutation {
+        Mutation::Delete {
+            key: Bytes::from(key),
+        }
+    }
+
+    #[test]
+    fn test_json_lines() {
+        let set = Mutation::Set {
+            key: Bytes::from("key"),
+            value: Bytes::from("say \"hi\"\n\u{1}"),
+            flags: 5,
+            expiration: 1_700_000_100,
+        };
+        assert_eq!(
+            json_line(&set),
+            "{\"op\":\"set\",\"key\":\"key\",\"value\":\"say \\\"hi\\\"\\n\\u0001\",\"flags\":5,\"expiration\":1700000100}\n"
+        );
+        let binary = Mutation::Delete {
+            key: Bytes::from_static(&[0xff, 0x01]),
+        };
+        assert_eq!(
+            json_line(&binary),
+            "{\"op\":\"delete\",\"key_hex\":\"ff01\"}\n"
+        );
+
+        let file = TempFile::new("json");
+        let sink = JsonLinesSink::open(&file.path).unwrap();
+        sink.write(&[set, delete("key")]).unwrap();
+        sink.write(&[delete("other")]).unwrap();
+        let lines = fs::read_to_string(&file.path).unwrap();
+        assert_eq!(lines.lines().count(), 3);
+        assert!(lines.ends_with("{\"op\":\"delete\",\"key\":\"other\"}\n"));
+    }
+
+    #[tokio::test]
+    async fn test_queued_mutations_are_batched_and_retried() {
+        let sink = Arc::new(FlakySink {
+            failures: AtomicU32::new(2),
+            batches: Mutex::new(Vec::new()),
+        });
+        let config = WriteBehindConfig {
+            queue_size: 3,
+            batch_size: 2,
+            retries: 2,
+            retry_delay: Duration::from_millis(1),
+        };
+        let timer = Arc::new(MockSystemTimer::new());
+        let token = CancellationToken::new();
+        let (queue, service) = write_behind(sink.clone(), config, timer, token.clone());
+
+        queue.set(Bytes::from("a"), &Record::new(Bytes::from("1"), 0, 0, 10));
+        queue.delete(Bytes::from("b"));
+        queue.delete(Bytes::from("c"));
+        queue.delete(Bytes::from("d"));
+        token.cancel();
+        service.run().await;
+
+        let batches = sink.batches.lock().unwrap();
+        assert_eq!(batches.len(), 2);
+        assert_eq!(
+            batches[0][0],
+            Mutation::Set {
+                key: Bytes::from("a"),
+                value: Bytes::from("1"),
+                flags: 0,
+                expiration: MOCK_UNIX_START + 10,
+            }
+        );
+        assert_eq!(batches[0][1], delete("b"));
+        assert_eq!(batches[1], vec![delete("c")]);
+        let stats = queue.stats();
+        assert_eq!(
+            stats,
+            WriteBehindStats {
+                queued: 3,
+                written: 3,
+                dropped: 1,
+                failed: 0,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_batch_is_dropped_after_retries() {
+        let sink = Arc::new(FlakySink {
+            failures: AtomicU32::new(u32::MAX),
+            batches: Mutex::new(Vec::new()),
+        });
+        let config = WriteBehindConfig {
+            retries: 1,
+            retry_delay: Duration::from_millis(1),
+            ..Default::default()
+        };
+        let token = CancellationToken::new();
+        let (queue, service) = write_behind(
+            sink.clone(),
+            config,
+            Arc::new(MockSystemTimer::new()),
+            token.clone(),
+        );
+        queue.delete(Bytes::from("a"));
+        token.cancel();
+        service.run().await;
+        assert_eq!(sink.failures.load(Ordering::SeqCst), u32::MAX - 2);
+        assert_eq!(queue.stats().failed, 1);
+    }
+}
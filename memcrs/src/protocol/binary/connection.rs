@@ -151,6 +151,22 @@ impl MemcacheBinaryConnection {
         Ok(())
     }
 
+    /// Writes responses with a single write to the socket
+    pub async fn write_batch(&mut self, msgs: &[BinaryResponse]) -> io::Result<()> {
+        let mut data = BytesMut::new();
+        for msg in msgs {
+            data.extend_from_slice(&self.encoder.encode_message(msg).data);
+        }
+        self.stream.write_all(&data[..]).await?;
+        Ok(())
+    }
+
+    /// Returns true if received data is waiting to be decoded,
+    /// read_frame will not wait for the socket then
+    pub fn has_buffered_data(&self) -> bool {
+        !self.buffer.is_empty()
+    }
+
     async fn write_data_to_stream(&mut self, msg: ResponseMessage) -> io::Result<()> {
         self.stream.write_all(&msg.data[..]).await?;
         Ok(())
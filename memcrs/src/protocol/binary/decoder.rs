@@ -1,3 +1,5 @@
+use crate::cache::error::CacheError;
+use crate::protocol::binary::encoder::{storage_error_to_response, BinaryResponse};
 use crate::protocol::binary::network;
 use bytes::{Buf, Bytes, BytesMut};
 use num_traits::FromPrimitive;
@@ -36,6 +38,8 @@ pub enum BinaryRequest {
     QuitQuietly(network::QuitRequest),
     ItemTooLarge(network::SetRequest),
     Stats(network::StatsRequest),
+    Scan(network::ScanRequest),
+    InvalidateTag(network::InvalidateTagRequest),
     UnkownCommand(network::UnkownCommandErrorRequest),
 }
 
@@ -63,11 +67,13 @@ impl BinaryRequest {
             | BinaryRequest::IncrementQuiet(request)
             | BinaryRequest::Decrement(request)
             | BinaryRequest::DecrementQuiet(request) => request.key.clone(),
+            BinaryRequest::Scan(request) => request.prefix.clone(),
+            BinaryRequest::InvalidateTag(request) => request.key.clone(),
 
             BinaryRequest::Noop(_request)
             | BinaryRequest::Version(_request)
-            | BinaryRequest::Stats(_request)
             | BinaryRequest::UnkownCommand(_request) => Bytes::from(""),
+            BinaryRequest::Stats(request) => request.key.clone(),
             BinaryRequest::Flush(_request) | BinaryRequest::FlushQuietly(_request) => {
                 Bytes::from("")
             }
@@ -102,16 +108,45 @@ impl BinaryRequest {
             | BinaryRequest::Decrement(request)
             | BinaryRequest::DecrementQuiet(request) => &request.header,
 
+            BinaryRequest::Scan(request) => &request.header,
+            BinaryRequest::InvalidateTag(request) => &request.header,
+
             BinaryRequest::Noop(request)
             | BinaryRequest::Version(request)
-            | BinaryRequest::Stats(request)
             | BinaryRequest::UnkownCommand(request) => &request.header,
+            BinaryRequest::Stats(request) => &request.header,
 
             BinaryRequest::Flush(request) | BinaryRequest::FlushQuietly(request) => &request.header,
 
             BinaryRequest::Quit(request) | BinaryRequest::QuitQuietly(request) => &request.header,
         }
     }
+
+    /// Requests changing the cache, replicas reject them
+    pub fn is_mutation(&self) -> bool {
+        matches!(
+            self,
+            BinaryRequest::Set(_)
+                | BinaryRequest::SetQuietly(_)
+                | BinaryRequest::Add(_)
+                | BinaryRequest::AddQuietly(_)
+                | BinaryRequest::Replace(_)
+                | BinaryRequest::ReplaceQuietly(_)
+                | BinaryRequest::Append(_)
+                | BinaryRequest::AppendQuietly(_)
+                | BinaryRequest::Prepend(_)
+                | BinaryRequest::PrependQuietly(_)
+                | BinaryRequest::Increment(_)
+                | BinaryRequest::IncrementQuiet(_)
+                | BinaryRequest::Decrement(_)
+                | BinaryRequest::DecrementQuiet(_)
+                | BinaryRequest::Delete(_)
+                | BinaryRequest::DeleteQuiet(_)
+                | BinaryRequest::Flush(_)
+                | BinaryRequest::FlushQuietly(_)
+                | BinaryRequest::InvalidateTag(_)
+        )
+    }
 }
 
 #[derive(PartialEq, Debug)]
@@ -244,13 +279,17 @@ impl MemcacheBinaryDecoder {
             Some(network::Command::Noop)
             | Some(network::Command::Quit)
             | Some(network::Command::QuitQuiet)
-            | Some(network::Command::Stat)
             | Some(network::Command::Version) => self.parse_header_only_request(src),
 
+            Some(network::Command::Stat) => self.parse_stats_request(src),
+
             Some(network::Command::Flush) | Some(network::Command::FlushQuiet) => {
                 self.parse_flush_request(src)
             }
 
+            Some(network::Command::Scan) => self.parse_scan_request(src),
+            Some(network::Command::InvalidateTag) => self.parse_invalidate_tag_request(src),
+
             Some(network::Command::Touch)
             | Some(network::Command::GetAndTouch)
             | Some(network::Command::GetAndTouchQuiet)
@@ -370,6 +409,23 @@ impl MemcacheBinaryDecoder {
         }
     }
 
+    fn parse_stats_request(&self, src: &mut BytesMut) -> Result<Option<BinaryRequest>, io::Error> {
+        if !self.request_valid(src, false) {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "Incorrect stats request",
+            ));
+        }
+        let value_len = self.get_value_len();
+        src.advance(self.header.extras_length as usize);
+        let key = src.split_to(self.header.key_length as usize).freeze();
+        src.advance(value_len);
+        Ok(Some(BinaryRequest::Stats(network::StatsRequest {
+            header: self.header,
+            key,
+        })))
+    }
+
     fn parse_flush_request(&self, src: &mut BytesMut) -> Result<Option<BinaryRequest>, io::Error> {
         if !self.request_valid(src, false) {
             return Err(Error::new(
@@ -394,6 +450,49 @@ impl MemcacheBinaryDecoder {
         }
     }
 
+    fn parse_scan_request(&self, src: &mut BytesMut) -> Result<Option<BinaryRequest>, io::Error> {
+        if !self.request_valid(src, false)
+            || (self.header.extras_length != 0 && self.header.extras_length != 12)
+        {
+            return Err(Error::new(ErrorKind::InvalidData, "Incorrect scan request"));
+        }
+        let (mut cursor, mut count) = (0, 0);
+        if self.header.extras_length == 12 {
+            cursor = src.get_u64();
+            count = src.get_u32();
+        }
+        let value_len = self.get_value_len();
+        let prefix = src.split_to(self.header.key_length as usize).freeze();
+        src.advance(value_len);
+        Ok(Some(BinaryRequest::Scan(network::ScanRequest {
+            header: self.header,
+            cursor,
+            count,
+            prefix,
+        })))
+    }
+
+    fn parse_invalidate_tag_request(
+        &self,
+        src: &mut BytesMut,
+    ) -> Result<Option<BinaryRequest>, io::Error> {
+        if !self.request_valid(src, true) || self.header.extras_length != 0 {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "Incorrect invalidate tag request",
+            ));
+        }
+        let value_len = self.get_value_len();
+        let key = src.split_to(self.header.key_length as usize).freeze();
+        src.advance(value_len);
+        Ok(Some(BinaryRequest::InvalidateTag(
+            network::InvalidateTagRequest {
+                header: self.header,
+                key,
+            },
+        )))
+    }
+
     fn parse_append_prepend_request(
         &self,
         src: &mut BytesMut,
@@ -480,6 +579,7 @@ impl MemcacheBinaryDecoder {
             expiration: 0,
             key: BytesMut::new().freeze(),
             value: BytesMut::new().freeze(),
+            tags: Vec::new(),
         };
         Ok(Some(BinaryRequest::ItemTooLarge(set_request)))
     }
@@ -490,9 +590,12 @@ impl MemcacheBinaryDecoder {
         }
 
         let value_len = self.get_value_len();
-        // flags u32 +expiration u32
-        let required_len =
-            2 * std::mem::size_of::<u32>() + self.header.key_length as usize + value_len;
+        // flags u32 +expiration u32, u16 tag list length if tagged
+        let extras_len = match self.header.extras_length {
+            TAGGED_SET_EXTRAS_LENGTH => TAGGED_SET_EXTRAS_LENGTH as usize,
+            _ => 2 * std::mem::size_of::<u32>(),
+        };
+        let required_len = extras_len + self.header.key_length as usize + value_len;
 
         if src.len() < required_len {
             error!(
@@ -509,12 +612,24 @@ impl MemcacheBinaryDecoder {
             ));
         }
 
+        let flags = src.get_u32();
+        let expiration = src.get_u32();
+        let mut tags_len = 0;
+        if extras_len == TAGGED_SET_EXTRAS_LENGTH as usize {
+            tags_len = src.get_u16() as usize;
+        }
+        if tags_len > value_len {
+            return Err(Error::new(ErrorKind::InvalidData, "Incorrect tag list"));
+        }
+        let key = src.split_to(self.header.key_length as usize).freeze();
+        let tags = parse_tags(src.split_to(tags_len).freeze())?;
         let set_request = network::SetRequest {
             header: self.header,
-            flags: src.get_u32(),
-            expiration: src.get_u32(),
-            key: src.split_to(self.header.key_length as usize).freeze(),
-            value: src.split_to(value_len).freeze(),
+            flags,
+            expiration,
+            key,
+            value: src.split_to(value_len - tags_len).freeze(),
+            tags,
         };
 
         match FromPrimitive::from_u8(self.header.opcode) {
@@ -565,6 +680,21 @@ impl MemcacheBinaryDecoder {
     const HEADER_LEN: usize = 24;
 }
 
+const TAGGED_SET_EXTRAS_LENGTH: u8 = 10;
+
+/// Splits a tag list, every tag is prefixed with its u8 length
+fn parse_tags(mut list: Bytes) -> Result<Vec<Bytes>, io::Error> {
+    let mut tags = Vec::new();
+    while list.has_remaining() {
+        let len = list.get_u8() as usize;
+        if len == 0 || len > list.len() {
+            return Err(Error::new(ErrorKind::InvalidData, "Incorrect tag list"));
+        }
+        tags.push(list.split_to(len));
+    }
+    Ok(tags)
+}
+
 impl Decoder for MemcacheBinaryDecoder {
     type Item = BinaryRequest;
     type Error = io::Error;
@@ -591,5 +721,170 @@ impl Decoder for MemcacheBinaryDecoder {
     }
 }
 
+/// Decodes responses of another server, the proxy reads backend
+/// responses with it. Stats responses are not decoded.
+#[derive(Default)]
+pub struct MemcacheBinaryResponseDecoder {}
+
+impl MemcacheBinaryResponseDecoder {
+    const HEADER_LEN: usize = 24;
+
+    pub fn new() -> MemcacheBinaryResponseDecoder {
+        MemcacheBinaryResponseDecoder {}
+    }
+
+    fn parse_header(&self, mut src: &[u8]) -> network::ResponseHeader {
+        network::ResponseHeader {
+            magic: src.get_u8(),
+            opcode: src.get_u8(),
+            key_length: src.get_u16(),
+            extras_length: src.get_u8(),
+            data_type: src.get_u8(),
+            status: src.get_u16(),
+            body_length: src.get_u32(),
+            opaque: src.get_u32(),
+            cas: src.get_u64(),
+        }
+    }
+
+    fn parse_response(
+        &self,
+        mut header: network::ResponseHeader,
+        mut extras: Bytes,
+        key: Bytes,
+        value: Bytes,
+    ) -> Result<BinaryResponse, io::Error> {
+        if header.status != 0 {
+            let err = CacheError::from_status(header.status).unwrap_or(CacheError::InternalError);
+            return Ok(storage_error_to_response(err, &mut header));
+        }
+        let response = match FromPrimitive::from_u8(header.opcode) {
+            Some(network::Command::Get)
+            | Some(network::Command::GetQuiet)
+            | Some(network::Command::GetKey)
+            | Some(network::Command::GetKeyQuiet) => {
+                let flags = match extras.len() {
+                    4 => extras.get_u32(),
+                    _ => 0,
+                };
+                let response = network::GetResponse {
+                    header,
+                    flags,
+                    key,
+                    value,
+                };
+                match FromPrimitive::from_u8(header.opcode) {
+                    Some(network::Command::Get) => BinaryResponse::Get(response),
+                    Some(network::Command::GetQuiet) => BinaryResponse::GetQuietly(response),
+                    Some(network::Command::GetKey) => BinaryResponse::GetKey(response),
+                    _ => BinaryResponse::GetKeyQuietly(response),
+                }
+            }
+            Some(network::Command::Set) | Some(network::Command::SetQuiet) => {
+                BinaryResponse::Set(network::SetResponse { header })
+            }
+            Some(network::Command::Add) | Some(network::Command::AddQuiet) => {
+                BinaryResponse::Add(network::AddResponse { header })
+            }
+            Some(network::Command::Replace) | Some(network::Command::ReplaceQuiet) => {
+                BinaryResponse::Replace(network::ReplaceResponse { header })
+            }
+            Some(network::Command::Append) | Some(network::Command::AppendQuiet) => {
+                BinaryResponse::Append(network::AppendResponse { header })
+            }
+            Some(network::Command::Prepend) | Some(network::Command::PrependQuiet) => {
+                BinaryResponse::Prepend(network::PrependResponse { header })
+            }
+            Some(network::Command::Delete) | Some(network::Command::DeleteQuiet) => {
+                BinaryResponse::Delete(network::DeleteResponse { header })
+            }
+            Some(network::Command::Flush) | Some(network::Command::FlushQuiet) => {
+                BinaryResponse::Flush(network::FlushResponse { header })
+            }
+            Some(network::Command::Quit) | Some(network::Command::QuitQuiet) => {
+                BinaryResponse::Quit(network::QuitResponse { header })
+            }
+            Some(network::Command::Noop) => BinaryResponse::Noop(network::NoopResponse { header }),
+            Some(network::Command::InvalidateTag) => {
+                BinaryResponse::InvalidateTag(network::InvalidateTagResponse { header })
+            }
+            Some(network::Command::Increment)
+            | Some(network::Command::IncrementQuiet)
+            | Some(network::Command::Decrement)
+            | Some(network::Command::DecrementQuiet) => {
+                let mut value = value;
+                if value.len() != 8 {
+                    return Err(Error::new(
+                        ErrorKind::InvalidData,
+                        "Incorrect inc/dec response",
+                    ));
+                }
+                let response = network::IncrementResponse {
+                    header,
+                    value: value.get_u64(),
+                };
+                match FromPrimitive::from_u8(header.opcode) {
+                    Some(network::Command::Increment) | Some(network::Command::IncrementQuiet) => {
+                        BinaryResponse::Increment(response)
+                    }
+                    _ => BinaryResponse::Decrement(response),
+                }
+            }
+            Some(network::Command::Version) => BinaryResponse::Version(network::VersionResponse {
+                header,
+                version: String::from_utf8_lossy(&value).into_owned(),
+            }),
+            Some(network::Command::Scan) => {
+                if extras.len() != 8 {
+                    return Err(Error::new(
+                        ErrorKind::InvalidData,
+                        "Incorrect scan response",
+                    ));
+                }
+                BinaryResponse::Scan(network::ScanResponse {
+                    header,
+                    cursor: extras.get_u64(),
+                    dump: value,
+                })
+            }
+            _ => {
+                error!("Cannot decode response opcode: {:?}", header.opcode);
+                return Err(Error::new(ErrorKind::InvalidData, "Incorrect op code"));
+            }
+        };
+        Ok(response)
+    }
+}
+
+impl Decoder for MemcacheBinaryResponseDecoder {
+    type Item = BinaryResponse;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<BinaryResponse>, io::Error> {
+        if src.len() < MemcacheBinaryResponseDecoder::HEADER_LEN {
+            return Ok(None);
+        }
+        let header = self.parse_header(&src[..MemcacheBinaryResponseDecoder::HEADER_LEN]);
+        if header.magic != network::Magic::Response as u8
+            || (header.key_length as u32 + header.extras_length as u32) > header.body_length
+        {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "Incorrect response header",
+            ));
+        }
+        let len = MemcacheBinaryResponseDecoder::HEADER_LEN + header.body_length as usize;
+        if src.len() < len {
+            src.reserve(len - src.len());
+            return Ok(None);
+        }
+        src.advance(MemcacheBinaryResponseDecoder::HEADER_LEN);
+        let mut body = src.split_to(header.body_length as usize).freeze();
+        let extras = body.split_to(header.extras_length as usize);
+        let key = body.split_to(header.key_length as usize);
+        self.parse_response(header, extras, key, body).map(Some)
+    }
+}
+
 #[cfg(test)]
 mod binary_decoder_tests;
@@ -28,7 +28,7 @@ mod tests {
     ) -> Result<Option<BinaryRequest>, io::Error> {
         let mut decoder = MemcacheBinaryDecoder::new(decoder_params.item_size_limit);
         let mut buf = BytesMut::with_capacity(src.len());
-        buf.put_slice(&src);
+        buf.put_slice(src);
         decoder.decode(&mut buf)
     }
     #[test]
@@ -461,7 +461,7 @@ mod tests {
     fn decode_if_opcode_is_greater_than_opcode_max_error_should_be_returned() {
         let set_request_packet: [u8; 39] = [
             0x80, // magic
-            0x25, // opcode
+            0x27, // opcode
             0x00, 0x03, // key length
             0x08, // extras length
             0x00, // data type
@@ -911,6 +911,39 @@ mod tests {
         decode_header_only_request(network::Command::Version);
     }
 
+    #[test]
+    fn decode_stats_request() {
+        decode_header_only_request(network::Command::Stat);
+        let mut packet = [0u8; 24];
+        packet[0] = network::Magic::Request as u8;
+        packet[1] = network::Command::Stat as u8;
+        let decode_result = decode_packet(&packet);
+        assert!(matches!(decode_result, Ok(Some(BinaryRequest::Stats(_)))));
+    }
+
+    #[test]
+    fn decode_stats_request_with_key() {
+        let stats_request_packet: [u8; 31] = [
+            0x80, // magic
+            0x10, // opcode
+            0x00, 0x07, // key len
+            0x00, // extras len
+            0x00, // data type
+            0x00, 0x00, // vbucket id
+            0x00, 0x00, 0x00, 0x07, // total body len
+            0x00, 0x00, 0x00, 0x00, // opaque
+            0x00, 0x00, 0x00, 0x00, // cas
+            0x00, 0x00, 0x00, 0x00, // cas
+            0x68, 0x6f, 0x74, 0x6b, 0x65, 0x79, 0x73, // key 'hotkeys'
+        ];
+        match decode_packet(&stats_request_packet) {
+            Ok(Some(BinaryRequest::Stats(req))) => {
+                assert_eq!(req.key, Bytes::from("hotkeys"));
+            }
+            _ => unreachable!(),
+        }
+    }
+
     fn decode_header_only_request(opcode: network::Command) {
         let noop_request_packet: [u8; 24] = [
             0x80,         // magic
@@ -1047,6 +1080,141 @@ mod tests {
             Err(_) => unreachable!(),
         }
     }
+    #[test]
+    fn decode_scan_request() {
+        let scan_request_packet: [u8; 39] = [
+            0x80, // magic
+            0x25, // opcode
+            0x00, 0x03, // key len
+            0x0c, // extras len
+            0x00, // data type
+            0x00, 0x00, // vbucket id
+            0x00, 0x00, 0x00, 0x0f, // total body len
+            0x00, 0x00, 0x00, 0x00, // opaque
+            0x00, 0x00, 0x00, 0x00, // cas
+            0x00, 0x00, 0x00, 0x00, // cas
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x02, // cursor
+            0x00, 0x00, 0x00, 0x0a, // count
+            0x66, 0x6f, 0x6f, // prefix 'foo'
+        ];
+
+        match decode_packet(&scan_request_packet) {
+            Ok(Some(BinaryRequest::Scan(req))) => {
+                assert_eq!(req.header.opcode, network::Command::Scan as u8);
+                assert_eq!(req.cursor, 258);
+                assert_eq!(req.count, 10);
+                assert_eq!(req.prefix[..], [b'f', b'o', b'o']);
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn decode_scan_request_without_extras() {
+        let scan_request_packet: [u8; 24] = [
+            0x80, // magic
+            0x25, // opcode
+            0x00, 0x00, // key len
+            0x00, // extras len
+            0x00, // data type
+            0x00, 0x00, // vbucket id
+            0x00, 0x00, 0x00, 0x00, // total body len
+            0x00, 0x00, 0x00, 0x00, // opaque
+            0x00, 0x00, 0x00, 0x00, // cas
+            0x00, 0x00, 0x00, 0x00, // cas
+        ];
+
+        match decode_packet(&scan_request_packet) {
+            Ok(Some(BinaryRequest::Scan(req))) => {
+                assert_eq!(req.cursor, 0);
+                assert_eq!(req.count, 0);
+                assert!(req.prefix.is_empty());
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn decode_tagged_set_request() {
+        let set_request_packet: [u8; 50] = [
+            0x80, // magic
+            0x01, // opcode
+            0x00, 0x03, // key length
+            0x0a, // extras length
+            0x00, // data type
+            0x00, 0x00, // vbucket id
+            0x00, 0x00, 0x00, 0x1a, // total body length
+            0x00, 0x00, 0x00, 0x00, // opaque
+            0x00, 0x00, 0x00, 0x00, // cas
+            0x00, 0x00, 0x00, 0x00, // cas
+            0xAB, 0xAD, 0xCA, 0xFE, // flags
+            0x00, 0x00, 0x00, 0x32, // expiration
+            0x00, 0x09, // tag list length
+            0x66, 0x6f, 0x6f, // key 'foo'
+            0x03, 0x74, 0x61, 0x67, // tag 'tag'
+            0x04, 0x75, 0x3a, 0x31, 0x30, // tag 'u:10'
+            0x74, 0x65, 0x73, 0x74, // value 'test'
+        ];
+        match decode_packet(&set_request_packet) {
+            Ok(Some(BinaryRequest::Set(req))) => {
+                assert_eq!(req.flags, 0xabadcafe);
+                assert_eq!(req.expiration, 0x32);
+                assert_eq!(req.key[..], [b'f', b'o', b'o']);
+                assert_eq!(req.tags, vec![Bytes::from("tag"), Bytes::from("u:10")]);
+                assert_eq!(req.value[..], [b't', b'e', b's', b't']);
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn decode_tagged_set_request_with_incorrect_tag_list() {
+        let set_request_packet: [u8; 45] = [
+            0x80, // magic
+            0x01, // opcode
+            0x00, 0x03, // key length
+            0x0a, // extras length
+            0x00, // data type
+            0x00, 0x00, // vbucket id
+            0x00, 0x00, 0x00, 0x15, // total body length
+            0x00, 0x00, 0x00, 0x00, // opaque
+            0x00, 0x00, 0x00, 0x00, // cas
+            0x00, 0x00, 0x00, 0x00, // cas
+            0x00, 0x00, 0x00, 0x00, // flags
+            0x00, 0x00, 0x00, 0x00, // expiration
+            0x00, 0x04, // tag list length
+            0x66, 0x6f, 0x6f, // key 'foo'
+            0x05, 0x74, 0x61, 0x67, // tag longer than the list
+            0x74, 0x65, 0x73, 0x74, // value 'test'
+        ];
+        assert!(decode_packet(&set_request_packet).is_err());
+    }
+
+    #[test]
+    fn decode_invalidate_tag_request() {
+        let invalidate_request_packet: [u8; 27] = [
+            0x80, // magic
+            0x26, // opcode
+            0x00, 0x03, // key len
+            0x00, // extras len
+            0x00, // data type
+            0x00, 0x00, // vbucket id
+            0x00, 0x00, 0x00, 0x03, // total body len
+            0x00, 0x00, 0x00, 0x00, // opaque
+            0x00, 0x00, 0x00, 0x00, // cas
+            0x00, 0x00, 0x00, 0x00, // cas
+            0x74, 0x61, 0x67, // tag 'tag'
+        ];
+
+        match decode_packet(&invalidate_request_packet) {
+            Ok(Some(BinaryRequest::InvalidateTag(req))) => {
+                assert_eq!(req.header.opcode, network::Command::InvalidateTag as u8);
+                assert_eq!(req.key[..], [b't', b'a', b'g']);
+            }
+            _ => unreachable!(),
+        }
+    }
+
     #[test]
     fn decode_fuzz_crash1_request() {
         let crash_request_packet: [u8; 29] = [
@@ -1174,4 +1342,142 @@ mod tests {
             );
         }
     }
+
+    fn encode_request(request: &BinaryRequest, opaque: u32) -> BytesMut {
+        let encoder = crate::protocol::binary::encoder::MemcacheBinaryEncoder::new();
+        let mut dst = BytesMut::new();
+        encoder.encode_request(request, opaque, &mut dst);
+        dst
+    }
+
+    #[test]
+    fn encoded_requests_should_decode_to_the_same_request() {
+        let header = network::RequestHeader {
+            magic: network::Magic::Request as u8,
+            opcode: network::Command::SetQuiet as u8,
+            key_length: 3,
+            extras_length: 10,
+            body_length: 24,
+            opaque: 7,
+            cas: 0x1234,
+            ..Default::default()
+        };
+        let request = BinaryRequest::SetQuietly(network::SetRequest {
+            header,
+            flags: 0xDEAD_BEEF,
+            expiration: 60,
+            key: Bytes::from("key"),
+            value: Bytes::from("value"),
+            tags: vec![Bytes::from("a"), Bytes::from("tag")],
+        });
+        let mut src = encode_request(&request, 42);
+        let mut decoder = MemcacheBinaryDecoder::new(ITEM_SIZE_LIMIT);
+        match decoder.decode(&mut src) {
+            Ok(Some(BinaryRequest::SetQuietly(decoded))) => {
+                assert_eq!(decoded.header.opaque, 42);
+                assert_eq!(decoded.header.cas, 0x1234);
+                assert_eq!(decoded.header.body_length, 24);
+                assert_eq!(decoded.flags, 0xDEAD_BEEF);
+                assert_eq!(decoded.expiration, 60);
+                assert_eq!(decoded.key, Bytes::from("key"));
+                assert_eq!(decoded.value, Bytes::from("value"));
+                assert_eq!(decoded.tags, vec![Bytes::from("a"), Bytes::from("tag")]);
+            }
+            other => panic!("Expected SetQuietly, got {:?}", other),
+        }
+        assert!(src.is_empty());
+
+        let header = network::RequestHeader {
+            opcode: network::Command::Decrement as u8,
+            ..Default::default()
+        };
+        let request = BinaryRequest::Decrement(network::IncrementRequest {
+            header,
+            delta: 5,
+            initial: 10,
+            expiration: 0,
+            key: Bytes::from("counter"),
+        });
+        let mut src = encode_request(&request, 1);
+        match decoder.decode(&mut src) {
+            Ok(Some(BinaryRequest::Decrement(decoded))) => {
+                assert_eq!(decoded.delta, 5);
+                assert_eq!(decoded.initial, 10);
+                assert_eq!(decoded.key, Bytes::from("counter"));
+            }
+            other => panic!("Expected Decrement, got {:?}", other),
+        }
+    }
+
+    fn decode_response(src: &[u8]) -> Result<Option<BinaryResponse>, io::Error> {
+        let mut decoder = MemcacheBinaryResponseDecoder::new();
+        let mut buf = BytesMut::from(src);
+        decoder.decode(&mut buf)
+    }
+
+    #[test]
+    fn decode_get_key_response() {
+        let encoder = crate::protocol::binary::encoder::MemcacheBinaryEncoder::new();
+        let mut header = network::ResponseHeader::new(network::Command::GetKey as u8, 9);
+        header.key_length = 3;
+        header.extras_length = 4;
+        header.body_length = 12;
+        header.cas = 77;
+        let response = BinaryResponse::GetKey(network::GetKeyResponse {
+            header,
+            flags: 0xAB,
+            key: Bytes::from("key"),
+            value: Bytes::from("value"),
+        });
+        let data = encoder.encode_message(&response).data;
+
+        // not decoded until the whole body is received
+        assert!(decode_response(&data[..data.len() - 1]).unwrap().is_none());
+        match decode_response(&data) {
+            Ok(Some(BinaryResponse::GetKey(decoded))) => {
+                assert_eq!(decoded.header.opaque, 9);
+                assert_eq!(decoded.header.cas, 77);
+                assert_eq!(decoded.flags, 0xAB);
+                assert_eq!(decoded.key, Bytes::from("key"));
+                assert_eq!(decoded.value, Bytes::from("value"));
+            }
+            other => panic!("Expected GetKey, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn decode_error_and_increment_responses() {
+        let encoder = crate::protocol::binary::encoder::MemcacheBinaryEncoder::new();
+        let mut header = network::ResponseHeader::new(network::Command::GetQuiet as u8, 3);
+        let error = crate::protocol::binary::encoder::storage_error_to_response(
+            CacheError::NotFound,
+            &mut header,
+        );
+        let mut header = network::ResponseHeader::new(network::Command::Increment as u8, 4);
+        header.body_length = 8;
+        let increment = BinaryResponse::Increment(network::IncrementResponse { header, value: 11 });
+        let mut src = BytesMut::new();
+        src.put_slice(&encoder.encode_message(&error).data);
+        src.put_slice(&encoder.encode_message(&increment).data);
+
+        let mut decoder = MemcacheBinaryResponseDecoder::new();
+        match decoder.decode(&mut src) {
+            Ok(Some(BinaryResponse::Error(decoded))) => {
+                assert_eq!(decoded.header.status, CacheError::NotFound as u16);
+                assert_eq!(decoded.header.opaque, 3);
+                assert_eq!(decoded.error, "Not found");
+            }
+            other => panic!("Expected Error, got {:?}", other),
+        }
+        match decoder.decode(&mut src) {
+            Ok(Some(BinaryResponse::Increment(decoded))) => {
+                assert_eq!(decoded.header.opaque, 4);
+                assert_eq!(decoded.value, 11);
+            }
+            other => panic!("Expected Increment, got {:?}", other),
+        }
+        assert!(src.is_empty());
+        // requests are not responses
+        assert!(decode_response(&[0x80; 24]).is_err());
+    }
 }
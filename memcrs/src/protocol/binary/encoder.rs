@@ -1,4 +1,5 @@
 use crate::cache::error::CacheError;
+use crate::protocol::binary::decoder::BinaryRequest;
 use crate::protocol::binary::network;
 use bytes::{BufMut, Bytes, BytesMut};
 
@@ -23,6 +24,8 @@ pub enum BinaryResponse {
     Decrement(network::DecrementResponse),
     Quit(network::QuitResponse),
     Stats(network::StatsResponse),
+    Scan(network::ScanResponse),
+    InvalidateTag(network::InvalidateTagResponse),
 }
 
 impl BinaryResponse {
@@ -46,6 +49,33 @@ impl BinaryResponse {
             BinaryResponse::Decrement(response) => &response.header,
             BinaryResponse::Quit(response) => &response.header,
             BinaryResponse::Stats(response) => &response.header,
+            BinaryResponse::Scan(response) => &response.header,
+            BinaryResponse::InvalidateTag(response) => &response.header,
+        }
+    }
+
+    pub fn get_header_mut(&'_ mut self) -> &'_ mut network::ResponseHeader {
+        match self {
+            BinaryResponse::Error(response) => &mut response.header,
+            BinaryResponse::Get(response) => &mut response.header,
+            BinaryResponse::GetKey(response) => &mut response.header,
+            BinaryResponse::GetKeyQuietly(response) => &mut response.header,
+            BinaryResponse::GetQuietly(response) => &mut response.header,
+            BinaryResponse::Set(response) => &mut response.header,
+            BinaryResponse::Replace(response) => &mut response.header,
+            BinaryResponse::Add(response) => &mut response.header,
+            BinaryResponse::Append(response) => &mut response.header,
+            BinaryResponse::Prepend(response) => &mut response.header,
+            BinaryResponse::Version(response) => &mut response.header,
+            BinaryResponse::Noop(response) => &mut response.header,
+            BinaryResponse::Delete(response) => &mut response.header,
+            BinaryResponse::Flush(response) => &mut response.header,
+            BinaryResponse::Increment(response) => &mut response.header,
+            BinaryResponse::Decrement(response) => &mut response.header,
+            BinaryResponse::Quit(response) => &mut response.header,
+            BinaryResponse::Stats(response) => &mut response.header,
+            BinaryResponse::Scan(response) => &mut response.header,
+            BinaryResponse::InvalidateTag(response) => &mut response.header,
         }
     }
 }
@@ -77,6 +107,7 @@ impl Default for MemcacheBinaryEncoder {
 
 impl MemcacheBinaryEncoder {
     const RESPONSE_HEADER_LEN: usize = 24;
+    const REQUEST_HEADER_LEN: usize = 24;
 
     pub fn new() -> MemcacheBinaryEncoder {
         MemcacheBinaryEncoder {}
@@ -104,6 +135,9 @@ impl MemcacheBinaryEncoder {
     // written into socket directly.
     //
     pub fn encode_message(&self, msg: &BinaryResponse) -> ResponseMessage {
+        if let BinaryResponse::Stats(response) = msg {
+            return self.encode_stats(response);
+        }
         let len = self.get_length(msg);
         let mut dst = BytesMut::with_capacity(len);
         self.write_header_impl(self.get_header(msg), &mut dst);
@@ -137,14 +171,162 @@ impl MemcacheBinaryEncoder {
             BinaryResponse::Delete(_response) => {}
             BinaryResponse::Flush(_response) => {}
             BinaryResponse::Quit(_response) => {}
-            BinaryResponse::Stats(_response) => {}
+            BinaryResponse::InvalidateTag(_response) => {}
+            BinaryResponse::Stats(_response) => unreachable!("stats are encoded separately"),
             BinaryResponse::Increment(response) | BinaryResponse::Decrement(response) => {
                 dst.put_u64(response.value);
             }
+            BinaryResponse::Scan(response) => {
+                dst.put_u64(response.cursor);
+                dst.put(response.dump.clone());
+            }
+        }
+        ResponseMessage { data: dst.freeze() }
+    }
+
+    /// One packet for every stat, terminated with a packet without a key
+    fn encode_stats(&self, response: &network::StatsResponse) -> ResponseMessage {
+        let len = response
+            .records
+            .iter()
+            .map(|(name, value)| {
+                MemcacheBinaryEncoder::RESPONSE_HEADER_LEN + name.len() + value.len()
+            })
+            .sum::<usize>()
+            + self.get_len_from_header(&response.header);
+        let mut dst = BytesMut::with_capacity(len);
+        for (name, value) in &response.records {
+            let header = network::ResponseHeader {
+                key_length: name.len() as u16,
+                body_length: (name.len() + value.len()) as u32,
+                ..response.header
+            };
+            self.write_header_impl(&header, &mut dst);
+            dst.put_slice(name.as_bytes());
+            dst.put_slice(value.as_bytes());
         }
+        self.write_header_impl(&response.header, &mut dst);
         ResponseMessage { data: dst.freeze() }
     }
 
+    /// Encodes a request for another server, used by the proxy to talk to
+    /// backends. `opaque` replaces the one sent by the client so responses
+    /// of a pipeline can be matched with their requests.
+    pub fn encode_request(&self, request: &BinaryRequest, opaque: u32, dst: &mut BytesMut) {
+        match request {
+            BinaryRequest::Get(request)
+            | BinaryRequest::GetQuietly(request)
+            | BinaryRequest::GetKey(request)
+            | BinaryRequest::GetKeyQuietly(request)
+            | BinaryRequest::Delete(request)
+            | BinaryRequest::DeleteQuiet(request)
+            | BinaryRequest::Stats(request)
+            | BinaryRequest::InvalidateTag(request) => {
+                self.write_request_impl(&request.header, &[], &request.key, &[], opaque, dst)
+            }
+            BinaryRequest::Set(request)
+            | BinaryRequest::SetQuietly(request)
+            | BinaryRequest::Add(request)
+            | BinaryRequest::AddQuietly(request)
+            | BinaryRequest::Replace(request)
+            | BinaryRequest::ReplaceQuietly(request) => {
+                let mut extras = BytesMut::with_capacity(10);
+                extras.put_u32(request.flags);
+                extras.put_u32(request.expiration);
+                let mut tags = BytesMut::new();
+                for tag in &request.tags {
+                    tags.put_u8(tag.len() as u8);
+                    tags.put_slice(tag);
+                }
+                if !request.tags.is_empty() {
+                    extras.put_u16(tags.len() as u16);
+                }
+                self.write_request_impl(
+                    &request.header,
+                    &extras,
+                    &request.key,
+                    &[&tags, &request.value],
+                    opaque,
+                    dst,
+                )
+            }
+            BinaryRequest::Append(request)
+            | BinaryRequest::AppendQuietly(request)
+            | BinaryRequest::Prepend(request)
+            | BinaryRequest::PrependQuietly(request) => self.write_request_impl(
+                &request.header,
+                &[],
+                &request.key,
+                &[&request.value],
+                opaque,
+                dst,
+            ),
+            BinaryRequest::Increment(request)
+            | BinaryRequest::IncrementQuiet(request)
+            | BinaryRequest::Decrement(request)
+            | BinaryRequest::DecrementQuiet(request) => {
+                let mut extras = BytesMut::with_capacity(20);
+                extras.put_u64(request.delta);
+                extras.put_u64(request.initial);
+                extras.put_u32(request.expiration);
+                self.write_request_impl(&request.header, &extras, &request.key, &[], opaque, dst)
+            }
+            BinaryRequest::Flush(request) | BinaryRequest::FlushQuietly(request) => self
+                .write_request_impl(
+                    &request.header,
+                    &request.expiration.to_be_bytes(),
+                    &[],
+                    &[],
+                    opaque,
+                    dst,
+                ),
+            BinaryRequest::Scan(request) => {
+                let mut extras = BytesMut::with_capacity(12);
+                extras.put_u64(request.cursor);
+                extras.put_u32(request.count);
+                self.write_request_impl(&request.header, &extras, &request.prefix, &[], opaque, dst)
+            }
+            BinaryRequest::Noop(request)
+            | BinaryRequest::Version(request)
+            | BinaryRequest::Quit(request)
+            | BinaryRequest::QuitQuietly(request)
+            | BinaryRequest::UnkownCommand(request) => {
+                self.write_request_impl(&request.header, &[], &[], &[], opaque, dst)
+            }
+            BinaryRequest::ItemTooLarge(_request) => {
+                unreachable!("requests over the item size limit carry no value")
+            }
+        }
+    }
+
+    fn write_request_impl(
+        &self,
+        header: &network::RequestHeader,
+        extras: &[u8],
+        key: &[u8],
+        value: &[&[u8]],
+        opaque: u32,
+        dst: &mut BytesMut,
+    ) {
+        let body_length =
+            extras.len() + key.len() + value.iter().map(|part| part.len()).sum::<usize>();
+        dst.reserve(MemcacheBinaryEncoder::REQUEST_HEADER_LEN + body_length);
+        dst.put_u8(network::Magic::Request as u8);
+        dst.put_u8(header.opcode);
+        dst.put_u16(key.len() as u16);
+        dst.put_u8(extras.len() as u8);
+        dst.put_u8(network::DataTypes::RawBytes as u8);
+        dst.put_u16(header.vbucket_id);
+        dst.put_u32(body_length as u32);
+        dst.put_u32(opaque);
+        dst.put_u64(header.cas);
+        dst.put_slice(extras);
+        dst.put_slice(key);
+        for part in value {
+            dst.put_slice(part);
+        }
+    }
+
     fn write_header_impl(&self, header: &network::ResponseHeader, dst: &mut BytesMut) {
         dst.put_u8(header.magic);
         dst.put_u8(header.opcode);
@@ -39,6 +39,22 @@ mod tests {
         test_encode(&expected_result, response);
     }
 
+    #[test]
+    fn encode_stats_response() {
+        let header = create_response_header(network::Command::Stat, 7, 0);
+        let response = BinaryResponse::Stats(network::StatsResponse {
+            header,
+            records: vec![("ab".to_string(), "1".to_string())],
+        });
+        let expected_result: [u8; 51] = [
+            0x81, 0x10, 0x00, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03, 0x00, 0x00,
+            0x00, 0x07, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, b'a', b'b', b'1', 0x81,
+            0x10, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x07, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ];
+        test_encode(&expected_result, response);
+    }
+
     #[test]
     fn encode_replace_response() {
         let header = create_response_header(network::Command::Replace, 0, 4);
@@ -182,6 +198,25 @@ mod tests {
         test_encode(&expected_result, response);
     }
 
+    #[test]
+    fn encode_scan_response() {
+        let expected_result: [u8; 34] = [
+            0x81, 0x25, 0x00, 0x00, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x0a, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // cas: 0
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x02, // cursor: 258
+            b'k', b'\n', // dump
+        ];
+        let mut header = create_response_header(network::Command::Scan, 0, 0);
+        header.extras_length = 8;
+        header.body_length = 10;
+        let response = BinaryResponse::Scan(network::ScanResponse {
+            header,
+            cursor: 258,
+            dump: from_string("k\n"),
+        });
+        test_encode(&expected_result, response);
+    }
+
     #[test]
     fn encode_decrement_response() {
         let expected_result: [u8; 32] = [
@@ -72,7 +72,12 @@ pub enum Command {
     GetAndTouchKey = 0x23,
     GetAndTouchKeyQuiet = 0x24,
 
-    OpCodeMax = 0x25,
+    /// memcrs extension, cursor based key scan
+    Scan = 0x25,
+    /// memcrs extension, expires every item stored with a tag
+    InvalidateTag = 0x26,
+
+    OpCodeMax = 0x27,
 }
 
 #[derive(Serialize, Deserialize, Debug, Copy, Clone, Default, PartialEq)]
@@ -141,7 +146,7 @@ pub struct ErrorResponse {
     pub error: &'static str,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct GetRequest {
     pub(crate) header: RequestHeader,
     pub(crate) key: Bytes,
@@ -166,6 +171,9 @@ pub type GetQuietlyResponse = GetResponse;
 pub type GetKeyResponse = GetResponse;
 pub type GetKeyQuietlyResponse = GetResponse;
 
+/// Set, add and replace request, memcrs extension: with 10 bytes of
+/// extras the last two are length of a tag list the value starts with,
+/// every tag in the list is prefixed with its u8 length
 #[derive(Clone, Debug)]
 pub struct SetRequest {
     pub(crate) header: RequestHeader,
@@ -173,6 +181,7 @@ pub struct SetRequest {
     pub(crate) expiration: u32,
     pub(crate) key: Bytes,
     pub(crate) value: Bytes,
+    pub(crate) tags: Vec<Bytes>,
 }
 
 pub type AddRequest = SetRequest;
@@ -227,21 +236,39 @@ pub type FlushResponse = Response;
 pub type QuitRequest = Request;
 pub type QuitResponse = Response;
 
-pub type StatsRequest = Request;
+/// Key selects a group of stats, general stats are sent without a key
+pub type StatsRequest = GetRequest;
 #[derive(Debug)]
 pub struct StatsResponse {
     pub(crate) header: ResponseHeader,
+    /// name and value of every stat, each one is sent in its own packet
+    /// followed by a packet with an empty key
+    pub(crate) records: Vec<(String, String)>,
+}
+
+/// Scan request, extras are optional: cursor u64 and count u32,
+/// key is a prefix keys have to start with
+#[derive(Debug)]
+pub struct ScanRequest {
+    pub(crate) header: RequestHeader,
+    pub(crate) cursor: u64,
+    pub(crate) count: u32,
+    pub(crate) prefix: Bytes,
+}
+
+/// Scan response, extras are the cursor of the next page (0 when the scan
+/// is done), value is one lru_crawler metadump line for every item
+#[derive(Debug)]
+pub struct ScanResponse {
+    pub(crate) header: ResponseHeader,
+    pub(crate) cursor: u64,
+    pub(crate) dump: Bytes,
 }
 
+/// Invalidate tag request, key is the tag
+pub type InvalidateTagRequest = GetRequest;
+pub type InvalidateTagResponse = Response;
+
 pub const DELTA_NO_INITIAL_VALUE: u32 = 0xffffffff;
-// pub struct StatsResponseRecord {
-//     pub(crate) header: ResponseHeader,
-//     pub(crate) key: Vec<u8>,
-//     pub(crate) value: Bytes,
-// }
-// #[derive(Debug)]
-// pub struct StatsResponse {
-//     pub(crate) records: Vec<StatsResponseRecord>,
-// }
 
 /* TODO Get And Touch (GAT) */
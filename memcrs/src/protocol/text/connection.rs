@@ -0,0 +1,55 @@
+use super::decoder::{MemcacheTextDecoder, TextCommand};
+use bytes::BytesMut;
+use std::io::{self, Error, ErrorKind};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio_util::codec::Decoder;
+
+/// Connection of a text protocol client
+pub struct MemcacheTextConnection {
+    stream: TcpStream,
+    decoder: MemcacheTextDecoder,
+    buffer: BytesMut,
+}
+
+impl MemcacheTextConnection {
+    pub fn new(socket: TcpStream, item_size_limit: u32) -> Self {
+        MemcacheTextConnection {
+            stream: socket,
+            decoder: MemcacheTextDecoder::new(item_size_limit),
+            buffer: BytesMut::with_capacity(item_size_limit as usize),
+        }
+    }
+
+    pub async fn read_frame(&mut self) -> Result<Option<TextCommand>, io::Error> {
+        loop {
+            if let Some(command) = self.decoder.decode(&mut self.buffer)? {
+                return Ok(Some(command));
+            }
+            if 0 == self.stream.read_buf(&mut self.buffer).await? {
+                if self.buffer.is_empty() {
+                    return Ok(None);
+                } else {
+                    return Err(Error::new(
+                        ErrorKind::ConnectionReset,
+                        "Buffer not empty but connection closed by peer",
+                    ));
+                }
+            }
+        }
+    }
+
+    pub async fn write(&mut self, data: &[u8]) -> io::Result<()> {
+        self.stream.write_all(data).await
+    }
+
+    /// Returns true if received data is waiting to be decoded,
+    /// read_frame will not wait for the socket then
+    pub fn has_buffered_data(&self) -> bool {
+        !self.buffer.is_empty()
+    }
+
+    pub async fn shutdown(&mut self) -> io::Result<()> {
+        self.stream.shutdown().await
+    }
+}
@@ -0,0 +1,421 @@
+use crate::protocol::binary::decoder::BinaryRequest;
+use crate::protocol::binary::network;
+use bytes::{Buf, Bytes, BytesMut};
+use std::io::{self, Error, ErrorKind};
+use std::str::FromStr;
+use tokio_util::codec::Decoder;
+
+/// Longest command line, a get line lists all of its keys
+const MAX_LINE: usize = 64 * 1024;
+/// Longest key of the text protocol
+const MAX_KEY: usize = 250;
+
+const BAD_FORMAT: &str = "CLIENT_ERROR bad command line format";
+
+/// Text protocol command, keyed commands are translated to the binary
+/// requests they are forwarded as
+#[derive(Debug)]
+pub enum TextCommand {
+    /// get and gets, `cas` is set for gets
+    Get {
+        keys: Vec<Bytes>,
+        cas: bool,
+    },
+    /// Storage commands, delete, incr, decr and flush_all, requests with
+    /// noreply are sent as their quiet variant
+    Request {
+        request: BinaryRequest,
+        noreply: bool,
+    },
+    Version,
+    Stats,
+    /// `lru_crawler metadump all [prefix]`, items with keys starting with
+    /// the prefix, a memcrsd extension of the command
+    Metadump {
+        prefix: Bytes,
+    },
+    Quit,
+    /// Value over the item size limit, it is dropped
+    TooLarge {
+        noreply: bool,
+    },
+    /// Command which can't be parsed, the reply tells why
+    ClientError(&'static str),
+    Unknown,
+}
+
+/// Decodes text protocol commands of text clients
+pub struct MemcacheTextDecoder {
+    item_size_limit: u32,
+    /// Bytes of a value over the item size limit still to be dropped
+    skip: usize,
+}
+
+impl MemcacheTextDecoder {
+    pub fn new(item_size_limit: u32) -> MemcacheTextDecoder {
+        MemcacheTextDecoder {
+            item_size_limit,
+            skip: 0,
+        }
+    }
+
+    fn parse(&mut self, line: Bytes, src: &mut BytesMut) -> TextCommand {
+        let tokens = tokens(&line);
+        let (name, args) = match tokens.split_first() {
+            Some((name, args)) => (*name, args),
+            None => return TextCommand::Unknown,
+        };
+        match name {
+            b"get" | b"gets" if !args.is_empty() => {
+                if args.iter().any(|key| key.len() > MAX_KEY) {
+                    return TextCommand::ClientError(BAD_FORMAT);
+                }
+                TextCommand::Get {
+                    keys: args.iter().map(|key| line.slice_ref(key)).collect(),
+                    cas: name == b"gets",
+                }
+            }
+            b"set" | b"add" | b"replace" | b"append" | b"prepend" | b"cas" => {
+                self.parse_storage(name, args, &line, src)
+            }
+            b"delete" => {
+                let (args, noreply) = noreply(args);
+                match args {
+                    [key] if key.len() <= MAX_KEY => {
+                        let opcode = match noreply {
+                            true => network::Command::DeleteQuiet,
+                            false => network::Command::Delete,
+                        };
+                        let request = network::DeleteRequest {
+                            header: header(opcode, 0),
+                            key: line.slice_ref(key),
+                        };
+                        TextCommand::Request {
+                            request: match noreply {
+                                true => BinaryRequest::DeleteQuiet(request),
+                                false => BinaryRequest::Delete(request),
+                            },
+                            noreply,
+                        }
+                    }
+                    _ => TextCommand::ClientError(
+                        "CLIENT_ERROR bad command line format.  Usage: delete <key> [noreply]",
+                    ),
+                }
+            }
+            b"incr" | b"decr" => {
+                let (args, noreply) = noreply(args);
+                let (key, delta) = match args {
+                    [key, delta] if key.len() <= MAX_KEY => (key, delta),
+                    _ => return TextCommand::ClientError(BAD_FORMAT),
+                };
+                let delta = match number(delta) {
+                    Some(delta) => delta,
+                    None => {
+                        return TextCommand::ClientError(
+                            "CLIENT_ERROR invalid numeric delta argument",
+                        )
+                    }
+                };
+                let opcode = match (name, noreply) {
+                    (b"incr", false) => network::Command::Increment,
+                    (b"incr", true) => network::Command::IncrementQuiet,
+                    (_, false) => network::Command::Decrement,
+                    (_, true) => network::Command::DecrementQuiet,
+                };
+                // the text protocol does not create missing counters
+                let request = network::IncrementRequest {
+                    header: header(opcode, 0),
+                    delta,
+                    initial: 0,
+                    expiration: network::DELTA_NO_INITIAL_VALUE,
+                    key: line.slice_ref(key),
+                };
+                TextCommand::Request {
+                    request: match opcode {
+                        network::Command::Increment => BinaryRequest::Increment(request),
+                        network::Command::IncrementQuiet => BinaryRequest::IncrementQuiet(request),
+                        network::Command::Decrement => BinaryRequest::Decrement(request),
+                        _ => BinaryRequest::DecrementQuiet(request),
+                    },
+                    noreply,
+                }
+            }
+            b"flush_all" => {
+                let (args, noreply) = noreply(args);
+                let expiration = match args {
+                    [] => 0,
+                    [delay] => match number(delay) {
+                        Some(delay) => delay,
+                        None => return TextCommand::ClientError(BAD_FORMAT),
+                    },
+                    _ => return TextCommand::ClientError(BAD_FORMAT),
+                };
+                let request = network::FlushRequest {
+                    header: header(network::Command::Flush, 0),
+                    expiration,
+                };
+                TextCommand::Request {
+                    request: BinaryRequest::Flush(request),
+                    noreply,
+                }
+            }
+            b"version" if args.is_empty() => TextCommand::Version,
+            b"stats" if args.is_empty() => TextCommand::Stats,
+            b"lru_crawler" => match args {
+                [b"metadump", b"all"] => TextCommand::Metadump {
+                    prefix: Bytes::new(),
+                },
+                [b"metadump", b"all", prefix] => TextCommand::Metadump {
+                    prefix: line.slice_ref(prefix),
+                },
+                _ => TextCommand::ClientError(BAD_FORMAT),
+            },
+            b"quit" => TextCommand::Quit,
+            _ => TextCommand::Unknown,
+        }
+    }
+
+    /// Parses `<command> <key> <flags> <exptime> <bytes> [<cas unique>]
+    /// [noreply]` and takes the value following it from `src`
+    fn parse_storage(
+        &mut self,
+        name: &[u8],
+        args: &[&[u8]],
+        line: &Bytes,
+        src: &mut BytesMut,
+    ) -> TextCommand {
+        let (args, noreply) = noreply(args);
+        let length = match args.get(3).and_then(|length| number::<usize>(length)) {
+            Some(length) => length,
+            None => return TextCommand::ClientError(BAD_FORMAT),
+        };
+        if length > self.item_size_limit as usize {
+            self.skip = length + 2;
+            return TextCommand::TooLarge { noreply };
+        }
+        let data = src.split_to(length + 2).freeze();
+        if &data[length..] != b"\r\n" {
+            return TextCommand::ClientError("CLIENT_ERROR bad data chunk");
+        }
+        let value = data.slice(..length);
+
+        let (key, flags, expiration, cas) = match (name, args) {
+            (b"cas", [key, flags, expiration, _, cas]) => (key, flags, expiration, number(cas)),
+            (b"cas", _) => return TextCommand::ClientError(BAD_FORMAT),
+            (_, [key, flags, expiration, _]) => (key, flags, expiration, Some(0)),
+            _ => return TextCommand::ClientError(BAD_FORMAT),
+        };
+        let (flags, expiration, cas) = match (number(flags), number(expiration), cas) {
+            (Some(flags), Some(expiration), Some(cas)) if key.len() <= MAX_KEY => {
+                (flags, expiration, cas)
+            }
+            _ => return TextCommand::ClientError(BAD_FORMAT),
+        };
+        let key = line.slice_ref(key);
+
+        let request = match name {
+            b"append" | b"prepend" => {
+                let (opcode, quiet_opcode) = match name {
+                    b"append" => (network::Command::Append, network::Command::AppendQuiet),
+                    _ => (network::Command::Prepend, network::Command::PrependQuiet),
+                };
+                let request = network::AppendRequest {
+                    header: header(if noreply { quiet_opcode } else { opcode }, 0),
+                    key,
+                    value,
+                };
+                match (name, noreply) {
+                    (b"append", false) => BinaryRequest::Append(request),
+                    (b"append", true) => BinaryRequest::AppendQuietly(request),
+                    (_, false) => BinaryRequest::Prepend(request),
+                    (_, true) => BinaryRequest::PrependQuietly(request),
+                }
+            }
+            _ => {
+                let (opcode, quiet_opcode) = match name {
+                    b"add" => (network::Command::Add, network::Command::AddQuiet),
+                    b"replace" => (network::Command::Replace, network::Command::ReplaceQuiet),
+                    _ => (network::Command::Set, network::Command::SetQuiet),
+                };
+                let request = network::SetRequest {
+                    header: header(if noreply { quiet_opcode } else { opcode }, cas),
+                    flags,
+                    expiration,
+                    key,
+                    value,
+                    tags: Vec::new(),
+                };
+                match (name, noreply) {
+                    (b"add", false) => BinaryRequest::Add(request),
+                    (b"add", true) => BinaryRequest::AddQuietly(request),
+                    (b"replace", false) => BinaryRequest::Replace(request),
+                    (b"replace", true) => BinaryRequest::ReplaceQuietly(request),
+                    (_, false) => BinaryRequest::Set(request),
+                    (_, true) => BinaryRequest::SetQuietly(request),
+                }
+            }
+        };
+        TextCommand::Request { request, noreply }
+    }
+}
+
+impl Decoder for MemcacheTextDecoder {
+    type Item = TextCommand;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<TextCommand>, io::Error> {
+        if self.skip > 0 {
+            let skipped = self.skip.min(src.len());
+            src.advance(skipped);
+            self.skip -= skipped;
+            if self.skip > 0 {
+                return Ok(None);
+            }
+        }
+        let line_length = match src.iter().position(|byte| *byte == b'\n') {
+            Some(position) => position + 1,
+            None if src.len() > MAX_LINE => {
+                return Err(Error::new(ErrorKind::InvalidData, "Command line too long"));
+            }
+            None => return Ok(None),
+        };
+        // a storage command is parsed once its value is received
+        if let Some(length) = value_length(&src[..line_length]) {
+            if length <= self.item_size_limit as usize && src.len() < line_length + length + 2 {
+                return Ok(None);
+            }
+        }
+        let line = src.split_to(line_length).freeze();
+        Ok(Some(self.parse(line, src)))
+    }
+}
+
+/// Words of a command line
+fn tokens(line: &[u8]) -> Vec<&[u8]> {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    line.split(|byte| *byte == b' ')
+        .filter(|token| !token.is_empty())
+        .collect()
+}
+
+/// Arguments without a trailing noreply, and whether it was there
+fn noreply<'a, 'b>(args: &'a [&'b [u8]]) -> (&'a [&'b [u8]], bool) {
+    match args.split_last() {
+        Some((last, args)) if *last == b"noreply" => (args, true),
+        _ => (args, false),
+    }
+}
+
+fn number<T: FromStr>(token: &[u8]) -> Option<T> {
+    std::str::from_utf8(token).ok()?.parse().ok()
+}
+
+/// Length of the value following a storage command line
+fn value_length(line: &[u8]) -> Option<usize> {
+    match tokens(line).as_slice() {
+        [b"set" | b"add" | b"replace" | b"append" | b"prepend" | b"cas", _, _, _, length, ..] => {
+            number(length)
+        }
+        _ => None,
+    }
+}
+
+pub(crate) fn header(opcode: network::Command, cas: u64) -> network::RequestHeader {
+    network::RequestHeader {
+        magic: network::Magic::Request as u8,
+        opcode: opcode as u8,
+        cas,
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(data: &[u8]) -> (Vec<TextCommand>, BytesMut) {
+        let mut decoder = MemcacheTextDecoder::new(16);
+        let mut src = BytesMut::from(data);
+        let mut commands = Vec::new();
+        while let Some(command) = decoder.decode(&mut src).unwrap() {
+            commands.push(command);
+        }
+        (commands, src)
+    }
+
+    #[test]
+    fn test_storage_command_waits_for_its_value() {
+        let (commands, src) = decode(b"set key 5 0 5\r\nval");
+        assert!(commands.is_empty());
+        assert_eq!(&src[..], b"set key 5 0 5\r\nval");
+
+        let (commands, src) = decode(b"set key 5 100 5 noreply\r\nvalue\r\nget a b\r\n");
+        assert!(src.is_empty());
+        match &commands[..] {
+            [TextCommand::Request {
+                request: BinaryRequest::SetQuietly(request),
+                noreply: true,
+            }, TextCommand::Get { keys, cas: false }] => {
+                assert_eq!(request.key, Bytes::from("key"));
+                assert_eq!(request.value, Bytes::from("value"));
+                assert_eq!((request.flags, request.expiration), (5, 100));
+                assert_eq!(keys, &vec![Bytes::from("a"), Bytes::from("b")]);
+            }
+            commands => panic!("Unexpected commands {:?}", commands),
+        }
+    }
+
+    #[test]
+    fn test_cas_is_sent_with_set() {
+        let (commands, _src) = decode(b"cas key 0 0 1 42\r\nv\r\n");
+        match &commands[..] {
+            [TextCommand::Request {
+                request: BinaryRequest::Set(request),
+                noreply: false,
+            }] => assert_eq!(request.header.cas, 42),
+            commands => panic!("Unexpected commands {:?}", commands),
+        }
+    }
+
+    #[test]
+    fn test_value_over_limit_is_skipped() {
+        let (commands, src) = decode(b"set key 0 0 20\r\n01234567890123456789\r\nversion\r\n");
+        assert!(src.is_empty());
+        assert!(matches!(
+            &commands[..],
+            [
+                TextCommand::TooLarge { noreply: false },
+                TextCommand::Version
+            ]
+        ));
+    }
+
+    #[test]
+    fn test_malformed_commands_are_reported() {
+        let (commands, _src) = decode(b"set key 0 0 1\r\nvxyincr key x\r\nbogus\r\n");
+        assert!(matches!(
+            &commands[..],
+            [
+                TextCommand::ClientError("CLIENT_ERROR bad data chunk"),
+                TextCommand::ClientError("CLIENT_ERROR invalid numeric delta argument"),
+                TextCommand::Unknown
+            ]
+        ));
+    }
+
+    #[test]
+    fn test_metadump_takes_an_optional_prefix() {
+        let (commands, _src) =
+            decode(b"lru_crawler metadump all\r\nlru_crawler metadump all user:\r\nlru_crawler metadump 1\r\n");
+        match &commands[..] {
+            [TextCommand::Metadump { prefix: all }, TextCommand::Metadump { prefix }, TextCommand::ClientError(BAD_FORMAT)] =>
+            {
+                assert!(all.is_empty());
+                assert_eq!(prefix, &Bytes::from("user:"));
+            }
+            commands => panic!("Unexpected commands {:?}", commands),
+        }
+    }
+}
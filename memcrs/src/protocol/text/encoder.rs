@@ -0,0 +1,139 @@
+use crate::cache::error::CacheError;
+use crate::protocol::binary::decoder::BinaryRequest;
+use crate::protocol::binary::encoder::BinaryResponse;
+use bytes::{BufMut, BytesMut};
+use std::fmt::Write;
+
+pub(crate) fn put_line(dst: &mut BytesMut, line: &str) {
+    dst.put_slice(line.as_bytes());
+    dst.put_slice(b"\r\n");
+}
+
+/// What the text reply to a request depends on, it is taken before
+/// the request is handed over
+#[derive(Clone, Copy)]
+pub(crate) struct ReplyKind {
+    /// storage command, other than cas
+    stores: bool,
+    cas: bool,
+}
+
+impl ReplyKind {
+    pub(crate) fn of(request: &BinaryRequest) -> ReplyKind {
+        ReplyKind {
+            stores: matches!(
+                request,
+                BinaryRequest::Set(_)
+                    | BinaryRequest::Add(_)
+                    | BinaryRequest::Replace(_)
+                    | BinaryRequest::Append(_)
+                    | BinaryRequest::Prepend(_)
+            ),
+            cas: request.get_header().cas != 0,
+        }
+    }
+}
+
+/// Writes the text reply to a request of `kind` answered with `response`
+pub(crate) fn put_reply(dst: &mut BytesMut, kind: ReplyKind, response: &BinaryResponse) {
+    let err = match response {
+        BinaryResponse::Error(response) => {
+            CacheError::from_status(response.header.status).unwrap_or(CacheError::InternalError)
+        }
+        BinaryResponse::Increment(response) | BinaryResponse::Decrement(response) => {
+            return put_line(dst, &response.value.to_string());
+        }
+        BinaryResponse::Delete(_) => return put_line(dst, "DELETED"),
+        BinaryResponse::Flush(_) => return put_line(dst, "OK"),
+        _ => return put_line(dst, "STORED"),
+    };
+    // a cas reply tells a missing item from a changed one, other storage
+    // commands just did not store
+    let ReplyKind { stores, cas } = kind;
+    match err {
+        CacheError::NotFound if cas || !stores => put_line(dst, "NOT_FOUND"),
+        CacheError::KeyExists if cas => put_line(dst, "EXISTS"),
+        CacheError::NotFound | CacheError::KeyExists | CacheError::ItemNotStored => {
+            put_line(dst, "NOT_STORED")
+        }
+        CacheError::ArithOnNonNumeric => put_line(
+            dst,
+            "CLIENT_ERROR cannot increment or decrement non-numeric value",
+        ),
+        CacheError::ValueTooLarge => put_line(dst, "SERVER_ERROR object too large for cache"),
+        CacheError::OutOfMemory => put_line(dst, "SERVER_ERROR out of memory storing object"),
+        err => put_line(dst, &format!("SERVER_ERROR {}", err.to_static_string())),
+    }
+}
+
+/// Writes the reply to a get, `responses` are the ones of quiet gets
+/// with the key, found items and errors other than a miss
+pub(crate) fn put_values(
+    dst: &mut BytesMut,
+    responses: impl IntoIterator<Item = BinaryResponse>,
+    cas: bool,
+) {
+    for response in responses {
+        match response {
+            BinaryResponse::Get(item) | BinaryResponse::GetKeyQuietly(item) => {
+                dst.put_slice(b"VALUE ");
+                dst.put_slice(&item.key);
+                let _ = write!(dst, " {} {}", item.flags, item.value.len());
+                if cas {
+                    let _ = write!(dst, " {}", item.header.cas);
+                }
+                dst.put_slice(b"\r\n");
+                dst.put_slice(&item.value);
+                dst.put_slice(b"\r\n");
+            }
+            BinaryResponse::Error(response)
+                if response.header.status != CacheError::NotFound as u16 =>
+            {
+                dst.clear();
+                return put_line(dst, &format!("SERVER_ERROR {}", response.error));
+            }
+            _ => {}
+        }
+    }
+    put_line(dst, "END");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::binary::network;
+    use crate::protocol::text::decoder::{MemcacheTextDecoder, TextCommand};
+    use tokio_util::codec::Decoder;
+
+    fn decode(data: &[u8]) -> Vec<TextCommand> {
+        let mut decoder = MemcacheTextDecoder::new(16);
+        let mut src = BytesMut::from(data);
+        let mut commands = Vec::new();
+        while let Some(command) = decoder.decode(&mut src).unwrap() {
+            commands.push(command);
+        }
+        commands
+    }
+
+    #[test]
+    fn test_replies_follow_text_protocol() {
+        let commands = decode(b"add key 0 0 1\r\nv\r\ncas key 0 0 1 7\r\nv\r\n");
+        let requests: Vec<BinaryRequest> = commands
+            .into_iter()
+            .map(|command| match command {
+                TextCommand::Request { request, .. } => request,
+                command => panic!("Unexpected command {:?}", command),
+            })
+            .collect();
+        let error = |err: CacheError| {
+            let mut header = network::ResponseHeader::new(0, 0);
+            crate::protocol::binary::encoder::storage_error_to_response(err, &mut header)
+        };
+        let mut reply = BytesMut::new();
+        let (add, cas) = (ReplyKind::of(&requests[0]), ReplyKind::of(&requests[1]));
+        put_reply(&mut reply, add, &error(CacheError::KeyExists));
+        put_reply(&mut reply, cas, &error(CacheError::KeyExists));
+        put_reply(&mut reply, cas, &error(CacheError::NotFound));
+        assert_eq!(&reply[..], b"NOT_STORED\r\nEXISTS\r\nNOT_FOUND\r\n");
+    }
+}
@@ -0,0 +1,3 @@
+pub mod connection;
+pub mod decoder;
+pub mod encoder;
@@ -0,0 +1,142 @@
+use crate::protocol::binary::decoder::{BinaryRequest, MemcacheBinaryResponseDecoder};
+use crate::protocol::binary::encoder::{BinaryResponse, MemcacheBinaryEncoder};
+use crate::protocol::binary::network;
+use bytes::BytesMut;
+use std::io::{self, Error, ErrorKind};
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio_util::codec::Decoder;
+
+/// Memcache server keys are routed to
+pub struct Backend {
+    address: String,
+    /// proxy time in milliseconds the backend is retried at after it
+    /// failed, it is up once the time has passed
+    down_until: AtomicU64,
+    requests: AtomicU64,
+    failures: AtomicU64,
+}
+
+impl Backend {
+    pub fn new(address: String) -> Backend {
+        Backend {
+            address,
+            down_until: AtomicU64::new(0),
+            requests: AtomicU64::new(0),
+            failures: AtomicU64::new(0),
+        }
+    }
+
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+
+    pub fn is_up(&self, now: u64) -> bool {
+        self.down_until.load(Ordering::Acquire) <= now
+    }
+
+    pub(crate) fn succeeded(&self, requests: usize) {
+        self.requests.fetch_add(requests as u64, Ordering::Relaxed);
+    }
+
+    /// Keys of the backend go to other backends until `retry_at`,
+    /// returns false if it was down already
+    pub(crate) fn failed(&self, now: u64, retry_at: u64) -> bool {
+        self.failures.fetch_add(1, Ordering::Relaxed);
+        self.down_until.swap(retry_at, Ordering::AcqRel) <= now
+    }
+
+    pub fn requests(&self) -> u64 {
+        self.requests.load(Ordering::Relaxed)
+    }
+
+    pub fn failures(&self) -> u64 {
+        self.failures.load(Ordering::Relaxed)
+    }
+}
+
+/// Connection to a backend, requests sent together are pipelined
+pub struct BackendConnection {
+    stream: TcpStream,
+    buffer: BytesMut,
+    encoder: MemcacheBinaryEncoder,
+    decoder: MemcacheBinaryResponseDecoder,
+}
+
+impl BackendConnection {
+    pub async fn connect(address: &str) -> io::Result<BackendConnection> {
+        let stream = TcpStream::connect(address).await?;
+        stream.set_nodelay(true)?;
+        Ok(BackendConnection {
+            stream,
+            buffer: BytesMut::with_capacity(64 * 1024),
+            encoder: MemcacheBinaryEncoder::new(),
+            decoder: MemcacheBinaryResponseDecoder::new(),
+        })
+    }
+
+    /// Sends `requests` followed by a noop and returns responses received
+    /// before the noop one, the opaque of a response is the index of its
+    /// request. Quiet requests which succeeded have no response.
+    pub async fn exchange(
+        &mut self,
+        requests: &[&BinaryRequest],
+    ) -> io::Result<Vec<BinaryResponse>> {
+        let mut data = BytesMut::new();
+        for (idx, request) in requests.iter().enumerate() {
+            self.encoder.encode_request(request, idx as u32, &mut data);
+        }
+        self.pipeline(data, requests.len()).await
+    }
+
+    /// Sends `requests` already encoded requests followed by a noop, the
+    /// opaque of every request has to be below `requests`
+    pub async fn pipeline(
+        &mut self,
+        mut data: BytesMut,
+        requests: usize,
+    ) -> io::Result<Vec<BinaryResponse>> {
+        let noop = BinaryRequest::Noop(network::NoopRequest {
+            header: network::RequestHeader {
+                opcode: network::Command::Noop as u8,
+                ..Default::default()
+            },
+        });
+        self.encoder
+            .encode_request(&noop, requests as u32, &mut data);
+        self.stream.write_all(&data).await?;
+
+        let mut responses = Vec::new();
+        loop {
+            let response = self.read_response().await?;
+            let opaque = response.get_header().opaque as usize;
+            if let BinaryResponse::Noop(_) = response {
+                if opaque == requests {
+                    return Ok(responses);
+                }
+            }
+            if opaque >= requests {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    "Response to an unknown request",
+                ));
+            }
+            responses.push(response);
+        }
+    }
+
+    async fn read_response(&mut self) -> io::Result<BinaryResponse> {
+        loop {
+            if let Some(response) = self.decoder.decode(&mut self.buffer)? {
+                return Ok(response);
+            }
+            if 0 == self.stream.read_buf(&mut self.buffer).await? {
+                return Err(Error::new(
+                    ErrorKind::ConnectionReset,
+                    "Connection closed by backend",
+                ));
+            }
+        }
+    }
+}
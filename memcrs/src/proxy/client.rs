@@ -0,0 +1,232 @@
+use super::BackendPool;
+use crate::cache::error::CacheError;
+use crate::memcache_server::client_handler::ClientConfig;
+use crate::protocol::binary::connection::MemcacheBinaryConnection;
+use crate::protocol::binary::decoder::BinaryRequest;
+use crate::protocol::binary::encoder::{storage_error_to_response, BinaryResponse};
+use crate::protocol::binary::network;
+use crate::version::MEMCRS_VERSION;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::sync::Semaphore;
+use tokio::time::timeout;
+use tokio_util::sync::CancellationToken;
+
+/// Longest run of quiet requests forwarded in one pipeline
+const MAX_QUIET_BATCH: usize = 1024;
+
+fn is_quiet(request: &BinaryRequest) -> bool {
+    matches!(
+        request,
+        BinaryRequest::GetQuietly(_)
+            | BinaryRequest::GetKeyQuietly(_)
+            | BinaryRequest::SetQuietly(_)
+            | BinaryRequest::AddQuietly(_)
+            | BinaryRequest::ReplaceQuietly(_)
+            | BinaryRequest::AppendQuietly(_)
+            | BinaryRequest::PrependQuietly(_)
+            | BinaryRequest::IncrementQuiet(_)
+            | BinaryRequest::DecrementQuiet(_)
+            | BinaryRequest::DeleteQuiet(_)
+    )
+}
+
+/// Protocol a proxy client speaks
+pub enum Protocol {
+    Binary,
+    Text,
+}
+
+/// Tells the protocol of a new client by the first byte it sends, binary
+/// requests start with the request magic. None when the client sends
+/// nothing within `wait` or closes the connection.
+pub async fn detect_protocol(socket: &TcpStream, wait: Duration) -> Option<Protocol> {
+    let mut first = [0u8; 1];
+    match timeout(wait, socket.peek(&mut first)).await {
+        Ok(Ok(1)) if first[0] == network::Magic::Request as u8 => Some(Protocol::Binary),
+        Ok(Ok(1)) => Some(Protocol::Text),
+        _ => None,
+    }
+}
+
+/// Client of the proxy, keyed requests are forwarded to the backend
+/// owning the key, the rest is answered by the proxy
+pub struct ProxyClient {
+    stream: MemcacheBinaryConnection,
+    addr: SocketAddr,
+    config: ClientConfig,
+    pool: Arc<BackendPool>,
+    /// See client_handler::Client
+    limit_connections: Arc<Semaphore>,
+    cancellation_token: CancellationToken,
+    /// Quiet requests waiting to be forwarded together
+    quiet: Vec<BinaryRequest>,
+}
+
+impl ProxyClient {
+    pub fn new(
+        pool: Arc<BackendPool>,
+        socket: TcpStream,
+        addr: SocketAddr,
+        config: ClientConfig,
+        limit_connections: Arc<Semaphore>,
+        cancellation_token: CancellationToken,
+    ) -> Self {
+        ProxyClient {
+            stream: MemcacheBinaryConnection::new(socket, config.item_memory_limit),
+            addr,
+            config,
+            pool,
+            limit_connections,
+            cancellation_token,
+            quiet: Vec::new(),
+        }
+    }
+
+    pub async fn handle(&mut self) {
+        debug!("New proxy client connected: {}", self.addr);
+        loop {
+            // nothing more to read without waiting, forward the run so far
+            if !self.stream.has_buffered_data() && self.flush_quiet().await {
+                return;
+            }
+            tokio::select! {
+                _ = self.cancellation_token.cancelled() => {
+                    info!("Cancelling proxy client loop for {}", self.addr);
+                    let _ = self.stream.shutdown().await;
+                    return;
+                }
+                req_or_none = timeout(
+                    Duration::from_secs(self.config.rx_timeout_secs as u64),
+                    self.stream.read_frame(),
+                ) => {
+                    let client_close = match req_or_none {
+                        Ok(Ok(Some(request))) => self.handle_request(request).await,
+                        Ok(Ok(None)) => {
+                            debug!("Connection closed: {}", self.addr);
+                            true
+                        }
+                        Ok(Err(err)) => {
+                            error!("Error when reading frame; error = {:?}", err);
+                            true
+                        }
+                        Err(err) => {
+                            debug!(
+                                "Timeout {}s elapsed, disconecting client: {}, error: {}",
+                                self.config.rx_timeout_secs, self.addr, err
+                            );
+                            true
+                        }
+                    };
+                    if client_close {
+                        return;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns true if we should leave client receive loop
+    async fn handle_request(&mut self, request: BinaryRequest) -> bool {
+        if is_quiet(&request) {
+            self.quiet.push(request);
+            if self.quiet.len() >= MAX_QUIET_BATCH {
+                return self.flush_quiet().await;
+            }
+            return false;
+        }
+        if self.flush_quiet().await {
+            return true;
+        }
+
+        let header = request.get_header();
+        let mut response_header = network::ResponseHeader::new(header.opcode, header.opaque);
+        let response = match request {
+            BinaryRequest::QuitQuietly(_request) => {
+                let _ = self.stream.shutdown().await;
+                return true;
+            }
+            BinaryRequest::Quit(_request) => {
+                let response = BinaryResponse::Quit(network::QuitResponse {
+                    header: response_header,
+                });
+                let _ = self.stream.write(&response).await;
+                let _ = self.stream.shutdown().await;
+                return true;
+            }
+            BinaryRequest::Noop(_request) => Some(BinaryResponse::Noop(network::NoopResponse {
+                header: response_header,
+            })),
+            BinaryRequest::Version(_request) => {
+                response_header.body_length = MEMCRS_VERSION.len() as u32;
+                Some(BinaryResponse::Version(network::VersionResponse {
+                    header: response_header,
+                    version: String::from(MEMCRS_VERSION),
+                }))
+            }
+            BinaryRequest::Stats(request) if request.key.is_empty() => {
+                Some(BinaryResponse::Stats(network::StatsResponse {
+                    header: response_header,
+                    records: self.pool.proxy().stats(),
+                }))
+            }
+            BinaryRequest::Stats(_request) => Some(storage_error_to_response(
+                CacheError::NotFound,
+                &mut response_header,
+            )),
+            // keys of a scan are spread over the backends
+            BinaryRequest::Scan(_request) => Some(storage_error_to_response(
+                CacheError::NotSupported,
+                &mut response_header,
+            )),
+            BinaryRequest::ItemTooLarge(_request) => Some(storage_error_to_response(
+                CacheError::ValueTooLarge,
+                &mut response_header,
+            )),
+            BinaryRequest::UnkownCommand(_request) => Some(storage_error_to_response(
+                CacheError::UnkownCommand,
+                &mut response_header,
+            )),
+            BinaryRequest::Flush(_)
+            | BinaryRequest::FlushQuietly(_)
+            | BinaryRequest::InvalidateTag(_) => self.pool.broadcast(&request).await,
+            request => self.pool.dispatch(&[request]).await.pop(),
+        };
+        match response {
+            Some(response) => {
+                if let Err(e) = self.stream.write(&response).await {
+                    error!("error on sending response; error = {:?}", e);
+                    return true;
+                }
+                false
+            }
+            None => false,
+        }
+    }
+
+    /// Forwards collected quiet requests and sends their responses
+    /// Returns true if we should leave client receive loop
+    async fn flush_quiet(&mut self) -> bool {
+        if self.quiet.is_empty() {
+            return false;
+        }
+        let requests = std::mem::take(&mut self.quiet);
+        let responses = self.pool.dispatch(&requests).await;
+        if responses.is_empty() {
+            return false;
+        }
+        if let Err(e) = self.stream.write_batch(&responses).await {
+            error!("error on sending response; error = {:?}", e);
+            return true;
+        }
+        false
+    }
+}
+
+impl Drop for ProxyClient {
+    fn drop(&mut self) {
+        self.limit_connections.add_permits(1);
+    }
+}
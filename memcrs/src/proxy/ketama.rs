@@ -0,0 +1,92 @@
+/// Points every backend gets on the continuum, 4 points are taken from
+/// every md5 digest like libmemcached and twemproxy do
+const POINTS_PER_BACKEND: usize = 160;
+
+fn ketama_hash(digest: &md5::Digest, point: usize) -> u32 {
+    u32::from_le_bytes([
+        digest[point * 4],
+        digest[point * 4 + 1],
+        digest[point * 4 + 2],
+        digest[point * 4 + 3],
+    ])
+}
+
+/// Ketama consistent hashing continuum, adding or removing a backend
+/// moves only the keys of that backend
+pub struct Continuum {
+    /// hash of the point and index of its backend, sorted by hash
+    points: Vec<(u32, usize)>,
+}
+
+impl Continuum {
+    pub fn new(backends: &[String]) -> Continuum {
+        let mut points = Vec::with_capacity(backends.len() * POINTS_PER_BACKEND);
+        for (backend, address) in backends.iter().enumerate() {
+            for digest_idx in 0..POINTS_PER_BACKEND / 4 {
+                let digest = md5::compute(format!("{}-{}", address, digest_idx));
+                for point in 0..4 {
+                    points.push((ketama_hash(&digest, point), backend));
+                }
+            }
+        }
+        points.sort_unstable();
+        Continuum { points }
+    }
+
+    /// Backend owning `key`, keys of backends which are not `up` go to
+    /// the next backend on the continuum
+    pub fn backend(&self, key: &[u8], up: impl Fn(usize) -> bool) -> Option<usize> {
+        if self.points.is_empty() {
+            return None;
+        }
+        let hash = ketama_hash(&md5::compute(key), 0);
+        let first = self.points.partition_point(|(point, _)| *point < hash);
+        (0..self.points.len())
+            .map(|offset| self.points[(first + offset) % self.points.len()].1)
+            .find(|backend| up(*backend))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn backends(count: usize) -> Vec<String> {
+        (0..count)
+            .map(|idx| format!("10.0.0.{}:11211", idx + 1))
+            .collect()
+    }
+
+    #[test]
+    fn keys_should_spread_over_backends() {
+        let continuum = Continuum::new(&backends(4));
+        let mut counts = [0; 4];
+        for idx in 0..10_000 {
+            let key = format!("key{}", idx);
+            counts[continuum.backend(key.as_bytes(), |_| true).unwrap()] += 1;
+        }
+        for count in counts {
+            assert!(count > 1_500, "{:?}", counts);
+        }
+    }
+
+    #[test]
+    fn only_keys_of_removed_backend_should_move() {
+        let all = Continuum::new(&backends(4));
+        let fewer = Continuum::new(&backends(3));
+        for idx in 0..1_000 {
+            let key = format!("key{}", idx);
+            let before = all.backend(key.as_bytes(), |_| true).unwrap();
+            let after = fewer.backend(key.as_bytes(), |_| true).unwrap();
+            if before != 3 {
+                assert_eq!(before, after);
+            }
+            // a backend which is down is skipped the same way
+            assert_eq!(
+                all.backend(key.as_bytes(), |backend| backend != 3),
+                Some(after)
+            );
+        }
+        assert_eq!(all.backend(b"key", |_| false), None);
+    }
+}
@@ -0,0 +1,270 @@
+use crate::cache::error::CacheError;
+use crate::protocol::binary::decoder::BinaryRequest;
+use crate::protocol::binary::encoder::{storage_error_to_response, BinaryResponse};
+use crate::protocol::binary::network;
+use backend::{Backend, BackendConnection};
+use futures::future::join_all;
+use ketama::Continuum;
+use std::collections::BTreeMap;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+pub mod backend;
+pub mod client;
+pub mod ketama;
+pub mod shadow;
+pub mod text;
+pub mod warm;
+
+/// Reads are sent again to the backend their keys moved to when their
+/// backend fails, writes might have been applied already
+fn is_read(request: &BinaryRequest) -> bool {
+    matches!(
+        request,
+        BinaryRequest::Get(_)
+            | BinaryRequest::GetQuietly(_)
+            | BinaryRequest::GetKey(_)
+            | BinaryRequest::GetKeyQuietly(_)
+    )
+}
+
+fn error_response(request: &BinaryRequest, err: CacheError) -> BinaryResponse {
+    let header = request.get_header();
+    let mut response_header = network::ResponseHeader::new(header.opcode, header.opaque);
+    storage_error_to_response(err, &mut response_header)
+}
+
+#[derive(Clone, Debug)]
+pub struct ProxyConfig {
+    /// HOST:PORT of every backend
+    pub backends: Vec<String>,
+    /// idle connections kept to every backend by each worker
+    pub pool_size: usize,
+    /// longest wait for a backend to connect or respond
+    pub timeout: Duration,
+    /// how long keys of a failed backend go to other backends
+    pub retry_delay: Duration,
+}
+
+/// Routes keys to backend memcache servers with ketama consistent
+/// hashing, shared by all workers
+pub struct Proxy {
+    backends: Vec<Backend>,
+    continuum: Continuum,
+    config: ProxyConfig,
+    started: Instant,
+}
+
+impl Proxy {
+    pub fn new(config: ProxyConfig) -> Proxy {
+        Proxy {
+            backends: config.backends.iter().cloned().map(Backend::new).collect(),
+            continuum: Continuum::new(&config.backends),
+            config,
+            started: Instant::now(),
+        }
+    }
+
+    /// Milliseconds since the proxy started
+    fn now(&self) -> u64 {
+        self.started.elapsed().as_millis() as u64
+    }
+
+    /// Backend `key` is sent to, None when every backend is down
+    pub fn route(&self, key: &[u8]) -> Option<usize> {
+        let now = self.now();
+        self.continuum
+            .backend(key, |backend| self.backends[backend].is_up(now))
+    }
+
+    /// Backends which are up, requests without a key go to all of them
+    pub fn up_backends(&self) -> Vec<usize> {
+        let now = self.now();
+        (0..self.backends.len())
+            .filter(|backend| self.backends[*backend].is_up(now))
+            .collect()
+    }
+
+    fn failed(&self, backend: usize, err: &io::Error) {
+        let now = self.now();
+        let retry_at = now + self.config.retry_delay.as_millis() as u64;
+        let backend = &self.backends[backend];
+        if backend.failed(now, retry_at) {
+            warn!("Backend {} is down: {}", backend.address(), err);
+        }
+    }
+
+    pub fn stats(&self) -> Vec<(String, String)> {
+        let now = self.now();
+        let mut records = vec![("backends".to_string(), self.backends.len().to_string())];
+        for backend in &self.backends {
+            let state = match backend.is_up(now) {
+                true => "up",
+                false => "down",
+            };
+            let name = |stat: &str| format!("backend:{}:{}", backend.address(), stat);
+            records.push((name("state"), state.to_string()));
+            records.push((name("requests"), backend.requests().to_string()));
+            records.push((name("failures"), backend.failures().to_string()));
+        }
+        records
+    }
+}
+
+/// Connections of one worker to the backends
+pub struct BackendPool {
+    proxy: Arc<Proxy>,
+    idle: Vec<Mutex<Vec<BackendConnection>>>,
+}
+
+impl BackendPool {
+    pub fn new(proxy: Arc<Proxy>) -> BackendPool {
+        BackendPool {
+            idle: proxy
+                .backends
+                .iter()
+                .map(|_| Mutex::new(Vec::new()))
+                .collect(),
+            proxy,
+        }
+    }
+
+    pub fn proxy(&self) -> &Proxy {
+        &self.proxy
+    }
+
+    /// Sends requests to a backend in one pipeline, the backend is marked
+    /// down when it fails or times out
+    pub async fn forward(
+        &self,
+        backend: usize,
+        requests: &[&BinaryRequest],
+    ) -> io::Result<Vec<BinaryResponse>> {
+        let timeout = self.proxy.config.timeout;
+        let idle = self.idle[backend].lock().unwrap().pop();
+        let result = tokio::time::timeout(timeout, async {
+            let mut connection = match idle {
+                Some(connection) => connection,
+                None => BackendConnection::connect(self.proxy.backends[backend].address()).await?,
+            };
+            let responses = connection.exchange(requests).await?;
+            Ok::<_, io::Error>((connection, responses))
+        })
+        .await
+        .unwrap_or_else(|_| Err(io::Error::new(io::ErrorKind::TimedOut, "Backend timed out")));
+
+        match result {
+            Ok((connection, responses)) => {
+                self.proxy.backends[backend].succeeded(requests.len());
+                let mut idle = self.idle[backend].lock().unwrap();
+                if idle.len() < self.proxy.config.pool_size {
+                    idle.push(connection);
+                }
+                Ok(responses)
+            }
+            Err(err) => {
+                // other idle connections are most likely broken too
+                self.idle[backend].lock().unwrap().clear();
+                self.proxy.failed(backend, &err);
+                Err(err)
+            }
+        }
+    }
+
+    /// Forwards keyed requests, requests of one backend are sent in one
+    /// pipeline and backends are asked concurrently. Responses come in
+    /// the order of their requests.
+    pub async fn dispatch(&self, requests: &[BinaryRequest]) -> Vec<BinaryResponse> {
+        let proxy = self.proxy();
+        let mut responses: Vec<(usize, BinaryResponse)> = Vec::new();
+        let mut pending: Vec<usize> = (0..requests.len()).collect();
+        for attempt in 0..2 {
+            let mut batches: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+            for idx in pending.drain(..) {
+                match proxy.route(&requests[idx].get_key()) {
+                    Some(backend) => batches.entry(backend).or_default().push(idx),
+                    None => responses.push((
+                        idx,
+                        error_response(&requests[idx], CacheError::TemporaryFailure),
+                    )),
+                }
+            }
+            let results = join_all(batches.into_iter().map(|(backend, batch)| async move {
+                let batch_requests: Vec<&BinaryRequest> =
+                    batch.iter().map(|idx| &requests[*idx]).collect();
+                let result = self.forward(backend, &batch_requests).await;
+                (batch, result)
+            }))
+            .await;
+            for (batch, result) in results {
+                match result {
+                    Ok(batch_responses) => {
+                        for mut response in batch_responses {
+                            let idx = batch[response.get_header().opaque as usize];
+                            response.get_header_mut().opaque = requests[idx].get_header().opaque;
+                            responses.push((idx, response));
+                        }
+                    }
+                    Err(_err) => {
+                        for idx in batch {
+                            if attempt == 0 && is_read(&requests[idx]) {
+                                pending.push(idx);
+                            } else {
+                                responses.push((
+                                    idx,
+                                    error_response(&requests[idx], CacheError::TemporaryFailure),
+                                ));
+                            }
+                        }
+                    }
+                }
+            }
+            if pending.is_empty() {
+                break;
+            }
+        }
+        responses.sort_by_key(|(idx, _)| *idx);
+        responses
+            .into_iter()
+            .map(|(_, response)| response)
+            .collect()
+    }
+
+    /// Sends a request without a key to every backend which is up, the
+    /// first error is returned to the client
+    pub async fn broadcast(&self, request: &BinaryRequest) -> Option<BinaryResponse> {
+        let backends = self.proxy().up_backends();
+        if backends.is_empty() {
+            return Some(error_response(request, CacheError::TemporaryFailure));
+        }
+        let batch = [request];
+        let results = join_all(
+            backends
+                .into_iter()
+                .map(|backend| self.forward(backend, &batch)),
+        )
+        .await;
+        let mut response = None;
+        for result in results {
+            match result {
+                Ok(mut responses) => {
+                    if let Some(backend_response) = responses.pop() {
+                        let failed = matches!(backend_response, BinaryResponse::Error(_));
+                        response = Some(backend_response);
+                        if failed {
+                            break;
+                        }
+                    }
+                }
+                Err(_err) => {
+                    return Some(error_response(request, CacheError::TemporaryFailure));
+                }
+            }
+        }
+        response.map(|mut response| {
+            response.get_header_mut().opaque = request.get_header().opaque;
+            response
+        })
+    }
+}
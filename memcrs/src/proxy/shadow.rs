@@ -0,0 +1,275 @@
+use super::backend::BackendConnection;
+use crate::protocol::binary::decoder::BinaryRequest;
+use crate::protocol::binary::encoder::MemcacheBinaryEncoder;
+use crate::protocol::binary::network::GetRequest;
+use bytes::{Bytes, BytesMut};
+use std::io::{self, Error, ErrorKind};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+
+/// most requests pipelined to the shadow server at once
+const BATCH_SIZE: usize = 256;
+/// longest wait for the shadow server to connect or respond to a batch
+const TIMEOUT: Duration = Duration::from_secs(1);
+/// pause after the shadow server failed, requests are dropped once the
+/// queue fills up in the meantime
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+
+#[derive(Clone, Debug)]
+pub struct ShadowConfig {
+    /// HOST:PORT of the shadow server
+    pub address: String,
+    /// one of `sample_rate` keyed requests is mirrored
+    pub sample_rate: u32,
+    /// gets are mirrored next to mutations
+    pub reads: bool,
+    /// requests waiting to be mirrored, requests over it are dropped
+    pub queue_size: usize,
+}
+
+#[derive(Default)]
+struct Counters {
+    queued: AtomicU64,
+    mirrored: AtomicU64,
+    dropped: AtomicU64,
+    failed: AtomicU64,
+}
+
+/// Requests mirrored to a shadow server, counted in requests
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ShadowStats {
+    pub queued: u64,
+    /// requests the shadow server responded to
+    pub mirrored: u64,
+    /// requests which did not fit in the queue
+    pub dropped: u64,
+    /// requests lost because the shadow server was down or too slow
+    pub failed: u64,
+}
+
+/// Mirrors a sample of client requests to a shadow server without
+/// waiting for it, shared by all handlers
+pub struct Shadow {
+    queue: mpsc::Sender<Bytes>,
+    encoder: MemcacheBinaryEncoder,
+    sample_rate: u32,
+    reads: bool,
+    counters: Arc<Counters>,
+}
+
+impl Shadow {
+    /// Queues `request` if it is mirrored and sampled, flushes and tag
+    /// invalidations are not sampled so the shadow server drops the
+    /// same items
+    pub fn mirror(&self, request: &BinaryRequest) {
+        let sampled = match request {
+            BinaryRequest::Flush(_)
+            | BinaryRequest::FlushQuietly(_)
+            | BinaryRequest::InvalidateTag(_) => true,
+            request if request.is_mutation() => self.sampled(),
+            BinaryRequest::Get(_)
+            | BinaryRequest::GetKey(_)
+            | BinaryRequest::GetQuietly(_)
+            | BinaryRequest::GetKeyQuietly(_) => self.reads && self.sampled(),
+            _ => false,
+        };
+        if !sampled {
+            return;
+        }
+        let mut data = BytesMut::new();
+        self.encoder.encode_request(request, 0, &mut data);
+        match self.queue.try_send(data.freeze()) {
+            Ok(()) => self.counters.queued.fetch_add(1, Ordering::Relaxed),
+            Err(_) => self.counters.dropped.fetch_add(1, Ordering::Relaxed),
+        };
+    }
+
+    /// Mirrors a run of quiet gets resolved together
+    pub fn mirror_reads(&self, requests: &[GetRequest]) {
+        if self.reads {
+            for request in requests {
+                self.mirror(&BinaryRequest::GetQuietly(request.clone()));
+            }
+        }
+    }
+
+    fn sampled(&self) -> bool {
+        self.sample_rate == 1 || rand::random::<u32>().is_multiple_of(self.sample_rate)
+    }
+
+    pub fn stats(&self) -> ShadowStats {
+        ShadowStats {
+            queued: self.counters.queued.load(Ordering::Relaxed),
+            mirrored: self.counters.mirrored.load(Ordering::Relaxed),
+            dropped: self.counters.dropped.load(Ordering::Relaxed),
+            failed: self.counters.failed.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Pipelines queued requests to the shadow server, its responses are
+/// read and ignored
+pub struct ShadowService {
+    address: String,
+    receiver: Mutex<Option<mpsc::Receiver<Bytes>>>,
+    counters: Arc<Counters>,
+    cancellation_token: CancellationToken,
+}
+
+/// Queue of mirrored requests and the service draining it into the
+/// shadow server
+pub fn shadow(
+    config: ShadowConfig,
+    cancellation_token: CancellationToken,
+) -> (Arc<Shadow>, Arc<ShadowService>) {
+    let (sender, receiver) = mpsc::channel(config.queue_size.max(1));
+    let counters = Arc::new(Counters::default());
+    let queue = Arc::new(Shadow {
+        queue: sender,
+        encoder: MemcacheBinaryEncoder::new(),
+        sample_rate: config.sample_rate.max(1),
+        reads: config.reads,
+        counters: counters.clone(),
+    });
+    let service = Arc::new(ShadowService {
+        address: config.address,
+        receiver: Mutex::new(Some(receiver)),
+        counters,
+        cancellation_token,
+    });
+    (queue, service)
+}
+
+impl ShadowService {
+    /// Runs until cancelled, requests still queued by then are dropped
+    pub async fn run(self: Arc<Self>) {
+        let mut receiver = match self.receiver.lock().unwrap().take() {
+            Some(receiver) => receiver,
+            None => return,
+        };
+        let mut connection = None;
+        let mut batch = Vec::with_capacity(BATCH_SIZE);
+        let mut up = true;
+        loop {
+            let received = tokio::select! {
+                _ = self.cancellation_token.cancelled() => {
+                    info!("Shadow service received cancellation signal, stopping...");
+                    break;
+                },
+                received = receiver.recv_many(&mut batch, BATCH_SIZE) => received,
+            };
+            if received == 0 {
+                break;
+            }
+            let requests = batch.len();
+            let mut data = BytesMut::new();
+            for request in batch.drain(..) {
+                data.extend_from_slice(&request);
+            }
+            match self.send(&mut connection, data, requests).await {
+                Ok(()) => {
+                    self.counters
+                        .mirrored
+                        .fetch_add(requests as u64, Ordering::Relaxed);
+                    if !up {
+                        info!("Shadow server {} is back up", self.address);
+                        up = true;
+                    }
+                }
+                Err(err) => {
+                    connection = None;
+                    self.counters
+                        .failed
+                        .fetch_add(requests as u64, Ordering::Relaxed);
+                    if up {
+                        warn!("Shadow server {} failed: {}", self.address, err);
+                        up = false;
+                    }
+                    tokio::select! {
+                        _ = self.cancellation_token.cancelled() => break,
+                        _ = tokio::time::sleep(RETRY_DELAY) => {},
+                    }
+                }
+            }
+        }
+    }
+
+    async fn send(
+        &self,
+        connection: &mut Option<BackendConnection>,
+        data: BytesMut,
+        requests: usize,
+    ) -> io::Result<()> {
+        let exchange = async {
+            let connection = match connection {
+                Some(connection) => connection,
+                None => connection.insert(BackendConnection::connect(&self.address).await?),
+            };
+            connection.pipeline(data, requests).await
+        };
+        match tokio::time::timeout(TIMEOUT, exchange).await {
+            Ok(result) => result.map(|_| ()),
+            Err(_) => Err(Error::new(ErrorKind::TimedOut, "Shadow server timed out")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::binary::network;
+
+    fn request(opcode: network::Command, key: &'static str) -> BinaryRequest {
+        let request = GetRequest {
+            header: network::RequestHeader {
+                opcode: opcode as u8,
+                key_length: key.len() as u16,
+                body_length: key.len() as u32,
+                ..Default::default()
+            },
+            key: Bytes::from(key),
+        };
+        match opcode {
+            network::Command::Delete => BinaryRequest::Delete(request),
+            _ => BinaryRequest::Get(request),
+        }
+    }
+
+    fn config(reads: bool) -> ShadowConfig {
+        ShadowConfig {
+            address: "127.0.0.1:1".to_string(),
+            sample_rate: 1,
+            reads,
+            queue_size: 2,
+        }
+    }
+
+    #[test]
+    fn test_mutations_are_mirrored_until_queue_is_full() {
+        let (shadow, _service) = shadow(config(false), CancellationToken::new());
+        shadow.mirror(&request(network::Command::Get, "key"));
+        assert_eq!(shadow.stats(), ShadowStats::default());
+
+        for _ in 0..3 {
+            shadow.mirror(&request(network::Command::Delete, "key"));
+        }
+        assert_eq!(
+            shadow.stats(),
+            ShadowStats {
+                queued: 2,
+                dropped: 1,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_reads_are_mirrored_when_enabled() {
+        let (shadow, _service) = shadow(config(true), CancellationToken::new());
+        shadow.mirror(&request(network::Command::Get, "key"));
+        assert_eq!(shadow.stats().queued, 1);
+    }
+}
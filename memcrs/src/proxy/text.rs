@@ -0,0 +1,194 @@
+use super::BackendPool;
+use crate::memcache_server::client_handler::ClientConfig;
+use crate::protocol::binary::decoder::BinaryRequest;
+use crate::protocol::binary::network;
+use crate::protocol::text::connection::MemcacheTextConnection;
+use crate::protocol::text::decoder::{header, TextCommand};
+use crate::protocol::text::encoder::{put_line, put_reply, put_values, ReplyKind};
+use crate::version::MEMCRS_VERSION;
+use bytes::{Bytes, BytesMut};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::sync::Semaphore;
+use tokio::time::timeout;
+use tokio_util::sync::CancellationToken;
+
+/// Longest run of noreply requests forwarded in one pipeline
+const MAX_QUIET_BATCH: usize = 1024;
+
+/// Text protocol client of the proxy, its commands are forwarded as
+/// binary requests like the ones of binary clients
+pub struct TextProxyClient {
+    stream: MemcacheTextConnection,
+    addr: SocketAddr,
+    config: ClientConfig,
+    pool: Arc<BackendPool>,
+    /// See client_handler::Client
+    limit_connections: Arc<Semaphore>,
+    cancellation_token: CancellationToken,
+    /// noreply requests waiting to be forwarded together
+    quiet: Vec<BinaryRequest>,
+}
+
+impl TextProxyClient {
+    pub fn new(
+        pool: Arc<BackendPool>,
+        socket: TcpStream,
+        addr: SocketAddr,
+        config: ClientConfig,
+        limit_connections: Arc<Semaphore>,
+        cancellation_token: CancellationToken,
+    ) -> Self {
+        TextProxyClient {
+            stream: MemcacheTextConnection::new(socket, config.item_memory_limit),
+            addr,
+            config,
+            pool,
+            limit_connections,
+            cancellation_token,
+            quiet: Vec::new(),
+        }
+    }
+
+    pub async fn handle(&mut self) {
+        debug!("New text proxy client connected: {}", self.addr);
+        loop {
+            // nothing more to read without waiting, forward the run so far
+            if !self.stream.has_buffered_data() {
+                self.flush_quiet().await;
+            }
+            tokio::select! {
+                _ = self.cancellation_token.cancelled() => {
+                    info!("Cancelling proxy client loop for {}", self.addr);
+                    let _ = self.stream.shutdown().await;
+                    return;
+                }
+                command_or_none = timeout(
+                    Duration::from_secs(self.config.rx_timeout_secs as u64),
+                    self.stream.read_frame(),
+                ) => {
+                    let client_close = match command_or_none {
+                        Ok(Ok(Some(command))) => self.handle_command(command).await,
+                        Ok(Ok(None)) => {
+                            debug!("Connection closed: {}", self.addr);
+                            true
+                        }
+                        Ok(Err(err)) => {
+                            error!("Error when reading command; error = {:?}", err);
+                            true
+                        }
+                        Err(err) => {
+                            debug!(
+                                "Timeout {}s elapsed, disconecting client: {}, error: {}",
+                                self.config.rx_timeout_secs, self.addr, err
+                            );
+                            true
+                        }
+                    };
+                    if client_close {
+                        return;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns true if we should leave client receive loop
+    async fn handle_command(&mut self, command: TextCommand) -> bool {
+        let command = match command {
+            // flushes are not keyed, they are not part of a run
+            TextCommand::Request {
+                request,
+                noreply: true,
+            } if !matches!(request, BinaryRequest::Flush(_)) => {
+                self.quiet.push(request);
+                if self.quiet.len() >= MAX_QUIET_BATCH {
+                    self.flush_quiet().await;
+                }
+                return false;
+            }
+            command => command,
+        };
+        self.flush_quiet().await;
+
+        let mut reply = BytesMut::new();
+        match command {
+            TextCommand::Get { keys, cas } => self.get(keys, cas, &mut reply).await,
+            TextCommand::Request { request, noreply } => {
+                let response = match request {
+                    BinaryRequest::Flush(_) => self.pool.broadcast(&request).await,
+                    _ => self
+                        .pool
+                        .dispatch(std::slice::from_ref(&request))
+                        .await
+                        .pop(),
+                };
+                if let (Some(response), false) = (response, noreply) {
+                    put_reply(&mut reply, ReplyKind::of(&request), &response);
+                }
+            }
+            TextCommand::Version => put_line(&mut reply, &format!("VERSION {}", MEMCRS_VERSION)),
+            TextCommand::Stats => {
+                for (name, value) in self.pool.proxy().stats() {
+                    put_line(&mut reply, &format!("STAT {} {}", name, value));
+                }
+                put_line(&mut reply, "END");
+            }
+            TextCommand::Quit => {
+                let _ = self.stream.shutdown().await;
+                return true;
+            }
+            TextCommand::TooLarge { noreply } => {
+                if !noreply {
+                    put_line(&mut reply, "SERVER_ERROR object too large for cache");
+                }
+            }
+            TextCommand::Metadump { .. } => put_line(
+                &mut reply,
+                "SERVER_ERROR metadump is not supported by the proxy",
+            ),
+            TextCommand::ClientError(message) => put_line(&mut reply, message),
+            TextCommand::Unknown => put_line(&mut reply, "ERROR"),
+        }
+        if reply.is_empty() {
+            return false;
+        }
+        if let Err(e) = self.stream.write(&reply).await {
+            error!("error on sending response; error = {:?}", e);
+            return true;
+        }
+        false
+    }
+
+    /// Gets items of `keys` with one quiet request per key, so the keys
+    /// are split between backends like a binary multi-get
+    async fn get(&self, keys: Vec<Bytes>, cas: bool, reply: &mut BytesMut) {
+        let requests: Vec<BinaryRequest> = keys
+            .into_iter()
+            .map(|key| {
+                BinaryRequest::GetKeyQuietly(network::GetKeyQuietRequest {
+                    header: header(network::Command::GetKeyQuiet, 0),
+                    key,
+                })
+            })
+            .collect();
+        put_values(reply, self.pool.dispatch(&requests).await, cas);
+    }
+
+    /// Forwards collected noreply requests, their errors are not sent
+    async fn flush_quiet(&mut self) {
+        if self.quiet.is_empty() {
+            return;
+        }
+        let requests = std::mem::take(&mut self.quiet);
+        self.pool.dispatch(&requests).await;
+    }
+}
+
+impl Drop for TextProxyClient {
+    fn drop(&mut self) {
+        self.limit_connections.add_permits(1);
+    }
+}
@@ -0,0 +1,216 @@
+use super::backend::BackendConnection;
+use crate::cache::cache::{Cache, KeyType, Record};
+use crate::protocol::binary::decoder::BinaryRequest;
+use crate::protocol::binary::encoder::BinaryResponse;
+use crate::protocol::binary::network;
+use bytes::Bytes;
+use std::io::{self, Error, ErrorKind};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio_util::sync::CancellationToken;
+
+/// keys asked for in one scan, their values are fetched in one pipeline
+const PAGE_SIZE: u32 = 1000;
+/// longest wait for the peer to connect or answer a page
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Percent-decodes a key of a metadump line
+fn uri_decode(key: &[u8]) -> Option<Bytes> {
+    let mut decoded = Vec::with_capacity(key.len());
+    let mut bytes = key.iter();
+    while let Some(&byte) = bytes.next() {
+        if byte != b'%' {
+            decoded.push(byte);
+            continue;
+        }
+        let hex = [*bytes.next()?, *bytes.next()?];
+        let hex = std::str::from_utf8(&hex).ok()?;
+        decoded.push(u8::from_str_radix(hex, 16).ok()?);
+    }
+    Some(Bytes::from(decoded))
+}
+
+/// Key and expiration of every metadump line, expiration is a unix time
+/// or 0 if the item never expires
+fn parse_metadump(dump: &[u8]) -> io::Result<Vec<(KeyType, u32)>> {
+    let invalid = || Error::new(ErrorKind::InvalidData, "Incorrect metadump line");
+    let mut items = Vec::new();
+    for line in dump.split(|&byte| byte == b'\n') {
+        if line.is_empty() {
+            continue;
+        }
+        let mut key = None;
+        let mut expiration = None;
+        for field in line.split(|&byte| byte == b' ') {
+            if let Some(value) = field.strip_prefix(b"key=") {
+                key = uri_decode(value);
+            } else if let Some(value) = field.strip_prefix(b"exp=") {
+                expiration = match std::str::from_utf8(value).ok() {
+                    Some("-1") => Some(0),
+                    Some(value) => value.parse::<u32>().ok(),
+                    None => None,
+                };
+            }
+        }
+        items.push((key.ok_or_else(invalid)?, expiration.ok_or_else(invalid)?));
+    }
+    Ok(items)
+}
+
+fn scan_request(cursor: u64) -> BinaryRequest {
+    BinaryRequest::Scan(network::ScanRequest {
+        header: network::RequestHeader {
+            opcode: network::Command::Scan as u8,
+            ..Default::default()
+        },
+        cursor,
+        count: PAGE_SIZE,
+        prefix: Bytes::new(),
+    })
+}
+
+fn get_request(key: KeyType) -> BinaryRequest {
+    BinaryRequest::GetKeyQuietly(network::GetRequest {
+        header: network::RequestHeader {
+            opcode: network::Command::GetKeyQuiet as u8,
+            ..Default::default()
+        },
+        key,
+    })
+}
+
+/// Copies the items of a running peer into the local store while the
+/// server is already serving, items written by clients in the meantime
+/// are kept
+pub struct WarmService {
+    peer: String,
+    store: Arc<dyn Cache + Send + Sync>,
+    cancellation_token: CancellationToken,
+}
+
+impl WarmService {
+    pub fn new(
+        peer: String,
+        store: Arc<dyn Cache + Send + Sync>,
+        cancellation_token: CancellationToken,
+    ) -> Self {
+        WarmService {
+            peer,
+            store,
+            cancellation_token,
+        }
+    }
+
+    /// Warms the store once, failures are logged and leave the items
+    /// copied so far in place
+    pub async fn run(self: Arc<Self>) {
+        let start = Instant::now();
+        info!("Warming cache from {}", self.peer);
+        tokio::select! {
+            _ = self.cancellation_token.cancelled() => {
+                info!("Warm service received cancellation signal, stopping...");
+            },
+            result = self.warm() => match result {
+                Ok(items) => info!(
+                    "Warmed {} items from {} in {:?}",
+                    items,
+                    self.peer,
+                    start.elapsed()
+                ),
+                Err(err) => error!("Cannot warm cache from {}: {}", self.peer, err),
+            },
+        }
+    }
+
+    async fn warm(&self) -> io::Result<u64> {
+        let mut connection = timeout(BackendConnection::connect(&self.peer)).await?;
+        let mut cursor = 0;
+        let mut loaded = 0;
+        loop {
+            let (items, next) = timeout(self.scan(&mut connection, cursor)).await?;
+            loaded += timeout(self.load(&mut connection, items)).await?;
+            if next == 0 {
+                return Ok(loaded);
+            }
+            cursor = next;
+        }
+    }
+
+    /// Keys of a page and the cursor of the next one
+    async fn scan(
+        &self,
+        connection: &mut BackendConnection,
+        cursor: u64,
+    ) -> io::Result<(Vec<(KeyType, u32)>, u64)> {
+        let request = scan_request(cursor);
+        let responses = connection.exchange(&[&request]).await?;
+        match responses.into_iter().next() {
+            Some(BinaryResponse::Scan(response)) => {
+                Ok((parse_metadump(&response.dump)?, response.cursor))
+            }
+            Some(BinaryResponse::Error(response)) => {
+                Err(Error::other(format!("Scan failed: {}", response.error)))
+            }
+            _ => Err(Error::new(
+                ErrorKind::InvalidData,
+                "Unexpected scan response",
+            )),
+        }
+    }
+
+    /// Fetches values of `items` and adds them to the store, returns how
+    /// many were added. Items gone from the peer in the meantime are
+    /// skipped.
+    async fn load(
+        &self,
+        connection: &mut BackendConnection,
+        items: Vec<(KeyType, u32)>,
+    ) -> io::Result<u64> {
+        let requests: Vec<BinaryRequest> = items
+            .iter()
+            .map(|(key, _)| get_request(key.clone()))
+            .collect();
+        let requests: Vec<&BinaryRequest> = requests.iter().collect();
+        let mut loaded = 0;
+        for response in connection.exchange(&requests).await? {
+            let response = match response {
+                BinaryResponse::GetKeyQuietly(response) => response,
+                _ => continue,
+            };
+            let (key, expiration) = &items[response.header.opaque as usize];
+            let record = Record::new(response.value, 0, response.flags, *expiration);
+            if self.store.add(key.clone(), record).is_ok() {
+                loaded += 1;
+            }
+        }
+        Ok(loaded)
+    }
+}
+
+async fn timeout<T>(future: impl std::future::Future<Output = io::Result<T>>) -> io::Result<T> {
+    match tokio::time::timeout(TIMEOUT, future).await {
+        Ok(result) => result,
+        Err(_) => Err(Error::new(ErrorKind::TimedOut, "Peer timed out")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_metadump() {
+        let dump = b"key=user%3Aa%20b exp=-1 la=0 cas=1 fetch=no cls=0 size=10\n\
+                     key=other exp=1700003600 la=0 cas=2 fetch=no cls=0 size=12\n";
+        assert_eq!(
+            parse_metadump(dump).unwrap(),
+            vec![
+                (Bytes::from("user:a b"), 0),
+                (Bytes::from("other"), 1_700_003_600)
+            ]
+        );
+        assert!(parse_metadump(b"").unwrap().is_empty());
+        assert!(parse_metadump(b"key=a%2 exp=-1\n").is_err());
+        assert!(parse_metadump(b"key=a\n").is_err());
+    }
+}
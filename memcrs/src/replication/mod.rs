@@ -0,0 +1,317 @@
+use crate::cache::cache::{Cache, CacheMetaData, KeyType};
+use crate::cache::error::Result;
+use crate::persistence::aof::{self, KeyState};
+use crate::persistence::format::{invalid_data, read_u16};
+use crate::persistence::stripes::KeyStripes;
+use crate::server::timer::Timer;
+use bytes::Bytes;
+use std::io::{self, Read};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::broadcast;
+
+pub mod primary;
+pub mod replica;
+
+/// Replication stream, all integers are big endian:
+///
+/// ```text
+/// handshake: magic "MCRSREPL" | version u16, sent by the replica
+///            and echoed by the primary
+/// snapshot:  every live item, see persistence::snapshot
+/// stream:    mutation log entries, see persistence::aof, and
+/// heartbeat: tag 0x80 | primary unix time in milliseconds u64
+/// ```
+///
+/// Entries carry the state a mutation left the key in, so applying
+/// entries of mutations already in the snapshot does no harm.
+const MAGIC: &[u8; 8] = b"MCRSREPL";
+const VERSION: u16 = 1;
+const TAG_HEARTBEAT: u8 = 0x80;
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
+/// Entries a replica can fall behind before it is disconnected and
+/// has to sync again
+const BACKLOG: usize = 1 << 16;
+
+fn handshake() -> Vec<u8> {
+    let mut handshake = MAGIC.to_vec();
+    handshake.extend_from_slice(&VERSION.to_be_bytes());
+    handshake
+}
+
+fn check_handshake(handshake: &[u8; 10]) -> io::Result<()> {
+    let mut reader = &handshake[..];
+    let mut magic = [0u8; 8];
+    reader.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(invalid_data("Not a memcrs replication stream"));
+    }
+    let version = read_u16(&mut reader)?;
+    if version != VERSION {
+        return Err(invalid_data(&format!(
+            "Unsupported replication version: {}",
+            version
+        )));
+    }
+    Ok(())
+}
+
+fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |now| now.as_millis() as u64)
+}
+
+fn heartbeat() -> Vec<u8> {
+    let mut heartbeat = vec![TAG_HEARTBEAT];
+    heartbeat.extend_from_slice(&unix_millis().to_be_bytes());
+    heartbeat
+}
+
+/// State of the link of a replica to its primary
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LinkState {
+    Connecting = 0,
+    /// receiving snapshot
+    Syncing = 1,
+    /// applying mutations as they happen on the primary
+    Streaming = 2,
+    Down = 3,
+}
+
+impl LinkState {
+    pub fn name(&self) -> &'static str {
+        match self {
+            LinkState::Connecting => "connecting",
+            LinkState::Syncing => "syncing",
+            LinkState::Streaming => "streaming",
+            LinkState::Down => "down",
+        }
+    }
+
+    fn from_u8(state: u8) -> LinkState {
+        match state {
+            0 => LinkState::Connecting,
+            1 => LinkState::Syncing,
+            2 => LinkState::Streaming,
+            _ => LinkState::Down,
+        }
+    }
+}
+
+/// Replication role and link, fields from `primary` on are only set
+/// on replicas
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ReplicationStats {
+    pub role: &'static str,
+    /// mutations published to replicas since start
+    pub offset: u64,
+    /// replicas connected to this server
+    pub replicas: u64,
+    /// address of the primary
+    pub primary: Option<String>,
+    pub link: Option<&'static str>,
+    /// mutations applied from the primary since start
+    pub applied: u64,
+    /// age of the last heartbeat applied, by the primary's clock
+    pub lag_ms: Option<u64>,
+    pub full_syncs: u64,
+}
+
+/// Replication role of a server and counters reported by stats.
+///
+/// A primary publishes every mutation done through `MemcStore` to the
+/// connected replicas. A replica applies mutations of its primary and
+/// rejects writes of clients until it is promoted.
+pub struct Replication {
+    timer: Arc<dyn Timer + Send + Sync>,
+    stripes: KeyStripes,
+    entries: broadcast::Sender<Bytes>,
+    /// entries published since start
+    offset: AtomicU64,
+    replicas: AtomicU64,
+    /// address of the primary of a replica
+    primary: Option<String>,
+    promoted: AtomicBool,
+    link: AtomicU8,
+    /// entries applied by a replica since start
+    applied: AtomicU64,
+    /// primary time of the last heartbeat applied, 0 before the first one
+    heartbeat: AtomicU64,
+    full_syncs: AtomicU64,
+}
+
+impl Replication {
+    fn new(timer: Arc<dyn Timer + Send + Sync>, primary: Option<String>) -> Replication {
+        Replication {
+            timer,
+            stripes: KeyStripes::new(),
+            entries: broadcast::channel(BACKLOG).0,
+            offset: AtomicU64::new(0),
+            replicas: AtomicU64::new(0),
+            primary,
+            promoted: AtomicBool::new(false),
+            link: AtomicU8::new(LinkState::Down as u8),
+            applied: AtomicU64::new(0),
+            heartbeat: AtomicU64::new(0),
+            full_syncs: AtomicU64::new(0),
+        }
+    }
+
+    /// Server accepting replicas
+    pub fn primary(timer: Arc<dyn Timer + Send + Sync>) -> Replication {
+        Replication::new(timer, None)
+    }
+
+    /// Server replicating from the primary at `address`
+    pub fn replica(timer: Arc<dyn Timer + Send + Sync>, address: String) -> Replication {
+        Replication::new(timer, Some(address))
+    }
+
+    /// Replicas reject client writes until they are promoted
+    pub fn is_read_only(&self) -> bool {
+        self.primary.is_some() && !self.promoted.load(Ordering::Acquire)
+    }
+
+    /// Turns a replica into a primary, it stops applying mutations of
+    /// its old primary and accepts writes. Returns false if it was not
+    /// a replica.
+    pub fn promote(&self) -> bool {
+        self.primary.is_some() && !self.promoted.swap(true, Ordering::AcqRel)
+    }
+
+    /// Applies a mutation of a key and publishes the state `state` says
+    /// it left the key in, mutations of a key are published in the order
+    /// they were applied
+    pub fn write<T>(
+        &self,
+        store: &dyn Cache,
+        key: &KeyType,
+        mutation: impl FnOnce() -> Result<T>,
+        state: impl FnOnce(&T) -> KeyState,
+    ) -> Result<T> {
+        let _stripe = self.stripes.lock(key);
+        let result = mutation()?;
+        self.publish(|| aof::state_entry(store, self.timer.as_ref(), key, state(&result)));
+        Ok(result)
+    }
+
+    /// Applies a flush with `flush` and publishes it
+    pub fn flush(&self, header: CacheMetaData, flush: impl FnOnce(CacheMetaData)) {
+        let _stripes = self.stripes.lock_all();
+        let entry = aof::flush_entry(self.timer.as_ref(), &header);
+        flush(header);
+        self.publish(|| entry);
+    }
+
+    /// Entries are only encoded while replicas are connected, a replica
+    /// subscribes before its snapshot is taken so it misses nothing
+    fn publish(&self, entry: impl FnOnce() -> Vec<u8>) {
+        self.offset.fetch_add(1, Ordering::Relaxed);
+        if self.entries.receiver_count() > 0 {
+            let _ = self.entries.send(Bytes::from(entry()));
+        }
+    }
+
+    fn subscribe(&self) -> broadcast::Receiver<Bytes> {
+        self.entries.subscribe()
+    }
+
+    fn set_link(&self, state: LinkState) {
+        self.link.store(state as u8, Ordering::Release);
+    }
+
+    pub fn link(&self) -> LinkState {
+        LinkState::from_u8(self.link.load(Ordering::Acquire))
+    }
+
+    pub fn stats(&self) -> ReplicationStats {
+        let role = match self.is_read_only() {
+            true => "replica",
+            false => "primary",
+        };
+        let lag_ms = match self.heartbeat.load(Ordering::Acquire) {
+            0 => None,
+            heartbeat => Some(unix_millis().saturating_sub(heartbeat)),
+        };
+        ReplicationStats {
+            role,
+            offset: self.offset.load(Ordering::Relaxed),
+            replicas: self.replicas.load(Ordering::Relaxed),
+            primary: self.primary.clone(),
+            link: self.primary.as_ref().map(|_| self.link().name()),
+            applied: self.applied.load(Ordering::Relaxed),
+            lag_ms,
+            full_syncs: self.full_syncs.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::cache::Record;
+    use crate::memcache::cli::parser::DashMapConfig;
+    use crate::memory_store::dash_map_store::DashMapMemoryStore;
+    use crate::mock::mock_server::MockSystemTimer;
+
+    #[test]
+    fn test_entries_are_published_once_replicas_subscribed() {
+        let timer = Arc::new(MockSystemTimer::new());
+        let store = DashMapMemoryStore::new(timer.clone(), DashMapConfig::default());
+        let replication = Replication::primary(timer);
+        let set = |key: &'static str| {
+            let record = Record::new(Bytes::from("value"), 0, 0, 0);
+            replication
+                .write(
+                    &store,
+                    &Bytes::from(key),
+                    || store.set(Bytes::from(key), record.clone()),
+                    |status| KeyState::written(record.clone(), status.cas),
+                )
+                .unwrap()
+        };
+        set("before");
+        let mut entries = replication.subscribe();
+        set("after");
+        replication.flush(CacheMetaData::new(0, 0, 0), |header| store.flush(header));
+
+        let entry = entries.try_recv().unwrap();
+        assert_eq!(entry[0], 1);
+        assert_eq!(&entry[3..8], b"after");
+        assert_eq!(entries.try_recv().unwrap()[0], 3);
+        assert!(entries.try_recv().is_err());
+        assert_eq!(replication.stats().offset, 3);
+        assert_eq!(replication.stats().role, "primary");
+    }
+
+    #[test]
+    fn test_replica_is_read_only_until_promoted() {
+        let timer = Arc::new(MockSystemTimer::new());
+        let primary = Replication::primary(timer.clone());
+        assert!(!primary.is_read_only());
+        assert!(!primary.promote());
+
+        let replica = Replication::replica(timer, "127.0.0.1:11212".to_string());
+        assert!(replica.is_read_only());
+        let stats = replica.stats();
+        assert_eq!(stats.role, "replica");
+        assert_eq!(stats.link, Some("down"));
+        assert_eq!(stats.lag_ms, None);
+        assert!(replica.promote());
+        assert!(!replica.promote());
+        assert!(!replica.is_read_only());
+        assert_eq!(replica.stats().role, "primary");
+    }
+
+    #[test]
+    fn test_handshake() {
+        let mut expected = [0u8; 10];
+        expected.copy_from_slice(&handshake());
+        assert!(check_handshake(&expected).is_ok());
+        expected[9] = 2;
+        assert!(check_handshake(&expected).is_err());
+        assert!(check_handshake(b"MCRSSNAP\x00\x01").is_err());
+    }
+}
@@ -0,0 +1,154 @@
+use super::{check_handshake, handshake, heartbeat, Replication, HEARTBEAT_INTERVAL};
+use crate::cache::cache::Cache;
+use crate::persistence::snapshot;
+use crate::server::timer::Timer;
+use bytes::Bytes;
+use std::io::{self, Write};
+use std::net::SocketAddr;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt, BufWriter};
+use tokio::net::{tcp::OwnedWriteHalf, TcpListener, TcpStream};
+use tokio::sync::broadcast::{
+    self,
+    error::{RecvError, TryRecvError},
+};
+use tokio_util::io::SyncIoBridge;
+use tokio_util::sync::CancellationToken;
+
+/// Accepts replicas, sends each of them a snapshot followed by the
+/// mutations published after it was taken
+pub struct ReplicationServer {
+    replication: Arc<Replication>,
+    store: Arc<dyn Cache + Send + Sync>,
+    timer: Arc<dyn Timer + Send + Sync>,
+    address: SocketAddr,
+    cancellation_token: CancellationToken,
+}
+
+impl ReplicationServer {
+    pub fn new(
+        replication: Arc<Replication>,
+        store: Arc<dyn Cache + Send + Sync>,
+        timer: Arc<dyn Timer + Send + Sync>,
+        address: SocketAddr,
+        cancellation_token: CancellationToken,
+    ) -> Self {
+        ReplicationServer {
+            replication,
+            store,
+            timer,
+            address,
+            cancellation_token,
+        }
+    }
+
+    pub async fn run(self: Arc<Self>) {
+        let listener = match TcpListener::bind(self.address).await {
+            Ok(listener) => listener,
+            Err(err) => {
+                error!("Cannot listen for replicas on {}: {}", self.address, err);
+                return;
+            }
+        };
+        info!("Listening for replicas on {}", self.address);
+        loop {
+            tokio::select! {
+                _ = self.cancellation_token.cancelled() => {
+                    info!("Replication server received cancellation signal, stopping...");
+                    break;
+                },
+                accepted = listener.accept() => match accepted {
+                    Ok((stream, peer)) => {
+                        tokio::spawn(self.clone().serve(stream, peer));
+                    }
+                    Err(err) => warn!("Cannot accept replica: {}", err),
+                },
+            }
+        }
+    }
+
+    async fn serve(self: Arc<Self>, stream: TcpStream, peer: SocketAddr) {
+        info!("Replica {} connected", peer);
+        self.replication.replicas.fetch_add(1, Ordering::Relaxed);
+        match self.stream_to(stream).await {
+            Ok(()) => info!("Replica {} disconnected", peer),
+            Err(err) => warn!("Replication to {} stopped: {}", peer, err),
+        }
+        self.replication.replicas.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    async fn stream_to(&self, stream: TcpStream) -> io::Result<()> {
+        let _ = stream.set_nodelay(true);
+        let (mut reader, writer) = stream.into_split();
+        let mut replica_handshake = [0u8; 10];
+        reader.read_exact(&mut replica_handshake).await?;
+        check_handshake(&replica_handshake)?;
+        // subscribed before the snapshot is taken, mutations done while
+        // it is written are applied on top of it
+        let mut entries = self.replication.subscribe();
+        let mut writer = BufWriter::new(writer);
+        writer.write_all(&handshake()).await?;
+        let mut writer = self.write_snapshot(writer).await?;
+        writer.flush().await?;
+
+        let mut heartbeats = tokio::time::interval(HEARTBEAT_INTERVAL);
+        loop {
+            tokio::select! {
+                _ = self.cancellation_token.cancelled() => return Ok(()),
+                entry = entries.recv() => {
+                    match entry {
+                        Ok(entry) => writer.write_all(&entry).await?,
+                        Err(RecvError::Lagged(missed)) => return Err(lagged(missed)),
+                        Err(RecvError::Closed) => return Ok(()),
+                    }
+                    write_published(&mut entries, &mut writer).await?;
+                    writer.flush().await?;
+                },
+                _ = heartbeats.tick() => {
+                    // replica is as fresh as the heartbeat once it
+                    // applied everything published before it
+                    write_published(&mut entries, &mut writer).await?;
+                    writer.write_all(&heartbeat()).await?;
+                    writer.flush().await?;
+                },
+            }
+        }
+    }
+
+    /// Store is walked on a blocking thread while it keeps serving
+    async fn write_snapshot(
+        &self,
+        writer: BufWriter<OwnedWriteHalf>,
+    ) -> io::Result<BufWriter<OwnedWriteHalf>> {
+        let store = self.store.clone();
+        let timer = self.timer.clone();
+        let mut bridge = SyncIoBridge::new(writer);
+        tokio::task::spawn_blocking(move || {
+            let items = snapshot::write_to(&mut bridge, store.as_ref(), timer.as_ref())?;
+            bridge.flush()?;
+            debug!("Snapshot of {} items sent to replica", items);
+            Ok(bridge.into_inner())
+        })
+        .await
+        .map_err(io::Error::other)?
+    }
+}
+
+/// Writes entries waiting in the channel without waiting for more
+async fn write_published(
+    entries: &mut broadcast::Receiver<Bytes>,
+    writer: &mut BufWriter<OwnedWriteHalf>,
+) -> io::Result<()> {
+    loop {
+        match entries.try_recv() {
+            Ok(entry) => writer.write_all(&entry).await?,
+            Err(TryRecvError::Lagged(missed)) => return Err(lagged(missed)),
+            Err(_) => return Ok(()),
+        }
+    }
+}
+
+fn lagged(missed: u64) -> io::Error {
+    io::Error::other(format!("replica fell {} entries behind", missed))
+}
@@ -0,0 +1,166 @@
+use super::{
+    check_handshake, handshake, LinkState, Replication, HEARTBEAT_INTERVAL, TAG_HEARTBEAT,
+};
+use crate::cache::cache::{Cache, CacheMetaData};
+use crate::persistence::aof;
+use crate::persistence::format::{read_u64, read_u8, Clock};
+use crate::persistence::snapshot;
+use crate::server::admin_signal::{AdminSignal, AdminSignalKind};
+use crate::server::timer::Timer;
+use std::io::{self, BufReader, Read, Write};
+use std::net::{Shutdown, TcpStream, ToSocketAddrs};
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+/// Link is considered broken after this long without a heartbeat
+const LINK_TIMEOUT: Duration = Duration::from_secs(5 * HEARTBEAT_INTERVAL.as_secs());
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// Keeps a replica in sync with its primary. Every time the link is
+/// (re)established the local store is replaced by a snapshot of the
+/// primary, then mutations are applied as they are streamed.
+pub struct ReplicaService {
+    replication: Arc<Replication>,
+    store: Arc<dyn Cache + Send + Sync>,
+    timer: Arc<dyn Timer + Send + Sync>,
+    primary: String,
+    cancellation_token: CancellationToken,
+    /// connection to the primary, shut down to stop replicating
+    link: Mutex<Option<TcpStream>>,
+}
+
+impl ReplicaService {
+    pub fn new(
+        replication: Arc<Replication>,
+        store: Arc<dyn Cache + Send + Sync>,
+        timer: Arc<dyn Timer + Send + Sync>,
+        primary: String,
+        cancellation_token: CancellationToken,
+    ) -> Self {
+        ReplicaService {
+            replication,
+            store,
+            timer,
+            primary,
+            cancellation_token,
+            link: Mutex::new(None),
+        }
+    }
+
+    /// Replicates on a thread of its own until the server is stopped or
+    /// the replica is promoted with SIGUSR2
+    pub async fn run(self: Arc<Self>) {
+        let service = self.clone();
+        if let Err(err) = std::thread::Builder::new()
+            .name("replica".to_string())
+            .spawn(move || service.replicate())
+        {
+            error!("Cannot start replication: {}", err);
+            return;
+        }
+        let mut promote_signal = AdminSignal::new(AdminSignalKind::Promote);
+        tokio::select! {
+            _ = self.cancellation_token.cancelled() => {
+                info!("Replica service received cancellation signal, stopping...");
+                self.disconnect();
+            },
+            _ = promote_signal.recv() => {
+                self.promote();
+            },
+        }
+    }
+
+    /// Stops replicating, the replica keeps its items and accepts writes
+    pub fn promote(&self) {
+        if self.replication.promote() {
+            info!(
+                "Promoted to primary, stopped replicating from {}",
+                self.primary
+            );
+        }
+        self.disconnect();
+    }
+
+    fn disconnect(&self) {
+        if let Some(link) = self.link.lock().unwrap().take() {
+            let _ = link.shutdown(Shutdown::Both);
+        }
+    }
+
+    fn stopped(&self) -> bool {
+        self.cancellation_token.is_cancelled() || !self.replication.is_read_only()
+    }
+
+    fn replicate(&self) {
+        while !self.stopped() {
+            self.replication.set_link(LinkState::Connecting);
+            if let Err(err) = self.sync() {
+                if !self.stopped() {
+                    warn!("Replication from {} failed: {}", self.primary, err);
+                }
+            }
+            self.replication.set_link(LinkState::Down);
+            self.disconnect();
+            if !self.stopped() {
+                std::thread::sleep(RECONNECT_DELAY);
+            }
+        }
+    }
+
+    fn connect(&self) -> io::Result<TcpStream> {
+        let mut last_err = io::Error::new(io::ErrorKind::NotFound, "primary address not resolved");
+        for address in self.primary.to_socket_addrs()? {
+            match TcpStream::connect_timeout(&address, CONNECT_TIMEOUT) {
+                Ok(stream) => return Ok(stream),
+                Err(err) => last_err = err,
+            }
+        }
+        Err(last_err)
+    }
+
+    /// Runs one link to the primary until it breaks
+    fn sync(&self) -> io::Result<()> {
+        let stream = self.connect()?;
+        stream.set_nodelay(true)?;
+        stream.set_read_timeout(Some(LINK_TIMEOUT))?;
+        *self.link.lock().unwrap() = Some(stream.try_clone()?);
+        // promoted or cancelled while connecting
+        if self.stopped() {
+            return Ok(());
+        }
+        (&stream).write_all(&handshake())?;
+        let mut reader = BufReader::new(&stream);
+        let mut primary_handshake = [0u8; 10];
+        reader.read_exact(&mut primary_handshake)?;
+        check_handshake(&primary_handshake)?;
+
+        self.replication.set_link(LinkState::Syncing);
+        // keys deleted on the primary while the link was down go too
+        self.store.flush(CacheMetaData::new(0, 0, 0));
+        let stats = snapshot::restore_from(&mut reader, self.store.as_ref(), self.timer.as_ref())?;
+        self.replication.full_syncs.fetch_add(1, Ordering::Relaxed);
+        info!(
+            "Synced {} items from {}, {} expired, {} skipped",
+            stats.restored, self.primary, stats.expired, stats.skipped
+        );
+
+        self.replication.set_link(LinkState::Streaming);
+        loop {
+            let tag = read_u8(&mut reader)?;
+            if tag == TAG_HEARTBEAT {
+                let sent = read_u64(&mut reader)?;
+                self.replication.heartbeat.store(sent, Ordering::Release);
+                continue;
+            }
+            if self.stopped() {
+                return Ok(());
+            }
+            let clock = Clock::new(self.timer.as_ref());
+            aof::apply_entry(&mut reader, tag, self.store.as_ref(), &clock)?;
+            self.replication.applied.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
@@ -0,0 +1,53 @@
+/// Signals operators send to a running server
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AdminSignalKind {
+    /// `kill -USR1 <pid>` takes a snapshot
+    Snapshot,
+    /// `kill -USR2 <pid>` promotes a replica
+    Promote,
+}
+
+impl AdminSignalKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            AdminSignalKind::Snapshot => "SIGUSR1",
+            AdminSignalKind::Promote => "SIGUSR2",
+        }
+    }
+}
+
+pub struct AdminSignal {
+    #[cfg(unix)]
+    signal: Option<tokio::signal::unix::Signal>,
+}
+
+impl AdminSignal {
+    #[cfg(unix)]
+    pub fn new(kind: AdminSignalKind) -> Self {
+        use tokio::signal::unix::{signal, SignalKind};
+        let signal_kind = match kind {
+            AdminSignalKind::Snapshot => SignalKind::user_defined1(),
+            AdminSignalKind::Promote => SignalKind::user_defined2(),
+        };
+        let signal = signal(signal_kind)
+            .map_err(|err| warn!("Cannot listen for {}: {}", kind.name(), err))
+            .ok();
+        AdminSignal { signal }
+    }
+
+    #[cfg(not(unix))]
+    pub fn new(_kind: AdminSignalKind) -> Self {
+        AdminSignal {}
+    }
+
+    /// Waits for the signal, never returns if it cannot be received
+    pub async fn recv(&mut self) {
+        #[cfg(unix)]
+        if let Some(signal) = self.signal.as_mut() {
+            if signal.recv().await.is_some() {
+                return;
+            }
+        }
+        std::future::pending::<()>().await
+    }
+}
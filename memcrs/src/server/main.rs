@@ -63,8 +63,67 @@ fn log_config(cli_config: &MemcrsdConfig) {
         log::info!("Eviction policy: {}", cfg.eviction_policy.as_str());
         log::info!("Maximum capacity: {}", cfg.max_capacity);
     }
+    if let Some(cfg) = cli_config.sieve {
+        log::info!("Eviction policy: {}", cfg.eviction_policy.as_str());
+        log::info!("Maximum capacity: {}", cfg.max_capacity);
+    }
+    if let Some(cfg) = cli_config.slab {
+        log::info!(
+            "Memory limit: {}",
+            byte_unit::Byte::from_u64(cfg.memory_limit)
+                .get_appropriate_unit(byte_unit::UnitType::Decimal)
+        );
+        log::info!("Slab growth factor: {}", cfg.slab_growth_factor);
+        log::info!(
+            "Slab page size: {}",
+            byte_unit::Byte::from_u64(cfg.slab_page_size)
+                .get_appropriate_unit(byte_unit::UnitType::Decimal)
+        );
+        log::info!("Slab huge pages: {}", cfg.slab_huge_pages);
+        if cli_config.item_size_limit > cfg.slab_page_size {
+            log::warn!("Items larger than slab page size will be rejected");
+        }
+    }
 
     log::info!("Runtime type: {}", cli_config.runtime_type.as_str());
+    if cli_config.partition_keyspace {
+        log::info!(
+            "Keyspace partitioned between {} workers",
+            cli_config.threads
+        );
+    }
+    if let Some(path) = &cli_config.snapshot_path {
+        log::info!("Snapshot path: {}", path.display());
+        if cli_config.snapshot_interval > 0 {
+            log::info!("Snapshot interval: {}s", cli_config.snapshot_interval);
+        }
+    }
+    if let Some(path) = &cli_config.aof_path {
+        log::info!("Mutation log: {}", path.display());
+        log::info!("Mutation log fsync: {}", cli_config.aof_fsync.as_str());
+    }
+    if let Some(path) = &cli_config.restore_from {
+        log::info!("Restore from: {}", path.display());
+    }
+    if let Some(algorithm) = &cli_config.compression {
+        log::info!(
+            "Compression: {}, values of at least {}",
+            algorithm.as_str(),
+            byte_unit::Byte::from_u64(cli_config.compression_min_size)
+                .get_appropriate_unit(byte_unit::UnitType::Binary)
+        );
+    }
+    if cli_config.cas_node_id != 0 {
+        log::info!("CAS node id: {}", cli_config.cas_node_id);
+    }
+    if let Some(path) = &cli_config.ext_path {
+        log::info!(
+            "Page file: {}, values larger than {} moved out of memory",
+            path.display(),
+            byte_unit::Byte::from_u64(cli_config.ext_item_size)
+                .get_appropriate_unit(byte_unit::UnitType::Binary)
+        );
+    }
     log::info!(
         "Max item size: {}",
         byte_unit::Byte::from_u64(cli_config.item_size_limit)
@@ -77,4 +136,15 @@ fn log_config(cli_config: &MemcrsdConfig) {
             cli_config.store_engine.as_str()
         );
     }
+    if let Some(cfg) = cli_config
+        .dash_map
+        .as_ref()
+        .filter(|cfg| cfg.reject_when_full)
+    {
+        log::warn!(
+            "Writes are rejected once memory limit of {} is reached.",
+            byte_unit::Byte::from_u64(cfg.memory_limit)
+                .get_appropriate_unit(byte_unit::UnitType::Decimal)
+        );
+    }
 }
@@ -1,2 +1,3 @@
+pub mod admin_signal;
 pub mod main;
 pub mod timer;
@@ -1,28 +1,68 @@
 use log::debug;
 use std::sync::atomic::{AtomicU64, Ordering};
-use std::time::Duration;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use tokio::time::{interval_at, Instant};
 use tokio_util::sync::CancellationToken;
 
+/// Expiration times larger than 30 days are absolute unix times,
+/// as in memcached
+pub const MAX_RELATIVE_EXPIRATION: u32 = 60 * 60 * 24 * 30;
+
 pub trait Timer {
+    /// Seconds since the server started, monotonic
     fn timestamp(&self) -> u32;
+
+    /// Unix time at which `timestamp` was 0
+    fn process_started(&self) -> u64;
+
+    /// Current unix time in seconds
+    fn unix_timestamp(&self) -> u64 {
+        self.process_started() + self.timestamp() as u64
+    }
 }
 
 pub trait SetableTimer {
     fn add_second(&self);
 }
 
-#[derive(Default)]
+/// Timestamp at startup, timestamp 1 is always in the past and is used
+/// for items which are already expired
+const START_TIMESTAMP: u64 = 2;
+/// Larger difference between system clock and process start plus
+/// timestamp is treated as a clock jump
+const MAX_CLOCK_DRIFT: u64 = 2;
+
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
+
+/// Server clock, timestamps are counted from a monotonic clock and unix
+/// time is derived from the wall time recorded at startup.
+///
+/// Like memcached's `process_started`, the start time is moved when the
+/// system clock jumps (NTP step, manual change), so unix time keeps
+/// following the system clock while timestamps, and relative expirations
+/// based on them, are not affected.
 pub struct SystemTimer {
     seconds: AtomicU64,
+    process_started: AtomicU64,
     cancellation_token: CancellationToken,
 }
 
+impl Default for SystemTimer {
+    fn default() -> Self {
+        SystemTimer::new(CancellationToken::new())
+    }
+}
+
 impl SystemTimer {
     pub fn new(cancellation_token: CancellationToken) -> Self {
         debug!("Creating system timer");
         SystemTimer {
-            seconds: AtomicU64::new(0),
+            seconds: AtomicU64::new(START_TIMESTAMP),
+            process_started: AtomicU64::new(unix_now().saturating_sub(START_TIMESTAMP)),
             cancellation_token,
         }
     }
@@ -37,18 +77,39 @@ impl SystemTimer {
                     break;
                 },
                 _ = interval.tick() => {
-                    self.add_second();
+                    self.tick(start.elapsed().as_secs(), unix_now());
                     trace!("Server tick: {}", self.timestamp());
                 },
             }
         }
     }
+
+    /// Updates timestamp from monotonic time elapsed since `run` started
+    /// and moves process start if the system clock jumped
+    fn tick(&self, elapsed: u64, unix_now: u64) {
+        let seconds = START_TIMESTAMP + elapsed;
+        self.seconds.fetch_max(seconds, Ordering::Release);
+        let timestamp = self.timestamp() as u64;
+        let process_started = self.process_started();
+        let expected = unix_now.saturating_sub(timestamp);
+        if expected.abs_diff(process_started) > MAX_CLOCK_DRIFT {
+            warn!(
+                "System clock jumped by {} seconds, adjusting process start time",
+                expected as i64 - process_started as i64
+            );
+            self.process_started.store(expected, Ordering::Release);
+        }
+    }
 }
 
 impl Timer for SystemTimer {
     fn timestamp(&self) -> u32 {
         self.seconds.load(Ordering::Acquire) as u32
     }
+
+    fn process_started(&self) -> u64 {
+        self.process_started.load(Ordering::Acquire)
+    }
 }
 
 impl SetableTimer for SystemTimer {
@@ -67,7 +128,18 @@ mod tests {
     async fn test_initial_timestamp() {
         let cancellation_token = CancellationToken::new();
         let timer = SystemTimer::new(cancellation_token.clone());
-        assert_eq!(timer.timestamp(), 0);
+        assert_eq!(timer.timestamp(), START_TIMESTAMP as u32);
+    }
+
+    #[tokio::test]
+    async fn test_unix_timestamp_follows_system_clock() {
+        let timer = SystemTimer::new(CancellationToken::new());
+        let now = unix_now();
+        assert!(timer.unix_timestamp().abs_diff(now) <= 1);
+        assert_eq!(
+            timer.process_started(),
+            timer.unix_timestamp() - START_TIMESTAMP
+        );
     }
 
     #[tokio::test]
@@ -75,9 +147,9 @@ mod tests {
         let cancellation_token = CancellationToken::new();
         let timer = SystemTimer::new(cancellation_token.clone());
         timer.add_second();
-        assert_eq!(timer.timestamp(), 1);
+        assert_eq!(timer.timestamp(), START_TIMESTAMP as u32 + 1);
         timer.add_second();
-        assert_eq!(timer.timestamp(), 2);
+        assert_eq!(timer.timestamp(), START_TIMESTAMP as u32 + 2);
     }
 
     #[tokio::test]
@@ -95,7 +167,29 @@ mod tests {
         });
 
         handle.await.unwrap();
-        assert!(timer.timestamp() >= 2);
+        assert!(timer.timestamp() > START_TIMESTAMP as u32);
+    }
+
+    #[test]
+    fn test_clock_jump_moves_process_start() {
+        let timer = SystemTimer::new(CancellationToken::new());
+        let started = timer.process_started();
+        let now = started + START_TIMESTAMP;
+
+        // tick jitter is not a jump
+        timer.tick(10, now + 11);
+        assert_eq!(timer.process_started(), started);
+        assert_eq!(timer.unix_timestamp(), now + 10);
+
+        timer.tick(11, now + 3611);
+        assert_eq!(timer.timestamp(), START_TIMESTAMP as u32 + 11);
+        assert_eq!(timer.process_started(), started + 3600);
+        assert_eq!(timer.unix_timestamp(), now + 3611);
+
+        // clock going back does not move timestamp back
+        timer.tick(12, now + 12);
+        assert_eq!(timer.timestamp(), START_TIMESTAMP as u32 + 12);
+        assert_eq!(timer.process_started(), started);
     }
 
     #[derive(Default)]
@@ -107,6 +201,10 @@ mod tests {
         fn timestamp(&self) -> u32 {
             *self.time.blocking_lock()
         }
+
+        fn process_started(&self) -> u64 {
+            0
+        }
     }
 
     impl SetableTimer for MockTimer {
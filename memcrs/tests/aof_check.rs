@@ -0,0 +1,44 @@
+//procspawn::enable_test_support!();
+mod common;
+use memcrs::memory_store::StoreEngine;
+use test_case::test_case;
+
+#[test_case(common::create_moka_engine() ; "moka_backend")]
+#[test_case(common::create_dashmap_engine() ; "dash_map_backend")]
+#[test_case(common::create_slab_engine() ; "slab_backend")]
+#[test_case(common::create_sieve_engine() ; "sieve_backend")]
+fn aof_replay_check(engine: StoreEngine) {
+    let path = std::env::temp_dir().join(format!("memcrs-aof-{:?}-{}", engine, std::process::id()));
+    let path = path.to_str().unwrap();
+    let _ = std::fs::remove_file(path);
+
+    let mut params_builder: common::MemcrsdServerParamsBuilder =
+        common::MemcrsdServerParamsBuilder::new(engine);
+    params_builder.with_aof_path(path);
+    let server_handle = common::spawn_server(params_builder);
+    let client = memcache::connect(server_handle.get_connection_string()).unwrap();
+    client.set("set", "value", 0).unwrap();
+    client.set("append", "head", 0).unwrap();
+    client.append("append", "-tail").unwrap();
+    client.set("counter", 10, 0).unwrap();
+    client.increment("counter", 5).unwrap();
+    client.set("deleted", "value", 0).unwrap();
+    client.delete("deleted").unwrap();
+    drop(server_handle);
+
+    let mut params_builder: common::MemcrsdServerParamsBuilder =
+        common::MemcrsdServerParamsBuilder::new(engine);
+    params_builder.with_aof_path(path);
+    let server_handle = common::spawn_server(params_builder);
+    let client = memcache::connect(server_handle.get_connection_string()).unwrap();
+    let value: Option<String> = client.get("set").unwrap();
+    assert_eq!(value, Some(String::from("value")));
+    let value: Option<String> = client.get("append").unwrap();
+    assert_eq!(value, Some(String::from("head-tail")));
+    let value: Option<u64> = client.get("counter").unwrap();
+    assert_eq!(value, Some(15));
+    let value: Option<String> = client.get("deleted").unwrap();
+    assert_eq!(value, None);
+    drop(server_handle);
+    std::fs::remove_file(path).unwrap();
+}
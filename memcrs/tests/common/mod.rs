@@ -9,12 +9,23 @@ mod random_port;
 pub use multi_thread_server::spawn_server;
 pub use params_builder::MemcrsdServerParamsBuilder;
 
+#[allow(dead_code)]
 pub fn create_moka_engine() -> StoreEngine {
-    return StoreEngine::Moka;
+    StoreEngine::Moka
 }
 
 pub fn create_dashmap_engine() -> StoreEngine {
-    return StoreEngine::DashMap;
+    StoreEngine::DashMap
+}
+
+#[allow(dead_code)]
+pub fn create_slab_engine() -> StoreEngine {
+    StoreEngine::Slab
+}
+
+#[allow(dead_code)]
+pub fn create_sieve_engine() -> StoreEngine {
+    StoreEngine::Sieve
 }
 
 #[allow(dead_code)]
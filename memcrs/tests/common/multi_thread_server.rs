@@ -1,14 +1,8 @@
 use std::process;
 
 use memcrs::{
-    memcache::{
-        self,
-        builder::EngineStoreConfig,
-        cli::parser::{DashMapConfig, MokaConfig},
-    },
-    memcache_server::{
-        runtime_builder::start_memcrs_server_with_ctxt, server_context::ServerContext,
-    },
+    memcache,
+    memcache_server::runtime_builder::{create_server_context, start_memcrs_server_with_ctxt},
 };
 use nix::errno::Errno;
 use tokio_util::sync::CancellationToken;
@@ -43,10 +37,28 @@ impl MemcrsdMultiThreadTestServer {
     }
 
     pub fn get_connection_string(&self) -> String {
-        String::from(format!(
+        format!(
             "memcache://127.0.0.1:{}?timeout=5&tcp_nodelay=true&protocol=binary",
             self.port
-        ))
+        )
+    }
+
+    #[allow(dead_code)]
+    pub fn get_text_connection_string(&self) -> String {
+        format!(
+            "memcache://127.0.0.1:{}?timeout=5&tcp_nodelay=true&protocol=ascii",
+            self.port
+        )
+    }
+
+    #[allow(dead_code)]
+    pub fn get_address(&self) -> String {
+        format!("127.0.0.1:{}", self.port)
+    }
+
+    #[allow(dead_code)]
+    pub fn get_replication_address(&self) -> String {
+        format!("127.0.0.1:{}", self.port + 1)
     }
 }
 
@@ -69,17 +81,7 @@ fn spawn_server_args(args: Vec<String>) -> MemcrsdMultiThreadTestServer {
             process::exit(1);
         }
     };
-    // From a MokaConfig
-    let engine_config = match config.store_engine {
-        memcrs::memory_store::StoreEngine::DashMap => {
-            EngineStoreConfig::DashMap(DashMapConfig::default())
-        }
-        memcrs::memory_store::StoreEngine::Moka => EngineStoreConfig::Moka(MokaConfig::default()),
-    };
-
-    let store_config =
-        memcache::builder::MemcacheStoreConfig::new(config.store_engine, engine_config);
-    let ctxt = ServerContext::get_default_server_context(store_config);
+    let ctxt = create_server_context(&config);
     let cancellation_token = ctxt.cancellation_token();
     let port = config.port;
     let handle = std::thread::spawn(move || start_memcrs_server_with_ctxt(config, ctxt));
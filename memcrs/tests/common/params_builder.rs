@@ -4,14 +4,38 @@ pub struct MemcrsdServerParamsBuilder {
     engine: StoreEngine,
     runtime: RuntimeType,
     port: u16,
+    partition_keyspace: bool,
+    threads: Option<usize>,
+    snapshot_path: Option<String>,
+    restore_from: Option<String>,
+    aof_path: Option<String>,
+    ext_path: Option<String>,
+    compression: Option<String>,
+    replication: bool,
+    replica_of: Option<String>,
+    proxy_backends: Vec<String>,
+    shadow_to: Option<String>,
+    warm_from: Option<String>,
 }
 
 impl MemcrsdServerParamsBuilder {
     pub fn new(engine: StoreEngine) -> MemcrsdServerParamsBuilder {
         MemcrsdServerParamsBuilder {
-            engine: engine,
+            engine,
             runtime: RuntimeType::CurrentThread,
             port: 11211,
+            partition_keyspace: false,
+            threads: None,
+            snapshot_path: None,
+            restore_from: None,
+            aof_path: None,
+            ext_path: None,
+            compression: None,
+            replication: false,
+            replica_of: None,
+            proxy_backends: Vec::new(),
+            shadow_to: None,
+            warm_from: None,
         }
     }
 
@@ -21,6 +45,82 @@ impl MemcrsdServerParamsBuilder {
         self
     }
 
+    #[allow(dead_code)]
+    pub fn with_partition_keyspace(&mut self) -> &mut Self {
+        self.partition_keyspace = true;
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn with_threads(&mut self, threads: usize) -> &mut Self {
+        self.threads = Some(threads);
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn with_snapshot_path(&mut self, path: &str) -> &mut Self {
+        self.snapshot_path = Some(String::from(path));
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn with_restore_from(&mut self, path: &str) -> &mut Self {
+        self.restore_from = Some(String::from(path));
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn with_aof_path(&mut self, path: &str) -> &mut Self {
+        self.aof_path = Some(String::from(path));
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn with_ext_path(&mut self, path: &str) -> &mut Self {
+        self.ext_path = Some(String::from(path));
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn with_compression(&mut self, algorithm: &str) -> &mut Self {
+        self.compression = Some(String::from(algorithm));
+        self
+    }
+
+    #[allow(dead_code)]
+    /// Replicas connect to the port following the server port
+    pub fn with_replication(&mut self) -> &mut Self {
+        self.replication = true;
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn with_replica_of(&mut self, primary: &str) -> &mut Self {
+        self.replica_of = Some(String::from(primary));
+        self
+    }
+
+    #[allow(dead_code)]
+    /// Runs in proxy mode routing keys to `backends`
+    pub fn with_proxy(&mut self, backends: &[String]) -> &mut Self {
+        self.proxy_backends = backends.to_vec();
+        self
+    }
+
+    #[allow(dead_code)]
+    /// Mirrors mutations to the server at `address`
+    pub fn with_shadow_to(&mut self, address: &str) -> &mut Self {
+        self.shadow_to = Some(String::from(address));
+        self
+    }
+
+    #[allow(dead_code)]
+    /// Copies items of the server at `peer` on startup
+    pub fn with_warm_from(&mut self, peer: &str) -> &mut Self {
+        self.warm_from = Some(String::from(peer));
+        self
+    }
+
     pub fn with_port(&mut self, port: u16) -> &mut Self {
         self.port = port;
         self
@@ -38,6 +138,14 @@ impl MemcrsdServerParamsBuilder {
                 result.push(String::from("--store-engine"));
                 result.push(String::from("moka"));
             }
+            StoreEngine::Sieve => {
+                result.push(String::from("--store-engine"));
+                result.push(String::from("sieve"));
+            }
+            StoreEngine::Slab => {
+                result.push(String::from("--store-engine"));
+                result.push(String::from("slab"));
+            }
         }
 
         match self.runtime {
@@ -51,6 +159,77 @@ impl MemcrsdServerParamsBuilder {
             }
         }
 
+        if let Some(threads) = self.threads {
+            result.push(String::from("--threads"));
+            result.push(threads.to_string());
+        }
+
+        if self.partition_keyspace {
+            result.push(String::from("--partition-keyspace"));
+        }
+
+        if let Some(path) = &self.snapshot_path {
+            result.push(String::from("--snapshot-path"));
+            result.push(path.clone());
+        }
+
+        if let Some(path) = &self.restore_from {
+            result.push(String::from("--restore-from"));
+            result.push(path.clone());
+        }
+
+        if let Some(path) = &self.aof_path {
+            result.push(String::from("--aof-path"));
+            result.push(path.clone());
+            result.push(String::from("--aof-fsync"));
+            result.push(String::from("always"));
+        }
+
+        if let Some(path) = &self.ext_path {
+            result.push(String::from("--ext-path"));
+            result.push(path.clone());
+            result.push(String::from("--ext-item-size"));
+            result.push(String::from("64B"));
+            result.push(String::from("--ext-size"));
+            result.push(String::from("4MiB"));
+            result.push(String::from("--ext-page-size"));
+            result.push(String::from("1MiB"));
+        }
+
+        if let Some(algorithm) = &self.compression {
+            result.push(String::from("--compression"));
+            result.push(algorithm.clone());
+        }
+
+        if self.replication {
+            result.push(String::from("--replication-port"));
+            result.push((self.port + 1).to_string());
+        }
+
+        if let Some(primary) = &self.replica_of {
+            result.push(String::from("--replica-of"));
+            result.push(primary.clone());
+        }
+
+        if !self.proxy_backends.is_empty() {
+            result.push(String::from("--mode"));
+            result.push(String::from("proxy"));
+            for backend in &self.proxy_backends {
+                result.push(String::from("--backend"));
+                result.push(backend.clone());
+            }
+        }
+
+        if let Some(address) = &self.shadow_to {
+            result.push(String::from("--shadow-to"));
+            result.push(address.clone());
+        }
+
+        if let Some(peer) = &self.warm_from {
+            result.push(String::from("--warm-from"));
+            result.push(peer.clone());
+        }
+
         result.push(String::from("--port"));
         result.push(self.port.to_string());
         // result.push(String::from("-vvv"));
@@ -36,10 +36,10 @@ impl MemcrsdTestServer {
     }
 
     pub fn get_connection_string(&self) -> String {
-        String::from(format!(
+        format!(
             "memcache://127.0.0.1:{}?timeout=5&tcp_nodelay=true&protocol=binary",
             self.port
-        ))
+        )
     }
 }
 
@@ -59,6 +59,6 @@ pub fn spawn_server(mut params: MemcrsdServerParamsBuilder) -> MemcrsdTestServer
     let port = pseudoRanomPort.lock().unwrap().get_next_port();
     params.with_port(port);
     let args = params.build();
-    let handle = procspawn::spawn(args, |args| server::main::run(args));
+    let handle = procspawn::spawn(args, server::main::run);
     MemcrsdTestServer::new(handle, port)
 }
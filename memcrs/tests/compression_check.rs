@@ -0,0 +1,27 @@
+//procspawn::enable_test_support!();
+mod common;
+use memcrs::memory_store::StoreEngine;
+use test_case::test_case;
+
+#[test_case(common::create_moka_engine(), "zstd" ; "moka_backend_zstd")]
+#[test_case(common::create_dashmap_engine(), "zstd" ; "dash_map_backend_zstd")]
+#[test_case(common::create_slab_engine(), "lz4" ; "slab_backend_lz4")]
+#[test_case(common::create_sieve_engine(), "lz4" ; "sieve_backend_lz4")]
+fn compression_check(engine: StoreEngine, algorithm: &str) {
+    let mut params_builder: common::MemcrsdServerParamsBuilder =
+        common::MemcrsdServerParamsBuilder::new(engine);
+    params_builder.with_compression(algorithm);
+    let server_handle = common::spawn_server(params_builder);
+    let client = memcache::connect(server_handle.get_connection_string()).unwrap();
+    let value = r#"{"id":1234,"name":"memcrs","tags":["cache","rust"]}"#.repeat(100);
+    client.set("json", value.as_str(), 0).unwrap();
+    let result: Option<String> = client.get("json").unwrap();
+    assert_eq!(result, Some(value));
+
+    let stats = client.stats().unwrap();
+    let (_server, stats) = &stats[0];
+    assert_eq!(stats.get("compression_algorithm").unwrap(), algorithm);
+    assert_eq!(stats.get("compression_values").unwrap(), "1");
+    let ratio: f64 = stats.get("compression_ratio").unwrap().parse().unwrap();
+    assert!(ratio > 2.0);
+}
@@ -5,6 +5,8 @@ use test_case::test_case;
 
 #[test_case(common::create_moka_engine() ; "moka_backend")]
 #[test_case(common::create_dashmap_engine() ; "dash_map_backend")]
+#[test_case(common::create_slab_engine() ; "slab_backend")]
+#[test_case(common::create_sieve_engine() ; "sieve_backend")]
 fn delete_check(engine: StoreEngine) {
     let params_builder: common::MemcrsdServerParamsBuilder =
         common::MemcrsdServerParamsBuilder::new(engine);
@@ -32,7 +34,7 @@ fn delete_check(engine: StoreEngine) {
 
     match client.delete("bar") {
         Ok(removed) => {
-            assert!(removed == false);
+            assert!(!removed);
         }
         Err(_err) => {
             unreachable!()
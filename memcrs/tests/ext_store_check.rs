@@ -0,0 +1,32 @@
+//procspawn::enable_test_support!();
+mod common;
+use memcrs::memory_store::StoreEngine;
+use test_case::test_case;
+
+#[test_case(common::create_moka_engine() ; "moka_backend")]
+#[test_case(common::create_dashmap_engine() ; "dash_map_backend")]
+#[test_case(common::create_slab_engine() ; "slab_backend")]
+#[test_case(common::create_sieve_engine() ; "sieve_backend")]
+fn ext_store_check(engine: StoreEngine) {
+    let path = std::env::temp_dir().join(format!("memcrs-ext-{:?}-{}", engine, std::process::id()));
+    let path = path.to_str().unwrap();
+
+    let mut params_builder: common::MemcrsdServerParamsBuilder =
+        common::MemcrsdServerParamsBuilder::new(engine);
+    params_builder.with_ext_path(path);
+    let server_handle = common::spawn_server(params_builder);
+    let client = memcache::connect(server_handle.get_connection_string()).unwrap();
+    let large = "a".repeat(10 * 1024);
+    client.set("large", large.as_str(), 0).unwrap();
+    client.set("small", "value", 0).unwrap();
+    client.append("large", "-tail").unwrap();
+    let value: Option<String> = client.get("large").unwrap();
+    assert_eq!(value, Some(format!("{}-tail", large)));
+    let value: Option<String> = client.get("small").unwrap();
+    assert_eq!(value, Some(String::from("value")));
+    client.delete("large").unwrap();
+    let value: Option<String> = client.get("large").unwrap();
+    assert_eq!(value, None);
+    drop(server_handle);
+    std::fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,59 @@
+mod common;
+use memcrs::memory_store::StoreEngine;
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use test_case::test_case;
+
+/// Sends `command` and reads the reply up to its END line
+fn metadump(stream: &mut TcpStream, command: &[u8]) -> Vec<String> {
+    stream.write_all(command).unwrap();
+    let mut reply = Vec::new();
+    let mut buffer = vec![0; 64 * 1024];
+    while !reply.ends_with(b"END\r\n") {
+        let length = stream.read(&mut buffer).unwrap();
+        assert!(length > 0);
+        reply.extend_from_slice(&buffer[..length]);
+    }
+    let reply = String::from_utf8(reply).unwrap();
+    reply
+        .strip_suffix("END\r\n")
+        .unwrap()
+        .lines()
+        .map(String::from)
+        .collect()
+}
+
+#[test_case(common::create_moka_engine() ; "moka_backend")]
+#[test_case(common::create_dashmap_engine() ; "dash_map_backend")]
+#[test_case(common::create_slab_engine() ; "slab_backend")]
+#[test_case(common::create_sieve_engine() ; "sieve_backend")]
+fn metadump_check(engine: StoreEngine) {
+    let server_handle = common::spawn_server(common::MemcrsdServerParamsBuilder::new(engine));
+    let client = memcache::connect(server_handle.get_connection_string()).unwrap();
+    for idx in 0..2500 {
+        client.set(&format!("key{}", idx), idx, 0).unwrap();
+    }
+    client.set("user:a b", "value", 0).unwrap();
+
+    // more items than one page of the scan
+    let mut stream = TcpStream::connect(server_handle.get_address()).unwrap();
+    let lines = metadump(&mut stream, b"lru_crawler metadump all\r\n");
+    assert_eq!(lines.len(), 2501);
+    let lines = metadump(&mut stream, b"lru_crawler metadump all user:\r\n");
+    assert_eq!(lines.len(), 1);
+    let fields: Vec<&str> = lines[0].split(' ').collect();
+    assert_eq!(&fields[..3], ["key=user%3Aa%20b", "exp=-1", "la=0"]);
+    assert!(fields[3].starts_with("cas="));
+    assert_eq!(&fields[4..6], ["fetch=no", "cls=0"]);
+    assert!(fields[6].starts_with("size="));
+
+    // text clients share items with binary ones
+    let text = memcache::connect(server_handle.get_text_connection_string()).unwrap();
+    let value: Option<u32> = text.get("key7").unwrap();
+    assert_eq!(value, Some(7));
+    text.set("text", "set", 0).unwrap();
+    let values: HashMap<String, String> = client.gets(&["text"]).unwrap();
+    assert_eq!(values.get("text"), Some(&String::from("set")));
+    assert_eq!(text.increment("key1", 10).unwrap(), 11);
+}
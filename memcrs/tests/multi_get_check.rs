@@ -0,0 +1,33 @@
+mod common;
+use memcrs::memory_store::StoreEngine;
+use std::collections::HashMap;
+use test_case::test_case;
+
+#[test_case(common::create_moka_engine() ; "moka_backend")]
+#[test_case(common::create_dashmap_engine() ; "dash_map_backend")]
+#[test_case(common::create_slab_engine() ; "slab_backend")]
+#[test_case(common::create_sieve_engine() ; "sieve_backend")]
+fn multi_get_check(engine: StoreEngine) {
+    let params_builder: common::MemcrsdServerParamsBuilder =
+        common::MemcrsdServerParamsBuilder::new(engine);
+    let server_handle = common::spawn_server(params_builder);
+    let client = memcache::connect(server_handle.get_connection_string()).unwrap();
+    client.flush().unwrap();
+
+    // longer than a single quiet get batch
+    let keys: Vec<String> = (0..1500).map(|idx| format!("key{}", idx)).collect();
+    for key in keys.iter().step_by(2) {
+        client.set(key, format!("value-{}", key), 0).unwrap();
+    }
+
+    let lookup: Vec<&str> = keys.iter().map(|key| key.as_str()).collect();
+    let result: HashMap<String, String> = client.gets(&lookup).unwrap();
+    assert_eq!(result.len(), 750);
+    for key in keys.iter().step_by(2) {
+        assert_eq!(result[key], format!("value-{}", key));
+    }
+
+    // connection keeps working after the run
+    let value: Option<String> = client.get("key0").unwrap();
+    assert_eq!(value, Some(String::from("value-key0")));
+}
@@ -0,0 +1,42 @@
+//procspawn::enable_test_support!();
+mod common;
+use std::collections::HashMap;
+
+// partitions are dash-map like stores owned by worker threads
+#[test]
+fn partition_keyspace_check() {
+    let mut params_builder: common::MemcrsdServerParamsBuilder =
+        common::MemcrsdServerParamsBuilder::new(common::create_dashmap_engine());
+    params_builder.with_threads(4).with_partition_keyspace();
+    let server_handle = common::spawn_server(params_builder);
+    let client = memcache::connect(server_handle.get_connection_string()).unwrap();
+    client.flush().unwrap();
+
+    // keys are spread over all partitions, most of them owned by other workers
+    for idx in 0..100 {
+        client.set(&format!("key{}", idx), idx, 0).unwrap();
+    }
+    for idx in 0..100 {
+        let value: Option<u32> = client.get(&format!("key{}", idx)).unwrap();
+        assert_eq!(value, Some(idx));
+    }
+
+    // multi-get is sent as one quiet get run, split between owners
+    let keys: Vec<String> = (0..100).map(|idx| format!("key{}", idx)).collect();
+    let keys: Vec<&str> = keys.iter().map(String::as_str).collect();
+    let values: HashMap<String, u32> = client.gets(&keys).unwrap();
+    assert_eq!(values.len(), 100);
+    for idx in 0..100 {
+        assert_eq!(values[&format!("key{}", idx)], idx);
+    }
+
+    client.increment("key1", 10).unwrap();
+    let value: Option<u32> = client.get("key1").unwrap();
+    assert_eq!(value, Some(11));
+
+    client.flush().unwrap();
+    for idx in 0..100 {
+        let value: Option<u32> = client.get(&format!("key{}", idx)).unwrap();
+        assert_eq!(value, None);
+    }
+}
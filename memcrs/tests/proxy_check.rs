@@ -0,0 +1,135 @@
+mod common;
+use memcrs::memory_store::StoreEngine;
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use test_case::test_case;
+
+#[test_case(common::create_moka_engine() ; "moka_backend")]
+#[test_case(common::create_dashmap_engine() ; "dash_map_backend")]
+#[test_case(common::create_slab_engine() ; "slab_backend")]
+#[test_case(common::create_sieve_engine() ; "sieve_backend")]
+fn proxy_check(engine: StoreEngine) {
+    let first_handle = common::spawn_server(common::MemcrsdServerParamsBuilder::new(engine));
+    let second_handle = common::spawn_server(common::MemcrsdServerParamsBuilder::new(engine));
+    let mut params_builder = common::MemcrsdServerParamsBuilder::new(engine);
+    params_builder.with_proxy(&[first_handle.get_address(), second_handle.get_address()]);
+    let proxy_handle = common::spawn_server(params_builder);
+    let proxy = memcache::connect(proxy_handle.get_connection_string()).unwrap();
+
+    let keys: Vec<String> = (0..200).map(|idx| format!("key{}", idx)).collect();
+    for (idx, key) in keys.iter().enumerate() {
+        proxy.set(key, idx as u32, 0).unwrap();
+    }
+    for (idx, key) in keys.iter().enumerate() {
+        let value: Option<u32> = proxy.get(key).unwrap();
+        assert_eq!(value, Some(idx as u32));
+    }
+
+    // every key is stored by exactly one backend
+    let lookup: Vec<&str> = keys.iter().map(|key| key.as_str()).collect();
+    let first = memcache::connect(first_handle.get_connection_string()).unwrap();
+    let second = memcache::connect(second_handle.get_connection_string()).unwrap();
+    let on_first: HashMap<String, u32> = first.gets(&lookup).unwrap();
+    let on_second: HashMap<String, u32> = second.gets(&lookup).unwrap();
+    assert!(!on_first.is_empty() && !on_second.is_empty());
+    assert_eq!(on_first.len() + on_second.len(), keys.len());
+
+    // multi-key pipelines are split between the backends
+    let result: HashMap<String, u32> = proxy.gets(&lookup).unwrap();
+    assert_eq!(result.len(), keys.len());
+    assert_eq!(proxy.increment("key1", 10).unwrap(), 11);
+    proxy.delete("key2").unwrap();
+    let value: Option<u32> = proxy.get("key2").unwrap();
+    assert_eq!(value, None);
+
+    // keys of a backend which is down go to the other one
+    drop(second_handle);
+    let result: HashMap<String, u32> = proxy.gets(&lookup).unwrap();
+    assert_eq!(
+        result.len(),
+        on_first.len() - on_first.contains_key("key2") as usize
+    );
+    let moved = on_second.keys().next().unwrap();
+    proxy.set(moved, "moved", 0).unwrap();
+    let value: Option<String> = proxy.get(moved).unwrap();
+    assert_eq!(value, Some(String::from("moved")));
+    let value: Option<String> = first.get(moved).unwrap();
+    assert_eq!(value, Some(String::from("moved")));
+}
+
+#[test_case(common::create_moka_engine() ; "moka_backend")]
+#[test_case(common::create_dashmap_engine() ; "dash_map_backend")]
+#[test_case(common::create_slab_engine() ; "slab_backend")]
+#[test_case(common::create_sieve_engine() ; "sieve_backend")]
+fn proxy_text_check(engine: StoreEngine) {
+    let first_handle = common::spawn_server(common::MemcrsdServerParamsBuilder::new(engine));
+    let second_handle = common::spawn_server(common::MemcrsdServerParamsBuilder::new(engine));
+    let mut params_builder = common::MemcrsdServerParamsBuilder::new(engine);
+    params_builder.with_proxy(&[first_handle.get_address(), second_handle.get_address()]);
+    let proxy_handle = common::spawn_server(params_builder);
+    let proxy = memcache::connect(proxy_handle.get_text_connection_string()).unwrap();
+
+    let keys: Vec<String> = (0..200).map(|idx| format!("key{}", idx)).collect();
+    for (idx, key) in keys.iter().enumerate() {
+        proxy.set(key, idx as u32, 0).unwrap();
+    }
+    let lookup: Vec<&str> = keys.iter().map(|key| key.as_str()).collect();
+    let result: HashMap<String, u32> = proxy.gets(&lookup).unwrap();
+    assert_eq!(result.len(), keys.len());
+    assert_eq!(result.get("key7"), Some(&7));
+
+    // text and binary clients see the same items on the backends
+    let first = memcache::connect(first_handle.get_connection_string()).unwrap();
+    let second = memcache::connect(second_handle.get_connection_string()).unwrap();
+    let on_first: HashMap<String, u32> = first.gets(&lookup).unwrap();
+    let on_second: HashMap<String, u32> = second.gets(&lookup).unwrap();
+    assert!(!on_first.is_empty() && !on_second.is_empty());
+    assert_eq!(on_first.len() + on_second.len(), keys.len());
+
+    assert_eq!(proxy.increment("key1", 10).unwrap(), 11);
+    assert_eq!(proxy.decrement("key1", 5).unwrap(), 6);
+    assert!(proxy.increment("missing", 1).is_err());
+    proxy.append("key3", "0").unwrap();
+    proxy.prepend("key3", "1").unwrap();
+    let value: Option<String> = proxy.get("key3").unwrap();
+    assert_eq!(value, Some(String::from("130")));
+
+    let (_, _, cas) = proxy
+        .get::<(Vec<u8>, u32, Option<u64>)>("key4")
+        .unwrap()
+        .unwrap();
+    assert!(proxy.cas("key4", "swapped", 0, cas.unwrap()).unwrap());
+    assert!(!proxy.cas("key4", "stale", 0, cas.unwrap()).unwrap());
+    let value: Option<String> = proxy.get("key4").unwrap();
+    assert_eq!(value, Some(String::from("swapped")));
+
+    assert!(proxy.delete("key2").unwrap());
+    assert!(!proxy.delete("key2").unwrap());
+    let value: Option<u32> = proxy.get("key2").unwrap();
+    assert_eq!(value, None);
+
+    // replies the client library does not tell apart
+    let mut stream = TcpStream::connect(proxy_handle.get_address()).unwrap();
+    let mut exchange = |request: &[u8]| {
+        stream.write_all(request).unwrap();
+        let mut reply = vec![0; 1024];
+        let length = stream.read(&mut reply).unwrap();
+        String::from_utf8(reply[..length].to_vec()).unwrap()
+    };
+    assert_eq!(exchange(b"add key3 0 0 1\r\nx\r\n"), "NOT_STORED\r\n");
+    assert_eq!(
+        exchange(b"replace missing 0 0 1\r\nx\r\n"),
+        "NOT_STORED\r\n"
+    );
+    assert_eq!(
+        exchange(b"set quiet 3 0 2 noreply\r\nhi\r\nget quiet missing\r\n"),
+        "VALUE quiet 3 2\r\nhi\r\nEND\r\n"
+    );
+    assert_eq!(exchange(b"bogus\r\n"), "ERROR\r\n");
+    let stats = exchange(b"stats\r\n");
+    assert!(stats.starts_with("STAT backends 2\r\n") && stats.ends_with("END\r\n"));
+    proxy.flush().unwrap();
+    let result: HashMap<String, u32> = proxy.gets(&lookup).unwrap();
+    assert!(result.is_empty());
+}
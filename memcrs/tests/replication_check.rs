@@ -0,0 +1,49 @@
+mod common;
+use memcrs::memory_store::StoreEngine;
+use std::time::{Duration, Instant};
+use test_case::test_case;
+
+/// Replication is asynchronous, waits for the replica to catch up
+fn eventually(check: impl Fn() -> bool) {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while !check() {
+        assert!(Instant::now() < deadline, "replica did not catch up");
+        std::thread::sleep(Duration::from_millis(50));
+    }
+}
+
+#[test_case(common::create_moka_engine() ; "moka_backend")]
+#[test_case(common::create_dashmap_engine() ; "dash_map_backend")]
+#[test_case(common::create_slab_engine() ; "slab_backend")]
+#[test_case(common::create_sieve_engine() ; "sieve_backend")]
+fn replica_check(engine: StoreEngine) {
+    let mut params_builder = common::MemcrsdServerParamsBuilder::new(engine);
+    params_builder.with_replication();
+    let primary_handle = common::spawn_server(params_builder);
+    let primary = memcache::connect(primary_handle.get_connection_string()).unwrap();
+    // items stored before the replica connects come with the snapshot
+    for idx in 0..100 {
+        primary.set(&format!("key{}", idx), idx, 0).unwrap();
+    }
+
+    let mut params_builder = common::MemcrsdServerParamsBuilder::new(engine);
+    params_builder.with_replica_of(&primary_handle.get_replication_address());
+    let replica_handle = common::spawn_server(params_builder);
+    let replica = memcache::connect(replica_handle.get_connection_string()).unwrap();
+    eventually(|| replica.get::<u32>("key99").unwrap() == Some(99));
+    for idx in 0..100 {
+        let value: Option<u32> = replica.get(&format!("key{}", idx)).unwrap();
+        assert_eq!(value, Some(idx));
+    }
+
+    // later mutations are streamed
+    primary.set("streamed", "value", 0).unwrap();
+    primary.delete("key0").unwrap();
+    eventually(|| replica.get::<String>("streamed").unwrap().is_some());
+    eventually(|| replica.get::<u32>("key0").unwrap().is_none());
+
+    assert!(replica.set("written", "value", 0).is_err());
+    assert!(replica.delete("key1").is_err());
+    let value: Option<u32> = replica.get("key1").unwrap();
+    assert_eq!(value, Some(1));
+}
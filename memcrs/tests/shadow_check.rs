@@ -0,0 +1,49 @@
+mod common;
+use memcrs::memory_store::StoreEngine;
+use std::time::{Duration, Instant};
+use test_case::test_case;
+
+/// Mirroring is asynchronous, waits for the shadow server to catch up
+fn eventually(check: impl Fn() -> bool) {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while !check() {
+        assert!(Instant::now() < deadline, "shadow server did not catch up");
+        std::thread::sleep(Duration::from_millis(50));
+    }
+}
+
+#[test_case(common::create_moka_engine() ; "moka_backend")]
+#[test_case(common::create_dashmap_engine() ; "dash_map_backend")]
+#[test_case(common::create_slab_engine() ; "slab_backend")]
+#[test_case(common::create_sieve_engine() ; "sieve_backend")]
+fn shadow_check(engine: StoreEngine) {
+    let shadow_handle = common::spawn_server(common::MemcrsdServerParamsBuilder::new(engine));
+    let shadow = memcache::connect(shadow_handle.get_connection_string()).unwrap();
+
+    let mut params_builder = common::MemcrsdServerParamsBuilder::new(engine);
+    params_builder.with_shadow_to(&shadow_handle.get_address());
+    let server_handle = common::spawn_server(params_builder);
+    let server = memcache::connect(server_handle.get_connection_string()).unwrap();
+
+    for idx in 0..100 {
+        server.set(&format!("key{}", idx), idx, 0).unwrap();
+    }
+    server.delete("key0").unwrap();
+    eventually(|| shadow.get::<u32>("key99").unwrap() == Some(99));
+    eventually(|| shadow.get::<u32>("key0").unwrap().is_none());
+    for idx in 1..100 {
+        let value: Option<u32> = shadow.get(&format!("key{}", idx)).unwrap();
+        assert_eq!(value, Some(idx));
+    }
+
+    // reads are served locally only
+    let value: Option<u32> = server.get("key1").unwrap();
+    assert_eq!(value, Some(1));
+    eventually(|| {
+        let stats = server.stats().unwrap();
+        stats[0].1.get("shadow_mirrored") == Some(&"101".to_string())
+    });
+    let stats = server.stats().unwrap();
+    assert_eq!(stats[0].1.get("shadow_dropped"), Some(&"0".to_string()));
+    assert_eq!(stats[0].1.get("shadow_failed"), Some(&"0".to_string()));
+}
@@ -0,0 +1,43 @@
+//procspawn::enable_test_support!();
+mod common;
+use memcrs::memory_store::StoreEngine;
+use test_case::test_case;
+
+#[test_case(common::create_moka_engine() ; "moka_backend")]
+#[test_case(common::create_dashmap_engine() ; "dash_map_backend")]
+#[test_case(common::create_slab_engine() ; "slab_backend")]
+#[test_case(common::create_sieve_engine() ; "sieve_backend")]
+fn snapshot_warm_restart_check(engine: StoreEngine) {
+    let path = std::env::temp_dir().join(format!(
+        "memcrs-warm-restart-{:?}-{}",
+        engine,
+        std::process::id()
+    ));
+    let path = path.to_str().unwrap();
+
+    let mut params_builder: common::MemcrsdServerParamsBuilder =
+        common::MemcrsdServerParamsBuilder::new(engine);
+    params_builder.with_snapshot_path(path);
+    let server_handle = common::spawn_server(params_builder);
+    let client = memcache::connect(server_handle.get_connection_string()).unwrap();
+    for idx in 0..100 {
+        client.set(&format!("key{}", idx), idx, 0).unwrap();
+    }
+    client.set("short-lived", 1, 1).unwrap();
+    // snapshot is written on shutdown
+    drop(server_handle);
+    std::thread::sleep(std::time::Duration::from_secs(2));
+
+    let mut params_builder: common::MemcrsdServerParamsBuilder =
+        common::MemcrsdServerParamsBuilder::new(engine);
+    params_builder.with_restore_from(path);
+    let server_handle = common::spawn_server(params_builder);
+    let client = memcache::connect(server_handle.get_connection_string()).unwrap();
+    for idx in 0..100 {
+        let value: Option<u32> = client.get(&format!("key{}", idx)).unwrap();
+        assert_eq!(value, Some(idx));
+    }
+    let value: Option<u32> = client.get("short-lived").unwrap();
+    assert_eq!(value, None);
+    std::fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,42 @@
+mod common;
+use memcrs::memory_store::StoreEngine;
+use std::time::{Duration, Instant};
+use test_case::test_case;
+
+/// Items are copied in the background, waits for them to arrive
+fn eventually(check: impl Fn() -> bool) {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while !check() {
+        assert!(Instant::now() < deadline, "cache was not warmed");
+        std::thread::sleep(Duration::from_millis(50));
+    }
+}
+
+#[test_case(common::create_moka_engine() ; "moka_backend")]
+#[test_case(common::create_dashmap_engine() ; "dash_map_backend")]
+#[test_case(common::create_slab_engine() ; "slab_backend")]
+#[test_case(common::create_sieve_engine() ; "sieve_backend")]
+fn warm_check(engine: StoreEngine) {
+    let peer_handle = common::spawn_server(common::MemcrsdServerParamsBuilder::new(engine));
+    let peer = memcache::connect(peer_handle.get_connection_string()).unwrap();
+    // more items than fit in one scan page
+    for idx in 0..2500 {
+        peer.set(&format!("key{}", idx), idx, 0).unwrap();
+    }
+    peer.set("expiring", "value", 3600).unwrap();
+
+    let mut params_builder = common::MemcrsdServerParamsBuilder::new(engine);
+    params_builder.with_warm_from(&peer_handle.get_address());
+    let server_handle = common::spawn_server(params_builder);
+    let server = memcache::connect(server_handle.get_connection_string()).unwrap();
+    eventually(|| {
+        (0..2500).all(|idx| server.get::<u32>(&format!("key{}", idx)).unwrap() == Some(idx))
+    });
+    let value: Option<String> = server.get("expiring").unwrap();
+    assert_eq!(value, Some("value".to_string()));
+
+    // items are served and written while the cache is warmed
+    server.set("local", "value", 0).unwrap();
+    let value: Option<String> = server.get("local").unwrap();
+    assert_eq!(value, Some("value".to_string()));
+}